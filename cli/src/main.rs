@@ -0,0 +1,538 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    process,
+};
+
+use clap::{Args, Parser, Subcommand};
+use nalgebra::Vector4;
+use pcc_common::{
+    feature::Feature,
+    filter::{ApproxFilter, Filter},
+    point::{Data, Point, Point3, Point3N},
+    point_cloud::{AsPointCloud, PointCloud},
+    search::SearchParam,
+};
+use pcc_filters::{RadiusOutlierRemoval, StatOutlierRemoval, VoxelGrid};
+use pcc_io::{pcd::PcdData, DynPointCloud, PcdFormat, Registry};
+use pcc_recognition::{Pipeline, Suggestion};
+use pcc_sac::{Arrsac, PlaneEstimator};
+use pcc_search::KdTree;
+use rand::{rngs::StdRng, SeedableRng};
+use sample_consensus::Consensus;
+
+/// Common operations on point clouds. The formats of the files are decided by
+/// their extensions, e.g. `.pcd`, `.pcq` or `.glb`.
+#[derive(Debug, Parser)]
+#[command(name = "pcc", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Clone, Args)]
+struct Output {
+    /// The output file.
+    output: PathBuf,
+    /// The data type of PCD output: ascii, binary, binary_compressed or
+    /// binary_zstd.
+    #[arg(long, value_parser = parse_data)]
+    data: Option<PcdData>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Prints the fields, the size and the bound of a point cloud.
+    Info { input: PathBuf },
+    /// Prints the statistics of a point cloud and the parameters suggested
+    /// from them for the other commands.
+    Suggest { input: PathBuf },
+    /// Converts a point cloud to another format, keeping all of its fields.
+    Convert {
+        input: PathBuf,
+        #[command(flatten)]
+        output: Output,
+    },
+    /// Replaces the points in every voxel with their centroid.
+    Downsample {
+        input: PathBuf,
+        #[command(flatten)]
+        output: Output,
+        /// The edge length of the voxels.
+        #[arg(long)]
+        leaf: f32,
+    },
+    /// Removes the points far from their neighbors, by statistical analysis
+    /// or, if `--radius` is given, by counting the neighbors within it.
+    RemoveOutliers {
+        input: PathBuf,
+        #[command(flatten)]
+        output: Output,
+        #[arg(long, default_value_t = 50)]
+        mean_k: usize,
+        #[arg(long, default_value_t = 1.)]
+        stddev: f32,
+        #[arg(long)]
+        radius: Option<f32>,
+        #[arg(long, default_value_t = 2)]
+        min_neighbors: usize,
+        /// Keeps the outliers instead.
+        #[arg(long)]
+        negative: bool,
+    },
+    /// Estimates the normals from the `--k` nearest neighbors or the
+    /// neighbors within `--radius`.
+    Normals {
+        input: PathBuf,
+        #[command(flatten)]
+        output: Output,
+        #[arg(long, default_value_t = 10, conflicts_with = "radius")]
+        k: usize,
+        #[arg(long)]
+        radius: Option<f32>,
+    },
+    /// Extracts the points on the dominant plane.
+    SegmentPlane {
+        input: PathBuf,
+        #[command(flatten)]
+        output: Output,
+        /// The maximum distance from an inlier to the plane.
+        #[arg(long, default_value_t = 0.01)]
+        threshold: f32,
+        /// Writes the points off the plane to this file, in the same data type
+        /// as the output.
+        #[arg(long)]
+        remaining: Option<PathBuf>,
+        /// Seeds the random number generator for reproducible results.
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Estimates the rigid transformation from `source` to `target` by
+    /// matching FPFH descriptors.
+    Register {
+        source: PathBuf,
+        target: PathBuf,
+        /// The mean spacing between neighboring points, from which the
+        /// parameters of the pipeline are derived.
+        #[arg(long)]
+        resolution: f32,
+        /// Writes the transformed source to this file.
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// The data type of PCD output: ascii, binary, binary_compressed or
+        /// binary_zstd.
+        #[arg(long, value_parser = parse_data, requires = "output")]
+        data: Option<PcdData>,
+        /// Seeds the random number generator for reproducible results.
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+}
+
+fn parse_data(s: &str) -> Result<PcdData, String> {
+    let types = [
+        PcdData::Ascii,
+        PcdData::Binary,
+        PcdData::BinaryCompressed,
+        PcdData::BinaryZstd,
+    ];
+    { types.into_iter() }
+        .find(|data| data.type_str() == s)
+        .ok_or_else(|| format!("Unknown PCD data type: {:?}", s))
+}
+
+impl Output {
+    fn write(&self, point_cloud: &DynPointCloud) -> Result<(), Box<dyn Error>> {
+        self.write_to(&self.output, point_cloud)
+    }
+
+    /// Writes to another file than the output, in the same data type.
+    fn write_to(&self, path: &Path, point_cloud: &DynPointCloud) -> Result<(), Box<dyn Error>> {
+        let mut registry = Registry::default();
+        if let Some(data) = self.data {
+            registry.register_writer(PcdFormat::new(data));
+        }
+        registry.write(path, point_cloud)
+    }
+}
+
+/// Reads a point cloud with all of its fields, along with its coordinates.
+fn load(path: &Path) -> Result<(DynPointCloud, PointCloud<Point3>), Box<dyn Error>> {
+    let dyn_pc = pcc_io::read(path)?;
+    if let Some(name) = ["x", "y", "z"]
+        .into_iter()
+        .find(|&name| dyn_pc.field(name).is_none())
+    {
+        return Err(format!("Missing field {:?} in {:?}", name, path).into());
+    }
+    let (point_cloud, _) = dyn_pc.clone().into_point_cloud()?;
+    Ok((dyn_pc, point_cloud))
+}
+
+/// Selects the records of `indices`, keeping all the fields.
+fn select(dyn_pc: &DynPointCloud, indices: &[usize]) -> DynPointCloud {
+    let rec_size = dyn_pc.rec_size();
+    let data = { indices.iter() }
+        .flat_map(|&index| &dyn_pc.data[index * rec_size..][..rec_size])
+        .copied()
+        .collect();
+    DynPointCloud {
+        fields: dyn_pc.fields.clone(),
+        width: indices.len(),
+        height: 1,
+        viewpoint: dyn_pc.viewpoint.clone(),
+        finite: dyn_pc.finite,
+        data,
+    }
+}
+
+fn info(input: &Path) -> Result<(), Box<dyn Error>> {
+    let dyn_pc = pcc_io::read(input)?;
+    println!(
+        "points: {} ({} x {})",
+        dyn_pc.len(),
+        dyn_pc.width,
+        dyn_pc.height
+    );
+    println!("finite: {}", dyn_pc.finite);
+    for field in &dyn_pc.fields {
+        println!("field: {} {:?} x {}", field.name, field.ty, field.count);
+    }
+    let (origin, quat) = (dyn_pc.viewpoint.origin, dyn_pc.viewpoint.quat);
+    println!(
+        "viewpoint: {} {} {} {} {} {} {}",
+        origin.x, origin.y, origin.z, quat.w, quat.i, quat.j, quat.k
+    );
+
+    if ["x", "y", "z"]
+        .iter()
+        .all(|name| dyn_pc.field(name).is_some())
+    {
+        let (point_cloud, _) = dyn_pc.into_point_cloud::<Point3>()?;
+        if let Some([min, max]) = point_cloud.finite_bound() {
+            println!(
+                "bound: [{}, {}, {}] - [{}, {}, {}]",
+                min.x, min.y, min.z, max.x, max.y, max.z
+            );
+        }
+    }
+    Ok(())
+}
+
+fn downsample(input: &Path, output: &Output, leaf: f32) -> Result<(), Box<dyn Error>> {
+    if !(leaf.is_finite() && leaf > 0.) {
+        return Err(format!("Invalid leaf size: {}", leaf).into());
+    }
+    let (dyn_pc, point_cloud) = load(input)?;
+    let downsampled = VoxelGrid::new(Vector4::new(leaf, leaf, leaf, 1.)).filter(&point_cloud);
+    output.write(&DynPointCloud::from_point_cloud(
+        &downsampled,
+        &dyn_pc.viewpoint,
+    ))
+}
+
+fn normals(
+    input: &Path,
+    output: &Output,
+    k: usize,
+    radius: Option<f32>,
+) -> Result<(), Box<dyn Error>> {
+    let search_param = match radius {
+        Some(radius) => SearchParam::radius(radius)?,
+        None => SearchParam::knn(k)?,
+    };
+    let (dyn_pc, point_cloud) = load(input)?;
+    let origin = dyn_pc.viewpoint.origin;
+    let viewpoint = Vector4::new(origin.x, origin.y, origin.z, 1.);
+
+    let tree = KdTree::new(&point_cloud);
+    let normals: PointCloud<Point3N> =
+        pcc_features::Normal::new(viewpoint).compute(&point_cloud, &tree, search_param);
+    let storage = { normals.iter().zip(point_cloud.iter()) }
+        .map(|(normal, point)| normal.clone().with_coords(point.coords().clone()))
+        .collect();
+    let normals = PointCloud::from_vec(storage, point_cloud.width());
+    output.write(&DynPointCloud::from_point_cloud(
+        &normals,
+        &dyn_pc.viewpoint,
+    ))
+}
+
+fn rng(seed: Option<u64>) -> StdRng {
+    seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64)
+}
+
+fn segment_plane(
+    input: &Path,
+    output: &Output,
+    threshold: f32,
+    remaining: Option<&Path>,
+    seed: Option<u64>,
+) -> Result<(), Box<dyn Error>> {
+    let (dyn_pc, point_cloud) = load(input)?;
+    let finite = { point_cloud.iter().enumerate() }
+        .filter(|(_, point)| point.is_finite())
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+
+    let mut sac = Arrsac::new(threshold, rng(seed));
+    let coords = finite
+        .iter()
+        .map(|&index| point_cloud[index].coords().clone());
+    let (plane, inliers) =
+        { sac.model_inliers(&PlaneEstimator, coords) }.ok_or("Failed to find a plane")?;
+    let inliers = inliers.into_iter().map(|i| finite[i]).collect::<Vec<_>>();
+
+    let normal = plane.normal.xyz().normalize();
+    println!(
+        "plane: {} {} {} {}",
+        normal.x,
+        normal.y,
+        normal.z,
+        -normal.dot(&plane.coords.xyz())
+    );
+    println!("inliers: {} of {}", inliers.len(), point_cloud.len());
+
+    output.write(&select(&dyn_pc, &inliers))?;
+    if let Some(remaining) = remaining {
+        let mut is_inlier = vec![false; point_cloud.len()];
+        inliers.iter().for_each(|&index| is_inlier[index] = true);
+        let outliers = (0..point_cloud.len())
+            .filter(|&index| !is_inlier[index])
+            .collect::<Vec<_>>();
+        output.write_to(remaining, &select(&dyn_pc, &outliers))?;
+    }
+    Ok(())
+}
+
+fn register(
+    source: &Path,
+    target: &Path,
+    resolution: f32,
+    output: Option<&Output>,
+    seed: Option<u64>,
+) -> Result<(), Box<dyn Error>> {
+    let (dyn_pc, source) = load(source)?;
+    let (_, target) = load(target)?;
+
+    let mut pipeline = Pipeline::new(resolution)?.with_rng(rng(seed));
+    let estimate = { pipeline.compute(&source, &target) }.ok_or("Failed to register")?;
+    let transform = estimate.transform.to_homogeneous();
+    print!("transformation:{}", transform);
+    println!("inliers: {}", estimate.correspondences.len());
+
+    if let Some(output) = output {
+        let mut transformed = PointCloud::new();
+        source.transform(&transform, &mut transformed);
+        let transformed = DynPointCloud::from_point_cloud(&transformed, &dyn_pc.viewpoint);
+        output.write(&transformed)?;
+    }
+    Ok(())
+}
+
+fn run(command: Command) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Info { input } => info(&input),
+        Command::Suggest { input } => {
+            let (_, point_cloud) = load(&input)?;
+            let suggestion = { Suggestion::from_point_cloud(&point_cloud) }
+                .ok_or("Too few finite points to suggest parameters")?;
+            println!("{}", suggestion);
+            Ok(())
+        }
+        Command::Convert { input, output } => output.write(&pcc_io::read(input)?),
+        Command::Downsample {
+            input,
+            output,
+            leaf,
+        } => downsample(&input, &output, leaf),
+        Command::RemoveOutliers {
+            input,
+            output,
+            mean_k,
+            stddev,
+            radius,
+            min_neighbors,
+            negative,
+        } => {
+            let (dyn_pc, point_cloud) = load(&input)?;
+            let indices = match radius {
+                Some(radius) => RadiusOutlierRemoval::new(radius, min_neighbors, negative)
+                    .filter_indices(&point_cloud),
+                None => {
+                    StatOutlierRemoval::new(mean_k, stddev, negative).filter_indices(&point_cloud)
+                }
+            };
+            println!("kept: {} of {}", indices.len(), point_cloud.len());
+            output.write(&select(&dyn_pc, &indices))
+        }
+        Command::Normals {
+            input,
+            output,
+            k,
+            radius,
+        } => normals(&input, &output, k, radius),
+        Command::SegmentPlane {
+            input,
+            output,
+            threshold,
+            remaining,
+            seed,
+        } => segment_plane(&input, &output, threshold, remaining.as_deref(), seed),
+        Command::Register {
+            source,
+            target,
+            resolution,
+            output,
+            data,
+            seed,
+        } => {
+            let output = output.map(|output| Output { output, data });
+            register(&source, &target, resolution, output.as_ref(), seed)
+        }
+    }
+}
+
+fn main() {
+    if let Err(err) = run(Cli::parse().command) {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, iter, path::Path};
+
+    use clap::Parser;
+    use nalgebra::{Isometry3, Vector3, Vector4};
+    use pcc_common::{
+        point::{Data, DataFields, Normal, Point, Point3, Point3N},
+        point_cloud::PointCloud,
+    };
+    use pcc_io::{pcd::Viewpoint, DynPointCloud};
+
+    use super::{run, Cli};
+
+    fn pcc(args: &[&str]) -> Result<(), Box<dyn Error>> {
+        run(Cli::try_parse_from(iter::once("pcc").chain(args.iter().copied()))?.command)
+    }
+
+    fn save(path: &Path, point_cloud: &PointCloud<Point3>) {
+        let dyn_pc = DynPointCloud::from_point_cloud(point_cloud, &Viewpoint::default());
+        pcc_io::write(path, &dyn_pc).expect("Failed to write");
+    }
+
+    fn load<P>(path: &Path) -> PointCloud<P>
+    where
+        P: Data<Data = f32> + DataFields,
+    {
+        let dyn_pc = pcc_io::read(path).expect("Failed to read");
+        dyn_pc.into_point_cloud().expect("Failed to convert").0
+    }
+
+    fn is_ascii(path: &Path) -> bool {
+        let content = std::fs::read(path).expect("Failed to read");
+        content.windows(11).any(|line| line == b"DATA ascii\n")
+    }
+
+    #[test]
+    fn test_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_owned();
+
+        // A grid on the plane z = 0 with a distant outlier.
+        let storage = { (0..20).flat_map(|x| (0..20).map(move |y| (x, y))) }
+            .map(|(x, y)| Vector4::new(x as f32 * 0.05, y as f32 * 0.05, 0., 1.))
+            .chain([Vector4::new(5., 5., 5., 1.)])
+            .map(|coords| Point3::default().with_coords(coords))
+            .collect::<Vec<_>>();
+        let input = path("input.pcd");
+        save(Path::new(&input), &PointCloud::from_vec(storage, 1));
+
+        pcc(&["info", &input]).unwrap();
+        pcc(&["suggest", &input]).unwrap();
+
+        let converted = path("converted.pcd");
+        pcc(&["convert", &input, &converted, "--data", "ascii"]).unwrap();
+        assert_eq!(load::<Point3>(Path::new(&converted)).len(), 401);
+        assert!(pcc(&["convert", &input, &converted, "--data", "text"]).is_err());
+
+        let downsampled = path("downsampled.pcd");
+        pcc(&["downsample", &input, &downsampled, "--leaf", "0.1"]).unwrap();
+        let len = load::<Point3>(Path::new(&downsampled)).len();
+        assert!(len > 1 && len < 401);
+
+        let inliers = path("inliers.pcd");
+        pcc(&["remove-outliers", &input, &inliers]).unwrap();
+        assert_eq!(load::<Point3>(Path::new(&inliers)).len(), 400);
+        pcc(&["remove-outliers", &input, &inliers, "--radius", "0.1"]).unwrap();
+        assert_eq!(load::<Point3>(Path::new(&inliers)).len(), 400);
+
+        let normals = path("normals.pcd");
+        pcc(&["normals", &input, &normals, "--k", "8"]).unwrap();
+        let normals = load::<Point3N>(Path::new(&normals));
+        assert_eq!(normals[0].coords(), &Vector4::new(0., 0., 0., 1.));
+        assert!((normals[0].normal().z.abs() - 1.).abs() < 1e-4);
+        assert!(pcc(&["normals", &input, &path("normals.pcd"), "--k", "1"]).is_err());
+
+        let (plane, remaining) = (path("plane.pcd"), path("remaining.pcd"));
+        pcc(&[
+            "segment-plane",
+            &input,
+            &plane,
+            "--remaining",
+            &remaining,
+            "--seed",
+            "0",
+            "--data",
+            "ascii",
+        ])
+        .unwrap();
+        assert_eq!(load::<Point3>(Path::new(&plane)).len(), 400);
+        assert!(is_ascii(Path::new(&plane)) && is_ascii(Path::new(&remaining)));
+        let remaining = load::<Point3>(Path::new(&remaining));
+        assert_eq!(remaining[0].coords(), &Vector4::new(5., 5., 5., 1.));
+    }
+
+    #[test]
+    fn test_register() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_owned();
+
+        let resolution = 0.05;
+        let storage = { (0..40).flat_map(|x| (0..40).map(move |y| (x, y))) }
+            .map(|(x, y)| (x as f32 * resolution, y as f32 * resolution))
+            .map(|(x, y)| {
+                let z = (x * 3.).sin() * (y * 5.).cos() * 0.3 + x * x * 0.2;
+                Point3::default().with_coords(Vector4::new(x, y, z, 1.))
+            })
+            .collect::<Vec<_>>();
+        let target = PointCloud::from_vec(storage, 1);
+        let truth = Isometry3::new(Vector3::new(0.2, -0.1, 0.05), Vector3::new(0., 0., 0.3));
+        let mut source = PointCloud::new();
+        target.transform(&truth.inverse().to_homogeneous(), &mut source);
+
+        let (source_path, target_path) = (path("source.pcd"), path("target.pcd"));
+        save(Path::new(&source_path), &source);
+        save(Path::new(&target_path), &target);
+
+        let output = path("registered.pcd");
+        pcc(&[
+            "register",
+            &source_path,
+            &target_path,
+            "--resolution",
+            "0.05",
+            "--output",
+            &output,
+            "--seed",
+            "0",
+            "--data",
+            "ascii",
+        ])
+        .unwrap();
+        assert_eq!(load::<Point3>(Path::new(&output)).len(), target.len());
+        assert!(is_ascii(Path::new(&output)));
+    }
+}
@@ -0,0 +1,310 @@
+use std::ops::{Deref, Index, IndexMut};
+
+use nalgebra::{Affine3, RealField, Scalar, Vector4};
+use num::ToPrimitive;
+
+use crate::{
+    point::{Point, PointRgba},
+    point_cloud::PointCloud,
+};
+
+/// A row-major image, indexed with `(x, y)` like organized point clouds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image<C> {
+    data: Vec<C>,
+    width: usize,
+    height: usize,
+}
+
+impl<C> Image<C> {
+    /// Creates an image from the rows in `data`, which must be empty if
+    /// `width` is 0.
+    pub fn new(data: Vec<C>, width: usize) -> Self {
+        let height = match width {
+            0 => {
+                assert!(data.is_empty());
+                0
+            }
+            _ => {
+                assert_eq!(data.len() % width, 0);
+                data.len() / width
+            }
+        };
+        Image {
+            data,
+            width,
+            height,
+        }
+    }
+
+    /// Creates an image filled with `value`, which is empty if `width` or
+    /// `height` is 0.
+    pub fn filled(value: C, width: usize, height: usize) -> Self
+    where
+        C: Clone,
+    {
+        Image {
+            data: vec![value; width * height],
+            width,
+            height,
+        }
+    }
+
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    #[inline]
+    pub fn into_vec(self) -> Vec<C> {
+        self.data
+    }
+}
+
+impl<C> Deref for Image<C> {
+    type Target = [C];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+impl<C> Index<(usize, usize)> for Image<C> {
+    type Output = C;
+
+    #[inline]
+    fn index(&self, (x, y): (usize, usize)) -> &Self::Output {
+        &self.data[y * self.width + x]
+    }
+}
+
+impl<C> IndexMut<(usize, usize)> for Image<C> {
+    #[inline]
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut Self::Output {
+        &mut self.data[y * self.width + x]
+    }
+}
+
+/// The intrinsics of a pinhole camera, which looks along +Z with +X to the
+/// right and +Y downward. Pixel `(x, y)` is centered at the image coordinates
+/// `(x, y)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinholeCamera<T: Scalar> {
+    pub fx: T,
+    pub fy: T,
+    pub cx: T,
+    pub cy: T,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl<T: Scalar> PinholeCamera<T> {
+    pub fn new(fx: T, fy: T, cx: T, cy: T, width: usize, height: usize) -> Self {
+        PinholeCamera {
+            fx,
+            fy,
+            cx,
+            cy,
+            width,
+            height,
+        }
+    }
+}
+
+impl<T: RealField + ToPrimitive> PinholeCamera<T> {
+    /// Projects `coords` in the camera frame to its pixel and its depth, or
+    /// returns `None` if it's behind the camera or out of the image.
+    pub fn project(&self, coords: &Vector4<T>) -> Option<((usize, usize), T)> {
+        let depth = coords.z.clone();
+        if !(depth > T::zero()) {
+            return None;
+        }
+
+        let u = self.fx.clone() * coords.x.clone() / depth.clone() + self.cx.clone();
+        let v = self.fy.clone() * coords.y.clone() / depth.clone() + self.cy.clone();
+        let (x, y) = (u.round().to_isize()?, v.round().to_isize()?);
+
+        let inside =
+            (0..self.width as isize).contains(&x) && (0..self.height as isize).contains(&y);
+        inside.then(|| ((x as usize, y as usize), depth))
+    }
+
+    /// Returns the point in the camera frame seen at the center of pixel `(x,
+    /// y)` with `depth`.
+    pub fn unproject(&self, (x, y): (usize, usize), depth: T) -> Vector4<T> {
+        let u = T::from_usize(x).unwrap() - self.cx.clone();
+        let v = T::from_usize(y).unwrap() - self.cy.clone();
+        Vector4::new(
+            u * depth.clone() / self.fx.clone(),
+            v * depth.clone() / self.fy.clone(),
+            depth,
+            T::one(),
+        )
+    }
+
+    fn project_all<P: Point<Data = T>>(
+        &self,
+        point_cloud: &PointCloud<P>,
+        pose: &Affine3<T>,
+    ) -> Vec<Option<((usize, usize), T)>> {
+        let inverse = pose.clone().inverse();
+        { point_cloud.iter() }
+            .map(|point| {
+                if !point.is_finite() {
+                    return None;
+                }
+                let coords = (&inverse * point.na_point()).to_homogeneous();
+                self.project(&coords)
+            })
+            .collect()
+    }
+
+    /// Splats the values of `f` of the points of `point_cloud` into an image,
+    /// where the camera is placed at `pose` in the frame of the point cloud.
+    ///
+    /// Every pixel keeps the value of its nearest point, and pixels without
+    /// points are `None`. The image is empty if the camera has no pixels.
+    pub fn splat<P, C, F>(
+        &self,
+        point_cloud: &PointCloud<P>,
+        pose: &Affine3<T>,
+        mut f: F,
+    ) -> Image<Option<C>>
+    where
+        P: Point<Data = T>,
+        C: Clone,
+        F: FnMut(&P) -> C,
+    {
+        let mut depth = Image::filled(None::<T>, self.width, self.height);
+        let mut output = Image::filled(None, self.width, self.height);
+
+        let projections = self.project_all(point_cloud, pose);
+        for (point, projection) in point_cloud.iter().zip(projections) {
+            if let Some((pixel, d)) = projection {
+                if depth[pixel].as_ref().map_or(true, |nearest| &d < nearest) {
+                    depth[pixel] = Some(d);
+                    output[pixel] = Some(f(point));
+                }
+            }
+        }
+
+        output
+    }
+
+    /// Samples the colors of the points of `point_cloud` from the RGB `image`
+    /// taken by the camera at `pose` in the frame of the point cloud, and
+    /// returns the number of colored points.
+    ///
+    /// Points out of the image keep their colors. If `occlusion` is given,
+    /// points farther than it behind the nearest point of their pixels are
+    /// regarded as occluded and keep their colors as well.
+    pub fn colorize<P>(
+        &self,
+        point_cloud: &mut PointCloud<P>,
+        pose: &Affine3<T>,
+        image: &Image<[u8; 3]>,
+        occlusion: Option<T>,
+    ) -> usize
+    where
+        P: PointRgba<Data = T>,
+    {
+        assert_eq!((image.width(), image.height()), (self.width, self.height));
+
+        let projections = self.project_all(point_cloud, pose);
+        let depth = occlusion.as_ref().map(|_| {
+            let mut depth = Image::filled(None::<T>, self.width, self.height);
+            for (pixel, d) in projections.iter().flatten() {
+                if depth[*pixel].as_ref().map_or(true, |nearest| d < nearest) {
+                    depth[*pixel] = Some(d.clone());
+                }
+            }
+            depth
+        });
+
+        let mut num = 0;
+        for (index, projection) in projections.into_iter().enumerate() {
+            let (pixel, d) = match projection {
+                Some(projection) => projection,
+                None => continue,
+            };
+            if let (Some(depth), Some(occlusion)) = (&depth, &occlusion) {
+                let nearest = depth[pixel].clone().unwrap();
+                if d > nearest + occlusion.clone() {
+                    continue;
+                }
+            }
+
+            let [r, g, b] = image[pixel].map(u32::from);
+            point_cloud[index].set_rgba(0xff << 24 | r << 16 | g << 8 | b);
+            num += 1;
+        }
+
+        num
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Affine3, Vector4};
+
+    use super::*;
+    use crate::point::{Point, Point3Rgba, PointRgba};
+
+    #[test]
+    fn test_colorize() {
+        let camera = PinholeCamera::new(2., 2., 1., 1., 3, 3);
+        let pose = Affine3::identity();
+
+        let mut pc = PointCloud::from_vec(
+            vec![
+                Point3Rgba::default().with_coords(Vector4::new(0., 0., 1., 1.)),
+                Point3Rgba::default().with_coords(Vector4::new(0., 0., 2., 1.)),
+                Point3Rgba::default().with_coords(Vector4::new(1., 0., 1., 1.)),
+                Point3Rgba::default().with_coords(Vector4::new(0., 0., -1., 1.)),
+            ],
+            1,
+        );
+
+        let image = Image::new((0..9).map(|i| [i * 10, 0, 0]).collect(), 3);
+        assert_eq!(camera.colorize(&mut pc, &pose, &image, Some(0.5)), 1);
+        assert_eq!(pc[0].rgba(), 0xff00_0000 | 40 << 16);
+        assert_eq!(pc[1].rgba(), 0);
+
+        let splat = camera.splat(&pc, &pose, |point| point.coords().z);
+        assert_eq!(splat[(1, 1)], Some(1.));
+        assert_eq!(splat[(0, 0)], None);
+
+        let coords = camera.unproject((1, 1), 2.);
+        assert_eq!(coords, Vector4::new(0., 0., 2., 1.));
+    }
+
+    #[test]
+    fn test_empty_camera() {
+        let pose = Affine3::identity();
+        let mut pc = PointCloud::from_vec(
+            vec![Point3Rgba::default().with_coords(Vector4::new(0., 0., 1., 1.))],
+            1,
+        );
+
+        for (width, height) in [(0, 3), (3, 0), (0, 0)] {
+            let camera = PinholeCamera::new(2., 2., 0., 0., width, height);
+            assert_eq!(camera.project(&Vector4::new(0., 0., 1., 1.)), None);
+
+            let splat = camera.splat(&pc, &pose, |point| point.coords().z);
+            assert!(splat.is_empty());
+            assert_eq!((splat.width(), splat.height()), (width, height));
+
+            let image = Image::filled([0; 3], width, height);
+            assert_eq!(camera.colorize(&mut pc, &pose, &image, Some(0.5)), 0);
+        }
+
+        let image = Image::<u8>::new(Vec::new(), 0);
+        assert_eq!((image.width(), image.height()), (0, 0));
+    }
+}
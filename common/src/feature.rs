@@ -1,3 +1,145 @@
+use std::{error::Error, fmt, ptr};
+
+use crate::point_cloud::PointCloud;
+
 pub trait Feature<I, O, S, P> {
     fn compute(&self, input: I, search: S, search_param: P) -> O;
 }
+
+/// The input of descriptors computed only at `keypoints`, with the neighbors
+/// searched in `surface` instead, which must be the input of the search.
+#[derive(Debug)]
+pub struct Keypoints<'a, K, P, N> {
+    pub keypoints: &'a PointCloud<K>,
+    pub surface: &'a PointCloud<P>,
+    /// The normals of the surface, not of the keypoints.
+    pub normals: &'a PointCloud<N>,
+}
+
+impl<'a, K, P, N> Keypoints<'a, K, P, N> {
+    pub fn new(
+        keypoints: &'a PointCloud<K>,
+        surface: &'a PointCloud<P>,
+        normals: &'a PointCloud<N>,
+    ) -> Self {
+        Keypoints {
+            keypoints,
+            surface,
+            normals,
+        }
+    }
+}
+
+impl<'a, K, P, N> Keypoints<'a, K, P, N> {
+    /// Returns the keypoints and the normals of the surface.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `search_input`, the input of the search, is not `surface`.
+    pub fn split(self, search_input: &PointCloud<P>) -> (&'a PointCloud<K>, &'a PointCloud<N>) {
+        assert!(
+            ptr::eq(search_input, self.surface),
+            "The surface must be the input of the search"
+        );
+        (self.keypoints, self.normals)
+    }
+}
+
+impl<'a, K, P, N> Clone for Keypoints<'a, K, P, N> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, K, P, N> Copy for Keypoints<'a, K, P, N> {}
+
+/// Decides what a feature emits for a point whose neighborhood is degenerate,
+/// e.g. empty or too small to estimate a surface.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum OutputPolicy {
+    /// Emits the default value (usually zeros) of the feature.
+    #[default]
+    Skip,
+    /// Emits a value filled with NaNs.
+    FillNan,
+    /// Aborts the computation with a [`DegenerateError`].
+    Error,
+}
+
+/// The output of a feature computed under an [`OutputPolicy`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyOutput<O> {
+    pub output: O,
+    /// The number of points with degenerate neighborhoods.
+    pub num_degenerate: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct DegenerateError {
+    /// The index of the first point with a degenerate neighborhood.
+    pub index: usize,
+}
+
+impl fmt::Display for DegenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the neighborhood of point {} is degenerate", self.index)
+    }
+}
+
+impl Error for DegenerateError {}
+
+impl OutputPolicy {
+    /// Resolves the per-point outputs, where `None` marks a degenerate
+    /// neighborhood, with `skip` or `nan` providing the substitution.
+    pub fn resolve<V>(
+        self,
+        values: Vec<Option<V>>,
+        mut skip: impl FnMut() -> V,
+        mut nan: impl FnMut() -> V,
+    ) -> Result<PolicyOutput<Vec<V>>, DegenerateError> {
+        let mut num_degenerate = 0;
+        let mut output = Vec::with_capacity(values.len());
+        for (index, value) in values.into_iter().enumerate() {
+            output.push(match value {
+                Some(value) => value,
+                None => {
+                    num_degenerate += 1;
+                    match self {
+                        OutputPolicy::Skip => skip(),
+                        OutputPolicy::FillNan => nan(),
+                        OutputPolicy::Error => return Err(DegenerateError { index }),
+                    }
+                }
+            });
+        }
+        Ok(PolicyOutput {
+            output,
+            num_degenerate,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_policy() {
+        let values = vec![Some(1.), None, Some(3.)];
+
+        let skip = OutputPolicy::Skip.resolve(values.clone(), || 0., || f32::NAN);
+        assert_eq!(
+            skip,
+            Ok(PolicyOutput {
+                output: vec![1., 0., 3.],
+                num_degenerate: 1
+            })
+        );
+
+        let nan = OutputPolicy::FillNan.resolve(values.clone(), || 0., || f32::NAN);
+        assert!(nan.unwrap().output[1].is_nan());
+
+        let error = OutputPolicy::Error.resolve(values, || 0., || f32::NAN);
+        assert_eq!(error, Err(DegenerateError { index: 1 }));
+    }
+}
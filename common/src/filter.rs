@@ -1,4 +1,7 @@
-use crate::{point::Data, point_cloud::PointCloud};
+use crate::{
+    point::Data,
+    point_cloud::{PointCloud, PointCloudRef},
+};
 
 /// A filter that keeps some parts of input, for example, some elements of an
 /// array, and transfers them to the output.
@@ -17,12 +20,18 @@ pub trait Filter<T: ?Sized> {
 
 /// A filter that often generate an approximation of some parts of input,
 /// instead of keeping all parts consistent.
-pub trait ApproxFilter<T> {
-    fn filter(&mut self, input: &T) -> T;
+///
+/// The output is of the same type as the input, unless the input borrows its
+/// data, e.g. a [`PointCloudRef`], from which an owned `O` is generated.
+pub trait ApproxFilter<T, O = T> {
+    fn filter(&mut self, input: &T) -> O;
 
     #[inline]
-    fn filter_mut(&mut self, obj: &mut T) {
-        *obj = self.filter(obj);
+    fn filter_mut(&mut self, obj: &mut T)
+    where
+        O: Into<T>,
+    {
+        *obj = self.filter(obj).into();
     }
 }
 
@@ -70,3 +79,123 @@ where
         obj.reinterpret(1);
     }
 }
+
+/// Runs a filter on a subset of a point cloud, e.g. a segment, which is copied
+/// out only if it's not the whole point cloud. The indices returned refer to
+/// the whole point cloud, so that they can be composed with the indices from
+/// other filters and segmentations.
+impl<'a, P: Data, F> Filter<PointCloudRef<'a, P>> for F
+where
+    F: Filter<PointCloud<P>>,
+{
+    fn filter_indices(&mut self, input: &PointCloudRef<'a, P>) -> Vec<usize> {
+        match input.indices() {
+            Some(indices) => {
+                let sub = input.point_cloud().create_sub(indices, 1);
+                let ret = Filter::<PointCloud<P>>::filter_indices(self, &sub);
+                ret.into_iter().map(|index| indices[index]).collect()
+            }
+            None => Filter::<PointCloud<P>>::filter_indices(self, input.point_cloud()),
+        }
+    }
+
+    fn filter_all_indices(&mut self, input: &PointCloudRef<'a, P>) -> (Vec<usize>, Vec<usize>) {
+        match input.indices() {
+            Some(indices) => {
+                let sub = input.point_cloud().create_sub(indices, 1);
+                let (ret, removed) = Filter::<PointCloud<P>>::filter_all_indices(self, &sub);
+                let map = |v: Vec<usize>| v.into_iter().map(|index| indices[index]).collect();
+                (map(ret), map(removed))
+            }
+            None => Filter::<PointCloud<P>>::filter_all_indices(self, input.point_cloud()),
+        }
+    }
+}
+
+/// Runs an approximate filter on a subset of a point cloud, which is copied
+/// out only if it's not the whole point cloud, and returns the filtered point
+/// cloud.
+impl<'a, P: Data, F> ApproxFilter<PointCloudRef<'a, P>, PointCloud<P>> for F
+where
+    F: ApproxFilter<PointCloud<P>>,
+{
+    fn filter(&mut self, input: &PointCloudRef<'a, P>) -> PointCloud<P> {
+        match input.indices() {
+            Some(indices) => {
+                let sub = input.point_cloud().create_sub(indices, 1);
+                ApproxFilter::<PointCloud<P>>::filter(self, &sub)
+            }
+            None => ApproxFilter::<PointCloud<P>>::filter(self, input.point_cloud()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use nalgebra::Vector4;
+
+    use super::*;
+    use crate::{
+        point::{Point, Point3},
+        point_cloud::AsPointCloud,
+    };
+
+    struct PositiveX;
+
+    impl Filter<PointCloud<Point3>> for PositiveX {
+        fn filter_indices(&mut self, input: &PointCloud<Point3>) -> Vec<usize> {
+            (|point: &Point3| point.coords().x > 0.).filter_indices(&**input)
+        }
+    }
+
+    #[test]
+    fn test_filter_ref() {
+        let storage = [-1., 2., 3., -4., 5.]
+            .map(|x| Point3::default().with_coords(Vector4::new(x, 0., 0., 1.)))
+            .to_vec();
+        let pc = PointCloud::from_vec(storage, 1);
+
+        let all = pc.as_ref();
+        assert_eq!(PositiveX.filter_indices(&all), vec![1, 2, 4]);
+
+        let sub = pc.select(Cow::Owned(vec![0, 2, 3, 4]));
+        assert_eq!(PositiveX.filter_indices(&sub), vec![2, 4]);
+        assert_eq!(PositiveX.filter_all_indices(&sub), (vec![2, 4], Vec::new()));
+    }
+
+    /// Averages all the points into one.
+    struct Mean;
+
+    impl ApproxFilter<PointCloud<Point3>> for Mean {
+        fn filter(&mut self, input: &PointCloud<Point3>) -> PointCloud<Point3> {
+            let (centroid, _) = input.centroid_coords();
+            let point = Point3::default().with_coords(centroid.unwrap());
+            PointCloud::from_vec(vec![point], 1)
+        }
+    }
+
+    #[test]
+    fn test_approx_filter_ref() {
+        let storage = [-1., 2., 3., -4., 5.]
+            .map(|x| Point3::default().with_coords(Vector4::new(x, 0., 0., 1.)))
+            .to_vec();
+        let pc = PointCloud::from_vec(storage, 1);
+
+        let all = Mean.filter(&pc.as_ref());
+        assert_eq!(all[0].coords(), &Vector4::new(1., 0., 0., 1.));
+
+        let sub = pc.select(Cow::Owned(vec![1, 2, 4]));
+        let output = Mean.filter(&sub);
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].coords(), &Vector4::new(10. / 3., 0., 0., 1.));
+
+        let mut positive = |point: &Point3| point.coords().x > 2.5;
+        let output = positive.filter(&sub);
+        assert_eq!(
+            output.iter().map(|p| p.coords().x).collect::<Vec<_>>(),
+            [3., 5.]
+        );
+    }
+}
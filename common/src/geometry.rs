@@ -0,0 +1,332 @@
+use std::cmp::Ordering;
+
+use nalgebra::{Matrix3, RealField, Rotation3, Scalar, Vector2, Vector3, Vector4};
+
+use crate::{
+    cov_matrix,
+    point::Point,
+    search::{Search, SearchType},
+};
+
+mod bvh;
+mod primitive;
+
+pub use self::{
+    bvh::{Aabb, Bvh, BvhPrimitive, Ray, RayIntersect},
+    primitive::{Segment, Triangle, TriangleFeature},
+};
+
+fn finite_points<'a, T, Iter>(coords: Iter) -> Vec<Vector3<T>>
+where
+    T: 'a + RealField,
+    Iter: Iterator<Item = &'a Vector4<T>>,
+{
+    { coords.filter(|coords| coords.iter().all(|x| x.is_finite())) }
+        .map(|coords| coords.xyz())
+        .collect()
+}
+
+/// A sphere enclosing a set of points.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoundingSphere<T: Scalar> {
+    pub center: Vector4<T>,
+    pub radius: T,
+}
+
+impl<T: RealField> BoundingSphere<T> {
+    fn tolerance(&self) -> T {
+        T::default_epsilon().sqrt() * (T::one() + self.radius.clone())
+    }
+
+    #[inline]
+    pub fn contains(&self, coords: &Vector4<T>) -> bool {
+        (coords - &self.center).xyz().norm() <= self.radius.clone() + self.tolerance()
+    }
+
+    fn from_parts(center: Vector3<T>, radius: T) -> Self {
+        BoundingSphere {
+            center: center.insert_row(3, T::one()),
+            radius,
+        }
+    }
+
+    fn diametral(a: &Vector3<T>, b: &Vector3<T>) -> Self {
+        let two = T::one() + T::one();
+        Self::from_parts((a + b) / two.clone(), (a - b).norm() / two)
+    }
+
+    /// The smallest sphere with `a`, `b` and `c` on its boundary.
+    fn circumscribed3(a: &Vector3<T>, b: &Vector3<T>, c: &Vector3<T>) -> Self {
+        let (u, v) = (a - c, b - c);
+        let normal = u.cross(&v);
+        let denom = normal.norm_squared() * (T::one() + T::one());
+        if denom <= T::default_epsilon() {
+            let pairs = [(a, b), (b, c), (a, c)];
+            let (a, b) = { pairs.into_iter() }
+                .max_by(|(a1, b1), (a2, b2)| {
+                    let (d1, d2) = ((*a1 - *b1).norm(), (*a2 - *b2).norm());
+                    d1.partial_cmp(&d2).unwrap_or(Ordering::Equal)
+                })
+                .unwrap();
+            return Self::diametral(a, b);
+        }
+
+        let offset = (&v * u.norm_squared() - &u * v.norm_squared()).cross(&normal) / denom;
+        let radius = offset.norm();
+        Self::from_parts(c + offset, radius)
+    }
+
+    /// The sphere with `a`, `b`, `c` and `d` on its boundary, or `None` if
+    /// they are coplanar.
+    fn circumscribed4(
+        a: &Vector3<T>,
+        b: &Vector3<T>,
+        c: &Vector3<T>,
+        d: &Vector3<T>,
+    ) -> Option<Self> {
+        let two = T::one() + T::one();
+        let rows = [b - a, c - a, d - a].map(|row| row.transpose() * two.clone());
+        let matrix = Matrix3::from_rows(&rows);
+        let rhs = Vector3::from_iterator([b, c, d].map(|p| p.norm_squared() - a.norm_squared()));
+        let center = matrix.lu().solve(&rhs)?;
+        let radius = (&center - a).norm();
+        Some(Self::from_parts(center, radius))
+    }
+
+    /// Computes the minimal sphere enclosing the finite points of `coords`
+    /// with Welzl's algorithm, or returns `None` if there are none.
+    ///
+    /// The expected running time is linear if the points are in a random
+    /// order.
+    pub fn minimal<'a, Iter>(coords: Iter) -> Option<Self>
+    where
+        T: 'a,
+        Iter: Iterator<Item = &'a Vector4<T>>,
+    {
+        let points = finite_points(coords);
+        let first = points.first()?;
+
+        let mut sphere = Self::from_parts(first.clone(), T::zero());
+        let outside = |sphere: &Self, p: &Vector3<T>| {
+            (p - sphere.center.xyz()).norm() > sphere.radius.clone() + sphere.tolerance()
+        };
+
+        for i in 1..points.len() {
+            if !outside(&sphere, &points[i]) {
+                continue;
+            }
+            sphere = Self::from_parts(points[i].clone(), T::zero());
+            for j in 0..i {
+                if !outside(&sphere, &points[j]) {
+                    continue;
+                }
+                sphere = Self::diametral(&points[i], &points[j]);
+                for k in 0..j {
+                    if !outside(&sphere, &points[k]) {
+                        continue;
+                    }
+                    sphere = Self::circumscribed3(&points[i], &points[j], &points[k]);
+                    for pl in &points[..k] {
+                        if !outside(&sphere, pl) {
+                            continue;
+                        }
+                        let (pi, pj, pk) = (&points[i], &points[j], &points[k]);
+                        sphere = Self::circumscribed4(pi, pj, pk, pl).unwrap_or_else(|| {
+                            let radius = (pl - sphere.center.xyz()).norm();
+                            Self::from_parts(sphere.center.xyz(), radius)
+                        });
+                    }
+                }
+            }
+        }
+
+        Some(sphere)
+    }
+
+    /// Searches the points of `searcher` within the sphere. The radius is
+    /// slightly inflated so that the points on the boundary are found by
+    /// strict radius searches as well.
+    pub fn search<'a, P, S>(&self, searcher: &S, result: &mut Vec<(usize, T)>)
+    where
+        P: Point<Data = T> + 'a,
+        S: Search<'a, P> + ?Sized,
+    {
+        let radius = self.radius.clone() + self.tolerance();
+        searcher.search(&self.center, SearchType::Radius(radius), result)
+    }
+}
+
+/// An oriented bounding box, which spans `half_extents` in each direction
+/// around `center` along the columns of `rotation`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Obb<T: Scalar> {
+    pub center: Vector4<T>,
+    pub rotation: Rotation3<T>,
+    pub half_extents: Vector3<T>,
+}
+
+fn cross2<T: RealField>(o: &Vector2<T>, a: &Vector2<T>, b: &Vector2<T>) -> T {
+    let (u, v) = (a - o, b - o);
+    u.x.clone() * v.y.clone() - u.y.clone() * v.x.clone()
+}
+
+/// The convex hull of `points` in counter-clockwise order, with Andrew's
+/// monotone chain.
+fn convex_hull2<T: RealField>(mut points: Vec<Vector2<T>>) -> Vec<Vector2<T>> {
+    points.sort_by(|a, b| {
+        (a.x.clone(), a.y.clone())
+            .partial_cmp(&(b.x.clone(), b.y.clone()))
+            .unwrap_or(Ordering::Equal)
+    });
+    if points.len() < 3 {
+        return points;
+    }
+
+    let mut hull: Vec<Vector2<T>> = Vec::with_capacity(points.len() * 2);
+    for pass in 0..2 {
+        let start = hull.len();
+        let iter: Box<dyn Iterator<Item = &Vector2<T>>> = if pass == 0 {
+            Box::new(points.iter())
+        } else {
+            Box::new(points.iter().rev())
+        };
+        for point in iter {
+            while hull.len() >= start + 2
+                && cross2(&hull[hull.len() - 2], &hull[hull.len() - 1], point) <= T::zero()
+            {
+                hull.pop();
+            }
+            hull.push(point.clone());
+        }
+        hull.pop();
+    }
+    hull
+}
+
+impl<T: RealField> Obb<T> {
+    /// The box aligned to `rotation` that encloses `points`.
+    fn aligned(points: &[Vector3<T>], rotation: Rotation3<T>) -> Self {
+        let local = points
+            .iter()
+            .map(|point| rotation.inverse_transform_vector(point));
+        let (min, max) = local.fold(
+            (
+                Vector3::repeat(T::max_value().unwrap()),
+                Vector3::repeat(T::min_value().unwrap()),
+            ),
+            |(min, max), p| (min.inf(&p), max.sup(&p)),
+        );
+        let two = T::one() + T::one();
+        let center = &rotation * (&min + &max) / two.clone();
+        Obb {
+            center: center.insert_row(3, T::one()),
+            rotation,
+            half_extents: (max - min) / two,
+        }
+    }
+
+    #[inline]
+    pub fn volume(&self) -> T {
+        let two = T::one() + T::one();
+        self.half_extents.product() * two.clone() * two.clone() * two
+    }
+
+    /// Approximates the minimum-volume box enclosing the finite points of
+    /// `coords`, or returns `None` if there are none.
+    ///
+    /// Each principal axis of the points is tried as one of the axes of the
+    /// box, and the other two axes come from the minimum-area rectangle
+    /// enclosing the points projected onto the plane perpendicular to it,
+    /// which has a side on the convex hull.
+    pub fn minimal<'a, Iter>(coords: Iter) -> Option<Self>
+    where
+        T: 'a,
+        Iter: Iterator<Item = &'a Vector4<T>> + Clone,
+    {
+        let points = finite_points(coords.clone());
+        if points.is_empty() {
+            return None;
+        }
+
+        let axes = match cov_matrix(coords.filter(|c| c.iter().all(|x| x.is_finite()))) {
+            Some(cov) => cov.symmetric_eigen().eigenvectors,
+            None => Matrix3::identity(),
+        };
+
+        let mut best: Option<Self> = None;
+        for a in 0..3 {
+            let u = axes.column(a).normalize();
+            let v = axes.column((a + 1) % 3).normalize();
+            let w = u.cross(&v);
+
+            let projected = { points.iter() }
+                .map(|p| Vector2::new(p.dot(&v), p.dot(&w)))
+                .collect::<Vec<_>>();
+            let hull = convex_hull2(projected);
+
+            let edges = (0..hull.len()).map(|i| &hull[(i + 1) % hull.len()] - &hull[i]);
+            for edge in edges.chain([Vector2::x()]) {
+                let norm = edge.norm();
+                if norm <= T::default_epsilon() {
+                    continue;
+                }
+                let d = edge / norm;
+                let x = &v * d.x.clone() + &w * d.y.clone();
+                let y = u.cross(&x);
+                let rotation = Rotation3::from_basis_unchecked(&[x, y, u.clone()]);
+
+                let obb = Self::aligned(&points, rotation);
+                if best
+                    .as_ref()
+                    .map_or(true, |best| obb.volume() < best.volume())
+                {
+                    best = Some(obb);
+                }
+            }
+        }
+
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Rotation3, Vector3, Vector4};
+
+    use super::*;
+
+    #[test]
+    fn test_bounding_sphere() {
+        let points = [
+            Vector4::new(1f32, 0., 0., 1.),
+            Vector4::new(0., 0.5, 0.2, 1.),
+            Vector4::new(-1., 0., 0., 1.),
+            Vector4::new(0., 1., 0., 1.),
+            Vector4::new(0., -1., 0., 1.),
+            Vector4::new(0., 0., 1., 1.),
+        ];
+        let sphere = BoundingSphere::minimal(points.iter()).unwrap();
+        assert!((sphere.center - Vector4::new(0., 0., 0., 1.)).norm() < 1e-6);
+        assert!((sphere.radius - 1.).abs() < 1e-6);
+        assert!(points.iter().all(|point| sphere.contains(point)));
+    }
+
+    #[test]
+    fn test_obb() {
+        let rotation = Rotation3::from_euler_angles(0.3f64, -0.2, 0.5);
+        let points = (0..8)
+            .map(|i| {
+                let corner = Vector3::new(
+                    if i & 1 == 0 { -2. } else { 2. },
+                    if i & 2 == 0 { -1. } else { 1. },
+                    if i & 4 == 0 { -0.5 } else { 0.5 },
+                );
+                (rotation * corner + Vector3::new(1., 2., 3.)).insert_row(3, 1.)
+            })
+            .collect::<Vec<_>>();
+
+        let obb = Obb::minimal(points.iter()).unwrap();
+        assert!((obb.volume() - 8.).abs() < 1e-6);
+        assert!((obb.center - Vector4::new(1., 2., 3., 1.)).norm() < 1e-6);
+    }
+}
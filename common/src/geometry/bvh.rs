@@ -0,0 +1,355 @@
+use std::cmp::Ordering;
+
+use nalgebra::{convert, RealField, Scalar, Vector3};
+
+/// An axis-aligned bounding box.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Aabb<T: Scalar> {
+    pub min: Vector3<T>,
+    pub max: Vector3<T>,
+}
+
+impl<T: RealField> Aabb<T> {
+    /// The box enclosing `points`, or `None` if there are none.
+    pub fn from_points<'a, Iter>(mut points: Iter) -> Option<Self>
+    where
+        Iter: Iterator<Item = &'a Vector3<T>>,
+    {
+        let first = points.next()?.clone();
+        let init = Aabb {
+            min: first.clone(),
+            max: first,
+        };
+        Some(points.fold(init, |acc, point| Aabb {
+            min: acc.min.inf(point),
+            max: acc.max.sup(point),
+        }))
+    }
+
+    pub fn merge(&self, other: &Self) -> Self {
+        Aabb {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+        }
+    }
+
+    pub fn center(&self) -> Vector3<T> {
+        (&self.min + &self.max) / convert::<_, T>(2.)
+    }
+
+    pub fn half_extents(&self) -> Vector3<T> {
+        (&self.max - &self.min) / convert::<_, T>(2.)
+    }
+
+    pub fn contains(&self, point: &Vector3<T>) -> bool {
+        &self.min <= point && point <= &self.max
+    }
+
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.min <= other.max && other.min <= self.max
+    }
+
+    /// The squared distance from `point` to the box, which is zero inside.
+    pub fn distance_squared(&self, point: &Vector3<T>) -> T {
+        let outside = (&self.min - point)
+            .sup(&(point - &self.max))
+            .sup(&Vector3::zeros());
+        outside.norm_squared()
+    }
+
+    /// The parameter where `ray` enters the box within `[0, max]`, which is
+    /// zero if it starts inside, with the slab method.
+    pub fn ray_intersect(&self, ray: &Ray<T>, max: T) -> Option<T> {
+        let (mut near, mut far) = (T::zero(), max);
+        for axis in 0..3 {
+            let inv = ray.direction[axis].clone().recip();
+            let t1 = (self.min[axis].clone() - ray.origin[axis].clone()) * inv.clone();
+            let t2 = (self.max[axis].clone() - ray.origin[axis].clone()) * inv;
+            let (t1, t2) = if t1 <= t2 { (t1, t2) } else { (t2, t1) };
+            // Rays parallel to the slab and on its boundary make NaNs, which
+            // are ignored here.
+            if t1 > near {
+                near = t1;
+            }
+            if t2 < far {
+                far = t2;
+            }
+            if near > far {
+                return None;
+            }
+        }
+        Some(near)
+    }
+}
+
+/// A ray from `origin` along `direction`, whose points are
+/// `origin + t * direction` for `t >= 0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ray<T: Scalar> {
+    pub origin: Vector3<T>,
+    pub direction: Vector3<T>,
+}
+
+impl<T: RealField> Ray<T> {
+    pub fn at(&self, t: T) -> Vector3<T> {
+        &self.origin + &self.direction * t
+    }
+}
+
+/// The primitives that can be put into a [`Bvh`].
+pub trait BvhPrimitive<T: Scalar> {
+    fn aabb(&self) -> Aabb<T>;
+
+    /// The point of the primitive closest to `point`.
+    fn closest_point(&self, point: &Vector3<T>) -> Vector3<T>;
+
+    /// Checks if the primitive overlaps `aabb`.
+    fn overlaps(&self, aabb: &Aabb<T>) -> bool;
+}
+
+/// The primitives that rays can hit.
+pub trait RayIntersect<T: Scalar> {
+    /// The parameter of the first intersection of `ray` with the primitive.
+    fn ray_intersect(&self, ray: &Ray<T>) -> Option<T>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node<T: Scalar> {
+    Leaf {
+        aabb: Aabb<T>,
+        start: usize,
+        end: usize,
+    },
+    Branch {
+        aabb: Aabb<T>,
+        children: [usize; 2],
+    },
+}
+
+impl<T: Scalar> Node<T> {
+    fn aabb(&self) -> &Aabb<T> {
+        match self {
+            Node::Leaf { aabb, .. } | Node::Branch { aabb, .. } => aabb,
+        }
+    }
+}
+
+const LEAF_SIZE: usize = 4;
+
+/// A bounding volume hierarchy over primitives, e.g. the triangles of a
+/// triangle soup, split at the medians of the centers of their boxes along
+/// the longest axes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bvh<T: Scalar, P> {
+    primitives: Vec<P>,
+    nodes: Vec<Node<T>>,
+    indices: Vec<usize>,
+}
+
+impl<T: RealField, P: BvhPrimitive<T>> Bvh<T, P> {
+    pub fn new(primitives: Vec<P>) -> Self {
+        let aabbs = primitives.iter().map(P::aabb).collect::<Vec<_>>();
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            indices: (0..primitives.len()).collect(),
+            primitives,
+        };
+        if !aabbs.is_empty() {
+            bvh.build(&aabbs, 0, aabbs.len());
+        }
+        bvh
+    }
+
+    #[inline]
+    pub fn primitives(&self) -> &[P] {
+        &self.primitives
+    }
+
+    fn build(&mut self, aabbs: &[Aabb<T>], start: usize, end: usize) -> usize {
+        let aabb = { self.indices[start + 1..end].iter() }
+            .fold(aabbs[self.indices[start]].clone(), |acc, &index| {
+                acc.merge(&aabbs[index])
+            });
+        let node = self.nodes.len();
+        if end - start <= LEAF_SIZE {
+            self.nodes.push(Node::Leaf { aabb, start, end });
+            return node;
+        }
+
+        let axis = (&aabb.max - &aabb.min).imax();
+        let center = |index: &usize| {
+            let aabb = &aabbs[*index];
+            aabb.min[axis].clone() + aabb.max[axis].clone()
+        };
+        let mid = (start + end) / 2;
+        self.indices[start..end].select_nth_unstable_by(mid - start, |a, b| {
+            center(a).partial_cmp(&center(b)).unwrap_or(Ordering::Equal)
+        });
+
+        // A placeholder until the children are built.
+        self.nodes.push(Node::Leaf { aabb, start, end });
+        let left = self.build(aabbs, start, mid);
+        let right = self.build(aabbs, mid, end);
+        let aabb = self.nodes[node].aabb().clone();
+        self.nodes[node] = Node::Branch {
+            aabb,
+            children: [left, right],
+        };
+        node
+    }
+
+    /// The index of the primitive closest to `point`, the closest point on it
+    /// and the distance, or `None` if there are no primitives.
+    pub fn closest_point(&self, point: &Vector3<T>) -> Option<(usize, Vector3<T>, T)> {
+        let mut best: Option<(usize, Vector3<T>, T)> = None;
+        let mut stack = Vec::new();
+        if let Some(root) = self.nodes.first() {
+            stack.push((0, root.aabb().distance_squared(point)));
+        }
+        while let Some((node, bound)) = stack.pop() {
+            if matches!(best, Some((.., ref best)) if bound >= *best) {
+                continue;
+            }
+            match &self.nodes[node] {
+                Node::Leaf { start, end, .. } => {
+                    for &index in &self.indices[*start..*end] {
+                        let closest = self.primitives[index].closest_point(point);
+                        let distance = (&closest - point).norm_squared();
+                        if !matches!(best, Some((.., ref best)) if distance >= *best) {
+                            best = Some((index, closest, distance));
+                        }
+                    }
+                }
+                Node::Branch { children, .. } => {
+                    let [a, b] = children
+                        .map(|child| (child, self.nodes[child].aabb().distance_squared(point)));
+                    // Visits the nearer child first.
+                    let (near, far) = if a.1 <= b.1 { (a, b) } else { (b, a) };
+                    stack.push(far);
+                    stack.push(near);
+                }
+            }
+        }
+        best.map(|(index, closest, distance)| (index, closest, distance.sqrt()))
+    }
+
+    /// The indices of the primitives overlapping `aabb`.
+    pub fn overlapping(&self, aabb: &Aabb<T>) -> Vec<usize> {
+        let mut ret = Vec::new();
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if !node.aabb().overlaps(aabb) {
+                continue;
+            }
+            match node {
+                Node::Leaf { start, end, .. } => ret.extend(
+                    { self.indices[*start..*end].iter() }
+                        .filter(|&&index| self.primitives[index].overlaps(aabb)),
+                ),
+                Node::Branch { children, .. } => stack.extend(children),
+            }
+        }
+        ret
+    }
+}
+
+impl<T: RealField, P: BvhPrimitive<T> + RayIntersect<T>> Bvh<T, P> {
+    /// The index of the primitive that `ray` hits first within `[0, max]` and
+    /// the parameter of the hit.
+    pub fn ray_cast(&self, ray: &Ray<T>, max: T) -> Option<(usize, T)> {
+        let mut best: Option<(usize, T)> = None;
+        let mut stack = Vec::new();
+        if let Some(root) = self.nodes.first() {
+            stack.extend(root.aabb().ray_intersect(ray, max.clone()).map(|t| (0, t)));
+        }
+        while let Some((node, near)) = stack.pop() {
+            let limit = best.as_ref().map_or(max.clone(), |(_, t)| t.clone());
+            if near > limit {
+                continue;
+            }
+            match &self.nodes[node] {
+                Node::Leaf { start, end, .. } => {
+                    for &index in &self.indices[*start..*end] {
+                        let t = match self.primitives[index].ray_intersect(ray) {
+                            Some(t) => t,
+                            None => continue,
+                        };
+                        let limit = best.as_ref().map_or(max.clone(), |(_, t)| t.clone());
+                        if t <= limit {
+                            best = Some((index, t));
+                        }
+                    }
+                }
+                Node::Branch { children, .. } => {
+                    let mut hits = { children.iter() }
+                        .filter_map(|&child| {
+                            let aabb = self.nodes[child].aabb();
+                            Some((child, aabb.ray_intersect(ray, limit.clone())?))
+                        })
+                        .collect::<Vec<_>>();
+                    // Visits the nearer child first.
+                    hits.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+                    stack.extend(hits);
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{Segment, Triangle};
+
+    #[test]
+    fn test_bvh() {
+        // A grid of 2 triangles per unit square on the plane z = 0.
+        let triangles = { (0..8).flat_map(|y| (0..8).map(move |x| (x as f32, y as f32))) }
+            .flat_map(|(x, y)| {
+                let v = |dx: f32, dy: f32| Vector3::new(x + dx, y + dy, 0.);
+                [
+                    Triangle {
+                        vertices: [v(0., 0.), v(1., 0.), v(1., 1.)],
+                    },
+                    Triangle {
+                        vertices: [v(0., 0.), v(1., 1.), v(0., 1.)],
+                    },
+                ]
+            })
+            .collect::<Vec<_>>();
+        let bvh = Bvh::new(triangles);
+
+        let point = Vector3::new(2.3, 5.6, 1.5);
+        let (_, closest, distance) = bvh.closest_point(&point).unwrap();
+        assert!((closest - Vector3::new(2.3, 5.6, 0.)).norm() < 1e-5);
+        assert!((distance - 1.5).abs() < 1e-5);
+
+        let ray = Ray {
+            origin: Vector3::new(3.2, 4.7, 2.),
+            direction: Vector3::new(0., 0., -1.),
+        };
+        let (index, t) = bvh.ray_cast(&ray, 10.).unwrap();
+        assert!((t - 2.).abs() < 1e-5);
+        assert!(Triangle::ray_intersect(&bvh.primitives()[index], &ray).is_some());
+        assert!(bvh.ray_cast(&ray, 1.).is_none());
+
+        let aabb = Aabb {
+            min: Vector3::new(0.2, 0.2, -1.),
+            max: Vector3::new(1.4, 0.8, 1.),
+        };
+        assert_eq!(bvh.overlapping(&aabb).len(), 4);
+
+        let segments = Bvh::new(vec![Segment {
+            from: Vector3::new(0., 0., 0.),
+            to: Vector3::new(2., 0., 0.),
+        }]);
+        let (_, closest, _) = segments.closest_point(&Vector3::new(3., 1., 0.)).unwrap();
+        assert_eq!(closest, Vector3::new(2., 0., 0.));
+        assert_eq!(segments.overlapping(&aabb).len(), 0);
+    }
+}
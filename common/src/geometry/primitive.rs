@@ -0,0 +1,167 @@
+use nalgebra::{RealField, Scalar, Vector3};
+
+use super::bvh::{Aabb, BvhPrimitive, Ray, RayIntersect};
+
+/// The feature of a triangle closest to a point, with the indices of the
+/// vertices within the triangle.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TriangleFeature {
+    Vertex(usize),
+    Edge(usize, usize),
+    Face,
+}
+
+/// A triangle in 3D space.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Triangle<T: Scalar> {
+    pub vertices: [Vector3<T>; 3],
+}
+
+impl<T: RealField> Triangle<T> {
+    /// The unit normal, whose direction follows the right-hand rule.
+    pub fn normal(&self) -> Vector3<T> {
+        let [a, b, c] = &self.vertices;
+        (b - a).cross(&(c - a)).normalize()
+    }
+
+    /// The point of the triangle closest to `point`, and the feature it's on,
+    /// with the method from *Real-Time Collision Detection* by Ericson.
+    pub fn closest_feature(&self, point: &Vector3<T>) -> (Vector3<T>, TriangleFeature) {
+        let [a, b, c] = &self.vertices;
+        let (ab, ac, ap) = (b - a, c - a, point - a);
+        let (d1, d2) = (ab.dot(&ap), ac.dot(&ap));
+        if d1 <= T::zero() && d2 <= T::zero() {
+            return (a.clone(), TriangleFeature::Vertex(0));
+        }
+
+        let bp = point - b;
+        let (d3, d4) = (ab.dot(&bp), ac.dot(&bp));
+        if d3 >= T::zero() && d4 <= d3 {
+            return (b.clone(), TriangleFeature::Vertex(1));
+        }
+
+        let vc = d1.clone() * d4.clone() - d3.clone() * d2.clone();
+        if vc <= T::zero() && d1 >= T::zero() && d3 <= T::zero() {
+            let v = d1.clone() / (d1.clone() - d3.clone());
+            return (a + ab * v, TriangleFeature::Edge(0, 1));
+        }
+
+        let cp = point - c;
+        let (d5, d6) = (ab.dot(&cp), ac.dot(&cp));
+        if d6 >= T::zero() && d5 <= d6 {
+            return (c.clone(), TriangleFeature::Vertex(2));
+        }
+
+        let vb = d5.clone() * d2.clone() - d1 * d6.clone();
+        if vb <= T::zero() && d2 >= T::zero() && d6 <= T::zero() {
+            let w = d2.clone() / (d2 - d6);
+            return (a + ac * w, TriangleFeature::Edge(0, 2));
+        }
+
+        let va = d3.clone() * d6.clone() - d5.clone() * d4.clone();
+        let (e1, e2) = (d4 - d3, d5 - d6);
+        if va <= T::zero() && e1 >= T::zero() && e2 >= T::zero() {
+            let w = e1.clone() / (e1 + e2);
+            return (b + (c - b) * w, TriangleFeature::Edge(1, 2));
+        }
+
+        let denom = (va + vb.clone() + vc.clone()).recip();
+        let (v, w) = (vb * denom.clone(), vc * denom);
+        (a + ab * v + ac * w, TriangleFeature::Face)
+    }
+}
+
+impl<T: RealField> BvhPrimitive<T> for Triangle<T> {
+    fn aabb(&self) -> Aabb<T> {
+        Aabb::from_points(self.vertices.iter()).unwrap()
+    }
+
+    fn closest_point(&self, point: &Vector3<T>) -> Vector3<T> {
+        self.closest_feature(point).0
+    }
+
+    /// The separating axis test by Akenine-Möller.
+    fn overlaps(&self, aabb: &Aabb<T>) -> bool {
+        let center = aabb.center();
+        let half = aabb.half_extents();
+        let vertices = self.vertices.clone().map(|v| v - &center);
+        let [a, b, c] = &vertices;
+        let edges = [b - a, c - b, a - c];
+
+        let separated = |axis: &Vector3<T>| {
+            let projections = vertices.clone().map(|v| v.dot(axis));
+            let min = { projections.iter().cloned() }.fold(projections[0].clone(), T::min);
+            let max = { projections.iter().cloned() }.fold(projections[0].clone(), T::max);
+            let radius = half.dot(&axis.map(|x| x.abs()));
+            min > radius.clone() || max < -radius
+        };
+
+        let unit_axes = [Vector3::x(), Vector3::y(), Vector3::z()];
+        let cross_axes =
+            { unit_axes.iter() }.flat_map(|axis| edges.iter().map(move |edge| axis.cross(edge)));
+        let normal = edges[0].cross(&edges[1]);
+        !{ unit_axes.iter().cloned() }
+            .chain(cross_axes)
+            .chain([normal])
+            .any(|axis| separated(&axis))
+    }
+}
+
+impl<T: RealField> RayIntersect<T> for Triangle<T> {
+    /// The intersection by Möller and Trumbore.
+    fn ray_intersect(&self, ray: &Ray<T>) -> Option<T> {
+        let [a, b, c] = &self.vertices;
+        let (ab, ac) = (b - a, c - a);
+        let p = ray.direction.cross(&ac);
+        let det = ab.dot(&p);
+        if det.clone().abs() <= T::default_epsilon() {
+            return None;
+        }
+        let inv = det.recip();
+
+        let s = &ray.origin - a;
+        let u = s.dot(&p) * inv.clone();
+        if u < T::zero() || u > T::one() {
+            return None;
+        }
+        let q = s.cross(&ab);
+        let v = ray.direction.dot(&q) * inv.clone();
+        if v < T::zero() || u + v.clone() > T::one() {
+            return None;
+        }
+
+        let t = ac.dot(&q) * inv;
+        (t >= T::zero()).then_some(t)
+    }
+}
+
+/// A line segment in 3D space.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment<T: Scalar> {
+    pub from: Vector3<T>,
+    pub to: Vector3<T>,
+}
+
+impl<T: RealField> BvhPrimitive<T> for Segment<T> {
+    fn aabb(&self) -> Aabb<T> {
+        Aabb::from_points([&self.from, &self.to].into_iter()).unwrap()
+    }
+
+    fn closest_point(&self, point: &Vector3<T>) -> Vector3<T> {
+        let direction = &self.to - &self.from;
+        let norm_squared = direction.norm_squared();
+        if norm_squared <= T::default_epsilon() {
+            return self.from.clone();
+        }
+        let t = (point - &self.from).dot(&direction) / norm_squared;
+        &self.from + direction * t.clamp(T::zero(), T::one())
+    }
+
+    fn overlaps(&self, aabb: &Aabb<T>) -> bool {
+        let ray = Ray {
+            origin: self.from.clone(),
+            direction: &self.to - &self.from,
+        };
+        aabb.ray_intersect(&ray, T::one()).is_some()
+    }
+}
@@ -8,11 +8,15 @@
 
 use nalgebra::{Matrix3, RealField, Vector3, Vector4};
 
+pub mod camera;
 pub mod feature;
 pub mod filter;
+pub mod geometry;
+pub mod mesh;
 pub mod point;
 pub mod point_cloud;
 pub mod range_image;
+pub mod robust;
 pub mod search;
 
 pub fn cov_matrix<'a, T, Iter>(coords: Iter) -> Option<Matrix3<T>>
@@ -60,6 +64,82 @@ where
     Some((normal.insert_row(3, T::zero()), curvature))
 }
 
+/// Labels the 4-connected components of the `valid` cells of a row-major
+/// grid `width` cells wide, e.g. an organized point cloud or a range image,
+/// where `connected` decides whether a cell and its neighbor are in the same
+/// component. If `wrap`, the first and the last columns neighbor each other,
+/// e.g. in full panoramas.
+///
+/// Returns the labels of the cells, which are `None` for the invalid cells
+/// and the ones in components smaller than `min_size`, and the number of the
+/// components.
+pub fn flood_fill<V, C>(
+    width: usize,
+    len: usize,
+    wrap: bool,
+    min_size: usize,
+    mut valid: V,
+    mut connected: C,
+) -> (Vec<Option<usize>>, usize)
+where
+    V: FnMut(usize) -> bool,
+    C: FnMut(usize, usize) -> bool,
+{
+    let mut labels = vec![None; len];
+    let mut num = 0;
+    let mut queue = Vec::new();
+    let mut component = Vec::new();
+    for seed in 0..len {
+        if labels[seed].is_some() || !valid(seed) {
+            continue;
+        }
+
+        let label = Some(num);
+        labels[seed] = label;
+        component.clear();
+        queue.push(seed);
+        while let Some(index) = queue.pop() {
+            component.push(index);
+
+            let x = index % width;
+            let left = if x > 0 {
+                Some(index - 1)
+            } else {
+                wrap.then(|| index + width - 1)
+            };
+            let right = if x + 1 < width {
+                Some(index + 1)
+            } else {
+                wrap.then(|| index + 1 - width)
+            };
+            let up = index.checked_sub(width);
+            let down = Some(index + width).filter(|&down| down < len);
+            for neighbor in [left, right, up, down].into_iter().flatten() {
+                if labels[neighbor].is_none() && valid(neighbor) && connected(index, neighbor) {
+                    labels[neighbor] = label;
+                    queue.push(neighbor);
+                }
+            }
+        }
+
+        if component.len() < min_size {
+            // Marks the small component as visited until all are grown.
+            for &index in &component {
+                labels[index] = Some(usize::MAX);
+            }
+        } else {
+            num += 1;
+        }
+    }
+
+    for label in &mut labels {
+        if *label == Some(usize::MAX) {
+            *label = None;
+        }
+    }
+    (labels, num)
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Interpolation {
     None,
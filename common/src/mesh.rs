@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use nalgebra::{convert, RealField, Scalar, Vector3, Vector4};
+
+use crate::{
+    geometry::{Bvh, Triangle, TriangleFeature},
+    point::Point,
+    point_cloud::PointCloud,
+};
+
+/// Point-to-mesh distance queries over a triangle mesh of `vertices` and
+/// `triangles`, accelerated by a bounding volume hierarchy, e.g. for
+/// comparing scans against CAD models.
+///
+/// Signed distances are positive on the sides the normals of the triangles
+/// point to, and are decided by the angle-weighted pseudo-normals of the
+/// closest features by Bærentzen and Aanæs, which are correct for closed
+/// and consistently oriented meshes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeshDistance<T: Scalar> {
+    indices: Vec<[usize; 3]>,
+    vertex_normals: Vec<Vector3<T>>,
+    edge_normals: HashMap<[usize; 2], Vector3<T>>,
+    bvh: Bvh<T, Triangle<T>>,
+}
+
+fn edge_key(a: usize, b: usize) -> [usize; 2] {
+    if a < b {
+        [a, b]
+    } else {
+        [b, a]
+    }
+}
+
+impl<T: RealField> MeshDistance<T> {
+    /// # Panics
+    ///
+    /// Panics if any triangle refers to a nonexistent vertex.
+    pub fn new<P: Point<Data = T>>(vertices: &PointCloud<P>, triangles: &[[usize; 3]]) -> Self {
+        let coords = |index: usize| vertices[index].coords().xyz();
+        let indices = triangles.to_vec();
+        let triangles = { indices.iter() }
+            .map(|triangle| Triangle {
+                vertices: triangle.map(coords),
+            })
+            .collect::<Vec<_>>();
+
+        let mut vertex_normals = vec![Vector3::zeros(); vertices.len()];
+        let mut edge_normals = HashMap::<_, Vector3<T>>::new();
+        for (triangle, index) in triangles.iter().zip(&indices) {
+            let normal = triangle.normal();
+            if !normal.iter().all(|x| x.is_finite()) {
+                continue;
+            }
+            for i in 0..3 {
+                let [a, b, c] = [i, (i + 1) % 3, (i + 2) % 3].map(|j| &triangle.vertices[j]);
+                let angle = (b - a).angle(&(c - a));
+                vertex_normals[index[i]] += &normal * angle;
+
+                let key = edge_key(index[i], index[(i + 1) % 3]);
+                *edge_normals.entry(key).or_insert_with(Vector3::zeros) += &normal;
+            }
+        }
+
+        let bvh = Bvh::new(triangles);
+        MeshDistance {
+            indices,
+            vertex_normals,
+            edge_normals,
+            bvh,
+        }
+    }
+
+    /// The index of the triangle closest to `coords`, the closest point on it
+    /// and the feature it's on, or `None` if the mesh is empty.
+    pub fn closest(&self, coords: &Vector4<T>) -> Option<(usize, Vector4<T>, TriangleFeature)> {
+        let point = coords.xyz();
+        let (index, ..) = self.bvh.closest_point(&point)?;
+        let (closest, feature) = self.bvh.primitives()[index].closest_feature(&point);
+        Some((index, closest.insert_row(3, T::one()), feature))
+    }
+
+    /// The unsigned distance from `coords` to the mesh.
+    pub fn distance(&self, coords: &Vector4<T>) -> Option<T> {
+        let (_, closest, _) = self.closest(coords)?;
+        Some((closest - coords).xyz().norm())
+    }
+
+    /// The signed distance from `coords` to the mesh.
+    pub fn signed_distance(&self, coords: &Vector4<T>) -> Option<T> {
+        let (index, closest, feature) = self.closest(coords)?;
+        let triangle = &self.indices[index];
+        let normal = match feature {
+            TriangleFeature::Vertex(i) => self.vertex_normals[triangle[i]].clone(),
+            TriangleFeature::Edge(i, j) => {
+                // Edges touched only by degenerate triangles have no
+                // pseudo-normals.
+                let key = edge_key(triangle[i], triangle[j]);
+                match self.edge_normals.get(&key) {
+                    Some(normal) => normal.clone(),
+                    None => self.bvh.primitives()[index].normal(),
+                }
+            }
+            TriangleFeature::Face => self.bvh.primitives()[index].normal(),
+        };
+
+        let side = (coords - closest).xyz();
+        let distance = side.norm();
+        Some(if side.dot(&normal) < T::zero() {
+            -distance
+        } else {
+            distance
+        })
+    }
+
+    /// The (signed if `signed`) distances from the points of `input` to the
+    /// mesh, or NaN for the non-finite points.
+    pub fn deviations<P: Point<Data = T>>(&self, input: &PointCloud<P>, signed: bool) -> Vec<T> {
+        { input.iter() }
+            .map(|point| {
+                let distance = if !point.is_finite() {
+                    None
+                } else if signed {
+                    self.signed_distance(point.coords())
+                } else {
+                    self.distance(point.coords())
+                };
+                distance.unwrap_or_else(|| convert(f64::NAN))
+            })
+            .collect()
+    }
+}
+
+/// The statistics of the deviations of a point cloud from a mesh, e.g. from
+/// [`MeshDistance::deviations`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviationStats<T: Scalar> {
+    /// The number of the finite deviations.
+    pub count: usize,
+    pub min: T,
+    pub max: T,
+    pub mean: T,
+    /// The root mean square.
+    pub rms: T,
+    pub std_dev: T,
+}
+
+impl<T: RealField> DeviationStats<T> {
+    /// Computes the statistics of the finite `deviations`, or returns `None`
+    /// if there are none.
+    pub fn new(deviations: &[T]) -> Option<Self> {
+        let mut finite = deviations.iter().filter(|x| x.is_finite()).cloned();
+        let first = finite.next()?;
+        let init = (
+            1,
+            first.clone(),
+            first.clone(),
+            first.clone(),
+            first.clone() * first,
+        );
+        let (count, min, max, sum, sum_sq) =
+            finite.fold(init, |(count, min, max, sum, sum_sq), x| {
+                (
+                    count + 1,
+                    min.min(x.clone()),
+                    max.max(x.clone()),
+                    sum + x.clone(),
+                    sum_sq + x.clone() * x,
+                )
+            });
+
+        let num = T::from_usize(count).unwrap();
+        let mean = sum / num.clone();
+        let mean_sq = sum_sq / num;
+        let variance = (mean_sq.clone() - mean.clone() * mean.clone()).max(T::zero());
+        Some(DeviationStats {
+            count,
+            min,
+            max,
+            mean,
+            rms: mean_sq.sqrt(),
+            std_dev: variance.sqrt(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+
+    use super::*;
+    use crate::point::Point3;
+
+    #[test]
+    fn test_mesh_distance() {
+        // A unit tetrahedron with outward normals.
+        let vertices = [[0., 0., 0.], [1., 0., 0.], [0., 1., 0.], [0., 0., 1.]]
+            .map(|[x, y, z]| Point3::default().with_coords(Vector4::new(x, y, z, 1.)));
+        let vertices = PointCloud::from_vec(vertices.to_vec(), 1);
+        let triangles = [[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]];
+        let mesh = MeshDistance::new(&vertices, &triangles);
+
+        let inside = Vector4::new(0.1, 0.1, 0.1, 1.);
+        assert!((mesh.signed_distance(&inside).unwrap() + 0.1).abs() < 1e-6);
+
+        let below = Vector4::new(0.2, 0.3, -2., 1.);
+        assert!((mesh.signed_distance(&below).unwrap() - 2.).abs() < 1e-6);
+
+        // Closest to the vertex at the origin.
+        let corner = Vector4::new(-1., -1., -1., 1.);
+        let (_, _, feature) = mesh.closest(&corner).unwrap();
+        assert!(matches!(feature, TriangleFeature::Vertex(_)));
+        assert!((mesh.signed_distance(&corner).unwrap() - 3f32.sqrt()).abs() < 1e-6);
+
+        let stats = DeviationStats::new(&[1., -1., f32::NAN, 3.]).unwrap();
+        assert_eq!(stats.count, 3);
+        assert_eq!((stats.min, stats.max, stats.mean), (-1., 3., 1.));
+    }
+
+    #[test]
+    fn test_degenerate_triangle() {
+        let vertices = [
+            [0., 0., 0.],
+            [1., 0., 0.],
+            [0., 1., 0.],
+            [0., 0., 1.],
+            [5., 0., 0.],
+            [6., 0., 0.],
+            [7., 0., 0.],
+        ]
+        .map(|[x, y, z]| Point3::default().with_coords(Vector4::new(x, y, z, 1.)));
+        let vertices = PointCloud::from_vec(vertices.to_vec(), 1);
+        let triangles = [[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3], [4, 5, 6]];
+        let mesh = MeshDistance::new(&vertices, &triangles);
+
+        // Closest to the edge from 4 to 6 of the zero-area triangle only.
+        let point = Vector4::new(6.5, 1., 0., 1.);
+        let (index, _, feature) = mesh.closest(&point).unwrap();
+        assert_eq!(index, 4);
+        assert!(matches!(feature, TriangleFeature::Edge(..)));
+        assert!((mesh.signed_distance(&point).unwrap().abs() - 1.).abs() < 1e-6);
+    }
+}
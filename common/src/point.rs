@@ -9,7 +9,7 @@ use std::{array, collections::HashMap};
 use nalgebra::{ComplexField, RawStorage, RawStorageMut, SVector, Scalar, ToConst, Vector4};
 use num::FromPrimitive;
 use static_assertions::const_assert;
-use typenum::{Unsigned, U10, U4, U5, U8, U9};
+use typenum::{Unsigned, U10, U4, U5, U6, U7, U8, U9};
 
 pub use self::{
     centroid::{Centroid, CentroidBuilder},
@@ -110,6 +110,53 @@ pub trait PointRgba: Point {
         self
     }
 
+    /// The color in HSV, i.e. `[h, s, v]` where the hue `h` is in degrees
+    /// within `[0, 360)`, and the saturation `s` and the value `v` are within
+    /// `[0, 1]`.
+    #[inline]
+    fn hsv(&self) -> [f32; 3] {
+        let [b, g, r, _] = self.rgba_array().map(|c| c / 255.);
+        let max = r.max(g).max(b);
+        let delta = max - r.min(g).min(b);
+
+        let h = if delta <= 0. {
+            0.
+        } else if max == r {
+            60. * ((g - b) / delta).rem_euclid(6.)
+        } else if max == g {
+            60. * ((b - r) / delta + 2.)
+        } else {
+            60. * ((r - g) / delta + 4.)
+        };
+        let s = if max > 0. { delta / max } else { 0. };
+        [h, s, max]
+    }
+
+    /// Sets the color from HSV as in [`PointRgba::hsv`], keeping the alpha.
+    #[inline]
+    fn set_hsv(&mut self, [h, s, v]: [f32; 3]) {
+        let h = h.rem_euclid(360.) / 60.;
+        let c = v * s;
+        let x = c * (1. - (h % 2. - 1.).abs());
+        let [r, g, b] = match h as u32 {
+            0 => [c, x, 0.],
+            1 => [x, c, 0.],
+            2 => [0., c, x],
+            3 => [0., x, c],
+            4 => [x, 0., c],
+            _ => [c, 0., x],
+        };
+        let m = v - c;
+        let alpha = (self.rgba() >> 24) as f32;
+        let [b, g, r] = [b, g, r].map(|c| ((c + m) * 255.).round());
+        self.set_rgba_array(&[b, g, r, alpha])
+    }
+    #[inline]
+    fn with_hsv(mut self, hsv: [f32; 3]) -> Self {
+        self.set_hsv(hsv);
+        self
+    }
+
     fn fields() -> array::IntoIter<FieldInfo, 1>;
 
     type CentroidAccumulator = [f32; 4];
@@ -266,6 +313,39 @@ pub trait PointLabel: Point {
     }
 }
 
+/// The points of multi-echo lidars, which record several returns of a beam,
+/// e.g. from a vegetation canopy and the ground behind it.
+pub trait PointEcho: Point {
+    /// The index of the return within its beam, starting from 0 for the
+    /// first (nearest) one.
+    fn echo(&self) -> u32;
+
+    fn set_echo(&mut self, echo: u32);
+    #[inline]
+    fn with_echo(mut self, echo: u32) -> Self {
+        self.set_echo(echo);
+        self
+    }
+
+    fn fields() -> array::IntoIter<FieldInfo, 1>;
+}
+
+/// The points of spinning lidars, which record the lasers, i.e. the rings of
+/// the scans, that measured them.
+pub trait PointRing: Point {
+    /// The index of the laser, usually increasing with its elevation.
+    fn ring(&self) -> u32;
+
+    fn set_ring(&mut self, ring: u32);
+    #[inline]
+    fn with_ring(mut self, ring: u32) -> Self {
+        self.set_ring(ring);
+        self
+    }
+
+    fn fields() -> array::IntoIter<FieldInfo, 1>;
+}
+
 pub trait PointViewpoint: Point {
     fn viewpoint(&self) -> &Vector4<Self::Data>;
 
@@ -319,6 +399,17 @@ define_points! {
         viewpoint: PointViewpoint [4],
     }
 
+    pub struct Point3RangeIE<f32, U7> {
+        range: PointRange [4],
+        intensity: PointIntensity [5],
+        echo: PointEcho [6],
+    }
+
+    pub struct Point3IR<f32, U6> {
+        intensity: PointIntensity [4],
+        ring: PointRing [5],
+    }
+
     #[non_point]
     pub struct Normal3<f32, U4> {
         normal: Normal [0, 3],
@@ -339,6 +430,34 @@ impl Centroid for Point3Range {
     }
 }
 
+impl Centroid for Point3RangeIE {
+    type Accumulator = Vector4<f32>;
+
+    type Result = Point3;
+
+    fn accumulate(&self, accum: &mut Self::Accumulator) {
+        *accum += self.coords();
+    }
+
+    fn compute(accum: Self::Accumulator, num: usize) -> Self::Result {
+        Point3(accum / (num as f32))
+    }
+}
+
+impl Centroid for Point3IR {
+    type Accumulator = Vector4<f32>;
+
+    type Result = Point3;
+
+    fn accumulate(&self, accum: &mut Self::Accumulator) {
+        *accum += self.coords();
+    }
+
+    fn compute(accum: Self::Accumulator, num: usize) -> Self::Result {
+        Point3(accum / (num as f32))
+    }
+}
+
 impl Centroid for Point3V {
     type Accumulator = Vector4<f32>;
 
@@ -400,5 +519,20 @@ mod tests {
                 f32::from_bits(0xFF000000)
             ]
         );
+
+        let point = Point3RangeIE::default()
+            .with_range(5.)
+            .with_intensity(0.25)
+            .with_echo(2);
+        assert_eq!(point.echo(), 2);
+        let names = <Point3RangeIE as DataFields>::fields().map(|field| field.name);
+        assert!(names.eq(["x", "y", "z", "range", "intensity", "echo"]));
+
+        let point = Point3Rgba::default().with_rgba(0xff_ff_80_00);
+        let [h, s, v] = point.hsv();
+        assert!((h - 30.1176).abs() < 1e-3 && s == 1. && v == 1.);
+        assert_eq!(point.with_hsv([h, s, v]).rgba(), 0xff_ff_80_00);
+        let point = Point3Rgba::default().with_hsv([240., 0.5, 1.]);
+        assert_eq!(point.rgba(), 0x00_80_80_ff);
     }
 }
@@ -172,6 +172,42 @@ macro_rules! __define_point {
             }
         }
     };
+    (echo $get:ident: $trait:ident, $type:ident < $data:ident, $num:ident > , $index:literal) => {
+        impl $trait for $type {
+            #[inline]
+            fn $get(&self) -> u32 {
+                self.0[$index].to_bits()
+            }
+
+            #[inline]
+            fn set_echo(&mut self, echo: u32) {
+                self.0[$index] = $data::from_bits(echo)
+            }
+
+            #[inline]
+            fn fields() -> array::IntoIter<FieldInfo, 1> {
+                [FieldInfo::single::<Self::Data>("echo", $index)].into_iter()
+            }
+        }
+    };
+    (ring $get:ident: $trait:ident, $type:ident < $data:ident, $num:ident > , $index:literal) => {
+        impl $trait for $type {
+            #[inline]
+            fn $get(&self) -> u32 {
+                self.0[$index].to_bits()
+            }
+
+            #[inline]
+            fn set_ring(&mut self, ring: u32) {
+                self.0[$index] = $data::from_bits(ring)
+            }
+
+            #[inline]
+            fn fields() -> array::IntoIter<FieldInfo, 1> {
+                [FieldInfo::single::<Self::Data>("ring", $index)].into_iter()
+            }
+        }
+    };
     (viewpoint $get:ident: $trait:ident, $type:ident < $data:ident, $num:ident > , $index:literal) => {
         impl $trait for $type {
             #[inline]
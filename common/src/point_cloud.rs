@@ -1,4 +1,8 @@
+mod diff;
+mod label;
+mod metadata;
 mod reference;
+mod scan_line;
 mod transforms;
 
 use std::{
@@ -8,18 +12,29 @@ use std::{
 };
 
 use nalgebra::{ComplexField, RealField, Vector4};
+use num::ToPrimitive;
 
-pub use self::reference::{AsPointCloud, PointCloudRef};
 use self::transforms::Transform;
+pub use self::{
+    diff::{CloudDiff, FieldDiff},
+    label::LabelStats,
+    metadata::Metadata,
+    reference::{AsPointCloud, PointCloudRef},
+    scan_line::ScanLines,
+    transforms::{PoseProvider, PoseTrajectory},
+};
 use crate::point::{Data, Normal, Point};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PointCloud<P> {
     storage: Vec<P>,
     width: usize,
     bounded: bool,
+    metadata: Metadata,
 }
 
+impl<P: Eq> Eq for PointCloud<P> {}
+
 impl<P> PointCloud<P> {
     #[inline]
     pub fn width(&self) -> usize {
@@ -56,6 +71,22 @@ impl<P> PointCloud<P> {
         &mut self.storage
     }
 
+    #[inline]
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    #[inline]
+    pub fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+
+    #[inline]
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
     #[inline]
     pub fn select<'a>(&'a self, indices: Cow<'a, [usize]>) -> PointCloudRef<'a, P> {
         PointCloudRef::new(self, Some(indices))
@@ -70,6 +101,7 @@ impl<P: Clone> PointCloud<P> {
                 .collect(),
             width,
             bounded: self.bounded,
+            metadata: self.metadata.clone(),
         })
     }
 
@@ -128,6 +160,7 @@ impl<P> PointCloud<P> {
             storage: Vec::new(),
             width: 0,
             bounded: true,
+            metadata: Metadata::default(),
         }
     }
 
@@ -141,6 +174,7 @@ impl<P> PointCloud<P> {
             storage,
             width,
             bounded,
+            metadata: Metadata::default(),
         }
     }
 }
@@ -171,6 +205,7 @@ impl<P: Clone> PointCloud<P> {
 
         other.width = self.height();
         other.bounded = self.bounded;
+        other.metadata.clone_from(&self.metadata);
     }
 }
 
@@ -182,6 +217,7 @@ impl<P: Data> PointCloud<P> {
                 storage,
                 width,
                 bounded,
+                metadata: Metadata::default(),
             })
         } else {
             Err(storage)
@@ -245,6 +281,7 @@ where
 
         out.width = self.width;
         out.bounded = self.bounded;
+        out.metadata = self.metadata.transformed(z);
 
         if self.bounded {
             for (from, to) in self.storage.iter().zip(out.storage.iter_mut()) {
@@ -266,7 +303,7 @@ where
         R: Data,
     {
         let iter = self.storage.iter().map(f);
-        PointCloud::from_vec(iter.collect(), self.width)
+        PointCloud::from_vec(iter.collect(), self.width).with_metadata(self.metadata.clone())
     }
 
     pub fn zip_map<F, Q, R>(&self, other: &PointCloud<Q>, mut f: F) -> PointCloud<R>
@@ -281,7 +318,7 @@ where
         let iter = { self.storage.iter() }
             .zip(other.storage.iter())
             .map(|(p, q)| f(p, q));
-        PointCloud::from_vec(iter.collect(), self.width)
+        PointCloud::from_vec(iter.collect(), self.width).with_metadata(self.metadata.clone())
     }
 }
 
@@ -301,5 +338,105 @@ where
             point.coords_mut().y -= centroid.y.clone();
             point.coords_mut().z -= centroid.z.clone();
         }
+        self.metadata.sensor_origin -= centroid.xyz().map(metadata::to_f32);
+    }
+}
+
+/// Spreads the lower 21 bits of `x` to every third bit.
+fn spread_bits(x: u64) -> u64 {
+    let mut x = x & 0x1f_ffff;
+    x = (x | x << 32) & 0x1f_0000_0000_ffff;
+    x = (x | x << 16) & 0x1f_0000_ff00_00ff;
+    x = (x | x << 8) & 0x100f_00f0_0f00_f00f;
+    x = (x | x << 4) & 0x10c3_0c30_c30c_30c3;
+    x = (x | x << 2) & 0x1249_2492_4924_9249;
+    x
+}
+
+impl<P: Point> PointCloud<P>
+where
+    <P as Data>::Data: RealField + ToPrimitive,
+{
+    /// The Morton code of every point, with the coordinates quantized by
+    /// `resolution` from `min`, or `None` for non-finite points.
+    fn morton_codes(&self, min: &Vector4<P::Data>, resolution: P::Data) -> Vec<Option<u64>> {
+        { self.storage.iter() }
+            .map(|point| {
+                if !point.is_finite() {
+                    return None;
+                }
+                let cell = (point.coords() - min) / resolution.clone();
+                let [x, y, z] = [&cell.x, &cell.y, &cell.z]
+                    .map(|x| x.clone().floor().to_u64().unwrap_or(0).min(0x1f_ffff));
+                Some(spread_bits(x) | spread_bits(y) << 1 | spread_bits(z) << 2)
+            })
+            .collect()
+    }
+
+    /// Reorders the points along the Morton curve (Z-order) of the voxels of
+    /// `resolution`, so that nearby points are mostly stored nearby, and
+    /// returns the permutation, i.e. the original index of every point.
+    ///
+    /// Non-finite points are moved to the end in their original order, and
+    /// the point cloud becomes unorganized.
+    pub fn sort_morton(&mut self, resolution: P::Data) -> Vec<usize> {
+        let mut permutation = (0..self.storage.len()).collect::<Vec<_>>();
+        let [min, _] = match self.finite_bound() {
+            Some(bound) => bound,
+            None => {
+                self.width = 1;
+                return permutation;
+            }
+        };
+
+        let codes = self.morton_codes(&min, resolution);
+        permutation.sort_by_key(|&index| (codes[index].is_none(), codes[index]));
+
+        let mut storage = Vec::with_capacity(self.storage.len());
+        storage.extend(permutation.iter().map(|&index| self.storage[index].clone()));
+        self.storage = storage;
+        self.width = 1;
+
+        permutation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point::Point3;
+
+    #[test]
+    fn test_sort_morton() {
+        assert_eq!(spread_bits(0b1011), 0b1_000_001_001);
+        assert_eq!(spread_bits(u64::MAX), 0x1249_2492_4924_9249);
+
+        let nan = Vector4::new(f32::NAN, 0., 0., 1.);
+        let coords = [
+            Vector4::new(1.2, 1.3, 0.1, 1.),
+            nan,
+            Vector4::new(0.1, 0.2, 1.4, 1.),
+            Vector4::new(1.5, 0.2, 0.3, 1.),
+            nan * 2.,
+            Vector4::new(0., 0., 0., 1.),
+            Vector4::new(0.3, 1.1, 0.2, 1.),
+            Vector4::new(0.4, 0.3, 0.2, 1.),
+        ];
+        let storage = { coords.iter() }
+            .map(|coords| Point3::default().with_coords(*coords))
+            .collect::<Vec<_>>();
+        let mut point_cloud = PointCloud::from_vec(storage.clone(), 4);
+
+        // The voxels in the order of (0, 0, 0), (1, 0, 0), (0, 1, 0), (1, 1,
+        // 0) and (0, 0, 1), the points in the same voxel staying in order.
+        let permutation = point_cloud.sort_morton(1.);
+        assert_eq!(permutation, [5, 7, 3, 6, 0, 2, 1, 4]);
+        assert_eq!(point_cloud.width(), 1);
+        for (point, index) in point_cloud.iter().zip(&permutation).take(6) {
+            assert_eq!(point, &storage[*index]);
+        }
+
+        let mut nans = PointCloud::<Point3>::from_vec(vec![Point3::default().with_coords(nan)], 1);
+        assert_eq!(nans.sort_morton(1.), [0]);
     }
 }
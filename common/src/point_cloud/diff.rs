@@ -0,0 +1,184 @@
+use std::{fmt, mem, slice};
+
+use nalgebra::RealField;
+
+use super::PointCloud;
+use crate::point::{Data, DataFields};
+
+/// The differences of a field between two point clouds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDiff<T> {
+    pub name: &'static str,
+    /// The maximum absolute error of the finite values.
+    pub max_error: T,
+    /// The mean absolute error of the finite values.
+    pub mean_error: T,
+    /// The number of points whose values of the field mismatch.
+    pub num_mismatches: usize,
+}
+
+/// The differences between two point clouds of the same shape, compared
+/// point by point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CloudDiff<T> {
+    pub fields: Vec<FieldDiff<T>>,
+    /// The number of points with any mismatching field.
+    pub num_mismatches: usize,
+}
+
+impl<T> CloudDiff<T> {
+    #[inline]
+    pub fn is_match(&self) -> bool {
+        self.num_mismatches == 0
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for CloudDiff<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} mismatching points", self.num_mismatches)?;
+        for field in &self.fields {
+            write!(
+                f,
+                "\n  {}: max error {}, mean error {}, {} mismatches",
+                field.name, field.max_error, field.mean_error, field.num_mismatches
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn bits_eq<T>(a: &T, b: &T) -> bool {
+    let size = mem::size_of::<T>();
+    // SAFETY: The scalars of points are plain numbers without padding.
+    unsafe {
+        let a = slice::from_raw_parts(a as *const T as *const u8, size);
+        let b = slice::from_raw_parts(b as *const T as *const u8, size);
+        a == b
+    }
+}
+
+impl<T: RealField, P: Data<Data = T> + DataFields> PointCloud<P> {
+    /// Compares the fields of every pair of points at the same index, or
+    /// returns `None` if the shapes of the point clouds differ.
+    ///
+    /// Two values match if they are bitwise identical, e.g. both are the same
+    /// NaN or packed colors, or both are finite and differ by no more than
+    /// `epsilon`.
+    pub fn diff(&self, other: &Self, epsilon: T) -> Option<CloudDiff<T>> {
+        if self.len() != other.len() || self.width != other.width {
+            return None;
+        }
+
+        let fields = P::fields().collect::<Vec<_>>();
+        let mut diffs = { fields.iter() }
+            .map(|field| FieldDiff {
+                name: field.name,
+                max_error: T::zero(),
+                mean_error: T::zero(),
+                num_mismatches: 0,
+            })
+            .collect::<Vec<_>>();
+        let mut nums = vec![0usize; fields.len()];
+
+        let mut num_mismatches = 0;
+        for (a, b) in self.iter().zip(other.iter()) {
+            let (a, b) = (a.as_slice(), b.as_slice());
+
+            let mut mismatch = false;
+            for ((field, diff), num) in fields.iter().zip(&mut diffs).zip(&mut nums) {
+                let mut field_mismatch = false;
+                for (x, y) in a[field.offset..][..field.len]
+                    .iter()
+                    .zip(&b[field.offset..][..field.len])
+                {
+                    if x.is_finite() && y.is_finite() {
+                        let error = (x.clone() - y.clone()).abs();
+                        field_mismatch |= error > epsilon;
+                        if error > diff.max_error {
+                            diff.max_error = error.clone();
+                        }
+                        diff.mean_error += error;
+                        *num += 1;
+                    } else {
+                        field_mismatch |= !bits_eq(x, y);
+                    }
+                }
+                if field_mismatch {
+                    diff.num_mismatches += 1;
+                    mismatch = true;
+                }
+            }
+            if mismatch {
+                num_mismatches += 1;
+            }
+        }
+
+        for (diff, num) in diffs.iter_mut().zip(nums) {
+            if num > 0 {
+                diff.mean_error /= T::from_usize(num).unwrap();
+            }
+        }
+
+        Some(CloudDiff {
+            fields: diffs,
+            num_mismatches,
+        })
+    }
+
+    /// Returns if the point clouds have the same shape and all their fields
+    /// match within `epsilon`. See [`PointCloud::diff`] for details.
+    #[inline]
+    pub fn approx_eq(&self, other: &Self, epsilon: T) -> bool {
+        self.diff(other, epsilon)
+            .map_or(false, |diff| diff.is_match())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+
+    use crate::{
+        point::{Point, Point3Rgba, PointRgba},
+        point_cloud::PointCloud,
+    };
+
+    #[test]
+    fn test_diff() {
+        let point = |x, rgba| {
+            Point3Rgba::default()
+                .with_coords(Vector4::new(x, 0., 0., 1.))
+                .with_rgba(rgba)
+        };
+        let a = PointCloud::from_vec(
+            vec![
+                point(0., 0xff00_0000),
+                point(1., 0xff00_00ff),
+                point(f32::NAN, 0),
+            ],
+            1,
+        );
+        let b = PointCloud::from_vec(
+            vec![
+                point(0.05, 0xff00_0000),
+                point(1., 0xff00_ff00),
+                point(f32::NAN, 0),
+            ],
+            1,
+        );
+
+        assert!(a.approx_eq(&a, 0.));
+        assert!(!a.approx_eq(&b, 0.1));
+
+        let diff = a.diff(&b, 0.1).unwrap();
+        assert_eq!(diff.num_mismatches, 1);
+        assert_eq!(diff.fields[0].name, "x");
+        assert_eq!(diff.fields[0].num_mismatches, 0);
+        assert!((diff.fields[0].max_error - 0.05).abs() < 1e-6);
+        assert_eq!(diff.fields[3].name, "rgba");
+        assert_eq!(diff.fields[3].num_mismatches, 1);
+
+        let c = PointCloud::from_vec(vec![point(0., 0)], 1);
+        assert!(a.diff(&c, 0.1).is_none());
+    }
+}
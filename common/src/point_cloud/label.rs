@@ -0,0 +1,167 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use nalgebra::{RealField, Scalar, Vector4};
+
+use super::{PointCloud, PointCloudRef};
+use crate::point::PointLabel;
+
+/// The statistics of the finite points of a label.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelStats<T: Scalar> {
+    pub size: usize,
+    pub centroid: Vector4<T>,
+    /// The minimum corner of the axis-aligned bounding box.
+    pub min: Vector4<T>,
+    /// The maximum corner of the axis-aligned bounding box.
+    pub max: Vector4<T>,
+}
+
+impl<P: PointLabel> PointCloud<P> {
+    pub fn label_indices(&self, label: u32) -> Vec<usize> {
+        { self.storage.iter().enumerate() }
+            .filter(|(_, point)| point.label() == label)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Selects the points of `label`.
+    #[inline]
+    pub fn select_label(&self, label: u32) -> PointCloudRef<'_, P> {
+        self.select(Cow::Owned(self.label_indices(label)))
+    }
+
+    /// Renumbers the labels to `0..n` in the order of their first points,
+    /// and returns the old labels indexed by the new ones.
+    pub fn compact_labels(&mut self) -> Vec<u32> {
+        let mut map = HashMap::new();
+        let mut old = Vec::new();
+        for point in self.storage.iter_mut() {
+            let label = *map.entry(point.label()).or_insert_with(|| {
+                old.push(point.label());
+                old.len() as u32 - 1
+            });
+            point.set_label(label);
+        }
+        old
+    }
+
+    /// Relabels the points of all the labels satisfying `pred` as `into`.
+    pub fn merge_labels<F>(&mut self, mut pred: F, into: u32)
+    where
+        F: FnMut(u32) -> bool,
+    {
+        for point in self.storage.iter_mut() {
+            if pred(point.label()) {
+                point.set_label(into);
+            }
+        }
+    }
+
+    /// Relabels the points of `label` satisfying `pred` as `new_label`, and
+    /// returns their number.
+    pub fn split_label<F>(&mut self, label: u32, new_label: u32, mut pred: F) -> usize
+    where
+        F: FnMut(&P) -> bool,
+    {
+        let mut num = 0;
+        for point in self.storage.iter_mut() {
+            if point.label() == label && pred(point) {
+                point.set_label(new_label);
+                num += 1;
+            }
+        }
+        num
+    }
+
+    /// Relabels every 4-connected component of the points with the same
+    /// label in the organized point cloud as `0..n`, in the order of their
+    /// first points, and returns `n`.
+    pub fn label_components(&mut self) -> usize {
+        let storage = &self.storage;
+        let (components, num) = crate::flood_fill(
+            self.width,
+            storage.len(),
+            false,
+            0,
+            |_| true,
+            |index, neighbor| storage[index].label() == storage[neighbor].label(),
+        );
+
+        for (point, component) in self.storage.iter_mut().zip(components) {
+            point.set_label(component.unwrap() as u32);
+        }
+        num
+    }
+}
+
+impl<T: RealField, P: PointLabel<Data = T>> PointCloud<P> {
+    /// Computes the statistics of every label with finite points.
+    pub fn label_stats(&self) -> HashMap<u32, LabelStats<T>> {
+        let mut stats = HashMap::<u32, LabelStats<T>>::new();
+        for point in self.storage.iter() {
+            let coords = point.coords();
+            if !coords.iter().all(|x| x.is_finite()) {
+                continue;
+            }
+            match stats.get_mut(&point.label()) {
+                Some(stats) => {
+                    stats.size += 1;
+                    stats.centroid += coords;
+                    stats.min = stats.min.inf(coords);
+                    stats.max = stats.max.sup(coords);
+                }
+                None => {
+                    let init = LabelStats {
+                        size: 1,
+                        centroid: coords.clone(),
+                        min: coords.clone(),
+                        max: coords.clone(),
+                    };
+                    stats.insert(point.label(), init);
+                }
+            }
+        }
+
+        for stats in stats.values_mut() {
+            stats.centroid /= T::from_usize(stats.size).unwrap();
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+
+    use crate::{
+        point::{Point, Point3LN, PointLabel},
+        point_cloud::PointCloud,
+    };
+
+    #[test]
+    fn test_labels() {
+        let point = |x: f32, label| {
+            Point3LN::default()
+                .with_coords(Vector4::new(x, 0., 0., 1.))
+                .with_label(label)
+        };
+        let mut cloud = PointCloud::from_vec(
+            vec![point(0., 7), point(1., 7), point(2., 3), point(3., 7)],
+            4,
+        );
+
+        let stats = cloud.label_stats();
+        assert_eq!(stats[&7].size, 3);
+        assert!((stats[&7].centroid.x - 4. / 3.).abs() < 1e-6);
+        assert_eq!(stats[&7].max.x, 3.);
+
+        assert_eq!(cloud.select_label(3).indices(), Some(&[2][..]));
+        assert_eq!(cloud.compact_labels(), [7, 3]);
+        assert_eq!(cloud.label_components(), 3);
+        assert_eq!(cloud.label_indices(2), [3]);
+
+        cloud.merge_labels(|label| label > 0, 0);
+        assert_eq!(cloud.label_indices(0).len(), 4);
+        assert_eq!(cloud.split_label(0, 1, |point| point.coords().x > 1.5), 2);
+    }
+}
@@ -0,0 +1,109 @@
+use nalgebra::{
+    convert, try_convert, ComplexField, Isometry3, Matrix3, Translation3, UnitQuaternion, Vector3,
+    Vector4,
+};
+
+use super::transforms::Transform;
+
+/// The acquisition metadata of a point cloud, like the headers of point
+/// clouds in PCL and ROS.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    /// The origin of the sensor in the frame of the point cloud.
+    pub sensor_origin: Vector3<f32>,
+    /// The orientation of the sensor in the frame of the point cloud.
+    pub sensor_orientation: UnitQuaternion<f32>,
+    /// The acquisition time in microseconds.
+    pub stamp: Option<u64>,
+    /// The name of the coordinate frame of the point cloud.
+    pub frame_id: Option<String>,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Metadata {
+            sensor_origin: Vector3::zeros(),
+            sensor_orientation: UnitQuaternion::identity(),
+            stamp: None,
+            frame_id: None,
+        }
+    }
+}
+
+pub(super) fn from_f32<T: ComplexField>(x: f32) -> T {
+    convert(f64::from(x))
+}
+
+pub(super) fn to_f32<T: ComplexField>(x: T) -> f32 {
+    try_convert::<T, f64>(x).map_or(f32::NAN, |x| x as f32)
+}
+
+impl Metadata {
+    #[inline]
+    pub fn sensor_pose(&self) -> Isometry3<f32> {
+        Isometry3::from_parts(
+            Translation3::from(self.sensor_origin),
+            self.sensor_orientation,
+        )
+    }
+
+    #[inline]
+    pub fn set_sensor_pose(&mut self, pose: &Isometry3<f32>) {
+        self.sensor_origin = pose.translation.vector;
+        self.sensor_orientation = pose.rotation;
+    }
+
+    /// Moves the sensor along with the point cloud transformed by `z`.
+    pub(super) fn transformed<T: ComplexField, Z: Transform<T>>(&self, z: &Z) -> Self {
+        let mut origin = Vector4::zeros();
+        let from = self.sensor_origin.map(from_f32).insert_row(3, T::one());
+        z.se3(&from, &mut origin);
+
+        let rotation = self.sensor_orientation.to_rotation_matrix();
+        let mut axes = Matrix3::zeros();
+        for (index, axis) in rotation.matrix().column_iter().enumerate() {
+            let mut to = Vector4::zeros();
+            z.so3(&axis.map(from_f32).insert_row(3, T::zero()), &mut to);
+            axes.set_column(index, &to.xyz().map(to_f32));
+        }
+
+        Metadata {
+            sensor_origin: origin.xyz().map(to_f32),
+            sensor_orientation: UnitQuaternion::from_matrix(&axes),
+            stamp: self.stamp,
+            frame_id: self.frame_id.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Isometry3, Vector3, Vector4};
+
+    use crate::{
+        point::{Point, Point3},
+        point_cloud::PointCloud,
+    };
+
+    #[test]
+    fn test_metadata_transform() {
+        let mut pc = PointCloud::from_vec(
+            vec![Point3::default().with_coords(Vector4::new(1., 0., 0., 1.))],
+            1,
+        );
+        pc.metadata_mut().stamp = Some(42);
+        pc.metadata_mut().frame_id = Some("sensor".into());
+
+        let pose = Isometry3::new(Vector3::new(1., 2., 3.), Vector3::new(0., 0., 0.5));
+        let mut out = PointCloud::new();
+        pc.transform(&pose.to_homogeneous(), &mut out);
+
+        let metadata = out.metadata();
+        assert!((metadata.sensor_pose().to_homogeneous() - pose.to_homogeneous()).norm() < 1e-5);
+        assert_eq!(metadata.stamp, Some(42));
+        assert_eq!(metadata.frame_id.as_deref(), Some("sensor"));
+
+        let mapped = out.map(|point| *point);
+        assert_eq!(mapped.metadata(), out.metadata());
+    }
+}
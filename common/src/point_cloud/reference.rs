@@ -5,6 +5,7 @@ use nalgebra::{
     Vector3, Vector4,
 };
 use num::{FromPrimitive, Zero};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 use super::PointCloud;
 use crate::point::{Centroid, Data, Point, PointViewpoint};
@@ -66,6 +67,20 @@ impl<'a, P> Index<(usize, usize)> for PointCloudRef<'a, P> {
     }
 }
 
+fn par_select<P, F>(point_cloud: &PointCloudRef<P>, bounded: bool, f: F) -> Vec<usize>
+where
+    P: Point + Sync,
+    P::Data: RealField,
+    F: Fn(&Vector3<P::Data>) -> bool + Sync,
+{
+    { (0..point_cloud.data_len()).into_par_iter() }
+        .filter(|&index| {
+            let point = &point_cloud[index];
+            (bounded || point.is_finite()) && f(&point.coords().xyz())
+        })
+        .collect()
+}
+
 pub trait AsPointCloud<'a, P: 'a> {
     fn inner(&self) -> &PointCloud<P>;
 
@@ -319,6 +334,7 @@ pub trait AsPointCloud<'a, P: 'a> {
         residual
     }
 
+    /// The indices of the points within the box of `min` and `max`.
     fn box_select(&self, min: &Vector4<P::Data>, max: &Vector4<P::Data>) -> Vec<usize>
     where
         P: Point,
@@ -347,6 +363,33 @@ pub trait AsPointCloud<'a, P: 'a> {
         indices
     }
 
+    /// The indices of the points within the sphere of `center` and `radius`.
+    fn sphere_select(&self, center: &Vector4<P::Data>, radius: P::Data) -> Vec<usize>
+    where
+        P: Point + Sync,
+        <P as Data>::Data: RealField,
+    {
+        let center = center.xyz();
+        par_select(&self.as_ref(), self.is_bounded(), |coords| {
+            (coords - &center).norm() <= radius
+        })
+    }
+
+    /// The indices of the points within the convex region bounded by
+    /// `planes`, e.g. a view frustum, where every plane is the coefficients
+    /// `[a, b, c, d]` of `ax + by + cz + d = 0`, with its normal `[a, b, c]`
+    /// pointing to the inside.
+    fn frustum_select(&self, planes: &[Vector4<P::Data>]) -> Vec<usize>
+    where
+        P: Point + Sync,
+        <P as Data>::Data: RealField,
+    {
+        par_select(&self.as_ref(), self.is_bounded(), |coords| {
+            let coords = coords.clone().insert_row(3, one());
+            { planes.iter() }.all(|plane| plane.dot(&coords) >= zero())
+        })
+    }
+
     fn finite_bound(&self) -> Option<[Vector4<P::Data>; 2]>
     where
         P: Point,
@@ -449,19 +492,23 @@ where
 
     #[inline]
     fn data_len(&self) -> usize {
-        self.inner.len()
+        match self.indices {
+            Some(ref indices) => indices.len(),
+            None => self.inner.len(),
+        }
     }
 
     type DataIter<'b> = impl Iterator<Item = &'b P> + Clone where Self: 'b, P: 'b;
 
     #[inline]
     fn data_iter(&self) -> Self::DataIter<'_> {
-        let indices: &[usize] = match self.indices {
-            Some(ref indices) => indices.as_ref(),
-            None => &[],
+        let (indices, all): (&[usize], _) = match self.indices {
+            Some(ref indices) => (indices.as_ref(), 0..0),
+            None => (&[], 0..self.inner.len()),
         };
 
-        indices.iter().map(|&index| &self.inner[index])
+        let inner = self.inner;
+        { indices.iter().copied().chain(all) }.map(move |index| &inner[index])
     }
 }
 
@@ -499,3 +546,36 @@ where
         self.storage.iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point::Point3;
+
+    #[test]
+    fn test_select() {
+        let pc = PointCloud::from_vec(
+            (0..5)
+                .map(|i| Point3::default().with_coords(Vector4::new(i as f32, 0., 0., 1.)))
+                .collect(),
+            1,
+        );
+
+        let min = Vector4::new(0.5, -1., -1., 1.);
+        let max = Vector4::new(3., 1., 1., 1.);
+        assert_eq!(pc.box_select(&min, &max), [1, 2, 3]);
+
+        let center = Vector4::new(2., 0., 0., 1.);
+        assert_eq!(pc.sphere_select(&center, 1.), [1, 2, 3]);
+
+        // The slab between x = 1.5 and x = 3.5.
+        let planes = [
+            Vector4::new(1., 0., 0., -1.5),
+            Vector4::new(-1., 0., 0., 3.5),
+        ];
+        assert_eq!(pc.frustum_select(&planes), [2, 3]);
+
+        let sub = pc.select([4, 0, 2].as_slice().into());
+        assert_eq!(sub.sphere_select(&center, 1.), [2]);
+    }
+}
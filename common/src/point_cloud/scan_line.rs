@@ -0,0 +1,182 @@
+use std::{borrow::Cow, collections::BTreeMap};
+
+use nalgebra::RealField;
+use num::ToPrimitive;
+
+use super::{PointCloud, PointCloudRef};
+use crate::point::{Point, PointRing};
+
+/// The finite points of a rotating lidar split into scan lines, i.e. the
+/// indices of the points measured by every ring, keyed by the rings.
+///
+/// Only the rings with points have scan lines, so a bogus ring of a point
+/// doesn't allocate the lines of all the rings below it.
+///
+/// The points of a scan line keep their order in the point cloud, which is
+/// usually the order of the scan, unless sorted with
+/// [`ScanLines::sort_by_azimuth`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ScanLines {
+    pub lines: BTreeMap<usize, Vec<usize>>,
+}
+
+impl ScanLines {
+    /// The number of rings with points.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    #[inline]
+    pub fn line(&self, ring: usize) -> &[usize] {
+        self.lines.get(&ring).map_or(&[], |line| &line[..])
+    }
+
+    /// Selects the points of the scan line of `ring` from `point_cloud`,
+    /// which must be the one split.
+    #[inline]
+    pub fn select<'a, P>(
+        &'a self,
+        point_cloud: &'a PointCloud<P>,
+        ring: usize,
+    ) -> PointCloudRef<'a, P> {
+        point_cloud.select(Cow::Borrowed(self.line(ring)))
+    }
+
+    /// Iterates over the rings and the points of their scan lines selected
+    /// from `point_cloud`, which must be the one split.
+    pub fn iter<'a, P>(
+        &'a self,
+        point_cloud: &'a PointCloud<P>,
+    ) -> impl Iterator<Item = (usize, PointCloudRef<'a, P>)> + 'a {
+        { self.lines.iter() }
+            .map(move |(&ring, line)| (ring, point_cloud.select(Cow::Borrowed(&line[..]))))
+    }
+
+    /// Sorts the points of every scan line by their azimuths around the z
+    /// axis in `(-pi, pi]`, e.g. for the point clouds whose points aren't in
+    /// the order of the scan.
+    pub fn sort_by_azimuth<P>(&mut self, point_cloud: &PointCloud<P>)
+    where
+        P: Point,
+        P::Data: RealField,
+    {
+        let azimuth = |index: usize| {
+            let coords = point_cloud[index].coords();
+            coords.y.clone().atan2(coords.x.clone())
+        };
+        for line in self.lines.values_mut() {
+            line.sort_by(|&a, &b| azimuth(a).partial_cmp(&azimuth(b)).unwrap());
+        }
+    }
+}
+
+impl<P: PointRing> PointCloud<P> {
+    /// Splits the finite points into scan lines by their rings.
+    pub fn scan_lines(&self) -> ScanLines {
+        let mut lines = BTreeMap::<_, Vec<_>>::new();
+        for (index, point) in self.storage.iter().enumerate() {
+            if !point.is_finite() {
+                continue;
+            }
+            lines.entry(point.ring() as usize).or_default().push(index);
+        }
+        ScanLines { lines }
+    }
+}
+
+impl<T: RealField + ToPrimitive, P: Point<Data = T>> PointCloud<P> {
+    /// The elevation of the point of `index` above the xy plane in radians.
+    fn elevation(&self, index: usize) -> T {
+        let coords = self.storage[index].coords();
+        coords.z.clone().atan2(coords.xy().norm())
+    }
+
+    /// Splits the finite points into `num_rings` scan lines by their
+    /// elevations, for the point clouds without the ring fields.
+    ///
+    /// The rings are assumed to be evenly spaced within `fov`, the minimum
+    /// and the maximum elevations in radians, or within the ones of the
+    /// points if not given.
+    pub fn scan_lines_by_elevation(&self, num_rings: usize, fov: Option<[T; 2]>) -> ScanLines {
+        let finite = { self.storage.iter().enumerate() }
+            .filter(|(_, point)| point.is_finite())
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        if num_rings == 0 || finite.is_empty() {
+            return ScanLines::default();
+        }
+
+        let [min, max] = match fov {
+            Some(fov) => fov,
+            None => { finite.iter() }.map(|&index| self.elevation(index)).fold(
+                [T::max_value().unwrap(), T::min_value().unwrap()],
+                |[min, max], elevation| [min.min(elevation.clone()), max.max(elevation)],
+            ),
+        };
+        let steps = T::from_usize(num_rings - 1).unwrap();
+        let span = max - min.clone();
+
+        let mut lines = BTreeMap::<_, Vec<_>>::new();
+        for index in finite {
+            let ring = if span > T::zero() {
+                let ring = (self.elevation(index) - min.clone()) / span.clone() * steps.clone();
+                ring.round().to_usize().unwrap_or(0).min(num_rings - 1)
+            } else {
+                0
+            };
+            lines.entry(ring).or_default().push(index);
+        }
+        ScanLines { lines }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+
+    use super::*;
+    use crate::point::Point3IR;
+
+    #[test]
+    fn test_scan_lines() {
+        let storage = { (0..12).map(|i| (i % 3, i / 3)) }
+            .map(|(ring, step)| {
+                let azimuth = 3. - step as f32;
+                let elevation = ring as f32 * 0.1 - 0.1;
+                let coords = Vector4::new(
+                    azimuth.cos() * elevation.cos(),
+                    azimuth.sin() * elevation.cos(),
+                    elevation.sin(),
+                    1.,
+                );
+                Point3IR::default()
+                    .with_coords(coords)
+                    .with_ring(ring as u32)
+            })
+            .collect::<Vec<_>>();
+        let pc = PointCloud::from_vec(storage, 1);
+
+        let mut lines = pc.scan_lines();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines.line(1), [1, 4, 7, 10]);
+        assert_eq!(pc.scan_lines_by_elevation(3, None), lines);
+
+        lines.sort_by_azimuth(&pc);
+        assert_eq!(lines.line(1), [10, 7, 4, 1]);
+        let (ring, line) = lines.iter(&pc).last().unwrap();
+        assert_eq!((ring, line.indices()), (2, Some(&[11, 8, 5, 2][..])));
+
+        let storage = vec![Point3IR::default().with_ring(1 << 30)];
+        let lines = PointCloud::from_vec(storage, 1).scan_lines();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines.line(1 << 30), [0]);
+        assert!(lines.line(0).is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use nalgebra::{ClosedAdd, ClosedMul, Matrix4, RealField, Scalar, TCategory, Vector4};
+use nalgebra::{ClosedAdd, ClosedMul, Isometry3, Matrix4, RealField, Scalar, TCategory, Vector4};
 use num::Num;
 
 pub trait Transform<T: Scalar> {
@@ -28,3 +28,123 @@ impl<T: Scalar + Num + Copy + ClosedAdd + ClosedMul + RealField, C: TCategory> T
         self.matrix().se3(from, to)
     }
 }
+
+/// A source of the poses of a sensor in the world frame over time, e.g. an
+/// odometry or the preintegration of an IMU, which the users can implement
+/// for their own sources.
+///
+/// It's implemented for constant poses, the closures returning the poses at
+/// the timestamps, and [`PoseTrajectory`].
+pub trait PoseProvider<T: Scalar> {
+    /// The pose at `time`, or `None` if unknown, e.g. out of the range of
+    /// the measurements.
+    fn pose(&self, time: T) -> Option<Isometry3<T>>;
+}
+
+impl<T: RealField> PoseProvider<T> for Isometry3<T> {
+    #[inline]
+    fn pose(&self, _: T) -> Option<Isometry3<T>> {
+        Some(self.clone())
+    }
+}
+
+impl<T: Scalar, F> PoseProvider<T> for F
+where
+    F: Fn(T) -> Option<Isometry3<T>>,
+{
+    #[inline]
+    fn pose(&self, time: T) -> Option<Isometry3<T>> {
+        self(time)
+    }
+}
+
+/// The poses measured at the timestamps, between which the poses are
+/// interpolated, linearly for the translations and spherically for the
+/// rotations.
+#[derive(Debug, Clone, Default)]
+pub struct PoseTrajectory<T: Scalar> {
+    poses: Vec<(T, Isometry3<T>)>,
+}
+
+impl<T: RealField> PoseTrajectory<T> {
+    pub fn new() -> Self {
+        PoseTrajectory { poses: Vec::new() }
+    }
+
+    /// The poses sorted by their timestamps.
+    #[inline]
+    pub fn poses(&self) -> &[(T, Isometry3<T>)] {
+        &self.poses
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.poses.len()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.poses.is_empty()
+    }
+
+    /// Adds the pose measured at `time`, replacing the one at the same time.
+    pub fn push(&mut self, time: T, pose: Isometry3<T>) {
+        let index = self.poses.partition_point(|(t, _)| *t < time);
+        match self.poses.get_mut(index) {
+            Some((t, old)) if *t == time => *old = pose,
+            _ => self.poses.insert(index, (time, pose)),
+        }
+    }
+
+    /// Drops the poses before `time` except the last one, which is still
+    /// needed to interpolate the poses right after it.
+    pub fn prune(&mut self, time: T) {
+        let index = self.poses.partition_point(|(t, _)| *t < time);
+        self.poses.drain(..index.saturating_sub(1));
+    }
+}
+
+impl<T: RealField> PoseProvider<T> for PoseTrajectory<T> {
+    fn pose(&self, time: T) -> Option<Isometry3<T>> {
+        let index = self.poses.partition_point(|(t, _)| *t < time);
+        let (t1, pose1) = self.poses.get(index)?;
+        if *t1 == time {
+            return Some(pose1.clone());
+        }
+        let (t0, pose0) = self.poses.get(index.checked_sub(1)?)?;
+        let ratio = (time - t0.clone()) / (t1.clone() - t0.clone());
+        Some(pose0.lerp_slerp(pose1, ratio))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{UnitQuaternion, Vector3};
+
+    use super::*;
+
+    #[test]
+    fn test_pose_trajectory() {
+        let mut trajectory = PoseTrajectory::new();
+        trajectory.push(
+            2.,
+            Isometry3::new(Vector3::new(2., 0., 0.), Vector3::new(0., 0., 1.)),
+        );
+        trajectory.push(0., Isometry3::identity());
+        assert_eq!(trajectory.len(), 2);
+
+        let pose = trajectory.pose(1.).unwrap();
+        assert!((pose.translation.vector - Vector3::new(1., 0., 0.)).norm() < 1e-6);
+        let rotation = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), 0.5);
+        assert!(pose.rotation.angle_to(&rotation) < 1e-6);
+
+        assert!(trajectory.pose(-1.).is_none());
+        assert!(trajectory.pose(3.).is_none());
+
+        trajectory.prune(2.);
+        assert_eq!(trajectory.len(), 2);
+        trajectory.prune(3.);
+        assert_eq!(trajectory.len(), 1);
+    }
+}
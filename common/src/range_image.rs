@@ -6,7 +6,10 @@ use std::{mem, ops::Deref};
 use nalgebra::{Affine3, ComplexField, RealField, Vector2, Vector4};
 use num::{Float, FromPrimitive, ToPrimitive};
 
-pub use self::{creation::CreateOptions, surface::SurfaceInfo};
+pub use self::{
+    creation::{CreateOptions, ReturnPolicy},
+    surface::SurfaceInfo,
+};
 use crate::{
     point::{Centroid, PointRange},
     point_cloud::PointCloud,
@@ -48,7 +51,7 @@ fn image_to_angle<T: RealField>(
     ar: &Vector2<T>,
     io: &Vector2<usize>,
 ) -> Vector2<T> {
-    let mid = image + io.map(|x| T::from_usize(x).unwrap()).component_mul(ar);
+    let mid = (image + io.map(|x| T::from_usize(x).unwrap())).component_mul(ar);
     let y = mid.y.clone() - T::frac_pi_2();
     let cosy = y.clone().cos();
     let x = if cosy == T::zero() {
@@ -135,6 +138,17 @@ where
         self.transform.matrix().column(3).into()
     }
 
+    #[inline]
+    pub fn angular_resolution(&self) -> &Vector2<P::Data> {
+        &self.angular_resolution
+    }
+
+    /// The azimuth and elevation of the beam through `image`.
+    #[inline]
+    pub fn image_to_angle(&self, image: &Vector2<P::Data>) -> Vector2<P::Data> {
+        image_to_angle(image, &self.angular_resolution, &self.image_offset)
+    }
+
     #[inline]
     pub fn into_inner(ri: Self) -> PointCloud<P> {
         ri.point_cloud
@@ -226,9 +240,16 @@ impl<P: PointRange> RangeImage<P>
 where
     P::Data: RealField + Float,
 {
+    /// Crops the image to the pixels within `[xmin, xmax, ymin, ymax]`, which
+    /// are inclusive, plus `border_size` pixels around them as long as the
+    /// image offset allows. The pixels outside the original image are
+    /// unobserved.
     pub fn crop(&mut self, border_size: usize, &[xmin, xmax, ymin, ymax]: &[usize; 4]) {
-        let width = xmax - xmin + 1 + border_size * 2;
-        let height = ymax - ymin + 1 + border_size * 2;
+        let left = xmin.saturating_sub(border_size);
+        let top = ymin.saturating_sub(border_size);
+        let width = xmax + border_size + 1 - left;
+        let height = ymax + border_size + 1 - top;
+
         let old = mem::replace(&mut self.point_cloud, PointCloud::new());
         unsafe {
             self.point_cloud
@@ -238,10 +259,9 @@ where
 
         for x in 0..width {
             for y in 0..height {
-                let old_x = x + xmin + border_size;
-                let old_y = y + ymin + border_size;
+                let (old_x, old_y) = (x + left, y + top);
 
-                self.point_cloud[y * width + x] = if old_x <= xmax && old_y <= ymax {
+                self.point_cloud[y * width + x] = if old_x < old.width() && old_y < old.height() {
                     old[old_y * old.width() + old_x].clone()
                 } else {
                     unobserved()
@@ -249,7 +269,8 @@ where
             }
         }
 
-        self.point_cloud.reinterpret(width)
+        self.point_cloud.reinterpret(width);
+        self.image_offset += Vector2::new(left, top);
     }
 
     pub fn integrate_far_ranges<'a, Iter>(&mut self, far_ranges: Iter)
@@ -314,7 +335,15 @@ where
                             continue;
                         }
                         let src = &self.point_cloud[(src_x, src_y)];
-                        if !src.range().is_finite() || src.range() < dst.range() {
+                        // Prefers the nearest observed pixels, then the far
+                        // ranges to the unobserved ones.
+                        let (sr, dr) = (src.range(), dst.range());
+                        let nearer = if Float::is_finite(sr) {
+                            !Float::is_finite(dr) || sr < dr
+                        } else {
+                            !Float::is_finite(dr) && sr > dr
+                        };
+                        if nearer {
                             *dst = src.clone();
                         }
                     }
@@ -330,6 +359,51 @@ where
             image_offset,
         }
     }
+
+    /// Extracts the part of the image around the direction of
+    /// `center_angle`, which spans `extent` in azimuth and elevation, both in
+    /// radians, or returns `None` if it doesn't overlap the image.
+    ///
+    /// The azimuth range doesn't wrap around at `±π`, and the horizontal
+    /// bounds are taken at the elevation with the widest extent in pixels.
+    pub fn extract_angular_sub_image(
+        &self,
+        center_angle: &Vector2<P::Data>,
+        extent: &Vector2<P::Data>,
+    ) -> Option<Self> {
+        let (zero, one) = (nalgebra::zero::<P::Data>(), nalgebra::one::<P::Data>());
+        let bound = Vector2::new(RealField::pi(), RealField::frac_pi_2());
+        let half = extent / (one.clone() + one);
+        let min = (center_angle - &half).sup(&-bound.clone());
+        let max = (center_angle + &half).inf(&bound);
+        if min.x > max.x || min.y > max.y {
+            return None;
+        }
+        // The widest row is the one nearest to the equator.
+        let widest = RealField::min(RealField::max(zero.clone(), min.y.clone()), max.y.clone());
+
+        let origin = Vector2::zeros();
+        let ar = &self.angular_resolution;
+        let lower = angle_to_image(&Vector2::new(min.x.clone(), widest.clone()), ar, &origin);
+        let upper = angle_to_image(&Vector2::new(max.x.clone(), widest), ar, &origin);
+        let bottom = angle_to_image(&Vector2::new(zero.clone(), min.y), ar, &origin);
+        let top = angle_to_image(&Vector2::new(zero.clone(), max.y), ar, &origin);
+
+        let to_usize = |x: P::Data| RealField::max(x, zero.clone()).to_usize().unwrap_or(0);
+        let [xmin, ymin] = [lower.x.clone(), bottom.y.clone()].map(|x| to_usize(Float::floor(x)));
+        let [xmax, ymax] = [upper.x.clone(), top.y.clone()].map(|x| to_usize(Float::ceil(x)));
+
+        let io = &self.image_offset;
+        let xmin = xmin.max(io.x);
+        let ymin = ymin.max(io.y);
+        let xmax = xmax.min((io.x + self.width()).checked_sub(1)?);
+        let ymax = ymax.min((io.y + self.height()).checked_sub(1)?);
+        if xmin > xmax || ymin > ymax {
+            return None;
+        }
+
+        Some(self.create_sub(&[xmin, xmax, ymin, ymax], 1))
+    }
 }
 
 impl<P: PointRange> RangeImage<P>
@@ -367,3 +441,144 @@ where
         centroid.compute()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use super::*;
+    use crate::point::{Point, Point3, Point3Range, Point3RangeIE, PointEcho, PointIntensity};
+
+    /// A plate facing the sensor 2 away, spanning about 28 x 14 degrees.
+    fn plate() -> RangeImage<Point3Range> {
+        let storage = { (-100..=100).flat_map(|x| (-50..=50).map(move |y| (x, y))) }
+            .map(|(x, y)| Vector4::new(x as f32 * 0.005, y as f32 * 0.005, 2., 1.))
+            .map(|coords| Point3::default().with_coords(coords))
+            .collect::<Vec<_>>();
+        let point_cloud = PointCloud::from_vec(storage, 1);
+        let options = CreateOptions {
+            point_cloud: &point_cloud,
+            angular_resolution: Vector2::repeat(1f32.to_radians()),
+            noise: 0.,
+            min_range: 0.,
+            border_size: 0,
+        };
+        let angle_size = [90f32.to_radians(), 90f32.to_radians()];
+        RangeImage::new(&angle_size, Affine3::identity(), &options)
+    }
+
+    fn pixel(image: &RangeImage<Point3Range>, x: f32, y: f32, z: f32) -> (usize, usize) {
+        let (pixel, _) = image.point_to_image(&Vector4::new(x, y, z, 1.));
+        let [[x, y]] = pixel.map(|x| x.round() as usize).data.0;
+        (x, y)
+    }
+
+    #[test]
+    fn test_new() {
+        let image = plate();
+        // The image is cropped to the plate and the pixels around it.
+        assert!((29..=32).contains(&image.width()));
+        assert!((15..=18).contains(&image.height()));
+
+        for (x, y) in [(0., 0.), (-0.48, 0.), (0.48, 0.), (0., -0.23), (0., 0.23)] {
+            let norm = Vector3::new(x, y, 2f32).norm();
+            let point = &image[pixel(&image, x, y, 2.)];
+            assert!((point.range() - norm).abs() < 0.02);
+            // Within a pixel of the original point.
+            let coords = Vector4::new(x, y, 2., 1.);
+            assert!((point.coords() - coords).norm() < 0.04);
+        }
+    }
+
+    #[test]
+    fn test_crop() {
+        let image = plate();
+        let mut cropped = image.clone();
+        cropped.crop(1, &[2, 4, 3, 5]);
+        assert_eq!((cropped.width(), cropped.height()), (5, 5));
+        for (x, y) in (0..5).flat_map(|x| (0..5).map(move |y| (x, y))) {
+            assert_eq!(cropped[(x, y)], image[(x + 1, y + 2)]);
+        }
+
+        let center = pixel(&image, 0., 0., 2.);
+        let mut cropped = image.clone();
+        cropped.crop(0, &[center.0, center.0, center.1, center.1]);
+        assert_eq!(pixel(&cropped, 0., 0., 2.), (0, 0));
+    }
+
+    #[test]
+    fn test_extract_angular_sub_image() {
+        let image = plate();
+        let extent = Vector2::repeat(10f32.to_radians());
+        let sub = image
+            .extract_angular_sub_image(&Vector2::zeros(), &extent)
+            .unwrap();
+        assert!((10..=12).contains(&sub.width()));
+        assert!((10..=12).contains(&sub.height()));
+        assert!(sub.iter().all(|point| (2. ..2.05).contains(&point.range())));
+        assert_eq!(
+            sub[pixel(&sub, 0., 0., 2.)].range(),
+            image[pixel(&image, 0., 0., 2.)].range()
+        );
+
+        let aside = Vector2::new(1f32, 0.);
+        assert!(image.extract_angular_sub_image(&aside, &extent).is_none());
+    }
+
+    #[test]
+    fn test_with_returns() {
+        // Every beam hits a plate at 2 and then a wall at 4 behind it. The
+        // second returns are stronger on the right, and some of them are
+        // listed before the first ones.
+        let storage = { (-5..=5).flat_map(|x| (-5..=5).map(move |y| (x, y))) }
+            .flat_map(|(x, y)| {
+                let dir = Vector4::new(x as f32 * 0.02, y as f32 * 0.02, 1., 0.).normalize();
+                let first = Point3RangeIE::default()
+                    .with_coords(dir * 2. + Vector4::w())
+                    .with_intensity(if x < 0 { 1. } else { 0.1 })
+                    .with_echo(0);
+                let second = Point3RangeIE::default()
+                    .with_coords(dir * 4. + Vector4::w())
+                    .with_intensity(0.5)
+                    .with_echo(1);
+                if y % 2 == 0 {
+                    [first, second]
+                } else {
+                    [second, first]
+                }
+            })
+            .collect::<Vec<_>>();
+        let point_cloud = PointCloud::from_vec(storage, 1);
+        let options = CreateOptions {
+            point_cloud: &point_cloud,
+            angular_resolution: Vector2::repeat(0.5f32.to_radians()),
+            noise: 0.,
+            min_range: 0.,
+            border_size: 0,
+        };
+        let angle_size = [90f32.to_radians(), 90f32.to_radians()];
+        let image = |policy| {
+            RangeImage::<Point3Range>::with_returns(
+                &angle_size,
+                Affine3::identity(),
+                &options,
+                policy,
+            )
+        };
+
+        let range = |image: &RangeImage<Point3Range>, x: f32, y: f32| {
+            image[pixel(image, x * 0.04, y * 0.04, 2.)].range()
+        };
+        let (first, last, strongest) = (
+            image(ReturnPolicy::First),
+            image(ReturnPolicy::Last),
+            image(ReturnPolicy::Strongest),
+        );
+        for (x, y) in [(-4., -3.), (-2., 1.), (0., 0.), (3., -1.), (4., 4.)] {
+            assert!((range(&first, x, y) - 2.).abs() < 0.01);
+            assert!((range(&last, x, y) - 4.).abs() < 0.01);
+            let expected = if x < 0. { 2. } else { 4. };
+            assert!((range(&strongest, x, y) - expected).abs() < 0.01);
+        }
+    }
+}
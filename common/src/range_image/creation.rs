@@ -1,9 +1,11 @@
+use std::collections::HashMap;
+
 use nalgebra::{Affine3, RealField, Vector2, Vector4};
 use num::{one, Float, FromPrimitive, ToPrimitive};
 
 use super::{image_to_point, point_to_image, unobserved, RangeImage};
 use crate::{
-    point::{Point, PointRange, PointViewpoint},
+    point::{Point, PointEcho, PointIntensity, PointRange, PointViewpoint},
     point_cloud::{AsPointCloud, PointCloud},
 };
 
@@ -15,6 +17,30 @@ pub struct CreateOptions<'a, P: Point> {
     pub border_size: usize,
 }
 
+/// Decides which of the multiple returns of a beam populates its pixel.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ReturnPolicy {
+    /// The return with the least echo index.
+    First,
+    /// The return with the greatest echo index.
+    Last,
+    /// The return with the greatest intensity.
+    Strongest,
+}
+
+impl ReturnPolicy {
+    fn prefers<P: PointEcho + PointIntensity>(&self, new: &P, old: &P) -> bool
+    where
+        P::Data: PartialOrd,
+    {
+        match self {
+            ReturnPolicy::First => new.echo() < old.echo(),
+            ReturnPolicy::Last => new.echo() > old.echo(),
+            ReturnPolicy::Strongest => new.intensity() > old.intensity(),
+        }
+    }
+}
+
 impl<P> RangeImage<P>
 where
     P: PointRange,
@@ -40,6 +66,55 @@ where
         Self::new_inner(sensor_pose, image_offset, size, options)
     }
 
+    /// Like [`RangeImage::new`], but for the point clouds of multi-echo
+    /// lidars, where only one return of each beam, chosen by `policy`, is
+    /// kept in the image. The returns of a beam are the points falling into
+    /// the same pixel.
+    pub fn with_returns<P2>(
+        angle_size: &[P::Data; 2],
+        sensor_pose: Affine3<P::Data>,
+        options: &CreateOptions<P2>,
+        policy: ReturnPolicy,
+    ) -> Self
+    where
+        P2: PointEcho<Data = P::Data> + PointIntensity,
+    {
+        let input = options.point_cloud;
+        let inverse_transform = sensor_pose.inverse();
+
+        let mut beams = HashMap::<_, usize>::new();
+        for (index, point) in input.iter().enumerate() {
+            if !point.is_finite() {
+                continue;
+            }
+            let (image, _) = point_to_image(
+                point.coords(),
+                &inverse_transform,
+                &options.angular_resolution,
+                &Vector2::zeros(),
+            );
+            let pixel = image.map(|x| Float::round(x).to_usize().unwrap());
+
+            let selected = beams.entry((pixel.x, pixel.y)).or_insert(index);
+            if policy.prefers(point, &input[*selected]) {
+                *selected = index;
+            }
+        }
+
+        let mut indices = beams.into_values().collect::<Vec<_>>();
+        indices.sort_unstable();
+        let selected = input.create_sub(&indices, 1);
+
+        let options = CreateOptions {
+            point_cloud: &selected,
+            angular_resolution: options.angular_resolution,
+            noise: options.noise,
+            min_range: options.min_range,
+            border_size: options.border_size,
+        };
+        Self::new(angle_size, sensor_pose, &options)
+    }
+
     pub fn within_sphere<P2: Point<Data = P::Data>>(
         &(center, radius): &(Vector4<P::Data>, P::Data),
         sensor_pose: Affine3<P::Data>,
@@ -147,6 +222,7 @@ where
                 .storage()
                 .resize(width * height, unobserved())
         };
+        self.point_cloud.reinterpret(width);
 
         let mut counter = vec![0; width * height];
 
@@ -162,10 +238,11 @@ where
                 &self.image_offset,
             );
 
-            let (x, y) = (
-                Float::round(image.x).to_usize().unwrap(),
-                Float::round(image.y).to_usize().unwrap(),
-            );
+            // The points outside the image may have negative coordinates.
+            let (x, y) = match image.map(|x| Float::round(x).to_usize()).data.0 {
+                [[Some(x), Some(y)]] => (x, y),
+                _ => continue,
+            };
 
             if range < min_range || !self.contains_key(x, y) {
                 continue;
@@ -174,8 +251,8 @@ where
             {
                 let neighbors = {
                     let (floor, ceil) = (
-                        image.map(|x| Float::floor(x).to_usize().unwrap()),
-                        image.map(|x| Float::ceil(x).to_usize().unwrap()),
+                        image.map(|x| Float::floor(x).to_usize().unwrap_or(0)),
+                        image.map(|x| Float::ceil(x).to_usize().unwrap_or(0)),
                     );
                     [
                         (floor.x, floor.y),
@@ -186,11 +263,20 @@ where
                     .into_iter()
                 };
                 for (nx, ny) in neighbors {
+                    if !self.contains_key(nx, ny) {
+                        continue;
+                    }
                     if counter[ny * width + nx] == 0 {
                         let value = self.point_cloud[ny * width + nx].range();
-                        self.point_cloud[ny * width + nx].set_range(Float::min(value, range));
+                        // Unobserved neighbors take the range as is.
+                        let value = if Float::is_finite(value) {
+                            Float::min(value, range)
+                        } else {
+                            range
+                        };
+                        self.point_cloud[ny * width + nx].set_range(value);
 
-                        xmin = Some(xmin.map_or(nx, |xmin| nx.max(xmin)));
+                        xmin = Some(xmin.map_or(nx, |xmin| nx.min(xmin)));
                         xmax = Some(xmax.map_or(nx, |xmax| nx.max(xmax)));
                         ymin = Some(ymin.map_or(ny, |ymin| ny.min(ymin)));
                         ymax = Some(ymax.map_or(ny, |ymax| ny.max(ymax)));
@@ -204,7 +290,7 @@ where
             if *counter == 0 || range < *min_range - noise {
                 *counter = 1;
                 *min_range = range;
-                xmin = Some(xmin.map_or(x, |xmin| x.max(xmin)));
+                xmin = Some(xmin.map_or(x, |xmin| x.min(xmin)));
                 xmax = Some(xmax.map_or(x, |xmax| x.max(xmax)));
                 ymin = Some(ymin.map_or(y, |ymin| y.min(ymin)));
                 ymax = Some(ymax.map_or(y, |ymax| y.max(ymax)));
@@ -214,8 +300,6 @@ where
             }
         }
 
-        self.point_cloud.reinterpret(width);
-
         [xmin.unwrap(), xmax.unwrap(), ymin.unwrap(), ymax.unwrap()]
     }
 }
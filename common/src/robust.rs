@@ -0,0 +1,161 @@
+use nalgebra::RealField;
+use num::ToPrimitive;
+
+/// A robust loss of residuals, for the least-squares estimations that have to
+/// tolerate outliers, like ICP under partial overlap or the refinement of SAC
+/// models.
+pub trait RobustKernel<T: RealField> {
+    fn loss(&self, residual: T) -> T;
+
+    /// The weight of the residual in iteratively reweighted least squares,
+    /// i.e. the derivative of the loss divided by the residual.
+    fn weight(&self, residual: T) -> T;
+
+    /// The weights of all the residuals of one iteration, for kernels that
+    /// depend on the distribution of the residuals.
+    fn weights(&self, residuals: &[T]) -> Vec<T> {
+        { residuals.iter() }
+            .map(|residual| self.weight(residual.clone()))
+            .collect()
+    }
+}
+
+/// The plain squared loss, which weighs all the residuals equally.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Squared;
+
+impl<T: RealField> RobustKernel<T> for Squared {
+    fn loss(&self, residual: T) -> T {
+        residual.clone() * residual / (T::one() + T::one())
+    }
+
+    fn weight(&self, _: T) -> T {
+        T::one()
+    }
+}
+
+/// Squared for the residuals within `delta`, and linear beyond.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Huber<T> {
+    pub delta: T,
+}
+
+impl<T> Huber<T> {
+    pub fn new(delta: T) -> Self {
+        Huber { delta }
+    }
+}
+
+impl<T: RealField> RobustKernel<T> for Huber<T> {
+    fn loss(&self, residual: T) -> T {
+        let two = T::one() + T::one();
+        let residual = residual.abs();
+        if residual <= self.delta {
+            residual.clone() * residual / two
+        } else {
+            self.delta.clone() * (residual - self.delta.clone() / two)
+        }
+    }
+
+    fn weight(&self, residual: T) -> T {
+        let residual = residual.abs();
+        if residual <= self.delta {
+            T::one()
+        } else {
+            self.delta.clone() / residual
+        }
+    }
+}
+
+/// Tukey's biweight, which ignores the residuals beyond `c` completely.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Tukey<T> {
+    pub c: T,
+}
+
+impl<T> Tukey<T> {
+    pub fn new(c: T) -> Self {
+        Tukey { c }
+    }
+}
+
+impl<T: RealField> RobustKernel<T> for Tukey<T> {
+    fn loss(&self, residual: T) -> T {
+        let max = self.c.clone() * self.c.clone() / T::from_u8(6).unwrap();
+        if residual.clone().abs() > self.c {
+            return max;
+        }
+
+        let ratio = residual / self.c.clone();
+        let t = T::one() - ratio.clone() * ratio;
+        max * (T::one() - t.clone() * t.clone() * t)
+    }
+
+    fn weight(&self, residual: T) -> T {
+        if residual.clone().abs() > self.c {
+            return T::zero();
+        }
+
+        let ratio = residual / self.c.clone();
+        let t = T::one() - ratio.clone() * ratio;
+        t.clone() * t
+    }
+}
+
+/// Keeps the `ratio` of the residuals with the smallest magnitudes with the
+/// squared loss, and rejects the rest, like in trimmed ICP.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Trimmed<T> {
+    pub ratio: T,
+}
+
+impl<T> Trimmed<T> {
+    pub fn new(ratio: T) -> Self {
+        Trimmed { ratio }
+    }
+}
+
+impl<T: RealField + ToPrimitive> RobustKernel<T> for Trimmed<T> {
+    fn loss(&self, residual: T) -> T {
+        Squared.loss(residual)
+    }
+
+    fn weight(&self, _: T) -> T {
+        T::one()
+    }
+
+    fn weights(&self, residuals: &[T]) -> Vec<T> {
+        let len = T::from_usize(residuals.len()).unwrap();
+        let num = { (self.ratio.clone() * len).round().to_usize() }
+            .unwrap_or(0)
+            .min(residuals.len());
+
+        let mut indices = (0..residuals.len()).collect::<Vec<_>>();
+        indices.sort_by(|&a, &b| {
+            let (a, b) = (residuals[a].clone().abs(), residuals[b].clone().abs());
+            a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let mut weights = vec![T::zero(); residuals.len()];
+        for index in indices.into_iter().take(num) {
+            weights[index] = T::one();
+        }
+        weights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kernels() {
+        let residuals = [0.5, -2., 4., 1.];
+
+        assert_eq!(Huber::new(1.).weights(&residuals), vec![1., 0.5, 0.25, 1.]);
+        assert_eq!(Huber::new(1.).loss(-2.), 1.5);
+        assert_eq!(Tukey::new(2.).weights(&residuals)[1..3], [0., 0.]);
+        assert_eq!(Tukey::new(2.).loss(4.), Tukey::new(2.).loss(2.));
+        assert_eq!(Trimmed::new(0.5).weights(&residuals), vec![1., 0., 0., 1.]);
+    }
+}
@@ -1,4 +1,6 @@
-use nalgebra::Vector4;
+use std::{error::Error, fmt, marker::PhantomData};
+
+use nalgebra::{RealField, Vector4};
 use static_assertions::assert_obj_safe;
 
 use crate::{point::Point, point_cloud::PointCloud};
@@ -7,6 +9,146 @@ use crate::{point::Point, point_cloud::PointCloud};
 pub enum SearchType<T> {
     Knn(usize),
     Radius(T),
+    /// The `k` nearest neighbors within `radius`, which may be fewer than
+    /// `k`. Searchers like kd-trees prune the branches beyond `radius`, so
+    /// it's much faster than filtering the results of [`SearchType::Knn`]
+    /// when most of the pivots have no neighbors nearby, e.g. for the
+    /// correspondences of registration.
+    ///
+    /// This is also known as the hybrid or radius-limited kNN search.
+    #[doc(alias = "KnnRadius")]
+    KnnWithin {
+        k: usize,
+        radius: T,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SearchParamError<T> {
+    TooFewNeighbors {
+        num: usize,
+        min: usize,
+    },
+    InvalidRadius(T),
+    NotLarger {
+        param: SearchType<T>,
+        than: SearchType<T>,
+    },
+}
+
+impl<T: fmt::Debug> fmt::Display for SearchParamError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SearchParamError::TooFewNeighbors { num, min } => {
+                write!(
+                    f,
+                    "knn search with {num} neighbors, expected at least {min}"
+                )
+            }
+            SearchParamError::InvalidRadius(radius) => {
+                write!(
+                    f,
+                    "radius search with {radius:?}, expected a positive finite radius"
+                )
+            }
+            SearchParamError::NotLarger { param, than } => {
+                write!(f, "search parameter {param:?} is not larger than {than:?}")
+            }
+        }
+    }
+}
+
+impl<T: fmt::Debug> Error for SearchParamError<T> {}
+
+/// A [`SearchType`] validated at construction, so that features will not
+/// silently produce empty results from degenerate neighborhoods.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SearchParam<T>(SearchType<T>);
+
+impl<T: RealField> SearchParam<T> {
+    /// The minimum number of neighbors to estimate a local surface.
+    pub const MIN_KNN: usize = 3;
+
+    pub fn new(ty: SearchType<T>) -> Result<Self, SearchParamError<T>> {
+        match ty {
+            SearchType::Knn(num) => Self::knn(num),
+            SearchType::Radius(radius) => Self::radius(radius),
+            SearchType::KnnWithin { k, radius } => Self::knn_within(k, radius),
+        }
+    }
+
+    pub fn knn(num: usize) -> Result<Self, SearchParamError<T>> {
+        if num < Self::MIN_KNN {
+            return Err(SearchParamError::TooFewNeighbors {
+                num,
+                min: Self::MIN_KNN,
+            });
+        }
+        Ok(SearchParam(SearchType::Knn(num)))
+    }
+
+    pub fn radius(radius: T) -> Result<Self, SearchParamError<T>> {
+        if !(radius.is_finite() && radius > T::zero()) {
+            return Err(SearchParamError::InvalidRadius(radius));
+        }
+        Ok(SearchParam(SearchType::Radius(radius)))
+    }
+
+    pub fn knn_within(k: usize, radius: T) -> Result<Self, SearchParamError<T>> {
+        Self::knn(k)?;
+        Self::radius(radius.clone())?;
+        Ok(SearchParam(SearchType::KnnWithin { k, radius }))
+    }
+
+    /// Checks that this parameter covers a larger neighborhood than `than`,
+    /// e.g. the search for FPFH must be larger than the one for the normals it
+    /// depends on. Parameters of different kinds cannot be compared and pass
+    /// the check.
+    pub fn larger_than(self, than: &Self) -> Result<Self, SearchParamError<T>> {
+        let larger = match (&self.0, &than.0) {
+            (SearchType::Knn(num), SearchType::Knn(than)) => num > than,
+            (SearchType::Radius(radius), SearchType::Radius(than)) => radius > than,
+            (
+                SearchType::KnnWithin { k, radius },
+                SearchType::KnnWithin {
+                    k: than_k,
+                    radius: than_radius,
+                },
+            ) => k >= than_k && radius >= than_radius && (k > than_k || radius > than_radius),
+            _ => true,
+        };
+        if larger {
+            Ok(self)
+        } else {
+            Err(SearchParamError::NotLarger {
+                param: self.0,
+                than: than.0.clone(),
+            })
+        }
+    }
+}
+
+impl<T: Clone> SearchParam<T> {
+    #[inline]
+    pub fn ty(&self) -> SearchType<T> {
+        self.0.clone()
+    }
+}
+
+impl<T> From<SearchParam<T>> for SearchType<T> {
+    #[inline]
+    fn from(param: SearchParam<T>) -> Self {
+        param.0
+    }
+}
+
+impl<T: RealField> TryFrom<SearchType<T>> for SearchParam<T> {
+    type Error = SearchParamError<T>;
+
+    #[inline]
+    fn try_from(ty: SearchType<T>) -> Result<Self, Self::Error> {
+        Self::new(ty)
+    }
 }
 
 pub trait Search<'a, P: Point> {
@@ -27,11 +169,53 @@ pub trait Search<'a, P: Point> {
     ) {
         self.search(pivot, ty, result)
     }
+
+    /// Like [`Search::search`], but may trade the accuracy for the speed,
+    /// e.g. by pruning the branches of a tree that can't be closer than the
+    /// farthest result divided by `1 + epsilon`. The results may miss some
+    /// neighbors, but are within `1 + epsilon` times the distances of the
+    /// exact ones.
+    ///
+    /// The searchers without approximate modes search as usual.
+    fn search_approx(
+        &self,
+        pivot: &Vector4<P::Data>,
+        ty: SearchType<P::Data>,
+        epsilon: P::Data,
+        result: &mut Vec<(usize, P::Data)>,
+    ) {
+        let _ = epsilon;
+        self.search(pivot, ty, result)
+    }
+
+    /// Passes the neighbors that [`Search::search`] would find to `visitor`
+    /// with their distances, without collecting them, so that tight loops
+    /// over many queries avoid allocating a result for every query.
+    fn search_with(
+        &self,
+        pivot: &Vector4<P::Data>,
+        ty: SearchType<P::Data>,
+        visitor: &mut dyn FnMut(usize, P::Data),
+    ) {
+        let mut result = Vec::new();
+        self.search(pivot, ty, &mut result);
+        for (index, distance) in result {
+            visitor(index, distance)
+        }
+    }
+
+    /// Counts the neighbors within `radius`, as a radius search would find,
+    /// but at most `max` of them, so that the search can stop early.
+    fn count_radius(&self, pivot: &Vector4<P::Data>, radius: P::Data, max: usize) -> usize {
+        let mut result = Vec::new();
+        self.search(pivot, SearchType::Radius(radius), &mut result);
+        result.len().min(max)
+    }
 }
 
 impl<'b, 'a, P: Point, T> Search<'a, P> for &'b T
 where
-    T: Search<'a, P>,
+    T: Search<'a, P> + ?Sized,
 {
     #[inline]
     fn input(&self) -> &'a PointCloud<P> {
@@ -47,6 +231,329 @@ where
     ) {
         Search::search(*self, pivot, ty, result)
     }
+
+    #[inline]
+    fn search_approx(
+        &self,
+        pivot: &Vector4<P::Data>,
+        ty: SearchType<P::Data>,
+        epsilon: P::Data,
+        result: &mut Vec<(usize, P::Data)>,
+    ) {
+        Search::search_approx(*self, pivot, ty, epsilon, result)
+    }
+
+    #[inline]
+    fn search_with(
+        &self,
+        pivot: &Vector4<P::Data>,
+        ty: SearchType<P::Data>,
+        visitor: &mut dyn FnMut(usize, P::Data),
+    ) {
+        Search::search_with(*self, pivot, ty, visitor)
+    }
+
+    #[inline]
+    fn count_radius(&self, pivot: &Vector4<P::Data>, radius: P::Data, max: usize) -> usize {
+        Search::count_radius(*self, pivot, radius, max)
+    }
 }
 
 assert_obj_safe!(Search<'_, crate::point::Point3>);
+
+/// A searcher erased of its point type and lifetime parameters, working on
+/// `f32` coordinates, so that searchers of different backends and point
+/// types can be boxed, held together and swapped at runtime.
+///
+/// Wrap a [`Search`] with [`ErasedSearch`] or [`erase`] to get one.
+pub trait DynSearch {
+    /// The number of points in the input.
+    fn len(&self) -> usize;
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The coordinates of the input point at `index`.
+    fn coords(&self, index: usize) -> &Vector4<f32>;
+
+    fn search(&self, pivot: &Vector4<f32>, ty: SearchType<f32>, result: &mut Vec<(usize, f32)>);
+
+    fn search_exact(
+        &self,
+        pivot: &Vector4<f32>,
+        ty: SearchType<f32>,
+        result: &mut Vec<(usize, f32)>,
+    ) {
+        self.search(pivot, ty, result)
+    }
+
+    /// See [`Search::search_approx`].
+    fn search_approx(
+        &self,
+        pivot: &Vector4<f32>,
+        ty: SearchType<f32>,
+        epsilon: f32,
+        result: &mut Vec<(usize, f32)>,
+    ) {
+        let _ = epsilon;
+        self.search(pivot, ty, result)
+    }
+
+    /// See [`Search::search_with`].
+    fn search_with(
+        &self,
+        pivot: &Vector4<f32>,
+        ty: SearchType<f32>,
+        visitor: &mut dyn FnMut(usize, f32),
+    ) {
+        let mut result = Vec::new();
+        self.search(pivot, ty, &mut result);
+        for (index, distance) in result {
+            visitor(index, distance)
+        }
+    }
+
+    /// See [`Search::count_radius`].
+    fn count_radius(&self, pivot: &Vector4<f32>, radius: f32, max: usize) -> usize {
+        let mut result = Vec::new();
+        self.search(pivot, SearchType::Radius(radius), &mut result);
+        result.len().min(max)
+    }
+}
+
+assert_obj_safe!(DynSearch);
+
+/// Adapts a [`Search`] into a [`DynSearch`].
+#[derive(Debug, Clone)]
+pub struct ErasedSearch<'a, P, S> {
+    inner: S,
+    marker: PhantomData<&'a P>,
+}
+
+impl<'a, P, S> ErasedSearch<'a, P, S> {
+    #[inline]
+    pub fn new(inner: S) -> Self {
+        ErasedSearch {
+            inner,
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<'a, P, S> DynSearch for ErasedSearch<'a, P, S>
+where
+    P: Point<Data = f32>,
+    S: Search<'a, P>,
+{
+    #[inline]
+    fn len(&self) -> usize {
+        self.inner.input().len()
+    }
+
+    #[inline]
+    fn coords(&self, index: usize) -> &Vector4<f32> {
+        self.inner.input()[index].coords()
+    }
+
+    #[inline]
+    fn search(&self, pivot: &Vector4<f32>, ty: SearchType<f32>, result: &mut Vec<(usize, f32)>) {
+        self.inner.search(pivot, ty, result)
+    }
+
+    #[inline]
+    fn search_exact(
+        &self,
+        pivot: &Vector4<f32>,
+        ty: SearchType<f32>,
+        result: &mut Vec<(usize, f32)>,
+    ) {
+        self.inner.search_exact(pivot, ty, result)
+    }
+
+    #[inline]
+    fn search_approx(
+        &self,
+        pivot: &Vector4<f32>,
+        ty: SearchType<f32>,
+        epsilon: f32,
+        result: &mut Vec<(usize, f32)>,
+    ) {
+        self.inner.search_approx(pivot, ty, epsilon, result)
+    }
+
+    #[inline]
+    fn search_with(
+        &self,
+        pivot: &Vector4<f32>,
+        ty: SearchType<f32>,
+        visitor: &mut dyn FnMut(usize, f32),
+    ) {
+        self.inner.search_with(pivot, ty, visitor)
+    }
+
+    #[inline]
+    fn count_radius(&self, pivot: &Vector4<f32>, radius: f32, max: usize) -> usize {
+        self.inner.count_radius(pivot, radius, max)
+    }
+}
+
+/// Boxes `searcher` as a [`DynSearch`].
+#[inline]
+pub fn erase<'a, P, S>(searcher: S) -> Box<dyn DynSearch + 'a>
+where
+    P: Point<Data = f32> + 'a,
+    S: Search<'a, P> + 'a,
+{
+    Box::new(ErasedSearch::new(searcher))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point::Point3;
+
+    #[test]
+    fn test_search_param() {
+        assert_eq!(
+            SearchParam::<f32>::knn(10).unwrap().ty(),
+            SearchType::Knn(10)
+        );
+        assert_eq!(
+            SearchParam::<f32>::knn(2),
+            Err(SearchParamError::TooFewNeighbors { num: 2, min: 3 })
+        );
+        for radius in [0., -1., f32::NAN, f32::INFINITY] {
+            let err = SearchParam::radius(radius).unwrap_err();
+            assert!(
+                matches!(err, SearchParamError::InvalidRadius(r) if r.to_bits() == radius.to_bits())
+            );
+        }
+        assert!(SearchParam::knn_within(2, 0.1).is_err());
+        assert!(SearchParam::knn_within(5, 0.).is_err());
+        assert_eq!(
+            SearchParam::try_from(SearchType::KnnWithin { k: 5, radius: 0.1 }),
+            SearchParam::knn_within(5, 0.1)
+        );
+
+        let normal = SearchParam::radius(0.1).unwrap();
+        let fpfh = SearchParam::radius(0.25).unwrap();
+        assert_eq!(fpfh.larger_than(&normal), Ok(fpfh));
+        assert_eq!(
+            normal.larger_than(&normal),
+            Err(SearchParamError::NotLarger {
+                param: SearchType::Radius(0.1),
+                than: SearchType::Radius(0.1)
+            })
+        );
+        let normal = SearchParam::knn_within(10, 0.1).unwrap();
+        assert!(SearchParam::knn_within(10, 0.2)
+            .unwrap()
+            .larger_than(&normal)
+            .is_ok());
+        assert!(SearchParam::knn_within(20, 0.05)
+            .unwrap()
+            .larger_than(&normal)
+            .is_err());
+        // Different kinds are not compared.
+        assert!(SearchParam::knn(3).unwrap().larger_than(&normal).is_ok());
+        assert_eq!(
+            SearchType::from(normal),
+            SearchType::KnnWithin { k: 10, radius: 0.1 }
+        );
+    }
+
+    /// Searches all the points, and counts how many searches there were.
+    struct Brute<'a> {
+        input: &'a PointCloud<Point3>,
+        searches: std::cell::Cell<usize>,
+    }
+
+    impl<'a> Search<'a, Point3> for Brute<'a> {
+        fn input(&self) -> &'a PointCloud<Point3> {
+            self.input
+        }
+
+        fn search(
+            &self,
+            pivot: &Vector4<f32>,
+            ty: SearchType<f32>,
+            result: &mut Vec<(usize, f32)>,
+        ) {
+            self.searches.set(self.searches.get() + 1);
+            let mut all = { self.input.iter().enumerate() }
+                .map(|(index, point)| (index, (point.coords() - pivot).norm()))
+                .collect::<Vec<_>>();
+            all.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+            result.clear();
+            result.extend(match ty {
+                SearchType::Knn(k) => all.into_iter().take(k).collect::<Vec<_>>(),
+                SearchType::Radius(radius) => {
+                    all.into_iter().filter(|(_, d)| *d < radius).collect()
+                }
+                SearchType::KnnWithin { k, radius } => all
+                    .into_iter()
+                    .filter(|(_, d)| *d < radius)
+                    .take(k)
+                    .collect(),
+            })
+        }
+
+        fn count_radius(&self, pivot: &Vector4<f32>, radius: f32, max: usize) -> usize {
+            let mut result = Vec::new();
+            self.search(pivot, SearchType::Radius(radius), &mut result);
+            result.len().min(max) + 100
+        }
+    }
+
+    #[test]
+    fn test_dyn_search() {
+        let storage = { (0..10).map(|x| Vector4::new(x as f32, 0., 0., 1.)) }
+            .map(|coords| Point3::default().with_coords(coords))
+            .collect::<Vec<_>>();
+        let input = PointCloud::from_vec(storage, 1);
+        let brute = Brute {
+            input: &input,
+            searches: Default::default(),
+        };
+
+        let searcher: &dyn DynSearch = &ErasedSearch::new(&brute);
+        assert_eq!((searcher.len(), searcher.is_empty()), (10, false));
+        assert_eq!(searcher.coords(3), &Vector4::new(3., 0., 0., 1.));
+
+        let pivot = Vector4::new(4.2, 0., 0., 1.);
+        let mut result = Vec::new();
+        searcher.search(&pivot, SearchType::Knn(2), &mut result);
+        assert_eq!(result.iter().map(|(i, _)| *i).collect::<Vec<_>>(), [4, 5]);
+        searcher.search_approx(&pivot, SearchType::Radius(1.5), 0.5, &mut result);
+        assert_eq!(
+            result.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            [4, 5, 3]
+        );
+
+        let mut visited = Vec::new();
+        let ty = SearchType::KnnWithin { k: 5, radius: 1.5 };
+        searcher.search_with(&pivot, ty, &mut |index, _| visited.push(index));
+        assert_eq!(visited, [4, 5, 3]);
+        assert_eq!(brute.searches.get(), 3);
+        // The overridden methods of the inner searcher are forwarded.
+        assert_eq!(searcher.count_radius(&pivot, 1.5, 2), 102);
+
+        let boxed = erase(&brute);
+        boxed.search_exact(&pivot, SearchType::Knn(1), &mut result);
+        assert_eq!(result.len(), 1);
+        assert!(result[0].0 == 4 && (result[0].1 - 0.2).abs() < 1e-6);
+        assert_eq!(brute.searches.get(), 5);
+    }
+}
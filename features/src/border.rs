@@ -3,7 +3,7 @@ use std::{array, slice};
 use nalgebra::{convert, RealField};
 use num::ToPrimitive;
 use pcc_common::{
-    feature::Feature,
+    feature::{DegenerateError, Feature, OutputPolicy, PolicyOutput},
     point::{Centroid, Data, DataFields, FieldInfo, PointRange},
     point_cloud::PointCloud,
     range_image::{RangeImage, SurfaceInfo},
@@ -58,6 +58,62 @@ impl BorderTraits {
     }
 }
 
+impl BorderTraits {
+    /// Whether each of `traits` has all the flags of `kind`.
+    pub fn mask(traits: &[BorderTraits], kind: BorderTraits) -> Vec<bool> {
+        traits.iter().map(|t| t.contains(kind)).collect()
+    }
+}
+
+/// The indices of the border points of each kind, extracted from the output
+/// of [`Border`] in one scan, like the arrays of PCL's `BorderDescription`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BorderDescription {
+    obstacle_borders: Vec<usize>,
+    shadow_borders: Vec<usize>,
+    veil_points: Vec<usize>,
+}
+
+impl BorderDescription {
+    pub fn new(traits: &PointCloud<BorderTraits>) -> Self {
+        let mut desc = BorderDescription::default();
+        for (index, t) in traits.iter().enumerate() {
+            if t.contains(BorderTraits::OBSTACLE_BORDER) {
+                desc.obstacle_borders.push(index);
+            }
+            if t.contains(BorderTraits::SHADOW_BORDER) {
+                desc.shadow_borders.push(index);
+            }
+            if t.contains(BorderTraits::VEIL_POINT) {
+                desc.veil_points.push(index);
+            }
+        }
+        desc
+    }
+
+    #[inline]
+    pub fn obstacle_border_indices(&self) -> &[usize] {
+        &self.obstacle_borders
+    }
+
+    #[inline]
+    pub fn shadow_border_indices(&self) -> &[usize] {
+        &self.shadow_borders
+    }
+
+    #[inline]
+    pub fn veil_indices(&self) -> &[usize] {
+        &self.veil_points
+    }
+}
+
+impl From<&PointCloud<BorderTraits>> for BorderDescription {
+    #[inline]
+    fn from(traits: &PointCloud<BorderTraits>) -> Self {
+        Self::new(traits)
+    }
+}
+
 impl Data for BorderTraits {
     type Data = u32;
 
@@ -106,7 +162,9 @@ impl<T> Border<T> {
         }
     }
 
-    pub fn surface<P>(&self, input: &RangeImage<P>) -> Option<Vec<SurfaceInfo<T>>>
+    /// Returns the surface information of every point in `input`, which is
+    /// `None` for the points with degenerate neighborhoods.
+    pub fn surface<P>(&self, input: &RangeImage<P>) -> Option<Vec<Option<SurfaceInfo<T>>>>
     where
         T: RealField + ToPrimitive,
         P: Sync + PointRange<Data = T>,
@@ -124,15 +182,14 @@ impl<T> Border<T> {
         let iter = input.par_iter().enumerate().map(|(index, point)| {
             let x = index % input.width();
             let y = index / input.width();
-            let surface_info = input.surface_info(
+            input.surface_info(
                 (x, y),
                 self.radius_plane_extraction,
                 step,
                 point.coords(),
                 num_neighbors,
                 true,
-            );
-            surface_info.unwrap()
+            )
         });
         iter.collect_into_vec(&mut surface);
 
@@ -212,7 +269,7 @@ impl<T> Border<T> {
     pub fn border_scores<P>(
         &self,
         input: &RangeImage<P>,
-        surface: &[SurfaceInfo<T>],
+        surface: &[Option<SurfaceInfo<T>>],
     ) -> Option<[Vec<T>; 4]>
     where
         T: RealField + Default,
@@ -229,6 +286,10 @@ impl<T> Border<T> {
             left.par_iter_mut(),
         ));
         iter.try_for_each(|((index, point), (surface, top, right, bottom, left))| {
+            let surface = match surface {
+                Some(surface) => surface,
+                None => return Some(()),
+            };
             let [x, y] = input.index(index);
 
             *top = self.border_score(input, (x, y), Self::OFFSET[0], point, surface)?;
@@ -361,6 +422,115 @@ impl<T> Border<T> {
         }
         true
     }
+
+    /// Classifies the points from the shadow borders of the obstacle borders
+    /// in every direction.
+    ///
+    /// Every point gathers its traits from the obstacle borders behind it,
+    /// whose shadow borders lie within the radius along the direction, so the
+    /// pass stays parallel and deterministic.
+    fn classify(
+        &self,
+        (width, height): (usize, usize),
+        borders: &[[Option<usize>; 4]],
+    ) -> Vec<BorderTraits>
+    where
+        T: Sync,
+    {
+        let mut storage = Vec::new();
+        let iter = (0..borders.len()).into_par_iter().map(|index| {
+            let (x, y) = (index % width, index / width);
+            let mut traits = BorderTraits::default();
+            for (di, &(ox, oy)) in Self::OFFSET.iter().enumerate() {
+                if borders[index][di].is_some() {
+                    traits |= BorderTraits::obstacle_border(di);
+                }
+
+                let oi = (di + 2) % 4;
+                for step in 1..=self.radius_borders.max(1) {
+                    let (bx, by) = (
+                        (x as isize) - (step as isize) * ox,
+                        (y as isize) - (step as isize) * oy,
+                    );
+                    if !((0..(width as isize)).contains(&bx)
+                        && (0..(height as isize)).contains(&by))
+                    {
+                        break;
+                    }
+                    let (bx, by) = (bx as usize, by as usize);
+                    match borders[by * width + bx][di] {
+                        Some(shadow_index) if shadow_index == index => {
+                            traits |= BorderTraits::shadow_border(oi)
+                        }
+                        // The points between the border and its shadow.
+                        Some(shadow_index) => {
+                            let (sx, sy) = (shadow_index % width, shadow_index / width);
+                            if step < sx.abs_diff(bx) + sy.abs_diff(by) {
+                                traits |= BorderTraits::veil_point(oi)
+                            }
+                        }
+                        None => {}
+                    }
+                }
+            }
+            traits
+        });
+        iter.collect_into_vec(&mut storage);
+        storage
+    }
+}
+
+impl<T> Border<T> {
+    /// Computes the border traits, where the points with degenerate
+    /// neighborhoods are never treated as borders. [`OutputPolicy::FillNan`]
+    /// is thus the same as [`OutputPolicy::Skip`].
+    fn compute_with<P>(
+        &self,
+        input: &RangeImage<P>,
+        policy: OutputPolicy,
+    ) -> Option<Result<PolicyOutput<PointCloud<BorderTraits>>, DegenerateError>>
+    where
+        T: RealField + ToPrimitive + Default,
+        P: Sync + PointRange<Data = T> + Centroid<Result = P>,
+        <P as Centroid>::Accumulator: Default,
+    {
+        let surface = self.surface(input)?;
+        let num_degenerate = surface.iter().filter(|surface| surface.is_none()).count();
+        if policy == OutputPolicy::Error {
+            if let Some(index) = surface.iter().position(|surface| surface.is_none()) {
+                return Some(Err(DegenerateError { index }));
+            }
+        }
+
+        let mut border_scores = self.border_scores(input, &surface)?;
+        let shadow_indices = self.shadow_indices(input, &mut border_scores);
+
+        // The shadow borders of the obstacle borders in every direction.
+        let mut borders = Vec::new();
+        let iter = shadow_indices
+            .par_iter()
+            .enumerate()
+            .map(|(index, shadow_indices)| {
+                array::from_fn(|di| {
+                    let shadow_index = shadow_indices[di]?;
+                    self.check_maximum(
+                        (input.width(), input.height()),
+                        input.index(index),
+                        Self::OFFSET[di],
+                        &border_scores[di],
+                        shadow_index,
+                    )
+                    .then_some(shadow_index)
+                })
+            });
+        iter.collect_into_vec(&mut borders);
+
+        let storage = self.classify((input.width(), input.height()), &borders);
+        Some(Ok(PolicyOutput {
+            output: unsafe { PointCloud::from_raw_parts(storage, input.width(), true) },
+            num_degenerate,
+        }))
+    }
 }
 
 impl<'a, T, P> Feature<&'a RangeImage<P>, Option<PointCloud<BorderTraits>>, (), ()> for Border<T>
@@ -370,41 +540,30 @@ where
     <P as Centroid>::Accumulator: Default,
 {
     fn compute(&self, input: &'a RangeImage<P>, _: (), _: ()) -> Option<PointCloud<BorderTraits>> {
-        let surface = self.surface(input)?;
-        let mut border_scores = self.border_scores(input, &surface)?;
-        let shadow_indices = self.shadow_indices(input, &mut border_scores);
-
-        let mut storage = vec![Default::default(); input.len()];
-        for index in 0..input.len() {
-            if !shadow_indices[index].iter().any(|x| x.is_some()) {
-                continue;
-            }
+        let output = self.compute_with(input, OutputPolicy::Skip)?;
+        Some(output.unwrap().output)
+    }
+}
 
-            let iter = shadow_indices[index]
-                .into_iter()
-                .enumerate()
-                .filter_map(|(di, si)| {
-                    si.and_then(|si| {
-                        self.check_maximum(
-                            (input.width(), input.height()),
-                            input.index(index),
-                            Self::OFFSET[di],
-                            &border_scores[di],
-                            si,
-                        )
-                        .then_some((di, si))
-                    })
-                });
-            for (di, shadow_index) in iter {
-                storage[index] |= BorderTraits::obstacle_border(di);
-                let oi = (di + 2) % 4;
-                storage[shadow_index] |= BorderTraits::shadow_border(oi);
-                for veil_point in storage.iter_mut().take(index).skip(shadow_index + 1) {
-                    *veil_point |= BorderTraits::veil_point(oi)
-                }
-            }
-        }
-        Some(unsafe { PointCloud::from_raw_parts(storage, input.width(), true) })
+impl<'a, T, P>
+    Feature<
+        &'a RangeImage<P>,
+        Option<Result<PolicyOutput<PointCloud<BorderTraits>>, DegenerateError>>,
+        (),
+        OutputPolicy,
+    > for Border<T>
+where
+    T: RealField + ToPrimitive + Default,
+    P: Sync + PointRange<Data = T> + Centroid<Result = P>,
+    <P as Centroid>::Accumulator: Default,
+{
+    fn compute(
+        &self,
+        input: &'a RangeImage<P>,
+        _: (),
+        policy: OutputPolicy,
+    ) -> Option<Result<PolicyOutput<PointCloud<BorderTraits>>, DegenerateError>> {
+        self.compute_with(input, policy)
     }
 }
 
@@ -412,8 +571,43 @@ where
 mod tests {
     use std::{array, convert::identity};
 
+    use pcc_common::point_cloud::PointCloud;
     use rayon::prelude::{IndexedParallelIterator, IntoParallelIterator};
 
+    use super::{Border, BorderDescription, BorderTraits};
+
+    #[test]
+    fn test_border_description() {
+        let traits = vec![
+            BorderTraits::empty(),
+            BorderTraits::obstacle_border(0),
+            BorderTraits::veil_point(1),
+            BorderTraits::shadow_border(2) | BorderTraits::veil_point(2),
+        ];
+        let mask = BorderTraits::mask(&traits, BorderTraits::VEIL_POINT);
+        assert_eq!(mask, [false, false, true, true]);
+
+        let desc = BorderDescription::new(&PointCloud::from_vec(traits, 1));
+        assert_eq!(desc.obstacle_border_indices(), [1]);
+        assert_eq!(desc.shadow_border_indices(), [3]);
+        assert_eq!(desc.veil_indices(), [2, 3]);
+    }
+
+    #[test]
+    fn test_classify() {
+        // An obstacle border at 1 facing right, whose shadow border is at 4.
+        let mut borders = vec![[None; 4]; 6];
+        borders[1][1] = Some(4);
+
+        let border = Border::new(0.8f32, 2, 3);
+        let traits = border.classify((6, 1), &borders);
+        assert_eq!(traits[1], BorderTraits::obstacle_border(1));
+        assert_eq!(traits[2], BorderTraits::veil_point(3));
+        assert_eq!(traits[3], BorderTraits::veil_point(3));
+        assert_eq!(traits[4], BorderTraits::shadow_border(3));
+        assert!(traits[0].is_empty() && traits[5].is_empty());
+    }
+
     #[test]
     fn test_par_iter() {
         let orig: [_; 20] = array::from_fn(identity);
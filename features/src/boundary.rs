@@ -19,7 +19,12 @@ impl<T: Scalar> Boundary<T> {
 }
 
 impl<T: RealField> Boundary<T> {
-    fn boundary<'a, Iter>(&self, pivot: &Vector4<T>, coords: Iter, [u, v]: &[Vector3<T>; 2]) -> bool
+    pub(crate) fn boundary<'a, Iter>(
+        &self,
+        pivot: &Vector4<T>,
+        coords: Iter,
+        [u, v]: &[Vector3<T>; 2],
+    ) -> bool
     where
         Iter: Iterator<Item = &'a Vector4<T>>,
     {
@@ -46,22 +51,23 @@ impl<T: RealField> Boundary<T> {
     }
 }
 
-impl<'a, 'b, T, I, S, N>
-    Feature<(&'a PointCloud<I>, &'b PointCloud<N>), PointCloud<bool>, S, SearchType<T>>
-    for Boundary<T>
+impl<'a, 'b, T, I, S, N, Sp>
+    Feature<(&'a PointCloud<I>, &'b PointCloud<N>), PointCloud<bool>, S, Sp> for Boundary<T>
 where
     T: RealField,
     I: Point<Data = T> + 'a,
     S: Search<'a, I>,
     N: Normal<Data = T> + 'b,
     rand::distributions::Standard: rand::distributions::Distribution<T>,
+    Sp: Into<SearchType<T>>,
 {
     fn compute(
         &self,
         (input, normals): (&'a PointCloud<I>, &'b PointCloud<N>),
         search: S,
-        search_param: SearchType<T>,
+        search_param: Sp,
     ) -> PointCloud<bool> {
+        let search_param = search_param.into();
         let mut result = Vec::new();
         let mut bounded = true;
         let storage = if input.is_bounded() {
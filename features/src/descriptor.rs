@@ -0,0 +1,327 @@
+use std::{
+    error::Error,
+    io::{self, Read},
+};
+
+use nalgebra::{DVector, Scalar};
+use num::{FromPrimitive, ToPrimitive};
+use pcc_common::{point_cloud::PointCloud, search::SearchType};
+
+/// The parameters a set of descriptors is computed with, which must be equal
+/// for the descriptors to be comparable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescriptorParams<T> {
+    /// The name of the descriptor, e.g. `"fpfh"`.
+    pub name: String,
+    pub search: SearchType<T>,
+    /// The numbers of the bins of the successive histograms of a descriptor.
+    pub layout: Vec<usize>,
+}
+
+impl<T> DescriptorParams<T> {
+    pub fn new(name: impl Into<String>, search: SearchType<T>, layout: Vec<usize>) -> Self {
+        DescriptorParams {
+            name: name.into(),
+            search,
+            layout,
+        }
+    }
+
+    /// The length of a descriptor.
+    #[inline]
+    pub fn dims(&self) -> usize {
+        self.layout.iter().sum()
+    }
+}
+
+/// Descriptors stored as the rows of a contiguous row-major matrix, along
+/// with the indices of their source points and their parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescriptorCloud<T> {
+    data: Vec<T>,
+    indices: Vec<usize>,
+    params: DescriptorParams<T>,
+}
+
+impl<T: Scalar> DescriptorCloud<T> {
+    pub fn new(params: DescriptorParams<T>) -> Self {
+        DescriptorCloud {
+            data: Vec::new(),
+            indices: Vec::new(),
+            params,
+        }
+    }
+
+    /// Collects the descriptors of a point cloud computed by a feature,
+    /// skipping the ones not of the length of `params`, e.g. the empty ones of
+    /// the degenerate neighborhoods.
+    pub fn from_point_cloud(
+        descriptors: &PointCloud<DVector<T>>,
+        params: DescriptorParams<T>,
+    ) -> Self {
+        let mut ret = Self::new(params);
+        for (index, descriptor) in descriptors.iter().enumerate() {
+            if descriptor.len() == ret.dims() {
+                ret.push(index, descriptor.as_slice());
+            }
+        }
+        ret
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the length of `descriptor` is not the one of the parameters.
+    pub fn push(&mut self, index: usize, descriptor: &[T]) {
+        assert_eq!(
+            descriptor.len(),
+            self.dims(),
+            "Mismatched descriptor length"
+        );
+        self.data.extend_from_slice(descriptor);
+        self.indices.push(index);
+    }
+
+    #[inline]
+    pub fn params(&self) -> &DescriptorParams<T> {
+        &self.params
+    }
+
+    #[inline]
+    pub fn dims(&self) -> usize {
+        self.params.dims()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// The descriptors in row-major order.
+    #[inline]
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+
+    /// The indices of the source points of the descriptors.
+    #[inline]
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+    #[inline]
+    pub fn row(&self, index: usize) -> &[T] {
+        &self.data[(index * self.dims())..][..self.dims()]
+    }
+
+    /// Iterates over the descriptors with the indices of their source points.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &[T])> + '_ {
+        { self.indices.iter().copied() }.zip(self.data.chunks_exact(self.dims().max(1)))
+    }
+
+    /// The descriptors as vectors, e.g. for the matching in
+    /// [`metrics`](crate::metrics).
+    pub fn to_vectors(&self) -> Vec<DVector<T>> {
+        { self.iter() }
+            .map(|(_, row)| DVector::from_column_slice(row))
+            .collect()
+    }
+
+    /// Checks whether the descriptors of `other` are comparable to these ones,
+    /// i.e. computed with the same parameters.
+    pub fn check_compatible(&self, other: &Self) -> Result<(), Box<dyn Error>> {
+        let (a, b) = (&self.params, &other.params);
+        if a.name != b.name {
+            return Err(format!("Mismatched descriptors: {:?} and {:?}", a.name, b.name).into());
+        }
+        if a.layout != b.layout {
+            return Err(format!("Mismatched layouts: {:?} and {:?}", a.layout, b.layout).into());
+        }
+        if a.search != b.search {
+            return Err(format!("Mismatched searches: {:?} and {:?}", a.search, b.search).into());
+        }
+        Ok(())
+    }
+}
+
+const MAGIC: &[u8; 4] = b"PCCD";
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_usize(output: &mut impl io::Write, value: usize) -> io::Result<()> {
+    output.write_all(&(value as u64).to_le_bytes())
+}
+
+fn read_usize(input: &mut impl io::Read) -> io::Result<usize> {
+    let mut buf = [0; 8];
+    input.read_exact(&mut buf)?;
+    usize::try_from(u64::from_le_bytes(buf)).map_err(|_| invalid("Too large value"))
+}
+
+fn write_value<T: ToPrimitive>(output: &mut impl io::Write, value: &T) -> io::Result<()> {
+    let value = value
+        .to_f64()
+        .ok_or_else(|| invalid("Unrepresentable value"))?;
+    output.write_all(&value.to_le_bytes())
+}
+
+fn read_value<T: FromPrimitive>(input: &mut impl io::Read) -> io::Result<T> {
+    let mut buf = [0; 8];
+    input.read_exact(&mut buf)?;
+    T::from_f64(f64::from_le_bytes(buf)).ok_or_else(|| invalid("Unrepresentable value"))
+}
+
+impl<T: Scalar + ToPrimitive + FromPrimitive> DescriptorCloud<T> {
+    /// Encodes the descriptors with their parameters in a binary format, with
+    /// the values stored as little-endian `f64`s.
+    pub fn encode(&self, mut output: impl io::Write) -> io::Result<()> {
+        output.write_all(MAGIC)?;
+
+        let params = &self.params;
+        write_usize(&mut output, params.name.len())?;
+        output.write_all(params.name.as_bytes())?;
+        match &params.search {
+            SearchType::Knn(k) => {
+                output.write_all(&[0])?;
+                write_usize(&mut output, *k)?;
+            }
+            SearchType::Radius(radius) => {
+                output.write_all(&[1])?;
+                write_value(&mut output, radius)?;
+            }
+            SearchType::KnnWithin { k, radius } => {
+                output.write_all(&[2])?;
+                write_usize(&mut output, *k)?;
+                write_value(&mut output, radius)?;
+            }
+        }
+        write_usize(&mut output, params.layout.len())?;
+        for &bins in &params.layout {
+            write_usize(&mut output, bins)?;
+        }
+
+        write_usize(&mut output, self.len())?;
+        for &index in &self.indices {
+            write_usize(&mut output, index)?;
+        }
+        for value in &self.data {
+            write_value(&mut output, value)?;
+        }
+        Ok(())
+    }
+
+    /// Decodes the descriptors encoded by [`DescriptorCloud::encode`].
+    pub fn decode(mut input: impl io::Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("Not a descriptor cloud"));
+        }
+
+        // Reads the name without trusting its length, which may be corrupted.
+        let len = read_usize(&mut input)?;
+        let mut name = Vec::new();
+        (&mut input).take(len as u64).read_to_end(&mut name)?;
+        if name.len() != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let name = String::from_utf8(name).map_err(|_| invalid("Invalid descriptor name"))?;
+        let mut tag = [0];
+        input.read_exact(&mut tag)?;
+        let search = match tag[0] {
+            0 => SearchType::Knn(read_usize(&mut input)?),
+            1 => SearchType::Radius(read_value(&mut input)?),
+            2 => SearchType::KnnWithin {
+                k: read_usize(&mut input)?,
+                radius: read_value(&mut input)?,
+            },
+            _ => return Err(invalid("Unknown search type")),
+        };
+        let layout = { 0..read_usize(&mut input)? }
+            .map(|_| read_usize(&mut input))
+            .collect::<io::Result<Vec<_>>>()?;
+        if { layout.iter() }
+            .try_fold(0usize, |acc, &dims| acc.checked_add(dims))
+            .is_none()
+        {
+            return Err(invalid("Too large layout"));
+        }
+        let params = DescriptorParams::new(name, search, layout);
+
+        let len = read_usize(&mut input)?;
+        let size = match len.checked_mul(params.dims()) {
+            Some(size) => size,
+            None => return Err(invalid("Too many descriptors")),
+        };
+        let indices = { (0..len).map(|_| read_usize(&mut input)) }.collect::<io::Result<_>>()?;
+        let data = { (0..size).map(|_| read_value(&mut input)) }.collect::<io::Result<_>>()?;
+        Ok(DescriptorCloud {
+            data,
+            indices,
+            params,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_descriptor_cloud() {
+        let descriptors = vec![
+            DVector::from_vec(vec![1., 2., 3.]),
+            DVector::from_vec(Vec::new()),
+            DVector::from_vec(vec![4., 5., 6.]),
+        ];
+        let descriptors = unsafe { PointCloud::from_raw_parts(descriptors, 3, true) };
+        let params = DescriptorParams::new("test", SearchType::Radius(0.5f32), vec![2, 1]);
+        let cloud = DescriptorCloud::from_point_cloud(&descriptors, params.clone());
+        assert_eq!(cloud.indices(), [0, 2]);
+        assert_eq!(cloud.row(1), [4., 5., 6.]);
+
+        let mut data = Vec::new();
+        cloud.encode(&mut data).unwrap();
+        let decoded = DescriptorCloud::decode(&*data).unwrap();
+        assert_eq!(decoded, cloud);
+        assert!(decoded.check_compatible(&cloud).is_ok());
+
+        let other = DescriptorCloud::new(DescriptorParams {
+            search: SearchType::Radius(1.),
+            ..params
+        });
+        assert!(other.check_compatible(&cloud).is_err());
+    }
+
+    #[test]
+    fn test_decode_corrupted() {
+        let params = DescriptorParams::new("test", SearchType::Knn(4), vec![2, 1]);
+        let cloud = DescriptorCloud::<f64>::new(params);
+        let mut data = Vec::new();
+        cloud.encode(&mut data).unwrap();
+
+        // The length of the name.
+        let mut corrupted = data.clone();
+        corrupted[4..12].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(DescriptorCloud::<f64>::decode(&*corrupted).is_err());
+
+        // The dimensions of the layout, which overflow when summed.
+        let mut corrupted = data.clone();
+        let layout = 4 + 8 + 4 + 1 + 8 + 8;
+        corrupted[layout..layout + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(DescriptorCloud::<f64>::decode(&*corrupted).is_err());
+
+        // The number of the descriptors, whose values overflow.
+        let mut corrupted = data;
+        let len = corrupted.len() - 8;
+        corrupted[len..].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+        assert!(DescriptorCloud::<f64>::decode(&*corrupted).is_err());
+    }
+}
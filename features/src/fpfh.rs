@@ -5,13 +5,13 @@ use nalgebra::{
 };
 use num::ToPrimitive;
 use pcc_common::{
-    feature::Feature,
+    feature::{DegenerateError, Feature, Keypoints, OutputPolicy, PolicyOutput},
     point::{Normal, Point},
     point_cloud::PointCloud,
     search::{Search, SearchType},
 };
 
-use crate::{pfh::PfhPair, HIST_MAX};
+use crate::{pfh::PfhPair, DescriptorParams, HIST_MAX};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Fpfh {
@@ -22,6 +22,11 @@ impl Fpfh {
     pub fn new(subdivision: [usize; 3]) -> Self {
         Fpfh { subdivision }
     }
+
+    /// The parameters of the descriptors computed with `search`.
+    pub fn descriptor_params<T>(&self, search: SearchType<T>) -> DescriptorParams<T> {
+        DescriptorParams::new("fpfh", search, self.subdivision.to_vec())
+    }
 }
 
 impl Fpfh {
@@ -60,6 +65,7 @@ impl Fpfh {
             ];
             for ((data, num), hist) in data.into_iter().zip(num.clone()).zip(hist.iter_mut()) {
                 let index = { data.clamp(T::zero(), num).floor() }.to_usize().unwrap();
+                let index = index.min(hist.ncols() - 1);
                 hist[index] += inc.clone()
             }
         }
@@ -67,44 +73,48 @@ impl Fpfh {
 }
 
 impl Fpfh {
-    fn compute_spfh<'a, T: RealField, P, S, N>(
+    /// Computes the SPFHs of the points of the surface, i.e. the input of
+    /// `search`, that are the neighbors of `keypoints`, or of all of them if
+    /// `keypoints` is `None`.
+    fn compute_spfh<'a, T: RealField, K, P, S, N>(
         &self,
-        input: &PointCloud<P>,
+        keypoints: Option<&PointCloud<K>>,
         normals: &PointCloud<N>,
         search: &S,
         ty: SearchType<T>,
     ) -> (Vec<usize>, [DMatrix<T>; 3])
     where
         T: RealField + ToPrimitive,
+        K: Point<Data = T>,
         P: Point<Data = T> + 'a,
         S: Search<'a, P>,
         N: Normal<Data = T>,
     {
         let mut result = Vec::new();
+        let surface = search.input();
 
-        let indices = if search.input() == input {
-            (0..input.len()).collect::<HashSet<_>>()
-        } else {
-            input.iter().fold(HashSet::new(), |mut set, point| {
+        let indices = match keypoints {
+            None => (0..surface.len()).collect::<HashSet<_>>(),
+            Some(keypoints) => keypoints.iter().fold(HashSet::new(), |mut set, point| {
                 search.search(point.coords(), ty.clone(), &mut result);
                 set.extend(result.iter().map(|&(index, _)| index));
                 set
-            })
+            }),
         };
 
-        let mut ret = vec![0; indices.len()];
+        let mut ret = vec![0; surface.len()];
 
         let mut hist = self
             .subdivision
             .map(|sub| DMatrix::zeros(indices.len(), sub));
 
         for (ii, index) in indices.into_iter().enumerate() {
-            search.search(input[index].coords(), ty.clone(), &mut result);
+            search.search(surface[index].coords(), ty.clone(), &mut result);
             let [h1, h2, h3] = &mut hist;
             self.point_spfh(
                 index,
                 &result,
-                search.input(),
+                surface,
                 normals,
                 [h1.row_mut(ii), h2.row_mut(ii), h3.row_mut(ii)],
             );
@@ -139,76 +149,216 @@ impl Fpfh {
             }
         }
 
-        let sum = sum.map(|sum| convert::<_, T>(HIST_MAX) / sum);
-
-        ret.columns_range_mut(0..hist[0].ncols())
-            .apply(|elem| *elem *= sum[0].clone());
-        ret.columns_range_mut(hist[0].ncols()..)
-            .columns_range_mut(..hist[1].ncols())
-            .apply(|elem| *elem *= sum[1].clone());
-        ret.columns_range_mut(hist[1].ncols()..)
-            .apply(|elem| *elem *= sum[2].clone());
+        let mut offset = 0;
+        for (hist, sum) in hist.iter().zip(sum) {
+            if sum > T::zero() {
+                let scale = convert::<_, T>(HIST_MAX) / sum;
+                ret.rows_range_mut(offset..offset + hist.ncols())
+                    .apply(|elem| *elem *= scale.clone());
+            }
+            offset += hist.ncols();
+        }
 
         ret
     }
 }
 
-impl<'a, 'b, T, I, S, N>
-    Feature<(&'a PointCloud<I>, &'b PointCloud<N>), PointCloud<DVector<T>>, S, SearchType<T>>
-    for Fpfh
+impl Fpfh {
+    /// Computes the descriptors of the points of `input` from the surface,
+    /// i.e. the input of `search`, which `input` is if `is_surface`.
+    fn compute_with<'a, T, K, I, S, N>(
+        &self,
+        (input, normals): (&PointCloud<K>, &PointCloud<N>),
+        is_surface: bool,
+        search: S,
+        search_param: SearchType<T>,
+        policy: OutputPolicy,
+    ) -> Result<PolicyOutput<PointCloud<DVector<T>>>, DegenerateError>
+    where
+        T: RealField + ToPrimitive,
+        K: Point<Data = T>,
+        I: Point<Data = T> + 'a,
+        S: Search<'a, I>,
+        N: Normal<Data = T>,
+    {
+        let mut result = Vec::new();
+
+        let keypoints = (!is_surface).then_some(input);
+        let (indices, hist) = self.compute_spfh(keypoints, normals, &search, search_param.clone());
+        let len = hist.iter().map(|mat| mat.ncols()).sum();
+
+        let values = { input.iter() }
+            .map(|point| {
+                if !input.is_bounded() && !point.is_finite() {
+                    return Some(DVector::zeros(len));
+                }
+                search.search(point.coords(), search_param.clone(), &mut result);
+                if result.is_empty() {
+                    return None;
+                }
+                for (index, _) in result.iter_mut() {
+                    *index = indices[*index];
+                }
+                Some(self.weight_spfh(&hist, &result))
+            })
+            .collect::<Vec<_>>();
+
+        let PolicyOutput {
+            output,
+            num_degenerate,
+        } = policy.resolve(
+            values,
+            || DVector::zeros(len),
+            || DVector::from_element(len, convert(f64::NAN)),
+        )?;
+        let bounded = input.is_bounded() && num_degenerate == 0;
+        Ok(PolicyOutput {
+            output: unsafe { PointCloud::from_raw_parts(output, input.width(), bounded) },
+            num_degenerate,
+        })
+    }
+}
+
+impl<'a, 'b, T, I, S, N, Sp>
+    Feature<(&'a PointCloud<I>, &'b PointCloud<N>), PointCloud<DVector<T>>, S, Sp> for Fpfh
 where
     T: RealField + ToPrimitive,
     I: Point<Data = T> + 'a,
-    S: Search<'a, I> + Clone,
+    S: Search<'a, I>,
     N: Normal<Data = T> + 'b,
+    Sp: Into<SearchType<T>>,
 {
     fn compute(
         &self,
-        (input, normals): (&'a PointCloud<I>, &'b PointCloud<N>),
+        input: (&'a PointCloud<I>, &'b PointCloud<N>),
         search: S,
-        search_param: SearchType<T>,
+        search_param: Sp,
     ) -> PointCloud<DVector<T>> {
-        let mut result = Vec::new();
+        let is_surface = search.input() == input.0;
+        let output = self.compute_with(
+            input,
+            is_surface,
+            search,
+            search_param.into(),
+            OutputPolicy::Skip,
+        );
+        output.unwrap().output
+    }
+}
 
-        let (indices, hist) = self.compute_spfh(input, normals, &search, search_param.clone());
-
-        let mut bounded = true;
-        let storage = if input.is_bounded() {
-            { input.iter() }
-                .map(|point| {
-                    search.search(point.coords(), search_param.clone(), &mut result);
-                    if result.is_empty() {
-                        bounded = false;
-                        DVector::zeros(hist.iter().map(|mat| mat.ncols()).sum())
-                    } else {
-                        for (index, _) in result.iter_mut() {
-                            *index = indices[*index];
-                        }
-                        self.weight_spfh(&hist, &result)
-                    }
-                })
-                .collect::<Vec<_>>()
-        } else {
-            { input.iter() }
-                .map(|point| {
-                    if !point.is_finite() {
-                        bounded = false;
-                        return DVector::zeros(hist.iter().map(|mat| mat.ncols()).sum());
-                    }
-                    search.search(point.coords(), search_param.clone(), &mut result);
-                    if result.is_empty() {
-                        bounded = false;
-                        DVector::zeros(hist.iter().map(|mat| mat.ncols()).sum())
-                    } else {
-                        for (index, _) in result.iter_mut() {
-                            *index = indices[*index];
-                        }
-                        self.weight_spfh(&hist, &result)
-                    }
+impl<'a, 'b, T, I, S, N, Sp>
+    Feature<
+        (&'a PointCloud<I>, &'b PointCloud<N>),
+        Result<PolicyOutput<PointCloud<DVector<T>>>, DegenerateError>,
+        S,
+        (Sp, OutputPolicy),
+    > for Fpfh
+where
+    T: RealField + ToPrimitive,
+    I: Point<Data = T> + 'a,
+    S: Search<'a, I>,
+    N: Normal<Data = T> + 'b,
+    Sp: Into<SearchType<T>>,
+{
+    fn compute(
+        &self,
+        input: (&'a PointCloud<I>, &'b PointCloud<N>),
+        search: S,
+        (search_param, policy): (Sp, OutputPolicy),
+    ) -> Result<PolicyOutput<PointCloud<DVector<T>>>, DegenerateError> {
+        let is_surface = search.input() == input.0;
+        self.compute_with(input, is_surface, search, search_param.into(), policy)
+    }
+}
+
+impl<'a, T, K, I, S, N, Sp> Feature<Keypoints<'a, K, I, N>, PointCloud<DVector<T>>, S, Sp> for Fpfh
+where
+    T: RealField + ToPrimitive,
+    K: Point<Data = T>,
+    I: Point<Data = T> + 'a,
+    S: Search<'a, I>,
+    N: Normal<Data = T>,
+    Sp: Into<SearchType<T>>,
+{
+    fn compute(
+        &self,
+        input: Keypoints<'a, K, I, N>,
+        search: S,
+        search_param: Sp,
+    ) -> PointCloud<DVector<T>> {
+        let input = input.split(search.input());
+        let output = self.compute_with(
+            input,
+            false,
+            search,
+            search_param.into(),
+            OutputPolicy::Skip,
+        );
+        output.unwrap().output
+    }
+}
+
+impl<'a, T, K, I, S, N, Sp>
+    Feature<
+        Keypoints<'a, K, I, N>,
+        Result<PolicyOutput<PointCloud<DVector<T>>>, DegenerateError>,
+        S,
+        (Sp, OutputPolicy),
+    > for Fpfh
+where
+    T: RealField + ToPrimitive,
+    K: Point<Data = T>,
+    I: Point<Data = T> + 'a,
+    S: Search<'a, I>,
+    N: Normal<Data = T>,
+    Sp: Into<SearchType<T>>,
+{
+    fn compute(
+        &self,
+        input: Keypoints<'a, K, I, N>,
+        search: S,
+        (search_param, policy): (Sp, OutputPolicy),
+    ) -> Result<PolicyOutput<PointCloud<DVector<T>>>, DegenerateError> {
+        let input = input.split(search.input());
+        self.compute_with(input, false, search, search_param.into(), policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::point::{Point3, Point3N};
+    use pcc_search::KdTree;
+
+    use super::*;
+
+    #[test]
+    fn test_keypoints() {
+        let (surface, normals): (Vec<_>, Vec<_>) =
+            { (0..8).flat_map(|x| (0..8).map(move |y| (x, y))) }
+                .map(|(x, y)| {
+                    let coords = Vector4::new(x as f32, y as f32, ((x * y) % 3) as f32 * 0.1, 1.);
+                    let normal = Vector4::new(0., (x % 2) as f32 * 0.1, 1., 0.).normalize();
+                    (
+                        Point3::default().with_coords(coords),
+                        Point3N::default().with_coords(coords).with_normal(normal),
+                    )
                 })
-                .collect::<Vec<_>>()
-        };
+                .unzip();
+        let surface = PointCloud::from_vec(surface, 8);
+        let normals = PointCloud::from_vec(normals, 8);
+        let keypoints = PointCloud::from_vec(vec![surface[9].clone(), surface[42].clone()], 2);
 
-        unsafe { PointCloud::from_raw_parts(storage, input.width(), bounded) }
+        let fpfh = Fpfh::new([5, 5, 5]);
+        let search = KdTree::new(&surface);
+        let all: PointCloud<_> =
+            fpfh.compute((&surface, &normals), &search, SearchType::Radius(1.5));
+        let at_keypoints: PointCloud<_> = fpfh.compute(
+            Keypoints::new(&keypoints, &surface, &normals),
+            &search,
+            SearchType::Radius(1.5),
+        );
+        assert_eq!(at_keypoints[0], all[9]);
+        assert_eq!(at_keypoints[1], all[42]);
     }
 }
@@ -1,6 +1,6 @@
 use nalgebra::{convert, Matrix3, RealField, Vector3};
 use pcc_common::{
-    feature::Feature,
+    feature::{DegenerateError, Feature, OutputPolicy, PolicyOutput},
     point::{Normal, PointIntensity},
     point_cloud::PointCloud,
     search::{Search, SearchType},
@@ -64,102 +64,106 @@ impl IntensityGradient {
     }
 }
 
-impl<'a, T, P, N, S>
-    Feature<(&'a PointCloud<P>, &'a PointCloud<N>), PointCloud<Vector3<T>>, S, SearchType<T>>
+impl IntensityGradient {
+    fn compute_with<'a, T, P, N, S>(
+        &self,
+        (input, normals): (&'a PointCloud<P>, &'a PointCloud<N>),
+        search: S,
+        ty: SearchType<T>,
+        policy: OutputPolicy,
+    ) -> Result<PolicyOutput<PointCloud<Vector3<T>>>, DegenerateError>
+    where
+        T: RealField + Default,
+        P: Sync + PointIntensity<Data = T>,
+        N: Sync + Normal<Data = T>,
+        S: Sync + Search<'a, P>,
+    {
+        let mut values = Vec::new();
+        let iter = input.par_iter().zip(normals.par_iter());
+        iter.map(|(point, normal)| {
+            if !input.is_bounded() && !point.is_finite() {
+                return Some(Vector3::zeros());
+            }
+            let mut result = Vec::new();
+            search.search(point.coords(), ty.clone(), &mut result);
+            if result.is_empty() {
+                return None;
+            }
+            let (point, intensity) = result.iter().fold(
+                (Vector3::zeros(), T::zero()),
+                |(point, intensity), &(index, _)| {
+                    (
+                        point + search.input()[index].coords().xyz(),
+                        intensity + search.input()[index].intensity(),
+                    )
+                },
+            );
+            let num: T = convert(result.len() as f64);
+            self.point(
+                input,
+                &result,
+                point / num.clone(),
+                normal.normal().xyz(),
+                intensity / num,
+            )
+        })
+        .collect_into_vec(&mut values);
+
+        let PolicyOutput {
+            output,
+            num_degenerate,
+        } = policy.resolve(values, Vector3::zeros, || {
+            Vector3::repeat(convert(f64::NAN))
+        })?;
+        let bounded = input.is_bounded() && num_degenerate == 0;
+        Ok(PolicyOutput {
+            output: unsafe { PointCloud::from_raw_parts(output, input.width(), bounded) },
+            num_degenerate,
+        })
+    }
+}
+
+impl<'a, T, P, N, S, Sp>
+    Feature<(&'a PointCloud<P>, &'a PointCloud<N>), PointCloud<Vector3<T>>, S, Sp>
     for IntensityGradient
 where
     T: RealField + Default,
     P: Sync + PointIntensity<Data = T>,
     N: Sync + Normal<Data = T>,
     S: Sync + Search<'a, P>,
+    Sp: Into<SearchType<T>>,
 {
     fn compute(
         &self,
-        (input, normals): (&'a PointCloud<P>, &'a PointCloud<N>),
+        input: (&'a PointCloud<P>, &'a PointCloud<N>),
         search: S,
-        ty: SearchType<T>,
+        ty: Sp,
     ) -> PointCloud<Vector3<T>> {
-        fn collect<T: Clone + Send + Sync>(
-            iter: impl ParallelIterator<Item = (bool, Vector3<T>)>,
-            init: bool,
-        ) -> (Vec<Vector3<T>>, bool) {
-            let fold = iter.fold_with(
-                (Vec::new(), init),
-                |(mut storage, bounded), (b2, gradient)| {
-                    storage.push(gradient);
-                    (storage, bounded & b2)
-                },
-            );
-            fold.reduce(
-                || (Vec::new(), true),
-                |(mut sa, ba), (mut sb, bb)| {
-                    sa.append(&mut sb);
-                    (sa, ba & bb)
-                },
-            )
-        }
-
-        let zip = input.par_iter().zip(normals.par_iter());
-
-        let (storage, bounded) = if input.is_bounded() {
-            let iter = zip.map(|(point, normal)| {
-                let mut result = Vec::new();
-                search.search(point.coords(), ty.clone(), &mut result);
-                if result.is_empty() {
-                    return (false, Vector3::zeros());
-                }
-                let (point, intensity) = result.iter().fold(
-                    (Vector3::zeros(), T::zero()),
-                    |(point, intensity), &(index, _)| {
-                        (
-                            point + search.input()[index].coords().xyz(),
-                            intensity + search.input()[index].intensity(),
-                        )
-                    },
-                );
-                let num: T = convert(result.len() as f64);
-                let gradient = self.point(
-                    input,
-                    &result,
-                    point / num.clone(),
-                    normal.normal().xyz(),
-                    intensity / num,
-                );
-                (true, gradient.unwrap_or_default())
-            });
-            collect(iter, true)
-        } else {
-            let iter = zip.map(|(point, normal)| {
-                if !point.is_finite() {
-                    return (false, Vector3::zeros());
-                }
-                let mut result = Vec::new();
-                search.search(point.coords(), ty.clone(), &mut result);
-                if result.is_empty() {
-                    return (false, Vector3::zeros());
-                }
-                let (point, intensity) = result.iter().fold(
-                    (Vector3::zeros(), T::zero()),
-                    |(point, intensity), &(index, _)| {
-                        (
-                            point + search.input()[index].coords().xyz(),
-                            intensity + search.input()[index].intensity(),
-                        )
-                    },
-                );
-                let num: T = convert(result.len() as f64);
-                let gradient = self.point(
-                    input,
-                    &result,
-                    point / num.clone(),
-                    normal.normal().xyz(),
-                    intensity / num,
-                );
-                (true, gradient.unwrap_or_default())
-            });
-            collect(iter, true)
-        };
+        let output = self.compute_with(input, search, ty.into(), OutputPolicy::Skip);
+        output.unwrap().output
+    }
+}
 
-        unsafe { PointCloud::from_raw_parts(storage, input.width(), bounded) }
+impl<'a, T, P, N, S, Sp>
+    Feature<
+        (&'a PointCloud<P>, &'a PointCloud<N>),
+        Result<PolicyOutput<PointCloud<Vector3<T>>>, DegenerateError>,
+        S,
+        (Sp, OutputPolicy),
+    > for IntensityGradient
+where
+    T: RealField + Default,
+    P: Sync + PointIntensity<Data = T>,
+    N: Sync + Normal<Data = T>,
+    S: Sync + Search<'a, P>,
+    Sp: Into<SearchType<T>>,
+{
+    fn compute(
+        &self,
+        input: (&'a PointCloud<P>, &'a PointCloud<N>),
+        search: S,
+        (ty, policy): (Sp, OutputPolicy),
+    ) -> Result<PolicyOutput<PointCloud<Vector3<T>>>, DegenerateError> {
+        self.compute_with(input, search, ty.into(), policy)
     }
 }
@@ -4,26 +4,43 @@
 mod border;
 mod boundary;
 mod crh;
+mod descriptor;
 mod fpfh;
 mod gasd;
 mod intensity;
+mod loam;
+mod local_surface;
+pub mod metrics;
 mod moment;
 mod narf;
 mod normal;
 mod pfh;
+mod range_corner;
+mod robust_normal;
+mod streaming_normal;
+mod susan;
+mod trajkovic;
 mod vfh;
 
 pub use self::{
-    border::{Border, BorderTraits},
+    border::{Border, BorderDescription, BorderTraits},
     boundary::Boundary,
     crh::Crh,
+    descriptor::{DescriptorCloud, DescriptorParams},
     fpfh::Fpfh,
     gasd::{Gasd, GasdColor, GasdData, GasdOutput},
     intensity::IntensityGradient,
+    loam::{Loam, LoamOutput},
+    local_surface::{LocalSurface, PrincipalCurvatures, SurfaceData},
     moment::MomentInvariant,
-    narf::{Narf, NarfData, SurfacePatch},
-    normal::Normal,
+    narf::{narf_distance, Narf, NarfData, SurfacePatch},
+    normal::{AdaptiveNormal, Normal},
     pfh::Pfh,
+    range_corner::{RangeCorner, RangeKeypoint},
+    robust_normal::RobustNormal,
+    streaming_normal::StreamingNormal,
+    susan::Susan3d,
+    trajkovic::TrajkovicHedley,
     vfh::Vfh,
 };
 
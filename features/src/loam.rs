@@ -0,0 +1,216 @@
+use nalgebra::{convert, RealField, Vector3, Vector4};
+use pcc_common::{feature::Feature, point::PointRing, point_cloud::PointCloud};
+
+/// The feature point clouds extracted by [`Loam`].
+#[derive(Debug, Clone)]
+pub struct LoamOutput<P> {
+    /// The sharp edge points, with the largest curvatures.
+    pub edges: PointCloud<P>,
+    /// The planar points, with the smallest curvatures.
+    pub planes: PointCloud<P>,
+}
+
+/// The extraction of the edge and planar points of the LOAM front-end, after
+/// *LOAM: Lidar Odometry and Mapping in Real-time* by Zhang and Singh.
+///
+/// Every scan line, i.e. the points of the same ring in their order in the
+/// point cloud as split by [`PointCloud::scan_lines`], is processed
+/// separately. The curvature of a point is the norm of the sum of its
+/// differences with `neighbors` points on each side, divided by the number
+/// of them and its range. The scan lines are split into
+/// `sectors` parts of equal lengths, in each of which at most `max_edges`
+/// points with the largest curvatures above `edge_threshold` are edges, and
+/// at most `max_planes` points with the smallest curvatures below
+/// `plane_threshold` are planes.
+///
+/// The neighbors of a selected point are no longer selected, and neither are
+/// the points on the far side of a gap wider than `occlusion_threshold`
+/// between consecutive points, which may be occluded by the near side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loam<T> {
+    pub neighbors: usize,
+    pub sectors: usize,
+    pub max_edges: usize,
+    pub max_planes: usize,
+    pub edge_threshold: T,
+    pub plane_threshold: T,
+    pub occlusion_threshold: T,
+}
+
+impl<T: RealField> Loam<T> {
+    /// Creates an extraction with the other parameters of the original paper,
+    /// i.e. 5 neighbors on each side, 6 sectors, 2 edges and 4 planes per
+    /// sector, and the occlusion threshold of 0.1 in the units of the
+    /// coordinates.
+    pub fn new(edge_threshold: T, plane_threshold: T) -> Self {
+        Loam {
+            neighbors: 5,
+            sectors: 6,
+            max_edges: 2,
+            max_planes: 4,
+            edge_threshold,
+            plane_threshold,
+            occlusion_threshold: convert(0.1),
+        }
+    }
+
+    pub fn with_neighbors(mut self, neighbors: usize) -> Self {
+        self.neighbors = neighbors;
+        self
+    }
+
+    pub fn with_sectors(mut self, sectors: usize) -> Self {
+        self.sectors = sectors;
+        self
+    }
+
+    pub fn with_max_features(mut self, max_edges: usize, max_planes: usize) -> Self {
+        self.max_edges = max_edges;
+        self.max_planes = max_planes;
+        self
+    }
+
+    pub fn with_occlusion_threshold(mut self, occlusion_threshold: T) -> Self {
+        self.occlusion_threshold = occlusion_threshold;
+        self
+    }
+
+    /// Extracts the features of a scan line, and returns the indices of its
+    /// edges and planes in it.
+    fn extract_line(&self, line: &[&Vector4<T>]) -> (Vec<usize>, Vec<usize>) {
+        let k = self.neighbors;
+        let (mut edges, mut planes) = (Vec::new(), Vec::new());
+        if k == 0 || line.len() <= 2 * k {
+            return (edges, planes);
+        }
+
+        let curvatures = (0..line.len())
+            .map(|index| {
+                if index < k || index + k >= line.len() {
+                    return None;
+                }
+                let center = line[index].xyz();
+                let range = center.norm();
+                let sum = { line[index - k..=index + k].iter() }
+                    .fold(Vector3::zeros(), |acc, other| acc + (other.xyz() - &center));
+                let num = T::from_usize(2 * k).unwrap();
+                let curvature = sum.norm() / (num * range);
+                curvature.is_finite().then_some(curvature)
+            })
+            .collect::<Vec<_>>();
+
+        let mut picked = vec![false; line.len()];
+        for index in 0..line.len() - 1 {
+            let gap = (line[index + 1] - line[index]).xyz().norm();
+            if gap <= self.occlusion_threshold {
+                continue;
+            }
+            let (near, far) = (line[index].xyz().norm(), line[index + 1].xyz().norm());
+            if near > far {
+                let start = index.saturating_sub(k);
+                picked[start..=index].iter_mut().for_each(|p| *p = true);
+            } else {
+                let end = (index + 1 + k).min(line.len() - 1);
+                picked[index + 1..=end].iter_mut().for_each(|p| *p = true);
+            }
+        }
+
+        let pick = |picked: &mut [bool], index: usize| {
+            let start = index.saturating_sub(k);
+            let end = (index + k).min(line.len() - 1);
+            picked[start..=end].iter_mut().for_each(|p| *p = true);
+        };
+
+        let sectors = self.sectors.max(1);
+        let valid = line.len() - 2 * k;
+        for sector in 0..sectors {
+            let start = k + valid * sector / sectors;
+            let end = k + valid * (sector + 1) / sectors;
+            let mut sorted =
+                { (start..end).filter_map(|index| Some((index, curvatures[index].clone()?))) }
+                    .collect::<Vec<_>>();
+            sorted.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap());
+
+            let mut num = 0;
+            for (index, curvature) in &sorted {
+                if num >= self.max_edges || *curvature <= self.edge_threshold {
+                    break;
+                }
+                if !picked[*index] {
+                    edges.push(*index);
+                    pick(&mut picked, *index);
+                    num += 1;
+                }
+            }
+
+            let mut num = 0;
+            for (index, curvature) in sorted.iter().rev() {
+                if num >= self.max_planes || *curvature >= self.plane_threshold {
+                    break;
+                }
+                if !picked[*index] {
+                    planes.push(*index);
+                    pick(&mut picked, *index);
+                    num += 1;
+                }
+            }
+        }
+        (edges, planes)
+    }
+}
+
+impl<'a, T, P> Feature<&'a PointCloud<P>, LoamOutput<P>, (), ()> for Loam<T>
+where
+    T: RealField,
+    P: PointRing<Data = T>,
+{
+    fn compute(&self, input: &'a PointCloud<P>, _: (), _: ()) -> LoamOutput<P> {
+        let (mut edges, mut planes) = (Vec::new(), Vec::new());
+        for indices in input.scan_lines().lines.into_values() {
+            let line = { indices.iter() }
+                .map(|&index| input[index].coords())
+                .collect::<Vec<_>>();
+            let (e, p) = self.extract_line(&line);
+            edges.extend(e.into_iter().map(|i| input[indices[i]].clone()));
+            planes.extend(p.into_iter().map(|i| input[indices[i]].clone()));
+        }
+
+        LoamOutput {
+            edges: PointCloud::from_vec(edges, 1),
+            planes: PointCloud::from_vec(planes, 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pcc_common::point::{Point, Point3IR};
+
+    use super::*;
+
+    #[test]
+    fn test_loam() {
+        // Two walls meeting at a corner at (2, 2), scanned from the origin.
+        let storage = { (0..40).map(|i| i as f32 * 0.1) }
+            .map(|t| {
+                let coords = if t <= 2. {
+                    Vector4::new(t, 2., 0., 1.)
+                } else {
+                    Vector4::new(2., 4. - t, 0., 1.)
+                };
+                Point3IR::default().with_coords(coords).with_ring(3)
+            })
+            .collect::<Vec<_>>();
+        let pc = PointCloud::from_vec(storage, 1);
+
+        let output = Loam::new(0.05, 0.01)
+            .with_sectors(1)
+            .with_occlusion_threshold(0.5)
+            .compute(&pc, (), ());
+        assert_eq!(output.edges.len(), 1);
+        let edge = output.edges[0].coords();
+        assert!((edge - Vector4::new(2., 2., 0., 1.)).norm() < 0.15);
+        assert!(!output.planes.is_empty());
+        assert!(output.planes.iter().all(|point| point.ring() == 3));
+    }
+}
@@ -0,0 +1,282 @@
+use std::cmp::Ordering;
+
+use nalgebra::{Matrix3, RealField, Scalar, Vector3, Vector4};
+use pcc_common::{
+    feature::{DegenerateError, OutputPolicy, PolicyOutput},
+    point::{Normal, Point},
+    point_cloud::PointCloud,
+    search::{Search, SearchType},
+};
+
+use crate::{normal::resolve, susan::non_max_suppression, Boundary};
+
+/// The mean, the covariance and the eigen-decomposition of the neighborhood
+/// of a point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurfaceData<T: Scalar> {
+    pub mean: Vector3<T>,
+    pub covariance: Matrix3<T>,
+    /// The eigenvalues of the covariance in the ascending order.
+    pub eigenvalues: Vector3<T>,
+    /// The eigenvectors of the covariance as the columns, in the same order
+    /// as the eigenvalues, so the first one is the normal.
+    pub eigenvectors: Matrix3<T>,
+}
+
+impl<T: RealField> SurfaceData<T> {
+    fn new(coords: &[&Vector4<T>]) -> Option<Self> {
+        let covariance = pcc_common::cov_matrix(coords.iter().copied())?;
+        let mean = { coords.iter() }.fold(Vector3::zeros(), |acc, coords| acc + coords.xyz())
+            / T::from_usize(coords.len()).unwrap();
+
+        let se = covariance.clone().symmetric_eigen();
+        let mut order = [0, 1, 2];
+        order.sort_by(|&a, &b| {
+            { se.eigenvalues[a].partial_cmp(&se.eigenvalues[b]) }.unwrap_or(Ordering::Equal)
+        });
+        Some(SurfaceData {
+            mean,
+            covariance,
+            eigenvalues: Vector3::from_fn(|i, _| se.eigenvalues[order[i]].clone()),
+            eigenvectors: Matrix3::from_fn(|r, c| se.eigenvectors[(r, order[c])].clone()),
+        })
+    }
+
+    /// The normal, flipped towards `viewpoint` like [`pcc_common::normal`].
+    pub fn normal(&self, viewpoint: &Vector4<T>) -> Vector4<T> {
+        let mut normal = self.eigenvectors.column(0).into_owned();
+        if normal.dot(&viewpoint.xyz()) < T::zero() {
+            normal.neg_mut();
+        }
+        normal.insert_row(3, T::zero())
+    }
+
+    /// The surface variation, i.e. the smallest eigenvalue divided by the sum
+    /// of all of them, as the curvature of [`pcc_common::normal`].
+    pub fn curvature(&self) -> T {
+        self.eigenvalues[0].clone() / self.eigenvalues.sum()
+    }
+}
+
+/// The principal curvatures of a point, estimated from the variation of the
+/// normals of its neighbors projected onto its tangent plane.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrincipalCurvatures<T: Scalar> {
+    /// The direction of the maximum curvature.
+    pub direction: Vector3<T>,
+    pub max: T,
+    pub min: T,
+}
+
+/// The cached surfaces of the neighborhoods of all the points of a point
+/// cloud, which serve the normals, the curvatures, the boundaries and the ISS
+/// keypoints without searching and decomposing the same neighborhoods again
+/// for every one of them.
+#[derive(Debug, Clone)]
+pub struct LocalSurface<'a, P: Point> {
+    input: &'a PointCloud<P>,
+    neighbors: Vec<Vec<usize>>,
+    data: Vec<Option<SurfaceData<P::Data>>>,
+}
+
+impl<'a, T: RealField, P: Point<Data = T>> LocalSurface<'a, P> {
+    /// Computes the surfaces of all the points of the input of `search` from
+    /// their neighbors found by `search_param`.
+    pub fn new<S, Sp>(search: S, search_param: Sp) -> Self
+    where
+        S: Search<'a, P>,
+        Sp: Into<SearchType<P::Data>>,
+    {
+        let input = search.input();
+        let search_param = search_param.into();
+
+        let mut result = Vec::new();
+        let (neighbors, data) = { input.iter() }
+            .map(|point| {
+                if !point.is_finite() {
+                    return (Vec::new(), None);
+                }
+                search.search(point.coords(), search_param.clone(), &mut result);
+                let neighbors = result.iter().map(|&(index, _)| index).collect::<Vec<_>>();
+                let coords = { neighbors.iter() }
+                    .map(|&index| input[index].coords())
+                    .collect::<Vec<_>>();
+                let data = SurfaceData::new(&coords);
+                (neighbors, data)
+            })
+            .unzip();
+
+        LocalSurface {
+            input,
+            neighbors,
+            data,
+        }
+    }
+
+    #[inline]
+    pub fn input(&self) -> &'a PointCloud<P> {
+        self.input
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The surface of the point at `index`, or `None` if its neighborhood is
+    /// degenerate.
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<&SurfaceData<P::Data>> {
+        self.data.get(index)?.as_ref()
+    }
+
+    /// The indices of the neighbors of the point at `index`.
+    #[inline]
+    pub fn neighbors(&self, index: usize) -> &[usize] {
+        &self.neighbors[index]
+    }
+
+    /// The normals and the curvatures of all the points, like
+    /// [`Normal`](crate::Normal) with the same search.
+    pub fn normals<O>(
+        &self,
+        viewpoint: &Vector4<P::Data>,
+        policy: OutputPolicy,
+    ) -> Result<PolicyOutput<PointCloud<O>>, DegenerateError>
+    where
+        O: Normal<Data = P::Data>,
+    {
+        let values = { self.input.iter().zip(&self.data) }
+            .map(|(point, data)| {
+                if !self.input.is_bounded() && !point.is_finite() {
+                    return Some(Default::default());
+                }
+                let data = data.as_ref()?;
+                Some(
+                    O::default()
+                        .with_normal(data.normal(viewpoint))
+                        .with_curvature(data.curvature()),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        resolve(values, self.input.width(), policy)
+    }
+
+    /// The principal curvatures of the point at `index`, or `None` if the
+    /// surface of it or any of its neighbors is degenerate.
+    pub fn principal_curvatures(&self, index: usize) -> Option<PrincipalCurvatures<P::Data>> {
+        let normal = self.get(index)?.eigenvectors.column(0).into_owned();
+        let projection = Matrix3::identity() - &normal * normal.transpose();
+
+        let projected = { self.neighbors(index).iter() }
+            .map(|&other| {
+                let mut other = self.get(other)?.eigenvectors.column(0).into_owned();
+                // Orients the normals of the neighbors consistently.
+                if other.dot(&normal) < T::zero() {
+                    other.neg_mut();
+                }
+                Some((&projection * other).insert_row(3, T::one()))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let se = pcc_common::cov_matrix(projected.iter())?.symmetric_eigen();
+        let max = se.eigenvalues.imax();
+        let min = { (0..3).filter(|&i| i != max) }
+            .max_by(|&a, &b| {
+                { se.eigenvalues[a].partial_cmp(&se.eigenvalues[b]) }.unwrap_or(Ordering::Equal)
+            })
+            .unwrap();
+        Some(PrincipalCurvatures {
+            direction: se.eigenvectors.column(max).into_owned(),
+            max: se.eigenvalues[max].clone(),
+            min: se.eigenvalues[min].clone(),
+        })
+    }
+
+    /// The boundary points like [`Boundary`], with the tangent planes of the
+    /// cached surfaces instead of the given normals.
+    pub fn boundary(&self, boundary: &Boundary<P::Data>) -> PointCloud<bool> {
+        let mut bounded = true;
+        let storage = { self.data.iter().enumerate() }
+            .map(|(index, data)| match data {
+                Some(data) if !self.neighbors[index].is_empty() => {
+                    let u = data.eigenvectors.column(2).into_owned();
+                    let v = data.eigenvectors.column(0).cross(&u);
+                    boundary.boundary(
+                        self.input[index].coords(),
+                        { self.neighbors[index].iter() }.map(|&other| self.input[other].coords()),
+                        &[u, v],
+                    )
+                }
+                _ => {
+                    bounded = false;
+                    false
+                }
+            })
+            .collect::<Vec<_>>();
+        unsafe { PointCloud::from_raw_parts(storage, self.input.width(), bounded) }
+    }
+
+    /// The intrinsic shape signature keypoints, i.e. the points whose
+    /// eigenvalues `l0 <= l1 <= l2` satisfy `l1 / l2 < gamma_21` and
+    /// `l0 / l1 < gamma_32` with at least `min_neighbors` neighbors, and whose
+    /// smallest eigenvalues are the maximum among their neighbors.
+    pub fn iss(&self, gamma_21: P::Data, gamma_32: P::Data, min_neighbors: usize) -> Vec<usize> {
+        let responses = { self.data.iter().zip(&self.neighbors) }
+            .map(|(data, neighbors)| match data {
+                Some(data) if neighbors.len() >= min_neighbors => {
+                    let [l0, l1, l2] = [0, 1, 2].map(|i| data.eigenvalues[i].clone());
+                    if l1.clone() < l2 * gamma_21.clone() && l0.clone() < l1 * gamma_32.clone() {
+                        l0
+                    } else {
+                        T::zero()
+                    }
+                }
+                _ => T::zero(),
+            })
+            .collect::<Vec<_>>();
+
+        non_max_suppression(&responses, |index, visit| {
+            self.neighbors[index].iter().for_each(|&other| visit(other))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pcc_common::point::{Point3, Point3N};
+    use pcc_search::KdTree;
+
+    use super::*;
+
+    #[test]
+    fn test_local_surface() {
+        let storage = { (0..10).flat_map(|x| (0..10).map(move |y| (x, y))) }
+            .map(|(x, y)| Point3::default().with_coords(Vector4::new(x as f32, y as f32, 0., 1.)))
+            .collect::<Vec<_>>();
+        let input = PointCloud::from_vec(storage, 10);
+        let surface = LocalSurface::new(KdTree::new(&input), SearchType::Radius(1.5));
+
+        let viewpoint = Vector4::new(0., 0., 10., 1.);
+        let normals = surface
+            .normals::<Point3N>(&viewpoint, OutputPolicy::Error)
+            .unwrap()
+            .output;
+        assert!(
+            { normals.iter() }.all(|n| (n.normal() - Vector4::new(0., 0., 1., 0.)).norm() < 1e-4)
+        );
+
+        let curvatures = surface.principal_curvatures(55).unwrap();
+        assert!(curvatures.max.abs() < 1e-6);
+
+        let boundary = surface.boundary(&Boundary::new(std::f32::consts::FRAC_PI_2 + 0.1));
+        assert!(boundary[0] && boundary[5] && !boundary[55]);
+    }
+}
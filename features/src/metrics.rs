@@ -0,0 +1,348 @@
+//! Distances between descriptors, e.g. the histograms of [`Fpfh`](crate::Fpfh)
+//! and [`Pfh`](crate::Pfh), and the matchers of descriptors by them.
+
+use std::borrow::Cow;
+
+use nalgebra::{DVector, RealField, Scalar};
+use pcc_search::{KnnResultSet, ResultSet};
+
+/// The euclidean distance, which is the one used by [`DescriptorTree`].
+pub fn euclidean<T: RealField>(a: &DVector<T>, b: &DVector<T>) -> T {
+    (a - b).norm()
+}
+
+/// The chi-square distance `sum((a - b)^2 / (a + b))`, skipping the bins
+/// empty in both histograms.
+pub fn chi_square<T: RealField>(a: &DVector<T>, b: &DVector<T>) -> T {
+    a.iter().zip(b.iter()).fold(T::zero(), |acc, (a, b)| {
+        let sum = a.clone() + b.clone();
+        if sum == T::zero() {
+            acc
+        } else {
+            let diff = a.clone() - b.clone();
+            acc + diff.clone() * diff / sum
+        }
+    })
+}
+
+/// The histogram intersection distance, i.e. one minus the intersection
+/// `sum(min(a, b))` normalized by the smaller total, which is zero for the
+/// same histograms and one for disjoint ones.
+pub fn histogram_intersection<T: RealField>(a: &DVector<T>, b: &DVector<T>) -> T {
+    let intersection =
+        { a.iter().zip(b.iter()) }.fold(T::zero(), |acc, (a, b)| acc + a.clone().min(b.clone()));
+    let total = a.sum().min(b.sum());
+    if total <= T::zero() {
+        return if a == b { T::zero() } else { T::one() };
+    }
+    T::one() - intersection / total
+}
+
+/// The Kullback-Leibler divergence of `b` from `a`, after normalizing both to
+/// the unit mass, which is not symmetric. It's infinite if `b` is empty in
+/// any bin where `a` is not.
+pub fn kl_divergence<T: RealField>(a: &DVector<T>, b: &DVector<T>) -> T {
+    let (sum_a, sum_b) = (a.sum(), b.sum());
+    if sum_a <= T::zero() || sum_b <= T::zero() {
+        return T::zero();
+    }
+    a.iter().zip(b.iter()).fold(T::zero(), |acc, (a, b)| {
+        let p = a.clone() / sum_a.clone();
+        if p <= T::zero() {
+            return acc;
+        }
+        let q = b.clone() / sum_b.clone();
+        if q <= T::zero() {
+            return acc + T::one() / T::zero();
+        }
+        acc + p.clone() * (p / q).ln()
+    })
+}
+
+/// The earth mover's distance between 1D histograms after normalizing both
+/// to the unit mass, i.e. the sum of the differences of their cumulative
+/// distributions.
+pub fn emd_1d<T: RealField>(a: &DVector<T>, b: &DVector<T>) -> T {
+    let (sum_a, sum_b) = (a.sum(), b.sum());
+    if sum_a <= T::zero() || sum_b <= T::zero() {
+        return T::zero();
+    }
+    let (mut cdf_a, mut cdf_b) = (T::zero(), T::zero());
+    a.iter().zip(b.iter()).fold(T::zero(), |acc, (a, b)| {
+        cdf_a += a.clone() / sum_a.clone();
+        cdf_b += b.clone() / sum_b.clone();
+        acc + (cdf_a.clone() - cdf_b.clone()).abs()
+    })
+}
+
+/// The nearest target descriptors of a source descriptor, sorted by their
+/// distances.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescriptorMatch<T> {
+    pub source: usize,
+    pub targets: Vec<(usize, T)>,
+}
+
+/// Keeps the `k` nearest of `targets` sorted by the distances, unless
+/// rejected by the ratio test of Lowe, i.e. the nearest distance must be less
+/// than `ratio` times the second nearest one.
+fn select<T: RealField>(
+    source: usize,
+    mut targets: Vec<(usize, T)>,
+    k: usize,
+    ratio: Option<&T>,
+) -> Option<DescriptorMatch<T>> {
+    if let (Some(ratio), [(_, first), (_, second), ..]) = (ratio, &targets[..]) {
+        if *first >= ratio.clone() * second.clone() {
+            return None;
+        }
+    }
+    targets.truncate(k);
+    (!targets.is_empty()).then_some(DescriptorMatch { source, targets })
+}
+
+/// The number of the nearest targets to search, which includes the second
+/// nearest one for the ratio test.
+fn num_search<T>(k: usize, ratio: Option<&T>) -> usize {
+    if ratio.is_some() {
+        k.max(2)
+    } else {
+        k
+    }
+}
+
+/// Matches every source descriptor to its `k` nearest target descriptors by
+/// `distance`, comparing all the pairs. If `ratio` is given, the matches
+/// failing the ratio test are dropped.
+///
+/// Empty descriptors and the ones of different lengths are never matched.
+pub fn match_brute_force<T, D>(
+    source: &[DVector<T>],
+    target: &[DVector<T>],
+    k: usize,
+    ratio: Option<T>,
+    distance: D,
+) -> Vec<DescriptorMatch<T>>
+where
+    T: RealField,
+    D: Fn(&DVector<T>, &DVector<T>) -> T,
+{
+    let num = num_search(k, ratio.as_ref());
+    let mut result = KnnResultSet::new(num);
+    { source.iter().enumerate() }
+        .filter(|(_, descriptor)| !descriptor.is_empty())
+        .filter_map(|(index, descriptor)| {
+            { target.iter().enumerate() }
+                .filter(|(_, other)| other.len() == descriptor.len())
+                .for_each(|(other_index, other)| {
+                    result.push(distance(descriptor, other), other_index)
+                });
+            let mut targets = Vec::new();
+            result.drain_sorted_into(&mut targets);
+            select(index, targets, k, ratio.as_ref())
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+enum Node<T> {
+    Leaf(Vec<usize>),
+    Split {
+        dim: usize,
+        value: T,
+        children: [usize; 2],
+    },
+}
+
+const LEAF_SIZE: usize = 8;
+
+fn build<T: RealField>(
+    descriptors: &[DVector<T>],
+    dims: usize,
+    nodes: &mut Vec<Node<T>>,
+    indices: &mut [usize],
+) -> usize {
+    if indices.len() <= LEAF_SIZE {
+        nodes.push(Node::Leaf(indices.to_vec()));
+        return nodes.len() - 1;
+    }
+
+    // Splits the dimension with the largest spread at the median.
+    let spread = |dim: usize| {
+        let (min, max) = { indices.iter() }
+            .map(|&index| descriptors[index][dim].clone())
+            .fold((None::<T>, None::<T>), |(min, max), x| {
+                (
+                    Some(min.map_or(x.clone(), |min| min.min(x.clone()))),
+                    Some(max.map_or(x.clone(), |max| max.max(x))),
+                )
+            });
+        max.unwrap() - min.unwrap()
+    };
+    let dim = { (0..dims).map(|dim| (dim, spread(dim))) }
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+        .map(|(dim, _)| dim)
+        .unwrap();
+
+    let mid = indices.len() / 2;
+    indices.select_nth_unstable_by(mid, |&a, &b| {
+        { descriptors[a][dim].partial_cmp(&descriptors[b][dim]) }.unwrap()
+    });
+    let value = descriptors[indices[mid]][dim].clone();
+
+    let (left, right) = indices.split_at_mut(mid);
+    let left = build(descriptors, dims, nodes, left);
+    let right = build(descriptors, dims, nodes, right);
+    nodes.push(Node::Split {
+        dim,
+        value,
+        children: [left, right],
+    });
+    nodes.len() - 1
+}
+
+/// A kd-tree over descriptors of any length by the euclidean distance, for
+/// matching descriptors faster than [`match_brute_force`].
+///
+/// Only the finite descriptors of the same length as the first non-empty one
+/// are indexed.
+#[derive(Debug, Clone)]
+pub struct DescriptorTree<'a, T: Scalar> {
+    descriptors: Cow<'a, [DVector<T>]>,
+    dims: usize,
+    nodes: Vec<Node<T>>,
+}
+
+impl<'a, T: RealField> DescriptorTree<'a, T> {
+    pub fn new(descriptors: &'a [DVector<T>]) -> Self {
+        Self::with_descriptors(Cow::Borrowed(descriptors))
+    }
+
+    /// Like [`DescriptorTree::new`], but owns the descriptors, e.g. to be
+    /// kept along with them.
+    pub fn from_vec(descriptors: Vec<DVector<T>>) -> DescriptorTree<'static, T> {
+        DescriptorTree::with_descriptors(Cow::Owned(descriptors))
+    }
+
+    fn with_descriptors(descriptors: Cow<'a, [DVector<T>]>) -> Self {
+        let dims = { descriptors.iter() }
+            .find(|descriptor| !descriptor.is_empty())
+            .map_or(0, |descriptor| descriptor.len());
+        let mut nodes = Vec::new();
+        if dims > 0 {
+            let mut indices = { descriptors.iter().enumerate() }
+                .filter(|(_, descriptor)| descriptor.len() == dims)
+                .filter(|(_, descriptor)| descriptor.iter().all(|x| x.is_finite()))
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>();
+            build(&descriptors, dims, &mut nodes, &mut indices);
+        }
+        DescriptorTree {
+            descriptors,
+            dims,
+            nodes,
+        }
+    }
+
+    /// All the descriptors, including the ones not indexed.
+    #[inline]
+    pub fn descriptors(&self) -> &[DVector<T>] {
+        &self.descriptors
+    }
+
+    fn search(&self, node: usize, pivot: &DVector<T>, result: &mut KnnResultSet<T, usize>) {
+        match &self.nodes[node] {
+            Node::Leaf(indices) => {
+                for &index in indices {
+                    result.push(euclidean(pivot, &self.descriptors[index]), index);
+                }
+            }
+            Node::Split {
+                dim,
+                value,
+                children: [left, right],
+            } => {
+                let diff = pivot[*dim].clone() - value.clone();
+                let (near, far) = if diff < T::zero() {
+                    (*left, *right)
+                } else {
+                    (*right, *left)
+                };
+                self.search(near, pivot, result);
+                let diff = diff.abs();
+                if !result.is_full() || result.max_key().map_or(true, |max| *max > diff) {
+                    self.search(far, pivot, result);
+                }
+            }
+        }
+    }
+
+    /// The `k` nearest descriptors to `pivot` and their distances, sorted by
+    /// the distances.
+    pub fn knn(&self, pivot: &DVector<T>, k: usize, result: &mut Vec<(usize, T)>) {
+        result.clear();
+        let root = match self.nodes.len().checked_sub(1) {
+            Some(root) => root,
+            None => return,
+        };
+        if pivot.len() != self.dims {
+            return;
+        }
+        let mut set = KnnResultSet::new(k);
+        self.search(root, pivot, &mut set);
+        set.drain_sorted_into(result);
+    }
+
+    /// Like [`match_brute_force`] by the euclidean distance, with the
+    /// descriptors of this tree as the targets.
+    pub fn match_descriptors(
+        &self,
+        source: &[DVector<T>],
+        k: usize,
+        ratio: Option<T>,
+    ) -> Vec<DescriptorMatch<T>> {
+        let num = num_search(k, ratio.as_ref());
+        { source.iter().enumerate() }
+            .filter(|(_, descriptor)| !descriptor.is_empty())
+            .filter_map(|(index, descriptor)| {
+                let mut targets = Vec::new();
+                self.knn(descriptor, num, &mut targets);
+                select(index, targets, k, ratio.as_ref())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        let a = DVector::from_vec(vec![1f32, 0., 1., 2.]);
+        let b = DVector::from_vec(vec![0., 1., 1., 2.]);
+        assert!((chi_square(&a, &b) - 2.).abs() < 1e-6);
+        assert!((histogram_intersection(&a, &b) - 0.25).abs() < 1e-6);
+        assert!((emd_1d(&a, &b) - 0.25).abs() < 1e-6);
+        assert!(kl_divergence(&a, &a).abs() < 1e-6);
+        assert!(kl_divergence(&a, &b).is_infinite());
+    }
+
+    #[test]
+    fn test_matchers() {
+        let descriptors = { (0..100).map(|i| i as f64) }
+            .map(|i| DVector::from_fn(5, |d, _| (i * (d + 1) as f64 * 0.37).sin()))
+            .collect::<Vec<_>>();
+        let queries = { descriptors.iter() }
+            .map(|d| d.map(|x| x + 0.01))
+            .collect::<Vec<_>>();
+
+        let brute = match_brute_force(&queries, &descriptors, 3, None, euclidean);
+        let tree = DescriptorTree::new(&descriptors).match_descriptors(&queries, 3, None);
+        assert_eq!(brute.len(), 100);
+        assert_eq!(brute, tree);
+
+        let ratio = DescriptorTree::new(&descriptors).match_descriptors(&queries, 1, Some(0.8));
+        assert!(ratio.iter().all(|m| m.targets.len() == 1));
+    }
+}
@@ -1,6 +1,6 @@
 use nalgebra::{convert, RealField, Vector3, Vector4};
 use pcc_common::{
-    feature::Feature,
+    feature::{DegenerateError, Feature, OutputPolicy, PolicyOutput},
     point::Point,
     point_cloud::{AsPointCloud, PointCloud},
     search::{Search, SearchType},
@@ -33,48 +33,88 @@ impl MomentInvariant {
     }
 }
 
-impl<'a, T, P, S> Feature<&'a PointCloud<P>, Option<PointCloud<Vector3<T>>>, S, SearchType<T>>
+impl MomentInvariant {
+    fn compute_with<'a, T, P, S>(
+        &self,
+        input: &'a PointCloud<P>,
+        search: S,
+        search_param: SearchType<T>,
+        policy: OutputPolicy,
+    ) -> Option<Result<PolicyOutput<PointCloud<Vector3<T>>>, DegenerateError>>
+    where
+        T: RealField,
+        P: Point<Data = T>,
+        S: Search<'a, P>,
+    {
+        let centroid = input.centroid_coords().0?;
+
+        let mut result = Vec::new();
+        let values = { input.iter() }
+            .map(|point| {
+                if !input.is_bounded() && !point.is_finite() {
+                    return Some(Vector3::zeros());
+                }
+                search.search(point.coords(), search_param.clone(), &mut result);
+                (!result.is_empty()).then(|| Self::point_mi(&result, input, &centroid))
+            })
+            .collect::<Vec<_>>();
+
+        let output = policy.resolve(values, Vector3::zeros, || {
+            Vector3::repeat(convert(f64::NAN))
+        });
+        Some(output.map(
+            |PolicyOutput {
+                 output,
+                 num_degenerate,
+             }| {
+                let bounded = input.is_bounded() && num_degenerate == 0;
+                PolicyOutput {
+                    output: unsafe { PointCloud::from_raw_parts(output, input.width(), bounded) },
+                    num_degenerate,
+                }
+            },
+        ))
+    }
+}
+
+impl<'a, T, P, S, Sp> Feature<&'a PointCloud<P>, Option<PointCloud<Vector3<T>>>, S, Sp>
     for MomentInvariant
 where
     T: RealField,
     P: Point<Data = T>,
     S: Search<'a, P>,
+    Sp: Into<SearchType<T>>,
 {
     fn compute(
         &self,
         input: &'a PointCloud<P>,
         search: S,
-        search_param: SearchType<T>,
+        search_param: Sp,
     ) -> Option<PointCloud<Vector3<T>>> {
-        let centroid = input.centroid_coords().0?;
+        let output = self.compute_with(input, search, search_param.into(), OutputPolicy::Skip)?;
+        Some(output.unwrap().output)
+    }
+}
 
-        let mut result = Vec::new();
-        let mut bounded = input.is_bounded();
-        let storage = if bounded {
-            let iter = input.iter().map(|point| {
-                search.search(point.coords(), search_param.clone(), &mut result);
-                if result.is_empty() {
-                    bounded = false;
-                    Vector3::zeros()
-                } else {
-                    Self::point_mi(&result, input, &centroid)
-                }
-            });
-            iter.collect::<Vec<_>>()
-        } else {
-            let iter = input.iter().map(|point| {
-                if !point.is_finite() {
-                    return Vector3::zeros();
-                }
-                search.search(point.coords(), search_param.clone(), &mut result);
-                if result.is_empty() {
-                    Vector3::zeros()
-                } else {
-                    Self::point_mi(&result, input, &centroid)
-                }
-            });
-            iter.collect::<Vec<_>>()
-        };
-        Some(unsafe { PointCloud::from_raw_parts(storage, input.width(), bounded) })
+impl<'a, T, P, S, Sp>
+    Feature<
+        &'a PointCloud<P>,
+        Option<Result<PolicyOutput<PointCloud<Vector3<T>>>, DegenerateError>>,
+        S,
+        (Sp, OutputPolicy),
+    > for MomentInvariant
+where
+    T: RealField,
+    P: Point<Data = T>,
+    S: Search<'a, P>,
+    Sp: Into<SearchType<T>>,
+{
+    fn compute(
+        &self,
+        input: &'a PointCloud<P>,
+        search: S,
+        (search_param, policy): (Sp, OutputPolicy),
+    ) -> Option<Result<PolicyOutput<PointCloud<Vector3<T>>>, DegenerateError>> {
+        self.compute_with(input, search, search_param.into(), policy)
     }
 }
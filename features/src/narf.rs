@@ -1,8 +1,8 @@
-use std::{array, mem};
+use std::{array, io, mem};
 
 use nalgebra::{convert, Affine3, RealField, Rotation3, Translation3, Vector2, Vector3, Vector4};
 use num::{Float, ToPrimitive};
-use pcc_common::{feature::Feature, point::PointRange, range_image::RangeImage};
+use pcc_common::{camera::Image, feature::Feature, point::PointRange, range_image::RangeImage};
 use rayon::prelude::*;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
@@ -97,6 +97,39 @@ impl<T: RealField + Float> SurfacePatch<T> {
     }
 }
 
+impl<T: RealField + Float + ToPrimitive> SurfacePatch<T> {
+    /// Rasterizes the patch to an 8-bit grayscale image of `pixel_size` by
+    /// `pixel_size` in row-major order, mapping the heights from
+    /// `-world_size / 2` to `world_size / 2` to black through white.
+    ///
+    /// Positive infinities are white, and negative infinities and NaNs are
+    /// black.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pixel_size` is 0.
+    pub fn to_gray8(&self) -> Image<u8> {
+        let half = self.world_size / convert(2.);
+        let data = { self.data.iter() }
+            .map(|&value| {
+                if Float::is_nan(value) {
+                    return 0;
+                }
+                let ratio = (value + half) / self.world_size;
+                let ratio = Float::min(Float::max(ratio, T::zero()), T::one());
+                Float::round(ratio * convert(255.)).to_u8().unwrap_or(0)
+            })
+            .collect();
+        Image::new(data, self.pixel_size)
+    }
+
+    /// Writes the image of [`SurfacePatch::to_gray8`] as a binary PGM file.
+    pub fn write_pgm<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "P5\n{} {}\n255\n", self.pixel_size, self.pixel_size)?;
+        writer.write_all(&self.to_gray8())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
 pub struct NarfData<T: RealField> {
     pub position: Vector4<T>,
@@ -112,13 +145,16 @@ impl<T: RealField + Float + ToPrimitive> NarfData<T> {
         desc_size: usize,
         pixel_size: usize,
         world_size: T,
+        blur_radius: Option<usize>,
     ) -> Self
     where
         P: PointRange<Data = T>,
     {
         let position = pose.inverse().matrix().column(3).into_owned();
         let mut surface_patch = SurfacePatch::new(range_image, &pose, pixel_size, world_size);
-        surface_patch.blur(1);
+        if let Some(radius) = blur_radius {
+            surface_patch.blur(radius);
+        }
         let descriptor = Self::extract(desc_size, &surface_patch, &mut Vec::new());
 
         NarfData {
@@ -135,11 +171,19 @@ impl<T: RealField + Float + ToPrimitive> NarfData<T> {
         desc_size: usize,
         pixel_size: usize,
         world_size: T,
+        blur_radius: Option<usize>,
     ) -> impl Iterator<Item = NarfData<T>>
     where
         P: PointRange<Data = T>,
     {
-        let orig = Self::new(range_image, pose, desc_size, pixel_size, world_size);
+        let orig = Self::new(
+            range_image,
+            pose,
+            desc_size,
+            pixel_size,
+            world_size,
+            blur_radius,
+        );
         let (rotations, _) = orig.rotations();
 
         rotations
@@ -153,11 +197,19 @@ impl<T: RealField + Float + ToPrimitive> NarfData<T> {
         desc_size: usize,
         pixel_size: usize,
         world_size: T,
+        blur_radius: Option<usize>,
     ) -> impl ParallelIterator<Item = NarfData<T>>
     where
         P: PointRange<Data = T>,
     {
-        let orig = Self::new(range_image, pose, desc_size, pixel_size, world_size);
+        let orig = Self::new(
+            range_image,
+            pose,
+            desc_size,
+            pixel_size,
+            world_size,
+            blur_radius,
+        );
         let (rotations, _) = orig.rotations();
 
         rotations
@@ -279,6 +331,30 @@ impl<T: RealField + Float + ToPrimitive> NarfData<T> {
     }
 }
 
+/// The distance between the descriptors of two NARFs, which is the mean
+/// absolute difference of their values, minimized over the cyclic shifts of
+/// `b`'s descriptor, i.e. the rotations about the normal in steps of the
+/// angle between the beams, or `None` if their sizes differ or are zero.
+///
+/// The rotation handling makes the distance meaningful even for the NARFs
+/// extracted without rotating them to their dominant orientations.
+pub fn narf_distance<T: RealField>(a: &NarfData<T>, b: &NarfData<T>) -> Option<T> {
+    let (a, b) = (&a.descriptor, &b.descriptor);
+    if a.len() != b.len() || a.is_empty() {
+        return None;
+    }
+
+    let len = a.len();
+    let shifted = |shift: usize| {
+        { a.iter().enumerate() }
+            .map(|(index, x)| (x.clone() - b[(index + shift) % len].clone()).abs())
+            .fold(T::zero(), |acc, e| acc + e)
+    };
+    let min = { (0..len).map(shifted) }
+        .reduce(|min, distance| if distance < min { distance } else { min })?;
+    Some(min / convert(len as f64))
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 struct Next2Window<I: Iterator> {
     windows: Option<I::Item>,
@@ -320,17 +396,116 @@ pub struct Narf<T: RealField> {
     pub pixel_size: usize,
     pub world_size: T,
     pub rotate: bool,
+    /// The radius of the box blur applied to the surface patches, or `None`
+    /// to skip blurring, e.g. for high-resolution patches.
+    pub blur_radius: Option<usize>,
 }
 
 impl<T: RealField> Narf<T> {
-    pub fn new(desc_size: usize, pixel_size: usize, world_size: T, rotate: bool) -> Self {
+    pub fn new(
+        desc_size: usize,
+        pixel_size: usize,
+        world_size: T,
+        rotate: bool,
+        blur_radius: Option<usize>,
+    ) -> Self {
         Narf {
             desc_size,
             pixel_size,
             world_size,
             rotate,
+            blur_radius,
         }
     }
+
+    /// The maximum radius in pixels of the local support searched by
+    /// [`Narf::auto_params`].
+    const MAX_SUPPORT_RADIUS: usize = 20;
+
+    /// Suggests the world size and the descriptor size for the point at
+    /// `keypoint` of `range_image` from its local support, i.e. the continuous
+    /// surface around it, since fixed sizes generalize poorly across sensors
+    /// and scenes of different scales.
+    ///
+    /// The support grows ring by ring in the image as long as at least half
+    /// of the pixels of the next ring continue the surface, i.e. don't jump
+    /// farther than 3 times the pixel spacing beyond the support. The world
+    /// size is the diameter of the support, and the descriptor size is about
+    /// the number of pixels around it, within `[8, 36]`.
+    ///
+    /// Returns `None` if the point is not observed or has no support.
+    pub fn auto_params<P>(range_image: &RangeImage<P>, keypoint: usize) -> Option<(T, usize)>
+    where
+        P: PointRange<Data = T>,
+    {
+        let [x, y] = range_image.index(keypoint);
+        let center = &range_image[(x, y)];
+        if !center.range().is_finite() {
+            return None;
+        }
+        let center = center.coords();
+
+        let (mut radius, mut extent) = (0, T::zero());
+        for r in 1..=Self::MAX_SUPPORT_RADIUS {
+            let ring = {
+                (-(r as isize)..=(r as isize))
+                    .flat_map(|dy| (-(r as isize)..=(r as isize)).map(move |dx| (dx, dy)))
+            }
+            .filter(|&(dx, dy)| dx.unsigned_abs().max(dy.unsigned_abs()) == r)
+            .map(|(dx, dy)| ((x as isize) + dx, (y as isize) + dy))
+            .filter(|&(x, y)| {
+                (0..(range_image.width() as isize)).contains(&x)
+                    && (0..(range_image.height() as isize)).contains(&y)
+            })
+            .map(|(x, y)| &range_image[(x as usize, y as usize)])
+            .collect::<Vec<_>>();
+            let distances = { ring.iter() }
+                .filter(|point| point.range().is_finite())
+                .map(|point| (point.coords() - center).xyz().norm())
+                .collect::<Vec<_>>();
+
+            // The spacing of the first ring is its nearest pixel.
+            let limit = if radius == 0 {
+                let nearest = { distances.iter() }.fold(None::<T>, |acc, d| match acc {
+                    Some(acc) if acc <= *d => Some(acc),
+                    _ => Some(d.clone()),
+                });
+                nearest? * convert(3.)
+            } else {
+                let spacing = extent.clone() / T::from_usize(radius).unwrap();
+                extent.clone() + spacing * convert(3.)
+            };
+            let support = { distances.into_iter() }
+                .filter(|distance| *distance <= limit)
+                .collect::<Vec<_>>();
+            if support.is_empty() || support.len() * 2 < ring.len() {
+                break;
+            }
+
+            let sum = { support.iter() }.fold(T::zero(), |acc, d| acc + d.clone());
+            extent = sum / T::from_usize(support.len()).unwrap();
+            radius = r;
+        }
+
+        if radius == 0 {
+            return None;
+        }
+        let desc_size = (std::f64::consts::TAU * radius as f64).round() as usize;
+        Some((extent * convert(2.), desc_size.clamp(8, 36)))
+    }
+
+    /// Sets the world size and the descriptor size to the ones suggested by
+    /// [`Narf::auto_params`], or keeps them if there is no suggestion.
+    pub fn with_auto_params<P>(mut self, range_image: &RangeImage<P>, keypoint: usize) -> Self
+    where
+        P: PointRange<Data = T>,
+    {
+        if let Some((world_size, desc_size)) = Self::auto_params(range_image, keypoint) {
+            self.world_size = world_size;
+            self.desc_size = desc_size;
+        }
+        self
+    }
 }
 
 impl<'a, T, P> Feature<&'a RangeImage<P>, Vec<NarfData<T>>, (), ()> for Narf<T>
@@ -358,6 +533,7 @@ where
                     self.desc_size,
                     self.pixel_size,
                     self.world_size,
+                    self.blur_radius,
                 )
             });
             narfs.collect()
@@ -369,9 +545,115 @@ where
                     self.desc_size,
                     self.pixel_size,
                     self.world_size,
+                    self.blur_radius,
                 )
             });
             narfs.collect()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use pcc_common::{
+        point::{Point, Point3, Point3Range},
+        point_cloud::PointCloud,
+        range_image::CreateOptions,
+    };
+
+    use super::*;
+
+    /// Casts the beams through the centers of the pixels of 1 degree at a
+    /// square plate of the half side `0.3` at `z = 4`, in front of a wall at
+    /// `z = 10`.
+    fn scene() -> PointCloud<Point3> {
+        let step = 1f32.to_radians();
+        let storage = { (-15..=15).flat_map(|x| (-15..=15).map(move |y| (x, y))) }
+            .map(|(x, y)| {
+                let elevation = y as f32 * step;
+                let azimuth = x as f32 * step / elevation.cos();
+                let dir = Vector4::new(
+                    azimuth.sin() * elevation.cos(),
+                    elevation.sin(),
+                    azimuth.cos() * elevation.cos(),
+                    0.,
+                );
+                let hit = dir * (4. / dir.z);
+                let depth = if hit.x.abs() < 0.3 && hit.y.abs() < 0.3 {
+                    4.
+                } else {
+                    10.
+                };
+                let coords = dir * (depth / dir.z) + Vector4::w();
+                Point3::default().with_coords(coords)
+            })
+            .collect::<Vec<_>>();
+        PointCloud::from_vec(storage, 1)
+    }
+
+    #[test]
+    fn test_to_gray8() {
+        let patch = SurfacePatch {
+            data: vec![
+                f32::NAN,
+                -1.,
+                0.,
+                1.,
+                f32::INFINITY,
+                -f32::INFINITY,
+                0.25,
+                -0.25,
+                2.,
+            ],
+            pixel_size: 3,
+            world_size: 2.,
+            rotation: 0.,
+        };
+        let image = patch.to_gray8();
+        assert_eq!((image.width(), image.height()), (3, 3));
+        assert_eq!(*image, [0, 0, 128, 255, 255, 0, 159, 96, 255]);
+        assert_eq!(image[(1, 2)], 96);
+
+        let mut pgm = Vec::new();
+        patch.write_pgm(&mut pgm).unwrap();
+        assert!(pgm.starts_with(b"P5\n3 3\n255\n"));
+        assert!(pgm.ends_with(&image));
+    }
+
+    #[test]
+    fn test_auto_params() {
+        let scene = scene();
+        let options = CreateOptions {
+            point_cloud: &scene,
+            angular_resolution: Vector2::repeat(1f32.to_radians()),
+            noise: 0.,
+            min_range: 0.,
+            border_size: 0,
+        };
+        let image = RangeImage::<Point3Range>::new(
+            &[40f32.to_radians(), 40f32.to_radians()],
+            Affine3::identity(),
+            &options,
+        );
+        let pixel = |coords: Vector4<f32>| {
+            let (pixel, _) = image.point_to_image(&coords);
+            let [[x, y]] = pixel.map(|x| x.round() as usize).data.0;
+            y * image.width() + x
+        };
+
+        // The plate spans 4 pixels around its center, which are about 0.07
+        // apart, so the support is about as wide as the plate.
+        let center = pixel(Vector4::new(0., 0., 4., 1.));
+        let (world_size, desc_size) = Narf::auto_params(&image, center).unwrap();
+        assert!((0.55..0.8).contains(&world_size), "{world_size}");
+        assert_eq!(desc_size, 25);
+
+        let narf = Narf::new(36, 10, 1., false, None).with_auto_params(&image, center);
+        assert_eq!((narf.world_size, narf.desc_size), (world_size, desc_size));
+
+        // The support of the corner of the wall grows up to the maximum.
+        let (world_size, desc_size) = Narf::auto_params(&image, 0).unwrap();
+        assert!(world_size > 4.);
+        assert_eq!(desc_size, 36);
+    }
+}
@@ -1,6 +1,8 @@
-use nalgebra::{RealField, Scalar, Vector4};
+use std::cmp::Ordering;
+
+use nalgebra::{convert, RealField, Scalar, Vector4};
 use pcc_common::{
-    feature::Feature,
+    feature::{DegenerateError, Feature, OutputPolicy, PolicyOutput},
     point::Point,
     point_cloud::PointCloud,
     search::{Search, SearchType},
@@ -17,57 +19,266 @@ impl<T: Scalar> Normal<T> {
     }
 }
 
-impl<'a, T, I, O, S> Feature<&'a PointCloud<I>, PointCloud<O>, S, SearchType<T>> for Normal<T>
+pub(crate) fn resolve<T, O>(
+    values: Vec<Option<O>>,
+    width: usize,
+    policy: OutputPolicy,
+) -> Result<PolicyOutput<PointCloud<O>>, DegenerateError>
+where
+    T: RealField,
+    O: pcc_common::point::Normal<Data = T>,
+{
+    let nan = convert::<_, T>(f64::NAN);
+    let PolicyOutput {
+        output,
+        num_degenerate,
+    } = policy.resolve(values, Default::default, || {
+        O::default()
+            .with_normal(Vector4::repeat(nan.clone()))
+            .with_curvature(nan.clone())
+    })?;
+    Ok(PolicyOutput {
+        output: PointCloud::from_vec(output, width),
+        num_degenerate,
+    })
+}
+
+impl<T: RealField> Normal<T> {
+    fn compute_with<'a, I, O, S>(
+        &self,
+        input: &'a PointCloud<I>,
+        search: S,
+        search_param: SearchType<T>,
+        policy: OutputPolicy,
+    ) -> Result<PolicyOutput<PointCloud<O>>, DegenerateError>
+    where
+        I: Point<Data = T> + 'a,
+        S: Search<'a, I>,
+        O: pcc_common::point::Normal<Data = T>,
+    {
+        let mut result = Vec::new();
+        let values = { input.iter() }
+            .map(|point| {
+                if !input.is_bounded() && !point.is_finite() {
+                    return Some(Default::default());
+                }
+                search.search(point.coords(), search_param.clone(), &mut result);
+                pcc_common::normal(
+                    result
+                        .iter()
+                        .map(|&(index, _)| search.input()[index].coords()),
+                    &self.viewpoint,
+                )
+                .map(|(normal, curvature)| {
+                    O::default().with_normal(normal).with_curvature(curvature)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        resolve(values, input.width(), policy)
+    }
+}
+
+impl<'a, T, I, O, S, Sp> Feature<&'a PointCloud<I>, PointCloud<O>, S, Sp> for Normal<T>
+where
+    T: RealField,
+    I: Point<Data = T> + 'a,
+    S: Search<'a, I>,
+    O: pcc_common::point::Normal<Data = T>,
+    Sp: Into<SearchType<T>>,
+{
+    fn compute(&self, input: &'a PointCloud<I>, search: S, search_param: Sp) -> PointCloud<O> {
+        let output = self.compute_with(input, search, search_param.into(), OutputPolicy::Skip);
+        output.unwrap().output
+    }
+}
+
+impl<'a, T, I, O, S, Sp>
+    Feature<
+        &'a PointCloud<I>,
+        Result<PolicyOutput<PointCloud<O>>, DegenerateError>,
+        S,
+        (Sp, OutputPolicy),
+    > for Normal<T>
 where
     T: RealField,
     I: Point<Data = T> + 'a,
     S: Search<'a, I>,
     O: pcc_common::point::Normal<Data = T>,
+    Sp: Into<SearchType<T>>,
 {
     fn compute(
         &self,
         input: &'a PointCloud<I>,
         search: S,
-        search_param: SearchType<T>,
-    ) -> PointCloud<O> {
+        (search_param, policy): (Sp, OutputPolicy),
+    ) -> Result<PolicyOutput<PointCloud<O>>, DegenerateError> {
+        self.compute_with(input, search, search_param.into(), policy)
+    }
+}
+
+/// Estimates normals with a neighborhood chosen per point, for point clouds
+/// with strongly varying sampling like terrestrial scans.
+///
+/// The neighborhood starts with the `min_k` nearest neighbors and doubles up
+/// to `max_k` as long as the surface stays flat, i.e. the curvature of the
+/// larger neighborhood doesn't exceed `max_curvature`, and the sampling stays
+/// even, i.e. the distance to the farthest neighbor grows no more than
+/// `max_growth` times.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AdaptiveNormal<T: Scalar> {
+    pub viewpoint: Vector4<T>,
+    pub min_k: usize,
+    pub max_k: usize,
+    pub max_curvature: T,
+    pub max_growth: T,
+}
+
+impl<T: Scalar> AdaptiveNormal<T> {
+    pub fn new(
+        viewpoint: Vector4<T>,
+        min_k: usize,
+        max_k: usize,
+        max_curvature: T,
+        max_growth: T,
+    ) -> Self {
+        AdaptiveNormal {
+            viewpoint,
+            min_k,
+            max_k,
+            max_curvature,
+            max_growth,
+        }
+    }
+}
+
+impl<T: RealField> AdaptiveNormal<T> {
+    /// Selects the neighborhood among `neighbors` sorted by their distances
+    /// and returns its normal and curvature, along with its size.
+    fn select(&self, neighbors: &[(&Vector4<T>, T)]) -> Option<(Vector4<T>, T, usize)> {
+        let coords = |k: usize| neighbors[..k].iter().map(|&(coords, _)| coords);
+
+        let mut k = self.min_k.min(neighbors.len());
+        let (mut normal, mut curvature) = pcc_common::normal(coords(k), &self.viewpoint)?;
+        loop {
+            let next = (k * 2).min(self.max_k).min(neighbors.len());
+            if next <= k {
+                break;
+            }
+
+            let growth = neighbors[k - 1].1.clone() * self.max_growth.clone();
+            if neighbors[next - 1].1 > growth {
+                break;
+            }
+            match pcc_common::normal(coords(next), &self.viewpoint) {
+                Some((n, c)) if c <= self.max_curvature => {
+                    (normal, curvature, k) = (n, c, next);
+                }
+                _ => break,
+            }
+        }
+        Some((normal, curvature, k))
+    }
+
+    fn compute_with<'a, I, O, S>(
+        &self,
+        input: &'a PointCloud<I>,
+        search: S,
+        policy: OutputPolicy,
+    ) -> Result<PolicyOutput<PointCloud<O>>, DegenerateError>
+    where
+        I: Point<Data = T> + 'a,
+        S: Search<'a, I>,
+        O: pcc_common::point::Normal<Data = T>,
+    {
         let mut result = Vec::new();
-        if input.is_bounded() {
-            let storage = { input.iter() }
-                .map(|point| {
-                    search.search(point.coords(), search_param.clone(), &mut result);
-                    let res = pcc_common::normal(
-                        result
-                            .iter()
-                            .map(|&(index, _)| search.input()[index].coords()),
-                        &self.viewpoint,
-                    )
-                    .map(|(normal, curvature)| {
-                        O::default().with_normal(normal).with_curvature(curvature)
-                    });
-                    res.unwrap_or_default()
-                })
-                .collect::<Vec<_>>();
-            PointCloud::from_vec(storage, input.width())
-        } else {
-            let storage = { input.iter() }
-                .map(|point| {
-                    if !point.is_finite() {
-                        return Default::default();
-                    }
-                    search.search(point.coords(), search_param.clone(), &mut result);
-                    let res = pcc_common::normal(
-                        result
-                            .iter()
-                            .map(|&(index, _)| search.input()[index].coords()),
-                        &self.viewpoint,
-                    )
-                    .map(|(normal, curvature)| {
-                        O::default().with_normal(normal).with_curvature(curvature)
-                    });
-                    res.unwrap_or_default()
+        let values = { input.iter() }
+            .map(|point| {
+                if !input.is_bounded() && !point.is_finite() {
+                    return Some(Default::default());
+                }
+                search.search(point.coords(), SearchType::Knn(self.max_k), &mut result);
+
+                let mut neighbors = { result.iter() }
+                    .map(|(index, distance)| (search.input()[*index].coords(), distance.clone()))
+                    .collect::<Vec<_>>();
+                neighbors.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+                self.select(&neighbors).map(|(normal, curvature, _)| {
+                    O::default().with_normal(normal).with_curvature(curvature)
                 })
-                .collect::<Vec<_>>();
-            PointCloud::from_vec(storage, input.width())
-        }
+            })
+            .collect::<Vec<_>>();
+
+        resolve(values, input.width(), policy)
+    }
+}
+
+impl<'a, T, I, O, S> Feature<&'a PointCloud<I>, PointCloud<O>, S, ()> for AdaptiveNormal<T>
+where
+    T: RealField,
+    I: Point<Data = T> + 'a,
+    S: Search<'a, I>,
+    O: pcc_common::point::Normal<Data = T>,
+{
+    fn compute(&self, input: &'a PointCloud<I>, search: S, _: ()) -> PointCloud<O> {
+        let output = self.compute_with(input, search, OutputPolicy::Skip);
+        output.unwrap().output
+    }
+}
+
+impl<'a, T, I, O, S>
+    Feature<
+        &'a PointCloud<I>,
+        Result<PolicyOutput<PointCloud<O>>, DegenerateError>,
+        S,
+        OutputPolicy,
+    > for AdaptiveNormal<T>
+where
+    T: RealField,
+    I: Point<Data = T> + 'a,
+    S: Search<'a, I>,
+    O: pcc_common::point::Normal<Data = T>,
+{
+    fn compute(
+        &self,
+        input: &'a PointCloud<I>,
+        search: S,
+        policy: OutputPolicy,
+    ) -> Result<PolicyOutput<PointCloud<O>>, DegenerateError> {
+        self.compute_with(input, search, policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+
+    use super::*;
+
+    #[test]
+    fn test_adaptive_normal() {
+        // A flat part that creases upwards at x = 0.5.
+        let coords = { (0..20).flat_map(|x| (0..20).map(move |y| (x, y))) }
+            .map(|(x, y)| {
+                let (x, y) = (x as f32 * 0.05, y as f32 * 0.05);
+                Vector4::new(x, y, (x - 0.5).max(0.), 1.)
+            })
+            .collect::<Vec<_>>();
+        let pivot = Vector4::new(0.35, 0.5, 0., 1.);
+        let mut neighbors = { coords.iter() }
+            .map(|coords| (coords, (coords - pivot).norm()))
+            .collect::<Vec<_>>();
+        neighbors.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+
+        let viewpoint = Vector4::new(0., 0., 10., 1.);
+        let estimator = AdaptiveNormal::new(viewpoint, 8, 128, 1e-4, 2.);
+        let (normal, _, k) = estimator.select(&neighbors).unwrap();
+        assert!(k > 8 && k < 128);
+        assert!((normal - Vector4::new(0., 0., 1., 0.)).norm() < 1e-4);
+
+        let fixed = neighbors[..128].iter().map(|&(coords, _)| coords);
+        let (normal, _) = pcc_common::normal(fixed, &viewpoint).unwrap();
+        assert!((normal - Vector4::new(0., 0., 1., 0.)).norm() > 1e-2);
     }
 }
@@ -3,13 +3,13 @@ use std::collections::{HashMap, VecDeque};
 use nalgebra::{convert, DVector, RealField, Unit, Vector3};
 use num::ToPrimitive;
 use pcc_common::{
-    feature::Feature,
+    feature::{DegenerateError, Feature, Keypoints, OutputPolicy, PolicyOutput},
     point::{Normal, Point},
     point_cloud::PointCloud,
     search::{Search, SearchType},
 };
 
-use crate::HIST_MAX;
+use crate::{DescriptorParams, HIST_MAX};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
 pub(crate) struct PfhPair<T> {
@@ -50,6 +50,11 @@ impl Pfh {
         }
     }
 
+    /// The parameters of the descriptors computed with `search`.
+    pub fn descriptor_params<T>(&self, search: SearchType<T>) -> DescriptorParams<T> {
+        DescriptorParams::new("pfh", search, vec![self.subdivision.pow(3)])
+    }
+
     fn pfh<T, P, N>(
         &self,
         indices: &[(usize, T)],
@@ -120,74 +125,211 @@ impl Pfh {
     }
 }
 
-impl<'a, 'b, T, I, S, N>
-    Feature<(&'a PointCloud<I>, &'b PointCloud<N>), PointCloud<DVector<T>>, S, SearchType<T>>
-    for Pfh
+impl Pfh {
+    fn compute_with<'a, T, K, I, S, N>(
+        &self,
+        (input, normals): (&PointCloud<K>, &PointCloud<N>),
+        search: S,
+        search_param: SearchType<T>,
+        policy: OutputPolicy,
+    ) -> Result<PolicyOutput<PointCloud<DVector<T>>>, DegenerateError>
+    where
+        T: RealField + ToPrimitive,
+        K: Point<Data = T>,
+        I: Point<Data = T> + 'a,
+        S: Search<'a, I>,
+        N: Normal<Data = T>,
+    {
+        let mut result = Vec::new();
+
+        let mut cache = HashMap::new();
+        let mut cached_keys = VecDeque::new();
+
+        let values = { input.iter() }
+            .map(|point| {
+                if !input.is_bounded() && !point.is_finite() {
+                    return Some(DVector::from(Vec::new()));
+                }
+                search.search(point.coords(), search_param.clone(), &mut result);
+                if result.is_empty() {
+                    return None;
+                }
+                Some(self.pfh(
+                    &result,
+                    search.input(),
+                    normals,
+                    &mut cache,
+                    &mut cached_keys,
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        let len = self.subdivision * self.subdivision * self.subdivision;
+        let PolicyOutput {
+            output,
+            num_degenerate,
+        } = policy.resolve(
+            values,
+            || DVector::from(Vec::new()),
+            || DVector::from_element(len, convert(f64::NAN)),
+        )?;
+        let bounded = input.is_bounded() && num_degenerate == 0;
+        Ok(PolicyOutput {
+            output: unsafe { PointCloud::from_raw_parts(output, input.width(), bounded) },
+            num_degenerate,
+        })
+    }
+}
+
+impl<'a, 'b, T, I, S, N, Sp>
+    Feature<(&'a PointCloud<I>, &'b PointCloud<N>), PointCloud<DVector<T>>, S, Sp> for Pfh
 where
     T: RealField + ToPrimitive,
     I: Point<Data = T> + 'a,
     S: Search<'a, I>,
     N: Normal<Data = T> + 'b,
+    Sp: Into<SearchType<T>>,
 {
     fn compute(
         &self,
-        (input, normals): (&'a PointCloud<I>, &'b PointCloud<N>),
+        input: (&'a PointCloud<I>, &'b PointCloud<N>),
         search: S,
-        search_param: SearchType<T>,
+        search_param: Sp,
     ) -> PointCloud<DVector<T>> {
-        let mut result = Vec::new();
-        let mut bounded = true;
+        let output = self.compute_with(input, search, search_param.into(), OutputPolicy::Skip);
+        output.unwrap().output
+    }
+}
 
-        let mut cache = HashMap::new();
-        let mut cached_keys = VecDeque::new();
+impl<'a, 'b, T, I, S, N, Sp>
+    Feature<
+        (&'a PointCloud<I>, &'b PointCloud<N>),
+        Result<PolicyOutput<PointCloud<DVector<T>>>, DegenerateError>,
+        S,
+        (Sp, OutputPolicy),
+    > for Pfh
+where
+    T: RealField + ToPrimitive,
+    I: Point<Data = T> + 'a,
+    S: Search<'a, I>,
+    N: Normal<Data = T> + 'b,
+    Sp: Into<SearchType<T>>,
+{
+    fn compute(
+        &self,
+        input: (&'a PointCloud<I>, &'b PointCloud<N>),
+        search: S,
+        (search_param, policy): (Sp, OutputPolicy),
+    ) -> Result<PolicyOutput<PointCloud<DVector<T>>>, DegenerateError> {
+        self.compute_with(input, search, search_param.into(), policy)
+    }
+}
 
-        let storage = if input.is_bounded() {
-            input
-                .iter()
-                .map(|point| {
-                    search.search(point.coords(), search_param.clone(), &mut result);
-                    if result.is_empty() {
-                        bounded = false;
-                        return DVector::from(Vec::new());
-                    }
-                    self.pfh(
-                        &result,
-                        search.input(),
-                        normals,
-                        &mut cache,
-                        &mut cached_keys,
-                    )
-                })
-                .collect::<Vec<_>>()
-        } else {
-            input
-                .iter()
-                .map(|point| {
-                    if !point.is_finite() {
-                        bounded = false;
-                        return DVector::from(Vec::new());
-                    }
-                    search.search(point.coords(), search_param.clone(), &mut result);
-                    if result.is_empty() {
-                        bounded = false;
-                        return DVector::from(Vec::new());
-                    }
-                    self.pfh(
-                        &result,
-                        search.input(),
-                        normals,
-                        &mut cache,
-                        &mut cached_keys,
-                    )
-                })
-                .collect::<Vec<_>>()
-        };
-        unsafe { PointCloud::from_raw_parts(storage, input.width(), bounded) }
+impl<'a, T, K, I, S, N, Sp> Feature<Keypoints<'a, K, I, N>, PointCloud<DVector<T>>, S, Sp> for Pfh
+where
+    T: RealField + ToPrimitive,
+    K: Point<Data = T>,
+    I: Point<Data = T> + 'a,
+    S: Search<'a, I>,
+    N: Normal<Data = T>,
+    Sp: Into<SearchType<T>>,
+{
+    fn compute(
+        &self,
+        input: Keypoints<'a, K, I, N>,
+        search: S,
+        search_param: Sp,
+    ) -> PointCloud<DVector<T>> {
+        let input = input.split(search.input());
+        let output = self.compute_with(input, search, search_param.into(), OutputPolicy::Skip);
+        output.unwrap().output
+    }
+}
+
+impl<'a, T, K, I, S, N, Sp>
+    Feature<
+        Keypoints<'a, K, I, N>,
+        Result<PolicyOutput<PointCloud<DVector<T>>>, DegenerateError>,
+        S,
+        (Sp, OutputPolicy),
+    > for Pfh
+where
+    T: RealField + ToPrimitive,
+    K: Point<Data = T>,
+    I: Point<Data = T> + 'a,
+    S: Search<'a, I>,
+    N: Normal<Data = T>,
+    Sp: Into<SearchType<T>>,
+{
+    fn compute(
+        &self,
+        input: Keypoints<'a, K, I, N>,
+        search: S,
+        (search_param, policy): (Sp, OutputPolicy),
+    ) -> Result<PolicyOutput<PointCloud<DVector<T>>>, DegenerateError> {
+        let input = input.split(search.input());
+        self.compute_with(input, search, search_param.into(), policy)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::point::{Point3, Point3N};
+    use pcc_search::KdTree;
+
+    use super::*;
+
+    fn surface() -> (PointCloud<Point3>, PointCloud<Point3N>) {
+        let (surface, normals): (Vec<_>, Vec<_>) =
+            { (0..8).flat_map(|x| (0..8).map(move |y| (x, y))) }
+                .map(|(x, y)| {
+                    let coords = Vector4::new(x as f32, y as f32, ((x * y) % 3) as f32 * 0.1, 1.);
+                    let normal = Vector4::new(0., (x % 2) as f32 * 0.1, 1., 0.).normalize();
+                    (
+                        Point3::default().with_coords(coords),
+                        Point3N::default().with_coords(coords).with_normal(normal),
+                    )
+                })
+                .unzip();
+        (
+            PointCloud::from_vec(surface, 8),
+            PointCloud::from_vec(normals, 8),
+        )
+    }
+
+    #[test]
+    fn test_keypoints() {
+        let (surface, normals) = surface();
+        let keypoints = PointCloud::from_vec(vec![surface[9].clone(), surface[42].clone()], 2);
+
+        let pfh = Pfh::new(16, 3);
+        let search = KdTree::new(&surface);
+        let all: PointCloud<_> =
+            pfh.compute((&surface, &normals), &search, SearchType::Radius(1.5));
+        let at_keypoints: PointCloud<_> = pfh.compute(
+            Keypoints::new(&keypoints, &surface, &normals),
+            &search,
+            SearchType::Radius(1.5),
+        );
+        assert_eq!(at_keypoints[0], all[9]);
+        assert_eq!(at_keypoints[1], all[42]);
+    }
+
+    #[test]
+    #[should_panic = "The surface must be the input of the search"]
+    fn test_keypoints_other_surface() {
+        let (surface, normals) = surface();
+        let other = surface.clone();
+
+        let search = KdTree::new(&surface);
+        let _: PointCloud<_> = Pfh::new(16, 3).compute(
+            Keypoints::new(&surface, &other, &normals),
+            &search,
+            SearchType::Radius(1.5),
+        );
+    }
+
     #[test]
     fn test_flat_map() {
         let options = [Some(123), None, Some(3425)];
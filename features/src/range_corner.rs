@@ -0,0 +1,235 @@
+use nalgebra::{convert, RealField};
+use pcc_common::{feature::Feature, point::PointRange, range_image::RangeImage};
+
+use crate::susan::non_max_suppression;
+
+/// The offsets of the Bresenham circle of radius 3 around a pixel, in order.
+const CIRCLE: [(isize, isize); 16] = [
+    (0, -3),
+    (1, -3),
+    (2, -2),
+    (3, -1),
+    (3, 0),
+    (3, 1),
+    (2, 2),
+    (1, 3),
+    (0, 3),
+    (-1, 3),
+    (-2, 2),
+    (-3, 1),
+    (-3, 0),
+    (-3, -1),
+    (-2, -2),
+    (-1, -3),
+];
+
+/// A keypoint detected by [`RangeCorner`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeKeypoint<T> {
+    /// The index of the pixel in the range image.
+    pub index: usize,
+    /// The level of the pyramid where it's detected, where the pixels of the
+    /// level `n` cover the squares of `2^n` pixels of the range image.
+    pub octave: usize,
+    pub score: T,
+}
+
+/// A fast keypoint detector on range images in the style of AGAST and BRISK,
+/// which runs the segment test of FAST on the range channel over a pyramid of
+/// the range image, much cheaper than the NARF keypoints.
+///
+/// A pixel is a corner if at least `arc_length` contiguous pixels of the 16
+/// on the circle of radius 3 around it are all farther or all nearer than
+/// it by more than `threshold`, in the units of the ranges. The unobserved
+/// pixels never belong to the arcs. The score of a corner is the sum of the
+/// range differences beyond `threshold` of the pixels in the arcs, and the
+/// keypoints are the local maxima of the scores within 3x3 pixels.
+///
+/// Every one of the `octaves` levels of the pyramid halves the previous one,
+/// keeping the nearest range of every 2x2 pixels so that the foreground
+/// survives. The keypoints of the coarser levels are mapped to the nearest
+/// pixels in their squares, and dropped if already detected in finer levels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeCorner<T> {
+    pub threshold: T,
+    pub arc_length: usize,
+    pub octaves: usize,
+}
+
+impl<T> RangeCorner<T> {
+    pub fn new(threshold: T, arc_length: usize, octaves: usize) -> Self {
+        RangeCorner {
+            threshold,
+            arc_length,
+            octaves,
+        }
+    }
+}
+
+/// Halves the ranges of a level of the pyramid, keeping the nearest finite
+/// range of every 2x2 pixels.
+fn downsample<T: RealField>(ranges: &[T], width: usize, height: usize) -> Vec<T> {
+    let (new_width, new_height) = (width / 2, height / 2);
+    let nan = convert::<_, T>(f64::NAN);
+    (0..new_width * new_height)
+        .map(|index| {
+            let [x, y] = [index % new_width * 2, index / new_width * 2];
+            { [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)].into_iter() }
+                .map(|(x, y)| ranges[y * width + x].clone())
+                .filter(|range| range.is_finite())
+                .reduce(|a, b| a.min(b))
+                .unwrap_or_else(|| nan.clone())
+        })
+        .collect()
+}
+
+impl<T: RealField> RangeCorner<T> {
+    fn score(&self, ranges: &[T], width: usize, x: usize, y: usize) -> T {
+        let center = ranges[y * width + x].clone();
+        if !center.is_finite() {
+            return T::zero();
+        }
+        // 1 for farther, -1 for nearer and 0 for neither.
+        let diffs = CIRCLE.map(|(dx, dy)| {
+            let x = x.wrapping_add_signed(dx);
+            let y = y.wrapping_add_signed(dy);
+            let diff = ranges[y * width + x].clone() - center.clone();
+            if diff > self.threshold {
+                (1, diff - self.threshold.clone())
+            } else if diff < -self.threshold.clone() {
+                (-1, -diff - self.threshold.clone())
+            } else {
+                (0, T::zero())
+            }
+        });
+
+        let mut score = T::zero();
+        for sign in [1, -1] {
+            // Walks the circle twice to find the arcs across the start.
+            let mut run = 0;
+            let mut longest = 0;
+            for (s, _) in diffs.iter().chain(diffs.iter()) {
+                run = if *s == sign { run + 1 } else { 0 };
+                longest = longest.max(run);
+            }
+            if longest.min(CIRCLE.len()) >= self.arc_length {
+                let sum = { diffs.iter() }
+                    .filter(|(s, _)| *s == sign)
+                    .fold(T::zero(), |acc, (_, d)| acc + d.clone());
+                score = score.max(sum);
+            }
+        }
+        score
+    }
+
+    /// Detects the corners in a level of the pyramid, and returns their
+    /// indices and scores.
+    fn detect_level(&self, ranges: &[T], width: usize, height: usize) -> Vec<(usize, T)> {
+        if width <= 6 || height <= 6 {
+            return Vec::new();
+        }
+        let scores = (0..ranges.len())
+            .map(|index| {
+                let [x, y] = [index % width, index / width];
+                if x < 3 || y < 3 || x + 3 >= width || y + 3 >= height {
+                    return T::zero();
+                }
+                self.score(ranges, width, x, y)
+            })
+            .collect::<Vec<_>>();
+
+        let maxima = non_max_suppression(&scores, |index, visitor| {
+            let [x, y] = [index % width, index / width];
+            for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    visitor(ny * width + nx)
+                }
+            }
+        });
+        { maxima.into_iter() }
+            .map(|index| (index, scores[index].clone()))
+            .collect()
+    }
+}
+
+impl<'a, T, P> Feature<&'a RangeImage<P>, Vec<RangeKeypoint<T>>, (), ()> for RangeCorner<T>
+where
+    T: RealField,
+    P: PointRange<Data = T>,
+{
+    fn compute(&self, input: &'a RangeImage<P>, _: (), _: ()) -> Vec<RangeKeypoint<T>> {
+        let (width, height) = (input.width(), input.height());
+        let nan = convert::<_, T>(f64::NAN);
+        let mut ranges = { input.iter() }
+            .map(|point| {
+                let range = point.range();
+                if range.is_finite() {
+                    range
+                } else {
+                    nan.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut detected = vec![false; input.len()];
+        let mut result = Vec::new();
+        let (mut level_width, mut level_height) = (width, height);
+        for octave in 0..self.octaves {
+            if octave > 0 {
+                ranges = downsample(&ranges, level_width, level_height);
+                (level_width, level_height) = (level_width / 2, level_height / 2);
+            }
+
+            for (level_index, score) in self.detect_level(&ranges, level_width, level_height) {
+                let [x, y] = [level_index % level_width, level_index / level_width];
+                let size = 1 << octave;
+                // The nearest pixel in the square, which is the one kept by
+                // the downsampling.
+                let index = {
+                    (y * size..(y + 1) * size)
+                        .flat_map(|y| (x * size..(x + 1) * size).map(move |x| y * width + x))
+                }
+                .filter(|&index| input[index].range().is_finite())
+                .min_by(|&a, &b| input[a].range().partial_cmp(&input[b].range()).unwrap());
+                let index = match index {
+                    Some(index) if !detected[index] => index,
+                    _ => continue,
+                };
+                detected[index] = true;
+                result.push(RangeKeypoint {
+                    index,
+                    octave,
+                    score,
+                });
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range_corner() {
+        // A near square in front of a far wall, whose corners are at 5 and 14.
+        let ranges = { (0..400).map(|index| (index % 20, index / 20)) }
+            .map(|(x, y)| {
+                if (5..15).contains(&x) && (5..15).contains(&y) {
+                    1.
+                } else {
+                    5.
+                }
+            })
+            .collect::<Vec<f32>>();
+
+        let corners = RangeCorner::new(0.5, 9, 1).detect_level(&ranges, 20, 20);
+        assert_eq!(corners.len(), 4);
+        for (index, _) in corners {
+            let [x, y] = [index % 20, index / 20];
+            assert!([5, 14].iter().any(|c: &usize| c.abs_diff(x) <= 1));
+            assert!([5, 14].iter().any(|c: &usize| c.abs_diff(y) <= 1));
+        }
+    }
+}
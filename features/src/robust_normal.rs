@@ -0,0 +1,231 @@
+use nalgebra::{convert, Matrix3, RealField, Scalar, Vector3, Vector4};
+use pcc_common::{
+    feature::{DegenerateError, Feature, OutputPolicy, PolicyOutput},
+    point::Point,
+    point_cloud::PointCloud,
+    robust::{RobustKernel, Tukey},
+    search::{Search, SearchType},
+};
+
+/// Estimates normals by fitting the local planes with iteratively reweighted
+/// least squares, which down-weighs the neighbors far from the plane, like
+/// the ones across sharp edges or outliers, instead of the plain covariance
+/// of [`Normal`](crate::Normal).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RobustNormal<T: Scalar, K = Tukey<T>> {
+    pub viewpoint: Vector4<T>,
+    pub max_iterations: usize,
+    /// The robust kernel applied to the distances from the neighbors to the
+    /// fitted plane.
+    pub kernel: K,
+}
+
+impl<T: Scalar> RobustNormal<T> {
+    /// Creates an estimator with Tukey's biweight, which rejects the
+    /// neighbors farther than `c` from the plane.
+    pub fn new(viewpoint: Vector4<T>, max_iterations: usize, c: T) -> Self {
+        RobustNormal {
+            viewpoint,
+            max_iterations,
+            kernel: Tukey::new(c),
+        }
+    }
+}
+
+impl<T: Scalar, K> RobustNormal<T, K> {
+    pub fn with_kernel<K2>(self, kernel: K2) -> RobustNormal<T, K2> {
+        RobustNormal {
+            viewpoint: self.viewpoint,
+            max_iterations: self.max_iterations,
+            kernel,
+        }
+    }
+}
+
+/// The weighted centroid and covariance matrix of `points`, or `None` if less
+/// than 3 of them have positive weights.
+fn weighted_cov<T: RealField>(
+    points: &[Vector3<T>],
+    weights: &[T],
+) -> Option<(Vector3<T>, Matrix3<T>)> {
+    let num = weights.iter().filter(|&w| *w > T::zero()).count();
+    let sum = { weights.iter() }.fold(T::zero(), |acc, w| acc + w.clone());
+    if num < 3 || sum <= T::zero() {
+        return None;
+    }
+
+    let mean = { points.iter().zip(weights) }
+        .fold(Vector3::zeros(), |acc, (p, w)| acc + p * w.clone())
+        / sum.clone();
+    let mut cov = Matrix3::zeros();
+    for (point, weight) in points.iter().zip(weights) {
+        let d = point - &mean;
+        cov.syger(weight.clone(), &d, &d, T::one());
+    }
+    Some((mean, cov / sum))
+}
+
+impl<T: RealField, K: RobustKernel<T>> RobustNormal<T, K> {
+    /// Fits the plane of `coords`, returning its normal oriented towards the
+    /// viewpoint and the surface curvature, or `None` if there are less than
+    /// 3 points.
+    pub fn fit<'a, Iter>(&self, coords: Iter) -> Option<(Vector4<T>, T)>
+    where
+        T: 'a,
+        Iter: Iterator<Item = &'a Vector4<T>>,
+    {
+        let points = { coords.filter(|coords| coords.iter().all(|x| x.is_finite())) }
+            .map(|coords| coords.xyz())
+            .collect::<Vec<_>>();
+        let mut weights = vec![T::one(); points.len()];
+
+        let mut estimate: Option<(Vector3<T>, T)> = None;
+        for _ in 0..=self.max_iterations {
+            // Keeps the last estimate if the kernel rejects too many points.
+            let (mean, cov) = match weighted_cov(&points, &weights) {
+                Some(result) => result,
+                None => break,
+            };
+            let se = cov.symmetric_eigen();
+            let index = se.eigenvalues.imin();
+            let normal = se.eigenvectors.column(index).into_owned();
+            let sum = se.eigenvalues.sum();
+            let curvature = if sum > T::zero() {
+                se.eigenvalues[index].clone() / sum
+            } else {
+                T::zero()
+            };
+
+            let converged = estimate.as_ref().map_or(false, |(last, _)| {
+                T::one() - normal.dot(last).abs() <= T::default_epsilon()
+            });
+            let residuals = { points.iter() }
+                .map(|point| (point - &mean).dot(&normal))
+                .collect::<Vec<_>>();
+            estimate = Some((normal, curvature));
+            if converged {
+                break;
+            }
+            weights = self.kernel.weights(&residuals);
+        }
+
+        let (mut normal, curvature) = estimate?;
+        if normal.dot(&self.viewpoint.xyz()) < T::zero() {
+            normal.neg_mut();
+        }
+        Some((normal.insert_row(3, T::zero()), curvature))
+    }
+
+    fn compute_with<'a, I, O, S>(
+        &self,
+        input: &'a PointCloud<I>,
+        search: S,
+        search_param: SearchType<T>,
+        policy: OutputPolicy,
+    ) -> Result<PolicyOutput<PointCloud<O>>, DegenerateError>
+    where
+        I: Point<Data = T> + 'a,
+        S: Search<'a, I>,
+        O: pcc_common::point::Normal<Data = T>,
+    {
+        let mut result = Vec::new();
+        let values = { input.iter() }
+            .map(|point| {
+                if !input.is_bounded() && !point.is_finite() {
+                    return Some(Default::default());
+                }
+                search.search(point.coords(), search_param.clone(), &mut result);
+                self.fit(
+                    result
+                        .iter()
+                        .map(|&(index, _)| search.input()[index].coords()),
+                )
+                .map(|(normal, curvature)| {
+                    O::default().with_normal(normal).with_curvature(curvature)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let nan = convert::<_, T>(f64::NAN);
+        let PolicyOutput {
+            output,
+            num_degenerate,
+        } = policy.resolve(values, Default::default, || {
+            O::default()
+                .with_normal(Vector4::repeat(nan.clone()))
+                .with_curvature(nan.clone())
+        })?;
+        Ok(PolicyOutput {
+            output: PointCloud::from_vec(output, input.width()),
+            num_degenerate,
+        })
+    }
+}
+
+impl<'a, T, K, I, O, S, Sp> Feature<&'a PointCloud<I>, PointCloud<O>, S, Sp> for RobustNormal<T, K>
+where
+    T: RealField,
+    K: RobustKernel<T>,
+    I: Point<Data = T> + 'a,
+    S: Search<'a, I>,
+    O: pcc_common::point::Normal<Data = T>,
+    Sp: Into<SearchType<T>>,
+{
+    fn compute(&self, input: &'a PointCloud<I>, search: S, search_param: Sp) -> PointCloud<O> {
+        let output = self.compute_with(input, search, search_param.into(), OutputPolicy::Skip);
+        output.unwrap().output
+    }
+}
+
+impl<'a, T, K, I, O, S, Sp>
+    Feature<
+        &'a PointCloud<I>,
+        Result<PolicyOutput<PointCloud<O>>, DegenerateError>,
+        S,
+        (Sp, OutputPolicy),
+    > for RobustNormal<T, K>
+where
+    T: RealField,
+    K: RobustKernel<T>,
+    I: Point<Data = T> + 'a,
+    S: Search<'a, I>,
+    O: pcc_common::point::Normal<Data = T>,
+    Sp: Into<SearchType<T>>,
+{
+    fn compute(
+        &self,
+        input: &'a PointCloud<I>,
+        search: S,
+        (search_param, policy): (Sp, OutputPolicy),
+    ) -> Result<PolicyOutput<PointCloud<O>>, DegenerateError> {
+        self.compute_with(input, search, search_param.into(), policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+
+    use super::*;
+
+    #[test]
+    fn test_robust_normal() {
+        let mut coords = { (0..10).flat_map(|x| (0..10).map(move |y| (x, y))) }
+            .map(|(x, y)| Vector4::new(x as f32 * 0.1, y as f32 * 0.1, 0., 1.))
+            .collect::<Vec<_>>();
+        coords.extend([
+            Vector4::new(0.2, 0.3, 0.8, 1.),
+            Vector4::new(0.7, 0.1, 0.6, 1.),
+            Vector4::new(0.5, 0.8, 0.9, 1.),
+        ]);
+        let viewpoint = Vector4::new(0., 0., 10., 1.);
+
+        let (_, plain) = pcc_common::normal(coords.iter(), &viewpoint).unwrap();
+        assert!(plain > 0.05);
+
+        let estimator = RobustNormal::new(viewpoint, 10, 0.1);
+        let (normal, curvature) = estimator.fit(coords.iter()).unwrap();
+        assert!((normal - Vector4::new(0., 0., 1., 0.)).norm() < 1e-4);
+        assert!(curvature < 1e-4);
+    }
+}
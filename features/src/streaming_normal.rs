@@ -0,0 +1,260 @@
+use std::collections::{HashMap, HashSet};
+
+use nalgebra::{convert, RealField, Scalar, Vector4};
+use num::ToPrimitive;
+use pcc_common::{
+    point::{Point, PointNormal},
+    point_cloud::PointCloud,
+};
+
+/// Estimates normals of a point cloud growing and shrinking over frames, e.g.
+/// the local map of a SLAM front-end, without recomputing the unchanged
+/// parts.
+///
+/// The points are kept in a hash map of cubic voxels of `voxel_size`, and the
+/// neighborhood of every point is the points within `radius`. Inserting and
+/// removing points marks their voxels as changed, and [`StreamingNormal::update`]
+/// only recomputes the normals of the points whose neighborhoods may touch
+/// the changed voxels.
+///
+/// Every point is identified by the ID returned on insertion, which may be
+/// reused after the point is removed.
+#[derive(Debug, Clone)]
+pub struct StreamingNormal<T: Scalar> {
+    pub viewpoint: Vector4<T>,
+    voxel_size: T,
+    radius: T,
+    points: Vec<Option<Vector4<T>>>,
+    normals: Vec<Option<(Vector4<T>, T)>>,
+    free: Vec<usize>,
+    voxels: HashMap<[i64; 3], Vec<usize>>,
+    changed: HashSet<[i64; 3]>,
+}
+
+impl<T: RealField + ToPrimitive> StreamingNormal<T> {
+    /// # Panics
+    ///
+    /// Panics if `voxel_size` or `radius` is not positive.
+    pub fn new(viewpoint: Vector4<T>, voxel_size: T, radius: T) -> Self {
+        assert!(
+            voxel_size > T::zero() && radius > T::zero(),
+            "The voxel size and the radius must be positive"
+        );
+        StreamingNormal {
+            viewpoint,
+            voxel_size,
+            radius,
+            points: Vec::new(),
+            normals: Vec::new(),
+            free: Vec::new(),
+            voxels: HashMap::new(),
+            changed: HashSet::new(),
+        }
+    }
+
+    fn key(&self, coords: &Vector4<T>) -> [i64; 3] {
+        let key = coords
+            .xyz()
+            .map(|x| (x / self.voxel_size.clone()).floor().to_i64().unwrap());
+        *key.as_ref()
+    }
+
+    /// The number of voxels around a voxel that a neighborhood may reach.
+    fn rings(&self) -> i64 {
+        (self.radius.clone() / self.voxel_size.clone())
+            .ceil()
+            .to_i64()
+            .unwrap()
+    }
+
+    /// The number of points kept.
+    pub fn len(&self) -> usize {
+        self.points.len() - self.free.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Inserts a point and returns its ID, or `None` if it's not finite.
+    pub fn insert(&mut self, coords: Vector4<T>) -> Option<usize> {
+        if !coords.iter().all(|x| x.is_finite()) {
+            return None;
+        }
+        let key = self.key(&coords);
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.points[id] = Some(coords);
+                id
+            }
+            None => {
+                self.points.push(Some(coords));
+                self.normals.push(None);
+                self.points.len() - 1
+            }
+        };
+        self.voxels.entry(key).or_default().push(id);
+        self.changed.insert(key);
+        Some(id)
+    }
+
+    /// Inserts the points of a frame and returns their IDs, where the
+    /// non-finite points are `None`.
+    pub fn insert_frame<P: Point<Data = T>>(
+        &mut self,
+        point_cloud: &PointCloud<P>,
+    ) -> Vec<Option<usize>> {
+        { point_cloud.iter() }
+            .map(|point| self.insert(point.coords().clone()))
+            .collect()
+    }
+
+    /// Removes the point of `id`, returning its coordinates if it exists.
+    pub fn remove(&mut self, id: usize) -> Option<Vector4<T>> {
+        let coords = self.points.get_mut(id)?.take()?;
+        self.normals[id] = None;
+        self.free.push(id);
+
+        let key = self.key(&coords);
+        if let Some(ids) = self.voxels.get_mut(&key) {
+            ids.retain(|&other| other != id);
+            if ids.is_empty() {
+                self.voxels.remove(&key);
+            }
+        }
+        self.changed.insert(key);
+        Some(coords)
+    }
+
+    /// Removes the points not satisfying `pred`, e.g. the ones too far from
+    /// the current pose, and returns the number of removed points.
+    pub fn retain(&mut self, mut pred: impl FnMut(usize, &Vector4<T>) -> bool) -> usize {
+        let removed = { self.points.iter().enumerate() }
+            .filter_map(|(id, coords)| {
+                let coords = coords.as_ref()?;
+                (!pred(id, coords)).then_some(id)
+            })
+            .collect::<Vec<_>>();
+        for &id in &removed {
+            self.remove(id);
+        }
+        removed.len()
+    }
+
+    #[inline]
+    pub fn coords(&self, id: usize) -> Option<&Vector4<T>> {
+        self.points.get(id)?.as_ref()
+    }
+
+    /// The normal and the curvature of the point of `id` as of the last
+    /// update, or `None` if unknown or degenerate.
+    #[inline]
+    pub fn normal(&self, id: usize) -> Option<&(Vector4<T>, T)> {
+        self.normals.get(id)?.as_ref()
+    }
+
+    fn estimate(&self, coords: &Vector4<T>) -> Option<(Vector4<T>, T)> {
+        let center = self.key(coords);
+        let rings = self.rings();
+
+        let mut neighbors = Vec::new();
+        for dx in -rings..=rings {
+            for dy in -rings..=rings {
+                for dz in -rings..=rings {
+                    let key = [center[0] + dx, center[1] + dy, center[2] + dz];
+                    let ids = match self.voxels.get(&key) {
+                        Some(ids) => ids,
+                        None => continue,
+                    };
+                    neighbors.extend(
+                        { ids.iter() }
+                            .filter_map(|&id| self.points[id].as_ref())
+                            .filter(|other| (*other - coords).xyz().norm() <= self.radius),
+                    );
+                }
+            }
+        }
+        pcc_common::normal(neighbors.into_iter(), &self.viewpoint)
+    }
+
+    /// Recomputes the normals of the points whose neighborhoods may have
+    /// changed since the last update, and returns their IDs.
+    pub fn update(&mut self) -> Vec<usize> {
+        let rings = self.rings();
+        let mut affected = HashSet::new();
+        for key in self.changed.drain() {
+            for dx in -rings..=rings {
+                for dy in -rings..=rings {
+                    for dz in -rings..=rings {
+                        affected.insert([key[0] + dx, key[1] + dy, key[2] + dz]);
+                    }
+                }
+            }
+        }
+
+        let ids = { affected.iter() }
+            .filter_map(|key| self.voxels.get(key))
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        for &id in &ids {
+            let coords = self.points[id].as_ref().unwrap();
+            self.normals[id] = self.estimate(coords);
+        }
+        ids
+    }
+
+    /// Collects the points kept with their normals as of the last update, and
+    /// their IDs. The points without normals have NaN normals and
+    /// curvatures.
+    pub fn to_point_cloud<O>(&self) -> (PointCloud<O>, Vec<usize>)
+    where
+        O: PointNormal<Data = T>,
+    {
+        let nan = convert::<_, T>(f64::NAN);
+        let (storage, ids) = { self.points.iter().enumerate() }
+            .filter_map(|(id, coords)| {
+                let (normal, curvature) = match &self.normals[id] {
+                    Some((normal, curvature)) => (normal.clone(), curvature.clone()),
+                    None => (Vector4::repeat(nan.clone()), nan.clone()),
+                };
+                let point = { O::default().with_coords(coords.clone()?) }
+                    .with_normal(normal)
+                    .with_curvature(curvature);
+                Some((point, id))
+            })
+            .unzip::<_, _, Vec<_>, Vec<_>>();
+        (PointCloud::from_vec(storage, 1), ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streaming_normal() {
+        let mut normals = StreamingNormal::new(Vector4::new(0., 0., 10., 1.), 0.5f32, 0.35);
+        let plane = |x: usize, y: usize| Vector4::new(x as f32 * 0.1, y as f32 * 0.1, 0., 1.);
+
+        let first = { (0..10).flat_map(|x| (0..10).map(move |y| (x, y))) }
+            .map(|(x, y)| normals.insert(plane(x, y)).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(normals.update().len(), 100);
+        let (normal, _) = normals.normal(first[0]).unwrap();
+        assert!((normal.z.abs() - 1.).abs() < 1e-4);
+
+        // Far away, so only the new points are updated.
+        let second = { (0..10).flat_map(|x| (0..10).map(move |y| (x + 50, y))) }
+            .map(|(x, y)| normals.insert(plane(x, y)).unwrap())
+            .collect::<Vec<_>>();
+        let mut updated = normals.update();
+        updated.sort();
+        assert_eq!(updated, second);
+
+        normals.retain(|_, coords| coords.x < 3.);
+        assert_eq!(normals.len(), 100);
+        assert!(normals.update().is_empty());
+    }
+}
@@ -0,0 +1,254 @@
+use nalgebra::{RealField, Vector4};
+use pcc_common::{
+    feature::Feature,
+    point::{Normal, Point, PointIntensity},
+    point_cloud::PointCloud,
+    search::{Search, SearchType},
+};
+
+/// The 3D SUSAN keypoint detector, after *SUSAN - A New Approach to Low Level
+/// Image Processing* by Smith and Brady.
+///
+/// The USAN of a point is its neighbors whose normals differ from its own
+/// within `angular_threshold` in radians, and whose intensities differ
+/// within `intensity_threshold` if detected with intensities. The points with
+/// USANs smaller than half of their neighborhoods are corners, unless the
+/// centroids of their USANs are within `distance_threshold` from them, which
+/// suppresses the false positives on thin structures.
+///
+/// The points whose whole neighborhoods are off-centered by more than
+/// `border_threshold`, i.e. on the borders of the scans, are suppressed, and
+/// the keypoints are the local maxima of the responses within their
+/// neighborhoods. As a [`Feature`], it detects with only the normals and
+/// returns the indices of the keypoints.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Susan3d<T> {
+    pub angular_threshold: T,
+    pub intensity_threshold: T,
+    pub distance_threshold: T,
+    pub border_threshold: T,
+}
+
+impl<T> Susan3d<T> {
+    pub fn new(
+        angular_threshold: T,
+        intensity_threshold: T,
+        distance_threshold: T,
+        border_threshold: T,
+    ) -> Self {
+        Susan3d {
+            angular_threshold,
+            intensity_threshold,
+            distance_threshold,
+            border_threshold,
+        }
+    }
+}
+
+/// Keeps the points with positive responses that are the largest within
+/// their neighborhoods, where the ties are broken by the indices.
+pub(crate) fn non_max_suppression<T: RealField>(
+    responses: &[T],
+    mut neighbors: impl FnMut(usize, &mut dyn FnMut(usize)),
+) -> Vec<usize> {
+    { responses.iter().enumerate() }
+        .filter(|(_, response)| **response > T::zero())
+        .filter(|&(index, response)| {
+            let mut max = true;
+            neighbors(index, &mut |other| {
+                let other_response = &responses[other];
+                if other_response > response || (other_response == response && other < index) {
+                    max = false;
+                }
+            });
+            max
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+impl<T: RealField> Susan3d<T> {
+    fn response<I, N>(
+        &self,
+        (input, normals): (&PointCloud<I>, &PointCloud<N>),
+        intensity: Option<&dyn Fn(usize) -> T>,
+        index: usize,
+        neighbors: &[(usize, T)],
+    ) -> T
+    where
+        I: Point<Data = T>,
+        N: Normal<Data = T>,
+    {
+        let nucleus = input[index].coords();
+        let normal = normals[index].normal().xyz();
+        if neighbors.len() < 3 || !normals[index].is_finite() {
+            return T::zero();
+        }
+
+        let num = T::from_usize(neighbors.len()).unwrap();
+        let centroid = { neighbors.iter() }.fold(Vector4::zeros(), |acc, &(other, _)| {
+            acc + input[other].coords()
+        }) / num.clone();
+        if (centroid - nucleus).xyz().norm() > self.border_threshold {
+            return T::zero();
+        }
+
+        let cos = self.angular_threshold.clone().cos();
+        let usan = { neighbors.iter() }
+            .filter(|&&(other, _)| {
+                let other_normal = normals[other].normal().xyz();
+                normal.dot(&other_normal).abs() >= cos
+            })
+            .filter(|&&(other, _)| match intensity {
+                Some(intensity) => {
+                    (intensity(other) - intensity(index)).abs() <= self.intensity_threshold
+                }
+                None => true,
+            })
+            .map(|&(other, _)| input[other].coords())
+            .collect::<Vec<_>>();
+
+        let geometric = num / (T::one() + T::one());
+        let area = T::from_usize(usan.len()).unwrap();
+        if usan.is_empty() || area >= geometric {
+            return T::zero();
+        }
+        let usan_centroid = usan
+            .iter()
+            .fold(Vector4::zeros(), |acc, &coords| acc + coords)
+            / area.clone();
+        if (usan_centroid - nucleus).xyz().norm() <= self.distance_threshold {
+            return T::zero();
+        }
+        geometric - area
+    }
+
+    fn detect<'a, I, N, S>(
+        &self,
+        (input, normals): (&'a PointCloud<I>, &PointCloud<N>),
+        intensity: Option<&dyn Fn(usize) -> T>,
+        search: &S,
+        search_param: SearchType<T>,
+    ) -> Vec<usize>
+    where
+        I: Point<Data = T> + 'a,
+        N: Normal<Data = T>,
+        S: Search<'a, I>,
+    {
+        let mut result = Vec::new();
+        let responses = { input.iter().enumerate() }
+            .map(|(index, point)| {
+                if !point.is_finite() {
+                    return T::zero();
+                }
+                search.search(point.coords(), search_param.clone(), &mut result);
+                result.retain(|&(other, _)| other != index);
+                self.response((input, normals), intensity, index, &result)
+            })
+            .collect::<Vec<_>>();
+
+        non_max_suppression(&responses, |index, visitor| {
+            search.search_with(
+                input[index].coords(),
+                search_param.clone(),
+                &mut |other, _| visitor(other),
+            )
+        })
+    }
+
+    /// Detects the keypoints with both the normals and the intensities of
+    /// the points, and returns their indices.
+    pub fn compute_with_intensity<'a, I, N, S, Sp>(
+        &self,
+        (input, normals): (&'a PointCloud<I>, &PointCloud<N>),
+        search: S,
+        search_param: Sp,
+    ) -> Vec<usize>
+    where
+        I: PointIntensity<Data = T> + 'a,
+        N: Normal<Data = T>,
+        S: Search<'a, I>,
+        Sp: Into<SearchType<T>>,
+    {
+        let intensity = |index: usize| input[index].intensity();
+        self.detect(
+            (input, normals),
+            Some(&intensity),
+            &search,
+            search_param.into(),
+        )
+    }
+}
+
+impl<'a, 'b, T, I, N, S, Sp> Feature<(&'a PointCloud<I>, &'b PointCloud<N>), Vec<usize>, S, Sp>
+    for Susan3d<T>
+where
+    T: RealField,
+    I: Point<Data = T> + 'a,
+    N: Normal<Data = T> + 'b,
+    S: Search<'a, I>,
+    Sp: Into<SearchType<T>>,
+{
+    fn compute(
+        &self,
+        input: (&'a PointCloud<I>, &'b PointCloud<N>),
+        search: S,
+        search_param: Sp,
+    ) -> Vec<usize> {
+        self.detect(input, None, &search, search_param.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::point::{Point3, Point3N};
+    use pcc_search::KdTree;
+
+    use super::*;
+
+    #[test]
+    fn test_susan3d() {
+        // The surface of a cube, where the points on the edges take the
+        // normals of the faces perpendicular to the x and then the y axes.
+        let side = 8;
+        let (input, normals): (Vec<_>, Vec<_>) = { 0..=side }
+            .flat_map(|x| (0..=side).flat_map(move |y| (0..=side).map(move |z| [x, y, z])))
+            .filter(|coords| coords.iter().any(|&c| c == 0 || c == side))
+            .map(|coords| {
+                let axis = coords.iter().position(|&c| c == 0 || c == side).unwrap();
+                let mut normal = Vector4::zeros();
+                normal[axis] = if coords[axis] == 0 { -1. } else { 1. };
+                let [x, y, z] = coords.map(|c| c as f32);
+                let coords = Vector4::new(x, y, z, 1.);
+                (
+                    Point3::default().with_coords(coords),
+                    Point3N::default().with_coords(coords).with_normal(normal),
+                )
+            })
+            .unzip();
+        let len = input.len();
+        let input = PointCloud::from_vec(input, len);
+        let normals = PointCloud::from_vec(normals, len);
+
+        let susan = Susan3d::new(0.5f32, 0., 1., 100.);
+        let keypoints = susan.compute(
+            (&input, &normals),
+            &KdTree::new(&input),
+            SearchType::Radius(2.5),
+        );
+        assert_eq!(keypoints.len(), 8);
+        for index in keypoints {
+            // Every keypoint is next to one of the corners.
+            let coords = input[index].coords();
+            let corner = coords.map(|c| {
+                if c < side as f32 / 2. {
+                    0.
+                } else {
+                    side as f32
+                }
+            });
+            assert!((coords - corner).xyz().norm() < 1.5);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use nalgebra::{RealField, Vector3};
+use pcc_common::{point::Normal, point_cloud::PointCloud};
+
+use crate::susan::non_max_suppression;
+
+/// The Trajkovic-Hedley corner detector for organized point clouds, after
+/// *Fast Corner Detection* by Trajkovic and Hedley, with the differences of
+/// the intensities replaced by the ones of the normals.
+///
+/// Every pixel compares its normal with the ones `window` pixels away along
+/// the rows and the columns. The pixels whose normals change little along
+/// any of the directions, i.e. with simple responses below `first_threshold`,
+/// are not corners. The others are refined by the inter-pixel approximation
+/// and kept if above `second_threshold`.
+///
+/// The pixels within `window` from the borders of the images or next to
+/// invalid normals are suppressed, and the keypoints are the local maxima of
+/// the responses within the squares of `window` around them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TrajkovicHedley<T> {
+    pub window: usize,
+    pub first_threshold: T,
+    pub second_threshold: T,
+}
+
+impl<T> TrajkovicHedley<T> {
+    pub fn new(window: usize, first_threshold: T, second_threshold: T) -> Self {
+        TrajkovicHedley {
+            window,
+            first_threshold,
+            second_threshold,
+        }
+    }
+}
+
+impl<T: RealField> TrajkovicHedley<T> {
+    fn response(&self, normal: &dyn Fn(isize, isize) -> Option<Vector3<T>>) -> T {
+        let w = self.window as isize;
+        let center = match normal(0, 0) {
+            Some(center) => center,
+            None => return T::zero(),
+        };
+        // Aligns the signs of the normals with the center.
+        let aligned =
+            |dx, dy| normal(dx, dy).map(|n| if n.dot(&center) < T::zero() { -n } else { n });
+        let (right, left, down, up) =
+            match (aligned(w, 0), aligned(-w, 0), aligned(0, w), aligned(0, -w)) {
+                (Some(right), Some(left), Some(down), Some(up)) => (right, left, down, up),
+                _ => return T::zero(),
+            };
+
+        let diff = |n: &Vector3<T>| (n - &center).norm_squared();
+        let r_a = diff(&right) + diff(&left);
+        let r_b = diff(&down) + diff(&up);
+        let simple = r_a.clone().min(r_b.clone());
+        if simple < self.first_threshold {
+            return T::zero();
+        }
+
+        // The inter-pixel approximation along the directions between the
+        // rows and the columns.
+        let b1 = (&down - &right).dot(&(&right - &center)) + (&up - &left).dot(&(&left - &center));
+        let b2 = (&up - &right).dot(&(&right - &center)) + (&down - &left).dot(&(&left - &center));
+        let c = r_a.clone();
+        let a1 = r_b.clone() - r_a.clone() - (b1.clone() + b1.clone());
+        let a2 = r_b - r_a - (b2.clone() + b2.clone());
+        let response = if b1 < T::zero() && a1.clone() + b1.clone() > T::zero() {
+            c - b1.clone() * b1 / a1
+        } else if b2 < T::zero() && a2.clone() + b2.clone() > T::zero() {
+            c - b2.clone() * b2 / a2
+        } else {
+            simple
+        };
+
+        if response > self.second_threshold {
+            response
+        } else {
+            T::zero()
+        }
+    }
+
+    /// Detects the corners in the organized `normals`, and returns their
+    /// indices. Unorganized point clouds have no corners.
+    pub fn compute<N: Normal<Data = T>>(&self, normals: &PointCloud<N>) -> Vec<usize> {
+        if normals.is_empty() || normals.height() <= 1 || self.window == 0 {
+            return Vec::new();
+        }
+        let (width, height) = (normals.width(), normals.height());
+        let w = self.window;
+
+        let responses = (0..normals.len())
+            .map(|index| {
+                let [x, y] = [index % width, index / width];
+                if x < w || y < w || x + w >= width || y + w >= height {
+                    return T::zero();
+                }
+                let normal = |dx: isize, dy: isize| {
+                    let x = x.checked_add_signed(dx)?;
+                    let y = y.checked_add_signed(dy)?;
+                    let normal = normals[y * width + x].normal().xyz();
+                    normal.iter().all(|x| x.is_finite()).then_some(normal)
+                };
+                self.response(&normal)
+            })
+            .collect::<Vec<_>>();
+
+        non_max_suppression(&responses, |index, visitor| {
+            let [x, y] = [index % width, index / width];
+            for ny in y.saturating_sub(w)..(y + w + 1).min(height) {
+                for nx in x.saturating_sub(w)..(x + w + 1).min(width) {
+                    visitor(ny * width + nx)
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::point::Normal3;
+
+    use super::*;
+
+    #[test]
+    fn test_trajkovic_hedley() {
+        // A flat floor with a box standing in one quadrant, whose corner is at
+        // (10, 10).
+        let storage = { (0..400).map(|index| (index % 20, index / 20)) }
+            .map(|(x, y)| {
+                let normal = match (x >= 10, y >= 10) {
+                    (true, true) => Vector4::new(0., 0., 1., 0.),
+                    (true, false) => Vector4::new(0., -1., 0., 0.),
+                    (false, true) => Vector4::new(-1., 0., 0., 0.),
+                    (false, false) => Vector4::new(0., 0., 1., 0.),
+                };
+                Normal3::default().with_normal(normal)
+            })
+            .collect::<Vec<_>>();
+        let pc = PointCloud::from_vec(storage, 20);
+
+        let corners = TrajkovicHedley::new(2, 0.5f32, 0.5).compute(&pc);
+        assert!(!corners.is_empty());
+        for corner in corners {
+            let [x, y] = [corner % 20, corner / 20];
+            assert!(x.abs_diff(10) <= 2 && y.abs_diff(10) <= 2);
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use nalgebra::{convert, RealField, Scalar};
 use num::ToPrimitive;
 use pcc_common::{
-    filter::ApproxFilter, point::PointIntensity, point_cloud::PointCloud, search::SearchType,
+    filter::ApproxFilter,
+    point::PointIntensity,
+    point_cloud::PointCloud,
+    search::{Search, SearchType},
 };
 use pcc_search::searcher;
 
@@ -47,12 +50,15 @@ impl<T: RealField> Bilateral<T> {
     }
 }
 
-impl<T: RealField + ToPrimitive, P: PointIntensity<Data = T>> ApproxFilter<PointCloud<P>>
-    for Bilateral<T>
-{
-    fn filter(&mut self, input: &PointCloud<P>) -> PointCloud<P> {
-        searcher!(searcher in input, T::default_epsilon());
-
+impl<T: RealField> Bilateral<T> {
+    /// Like [`ApproxFilter::filter`], but reuses `searcher` on the input
+    /// instead of building a new one.
+    pub fn filter_with<'a, P, S>(&self, searcher: &S) -> PointCloud<P>
+    where
+        P: PointIntensity<Data = T> + 'a,
+        S: Search<'a, P> + ?Sized,
+    {
+        let input = searcher.input();
         let radius = self.sigma_d.clone() * convert(2.);
         let mut result = Vec::new();
         let mut output = input.clone();
@@ -73,3 +79,12 @@ impl<T: RealField + ToPrimitive, P: PointIntensity<Data = T>> ApproxFilter<Point
         output
     }
 }
+
+impl<T: RealField + ToPrimitive, P: PointIntensity<Data = T>> ApproxFilter<PointCloud<P>>
+    for Bilateral<T>
+{
+    fn filter(&mut self, input: &PointCloud<P>) -> PointCloud<P> {
+        searcher!(searcher in input, T::default_epsilon());
+        self.filter_with(searcher)
+    }
+}
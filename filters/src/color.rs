@@ -0,0 +1,102 @@
+use std::ops::RangeInclusive;
+
+use pcc_common::{
+    filter::{ApproxFilter, Filter},
+    point::PointRgba,
+    point_cloud::PointCloud,
+};
+
+/// Keeps the points whose colors are within the ranges (or outside if
+/// `negative`) in HSV as in [`PointRgba::hsv`].
+///
+/// The hue range wraps around if its start is greater than its end, e.g.
+/// `330.0..=30.0` for reds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorFilter {
+    /// The range of the hue in degrees.
+    pub hue: RangeInclusive<f32>,
+    pub saturation: RangeInclusive<f32>,
+    pub value: RangeInclusive<f32>,
+    pub negative: bool,
+}
+
+impl ColorFilter {
+    pub fn new(
+        hue: RangeInclusive<f32>,
+        saturation: RangeInclusive<f32>,
+        value: RangeInclusive<f32>,
+        negative: bool,
+    ) -> Self {
+        ColorFilter {
+            hue,
+            saturation,
+            value,
+            negative,
+        }
+    }
+
+    #[inline]
+    fn inner<P: PointRgba>(&self) -> impl FnMut(&P) -> bool + '_ {
+        move |point| {
+            let [h, s, v] = point.hsv();
+            let hue = if self.hue.start() <= self.hue.end() {
+                self.hue.contains(&h)
+            } else {
+                h >= *self.hue.start() || h <= *self.hue.end()
+            };
+            (hue && self.saturation.contains(&s) && self.value.contains(&v)) ^ self.negative
+        }
+    }
+}
+
+impl<P: PointRgba> Filter<[P]> for ColorFilter {
+    #[inline]
+    fn filter_indices(&mut self, input: &[P]) -> Vec<usize> {
+        self.inner().filter_indices(input)
+    }
+
+    #[inline]
+    fn filter_all_indices(&mut self, input: &[P]) -> (Vec<usize>, Vec<usize>) {
+        self.inner().filter_all_indices(input)
+    }
+}
+
+impl<P: PointRgba> ApproxFilter<PointCloud<P>> for ColorFilter {
+    #[inline]
+    fn filter(&mut self, input: &PointCloud<P>) -> PointCloud<P> {
+        self.inner().filter(input)
+    }
+
+    #[inline]
+    fn filter_mut(&mut self, obj: &mut PointCloud<P>) {
+        self.inner().filter_mut(obj)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pcc_common::point::Point3Rgba;
+
+    use super::*;
+
+    #[test]
+    fn test_color_filter() {
+        let input = { [0xffff0000, 0xffff0040, 0xff00ff00, 0xff400000, 0xff808080].into_iter() }
+            .map(|rgba| Point3Rgba::default().with_rgba(rgba))
+            .collect::<Vec<_>>();
+
+        // The hue of the second color is 345 degrees, within the wrapped reds.
+        let mut reds = ColorFilter::new(330.0..=30.0, 0.5..=1.0, 0.5..=1.0, false);
+        assert_eq!(reds.filter_indices(&input), [0, 1]);
+        let (inliers, outliers) = reds.filter_all_indices(&input);
+        assert_eq!(inliers, [0, 1]);
+        assert_eq!(outliers, [2, 3, 4]);
+
+        let mut greens = ColorFilter::new(90.0..=150.0, 0.5..=1.0, 0.5..=1.0, false);
+        assert_eq!(greens.filter_indices(&input), [2]);
+
+        // The gray has no saturation, and the dark red too low a value.
+        let mut dull = ColorFilter::new(0.0..=360.0, 0.5..=1.0, 0.5..=1.0, true);
+        assert_eq!(dull.filter_indices(&input), [3, 4]);
+    }
+}
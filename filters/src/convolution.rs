@@ -2,28 +2,58 @@ mod gauss;
 
 use std::fmt::Debug;
 
-use nalgebra::{ComplexField, DVector, Scalar, Vector4};
+use nalgebra::{convert, ComplexField, DVector, Scalar, Vector4};
 use pcc_common::{
     point::Point,
     point_cloud::PointCloud,
     search::{Search, SearchType},
 };
-use rayon::{iter::ParallelIterator, prelude::IntoParallelRefIterator};
+use rayon::{
+    iter::{IndexedParallelIterator, ParallelIterator},
+    prelude::IntoParallelRefIterator,
+};
 
-pub use self::gauss::{Gauss, GaussRgba};
+pub use self::gauss::{
+    gaussian_derivative_kernel, gaussian_kernel, Gauss, GaussRgba, GaussianSmooth2,
+};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum BorderOptions {
+    /// Leaves the default points at the borders.
     Default,
+    /// Mirrors the convolved points near the borders.
     Mirrored,
+    /// Repeats the convolved points at the borders.
     Repeated,
+    /// Convolves the points at the borders as if the input were padded with
+    /// zeros.
+    Zero,
+}
+
+/// What to output for a point whose finite neighbors have a total weight of
+/// zero.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ZeroWeight {
+    /// Outputs a point with NaN coordinates.
+    #[default]
+    Nan,
+    /// Keeps the input point.
+    Keep,
+    /// Outputs the weighted sum without normalization.
+    Sum,
 }
 
-/// This struct only processes organized point clouds (2-D indices-wise)
+/// This struct only processes organized point clouds (2-D indices-wise).
+///
+/// Non-finite neighbors are skipped, and if `normalize` is set, the weighted
+/// sum is divided by the total weight of the remaining ones. Non-finite
+/// points stay non-finite in the output.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Fixed2<T: Scalar> {
     pub kernel: DVector<T>,
     pub border_options: BorderOptions,
+    pub normalize: bool,
+    pub zero_weight: ZeroWeight,
 }
 
 impl<T: Scalar> Fixed2<T> {
@@ -31,105 +61,96 @@ impl<T: Scalar> Fixed2<T> {
         Fixed2 {
             kernel,
             border_options,
+            normalize: true,
+            zero_weight: ZeroWeight::Nan,
         }
     }
 }
 
 impl<T: ComplexField> Fixed2<T> {
-    fn convolve_one<P: Point<Data = T>>(&self, points: &[P], kernel_len: usize) -> Option<P> {
-        let (sum, weight) = (0..kernel_len).zip(points.iter().rev()).fold(
-            (Vector4::zeros(), T::zero()),
-            |(sum, w), (k, point)| {
-                (
-                    sum + point.coords().clone() * self.kernel[k].clone(),
-                    w + self.kernel[k].clone(),
-                )
-            },
-        );
-
-        (weight != T::zero()).then(|| P::default().with_coords(sum / weight))
-    }
-
-    fn convolve_default<P: Point<Data = T> + Clone>(
-        &self,
-        points: &[P],
-        seg_size: usize,
-        storage: &mut Vec<P>,
-    ) {
-        storage.clear();
-        storage.reserve(points.len());
+    /// Convolves the point at `center` of `row`, where the samples out of the
+    /// row count as zeros.
+    fn convolve_one<P: Point<Data = T> + Clone>(&self, row: &[P], center: usize) -> P {
+        let nan = || {
+            let nan = convert::<_, T>(f64::NAN);
+            P::default().with_coords(Vector4::new(nan.clone(), nan.clone(), nan, T::one()))
+        };
+        if !row[center].is_finite() {
+            return nan();
+        }
 
         let kernel_len = self.kernel.len();
-        let start_gap = kernel_len / 2;
-
-        for seg in (0..points.len()).step_by(seg_size) {
-            storage.resize_with(seg + start_gap, Default::default);
-            for index in seg..=(seg + seg_size - kernel_len) {
-                let point = self.convolve_one(&points[index..][..kernel_len], kernel_len);
-                storage.push(point.unwrap());
+        let end_gap = kernel_len - kernel_len / 2 - 1;
+
+        let mut sum = Vector4::zeros();
+        let mut weight = T::zero();
+        for k in 0..kernel_len {
+            let w = self.kernel[k].clone();
+            let index = (center + end_gap).checked_sub(k);
+            match index.and_then(|index| row.get(index)) {
+                Some(point) if point.is_finite() => {
+                    sum += point.coords() * w.clone();
+                    weight += w;
+                }
+                Some(_) => {}
+                None => weight += w,
             }
-            storage.resize_with(seg + seg_size, Default::default)
         }
-    }
 
-    fn convolve_mirrored<P: Point<Data = T> + Clone>(
-        &self,
-        points: &[P],
-        seg_size: usize,
-        storage: &mut Vec<P>,
-    ) {
-        storage.clear();
-        storage.reserve(points.len());
+        let mut coords = if !self.normalize {
+            sum
+        } else if weight != T::zero() {
+            sum / weight
+        } else {
+            match self.zero_weight {
+                ZeroWeight::Nan => return nan(),
+                ZeroWeight::Keep => return row[center].clone(),
+                ZeroWeight::Sum => sum,
+            }
+        };
+        coords.w = T::one();
+        P::default().with_coords(coords)
+    }
 
+    fn convolve_row<P: Point<Data = T> + Clone>(&self, row: &[P], storage: &mut Vec<P>) {
         let kernel_len = self.kernel.len();
         let start_gap = kernel_len / 2;
         let end_gap = kernel_len - start_gap - 1;
-        let last = seg_size - end_gap - 1;
+        let seg = storage.len();
 
-        for seg in (0..points.len()).step_by(seg_size) {
-            storage.resize_with(seg + start_gap, Default::default);
-            for index in seg..=(seg + seg_size - kernel_len) {
-                let point = self.convolve_one(&points[index..][..kernel_len], kernel_len);
-                storage.push(point.unwrap());
-            }
-            for index in 0..end_gap {
-                let point = storage[seg + last - index].clone();
-                storage.push(point);
-            }
-            for index in 0..start_gap {
-                let point = storage[seg + start_gap + index].clone();
-                storage[seg + start_gap - index - 1] = point;
-            }
+        if self.border_options == BorderOptions::Zero {
+            storage.extend((0..row.len()).map(|center| self.convolve_one(row, center)));
+            return;
         }
-    }
 
-    fn convolve_repeated<P: Point<Data = T> + Clone>(
-        &self,
-        points: &[P],
-        seg_size: usize,
-        storage: &mut Vec<P>,
-    ) {
-        storage.clear();
-        storage.reserve(points.len());
+        storage.resize_with(seg + row.len(), Default::default);
+        if row.len() < kernel_len {
+            return;
+        }
 
-        let kernel_len = self.kernel.len();
-        let start_gap = kernel_len / 2;
-        let last = seg_size - kernel_len + start_gap;
+        let last = row.len() - end_gap - 1;
+        for center in start_gap..=last {
+            storage[seg + center] = self.convolve_one(row, center);
+        }
 
-        for seg in (0..points.len()).step_by(seg_size) {
-            storage.resize_with(seg + start_gap, Default::default);
-            for index in seg..=(seg + seg_size - kernel_len) {
-                let point = self.convolve_one(&points[index..][..kernel_len], kernel_len);
-                storage.push(point.unwrap());
+        match self.border_options {
+            BorderOptions::Mirrored => {
+                for index in 0..start_gap {
+                    let point = storage[seg + (start_gap + index).min(last)].clone();
+                    storage[seg + start_gap - index - 1] = point;
+                }
+                for index in 0..end_gap {
+                    let point = storage[seg + last.saturating_sub(index).max(start_gap)].clone();
+                    storage[seg + last + index + 1] = point;
+                }
             }
-
-            let point = storage[seg + last].clone();
-            storage.resize_with(seg + seg_size, || point.clone());
-
-            let point = storage[seg + start_gap].clone();
-            for index in 0..start_gap {
-                storage[seg + index] = point.clone();
+            BorderOptions::Repeated => {
+                let point = storage[seg + start_gap].clone();
+                storage[seg..][..start_gap].fill(point);
+                let point = storage[seg + last].clone();
+                storage[seg + last + 1..][..end_gap].fill(point);
             }
+            _ => {}
         }
     }
 
@@ -140,10 +161,11 @@ impl<T: ComplexField> Fixed2<T> {
     ) {
         let width = input.width();
         unsafe {
-            match self.border_options {
-                BorderOptions::Default => self.convolve_default(input, width, output.storage()),
-                BorderOptions::Mirrored => self.convolve_mirrored(input, width, output.storage()),
-                BorderOptions::Repeated => self.convolve_repeated(input, width, output.storage()),
+            let storage = output.storage();
+            storage.clear();
+            storage.reserve(input.len());
+            for row in input.chunks(width) {
+                self.convolve_row(row, storage);
             }
 
             output.reinterpret(width)
@@ -217,6 +239,17 @@ pub trait DynamicKernel<'a, P: Point + 'a> {
     fn convolve<Iter>(&self, data: Iter) -> P
     where
         Iter: IntoIterator<Item = (&'a P, P::Data)>;
+
+    /// Convolves the neighbors of `point`, for kernels that weigh the
+    /// neighbors against the point itself, like bilateral ones.
+    #[inline]
+    fn convolve_around<Iter>(&self, point: &'a P, data: Iter) -> P
+    where
+        Iter: IntoIterator<Item = (&'a P, P::Data)>,
+    {
+        let _ = point;
+        self.convolve(data)
+    }
 }
 
 /// This struct proceesses point clouds 3-D coordinates-wise, and provides
@@ -238,6 +271,27 @@ impl<T: Scalar, K, S> Dynamic<T, K, S> {
     }
 }
 
+fn convolve_one<'a, P, K, S>(kernel: &K, searcher: &S, radius: &P::Data, point: &'a P) -> P
+where
+    P: Point + 'a,
+    K: DynamicKernel<'a, P>,
+    S: Search<'a, P>,
+{
+    let input = searcher.input();
+    let mut result = Vec::new();
+
+    searcher.search(
+        point.coords(),
+        SearchType::Radius(radius.clone()),
+        &mut result,
+    );
+
+    kernel.convolve_around(
+        point,
+        { result.into_iter() }.map(|(index, distance)| (&input[index], distance)),
+    )
+}
+
 impl<'a, T: ComplexField, K, S> Dynamic<T, K, S> {
     pub fn convolve_par<P>(&self) -> PointCloud<P>
     where
@@ -248,18 +302,28 @@ impl<'a, T: ComplexField, K, S> Dynamic<T, K, S> {
         let input = self.searcher.input();
 
         let output = { input.par_iter() }
-            .map(|point| {
-                let mut result = Vec::new();
+            .map(|point| convolve_one(&self.kernel, &self.searcher, &self.radius, point))
+            .collect::<Vec<_>>();
 
-                self.searcher.search(
-                    point.coords(),
-                    SearchType::Radius(self.radius.clone()),
-                    &mut result,
-                );
+        PointCloud::from_vec(output, input.width())
+    }
 
-                self.kernel.convolve(
-                    { result.into_iter() }.map(|(index, distance)| (&input[index], distance)),
-                )
+    /// Like [`Dynamic::convolve_par`], but every worker thread convolves
+    /// with its own kernel created by `init`, so the kernel may keep mutable
+    /// state or be not `Sync` at all, and `self.kernel` is not used.
+    pub fn convolve_par_with<P, K2, F>(&self, init: F) -> PointCloud<P>
+    where
+        P: Sync + Send + Point<Data = T> + 'a,
+        K2: DynamicKernel<'a, P>,
+        F: Fn() -> K2 + Sync + Send,
+        S: Sync + Search<'a, P>,
+    {
+        let (searcher, radius) = (&self.searcher, &self.radius);
+        let input = searcher.input();
+
+        let output = { input.par_iter() }
+            .map_init(init, |kernel, point| {
+                convolve_one(kernel, searcher, radius, point)
             })
             .collect::<Vec<_>>();
 
@@ -275,21 +339,161 @@ impl<'a, T: ComplexField, K, S> Dynamic<T, K, S> {
         let input = self.searcher.input();
 
         let output = { input.iter() }
-            .map(|point| {
-                let mut result = Vec::new();
+            .map(|point| convolve_one(&self.kernel, &self.searcher, &self.radius, point))
+            .collect::<Vec<_>>();
 
-                self.searcher.search(
-                    point.coords(),
-                    SearchType::Radius(self.radius.clone()),
-                    &mut result,
-                );
+        PointCloud::from_vec(output, input.width())
+    }
 
-                self.kernel.convolve(
-                    { result.into_iter() }.map(|(index, distance)| (&input[index], distance)),
+    /// Convolves with the precomputed `neighbors` of every point of the
+    /// input instead of searching within `self.radius`, e.g. to share the
+    /// results of one search among several kernels.
+    ///
+    /// The neighbors are in the form of the results of [`Search::search`].
+    pub fn convolve_neighbors<P>(&self, neighbors: &[Vec<(usize, T)>]) -> PointCloud<P>
+    where
+        P: Point<Data = T> + 'a,
+        K: DynamicKernel<'a, P>,
+        S: Search<'a, P>,
+    {
+        let input = self.searcher.input();
+        assert_eq!(input.len(), neighbors.len());
+
+        let output = { input.iter().zip(neighbors) }
+            .map(|(point, neighbors)| {
+                self.kernel.convolve_around(
+                    point,
+                    { neighbors.iter() }
+                        .map(|(index, distance)| (&input[*index], distance.clone())),
                 )
             })
             .collect::<Vec<_>>();
 
         PointCloud::from_vec(output, input.width())
     }
+
+    pub fn convolve_neighbors_par<P>(&self, neighbors: &[Vec<(usize, T)>]) -> PointCloud<P>
+    where
+        P: Sync + Send + Point<Data = T> + 'a,
+        K: Sync + DynamicKernel<'a, P>,
+        S: Sync + Search<'a, P>,
+    {
+        let input = self.searcher.input();
+        assert_eq!(input.len(), neighbors.len());
+
+        let output = { input.par_iter().zip(neighbors) }
+            .map(|(point, neighbors)| {
+                self.kernel.convolve_around(
+                    point,
+                    { neighbors.iter() }
+                        .map(|(index, distance)| (&input[*index], distance.clone())),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        PointCloud::from_vec(output, input.width())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{dvector, Vector3};
+    use pcc_common::point::Point3;
+    use pcc_search::KdTree;
+
+    use super::*;
+
+    fn row(xs: &[f32]) -> PointCloud<Point3> {
+        let storage = { xs.iter() }
+            .map(|&x| Point3::default().with_coords(Vector4::new(x, 0., 0., 1.)))
+            .collect::<Vec<_>>();
+        PointCloud::from_vec(storage, xs.len())
+    }
+
+    fn xs(point_cloud: &PointCloud<Point3>) -> Vec<f32> {
+        point_cloud.iter().map(|point| point.coords().x).collect()
+    }
+
+    #[test]
+    fn test_fixed2() {
+        let input = row(&[0., 1., f32::NAN, 3., 4.]);
+        let zero = Fixed2::new(dvector![1., 1., 1.], BorderOptions::Zero);
+        let output = xs(&zero.convolve_rows(&input));
+        let expected = [1. / 3., 0.5, f32::NAN, 3.5, 7. / 3.];
+        for (x, expected) in output.iter().zip(expected) {
+            assert!((x - expected).abs() < 1e-6 || (x.is_nan() && expected.is_nan()));
+        }
+
+        let input = row(&[0., 1., 2., 3., 4.]);
+        let repeated = Fixed2::new(dvector![1., 1., 1.], BorderOptions::Repeated);
+        assert_eq!(xs(&repeated.convolve_rows(&input)), [1., 1., 2., 3., 3.]);
+        let mirrored = Fixed2 {
+            border_options: BorderOptions::Mirrored,
+            ..repeated
+        };
+        assert_eq!(xs(&mirrored.convolve_rows(&input)), [1., 1., 2., 3., 3.]);
+
+        // The weights of the finite neighbors sum up to zero.
+        let mut difference = Fixed2::new(dvector![1., 0., -1.], BorderOptions::Default);
+        assert!(xs(&difference.convolve_rows(&input))[2].is_nan());
+        difference.zero_weight = ZeroWeight::Keep;
+        assert_eq!(xs(&difference.convolve_rows(&input))[2], 2.);
+        difference.zero_weight = ZeroWeight::Sum;
+        assert_eq!(xs(&difference.convolve_rows(&input))[2], 2.);
+    }
+
+    /// Gives the `x` of the center, the number of the neighbors and the sum of
+    /// their `x`.
+    struct Count;
+
+    impl<'a> DynamicKernel<'a, Point3> for Count {
+        fn convolve<Iter>(&self, _: Iter) -> Point3
+        where
+            Iter: IntoIterator<Item = (&'a Point3, f32)>,
+        {
+            unreachable!()
+        }
+
+        fn convolve_around<Iter>(&self, point: &'a Point3, data: Iter) -> Point3
+        where
+            Iter: IntoIterator<Item = (&'a Point3, f32)>,
+        {
+            let (count, sum) = { data.into_iter() }.fold((0., 0.), |(count, sum), (p, _)| {
+                (count + 1., sum + p.coords().x)
+            });
+            Point3::default().with_coords(Vector4::new(point.coords().x, count, sum, 1.))
+        }
+    }
+
+    #[test]
+    fn test_dynamic() {
+        let input = row(&[0., 1., 2., 4., 8.]);
+        let tree = KdTree::new(&input);
+        let dynamic = Dynamic::new(Count, tree, 1.5);
+
+        let output = dynamic.convolve();
+        let expected = [
+            [0., 2., 1.],
+            [1., 3., 3.],
+            [2., 2., 3.],
+            [4., 1., 4.],
+            [8., 1., 8.],
+        ];
+        for (point, expected) in output.iter().zip(expected) {
+            assert_eq!(point.coords().xyz(), Vector3::from(expected));
+        }
+        assert_eq!(dynamic.convolve_par_with(|| Count), output);
+        assert_eq!(dynamic.convolve_par(), output);
+
+        let neighbors = { input.iter() }
+            .map(|point| {
+                let mut result = Vec::new();
+                let radius = SearchType::Radius(1.5);
+                dynamic.searcher.search(point.coords(), radius, &mut result);
+                result
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(dynamic.convolve_neighbors(&neighbors), output);
+        assert_eq!(dynamic.convolve_neighbors_par(&neighbors), output);
+    }
 }
@@ -1,7 +1,14 @@
-use nalgebra::{convert, RealField, Scalar, Vector4};
-use pcc_common::point::{Point, PointRgba};
+use std::fmt::Debug;
 
-use super::DynamicKernel;
+use nalgebra::{convert, ComplexField, DVector, RealField, Scalar, Vector4};
+use num::ToPrimitive;
+use pcc_common::{
+    filter::ApproxFilter,
+    point::{Point, PointRgba},
+    point_cloud::PointCloud,
+};
+
+use super::{BorderOptions, DynamicKernel, Fixed2, ZeroWeight};
 
 pub struct Gauss<T: Scalar> {
     pub stddev: T,
@@ -88,3 +95,152 @@ impl<'a, T: RealField, P: PointRgba<Data = T> + 'a> DynamicKernel<'a, P> for Gau
             .with_rgba_array(&rgba)
     }
 }
+
+/// The taps of a Gaussian of `stddev` for the offsets within `stddev *
+/// stddev_mul`, from `-radius` to `radius`.
+fn gaussian_taps<T: RealField + ToPrimitive>(stddev: T, stddev_mul: T) -> Vec<(T, T)> {
+    let radius = { (stddev.clone() * stddev_mul).ceil() }
+        .to_usize()
+        .unwrap_or(0);
+    if stddev <= T::zero() {
+        return vec![(T::zero(), T::one())];
+    }
+
+    let var = stddev.clone() * stddev;
+    { (0..=2 * radius).map(|index| convert::<_, T>(index as f64 - radius as f64)) }
+        .map(|x| {
+            (
+                x.clone(),
+                (-x.clone() * x / var.clone() / convert(2.)).exp(),
+            )
+        })
+        .collect()
+}
+
+/// Builds a 1-D Gaussian kernel for [`Fixed2`], truncated at `stddev *
+/// stddev_mul` and normalized to a sum of one.
+pub fn gaussian_kernel<T: RealField + ToPrimitive>(stddev: T, stddev_mul: T) -> DVector<T> {
+    let taps = gaussian_taps(stddev, stddev_mul);
+    let sum = taps.iter().fold(T::zero(), |sum, (_, w)| sum + w.clone());
+    DVector::from_iterator(taps.len(), taps.into_iter().map(|(_, w)| w / sum.clone()))
+}
+
+/// Builds a 1-D derivative-of-Gaussian kernel for [`Fixed2`], truncated at
+/// `stddev * stddev_mul`.
+///
+/// The taps sum up to zero and are scaled so that a ramp of unit slope along
+/// the indices gives one, so the kernel is meant to be used without
+/// normalization.
+pub fn gaussian_derivative_kernel<T: RealField + ToPrimitive>(
+    stddev: T,
+    stddev_mul: T,
+) -> DVector<T> {
+    let taps = gaussian_taps(stddev, stddev_mul);
+    let scale = { taps.iter() }.fold(T::zero(), |sum, (x, w)| {
+        sum + x.clone() * x.clone() * w.clone()
+    });
+    if scale == T::zero() {
+        return DVector::zeros(taps.len());
+    }
+
+    // `Fixed2` flips the kernel, so the tap of offset `x` goes to `-x`.
+    DVector::from_iterator(
+        taps.len(),
+        { taps.into_iter().rev() }.map(|(x, w)| x * w / scale.clone()),
+    )
+}
+
+impl<T: RealField + ToPrimitive> Fixed2<T> {
+    pub fn gaussian(stddev: T, stddev_mul: T, border_options: BorderOptions) -> Self {
+        Fixed2::new(gaussian_kernel(stddev, stddev_mul), border_options)
+    }
+
+    /// The convolution gives the derivatives of the coordinates along the
+    /// indices, so the weights are not normalized.
+    pub fn gaussian_derivative(stddev: T, stddev_mul: T, border_options: BorderOptions) -> Self {
+        Fixed2 {
+            normalize: false,
+            zero_weight: ZeroWeight::Sum,
+            ..Fixed2::new(
+                gaussian_derivative_kernel(stddev, stddev_mul),
+                border_options,
+            )
+        }
+    }
+}
+
+/// Smooths organized point clouds with a separable Gaussian of `stddev`
+/// along both the rows and the columns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GaussianSmooth2<T: Scalar> {
+    pub inner: Fixed2<T>,
+}
+
+impl<T: RealField + ToPrimitive> GaussianSmooth2<T> {
+    pub fn new(stddev: T, stddev_mul: T, border_options: BorderOptions) -> Self {
+        GaussianSmooth2 {
+            inner: Fixed2::gaussian(stddev, stddev_mul, border_options),
+        }
+    }
+}
+
+impl<T, P> ApproxFilter<PointCloud<P>> for GaussianSmooth2<T>
+where
+    T: ComplexField,
+    P: Point<Data = T> + Clone + Debug,
+{
+    fn filter(&mut self, input: &PointCloud<P>) -> PointCloud<P> {
+        self.inner.convolve(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pcc_common::point::Point3;
+
+    use super::*;
+
+    #[test]
+    fn test_gaussian_kernels() {
+        let kernel = gaussian_kernel(1f32, 3.);
+        assert_eq!(kernel.len(), 7);
+        assert!((kernel.sum() - 1.).abs() < 1e-6);
+        assert_eq!(kernel.imax(), 3);
+        assert!((kernel[0] - kernel[6]).abs() < 1e-9);
+        assert_eq!(gaussian_kernel(0f32, 3.).as_slice(), [1.]);
+
+        // The derivative of a ramp of the slope 2 along the indices.
+        let storage = { (0..20).map(|x| Vector4::new(x as f32 * 2., 1., 0., 1.)) }
+            .map(|coords| Point3::default().with_coords(coords))
+            .collect::<Vec<_>>();
+        let ramp = PointCloud::from_vec(storage, 20);
+        let derivative = Fixed2::gaussian_derivative(1., 3., BorderOptions::Default);
+        let output = derivative.convolve_rows(&ramp);
+        for point in output.iter().skip(3).take(14) {
+            let [[x, y, _, _]] = point.coords().data.0;
+            assert!((x - 2.).abs() < 1e-4 && y.abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_gaussian_smooth2() {
+        // A tilted plane, which the smoothing keeps away from the borders.
+        let storage = { (0..8).flat_map(|y| (0..8).map(move |x| (x, y))) }
+            .map(|(x, y)| {
+                let (x, y) = (x as f32 * 0.1, y as f32 * 0.1);
+                Point3::default().with_coords(Vector4::new(x, y, x + y, 1.))
+            })
+            .collect::<Vec<_>>();
+        let input = PointCloud::from_vec(storage, 8);
+
+        let mut smooth = GaussianSmooth2::new(0.5, 2., BorderOptions::Repeated);
+        let output = smooth.filter(&input);
+        assert_eq!((output.width(), output.len()), (8, 64));
+        for (x, y) in (1..7).flat_map(|y| (1..7).map(move |x| (x, y))) {
+            let (a, b) = (&input[(x, y)], &output[(x, y)]);
+            assert!((a.coords() - b.coords()).norm() < 1e-5);
+        }
+        // The borders repeat their convolved neighbors.
+        assert_eq!(output[(0, 3)].coords(), output[(1, 3)].coords());
+    }
+}
@@ -1,94 +1,110 @@
-use nalgebra::{convert, RealField, Rotation3, Scalar, Vector4};
+use nalgebra::{
+    convert, Affine3, Isometry3, Matrix2x3, Point3, RealField, Rotation3, Scalar, Translation3,
+    UnitQuaternion, Vector2, Vector3, Vector4,
+};
 use pcc_common::{
     filter::{ApproxFilter, Filter},
+    geometry::Obb,
     point::Point,
     point_cloud::PointCloud,
 };
 use pcc_sac::Plane;
 
+/// Keeps the points inside (or outside if `negative`) a box, which spans from
+/// `min` to `max` in its local frame and is placed by `pose`.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct CropBox<T: RealField> {
     pub min: Vector4<T>,
     pub max: Vector4<T>,
-    pub rotation: Rotation3<T>,
+    /// The transformation from the local frame of the box to the frame of the
+    /// point cloud. It must be invertible.
+    pub pose: Affine3<T>,
     pub negative: bool,
 }
 
 impl<T: RealField> CropBox<T> {
+    /// Creates a box rotated by `rotation` around its center, as the box was
+    /// placed before it had a pose.
     pub fn new(
         min: Vector4<T>,
         max: Vector4<T>,
         rotation: Rotation3<T>,
         negative: bool,
+    ) -> CropBox<T> {
+        let center = (&min + &max).unscale(convert(2.)).xyz();
+        let pose = Translation3::from(center.clone()) * rotation * Translation3::from(-center);
+        let pose = Affine3::from_matrix_unchecked(pose.to_homogeneous());
+        CropBox::with_pose(min, max, pose, negative)
+    }
+
+    /// Creates a box placed by an arbitrary affine `pose`.
+    pub fn with_pose(
+        min: Vector4<T>,
+        max: Vector4<T>,
+        pose: Affine3<T>,
+        negative: bool,
     ) -> CropBox<T> {
         CropBox {
             min,
             max,
-            rotation,
+            pose,
             negative,
         }
     }
+
+    /// Creates a box placed by a rigid `pose`.
+    pub fn with_isometry(
+        min: Vector4<T>,
+        max: Vector4<T>,
+        pose: Isometry3<T>,
+        negative: bool,
+    ) -> CropBox<T> {
+        let pose = Affine3::from_matrix_unchecked(pose.to_homogeneous());
+        CropBox::with_pose(min, max, pose, negative)
+    }
+
+    /// Creates a box from an oriented bounding box.
+    pub fn from_obb(obb: &Obb<T>, negative: bool) -> CropBox<T> {
+        let half = obb.half_extents.clone().insert_row(3, T::zero());
+        let pose = Isometry3::from_parts(
+            Translation3::from(obb.center.xyz()),
+            UnitQuaternion::from_rotation_matrix(&obb.rotation),
+        );
+        CropBox::with_isometry(-half.clone(), half, pose, negative)
+    }
+
+    #[inline]
+    fn inner<P: Point<Data = T>>(&self) -> impl FnMut(&P) -> bool + '_ {
+        let inverse = self.pose.clone().inverse();
+        let (min, max) = (self.min.xyz(), self.max.xyz());
+        move |point| {
+            let local = inverse.transform_point(&Point3::from(point.coords().xyz()));
+            (min <= local.coords && local.coords <= max) ^ self.negative
+        }
+    }
 }
 
 impl<T: RealField, P: Point<Data = T>> Filter<[P]> for CropBox<T> {
+    #[inline]
     fn filter_indices(&mut self, input: &[P]) -> Vec<usize> {
-        let center = (&self.min + &self.max).unscale(convert(2.)).xyz();
-
-        let mut indices = (0..input.len()).collect::<Vec<_>>();
-        indices.retain(|&index| {
-            let coords = &input[index].coords().xyz();
-            let delta = coords - &center;
-            let local_delta = self.rotation.inverse_transform_vector(&delta);
-            let local_coords = local_delta + &center;
-
-            (self.min.xyz() <= local_coords && local_coords <= self.max.xyz()) ^ self.negative
-        });
-        indices
+        self.inner().filter_indices(input)
     }
 
+    #[inline]
     fn filter_all_indices(&mut self, input: &[P]) -> (Vec<usize>, Vec<usize>) {
-        let center = (&self.min + &self.max).unscale(convert(2.)).xyz();
-
-        let mut indices = (0..input.len()).collect::<Vec<_>>();
-        let mut removed = Vec::with_capacity(indices.len());
-        indices.retain(|&index| {
-            let coords = &input[index].coords().xyz();
-            let delta = coords - &center;
-            let local_delta = self.rotation.inverse_transform_vector(&delta);
-            let local_coords = local_delta + &center;
-
-            let ret =
-                (self.min.xyz() <= local_coords && local_coords <= self.max.xyz()) ^ self.negative;
-            if ret {
-                removed.push(index);
-            }
-            ret
-        });
-        (indices, removed)
+        self.inner().filter_all_indices(input)
     }
 }
 
 impl<T: RealField, P: Point<Data = T>> ApproxFilter<PointCloud<P>> for CropBox<T> {
     #[inline]
     fn filter(&mut self, input: &PointCloud<P>) -> PointCloud<P> {
-        let mut new = input.clone();
-        self.filter_mut(&mut new);
-        new
+        self.inner().filter(input)
     }
 
+    #[inline]
     fn filter_mut(&mut self, obj: &mut PointCloud<P>) {
-        let center = (&self.min + &self.max).unscale(convert(2.)).xyz();
-
-        let storage = unsafe { obj.storage() };
-        storage.retain(|point| {
-            let coords = &point.coords().xyz();
-            let delta = coords - &center;
-            let local_delta = self.rotation.inverse_transform_vector(&delta);
-            let local_coords = local_delta + &center;
-
-            (self.min.xyz() <= local_coords && local_coords <= self.max.xyz()) ^ self.negative
-        });
-        obj.reinterpret(1)
+        self.inner().filter_mut(obj)
     }
 }
 
@@ -127,3 +143,201 @@ impl<T: RealField, P: Point<Data = T>> ApproxFilter<PointCloud<P>> for CropPlane
         self.inner().filter_mut(obj)
     }
 }
+
+/// Keeps the points inside (or outside if `negative`) a prism, whose base is
+/// a planar polygon, e.g. the convex hull of a table top, and which spans
+/// from `min_height` to `max_height` along the normal of the polygon.
+///
+/// The normal of the polygon follows the right-hand rule of the order of the
+/// vertices, i.e. it points towards the viewer that sees them in
+/// counter-clockwise order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractPolygonalPrismData<T: Scalar> {
+    pub polygon: Vec<Vector4<T>>,
+    pub min_height: T,
+    pub max_height: T,
+    pub negative: bool,
+}
+
+impl<T: RealField> ExtractPolygonalPrismData<T> {
+    pub fn new(polygon: Vec<Vector4<T>>, min_height: T, max_height: T, negative: bool) -> Self {
+        ExtractPolygonalPrismData {
+            polygon,
+            min_height,
+            max_height,
+            negative,
+        }
+    }
+
+    /// The plane of the polygon with the unit normal computed with Newell's
+    /// method, or `None` if the polygon is degenerate.
+    pub fn plane(&self) -> Option<Plane<T>> {
+        let len = self.polygon.len();
+        let normal = (0..len).fold(Vector3::zeros(), |acc, i| {
+            let (a, b) = (&self.polygon[i], &self.polygon[(i + 1) % len]);
+            acc + Vector3::new(
+                (a.y.clone() - b.y.clone()) * (a.z.clone() + b.z.clone()),
+                (a.z.clone() - b.z.clone()) * (a.x.clone() + b.x.clone()),
+                (a.x.clone() - b.x.clone()) * (a.y.clone() + b.y.clone()),
+            )
+        });
+        let normal = normal.try_normalize(T::default_epsilon())?;
+        let center = { self.polygon.iter() }.fold(Vector4::zeros(), |acc, v| acc + v)
+            / T::from_usize(len).unwrap();
+
+        Some(Plane {
+            coords: center,
+            normal: normal.insert_row(3, T::zero()),
+        })
+    }
+
+    /// The plane of the polygon, the basis of the plane, and the polygon in
+    /// the basis.
+    fn frame(&self) -> Option<(Plane<T>, Matrix2x3<T>, Vec<Vector2<T>>)> {
+        let plane = self.plane()?;
+        let normal = plane.normal.xyz();
+        let axis = if normal.x.clone().abs() < convert(0.9) {
+            Vector3::x()
+        } else {
+            Vector3::y()
+        };
+        let u = normal.cross(&axis).normalize();
+        let v = normal.cross(&u);
+        let basis = Matrix2x3::from_rows(&[u.transpose(), v.transpose()]);
+
+        let polygon = { self.polygon.iter() }
+            .map(|vertex| &basis * vertex.xyz())
+            .collect();
+        Some((plane, basis, polygon))
+    }
+
+    #[inline]
+    fn inner<P: Point<Data = T>>(&self) -> impl FnMut(&P) -> bool + '_ {
+        let frame = self.frame();
+        move |point| {
+            // Degenerate polygons contain no points.
+            let inside = frame.as_ref().map_or(false, |(plane, basis, polygon)| {
+                let height = plane.distance_directed(point.coords());
+                let coords = basis * point.coords().xyz();
+                self.min_height <= height && height <= self.max_height && contains(polygon, &coords)
+            });
+            inside ^ self.negative
+        }
+    }
+}
+
+/// Checks if `point` is inside `polygon` with the crossing number test.
+fn contains<T: RealField>(polygon: &[Vector2<T>], point: &Vector2<T>) -> bool {
+    let mut inside = false;
+    for (i, a) in polygon.iter().enumerate() {
+        let b = &polygon[(i + polygon.len() - 1) % polygon.len()];
+        if (a.y > point.y) != (b.y > point.y) {
+            let x = (b.x.clone() - a.x.clone()) * (point.y.clone() - a.y.clone())
+                / (b.y.clone() - a.y.clone())
+                + a.x.clone();
+            if point.x < x {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+impl<T: RealField, P: Point<Data = T>> Filter<[P]> for ExtractPolygonalPrismData<T> {
+    #[inline]
+    fn filter_indices(&mut self, input: &[P]) -> Vec<usize> {
+        self.inner().filter_indices(input)
+    }
+
+    #[inline]
+    fn filter_all_indices(&mut self, input: &[P]) -> (Vec<usize>, Vec<usize>) {
+        self.inner().filter_all_indices(input)
+    }
+}
+
+impl<T: RealField, P: Point<Data = T>> ApproxFilter<PointCloud<P>>
+    for ExtractPolygonalPrismData<T>
+{
+    #[inline]
+    fn filter(&mut self, input: &PointCloud<P>) -> PointCloud<P> {
+        self.inner().filter(input)
+    }
+
+    #[inline]
+    fn filter_mut(&mut self, obj: &mut PointCloud<P>) {
+        self.inner().filter_mut(obj)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use pcc_common::point::Point3;
+
+    use super::*;
+
+    fn points(coords: &[[f32; 3]]) -> Vec<Point3> {
+        { coords.iter() }
+            .map(|&[x, y, z]| Point3::default().with_coords(Vector4::new(x, y, z, 1.)))
+            .collect()
+    }
+
+    #[test]
+    fn test_crop_box() {
+        let input = points(&[[1., 1.2, 0.5], [1.8, 0.5, 0.5], [1., 0.5, 0.5]]);
+        let (min, max) = (Vector4::new(0., 0., 0., 1.), Vector4::new(2., 1., 1., 1.));
+
+        let mut unrotated = CropBox::new(min, max, Rotation3::identity(), false);
+        assert_eq!(unrotated.filter_indices(&input), [1, 2]);
+
+        // Rotated around the center at (1, 0.5, 0.5), the box spans from 0.5
+        // to 1.5 along x and from -0.5 to 1.5 along y.
+        let rotation = Rotation3::from_axis_angle(&Vector3::z_axis(), FRAC_PI_2);
+        let mut rotated = CropBox::new(min, max, rotation, false);
+        assert_eq!(rotated.filter_indices(&input), [0, 2]);
+
+        let pose = Isometry3::translation(10., 0., 0.);
+        let mut moved = CropBox::with_isometry(min, max, pose, true);
+        assert_eq!(moved.filter_indices(&input), [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_polygonal_prism() {
+        let vertex = |x, y| Vector4::new(x, y, 0., 1.);
+        // An L-shaped polygon with its notch at (1..2, 1..2).
+        let polygon = vec![
+            vertex(0., 0.),
+            vertex(2., 0.),
+            vertex(2., 1.),
+            vertex(1., 1.),
+            vertex(1., 2.),
+            vertex(0., 2.),
+        ];
+        let input = points(&[
+            [0.5, 0.5, 0.5],
+            [1.5, 1.5, 0.5],
+            [0.5, 1.5, 0.5],
+            [0.5, 0.5, 1.5],
+            [0.5, 0.5, -0.5],
+            [3., 0.5, 0.5],
+        ]);
+
+        let mut prism = ExtractPolygonalPrismData::new(polygon.clone(), 0., 1., false);
+        let plane = prism.plane().unwrap();
+        assert!((plane.normal - Vector4::z()).norm() < 1e-6);
+        assert_eq!(prism.filter_indices(&input), [0, 2]);
+        let (inliers, outliers) = prism.filter_all_indices(&input);
+        assert_eq!(inliers, [0, 2]);
+        assert_eq!(outliers, [1, 3, 4, 5]);
+
+        // The heights are measured along the normal, below the polygon here.
+        let mut below = ExtractPolygonalPrismData::new(polygon.clone(), -1., 0., true);
+        assert_eq!(below.filter_indices(&input), [0, 1, 2, 3, 5]);
+
+        let line = vec![vertex(0., 0.), vertex(1., 0.), vertex(2., 0.)];
+        let mut degenerate = ExtractPolygonalPrismData::new(line, -1., 1., false);
+        assert!(degenerate.plane().is_none());
+        assert!(degenerate.filter_indices(&input).is_empty());
+    }
+}
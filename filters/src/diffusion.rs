@@ -0,0 +1,130 @@
+use std::{error::Error, fmt};
+
+use nalgebra::RealField;
+use pcc_common::{
+    point::{DataFields, Point},
+    point_cloud::PointCloud,
+};
+use pcc_search::{build_knn_graph, KnnGraph};
+
+/// Smooths a per-point field, e.g. the intensity, with Laplacian diffusion
+/// over the kNN graph of the points. See [`KnnGraph::diffuse`] for details.
+///
+/// NOTE: This filter doesn't modify point coordinates unless `field` names
+/// them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diffusion<T> {
+    /// The name of the field in [`DataFields::fields`].
+    pub field: String,
+    pub k: usize,
+    pub lambda: T,
+    pub iterations: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UnknownFieldError {
+    /// The name of the field missing in [`DataFields::fields`].
+    pub field: String,
+}
+
+impl fmt::Display for UnknownFieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the point type has no field named {:?}", self.field)
+    }
+}
+
+impl Error for UnknownFieldError {}
+
+impl<T> Diffusion<T> {
+    pub fn new(field: impl Into<String>, k: usize, lambda: T, iterations: usize) -> Self {
+        Diffusion {
+            field: field.into(),
+            k,
+            lambda,
+            iterations,
+        }
+    }
+}
+
+impl<T: RealField> Diffusion<T> {
+    /// Diffuses the field of `input` over its kNN graph, or returns an error
+    /// if the points have no such field.
+    pub fn filter<P>(&self, input: &PointCloud<P>) -> Result<PointCloud<P>, UnknownFieldError>
+    where
+        P: Point<Data = T> + DataFields + Sync,
+    {
+        let graph = build_knn_graph(input, self.k).symmetrize();
+        self.filter_with(&graph, input)
+    }
+
+    /// Like [`Diffusion::filter`], but reuses the `graph` of `input`.
+    pub fn filter_with<P>(
+        &self,
+        graph: &KnnGraph<T>,
+        input: &PointCloud<P>,
+    ) -> Result<PointCloud<P>, UnknownFieldError>
+    where
+        P: Point<Data = T> + DataFields,
+    {
+        let field = match <P as DataFields>::fields().find(|field| field.name == self.field) {
+            Some(field) => field,
+            None => {
+                return Err(UnknownFieldError {
+                    field: self.field.clone(),
+                })
+            }
+        };
+
+        let mut output = input.clone();
+        for offset in field.offset..field.offset + field.len {
+            let mut values = { input.iter() }
+                .map(|point| point.as_slice()[offset].clone())
+                .collect::<Vec<_>>();
+            graph.diffuse(&mut values, self.lambda.clone(), self.iterations);
+
+            unsafe {
+                for (point, value) in output.storage().iter_mut().zip(values) {
+                    point.as_mut_slice()[offset] = value;
+                }
+            }
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::point::{Point3IR, PointIntensity};
+
+    use super::*;
+
+    #[test]
+    fn test_diffusion() {
+        let storage = { (0..10).map(|i| (i, if i == 5 { 1. } else { 0. })) }
+            .map(|(i, intensity)| {
+                Point3IR::default()
+                    .with_coords(Vector4::new(i as f32, 0., 0., 1.))
+                    .with_intensity(intensity)
+            })
+            .collect::<Vec<_>>();
+        let input = PointCloud::from_vec(storage, 1);
+
+        let output = Diffusion::new("intensity", 2, 0.5, 3)
+            .filter(&input)
+            .unwrap();
+        let intensity = output
+            .iter()
+            .map(|point| point.intensity())
+            .collect::<Vec<_>>();
+        assert!(intensity[5] < 1. && intensity[4] > 0. && intensity[6] > 0.);
+        assert_eq!(intensity[0], 0.);
+        // The coordinates are kept.
+        assert!({ input.iter().zip(output.iter()) }.all(|(a, b)| a.coords() == b.coords()));
+
+        let error = Diffusion::new("normal", 2, 0.5, 3)
+            .filter(&input)
+            .unwrap_err();
+        assert_eq!(error.field, "normal");
+    }
+}
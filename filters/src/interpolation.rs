@@ -0,0 +1,267 @@
+use nalgebra::{convert, DMatrix, DVector, RealField, Scalar, Vector4};
+use pcc_common::{
+    point::Point,
+    point_cloud::PointCloud,
+    search::{Search, SearchType},
+};
+
+/// Inverse distance weighting of the `samples` of distances and values,
+/// weighted by the distances to the power of `-power`.
+///
+/// Returns the value of the first coincident sample if any, or `None` if
+/// there are no samples.
+pub(crate) fn idw<T: RealField>(samples: impl IntoIterator<Item = (T, T)>, power: &T) -> Option<T> {
+    let (mut sum, mut weights) = (T::zero(), T::zero());
+    for (distance, value) in samples {
+        if distance <= T::default_epsilon() {
+            return Some(value);
+        }
+        let weight = distance.powf(-power.clone());
+        sum += value * weight.clone();
+        weights += weight;
+    }
+    (weights > T::zero()).then(|| sum / weights)
+}
+
+/// The methods of [`ScatteredInterpolation`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InterpolationMethod<T> {
+    /// Inverse distance weighting, weighted by the distances to the power of
+    /// `-power`.
+    Idw { power: T },
+    /// Simple kriging with the known `mean` of the field, and the exponential
+    /// covariance `sill * exp(-3 * h / range)` of the distance `h`, plus
+    /// `nugget` for coincident samples.
+    SimpleKriging {
+        mean: T,
+        sill: T,
+        range: T,
+        nugget: T,
+    },
+}
+
+/// Interpolates scalar fields sampled at the points of a point cloud, i.e.
+/// the `values` of the points of a searcher, at arbitrary positions from the
+/// neighbors found by `search`.
+///
+/// The points that are non-finite or have non-finite values are ignored, and
+/// the positions without such neighbors get NaN.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ScatteredInterpolation<T: Scalar> {
+    pub search: SearchType<T>,
+    pub method: InterpolationMethod<T>,
+}
+
+impl<T: Scalar> ScatteredInterpolation<T> {
+    pub fn new(search: SearchType<T>, method: InterpolationMethod<T>) -> Self {
+        ScatteredInterpolation { search, method }
+    }
+}
+
+impl<T: RealField> ScatteredInterpolation<T> {
+    fn kriging(
+        samples: &[(Vector4<T>, T)],
+        pivot: &Vector4<T>,
+        [mean, sill, range, nugget]: [&T; 4],
+    ) -> Option<T> {
+        let covariance = |a: &Vector4<T>, b: &Vector4<T>| {
+            let distance = (a - b).xyz().norm();
+            sill.clone() * (convert::<_, T>(-3.) * distance / range.clone()).exp()
+        };
+
+        let num = samples.len();
+        let matrix = DMatrix::from_fn(num, num, |i, j| {
+            let c = covariance(&samples[i].0, &samples[j].0);
+            if i == j {
+                c + nugget.clone()
+            } else {
+                c
+            }
+        });
+        let rhs = DVector::from_fn(num, |i, _| covariance(&samples[i].0, pivot));
+        let weights = match matrix.clone().cholesky() {
+            Some(cholesky) => cholesky.solve(&rhs),
+            None => matrix.lu().solve(&rhs)?,
+        };
+
+        let residual = { weights.iter().zip(samples) }.fold(T::zero(), |acc, (w, (_, v))| {
+            acc + w.clone() * (v.clone() - mean.clone())
+        });
+        Some(mean.clone() + residual)
+    }
+
+    /// Interpolates the field at `pivot`, or returns NaN if it can't.
+    pub fn interpolate<'a, P, S>(&self, searcher: &S, values: &[T], pivot: &Vector4<T>) -> T
+    where
+        P: Point<Data = T> + 'a,
+        S: Search<'a, P> + ?Sized,
+    {
+        let input = searcher.input();
+        let mut samples = Vec::new();
+        searcher.search_with(pivot, self.search.clone(), &mut |index, _| {
+            let value = &values[index];
+            if input[index].is_finite() && value.is_finite() {
+                samples.push((input[index].coords().clone(), value.clone()));
+            }
+        });
+        if samples.is_empty() {
+            return convert(f64::NAN);
+        }
+
+        match &self.method {
+            InterpolationMethod::Idw { power } => {
+                let samples = { samples.into_iter() }
+                    .map(|(coords, value)| ((coords - pivot).xyz().norm(), value));
+                idw(samples, power)
+            }
+            InterpolationMethod::SimpleKriging {
+                mean,
+                sill,
+                range,
+                nugget,
+            } => Self::kriging(&samples, pivot, [mean, sill, range, nugget]),
+        }
+        .unwrap_or_else(|| convert(f64::NAN))
+    }
+
+    /// Resamples the field onto the points of `target`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the input of `searcher` and `values` have different lengths.
+    pub fn resample<'a, P, S, Q>(
+        &self,
+        searcher: &S,
+        values: &[T],
+        target: &PointCloud<Q>,
+    ) -> Vec<T>
+    where
+        P: Point<Data = T> + 'a,
+        S: Search<'a, P> + ?Sized,
+        Q: Point<Data = T>,
+    {
+        assert_eq!(
+            searcher.input().len(),
+            values.len(),
+            "Every point must have a value"
+        );
+        { target.iter() }
+            .map(|point| {
+                if point.is_finite() {
+                    self.interpolate(searcher, values, point.coords())
+                } else {
+                    convert(f64::NAN)
+                }
+            })
+            .collect()
+    }
+
+    /// Resamples the field onto a regular grid of `size` cells, whose cell
+    /// `[x, y, z]` is at `origin + step * [x, y, z]`. The result is in the
+    /// order of `x`, `y` and then `z` from the fastest.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the input of `searcher` and `values` have different lengths.
+    pub fn resample_grid<'a, P, S>(
+        &self,
+        searcher: &S,
+        values: &[T],
+        origin: &Vector4<T>,
+        step: &Vector4<T>,
+        size: [usize; 3],
+    ) -> Vec<T>
+    where
+        P: Point<Data = T> + 'a,
+        S: Search<'a, P> + ?Sized,
+    {
+        assert_eq!(
+            searcher.input().len(),
+            values.len(),
+            "Every point must have a value"
+        );
+        let [sx, sy, sz] = size;
+        let mut ret = Vec::with_capacity(sx * sy * sz);
+        for z in 0..sz {
+            for y in 0..sy {
+                for x in 0..sx {
+                    let cell = Vector4::new(x, y, z, 0).map(|x| T::from_usize(x).unwrap());
+                    let pivot = origin + step.component_mul(&cell);
+                    ret.push(self.interpolate(searcher, values, &pivot));
+                }
+            }
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pcc_common::point::Point3;
+    use pcc_search::KdTree;
+
+    use super::*;
+
+    fn points(xs: &[f32]) -> PointCloud<Point3> {
+        let storage = { xs.iter() }
+            .map(|&x| Point3::default().with_coords(Vector4::new(x, 0., 0., 1.)))
+            .collect::<Vec<_>>();
+        PointCloud::from_vec(storage, 1)
+    }
+
+    #[test]
+    fn test_idw() {
+        let input = points(&[0., 1., 2.]);
+        let tree = KdTree::new(&input);
+        let values = [0., 1., 4.];
+        let pivot = |x: f32| Vector4::new(x, 0., 0., 1.);
+
+        let idw =
+            ScatteredInterpolation::new(SearchType::Knn(2), InterpolationMethod::Idw { power: 1. });
+        assert_eq!(idw.interpolate(&tree, &values, &pivot(1.)), 1.);
+        assert!((idw.interpolate(&tree, &values, &pivot(0.5)) - 0.5).abs() < 1e-6);
+        // Weighted 4 and 4/3 by the distances 0.25 and 0.75.
+        assert!((idw.interpolate(&tree, &values, &pivot(0.25)) - 0.25).abs() < 1e-6);
+
+        // The samples with non-finite values are ignored.
+        let holed = [0., f32::NAN, 4.];
+        assert_eq!(idw.interpolate(&tree, &holed, &pivot(0.5)), 0.);
+
+        let target = points(&[2., f32::NAN]);
+        let resampled = idw.resample(&tree, &values, &target);
+        assert_eq!(resampled[0], 4.);
+        assert!(resampled[1].is_nan());
+
+        let grid = idw.resample_grid(&tree, &values, &pivot(0.), &Vector4::x(), [3, 1, 1]);
+        assert_eq!(grid, [0., 1., 4.]);
+
+        let near = ScatteredInterpolation::new(
+            SearchType::Radius(0.1),
+            InterpolationMethod::Idw { power: 2. },
+        );
+        assert!(near.interpolate(&tree, &values, &pivot(0.5)).is_nan());
+    }
+
+    #[test]
+    fn test_kriging() {
+        let input = points(&[0., 1., 2.]);
+        let tree = KdTree::new(&input);
+        let values = [0., 1., 4.];
+
+        let kriging = ScatteredInterpolation::new(
+            SearchType::Knn(3),
+            InterpolationMethod::SimpleKriging {
+                mean: 0.,
+                sill: 1.,
+                range: 1.,
+                nugget: 0.,
+            },
+        );
+        // Without the nugget, kriging honors the samples.
+        let at = kriging.interpolate(&tree, &values, &Vector4::new(1., 0., 0., 1.));
+        assert!((at - 1.).abs() < 1e-4);
+        // Far from the samples, the estimate reverts to the mean.
+        let far = kriging.interpolate(&tree, &values, &Vector4::new(20., 0., 0., 1.));
+        assert!(far.abs() < 1e-4);
+    }
+}
@@ -1,28 +1,40 @@
-#![feature(map_try_insert)]
-
 mod bilateral;
+mod color;
 pub mod convolution;
 mod crop;
+mod diffusion;
 mod frustum;
 mod inlier_proj;
+mod interpolation;
 mod local_max;
 mod median;
+mod mesh_sampling;
+mod noise;
 mod outlier_removal;
 mod random;
 mod shadow_points;
+mod terrain;
 mod uniform_sa;
+mod virtual_scanner;
 mod voxel_grid;
 
 pub use self::{
     bilateral::Bilateral,
-    crop::{CropBox, CropPlane},
+    color::ColorFilter,
+    crop::{CropBox, CropPlane, ExtractPolygonalPrismData},
+    diffusion::{Diffusion, UnknownFieldError},
     frustum::FrustumCulling,
     inlier_proj::InlierProjection,
+    interpolation::{InterpolationMethod, ScatteredInterpolation},
     local_max::LocalMaximumZ,
     median::Median2,
-    outlier_removal::{RadiusOutlierRemoval, StatOutlierRemoval},
+    mesh_sampling::MeshSampling,
+    noise::{add_noise, GaussianNoise, KinectNoise, MotionBlur, NoiseModel, SaltAndPepper},
+    outlier_removal::{RadiusOutlierRemoval, StatOutlierRemoval, StreamingStatOutlierRemoval},
     random::Random,
     shadow_points::ShadowPoints,
+    terrain::TerrainModel,
     uniform_sa::UniformSampling,
-    voxel_grid::{GridMinimumZ, HashVoxelGrid, VoxelGrid},
+    virtual_scanner::{ScannerModel, SpinningLidar, VirtualScanner},
+    voxel_grid::{GridMinimumZ, HashVoxelGrid, VoxelGrid, VoxelMapping},
 };
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+
+use nalgebra::{convert, RealField, Vector3};
+use num::ToPrimitive;
+use pcc_common::{
+    geometry::Triangle,
+    point::{Point, PointNormal},
+    point_cloud::PointCloud,
+};
+use rand::{rngs::ThreadRng, RngCore};
+
+use crate::noise::uniform;
+
+/// The number of the candidates drawn for every sample in the Poisson-disk
+/// sampling.
+const POISSON_CANDIDATES: usize = 30;
+
+/// Samples point clouds from the surfaces of triangle meshes, e.g. to
+/// generate synthetic scans from CAD models for testing recognition and
+/// registration.
+///
+/// The samples are distributed uniformly over the areas of the triangles.
+/// If `min_distance` is set, the samples are further thinned to a
+/// Poisson-disk distribution by dart throwing, where no two samples are
+/// closer than `min_distance`, so fewer than `num` samples may be produced
+/// if the mesh is too small to hold them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MeshSampling<T, R: RngCore = ThreadRng> {
+    pub rng: R,
+    pub num: usize,
+    pub min_distance: Option<T>,
+}
+
+impl<T, R: RngCore> MeshSampling<T, R> {
+    pub fn new(rng: R, num: usize, min_distance: Option<T>) -> Self {
+        MeshSampling {
+            rng,
+            num,
+            min_distance,
+        }
+    }
+}
+
+impl<T: RealField + ToPrimitive, R: RngCore> MeshSampling<T, R> {
+    fn sample_uniform(&mut self, triangles: &[Triangle<T>], cdf: &[T]) -> (usize, Vector3<T>) {
+        let total = cdf.last().unwrap().clone();
+        let target = uniform::<T, _>(&mut self.rng) * total;
+        let index = cdf.partition_point(|x| *x <= target).min(cdf.len() - 1);
+
+        let [a, b, c] = &triangles[index].vertices;
+        let r1 = uniform::<T, _>(&mut self.rng).sqrt();
+        let r2 = uniform::<T, _>(&mut self.rng);
+        let coords = a * (T::one() - r1.clone())
+            + b * (r1.clone() * (T::one() - r2.clone()))
+            + c * (r1 * r2);
+        (index, coords)
+    }
+
+    fn sample_inner<P: Point<Data = T>>(
+        &mut self,
+        vertices: &PointCloud<P>,
+        triangles: &[[usize; 3]],
+    ) -> Vec<(usize, Vector3<T>)> {
+        let triangles = { triangles.iter() }
+            .map(|triangle| Triangle {
+                vertices: triangle.map(|index| vertices[index].coords().xyz()),
+            })
+            .collect::<Vec<_>>();
+        let cdf = { triangles.iter() }
+            .scan(T::zero(), |acc, triangle| {
+                let [a, b, c] = &triangle.vertices;
+                let area = (b - a).cross(&(c - a)).norm() / convert::<_, T>(2.);
+                if area.is_finite() {
+                    *acc += area;
+                }
+                Some(acc.clone())
+            })
+            .collect::<Vec<_>>();
+        if cdf.last().map_or(true, |total| *total <= T::zero()) {
+            return Vec::new();
+        }
+
+        let min_distance = match self.min_distance.clone() {
+            Some(min_distance) => min_distance,
+            None => {
+                return (0..self.num)
+                    .map(|_| self.sample_uniform(&triangles, &cdf))
+                    .collect()
+            }
+        };
+
+        let key = |coords: &Vector3<T>| {
+            coords.map(|x| (x / min_distance.clone()).floor().to_i64().unwrap())
+        };
+        let mut grid = HashMap::<Vector3<i64>, Vec<usize>>::new();
+        let mut ret = Vec::<(usize, Vector3<T>)>::with_capacity(self.num);
+        for _ in 0..self.num * POISSON_CANDIDATES {
+            if ret.len() >= self.num {
+                break;
+            }
+            let (index, coords) = self.sample_uniform(&triangles, &cdf);
+            let center = key(&coords);
+
+            let neighbors = (-1..=1).flat_map(|x| {
+                (-1..=1).flat_map(move |y| (-1..=1).map(move |z| Vector3::new(x, y, z)))
+            });
+            let rejected = { neighbors }.any(|offset| {
+                let samples = match grid.get(&(center + offset)) {
+                    Some(samples) => samples,
+                    None => return false,
+                };
+                { samples.iter() }.any(|&sample| (&ret[sample].1 - &coords).norm() < min_distance)
+            });
+            if !rejected {
+                grid.entry(center).or_default().push(ret.len());
+                ret.push((index, coords));
+            }
+        }
+        ret
+    }
+
+    /// Samples a point cloud from the mesh of `vertices` and `triangles`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any triangle refers to a nonexistent vertex.
+    pub fn sample<P, Q>(
+        &mut self,
+        vertices: &PointCloud<P>,
+        triangles: &[[usize; 3]],
+    ) -> PointCloud<Q>
+    where
+        P: Point<Data = T>,
+        Q: Point<Data = T>,
+    {
+        let storage = { self.sample_inner(vertices, triangles).into_iter() }
+            .map(|(_, coords)| Q::default().with_coords(coords.insert_row(3, T::one())))
+            .collect::<Vec<_>>();
+        PointCloud::from_vec(storage, 1)
+    }
+
+    /// Like [`MeshSampling::sample`], but also sets the normals of the
+    /// samples to the ones of the triangles they're on.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any triangle refers to a nonexistent vertex.
+    pub fn sample_with_normals<P, Q>(
+        &mut self,
+        vertices: &PointCloud<P>,
+        triangles: &[[usize; 3]],
+    ) -> PointCloud<Q>
+    where
+        P: Point<Data = T>,
+        Q: PointNormal<Data = T>,
+    {
+        let storage = { self.sample_inner(vertices, triangles).into_iter() }
+            .map(|(index, coords)| {
+                let triangle = Triangle {
+                    vertices: triangles[index].map(|index| vertices[index].coords().xyz()),
+                };
+                Q::default()
+                    .with_coords(coords.insert_row(3, T::one()))
+                    .with_normal(triangle.normal().insert_row(3, T::zero()))
+            })
+            .collect::<Vec<_>>();
+        PointCloud::from_vec(storage, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::point::{Normal, Point3, Point3N};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    /// A unit square at `z = 0` and a square of the side 2 at `z = 1`, 4
+    /// times as large.
+    fn mesh() -> (PointCloud<Point3>, Vec<[usize; 3]>) {
+        let storage = { [(1., 0.), (2., 1.)].into_iter() }
+            .flat_map(|(side, z)| {
+                [(0., 0.), (side, 0.), (side, side), (0., side)]
+                    .map(|(x, y)| Point3::default().with_coords(Vector4::new(x, y, z, 1.)))
+            })
+            .collect::<Vec<_>>();
+        let triangles = vec![[0, 1, 2], [0, 2, 3], [4, 5, 6], [4, 6, 7]];
+        (PointCloud::from_vec(storage, 1), triangles)
+    }
+
+    #[test]
+    fn test_uniform() {
+        let (vertices, triangles) = mesh();
+        let mut sampling = MeshSampling::new(StdRng::seed_from_u64(0), 2000, None);
+        let output: PointCloud<Point3N> = sampling.sample_with_normals(&vertices, &triangles);
+        assert_eq!(output.len(), 2000);
+
+        let mut large = 0;
+        for point in output.iter() {
+            let [[x, y, z, _]] = point.coords().data.0;
+            let (side, height) = if z > 0.5 {
+                large += 1;
+                (2., 1.)
+            } else {
+                (1., 0.)
+            };
+            assert!((z - height).abs() < 1e-6);
+            assert!((-1e-6..=side + 1e-6).contains(&x) && (-1e-6..=side + 1e-6).contains(&y));
+            assert_eq!(*point.normal(), Vector4::z());
+        }
+        let ratio = large as f32 / 2000.;
+        assert!((ratio - 0.8).abs() < 0.05, "{}", ratio);
+
+        let line = vec![[0, 1, 1]];
+        let output: PointCloud<Point3> = sampling.sample(&vertices, &line);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_poisson_disk() {
+        let (vertices, triangles) = mesh();
+        let mut sampling = MeshSampling::new(StdRng::seed_from_u64(0), 2000, Some(0.1));
+        let output: PointCloud<Point3> = sampling.sample(&vertices, &triangles);
+        // The mesh can't hold so many samples.
+        assert!(output.len() > 100 && output.len() < 2000);
+        for (i, a) in output.iter().enumerate() {
+            for b in output.iter().skip(i + 1) {
+                assert!((a.coords() - b.coords()).norm() >= 0.1);
+            }
+        }
+    }
+}
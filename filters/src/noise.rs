@@ -0,0 +1,253 @@
+use nalgebra::{convert, Affine3, RealField, Scalar, Vector3};
+use pcc_common::{point::Point, point_cloud::PointCloud};
+use rand::RngCore;
+
+/// A uniform random number within `[0, 1)`.
+pub(crate) fn uniform<T: RealField, R: RngCore>(rng: &mut R) -> T {
+    convert((rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64)
+}
+
+/// A standard normal random number by the Box-Muller transform.
+pub(crate) fn gaussian<T: RealField, R: RngCore>(rng: &mut R) -> T {
+    let u1 = T::one() - uniform::<T, _>(rng);
+    let u2 = uniform::<T, _>(rng);
+    (convert::<_, T>(-2.) * u1.ln()).sqrt() * (T::two_pi() * u2).cos()
+}
+
+/// Simulated sensor noise, which can be composed with tuples, e.g.
+/// `(KinectNoise::new(525.), SaltAndPepper::new(0.01, 0., 5.))`.
+pub trait NoiseModel<T: Scalar> {
+    /// Perturbs `coords` in the sensor frame, or returns `false` if the point
+    /// is dropped.
+    fn perturb<R: RngCore>(&self, rng: &mut R, coords: &mut Vector3<T>) -> bool;
+}
+
+impl<T: Scalar, A: NoiseModel<T>, B: NoiseModel<T>> NoiseModel<T> for (A, B) {
+    fn perturb<R: RngCore>(&self, rng: &mut R, coords: &mut Vector3<T>) -> bool {
+        self.0.perturb(rng, coords) && self.1.perturb(rng, coords)
+    }
+}
+
+/// Gaussian noise along the rays from the sensor, i.e. on the ranges.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GaussianNoise<T> {
+    pub std_dev: T,
+}
+
+impl<T> GaussianNoise<T> {
+    pub fn new(std_dev: T) -> Self {
+        GaussianNoise { std_dev }
+    }
+}
+
+impl<T: RealField> NoiseModel<T> for GaussianNoise<T> {
+    fn perturb<R: RngCore>(&self, rng: &mut R, coords: &mut Vector3<T>) -> bool {
+        let range = coords.norm();
+        if range > T::zero() {
+            let noise = self.std_dev.clone() * gaussian(rng);
+            *coords *= (range.clone() + noise) / range;
+        }
+        true
+    }
+}
+
+/// The noise of Kinect-like structured light sensors looking along +Z, after
+/// *Modeling Kinect Sensor Noise for Improved 3D Reconstruction and Tracking*
+/// by Nguyen et al.
+///
+/// The axial noise along +Z grows quadratically with the depth `z` (in
+/// meters) by `0.0012 + 0.0019 * (z - 0.4)^2`, and the lateral noise is 0.8
+/// pixels of the camera with `focal_length` in pixels, assuming the surfaces
+/// face the sensor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KinectNoise<T> {
+    pub focal_length: T,
+}
+
+impl<T> KinectNoise<T> {
+    pub fn new(focal_length: T) -> Self {
+        KinectNoise { focal_length }
+    }
+}
+
+impl<T: RealField> NoiseModel<T> for KinectNoise<T> {
+    fn perturb<R: RngCore>(&self, rng: &mut R, coords: &mut Vector3<T>) -> bool {
+        let depth = coords.z.clone();
+        if !(depth > T::zero()) {
+            return true;
+        }
+
+        let offset = depth.clone() - convert(0.4);
+        let axial = convert::<_, T>(0.0012) + convert::<_, T>(0.0019) * offset.clone() * offset;
+        let lateral = convert::<_, T>(0.8) * depth.clone() / self.focal_length.clone();
+
+        let noise_z = axial * gaussian(rng);
+        // Moves along the rays from the sensor so the pixels are kept.
+        *coords *= (depth.clone() + noise_z) / depth;
+        coords.x += lateral.clone() * gaussian(rng);
+        coords.y += lateral * gaussian(rng);
+        true
+    }
+}
+
+/// Salt-and-pepper noise, where every point is dropped with the probability
+/// `dropout`, or otherwise replaced by a spurious return on its ray at a
+/// uniformly random range within `max_range` with the probability `outlier`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SaltAndPepper<T> {
+    pub dropout: T,
+    pub outlier: T,
+    pub max_range: T,
+}
+
+impl<T> SaltAndPepper<T> {
+    pub fn new(dropout: T, outlier: T, max_range: T) -> Self {
+        SaltAndPepper {
+            dropout,
+            outlier,
+            max_range,
+        }
+    }
+}
+
+impl<T: RealField> NoiseModel<T> for SaltAndPepper<T> {
+    fn perturb<R: RngCore>(&self, rng: &mut R, coords: &mut Vector3<T>) -> bool {
+        if uniform::<T, _>(rng) < self.dropout {
+            return false;
+        }
+        let range = coords.norm();
+        if uniform::<T, _>(rng) < self.outlier && range > T::zero() {
+            let spurious = self.max_range.clone() * uniform(rng);
+            *coords *= spurious / range;
+        }
+        true
+    }
+}
+
+/// The blur of a sensor moving at `velocity` in its own frame, where every
+/// point is captured at a uniformly random time within `exposure` and thus
+/// displaced by the motion until then.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MotionBlur<T: Scalar> {
+    pub velocity: Vector3<T>,
+    pub exposure: T,
+}
+
+impl<T: Scalar> MotionBlur<T> {
+    pub fn new(velocity: Vector3<T>, exposure: T) -> Self {
+        MotionBlur { velocity, exposure }
+    }
+}
+
+impl<T: RealField> NoiseModel<T> for MotionBlur<T> {
+    fn perturb<R: RngCore>(&self, rng: &mut R, coords: &mut Vector3<T>) -> bool {
+        let time = self.exposure.clone() * uniform(rng);
+        *coords -= &self.velocity * time;
+        true
+    }
+}
+
+/// Applies `noise` to the finite points of `point_cloud` observed by the
+/// sensor at `sensor_pose` in the frame of the point cloud. The dropped
+/// points become NaN, so organized point clouds keep their layouts.
+pub fn add_noise<T, N, R, P>(
+    noise: &N,
+    rng: &mut R,
+    point_cloud: &mut PointCloud<P>,
+    sensor_pose: &Affine3<T>,
+) where
+    T: RealField,
+    N: NoiseModel<T>,
+    R: RngCore,
+    P: Point<Data = T>,
+{
+    let inverse = sensor_pose.clone().inverse();
+    for index in 0..point_cloud.len() {
+        let point = &mut point_cloud[index];
+        if !point.is_finite() {
+            continue;
+        }
+
+        let mut coords = (&inverse * point.na_point()).coords;
+        *point.coords_mut() = if noise.perturb(rng, &mut coords) {
+            (sensor_pose * nalgebra::Point3::from(coords)).to_homogeneous()
+        } else {
+            Vector3::repeat(convert(f64::NAN)).insert_row(3, T::one())
+        };
+    }
+
+    if !point_cloud.is_empty() {
+        let width = point_cloud.width();
+        point_cloud.reinterpret(width);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Translation3, Vector2, Vector4};
+    use pcc_common::point::{Data, Point3};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    /// `num` points at `(0, 0, depth)` in the frame of the sensor, which is at
+    /// `(1, 0, 0)` in the frame of the point cloud.
+    fn frame(num: usize, depth: f32) -> (PointCloud<Point3>, Affine3<f32>) {
+        let point = Point3::default().with_coords(Vector4::new(1., 0., depth, 1.));
+        let pose = nalgebra::convert(Translation3::new(1., 0., 0.));
+        (PointCloud::from_vec(vec![point; num], 1), pose)
+    }
+
+    /// The mean and the standard deviation of the depths of the finite
+    /// points.
+    fn depths(point_cloud: &PointCloud<Point3>) -> (f32, f32) {
+        let depths = { point_cloud.iter().filter(|point| point.is_finite()) }
+            .map(|point| point.coords().z)
+            .collect::<Vec<_>>();
+        let mean = depths.iter().sum::<f32>() / depths.len() as f32;
+        let var = depths.iter().map(|z| (z - mean).powi(2)).sum::<f32>() / depths.len() as f32;
+        (mean, var.sqrt())
+    }
+
+    #[test]
+    fn test_noise_models() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let (mut input, pose) = frame(2000, 5.);
+        add_noise(&GaussianNoise::new(0.1), &mut rng, &mut input, &pose);
+        let (mean, std_dev) = depths(&input);
+        assert!((mean - 5.).abs() < 0.01 && (std_dev - 0.1).abs() < 0.01);
+        // The points stay on their rays from the sensor.
+        assert!(input
+            .iter()
+            .all(|point| (point.coords().xy() - Vector2::x()).norm() < 1e-5));
+
+        // 0.0012 + 0.0019 * (2 - 0.4)^2 on the depths.
+        let (mut input, pose) = frame(2000, 2.);
+        add_noise(&KinectNoise::new(525.), &mut rng, &mut input, &pose);
+        let (mean, std_dev) = depths(&input);
+        assert!((mean - 2.).abs() < 1e-3 && (std_dev - 0.006064).abs() < 6e-4);
+
+        let (mut input, pose) = frame(2000, 5.);
+        add_noise(
+            &SaltAndPepper::new(0.5, 1., 2.),
+            &mut rng,
+            &mut input,
+            &pose,
+        );
+        let num = input.iter().filter(|point| point.is_finite()).count();
+        assert!((900..1100).contains(&num));
+        assert!(
+            { input.iter().filter(|point| point.is_finite()) }.all(|point| {
+                let coords = point.coords();
+                coords.x == 1. && coords.y == 0. && (0. ..2.).contains(&coords.z)
+            })
+        );
+        assert!(!input.is_bounded());
+
+        let noise = (GaussianNoise::new(0.), MotionBlur::new(Vector3::z(), 0.1));
+        let (mut input, pose) = frame(100, 5.);
+        add_noise(&noise, &mut rng, &mut input, &pose);
+        assert!({ input.iter() }.all(|point| (4.9..=5.).contains(&point.coords().z)));
+    }
+}
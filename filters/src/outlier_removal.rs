@@ -1,4 +1,4 @@
-use std::fmt::Debug;
+use std::{collections::VecDeque, fmt::Debug};
 
 use nalgebra::{RealField, Scalar};
 use num::ToPrimitive;
@@ -6,9 +6,10 @@ use pcc_common::{
     filter::{ApproxFilter, Filter},
     point::Point,
     point_cloud::PointCloud,
-    search::SearchType,
+    search::{Search, SearchType},
 };
-use pcc_search::searcher;
+use pcc_search::{searcher, KdTree, OrganizedNeighbor};
+use rayon::{iter::ParallelIterator, prelude::IntoParallelRefIterator};
 
 /// Calculate the mean distance between each point and its `mean_k` nearest
 /// neighbors. If its mean distance is larger (or smaller if `negative`) than
@@ -31,49 +32,110 @@ impl<T: Scalar> StatOutlierRemoval<T> {
     }
 }
 
-impl<T: RealField + ToPrimitive> StatOutlierRemoval<T> {
-    fn filter_data<P: Point<Data = T>>(&self, input: &PointCloud<P>) -> (Vec<T>, T) {
-        searcher!(searcher in input, T::default_epsilon());
+/// The sums of the mean distances of some points, from which their mean and
+/// standard deviation are derived.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct DistanceStat<T> {
+    num: usize,
+    dsum: T,
+    dsum2: T,
+}
 
-        let distance = {
-            let mut result = Vec::with_capacity(self.mean_k);
-            let mut dmean_of_point = |point: &P| {
-                result.clear();
-                searcher.search(point.coords(), SearchType::Knn(self.mean_k), &mut result);
-                let sum = result
-                    .iter()
-                    .map(|(_, distance)| distance.clone())
-                    .fold(T::zero(), |acc, distance| acc + distance);
-                sum / T::from_usize(result.len()).unwrap()
-            };
+impl<T: RealField> DistanceStat<T> {
+    fn new(distance: &[Option<T>]) -> Self {
+        { distance.iter().flatten() }.fold(DistanceStat::default(), |acc, dmean| DistanceStat {
+            num: acc.num + 1,
+            dsum: acc.dsum + dmean.clone(),
+            dsum2: acc.dsum2 + dmean.clone() * dmean.clone(),
+        })
+    }
 
-            if input.is_bounded() {
-                input.iter().map(dmean_of_point).collect::<Vec<_>>()
-            } else {
-                { input.iter() }
-                    .filter_map(|point| point.is_finite().then(|| dmean_of_point(point)))
-                    .collect::<Vec<_>>()
-            }
-        };
+    fn merge(self, other: &Self) -> Self {
+        DistanceStat {
+            num: self.num + other.num,
+            dsum: self.dsum + other.dsum.clone(),
+            dsum2: self.dsum2 + other.dsum2.clone(),
+        }
+    }
 
-        let (num, dsum, dsum2) = {
-            distance
+    fn threshold(&self, stddev_mul: T) -> Option<T> {
+        let dnum = T::from_usize(self.num).filter(|_| self.num > 0)?;
+        let dmean = self.dsum.clone() / dnum.clone();
+        let dmean2 = self.dsum2.clone() / dnum;
+        // Clamp the rounding errors of nearly uniform distances.
+        let dvar = (dmean2 - dmean.clone() * dmean.clone()).max(T::zero());
+        Some(dmean + dvar.sqrt() * stddev_mul)
+    }
+}
+
+impl<T: RealField> Default for DistanceStat<T> {
+    fn default() -> Self {
+        DistanceStat {
+            num: 0,
+            dsum: T::zero(),
+            dsum2: T::zero(),
+        }
+    }
+}
+
+impl<T: RealField + ToPrimitive> StatOutlierRemoval<T> {
+    /// The mean distance between each point and its `mean_k` nearest
+    /// neighbors, or `None` for the non-finite points.
+    fn mean_distances<'a, P, S>(&self, searcher: &S) -> Vec<Option<T>>
+    where
+        P: Point<Data = T> + 'a,
+        S: Search<'a, P> + ?Sized,
+    {
+        let input = searcher.input();
+        let mut result = Vec::with_capacity(self.mean_k);
+        let mut dmean_of_point = |point: &P| {
+            result.clear();
+            searcher.search(point.coords(), SearchType::Knn(self.mean_k), &mut result);
+            let sum = result
                 .iter()
-                .cloned()
-                .fold((0, T::zero(), T::zero()), |(num, dsum, dsum2), dmean| {
-                    (num + 1, dsum + dmean.clone(), dsum2 + dmean.clone() * dmean)
-                })
+                .map(|(_, distance)| distance.clone())
+                .fold(T::zero(), |acc, distance| acc + distance);
+            sum / T::from_usize(result.len()).unwrap()
         };
 
-        let dnum = T::from_usize(num).unwrap();
-        let dmean = dsum / dnum.clone();
-        let dmean2 = dsum2 / dnum;
-        let dvar = dmean2 - dmean.clone() * dmean.clone();
-        let dstddev = dvar.sqrt();
+        if input.is_bounded() {
+            input
+                .iter()
+                .map(|point| Some(dmean_of_point(point)))
+                .collect()
+        } else {
+            { input.iter() }
+                .map(|point| point.is_finite().then(|| dmean_of_point(point)))
+                .collect()
+        }
+    }
 
-        let threshold = dmean + dstddev * self.stddev_mul.clone();
+    fn partition(&self, distance: &[Option<T>], threshold: Option<T>) -> (Vec<usize>, Vec<usize>) {
+        let (mut indices, mut removed) = (Vec::new(), Vec::new());
+        for (index, dmean) in distance.iter().enumerate() {
+            let ret = match (dmean, &threshold) {
+                (Some(dmean), Some(threshold)) => (dmean <= threshold) ^ self.negative,
+                _ => false,
+            };
+            if ret {
+                indices.push(index)
+            } else {
+                removed.push(index)
+            }
+        }
+        (indices, removed)
+    }
 
-        (distance, threshold)
+    /// Like [`Filter::filter_all_indices`], but reuses `searcher` on the input
+    /// instead of building a new one.
+    pub fn filter_all_indices_with<'a, P, S>(&self, searcher: &S) -> (Vec<usize>, Vec<usize>)
+    where
+        P: Point<Data = T> + 'a,
+        S: Search<'a, P> + ?Sized,
+    {
+        let distance = self.mean_distances(searcher);
+        let threshold = DistanceStat::new(&distance).threshold(self.stddev_mul.clone());
+        self.partition(&distance, threshold)
     }
 }
 
@@ -81,26 +143,15 @@ impl<T: RealField + ToPrimitive, P: Point<Data = T>> Filter<PointCloud<P>>
     for StatOutlierRemoval<T>
 {
     fn filter_indices(&mut self, input: &PointCloud<P>) -> Vec<usize> {
-        let (distance, threshold) = self.filter_data(input);
-
-        let mut indices = (0..input.len()).collect::<Vec<_>>();
-        indices.retain(|&index| (distance[index] <= threshold) ^ self.negative);
-        indices
+        self.filter_all_indices(input).0
     }
 
     fn filter_all_indices(&mut self, input: &PointCloud<P>) -> (Vec<usize>, Vec<usize>) {
-        let (distance, threshold) = self.filter_data(input);
-
-        let mut indices = (0..input.len()).collect::<Vec<_>>();
-        let mut removed = Vec::with_capacity(indices.len());
-        indices.retain(|&index| {
-            let ret = (distance[index] <= threshold) ^ self.negative;
-            if !ret {
-                removed.push(index)
-            }
-            ret
-        });
-        (indices, removed)
+        if input.is_empty() {
+            return (Vec::new(), Vec::new());
+        }
+        searcher!(searcher in input, T::default_epsilon());
+        self.filter_all_indices_with(searcher)
     }
 }
 
@@ -109,26 +160,111 @@ impl<T: RealField + ToPrimitive, P: Point<Data = T>> ApproxFilter<PointCloud<P>>
 {
     #[inline]
     fn filter(&mut self, input: &PointCloud<P>) -> PointCloud<P> {
-        let mut new = input.clone();
-        self.filter_mut(&mut new);
-        new
+        let indices = self.filter_indices(input);
+        input.create_sub(&indices, 1)
+    }
+}
+
+/// A [`StatOutlierRemoval`] for streams of frames, e.g. from a live sensor.
+/// The overall mean distance and its standard deviation are accumulated over
+/// a sliding window of the last `window` frames filtered, including the
+/// current one, instead of being computed from the current frame only.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamingStatOutlierRemoval<T: Scalar> {
+    pub inner: StatOutlierRemoval<T>,
+    pub window: usize,
+    frames: VecDeque<DistanceStat<T>>,
+}
+
+impl<T: Scalar> StreamingStatOutlierRemoval<T> {
+    pub fn new(mean_k: usize, stddev_mul: T, negative: bool, window: usize) -> Self {
+        StreamingStatOutlierRemoval {
+            inner: StatOutlierRemoval::new(mean_k, stddev_mul, negative),
+            window,
+            frames: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// The number of frames in the window.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Forgets all the frames filtered, e.g. when the sensor is moved.
+    #[inline]
+    pub fn reset(&mut self) {
+        self.frames.clear()
+    }
+}
+
+impl<T: RealField> StreamingStatOutlierRemoval<T> {
+    /// The current threshold of the mean distances of the inliers, or `None`
+    /// if no finite point has been filtered in the window.
+    pub fn threshold(&self) -> Option<T> {
+        { self.frames.iter() }
+            .fold(DistanceStat::default(), DistanceStat::merge)
+            .threshold(self.inner.stddev_mul.clone())
+    }
+}
+
+impl<T: RealField + ToPrimitive> StreamingStatOutlierRemoval<T> {
+    /// Like [`Filter::filter_all_indices`], but reuses `searcher` on the input
+    /// instead of building a new one.
+    pub fn filter_all_indices_with<'a, P, S>(&mut self, searcher: &S) -> (Vec<usize>, Vec<usize>)
+    where
+        P: Point<Data = T> + 'a,
+        S: Search<'a, P> + ?Sized,
+    {
+        let distance = self.inner.mean_distances(searcher);
+        self.push_frame(&distance)
     }
 
-    fn filter_mut(&mut self, obj: &mut PointCloud<P>) {
-        let (distance, threshold) = self.filter_data(obj);
+    fn push_frame(&mut self, distance: &[Option<T>]) -> (Vec<usize>, Vec<usize>) {
+        self.frames.push_back(DistanceStat::new(distance));
+        while self.frames.len() > self.window.max(1) {
+            self.frames.pop_front();
+        }
 
-        let storage = unsafe { obj.storage() };
-        let mut index = 0;
-        storage.retain(|_| {
-            let ret = (distance[index] <= threshold) ^ self.negative;
-            index += 1;
-            ret
-        });
+        self.inner.partition(distance, self.threshold())
+    }
+}
 
-        obj.reinterpret(1)
+impl<T: RealField + ToPrimitive, P: Point<Data = T>> Filter<PointCloud<P>>
+    for StreamingStatOutlierRemoval<T>
+{
+    fn filter_indices(&mut self, input: &PointCloud<P>) -> Vec<usize> {
+        self.filter_all_indices(input).0
+    }
+
+    fn filter_all_indices(&mut self, input: &PointCloud<P>) -> (Vec<usize>, Vec<usize>) {
+        if input.is_empty() {
+            return self.push_frame(&[]);
+        }
+        searcher!(searcher in input, T::default_epsilon());
+        self.filter_all_indices_with(searcher)
     }
 }
 
+impl<T: RealField + ToPrimitive, P: Point<Data = T>> ApproxFilter<PointCloud<P>>
+    for StreamingStatOutlierRemoval<T>
+{
+    #[inline]
+    fn filter(&mut self, input: &PointCloud<P>) -> PointCloud<P> {
+        let indices = self.filter_indices(input);
+        input.create_sub(&indices, 1)
+    }
+}
+
+/// Remove the points with less than `min_neighbors` neighbors (including
+/// themselves) within `radius`, or keep only them if `negative`. Non-finite
+/// points are always removed.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct RadiusOutlierRemoval<T: Scalar> {
     pub radius: T,
@@ -147,69 +283,68 @@ impl<T: Scalar> RadiusOutlierRemoval<T> {
 }
 
 impl<T: RealField + ToPrimitive> RadiusOutlierRemoval<T> {
-    fn filter_inner<P: Point<Data = T>, U>(
-        &self,
-        input: &PointCloud<P>,
-        retainer: &mut Vec<U>,
-        removed: Option<&mut Vec<usize>>,
-    ) {
-        macro_rules! retain {
-            ($condition:expr) => {
-                match removed {
-                    Some(removed) => retainer.retain(|_| {
-                        let (index, ret) = $condition();
-                        if !ret {
-                            removed.push(index)
-                        }
-                        ret
-                    }),
-                    None => retainer.retain(|_| $condition().1),
-                }
-            };
+    fn is_inlier<'a, P, S>(&self, point: &P, searcher: &S) -> bool
+    where
+        P: Point<Data = T> + 'a,
+        S: Search<'a, P> + ?Sized,
+    {
+        if !point.is_finite() {
+            return false;
         }
+        let num = searcher.count_radius(point.coords(), self.radius.clone(), self.min_neighbors);
+        (num >= self.min_neighbors) ^ self.negative
+    }
 
-        searcher!(searcher in input, T::default_epsilon());
+    fn partition(flags: impl IntoIterator<Item = bool>) -> (Vec<usize>, Vec<usize>) {
+        let (mut indices, mut removed) = (Vec::new(), Vec::new());
+        for (index, flag) in flags.into_iter().enumerate() {
+            if flag {
+                indices.push(index)
+            } else {
+                removed.push(index)
+            }
+        }
+        (indices, removed)
+    }
 
-        let mut index = 0;
-        if input.is_bounded() {
-            let mut result = Vec::with_capacity(self.min_neighbors);
-            let mut condition = || {
-                result.clear();
-                searcher.search(
-                    input[index].coords(),
-                    SearchType::Knn(self.min_neighbors),
-                    &mut result,
-                );
-
-                let enough_neighbors = result.len() >= self.min_neighbors;
-                let enough_distance = result.pop().unwrap().1 <= self.radius;
-
-                let ret = (enough_neighbors && enough_distance) ^ self.negative;
-                index += 1;
-                (index - 1, ret)
-            };
-            retain!(condition)
-        } else {
-            let mut result = Vec::with_capacity(self.min_neighbors);
-            let mut condition = || {
-                if !input[index].is_finite() {
-                    return (index, false);
-                }
-                result.clear();
-                searcher.search(
-                    input[index].coords(),
-                    SearchType::Radius(self.radius.clone()),
-                    &mut result,
-                );
-
-                let enough_neighbors = result.len() >= self.min_neighbors;
-
-                let ret = enough_neighbors ^ self.negative;
-                index += 1;
-                (index - 1, ret)
-            };
-            retain!(condition)
+    fn flags_par<'a, P, S>(&self, input: &'a PointCloud<P>, searcher: &S) -> Vec<bool>
+    where
+        P: Point<Data = T> + Send + Sync + 'a,
+        S: Search<'a, P> + Sync,
+    {
+        { input.par_iter() }
+            .map(|point| self.is_inlier(point, searcher))
+            .collect()
+    }
+
+    /// Like [`Filter::filter_all_indices`], but reuses `searcher` on the input
+    /// instead of building a new one.
+    pub fn filter_all_indices_with<'a, P, S>(&self, searcher: &S) -> (Vec<usize>, Vec<usize>)
+    where
+        P: Point<Data = T> + 'a,
+        S: Search<'a, P> + ?Sized,
+    {
+        let input = searcher.input();
+        Self::partition(input.iter().map(|point| self.is_inlier(point, searcher)))
+    }
+
+    /// The parallel version of [`Filter::filter_all_indices`], which checks
+    /// the points in parallel.
+    pub fn filter_all_indices_par<P>(&self, input: &PointCloud<P>) -> (Vec<usize>, Vec<usize>)
+    where
+        P: Point<Data = T> + Send + Sync,
+    {
+        if input.is_empty() {
+            return (Vec::new(), Vec::new());
         }
+        let org_neigh = { input.width() > 1 }
+            .then(|| OrganizedNeighbor::new(input, T::default_epsilon()))
+            .flatten();
+        let flags = match org_neigh {
+            Some(searcher) => self.flags_par(input, &searcher),
+            None => self.flags_par(input, &KdTree::new(input)),
+        };
+        Self::partition(flags)
     }
 }
 
@@ -217,16 +352,15 @@ impl<T: RealField + ToPrimitive, P: Point<Data = T>> Filter<PointCloud<P>>
     for RadiusOutlierRemoval<T>
 {
     fn filter_indices(&mut self, input: &PointCloud<P>) -> Vec<usize> {
-        let mut indices = (0..input.len()).collect::<Vec<_>>();
-        self.filter_inner(input, &mut indices, None);
-        indices
+        self.filter_all_indices(input).0
     }
 
     fn filter_all_indices(&mut self, input: &PointCloud<P>) -> (Vec<usize>, Vec<usize>) {
-        let mut indices = (0..input.len()).collect::<Vec<_>>();
-        let mut removed = Vec::with_capacity(indices.len());
-        self.filter_inner(input, &mut indices, Some(&mut removed));
-        (indices, removed)
+        if input.is_empty() {
+            return (Vec::new(), Vec::new());
+        }
+        searcher!(searcher in input, T::default_epsilon());
+        self.filter_all_indices_with(searcher)
     }
 }
 
@@ -234,8 +368,91 @@ impl<T: RealField + ToPrimitive, P: Point<Data = T>> ApproxFilter<PointCloud<P>>
     for RadiusOutlierRemoval<T>
 {
     fn filter(&mut self, input: &PointCloud<P>) -> PointCloud<P> {
-        let mut storage = Vec::from(&**input);
-        self.filter_inner(input, &mut storage, None);
+        let indices = self.filter_indices(input);
+        input.create_sub(&indices, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::point::Point3;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    /// A grid of `num * num` points of the cell size `step`.
+    fn grid(num: usize, step: f32) -> PointCloud<Point3> {
+        let storage = { (0..num).flat_map(|x| (0..num).map(move |y| (x, y))) }
+            .map(|(x, y)| Vector4::new(x as f32 * step, y as f32 * step, 0., 1.))
+            .map(|coords| Point3::default().with_coords(coords))
+            .collect::<Vec<_>>();
         PointCloud::from_vec(storage, 1)
     }
+
+    #[test]
+    fn test_streaming_stat_outlier_removal() {
+        let (dense, sparse) = (grid(10, 0.1), grid(5, 0.2));
+        let mut stat = StatOutlierRemoval::new(4, 1., false);
+        let mut streaming = StreamingStatOutlierRemoval::new(4, 1., false, 2);
+        assert_eq!(streaming.threshold(), None);
+
+        assert_eq!(
+            streaming.filter_all_indices(&dense),
+            stat.filter_all_indices(&dense)
+        );
+        let (indices, removed) = streaming.filter_all_indices(&sparse);
+        assert_eq!((indices.len(), removed.len()), (0, 25));
+        assert_eq!(streaming.len(), 2);
+
+        // The dense frame slides out of the window.
+        let expected = stat.filter_all_indices(&sparse);
+        assert!(!expected.0.is_empty());
+        assert_eq!(streaming.filter_all_indices(&sparse), expected);
+        assert_eq!(streaming.len(), 2);
+
+        streaming.reset();
+        assert!(streaming.is_empty());
+        streaming.window = 1;
+        assert_eq!(
+            streaming.filter_all_indices(&PointCloud::<Point3>::new()),
+            (vec![], vec![])
+        );
+        assert_eq!(streaming.threshold(), None);
+        assert_eq!(streaming.filter_all_indices(&sparse), expected);
+    }
+
+    #[test]
+    fn test_radius_outlier_removal_par() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut storage = { 0..400 }
+            .map(|_| Vector4::new(rng.gen(), rng.gen(), rng.gen::<f32>() * 0.1, 1.))
+            .map(|coords| Point3::default().with_coords(coords))
+            .collect::<Vec<_>>();
+        storage[7] = Point3::default().with_coords(Vector4::new(f32::NAN, 0., 0., 1.));
+        let unorganized = PointCloud::from_vec(storage, 1);
+
+        // A noisy height map, which is searched by `OrganizedNeighbor`.
+        let storage = { (0..20).flat_map(|y| (0..20).map(move |x| (x, y))) }
+            .map(|(x, y)| {
+                let z = rng.gen::<f32>() * if (x + y) % 7 == 0 { 0.5 } else { 0.01 };
+                let coords = Vector4::new(x as f32 * 0.05, y as f32 * 0.05, z, 1.);
+                Point3::default().with_coords(coords)
+            })
+            .collect::<Vec<_>>();
+        let organized = PointCloud::from_vec(storage, 20);
+
+        for (input, radius) in [(&unorganized, 0.08), (&organized, 0.06)] {
+            for negative in [false, true] {
+                let mut ror = RadiusOutlierRemoval::new(radius, 4, negative);
+                let (indices, removed) = ror.filter_all_indices(input);
+                assert!(!indices.is_empty() && !removed.is_empty());
+                let expected = ror.filter_all_indices_with(&KdTree::new(input));
+                assert_eq!((&indices, &removed), (&expected.0, &expected.1));
+                assert_eq!(ror.filter_all_indices_par(input), (indices, removed));
+            }
+        }
+        let mut ror = RadiusOutlierRemoval::new(0.08, 4, true);
+        assert!(ror.filter_all_indices_par(&unorganized).1.contains(&7));
+    }
 }
@@ -0,0 +1,275 @@
+use nalgebra::{convert, RealField, Scalar, Vector2};
+use num::ToPrimitive;
+use pcc_common::{
+    point::Point,
+    point_cloud::{AsPointCloud, PointCloud},
+};
+
+use crate::interpolation::idw;
+
+/// A digital elevation model, i.e. a height map over the XY plane gridded
+/// from ground points, e.g. the output of [`crate::GridMinimumZ`].
+///
+/// The elevation of a cell is the mean height of the points in it, or NaN
+/// if it's a hole without points, which can be filled by
+/// [`TerrainModel::fill_holes`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TerrainModel<T: Scalar> {
+    /// The minimum corner of the grid.
+    pub origin: Vector2<T>,
+    /// The side length of the cells.
+    pub resolution: T,
+    pub width: usize,
+    pub height: usize,
+    /// The elevations of the cells in row-major order.
+    pub elevations: Vec<T>,
+}
+
+impl<T: RealField + ToPrimitive> TerrainModel<T> {
+    /// Grids the finite points of `input` into cells of the side
+    /// `resolution`, or returns `None` if `resolution` is not positive and
+    /// finite, or too small for the extent of the points.
+    pub fn from_point_cloud<P: Point<Data = T>>(
+        input: &PointCloud<P>,
+        resolution: T,
+    ) -> Option<Self> {
+        if !(resolution.is_finite() && resolution > T::zero()) {
+            return None;
+        }
+        let [min, max] = match input.finite_bound() {
+            Some(bound) => bound,
+            None => {
+                return Some(TerrainModel {
+                    origin: Vector2::zeros(),
+                    resolution,
+                    width: 0,
+                    height: 0,
+                    elevations: Vec::new(),
+                })
+            }
+        };
+        let origin = min.xy();
+        let key = |coords: Vector2<T>| {
+            let key = (coords - &origin) / resolution.clone();
+            Some(Vector2::new(
+                key.x.clone().floor().to_usize()?,
+                key.y.clone().floor().to_usize()?,
+            ))
+        };
+        let size = key(max.xy())?;
+        let (width, height) = (size.x.checked_add(1)?, size.y.checked_add(1)?);
+
+        let mut sums = vec![(T::zero(), 0); width.checked_mul(height)?];
+        for point in input.iter().filter(|point| point.is_finite()) {
+            let coords = point.coords();
+            let key = key(coords.xy())?;
+            let (sum, num) = &mut sums[key.y * width + key.x];
+            *sum += coords.z.clone();
+            *num += 1;
+        }
+
+        let elevations = { sums.into_iter() }
+            .map(|(sum, num)| match num {
+                0 => convert::<_, T>(f64::NAN),
+                _ => sum / T::from_usize(num).unwrap(),
+            })
+            .collect();
+        Some(TerrainModel {
+            origin,
+            resolution,
+            width,
+            height,
+            elevations,
+        })
+    }
+
+    /// The elevation of the cell `(x, y)`, or `None` if it's out of the grid
+    /// or a hole.
+    pub fn elevation(&self, x: usize, y: usize) -> Option<T> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let elevation = self.elevations[y * self.width + x].clone();
+        elevation.is_finite().then_some(elevation)
+    }
+
+    /// The elevation of the cell containing `coords`.
+    pub fn elevation_at(&self, coords: &Vector2<T>) -> Option<T> {
+        let key = (coords - &self.origin) / self.resolution.clone();
+        let (x, y) = (key.x.clone().floor(), key.y.clone().floor());
+        if x < T::zero() || y < T::zero() {
+            return None;
+        }
+        self.elevation(x.to_usize()?, y.to_usize()?)
+    }
+
+    /// Fills the holes by the inverse distance weighting of the elevations of
+    /// the cells within `radius` cells, weighted by the distances to the
+    /// power of `-power`, and returns the number of the filled holes.
+    ///
+    /// Only the elevations before filling are used, so the holes farther than
+    /// `radius` from any elevation remain, and calling it again fills them
+    /// progressively.
+    pub fn fill_holes(&mut self, radius: usize, power: T) -> usize {
+        let mut filled = self.elevations.clone();
+        let mut num = 0;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.elevation(x, y).is_some() {
+                    continue;
+                }
+
+                let xs = x.saturating_sub(radius)..(x + radius + 1).min(self.width);
+                let ys = y.saturating_sub(radius)..(y + radius + 1).min(self.height);
+                let samples = { ys.flat_map(|ny| xs.clone().map(move |nx| (nx, ny))) }.filter_map(
+                    |(nx, ny)| {
+                        let elevation = self.elevation(nx, ny)?;
+                        let dx = T::from_usize(nx.abs_diff(x)).unwrap();
+                        let dy = T::from_usize(ny.abs_diff(y)).unwrap();
+                        Some(((dx.clone() * dx + dy.clone() * dy).sqrt(), elevation))
+                    },
+                );
+
+                if let Some(elevation) = idw(samples, &power) {
+                    filled[y * self.width + x] = elevation;
+                    num += 1;
+                }
+            }
+        }
+        self.elevations = filled;
+        num
+    }
+
+    /// The gradient of the elevations at the cell `(x, y)` by the central
+    /// differences, or the one-sided ones on the borders and next to holes.
+    pub fn gradient(&self, x: usize, y: usize) -> Option<Vector2<T>> {
+        let center = self.elevation(x, y)?;
+        let diff = |prev: Option<T>, next: Option<T>| match (prev, next) {
+            (Some(prev), Some(next)) => Some((next - prev) / convert::<_, T>(2.)),
+            (Some(prev), None) => Some(center.clone() - prev),
+            (None, Some(next)) => Some(next - center.clone()),
+            (None, None) => None,
+        };
+        let left = x.checked_sub(1).and_then(|x| self.elevation(x, y));
+        let down = y.checked_sub(1).and_then(|y| self.elevation(x, y));
+        let dx = diff(left, self.elevation(x + 1, y))?;
+        let dy = diff(down, self.elevation(x, y + 1))?;
+        Some(Vector2::new(dx, dy) / self.resolution.clone())
+    }
+
+    /// The slope at the cell `(x, y)` in radians from the horizontal plane.
+    pub fn slope(&self, x: usize, y: usize) -> Option<T> {
+        Some(self.gradient(x, y)?.norm().atan())
+    }
+
+    /// The aspect at the cell `(x, y)`, i.e. the azimuth of the steepest
+    /// descent in radians within `[0, 2π)`, measured clockwise from the `+y`
+    /// axis (north) with `+x` as east, or `None` if the cell is flat.
+    pub fn aspect(&self, x: usize, y: usize) -> Option<T> {
+        let gradient = self.gradient(x, y)?;
+        if gradient.norm_squared() <= T::default_epsilon() {
+            return None;
+        }
+        let aspect = (-gradient.x.clone()).atan2(-gradient.y.clone());
+        Some(if aspect < T::zero() {
+            aspect + T::two_pi()
+        } else {
+            aspect
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::point::Point3;
+
+    use super::*;
+
+    /// The plane `z = x + 2y` sampled at the centers of 3 by 3 cells, except
+    /// the central one.
+    fn plane() -> PointCloud<Point3> {
+        let storage = { (0..3).flat_map(|y| (0..3).map(move |x| (x, y))) }
+            .filter(|&cell| cell != (1, 1))
+            .map(|(x, y)| {
+                let (x, y) = (x as f32 + 0.5, y as f32 + 0.5);
+                Point3::default().with_coords(Vector4::new(x, y, x + 2. * y, 1.))
+            })
+            .collect::<Vec<_>>();
+        PointCloud::from_vec(storage, 1)
+    }
+
+    #[test]
+    fn test_elevation() {
+        let terrain = TerrainModel::from_point_cloud(&plane(), 1.).unwrap();
+        assert_eq!((terrain.width, terrain.height), (3, 3));
+        assert_eq!(terrain.origin, Vector2::new(0.5, 0.5));
+        assert_eq!(terrain.elevation(0, 0), Some(1.5));
+        assert_eq!(terrain.elevation(1, 1), None);
+        assert_eq!(terrain.elevation(3, 0), None);
+        assert_eq!(terrain.elevation_at(&Vector2::new(2.6, 0.6)), Some(3.5));
+        assert_eq!(terrain.elevation_at(&Vector2::new(0., 0.)), None);
+
+        // The points in the same cell are averaged.
+        let coarse = TerrainModel::from_point_cloud(&plane(), 10.).unwrap();
+        assert_eq!((coarse.width, coarse.height), (1, 1));
+        assert_eq!(coarse.elevation(0, 0), Some(4.5));
+
+        let empty = TerrainModel::from_point_cloud(&PointCloud::<Point3>::new(), 1.).unwrap();
+        assert_eq!((empty.width, empty.height), (0, 0));
+        for resolution in [0., -1., f32::NAN, f32::INFINITY, 1e-30] {
+            assert_eq!(TerrainModel::from_point_cloud(&plane(), resolution), None);
+        }
+    }
+
+    #[test]
+    fn test_fill_holes() {
+        let mut terrain = TerrainModel::from_point_cloud(&plane(), 1.).unwrap();
+        assert_eq!(terrain.fill_holes(0, 2.), 0);
+        // The neighbors are symmetric around the hole on the plane.
+        assert_eq!(terrain.fill_holes(1, 2.), 1);
+        assert!((terrain.elevation(1, 1).unwrap() - 4.5).abs() < 1e-5);
+        assert_eq!(terrain.fill_holes(1, 2.), 0);
+
+        // The hole 2 cells away from any elevation is filled by the second
+        // call only.
+        let mut terrain = TerrainModel {
+            origin: Vector2::zeros(),
+            resolution: 1.,
+            width: 5,
+            height: 1,
+            elevations: vec![1., f32::NAN, f32::NAN, f32::NAN, 1.],
+        };
+        assert_eq!(terrain.fill_holes(1, 2.), 2);
+        assert_eq!(terrain.elevation(2, 0), None);
+        assert_eq!(terrain.fill_holes(1, 2.), 1);
+        assert_eq!(terrain.elevation(2, 0), Some(1.));
+    }
+
+    #[test]
+    fn test_slope_aspect() {
+        let mut terrain = TerrainModel::from_point_cloud(&plane(), 1.).unwrap();
+        // The one-sided differences on the borders.
+        let gradient = terrain.gradient(0, 0).unwrap();
+        assert!((gradient - Vector2::new(1., 2.)).norm() < 1e-5);
+        // No vertical neighbor but the hole.
+        assert_eq!(terrain.gradient(1, 0), None);
+        assert_eq!(terrain.gradient(1, 1), None);
+
+        terrain.fill_holes(1, 2.);
+        let gradient = terrain.gradient(1, 1).unwrap();
+        assert!((gradient - Vector2::new(1., 2.)).norm() < 1e-5);
+        let slope = terrain.slope(0, 0).unwrap();
+        assert!((slope - 5f32.sqrt().atan()).abs() < 1e-5);
+        // The steepest descent points to -x and -y, i.e. south-west.
+        let aspect = terrain.aspect(2, 2).unwrap();
+        assert!((aspect - ((-1f32).atan2(-2.) + std::f32::consts::TAU)).abs() < 1e-5);
+
+        let flat = TerrainModel {
+            elevations: vec![2.; 9],
+            ..terrain
+        };
+        assert_eq!(flat.slope(1, 1), Some(0.));
+        assert_eq!(flat.aspect(1, 1), None);
+    }
+}
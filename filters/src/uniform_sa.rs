@@ -24,41 +24,44 @@ impl<T: RealField + ToPrimitive> UniformSampling<T> {
     fn filter_data<'a, P: Point<Data = T>>(
         grid_unit: &Vector4<T>,
         input: &'a PointCloud<P>,
-    ) -> Option<(Vector4<T>, Vec<([usize; 3], &'a P)>)> {
+    ) -> Option<(Vector4<T>, Vec<([usize; 3], usize, &'a P)>)> {
         let [min, _] = match input.finite_bound() {
             Some(bound) => bound,
             None => return None,
         };
         let bounded = input.is_bounded();
         let mut key_point = if bounded {
-            { input.iter() }
-                .map(|point| {
+            { input.iter().enumerate() }
+                .map(|(index, point)| {
                     let coords = point.coords();
                     let key = (coords - &min)
-                        .component_div(grid_unit)
+                        .xyz()
+                        .component_div(&grid_unit.xyz())
                         .map(|x| x.floor().to_usize().unwrap());
-                    (*key.xyz().as_ref(), point)
+                    (*key.as_ref(), index, point)
                 })
                 .collect::<Vec<_>>()
         } else {
-            { input.iter().filter(|point| point.is_finite()) }
-                .map(|point| {
+            { input.iter().enumerate() }
+                .filter(|(_, point)| point.is_finite())
+                .map(|(index, point)| {
                     let coords = point.coords();
                     let key = (coords - &min)
-                        .component_div(grid_unit)
+                        .xyz()
+                        .component_div(&grid_unit.xyz())
                         .map(|x| x.floor().to_usize().unwrap());
-                    (*key.xyz().as_ref(), point)
+                    (*key.as_ref(), index, point)
                 })
                 .collect::<Vec<_>>()
         };
-        key_point.sort_by(|(i1, _), (i2, _)| i1.cmp(i2));
+        key_point.sort_by(|(k1, ..), (k2, ..)| k1.cmp(k2));
         Some((min, key_point))
     }
 
     fn filter_inner<P: Point<Data = T>, F1, F2>(
         &self,
         min: &Vector4<T>,
-        key_point: Vec<([usize; 3], &P)>,
+        key_point: Vec<([usize; 3], usize, &P)>,
         mut push_point: F1,
         mut push_removed: F2,
     ) where
@@ -77,7 +80,7 @@ impl<T: RealField + ToPrimitive> UniformSampling<T> {
         let mut last_index = [0; 3];
         let mut center = get_center(last_index);
 
-        for (index, (key, point)) in key_point.into_iter().enumerate() {
+        for (key, index, point) in key_point {
             if key != last_index {
                 last_index = key;
                 center = get_center(key);
@@ -93,7 +96,11 @@ impl<T: RealField + ToPrimitive> UniformSampling<T> {
                     push_removed(i);
                     Some((index, point, distance))
                 }
-                _ => nearest,
+                None => Some((index, point, distance)),
+                _ => {
+                    push_removed(index);
+                    nearest
+                }
             };
         }
         if let Some((index, point, _)) = nearest.take() {
@@ -0,0 +1,334 @@
+use nalgebra::{convert, Affine3, Point3, RealField, Scalar, Vector3};
+use num::ToPrimitive;
+use pcc_common::{
+    camera::{Image, PinholeCamera},
+    geometry::{Bvh, Ray, Triangle},
+    point::Point,
+    point_cloud::PointCloud,
+};
+use rand::{rngs::ThreadRng, RngCore};
+
+use crate::noise::{gaussian, uniform, NoiseModel};
+
+/// A spinning lidar, which looks along +X with +Z upward, and has a beam of
+/// every elevation angle in `elevations` (as the rows) firing at `columns`
+/// azimuth angles evenly spaced over a revolution counterclockwise from +X.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpinningLidar<T> {
+    pub elevations: Vec<T>,
+    pub columns: usize,
+}
+
+impl<T> SpinningLidar<T> {
+    pub fn new(elevations: Vec<T>, columns: usize) -> Self {
+        SpinningLidar {
+            elevations,
+            columns,
+        }
+    }
+}
+
+impl<T: RealField> SpinningLidar<T> {
+    /// The row of the beam that a ray at `elevation` falls in, i.e. the one
+    /// with the nearest elevation angle if within half the spacing to its
+    /// neighbor on the side of the ray. Non-finite elevations have no beams.
+    fn beam(&self, elevation: &T) -> Option<usize> {
+        if !elevation.is_finite() {
+            return None;
+        }
+        let finite = { self.elevations.iter().enumerate() }.filter(|(_, e)| e.is_finite());
+        let (y, nearest) = { finite.clone() }
+            .map(|(y, e)| (y, e, (e.clone() - elevation.clone()).abs()))
+            .min_by(|(_, _, a), (_, _, b)| a.partial_cmp(b).unwrap())
+            .map(|(y, e, _)| (y, e))?;
+
+        let gap = |above: bool| {
+            { finite.clone() }
+                .filter(|(_, e)| if above { *e > nearest } else { *e < nearest })
+                .map(|(_, e)| (e.clone() - nearest.clone()).abs())
+                .min_by(|a, b| a.partial_cmp(b).unwrap())
+        };
+        let above = elevation >= nearest;
+        let spacing = match gap(above).or_else(|| gap(!above)) {
+            Some(spacing) => spacing,
+            // A single beam takes the rays within half the horizontal
+            // resolution, as if its neighbors were as far as its columns.
+            None => T::two_pi() / T::from_usize(self.columns).unwrap(),
+        };
+        let diff = (nearest.clone() - elevation.clone()).abs();
+        (diff <= spacing / convert::<_, T>(2.)).then_some(y)
+    }
+}
+
+/// The sensor models of [`VirtualScanner`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScannerModel<T: Scalar> {
+    /// The values of the rendered images are the depths along +Z.
+    Pinhole(PinholeCamera<T>),
+    /// The values of the rendered images are the ranges from the sensor.
+    Lidar(SpinningLidar<T>),
+}
+
+/// Renders meshes and point clouds from sensor poses, e.g. to generate
+/// simulated scans for testing.
+///
+/// The rendered images hold the values along the rays of the pixels as
+/// described in [`ScannerModel`] within `max_range`, perturbed by Gaussian
+/// noise with the standard deviation `noise`, and every pixel is dropped with
+/// the probability `dropout`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VirtualScanner<T: Scalar, R: RngCore = ThreadRng> {
+    pub model: ScannerModel<T>,
+    pub max_range: T,
+    pub noise: T,
+    pub dropout: T,
+    pub rng: R,
+}
+
+impl<T: Scalar, R: RngCore> VirtualScanner<T, R> {
+    pub fn new(model: ScannerModel<T>, max_range: T, noise: T, dropout: T, rng: R) -> Self {
+        VirtualScanner {
+            model,
+            max_range,
+            noise,
+            dropout,
+            rng,
+        }
+    }
+}
+
+impl<T: RealField + ToPrimitive, R: RngCore> VirtualScanner<T, R> {
+    /// The width and height of the rendered images.
+    pub fn size(&self) -> (usize, usize) {
+        match &self.model {
+            ScannerModel::Pinhole(camera) => (camera.width, camera.height),
+            ScannerModel::Lidar(lidar) => (lidar.columns, lidar.elevations.len()),
+        }
+    }
+
+    /// The direction of the ray of `pixel` in the sensor frame, scaled so
+    /// that the value of the image is its parameter.
+    fn direction(&self, (x, y): (usize, usize)) -> Vector3<T> {
+        match &self.model {
+            ScannerModel::Pinhole(camera) => camera.unproject((x, y), T::one()).xyz(),
+            ScannerModel::Lidar(lidar) => {
+                let azimuth =
+                    T::two_pi() * T::from_usize(x).unwrap() / T::from_usize(lidar.columns).unwrap();
+                let elevation = lidar.elevations[y].clone();
+                let horizontal = elevation.clone().cos();
+                Vector3::new(
+                    horizontal.clone() * azimuth.clone().cos(),
+                    horizontal * azimuth.sin(),
+                    elevation.sin(),
+                )
+            }
+        }
+    }
+
+    /// The pixel that `coords` in the sensor frame falls in and its value.
+    ///
+    /// For lidars, points fall in the beams with the nearest elevation
+    /// angles, if within half the spacing to the neighboring beams.
+    fn project(&self, coords: &Vector3<T>) -> Option<((usize, usize), T)> {
+        match &self.model {
+            ScannerModel::Pinhole(camera) => {
+                camera.project(&coords.clone().insert_row(3, T::one()))
+            }
+            ScannerModel::Lidar(lidar) => {
+                let columns = T::from_usize(lidar.columns).unwrap();
+                let step = T::two_pi() / columns;
+
+                let horizontal = coords.xy().norm();
+                let elevation = coords.z.clone().atan2(horizontal);
+                let y = lidar.beam(&elevation)?;
+
+                let mut azimuth = coords.y.clone().atan2(coords.x.clone());
+                if azimuth < T::zero() {
+                    azimuth += T::two_pi();
+                }
+                let x = (azimuth / step).round().to_usize()? % lidar.columns;
+                Some(((x, y), coords.norm()))
+            }
+        }
+    }
+
+    /// Applies the noise and dropout models to the value of a pixel.
+    fn perturb(&mut self, value: T) -> Option<T> {
+        if uniform::<T, _>(&mut self.rng) < self.dropout {
+            return None;
+        }
+        if self.noise > T::zero() {
+            let noise = self.noise.clone() * gaussian(&mut self.rng);
+            return Some(value + noise);
+        }
+        Some(value)
+    }
+
+    /// Renders the mesh in `mesh` seen by the sensor at `pose` in the frame of
+    /// the mesh.
+    pub fn render_mesh(
+        &mut self,
+        mesh: &Bvh<T, Triangle<T>>,
+        pose: &Affine3<T>,
+    ) -> Image<Option<T>> {
+        let (width, height) = self.size();
+        let origin = (pose * Point3::origin()).coords;
+
+        let mut image = Image::filled(None, width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let direction = self.direction((x, y));
+                let max = self.max_range.clone() / direction.norm();
+                let ray = Ray {
+                    origin: origin.clone(),
+                    direction: pose * direction,
+                };
+                if let Some((_, t)) = mesh.ray_cast(&ray, max) {
+                    image[(x, y)] = self.perturb(t);
+                }
+            }
+        }
+        image
+    }
+
+    /// Renders the points of `point_cloud` seen by the sensor at `pose` in the
+    /// frame of the point cloud, where every pixel keeps the value of its
+    /// nearest point.
+    pub fn render_point_cloud<P: Point<Data = T>>(
+        &mut self,
+        point_cloud: &PointCloud<P>,
+        pose: &Affine3<T>,
+    ) -> Image<Option<T>> {
+        let (width, height) = self.size();
+        let inverse = pose.clone().inverse();
+
+        let mut image = Image::filled(None::<T>, width, height);
+        for point in point_cloud.iter().filter(|point| point.is_finite()) {
+            let coords = (&inverse * point.na_point()).coords;
+            if coords.norm() > self.max_range {
+                continue;
+            }
+            if let Some((pixel, value)) = self.project(&coords) {
+                if image[pixel]
+                    .as_ref()
+                    .map_or(true, |nearest| &value < nearest)
+                {
+                    image[pixel] = Some(value);
+                }
+            }
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                if let Some(value) = image[(x, y)].take() {
+                    image[(x, y)] = self.perturb(value);
+                }
+            }
+        }
+        image
+    }
+
+    /// Applies `noise` to the points of a rendered `image` in the sensor
+    /// frame, and renders them again, so lateral noise may move them to
+    /// other pixels.
+    pub fn add_noise<N: NoiseModel<T>>(
+        &mut self,
+        noise: &N,
+        image: &Image<Option<T>>,
+    ) -> Image<Option<T>> {
+        let (width, height) = self.size();
+        let mut output = Image::filled(None::<T>, width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let value = match &image[(x, y)] {
+                    Some(value) => value.clone(),
+                    None => continue,
+                };
+                let mut coords = self.direction((x, y)) * value;
+                if !noise.perturb(&mut self.rng, &mut coords) {
+                    continue;
+                }
+                if let Some((pixel, value)) = self.project(&coords) {
+                    if output[pixel]
+                        .as_ref()
+                        .map_or(true, |nearest| &value < nearest)
+                    {
+                        output[pixel] = Some(value);
+                    }
+                }
+            }
+        }
+        output
+    }
+
+    /// Converts a rendered `image` back to an organized point cloud in the
+    /// frame where the sensor is at `pose`, whose pixels without values are
+    /// NaN points.
+    pub fn to_point_cloud<Q: Point<Data = T>>(
+        &self,
+        image: &Image<Option<T>>,
+        pose: &Affine3<T>,
+    ) -> PointCloud<Q> {
+        let width = image.width();
+        let storage = { image.iter().enumerate() }
+            .map(|(index, value)| {
+                let coords = match value {
+                    Some(value) => {
+                        let direction = self.direction((index % width, index / width));
+                        let point = Point3::from(direction * value.clone());
+                        (pose * point).to_homogeneous()
+                    }
+                    None => Vector3::repeat(convert(f64::NAN)).insert_row(3, T::one()),
+                };
+                Q::default().with_coords(coords)
+            })
+            .collect::<Vec<_>>();
+        PointCloud::from_vec(storage, width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::point;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_lidar_beam() {
+        let lidar = SpinningLidar::new(vec![-0.2f32, 0., 0.1, f32::NAN], 360);
+        // Far beyond half the horizontal resolution, but within half the
+        // spacing to the neighboring beams.
+        assert_eq!(lidar.beam(&0.04), Some(1));
+        assert_eq!(lidar.beam(&0.06), Some(2));
+        assert_eq!(lidar.beam(&-0.09), Some(1));
+        assert_eq!(lidar.beam(&-0.11), Some(0));
+        // Beyond the outermost beams.
+        assert_eq!(lidar.beam(&0.16), None);
+        assert_eq!(lidar.beam(&-0.31), None);
+        assert_eq!(lidar.beam(&f32::NAN), None);
+    }
+
+    #[test]
+    fn test_render_point_cloud() {
+        let lidar = SpinningLidar::new(vec![0f32, 0.1], 4);
+        let mut scanner = VirtualScanner::new(
+            ScannerModel::Lidar(lidar),
+            100.,
+            0.,
+            0.,
+            StdRng::seed_from_u64(0),
+        );
+        let storage = [[5., 0., 0.3], [0., 4., 0.], [0., 6., 0.], [0., 0., 5.]]
+            .map(|[x, y, z]| point::Point3::default().with_coords(Vector4::new(x, y, z, 1.)));
+        let point_cloud = PointCloud::from_vec(storage.to_vec(), 1);
+
+        let image = scanner.render_point_cloud(&point_cloud, &Affine3::identity());
+        assert_eq!(image[(0, 0)], None);
+        assert!((image[(0, 1)].unwrap() - 25.09f32.sqrt()).abs() < 1e-5);
+        // The nearest point is kept.
+        assert_eq!(image[(1, 0)], Some(4.));
+        assert_eq!({ image.iter() }.filter(|value| value.is_some()).count(), 2);
+    }
+}
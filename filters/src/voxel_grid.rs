@@ -4,143 +4,297 @@ use nalgebra::{RealField, Scalar, Vector2, Vector4};
 use num::ToPrimitive;
 use pcc_common::{
     filter::{ApproxFilter, Filter},
-    point::{Centroid, Point},
+    point::{Centroid, CentroidBuilder, Point},
     point_cloud::{AsPointCloud, PointCloud},
 };
+use rayon::{
+    iter::{IntoParallelIterator, ParallelIterator},
+    slice::ParallelSliceMut,
+};
+
+/// The mapping between the input points and the output centroids of
+/// [`VoxelGrid`] or [`HashVoxelGrid`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VoxelMapping {
+    /// The indices of the input points contributing to every output centroid.
+    pub voxels: Vec<Vec<usize>>,
+    /// The index of the output centroid of every input point, or `None` if
+    /// the point is not finite or its voxel is discarded.
+    pub inverse: Vec<Option<usize>>,
+}
+
+impl VoxelMapping {
+    fn reset(&mut self, len: usize) {
+        self.voxels.clear();
+        self.inverse.clear();
+        self.inverse.resize(len, None);
+    }
+
+    fn push(&mut self, output: usize, index: usize) {
+        if self.voxels.len() <= output {
+            self.voxels.resize_with(output + 1, Vec::new);
+        }
+        self.voxels[output].push(index);
+        self.inverse[index] = Some(output);
+    }
+
+    /// The number of input points contributing to every output centroid.
+    pub fn counts(&self) -> Vec<usize> {
+        self.voxels.iter().map(Vec::len).collect()
+    }
+}
+
+fn voxel_keys<T, P>(input: &PointCloud<P>, grid_unit: &Vector4<T>) -> Vec<([usize; 3], usize)>
+where
+    T: RealField + ToPrimitive,
+    P: Point<Data = T>,
+{
+    let [min, _] = match input.finite_bound() {
+        Some(bound) => bound,
+        None => return Vec::new(),
+    };
+
+    let bounded = input.is_bounded();
+    { input.iter().enumerate() }
+        .filter(|(_, point)| bounded || point.is_finite())
+        .map(|(index, point)| {
+            let coords = point.coords();
+            let key = (coords - &min)
+                .component_div(grid_unit)
+                .map(|x| x.floor().to_usize().unwrap());
+            (*key.xyz().as_ref(), index)
+        })
+        .collect()
+}
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct VoxelGrid<T: Scalar> {
     pub grid_unit: Vector4<T>,
+    /// The voxels with fewer points are discarded, which removes sparse
+    /// outliers while downsampling.
+    pub min_points_per_voxel: usize,
 }
 
 impl<T: Scalar> VoxelGrid<T> {
     pub fn new(grid_unit: Vector4<T>) -> Self {
-        VoxelGrid { grid_unit }
+        VoxelGrid {
+            grid_unit,
+            min_points_per_voxel: 1,
+        }
+    }
+
+    pub fn with_min_points_per_voxel(mut self, min_points_per_voxel: usize) -> Self {
+        self.min_points_per_voxel = min_points_per_voxel;
+        self
     }
 }
 
-impl<T, P> ApproxFilter<PointCloud<P>> for VoxelGrid<T>
+impl<T> VoxelGrid<T>
 where
-    T: RealField + ToPrimitive + Centroid + Default,
-    P: Point<Data = T> + Centroid<Result = P>,
-    <P as Centroid>::Accumulator: Default,
+    T: RealField + ToPrimitive + Default,
 {
-    fn filter(&mut self, input: &PointCloud<P>) -> PointCloud<P> {
-        let [min, _] = match input.finite_bound() {
-            Some(bound) => bound,
-            None => return PointCloud::new(),
-        };
+    fn filter_inner<P>(
+        &self,
+        input: &PointCloud<P>,
+        mut mapping: Option<&mut VoxelMapping>,
+    ) -> PointCloud<P>
+    where
+        P: Point<Data = T> + Centroid<Result = P>,
+        <P as Centroid>::Accumulator: Default,
+    {
+        if let Some(mapping) = mapping.as_deref_mut() {
+            mapping.reset(input.len());
+        }
 
-        let bounded = input.is_bounded();
+        let mut key_index = voxel_keys(input, &self.grid_unit);
+        key_index.sort_by(|(i1, _), (i2, _)| i1.cmp(i2));
 
-        let mut key_point = if bounded {
-            { input.iter() }
-                .map(|point| {
-                    let coords = point.coords();
-                    let index = (coords - &min)
-                        .component_div(&self.grid_unit)
-                        .map(|x| x.floor().to_usize().unwrap());
-                    (*index.xyz().as_ref(), point)
-                })
-                .collect::<Vec<_>>()
-        } else {
-            { input.iter().filter(|point| point.is_finite()) }
-                .map(|point| {
-                    let coords = point.coords();
-                    let index = (coords - &min)
-                        .component_div(&self.grid_unit)
-                        .map(|x| x.floor().to_usize().unwrap());
-                    (*index.xyz().as_ref(), point)
-                })
-                .collect::<Vec<_>>()
-        };
+        let mut storage = Vec::with_capacity(key_index.len() / 3);
+        let mut rest = &key_index[..];
+        while let Some(&(key, _)) = rest.first() {
+            let len = rest.partition_point(|(k, _)| *k == key);
+            let (voxel, next) = rest.split_at(len);
+            rest = next;
+            if voxel.len() < self.min_points_per_voxel {
+                continue;
+            }
 
-        key_point.sort_by(|(i1, _), (i2, _)| i1.cmp(i2));
+            let mut centroid_builder = Centroid::default_builder();
+            for &(_, index) in voxel {
+                centroid_builder.accumulate(&input[index]);
+                if let Some(mapping) = mapping.as_deref_mut() {
+                    mapping.push(storage.len(), index);
+                }
+            }
+            storage.push(centroid_builder.compute().unwrap());
+        }
 
-        let mut centroid_builder = Centroid::default_builder();
-        let mut last_key = [0; 3];
-        let mut storage = Vec::with_capacity(key_point.len() / 3);
+        PointCloud::from_vec(storage, 1)
+    }
 
-        for (key, coords) in key_point {
-            if key != last_key {
-                last_key = key;
-                let centroid = centroid_builder.compute().unwrap();
-                storage.push(centroid);
+    /// Like [`ApproxFilter::filter`], but also returns the mapping between
+    /// the input points and the output centroids, e.g. to project the results
+    /// computed on the downsampled point cloud back onto the input.
+    pub fn filter_mapped<P>(&self, input: &PointCloud<P>) -> (PointCloud<P>, VoxelMapping)
+    where
+        P: Point<Data = T> + Centroid<Result = P>,
+        <P as Centroid>::Accumulator: Default,
+    {
+        let mut mapping = VoxelMapping::default();
+        let output = self.filter_inner(input, Some(&mut mapping));
+        (output, mapping)
+    }
+}
 
-                centroid_builder = Centroid::default_builder();
-            }
+impl<T> VoxelGrid<T>
+where
+    T: RealField + ToPrimitive + Default,
+{
+    /// Like [`ApproxFilter::filter`], but the voxels are partitioned by the
+    /// hashes of their keys and processed in parallel. The output is sorted
+    /// by the keys as well, so it's identical to the serial one.
+    pub fn filter_par<P>(&self, input: &PointCloud<P>) -> PointCloud<P>
+    where
+        P: Point<Data = T> + Centroid<Result = P> + Send + Sync,
+        <P as Centroid>::Accumulator: Default + Send,
+    {
+        let key_index = voxel_keys(input, &self.grid_unit);
 
-            centroid_builder.accumulate(coords);
+        let num = rayon::current_num_threads().max(1);
+        let mut partitions = vec![Vec::new(); num];
+        for (key, index) in key_index {
+            let hash = { key[0].wrapping_mul(73856093) }
+                ^ key[1].wrapping_mul(19349663)
+                ^ key[2].wrapping_mul(83492791);
+            partitions[hash % num].push((key, index));
         }
-        let centroid = centroid_builder.compute().unwrap();
-        storage.push(centroid);
 
+        let mut voxels = { partitions.into_par_iter() }
+            .flat_map_iter(|partition| {
+                let mut builders = HashMap::new();
+                for (key, index) in partition {
+                    { builders.entry(key) }
+                        .or_insert_with(Centroid::default_builder)
+                        .accumulate(&input[index]);
+                }
+                { builders.into_iter() }
+                    .filter(|(_, builder)| builder.num() >= self.min_points_per_voxel)
+                    .map(|(key, builder)| (key, builder.compute().unwrap()))
+            })
+            .collect::<Vec<_>>();
+        voxels.par_sort_unstable_by(|(k1, _), (k2, _)| k1.cmp(k2));
+
+        let storage = voxels.into_iter().map(|(_, point)| point).collect();
         PointCloud::from_vec(storage, 1)
     }
 }
 
+impl<T, P> ApproxFilter<PointCloud<P>> for VoxelGrid<T>
+where
+    T: RealField + ToPrimitive + Default,
+    P: Point<Data = T> + Centroid<Result = P>,
+    <P as Centroid>::Accumulator: Default,
+{
+    fn filter(&mut self, input: &PointCloud<P>) -> PointCloud<P> {
+        self.filter_inner(input, None)
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct HashVoxelGrid<T: Scalar> {
     pub grid_unit: Vector4<T>,
+    /// See [`VoxelGrid::min_points_per_voxel`].
+    pub min_points_per_voxel: usize,
 }
 
 impl<T: Scalar> HashVoxelGrid<T> {
     pub fn new(grid_unit: Vector4<T>) -> Self {
-        HashVoxelGrid { grid_unit }
+        HashVoxelGrid {
+            grid_unit,
+            min_points_per_voxel: 1,
+        }
+    }
+
+    pub fn with_min_points_per_voxel(mut self, min_points_per_voxel: usize) -> Self {
+        self.min_points_per_voxel = min_points_per_voxel;
+        self
     }
 }
 
-impl<T, P> ApproxFilter<PointCloud<P>> for HashVoxelGrid<T>
+impl<T> HashVoxelGrid<T>
 where
-    T: RealField + ToPrimitive + Centroid + Default,
-    P: Point<Data = T> + Centroid<Result = P>,
-    <P as Centroid>::Accumulator: Default,
+    T: RealField + ToPrimitive + Default,
 {
-    fn filter(&mut self, input: &PointCloud<P>) -> PointCloud<P> {
-        let [min, _] = match input.finite_bound() {
-            Some(bound) => bound,
-            None => return PointCloud::new(),
-        };
-
-        let bounded = input.is_bounded();
+    fn filter_inner<P>(
+        &self,
+        input: &PointCloud<P>,
+        mut mapping: Option<&mut VoxelMapping>,
+    ) -> PointCloud<P>
+    where
+        P: Point<Data = T> + Centroid<Result = P>,
+        <P as Centroid>::Accumulator: Default,
+    {
+        if let Some(mapping) = mapping.as_deref_mut() {
+            mapping.reset(input.len());
+        }
 
-        let fold = |mut map: HashMap<_, _>, (index, point)| {
-            match map.try_insert(index, Centroid::default_builder()) {
-                Ok(builder) => builder.accumulate(point),
-                Err(mut e) => e.entry.get_mut().accumulate(point),
-            }
-            map
-        };
+        let mut outputs = HashMap::new();
+        let mut builders: Vec<CentroidBuilder<P>> = Vec::new();
+        let voxels = { voxel_keys(input, &self.grid_unit).into_iter() }
+            .map(|(key, index)| {
+                let output = *outputs.entry(key).or_insert_with(|| {
+                    builders.push(Centroid::default_builder());
+                    builders.len() - 1
+                });
+                builders[output].accumulate(&input[index]);
+                (output, index)
+            })
+            .collect::<Vec<_>>();
 
-        let key_point = if bounded {
-            { input.iter() }
-                .map(|point| {
-                    let coords = point.coords();
-                    let index = (coords - &min)
-                        .component_div(&self.grid_unit)
-                        .map(|x| x.floor().to_usize().unwrap());
-                    (*index.xyz().as_ref(), point)
-                })
-                .fold(HashMap::new(), fold)
-        } else {
-            { input.iter().filter(|point| point.is_finite()) }
-                .map(|point| {
-                    let coords = point.coords();
-                    let index = (coords - &min)
-                        .component_div(&self.grid_unit)
-                        .map(|x| x.floor().to_usize().unwrap());
-                    (*index.xyz().as_ref(), point)
+        // The outputs of the kept voxels after discarding the sparse ones.
+        let mut storage = Vec::with_capacity(builders.len());
+        let remap = { builders.into_iter() }
+            .map(|builder| {
+                (builder.num() >= self.min_points_per_voxel).then(|| {
+                    storage.push(builder.compute().unwrap());
+                    storage.len() - 1
                 })
-                .fold(HashMap::new(), fold)
-        };
-
-        let storage = key_point
-            .into_iter()
-            .map(|(_, builder)| builder.compute().unwrap())
+            })
             .collect::<Vec<_>>();
 
+        if let Some(mapping) = mapping {
+            for (output, index) in voxels {
+                if let Some(output) = remap[output] {
+                    mapping.push(output, index);
+                }
+            }
+        }
+
         PointCloud::from_vec(storage, 1)
     }
+
+    /// Like [`ApproxFilter::filter`], but also returns the mapping between
+    /// the input points and the output centroids.
+    pub fn filter_mapped<P>(&self, input: &PointCloud<P>) -> (PointCloud<P>, VoxelMapping)
+    where
+        P: Point<Data = T> + Centroid<Result = P>,
+        <P as Centroid>::Accumulator: Default,
+    {
+        let mut mapping = VoxelMapping::default();
+        let output = self.filter_inner(input, Some(&mut mapping));
+        (output, mapping)
+    }
+}
+
+impl<T, P> ApproxFilter<PointCloud<P>> for HashVoxelGrid<T>
+where
+    T: RealField + ToPrimitive + Default,
+    P: Point<Data = T> + Centroid<Result = P>,
+    <P as Centroid>::Accumulator: Default,
+{
+    fn filter(&mut self, input: &PointCloud<P>) -> PointCloud<P> {
+        self.filter_inner(input, None)
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -284,3 +438,93 @@ impl<T: RealField + ToPrimitive, P: Point<Data = T>> ApproxFilter<PointCloud<P>>
         PointCloud::from_vec(storage, 1)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use pcc_common::point::Point3;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_filter_par() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut storage = { 0..1000 }
+            .map(|_| {
+                let coords = Vector4::new(rng.gen(), rng.gen(), rng.gen(), 1.);
+                Point3::default().with_coords(coords)
+            })
+            .collect::<Vec<_>>();
+        storage.push(Point3::default().with_coords(Vector4::repeat(f32::NAN)));
+        let input = PointCloud::from_vec(storage, 1);
+
+        let mut grid = VoxelGrid::new(Vector4::new(0.1, 0.1, 0.1, 1.));
+        let output = grid.filter(&input);
+        assert!(!output.is_empty() && output.len() <= 1000);
+        assert_eq!(grid.filter_par(&input), output);
+
+        let mut sparse = grid.with_min_points_per_voxel(3);
+        let output = sparse.filter(&input);
+        assert_eq!(sparse.filter_par(&input), output);
+    }
+
+    fn points(coords: &[[f32; 3]]) -> PointCloud<Point3> {
+        let storage = { coords.iter() }
+            .map(|&[x, y, z]| Point3::default().with_coords(Vector4::new(x, y, z, 1.)))
+            .collect::<Vec<_>>();
+        PointCloud::from_vec(storage, 1)
+    }
+
+    #[test]
+    fn test_filter_mapped() {
+        let input = points(&[
+            [0.55, 0., 0.],
+            [0.01, 0., 0.],
+            [0.59, 0., 0.],
+            [f32::NAN, 0., 0.],
+            [0.03, 0., 0.],
+        ]);
+        let grid_unit = Vector4::new(0.1, 0.1, 0.1, 1.);
+
+        // The voxels are sorted by their keys.
+        let (output, mapping) = VoxelGrid::new(grid_unit).filter_mapped(&input);
+        assert_eq!(output.len(), 2);
+        assert!((output[0].coords().x - 0.02).abs() < 1e-6);
+        assert_eq!(mapping.voxels, [vec![1, 4], vec![0, 2]]);
+        assert_eq!(mapping.inverse, [Some(1), Some(0), Some(1), None, Some(0)]);
+        assert_eq!(mapping.counts(), [2, 2]);
+
+        // The voxels are in the order of their first points.
+        let (output, mapping) = HashVoxelGrid::new(grid_unit).filter_mapped(&input);
+        assert!((output[0].coords().x - 0.57).abs() < 1e-6);
+        assert_eq!(mapping.voxels, [vec![0, 2], vec![1, 4]]);
+        assert_eq!(mapping.inverse, [Some(0), Some(1), Some(0), None, Some(1)]);
+    }
+
+    #[test]
+    fn test_min_points_per_voxel() {
+        let input = points(&[
+            [0.95, 0., 0.],
+            [0.01, 0., 0.],
+            [0.03, 0., 0.],
+            [0.55, 0., 0.],
+        ]);
+        let grid_unit = Vector4::new(0.1, 0.1, 0.1, 1.);
+
+        let grid = VoxelGrid::new(grid_unit).with_min_points_per_voxel(2);
+        let (output, mapping) = grid.filter_mapped(&input);
+        assert_eq!(output.len(), 1);
+        assert!((output[0].coords().x - 0.02).abs() < 1e-6);
+        assert_eq!(mapping.voxels, [vec![1, 2]]);
+        assert_eq!(mapping.inverse, [None, Some(0), Some(0), None]);
+
+        // The outputs after the discarded voxels are renumbered.
+        let grid = HashVoxelGrid::new(grid_unit).with_min_points_per_voxel(2);
+        let (output, mapping) = grid.filter_mapped(&input);
+        assert_eq!(output.len(), 1);
+        assert_eq!(mapping.inverse, [None, Some(0), Some(0), None]);
+
+        let grid = VoxelGrid::new(grid_unit).with_min_points_per_voxel(3);
+        assert!(grid.filter_mapped(&input).0.is_empty());
+    }
+}
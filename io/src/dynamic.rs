@@ -0,0 +1,112 @@
+use std::error::Error;
+
+use num::FromPrimitive;
+use pcc_common::{
+    point::{Data, DataFields},
+    point_cloud::PointCloud,
+};
+
+use crate::pcd::{Pcd, PcdData, PcdField, PcdFieldData, PcdHeader, Viewpoint};
+
+/// A point cloud whose point type is only known at runtime, as read from a
+/// file of any format.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DynPointCloud {
+    pub fields: Vec<PcdField>,
+    pub width: usize,
+    pub height: usize,
+    pub viewpoint: Viewpoint,
+    pub finite: bool,
+    /// The records of the points, each of which packs the fields in the order
+    /// of `fields`, in native endianness.
+    pub data: Vec<u8>,
+}
+
+impl DynPointCloud {
+    pub fn from_point_cloud<P>(point_cloud: &PointCloud<P>, viewpoint: &Viewpoint) -> Self
+    where
+        P: Data + DataFields,
+        P::Data: PcdFieldData,
+    {
+        Pcd::from_point_cloud(point_cloud, viewpoint, PcdData::Binary).into()
+    }
+
+    pub fn into_point_cloud<P>(self) -> Result<(PointCloud<P>, Viewpoint), Box<dyn Error>>
+    where
+        P: Data + DataFields,
+        P::Data: FromPrimitive,
+    {
+        self.into_pcd(PcdData::Binary).to_point_cloud()
+    }
+
+    pub fn into_pcd(self, data_type: PcdData) -> Pcd {
+        Pcd {
+            header: PcdHeader {
+                rec_size: self.rec_size(),
+                fields: self.fields,
+                width: self.width,
+                height: self.height,
+                viewpoint_origin: self.viewpoint.origin,
+                viewpoint_quat: self.viewpoint.quat,
+                data: data_type,
+            },
+            finite: self.finite,
+            data: self.data,
+        }
+    }
+
+    #[inline]
+    pub fn rec_size(&self) -> usize {
+        { self.fields.iter() }.fold(0, |acc, field| acc + field.count * field.ty.size())
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.width * self.height
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline]
+    pub fn field(&self, name: &str) -> Option<&PcdField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// Returns the field of `name` and its byte offset in every record.
+    pub fn field_offset(&self, name: &str) -> Option<(&PcdField, usize)> {
+        let mut offset = 0;
+        for field in &self.fields {
+            if field.name == name {
+                return Some((field, offset));
+            }
+            offset += field.count * field.ty.size();
+        }
+        None
+    }
+
+    /// Iterates over the records of the points.
+    #[inline]
+    pub fn records(&self) -> impl Iterator<Item = &[u8]> {
+        self.data.chunks(self.rec_size().max(1))
+    }
+}
+
+impl From<Pcd> for DynPointCloud {
+    fn from(pcd: Pcd) -> Self {
+        DynPointCloud {
+            fields: pcd.header.fields,
+            width: pcd.header.width,
+            height: pcd.header.height,
+            viewpoint: Viewpoint {
+                origin: pcd.header.viewpoint_origin,
+                quat: pcd.header.viewpoint_quat,
+            },
+            finite: pcd.finite,
+            data: pcd.data,
+        }
+    }
+}
@@ -0,0 +1,541 @@
+//! Exports point clouds and meshes as a glTF 2.0 binary (GLB) scene.
+
+use std::{error::Error, fmt::Write as _, io::Write};
+
+use nalgebra::Matrix4;
+use num::ToPrimitive;
+use pcc_common::{
+    point::{Point, PointRgba},
+    point_cloud::PointCloud,
+};
+
+use crate::{pcd::PcdFieldType, CloudWriter, DynPointCloud};
+
+const GLB_MAGIC: u32 = 0x4654_6C67;
+const GLB_VERSION: u32 = 2;
+const CHUNK_JSON: u32 = 0x4E4F_534A;
+const CHUNK_BIN: u32 = 0x004E_4942;
+
+const COMPONENT_FLOAT: u32 = 5126;
+const COMPONENT_UNSIGNED_INT: u32 = 5125;
+const TARGET_ARRAY_BUFFER: u32 = 34962;
+const TARGET_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const MODE_POINTS: u32 = 0;
+const MODE_TRIANGLES: u32 = 4;
+
+/// An object of a glTF scene, which becomes a node with its own mesh.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfObject {
+    pub name: String,
+    pub positions: Vec<[f32; 3]>,
+    /// The RGB colors of the vertices, in `[0, 1]`.
+    pub colors: Option<Vec<[f32; 3]>>,
+    /// The triangles of a mesh, or `None` to draw the vertices as points.
+    pub triangles: Option<Vec<[u32; 3]>>,
+    /// The transformation from the object to the scene.
+    pub transform: Matrix4<f32>,
+}
+
+impl GltfObject {
+    pub fn new(name: &str, positions: Vec<[f32; 3]>) -> Self {
+        GltfObject {
+            name: name.to_owned(),
+            positions,
+            colors: None,
+            triangles: None,
+            transform: Matrix4::identity(),
+        }
+    }
+
+    pub fn with_colors(mut self, colors: Vec<[f32; 3]>) -> Self {
+        self.colors = Some(colors);
+        self
+    }
+
+    pub fn with_triangles(mut self, triangles: Vec<[u32; 3]>) -> Self {
+        self.triangles = Some(triangles);
+        self
+    }
+
+    pub fn with_transform(mut self, transform: Matrix4<f32>) -> Self {
+        self.transform = transform;
+        self
+    }
+}
+
+fn position<T: ToPrimitive>(point: &impl Point<Data = T>) -> [f32; 3] {
+    let coords = point.coords();
+    [0, 1, 2].map(|i| coords[i].to_f32().unwrap_or(f32::NAN))
+}
+
+fn rgb([b, g, r, _]: [f32; 4]) -> [f32; 3] {
+    [r / 255., g / 255., b / 255.]
+}
+
+/// A glTF scene of objects, each with its own transformation.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GltfScene {
+    pub objects: Vec<GltfObject>,
+}
+
+impl GltfScene {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add_object(&mut self, object: GltfObject) -> &mut Self {
+        self.objects.push(object);
+        self
+    }
+
+    /// Adds the finite points of `point_cloud` as point primitives.
+    pub fn add_cloud<P>(
+        &mut self,
+        name: &str,
+        point_cloud: &PointCloud<P>,
+        transform: Matrix4<f32>,
+    ) -> &mut Self
+    where
+        P: Point,
+        P::Data: ToPrimitive,
+    {
+        let positions = { point_cloud.iter().filter(|point| point.is_finite()) }
+            .map(position)
+            .collect();
+        self.add_object(GltfObject::new(name, positions).with_transform(transform))
+    }
+
+    /// Adds the finite points of `point_cloud` as point primitives, colored
+    /// with `COLOR_0`.
+    pub fn add_rgb_cloud<P>(
+        &mut self,
+        name: &str,
+        point_cloud: &PointCloud<P>,
+        transform: Matrix4<f32>,
+    ) -> &mut Self
+    where
+        P: PointRgba,
+        P::Data: ToPrimitive,
+    {
+        let (positions, colors) = { point_cloud.iter().filter(|point| point.is_finite()) }
+            .map(|point| (position(point), rgb(point.rgba_array())))
+            .unzip();
+        let object = GltfObject::new(name, positions)
+            .with_colors(colors)
+            .with_transform(transform);
+        self.add_object(object)
+    }
+
+    /// Adds the points of a point cloud of any format, colored with `COLOR_0`
+    /// if it has an `rgb` or `rgba` field.
+    pub fn add_dyn_cloud(
+        &mut self,
+        name: &str,
+        point_cloud: &DynPointCloud,
+        transform: Matrix4<f32>,
+    ) -> Result<&mut Self, Box<dyn Error>> {
+        let coords = ["x", "y", "z"].map(|name| point_cloud.field_offset(name));
+        let coords = match coords {
+            [Some(x), Some(y), Some(z)] => [x, y, z],
+            _ => return Err("Missing coordinates".into()),
+        };
+        let color = { point_cloud.field_offset("rgb") }
+            .or_else(|| point_cloud.field_offset("rgba"))
+            .filter(|(field, _)| field.ty.size() == 4);
+
+        let mut positions = Vec::with_capacity(point_cloud.len());
+        let mut colors = Vec::with_capacity(color.map_or(0, |_| point_cloud.len()));
+        for record in point_cloud.records() {
+            let mut position = [0.; 3];
+            for (value, (field, offset)) in position.iter_mut().zip(coords) {
+                *value = match field.ty {
+                    PcdFieldType::F32 => {
+                        f32::from_ne_bytes(record[offset..][..4].try_into().unwrap())
+                    }
+                    PcdFieldType::F64 => {
+                        f64::from_ne_bytes(record[offset..][..8].try_into().unwrap()) as f32
+                    }
+                    ty => return Err(format!("Invalid type of coordinates: {:?}", ty).into()),
+                };
+            }
+            if !position.iter().all(|x| x.is_finite()) {
+                continue;
+            }
+            positions.push(position);
+
+            if let Some((_, offset)) = color {
+                let rgba = u32::from_ne_bytes(record[offset..][..4].try_into().unwrap());
+                let [b, g, r, _] = rgba.to_le_bytes();
+                colors.push([r, g, b].map(|c| c as f32 / 255.));
+            }
+        }
+
+        let mut object = GltfObject::new(name, positions).with_transform(transform);
+        if color.is_some() {
+            object = object.with_colors(colors);
+        }
+        Ok(self.add_object(object))
+    }
+
+    /// Adds a triangle mesh of `vertices` and `triangles`.
+    pub fn add_mesh<P>(
+        &mut self,
+        name: &str,
+        vertices: &PointCloud<P>,
+        triangles: &[[usize; 3]],
+        transform: Matrix4<f32>,
+    ) -> &mut Self
+    where
+        P: Point,
+        P::Data: ToPrimitive,
+    {
+        let positions = vertices.iter().map(position).collect();
+        let triangles = { triangles.iter() }
+            .map(|triangle| triangle.map(|index| index as u32))
+            .collect();
+        let object = GltfObject::new(name, positions)
+            .with_triangles(triangles)
+            .with_transform(transform);
+        self.add_object(object)
+    }
+
+    /// Writes the scene as a GLB file.
+    ///
+    /// Returns an error if any transformation, position or color is not
+    /// finite, which glTF doesn't allow.
+    pub fn write_glb<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn Error>> {
+        let mut bin = Vec::new();
+        let mut json = Json::default();
+
+        for object in &self.objects {
+            if !object.transform.iter().all(|x| x.is_finite()) {
+                return Err(format!("Non-finite transformation of {:?}", object.name).into());
+            }
+            let colors = object.colors.iter().flatten();
+            if !{ object.positions.iter().chain(colors) }.all(|v| v.iter().all(|x| x.is_finite())) {
+                return Err(format!("Non-finite vertices of {:?}", object.name).into());
+            }
+
+            let node = json.nodes.len();
+            let mut node_json = format!("{{\"name\":{}", escape(&object.name));
+            if !object.positions.is_empty() {
+                let _ = write!(node_json, ",\"mesh\":{}", json.meshes.len());
+                json.push_mesh(object, &mut bin);
+            }
+            let matrix = object.transform.iter().map(|x| format!("{}", x));
+            let _ = write!(node_json, ",\"matrix\":[{}]}}", join(matrix));
+            json.nodes.push(node_json);
+            json.scene_nodes.push(node);
+        }
+
+        let mut json = json.finish(bin.len()).into_bytes();
+        json.resize((json.len() + 3) & !3, b' ');
+        bin.resize((bin.len() + 3) & !3, 0);
+
+        let bin_chunk = if bin.is_empty() { 0 } else { 8 + bin.len() };
+        let total = 12 + 8 + json.len() + bin_chunk;
+
+        writer.write_all(&GLB_MAGIC.to_le_bytes())?;
+        writer.write_all(&GLB_VERSION.to_le_bytes())?;
+        writer.write_all(&(total as u32).to_le_bytes())?;
+        writer.write_all(&(json.len() as u32).to_le_bytes())?;
+        writer.write_all(&CHUNK_JSON.to_le_bytes())?;
+        writer.write_all(&json)?;
+        if !bin.is_empty() {
+            writer.write_all(&(bin.len() as u32).to_le_bytes())?;
+            writer.write_all(&CHUNK_BIN.to_le_bytes())?;
+            writer.write_all(&bin)?;
+        }
+        Ok(())
+    }
+}
+
+fn escape(string: &str) -> String {
+    let mut output = String::with_capacity(string.len() + 2);
+    output.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(output, "\\u{:04x}", c as u32);
+            }
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+    output
+}
+
+fn join(items: impl Iterator<Item = String>) -> String {
+    items.collect::<Vec<_>>().join(",")
+}
+
+/// The JSON arrays of a glTF document, built as strings.
+#[derive(Default)]
+struct Json {
+    nodes: Vec<String>,
+    scene_nodes: Vec<usize>,
+    meshes: Vec<String>,
+    accessors: Vec<String>,
+    buffer_views: Vec<String>,
+}
+
+impl Json {
+    fn push_view(&mut self, bin: &mut Vec<u8>, data: &[u8], target: u32) -> usize {
+        let view = self.buffer_views.len();
+        self.buffer_views.push(format!(
+            "{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{},\"target\":{}}}",
+            bin.len(),
+            data.len(),
+            target
+        ));
+        bin.extend_from_slice(data);
+        view
+    }
+
+    fn push_vec3(&mut self, bin: &mut Vec<u8>, values: &[[f32; 3]], bound: bool) -> usize {
+        let data = { values.iter().flatten() }
+            .flat_map(|x| x.to_le_bytes())
+            .collect::<Vec<_>>();
+        let view = self.push_view(bin, &data, TARGET_ARRAY_BUFFER);
+
+        let mut accessor = format!(
+            "{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"VEC3\"",
+            view,
+            COMPONENT_FLOAT,
+            values.len()
+        );
+        if bound {
+            let (min, max) = values.iter().fold(
+                ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]),
+                |(min, max), v| {
+                    (
+                        [0, 1, 2].map(|i| min[i].min(v[i])),
+                        [0, 1, 2].map(|i| max[i].max(v[i])),
+                    )
+                },
+            );
+            let _ = write!(
+                accessor,
+                ",\"min\":[{}],\"max\":[{}]",
+                join(min.iter().map(|x| format!("{}", x))),
+                join(max.iter().map(|x| format!("{}", x)))
+            );
+        }
+        accessor.push('}');
+
+        let accessor_index = self.accessors.len();
+        self.accessors.push(accessor);
+        accessor_index
+    }
+
+    fn push_mesh(&mut self, object: &GltfObject, bin: &mut Vec<u8>) {
+        let position = self.push_vec3(bin, &object.positions, true);
+        let mut attributes = format!("\"POSITION\":{}", position);
+        if let Some(colors) = &object.colors {
+            let color = self.push_vec3(bin, colors, false);
+            let _ = write!(attributes, ",\"COLOR_0\":{}", color);
+        }
+
+        let mut primitive = format!("{{\"attributes\":{{{}}}", attributes);
+        match &object.triangles {
+            Some(triangles) => {
+                let data = { triangles.iter().flatten() }
+                    .flat_map(|x| x.to_le_bytes())
+                    .collect::<Vec<_>>();
+                let view = self.push_view(bin, &data, TARGET_ELEMENT_ARRAY_BUFFER);
+                let accessor = self.accessors.len();
+                self.accessors.push(format!(
+                    "{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"SCALAR\"}}",
+                    view,
+                    COMPONENT_UNSIGNED_INT,
+                    triangles.len() * 3
+                ));
+                let _ = write!(
+                    primitive,
+                    ",\"indices\":{},\"mode\":{}}}",
+                    accessor, MODE_TRIANGLES
+                );
+            }
+            None => {
+                let _ = write!(primitive, ",\"mode\":{}}}", MODE_POINTS);
+            }
+        }
+
+        self.meshes
+            .push(format!("{{\"primitives\":[{}]}}", primitive));
+    }
+
+    fn finish(self, buffer_len: usize) -> String {
+        let mut json =
+            String::from("{\"asset\":{\"version\":\"2.0\",\"generator\":\"pcc\"},\"scene\":0,");
+        // The arrays of glTF must not be empty if present.
+        if self.nodes.is_empty() {
+            json.push_str("\"scenes\":[{}]");
+        } else {
+            let scene_nodes = self.scene_nodes.iter().map(|node| node.to_string());
+            let _ = write!(
+                json,
+                "\"scenes\":[{{\"nodes\":[{}]}}],\"nodes\":[{}]",
+                join(scene_nodes),
+                self.nodes.join(",")
+            );
+        }
+        if !self.meshes.is_empty() {
+            let _ = write!(
+                json,
+                ",\"meshes\":[{}],\"accessors\":[{}],\"bufferViews\":[{}],\
+                 \"buffers\":[{{\"byteLength\":{}}}]",
+                self.meshes.join(","),
+                self.accessors.join(","),
+                self.buffer_views.join(","),
+                (buffer_len + 3) & !3
+            );
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// The GLB format, which writes a point cloud as a single object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct GltfFormat;
+
+impl CloudWriter for GltfFormat {
+    fn extensions(&self) -> &[&str] {
+        &["glb"]
+    }
+
+    fn write(
+        &self,
+        point_cloud: &DynPointCloud,
+        writer: &mut dyn Write,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut scene = GltfScene::new();
+        scene.add_dyn_cloud("cloud", point_cloud, Matrix4::identity())?;
+        scene.write_glb(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Matrix4, Vector3, Vector4};
+    use pcc_common::{
+        point::{Point, Point3Rgba, PointRgba},
+        point_cloud::PointCloud,
+    };
+
+    use super::*;
+
+    /// Splits a GLB file into its JSON and binary chunks.
+    fn read_glb(data: &[u8]) -> (String, Vec<u8>) {
+        let word = |offset: usize| u32::from_le_bytes(data[offset..][..4].try_into().unwrap());
+
+        assert_eq!(word(0), GLB_MAGIC);
+        assert_eq!(word(4), GLB_VERSION);
+        assert_eq!(word(8) as usize, data.len());
+
+        let json_len = word(12) as usize;
+        assert_eq!(word(16), CHUNK_JSON);
+        let json = String::from_utf8(data[20..][..json_len].to_vec()).unwrap();
+
+        let bin = if data.len() > 20 + json_len {
+            let bin_len = word(20 + json_len) as usize;
+            assert_eq!(word(24 + json_len), CHUNK_BIN);
+            data[28 + json_len..][..bin_len].to_vec()
+        } else {
+            Vec::new()
+        };
+        (json, bin)
+    }
+
+    #[test]
+    fn test_gltf() {
+        let pc = PointCloud::from_vec(
+            vec![
+                Point3Rgba::default()
+                    .with_coords(Vector4::new(0., 0., 0., 1.))
+                    .with_rgba(0xffff0000),
+                Point3Rgba::default()
+                    .with_coords(Vector4::new(1., 0., 0., 1.))
+                    .with_rgba(0xff00ff00),
+                Point3Rgba::default()
+                    .with_coords(Vector4::new(0., 1., 0., 1.))
+                    .with_rgba(0xff0000ff),
+            ],
+            1,
+        );
+
+        let mut scene = GltfScene::new();
+        scene
+            .add_rgb_cloud("cloud \"a\"", &pc, Matrix4::identity())
+            .add_mesh(
+                "mesh",
+                &pc,
+                &[[0, 1, 2]],
+                Matrix4::new_translation(&Vector3::new(0., 0., 1.)),
+            );
+        assert_eq!(scene.objects[0].colors.as_ref().unwrap()[0], [1., 0., 0.]);
+
+        let mut output = Vec::new();
+        scene.write_glb(&mut output).expect("Failed to write GLB");
+        assert_eq!(output.len() % 4, 0);
+
+        let (json, bin) = read_glb(&output);
+        assert!(json.contains("\"name\":\"cloud \\\"a\\\"\""));
+        assert!(json.contains("\"COLOR_0\":1"));
+        assert!(json.contains("\"mode\":0"));
+        assert!(json.contains("\"indices\":3,\"mode\":4"));
+        assert!(json.contains("\"matrix\":[1,0,0,0,0,1,0,0,0,0,1,0,0,0,1,1]"));
+        // Two clouds of positions, one of colors and the indices.
+        assert_eq!(bin.len(), 36 * 3 + 12);
+
+        let dyn_pc = DynPointCloud::from_point_cloud(&pc, &Default::default());
+        let mut scene = GltfScene::new();
+        { scene.add_dyn_cloud("dyn", &dyn_pc, Matrix4::identity()) }
+            .expect("Failed to add point cloud");
+        assert_eq!(scene.objects[0].positions[2], [0., 1., 0.]);
+        assert_eq!(scene.objects[0].colors.as_ref().unwrap()[1], [0., 1., 0.]);
+    }
+
+    #[test]
+    fn test_gltf_invalid() {
+        let mut output = Vec::new();
+        GltfScene::new().write_glb(&mut output).unwrap();
+        let (json, bin) = read_glb(&output);
+        assert!(!json.contains("[]"), "{}", json);
+        assert!(json.contains("\"scenes\":[{}]"));
+        assert!(bin.is_empty());
+
+        // Empty objects have nodes but no meshes.
+        let mut scene = GltfScene::new();
+        scene.add_object(GltfObject::new("empty", Vec::new()));
+        let mut output = Vec::new();
+        scene.write_glb(&mut output).unwrap();
+        let (json, _) = read_glb(&output);
+        assert!(!json.contains("[]"), "{}", json);
+        assert!(!json.contains("\"mesh\""));
+
+        let object = GltfObject::new("object", vec![[0., 0., 0.]]);
+        let transform = Matrix4::new_translation(&Vector3::new(f32::NAN, 0., 0.));
+        let mut scene = GltfScene::new();
+        scene.add_object(object.clone().with_transform(transform));
+        assert!(scene.write_glb(Vec::new()).is_err());
+
+        let mut scene = GltfScene::new();
+        scene.add_object(object.clone().with_colors(vec![[f32::INFINITY, 0., 0.]]));
+        assert!(scene.write_glb(Vec::new()).is_err());
+
+        let vertices = PointCloud::from_vec(
+            vec![
+                Point3Rgba::default().with_coords(Vector4::new(0., 0., 0., 1.)),
+                Point3Rgba::default().with_coords(Vector4::new(f32::NAN, 0., 0., 1.)),
+            ],
+            1,
+        );
+        let mut scene = GltfScene::new();
+        scene.add_mesh("mesh", &vertices, &[[0, 1, 0]], Matrix4::identity());
+        assert!(scene.write_glb(Vec::new()).is_err());
+    }
+}
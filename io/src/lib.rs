@@ -1,6 +1,18 @@
 #![feature(iterator_try_collect)]
 
+mod dynamic;
+pub mod gltf;
+pub mod lod;
 mod lzf;
 pub mod pcd;
+pub mod ply;
+pub mod quantize;
+mod registry;
+pub mod tile;
 
-pub use self::pcd::{read_pcd, write_pcd};
+pub use self::{
+    dynamic::DynPointCloud,
+    pcd::{read_pcd, write_pcd},
+    ply::{read_ply, write_ply},
+    registry::{read, write, CloudReader, CloudWriter, PcdFormat, Registry},
+};
@@ -0,0 +1,541 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+    error::Error,
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use nalgebra::{Matrix4, RealField, Scalar, Vector4};
+use num::{FromPrimitive, ToPrimitive};
+use pcc_common::{
+    point::{DataFields, Point},
+    point_cloud::{AsPointCloud, PointCloud},
+};
+
+use crate::pcd::{Pcd, PcdData, PcdFieldData, Viewpoint};
+
+/// The name of the hierarchy file in a LOD directory.
+pub const HIERARCHY_FILE: &str = "hierarchy.lod";
+
+/// The maximum depth of the LOD octree, which stops splitting nodes of
+/// coincident points.
+const MAX_DEPTH: usize = 21;
+
+/// A node of an out-of-core LOD octree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LodNode<T: Scalar> {
+    /// The name of the node, which is `r` followed by the octants of its
+    /// ancestors and itself, like Potree.
+    pub name: String,
+    pub len: usize,
+    /// The side length of the cells of the grid sampling the node, which
+    /// keeps at most one point per cell unless the node is a leaf, as its
+    /// geometric error.
+    pub spacing: T,
+    /// The cubic bound of the node.
+    pub bound: [Vector4<T>; 2],
+    children: Vec<usize>,
+}
+
+impl<T: Scalar> LodNode<T> {
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.name.len() - 1
+    }
+
+    /// The indices of the children of the node in its [`LodIndex`].
+    #[inline]
+    pub fn children(&self) -> &[usize] {
+        &self.children
+    }
+}
+
+/// Builds a LOD octree, storing every node as a PCD file.
+///
+/// Each node keeps a subsample of its points with at most one point in every
+/// cell of a `resolution`^3 grid over its bound, and passes the rest to its
+/// children. Nodes with at most `max_points` points keep all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LodBuilder {
+    pub max_points: usize,
+    pub resolution: usize,
+    pub data: PcdData,
+}
+
+impl LodBuilder {
+    pub fn new(max_points: usize, resolution: usize, data: PcdData) -> Self {
+        LodBuilder {
+            max_points,
+            resolution,
+            data,
+        }
+    }
+
+    /// Builds the LOD octree of the finite points of `point_cloud` into
+    /// `dir`, which is created if absent.
+    pub fn build<P, Q>(
+        &self,
+        point_cloud: &PointCloud<P>,
+        viewpoint: &Viewpoint,
+        dir: Q,
+    ) -> Result<LodIndex<P::Data>, Box<dyn Error>>
+    where
+        P: Point + DataFields,
+        P::Data: RealField + ToPrimitive + PcdFieldData,
+        Q: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        let mut index = LodIndex {
+            dir: dir.to_owned(),
+            nodes: Vec::new(),
+        };
+        if let Some([min, max]) = point_cloud.finite_bound() {
+            let edge = (&max - &min).xyz().max();
+            let max = min.clone() + Vector4::new(edge.clone(), edge.clone(), edge, num::zero());
+            let indices = { point_cloud.iter().enumerate() }
+                .filter(|(_, point)| point.is_finite())
+                .map(|(index, _)| index)
+                .collect();
+
+            let mut builder = Builder {
+                options: self,
+                point_cloud,
+                viewpoint,
+                index: &mut index,
+            };
+            builder.build(indices, [min, max], "r".to_owned())?;
+        }
+        index.write_hierarchy()?;
+        Ok(index)
+    }
+}
+
+struct Builder<'a, P: Point> {
+    options: &'a LodBuilder,
+    point_cloud: &'a PointCloud<P>,
+    viewpoint: &'a Viewpoint,
+    index: &'a mut LodIndex<P::Data>,
+}
+
+impl<'a, T, P> Builder<'a, P>
+where
+    T: RealField + ToPrimitive + PcdFieldData,
+    P: Point<Data = T> + DataFields,
+{
+    fn build(
+        &mut self,
+        indices: Vec<usize>,
+        [min, max]: [Vector4<P::Data>; 2],
+        name: String,
+    ) -> Result<usize, Box<dyn Error>> {
+        let resolution = self.options.resolution.max(1);
+        let spacing = (max.x.clone() - min.x.clone()) / T::from_usize(resolution).unwrap();
+
+        let (selected, rest) = if indices.len() <= self.options.max_points || name.len() > MAX_DEPTH
+        {
+            (indices, Vec::new())
+        } else {
+            let mut cells = HashSet::new();
+            let mut selected = Vec::new();
+            let mut rest = Vec::new();
+            for index in indices {
+                let key = (self.point_cloud[index].coords() - &min)
+                    .map(|x| (x / spacing.clone()).floor().to_usize().unwrap_or(0))
+                    .map(|x| x.min(resolution - 1));
+                if cells.insert(*key.xyz().as_ref()) {
+                    selected.push(index);
+                } else {
+                    rest.push(index);
+                }
+            }
+            (selected, rest)
+        };
+
+        let storage = { selected.iter() }
+            .map(|&index| self.point_cloud[index].clone())
+            .collect::<Vec<_>>();
+        let node_pc = PointCloud::from_vec(storage, selected.len());
+        let pcd = Pcd::from_point_cloud(&node_pc, self.viewpoint, self.options.data);
+        let mut writer = BufWriter::new(File::create(self.index.dir.join(node_file(&name)))?);
+        pcd.write(&mut writer)?;
+        writer.flush()?;
+
+        let id = self.index.nodes.len();
+        self.index.nodes.push(LodNode {
+            name: name.clone(),
+            len: selected.len(),
+            spacing,
+            bound: [min.clone(), max.clone()],
+            children: Vec::new(),
+        });
+
+        let center = (&min + &max) / nalgebra::convert::<_, P::Data>(2.);
+        let mut octants: [Vec<usize>; 8] = Default::default();
+        for index in rest {
+            octants[octant(self.point_cloud[index].coords(), &center)].push(index);
+        }
+
+        for (octant, indices) in octants.into_iter().enumerate() {
+            if indices.is_empty() {
+                continue;
+            }
+            let mut child_min = min.clone();
+            let mut child_max = center.clone();
+            for axis in 0..3 {
+                if octant & (1 << axis) != 0 {
+                    child_min[axis] = center[axis].clone();
+                    child_max[axis] = max[axis].clone();
+                }
+            }
+            let child_name = format!("{}{}", name, octant);
+            let child = self.build(indices, [child_min, child_max], child_name)?;
+            self.index.nodes[id].children.push(child);
+        }
+        Ok(id)
+    }
+}
+
+fn octant<T: RealField>(coords: &Vector4<T>, center: &Vector4<T>) -> usize {
+    (coords.x > center.x) as usize
+        | ((coords.y > center.y) as usize) << 1
+        | ((coords.z > center.z) as usize) << 2
+}
+
+fn node_file(name: &str) -> String {
+    format!("{}.pcd", name)
+}
+
+/// A view frustum, bounded by the planes where `plane.dot(point) >= 0` for
+/// the homogeneous points inside.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frustum<T: Scalar> {
+    pub planes: [Vector4<T>; 6],
+}
+
+impl<T: RealField> Frustum<T> {
+    /// Extracts the frustum of a projection-view matrix, whose clip space
+    /// spans `[-w, w]` in every axis.
+    pub fn from_matrix(matrix: &Matrix4<T>) -> Self {
+        let row = |index: usize| matrix.row(index).transpose();
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Frustum {
+            planes: [&w + &x, &w - &x, &w + &y, &w - &y, &w + &z, &w - &z],
+        }
+    }
+
+    /// Checks whether the box of `min` and `max` is at least partially inside
+    /// the frustum.
+    pub fn intersects(&self, [min, max]: &[Vector4<T>; 2]) -> bool {
+        self.planes.iter().all(|plane| {
+            let farthest = Vector4::from_fn(|axis, _| match axis {
+                3 => T::one(),
+                _ if plane[axis] >= T::zero() => max[axis].clone(),
+                _ => min[axis].clone(),
+            });
+            plane.dot(&farthest) >= T::zero()
+        })
+    }
+}
+
+/// The parameters of selecting LOD nodes to stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LodQuery<T: Scalar> {
+    pub frustum: Frustum<T>,
+    pub camera: Vector4<T>,
+    /// The maximum projected error, which is the spacing of a node divided by
+    /// its distance to the camera. Nodes coarser than it are refined.
+    pub max_error: T,
+    /// The budget of the total number of points to stream.
+    pub max_points: usize,
+}
+
+struct Candidate<T> {
+    error: T,
+    id: usize,
+}
+
+impl<T: PartialOrd> PartialEq for Candidate<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: PartialOrd> Eq for Candidate<T> {}
+
+impl<T: PartialOrd> PartialOrd for Candidate<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: PartialOrd> Ord for Candidate<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        { self.error.partial_cmp(&other.error) }
+            .unwrap_or(Ordering::Equal)
+            .then(other.id.cmp(&self.id))
+    }
+}
+
+/// The hierarchy of a LOD directory, which loads the nodes lazily.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LodIndex<T: Scalar> {
+    dir: PathBuf,
+    pub nodes: Vec<LodNode<T>>,
+}
+
+impl<T: Scalar> LodIndex<T> {
+    #[inline]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    #[inline]
+    pub fn root(&self) -> Option<&LodNode<T>> {
+        self.nodes.first()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.nodes.iter().map(|node| node.len).sum()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Scalar + ToPrimitive> LodIndex<T> {
+    fn write_hierarchy(&self) -> Result<(), Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(self.dir.join(HIERARCHY_FILE))?);
+        writeln!(writer, "# Point cloud LOD hierarchy")?;
+        writeln!(writer, "NODES {}", self.nodes.len())?;
+        for node in &self.nodes {
+            write!(
+                writer,
+                "{} {} {}",
+                node.name,
+                node.len,
+                node.spacing.to_f64().unwrap()
+            )?;
+            for value in node.bound.iter().flat_map(|bound| bound.iter().take(3)) {
+                write!(writer, " {}", value.to_f64().unwrap())?;
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<T: RealField + FromPrimitive> LodIndex<T> {
+    /// Opens the hierarchy of the LOD directory `dir`, without loading any
+    /// node.
+    pub fn open<Q: AsRef<Path>>(dir: Q) -> Result<Self, Box<dyn Error>> {
+        let dir = dir.as_ref();
+        let reader = BufReader::new(File::open(dir.join(HIERARCHY_FILE))?);
+
+        let mut num = None;
+        let mut nodes = Vec::<LodNode<T>>::new();
+        let mut ids = HashMap::<String, usize>::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            if let Some(data) = line.strip_prefix("NODES ") {
+                num = Some(data.trim().parse::<usize>()?);
+                continue;
+            }
+
+            let mut items = line.split_whitespace();
+            let name = items.next().unwrap().to_owned();
+            let len = { items.next() }
+                .ok_or_else(|| format!("Missing node length: {:?}", line))?
+                .parse()?;
+            let values = items
+                .map(|item| item.parse::<f64>().map(|value| T::from_f64(value).unwrap()))
+                .try_collect::<Vec<_>>()?;
+            if values.len() != 7 {
+                return Err(format!("Invalid node data: {:?}", line).into());
+            }
+
+            let id = nodes.len();
+            if name.len() > 1 {
+                let parent = { ids.get(&name[..name.len() - 1]) }
+                    .ok_or_else(|| format!("Missing parent node: {:?}", name))?;
+                nodes[*parent].children.push(id);
+            }
+            ids.insert(name.clone(), id);
+
+            let bound = [
+                Vector4::new(
+                    values[1].clone(),
+                    values[2].clone(),
+                    values[3].clone(),
+                    T::one(),
+                ),
+                Vector4::new(
+                    values[4].clone(),
+                    values[5].clone(),
+                    values[6].clone(),
+                    T::one(),
+                ),
+            ];
+            nodes.push(LodNode {
+                name,
+                len,
+                spacing: values[0].clone(),
+                bound,
+                children: Vec::new(),
+            });
+        }
+
+        if num != Some(nodes.len()) {
+            return Err("NODES conflicts with the number of nodes".into());
+        }
+        Ok(LodIndex {
+            dir: dir.to_owned(),
+            nodes,
+        })
+    }
+
+    fn projected_error(&self, node: &LodNode<T>, camera: &Vector4<T>) -> T {
+        let [min, max] = &node.bound;
+        let center = (min + max) / nalgebra::convert::<_, T>(2.);
+        let radius = (max - min).xyz().norm() / nalgebra::convert::<_, T>(2.);
+        let distance = (center - camera).xyz().norm() - radius;
+        node.spacing.clone() / distance.max(T::default_epsilon())
+    }
+
+    /// Selects the nodes to stream for `query`, from the coarsest to the
+    /// finest. A node is selected only after its parent, so the points of
+    /// the selected nodes always add up to a valid LOD.
+    pub fn select(&self, query: &LodQuery<T>) -> Vec<&LodNode<T>> {
+        let mut selected = Vec::new();
+        let mut num_points = 0;
+
+        let mut candidates = BinaryHeap::new();
+        if let Some(root) = self.root() {
+            if query.frustum.intersects(&root.bound) {
+                let error = self.projected_error(root, &query.camera);
+                candidates.push(Candidate { error, id: 0 });
+            }
+        }
+
+        while let Some(Candidate { error, id }) = candidates.pop() {
+            let node = &self.nodes[id];
+            if num_points + node.len > query.max_points {
+                continue;
+            }
+            num_points += node.len;
+            selected.push(node);
+
+            if error <= query.max_error {
+                continue;
+            }
+            for &child in &node.children {
+                let child_node = &self.nodes[child];
+                if query.frustum.intersects(&child_node.bound) {
+                    let error = self.projected_error(child_node, &query.camera);
+                    candidates.push(Candidate { error, id: child });
+                }
+            }
+        }
+
+        selected
+    }
+
+    pub fn load<P>(&self, node: &LodNode<T>) -> Result<PointCloud<P>, Box<dyn Error>>
+    where
+        P: Point<Data = T> + DataFields,
+    {
+        let reader = BufReader::new(File::open(self.dir.join(node_file(&node.name)))?);
+        let (point_cloud, _) = Pcd::read(reader)?.to_point_cloud()?;
+        Ok(point_cloud)
+    }
+
+    /// Streams the nodes selected for `query`, loading each of them only when
+    /// the iterator reaches it.
+    pub fn stream<'a, P>(
+        &'a self,
+        query: &LodQuery<T>,
+    ) -> impl Iterator<Item = Result<(&'a LodNode<T>, PointCloud<P>), Box<dyn Error>>> + 'a
+    where
+        P: Point<Data = T> + DataFields,
+    {
+        { self.select(query).into_iter() }
+            .map(move |node| self.load(node).map(|point_cloud| (node, point_cloud)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Isometry3, Perspective3, Point3 as NaPoint3, Vector3, Vector4};
+    use pcc_common::{
+        point::{Point, Point3},
+        point_cloud::PointCloud,
+    };
+
+    use super::*;
+
+    fn query(eye: NaPoint3<f32>, target: NaPoint3<f32>) -> LodQuery<f32> {
+        let view = Isometry3::look_at_rh(&eye, &target, &Vector3::y());
+        let projection = Perspective3::new(1., 1., 0.1, 1000.);
+        LodQuery {
+            frustum: Frustum::from_matrix(&(projection.as_matrix() * view.to_homogeneous())),
+            camera: eye.to_homogeneous(),
+            max_error: 0.,
+            max_points: usize::MAX,
+        }
+    }
+
+    #[test]
+    fn test_lod() {
+        let storage = (0..8000)
+            .map(|index| {
+                let coords = Vector4::new(
+                    (index % 20) as f32,
+                    (index / 20 % 20) as f32,
+                    (index / 400) as f32,
+                    1.,
+                );
+                Point3::default().with_coords(coords)
+            })
+            .collect::<Vec<_>>();
+        let pc = PointCloud::from_vec(storage, 1);
+
+        let dir = tempfile::tempdir().expect("Failed to create test directory");
+        let builder = LodBuilder::new(500, 4, PcdData::Binary);
+        let index = { builder.build(&pc, &Default::default(), dir.path()) }
+            .expect("Failed to build LOD octree");
+        assert_eq!(index.len(), 8000);
+        assert_eq!(index.root().unwrap().len, 64);
+
+        let index2 = LodIndex::open(dir.path()).expect("Failed to open LOD octree");
+        assert_eq!(index, index2);
+
+        let mut q = query(NaPoint3::new(10., 10., -30.), NaPoint3::new(10., 10., 10.));
+        let total = { index2.stream::<Point3>(&q) }
+            .map(|result| result.expect("Failed to load node").1.len())
+            .sum::<usize>();
+        assert_eq!(total, 8000);
+
+        q.max_error = f32::MAX;
+        assert_eq!(index2.select(&q).len(), 1);
+
+        q.max_error = 0.;
+        q.max_points = 1000;
+        let selected = index2.select(&q);
+        assert!(selected.iter().map(|node| node.len).sum::<usize>() <= 1000);
+        assert_eq!(selected[0].name, "r");
+
+        let q = query(NaPoint3::new(10., 10., -30.), NaPoint3::new(10., 10., -60.));
+        assert!(index2.select(&q).is_empty());
+    }
+}
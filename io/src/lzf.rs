@@ -12,7 +12,7 @@ use std::fmt;
 
 mod compress;
 mod decompress;
-pub use compress::compress;
+pub use compress::{compress, store};
 pub use decompress::decompress;
 
 #[derive(PartialEq, Eq, Clone, Debug, Copy)]
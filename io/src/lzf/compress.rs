@@ -132,7 +132,7 @@ pub fn compress(data: &[u8]) -> LzfResult<Vec<u8>> {
             current_offset += 1;
         } else {
             // one more literal byte we must copy
-            if current_offset >= out_buf_len {
+            if out_len as usize >= out_buf_len {
                 return Err(LzfError::NoCompressionPossible);
             }
 
@@ -178,6 +178,17 @@ pub fn compress(data: &[u8]) -> LzfResult<Vec<u8>> {
     Ok(out)
 }
 
+/// Stores the given data as literal runs only, which is valid lzf data for the
+/// input that [`compress`] can't shrink.
+pub fn store(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / MAX_LIT as usize + 1);
+    for run in data.chunks(MAX_LIT as usize) {
+        out.push((run.len() - 1) as u8);
+        out.extend_from_slice(run);
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -191,6 +202,24 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_store_incompressible() {
+        // An xorshift sequence, which has no repeats for lzf to refer to.
+        let mut state = 0x2545_f491_u32;
+        let data = { 0..1000 }
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(compress(&data), Err(LzfError::NoCompressionPossible));
+
+        let stored = store(&data);
+        assert_eq!(decompress(&stored, data.len()).unwrap(), data);
+    }
+
     #[test]
     fn test_compress_lorem() {
         let lorem = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod tempor invidunt ut labore et dolore magna aliquyam erat, sed diam voluptua. At vero eos et accusam et justo duo dolores et ea rebum. Stet clita kasd gubergren, no sea takimata sanctus est Lorem ipsum dolor sit amet. Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod tempor invidunt ut labore et dolore magna aliquyam erat, sed diam voluptua.";
@@ -14,7 +14,11 @@ use pcc_common::{
     point_cloud::PointCloud,
 };
 
-pub use self::convert::Viewpoint;
+pub use self::{
+    convert::Viewpoint,
+    read::{PcdPoints, PcdReader},
+    write::{AsciiOptions, FloatFormat},
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct PcdField {
@@ -154,6 +158,9 @@ pub enum PcdData {
     Ascii,
     Binary,
     BinaryCompressed,
+    /// Like `BinaryCompressed`, but compressed with zstd. This is not a
+    /// standard PCD data type, so PCL cannot read it.
+    BinaryZstd,
 }
 
 impl PcdData {
@@ -162,6 +169,16 @@ impl PcdData {
             PcdData::Ascii => "ascii",
             PcdData::Binary => "binary",
             PcdData::BinaryCompressed => "binary_compressed",
+            PcdData::BinaryZstd => "binary_zstd",
+        }
+    }
+
+    /// The data type actually written, which falls back to
+    /// `BinaryCompressed` for `BinaryZstd` without the `zstd` feature.
+    pub fn supported(&self) -> Self {
+        match self {
+            PcdData::BinaryZstd if !cfg!(feature = "zstd") => PcdData::BinaryCompressed,
+            &data => data,
         }
     }
 }
@@ -196,9 +213,21 @@ impl Pcd {
         })
     }
 
-    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn Error>> {
+    #[inline]
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        self.write_with(&Default::default(), writer)
+    }
+
+    /// Writes the PCD, formatting ASCII data with `options`.
+    pub fn write_with<W: Write>(
+        &self,
+        options: &AsciiOptions,
+        mut writer: W,
+    ) -> Result<(), Box<dyn Error>> {
         self.header.write(&mut writer)?;
-        self.header.data.write(&self.data, &self.header, writer)?;
+        self.header
+            .data
+            .write_with(&self.data, &self.header, options, writer)?;
         Ok(())
     }
 }
@@ -235,13 +264,104 @@ mod tests {
 
     use nalgebra::Vector4;
     use pcc_common::{
-        point::{Normal, Point, Point3LN, PointLabel},
+        point::{Normal, Point, Point3, Point3LN, PointLabel},
         point_cloud::PointCloud,
     };
 
-    use super::PcdData;
+    use super::{AsciiOptions, FloatFormat, PcdData, PcdReader};
     use crate::pcd::Pcd;
 
+    #[test]
+    fn test_ascii_options() {
+        let pc = PointCloud::from_vec(
+            vec![Point3::default().with_coords(Vector4::new(1.0, f32::NAN, 1234.5678, 1.)); 2],
+            1,
+        );
+        let pcd = Pcd::from_point_cloud(&pc, &Default::default(), PcdData::Ascii);
+
+        let options = AsciiOptions::new(FloatFormat::Fixed(2), true)
+            .with_format("z", FloatFormat::Scientific(3));
+        let mut output = Vec::new();
+        pcd.write_with(&options, &mut output)
+            .expect("Failed to write test data");
+
+        let text = String::from_utf8(output).unwrap();
+        assert!(text.ends_with("DATA ascii\n1.00 nan 1.235e3\n1.00 nan 1.235e3\n"));
+
+        let pcd2 = Pcd::read(text.as_bytes()).expect("Failed to read test data");
+        let (pc2, _) = { pcd2.to_point_cloud::<Point3>() }.expect("Failed to convert point cloud");
+        assert_eq!(pc2[0].coords().x, 1.);
+        assert!(pc2[0].coords().y.is_nan());
+        assert_eq!(pc2[0].coords().z, 1235.);
+    }
+
+    #[test]
+    fn test_unmapped_fields() {
+        // The extra field before `y` must not shift the fields after it.
+        let text = "FIELDS x intensity y z\nSIZE 4 4 4 4\nTYPE F F F F\nCOUNT 1 1 1 1\n\
+            WIDTH 1\nHEIGHT 1\nPOINTS 1\nDATA ascii\n1 9 2 3\n";
+        let pcd = Pcd::read(text.as_bytes()).expect("Failed to read test data");
+        let (pc, _) = { pcd.to_point_cloud::<Point3>() }.expect("Failed to convert point cloud");
+        let coords = pc[0].coords();
+        assert_eq!([coords.x, coords.y, coords.z], [1., 2., 3.]);
+
+        // The missing field keeps its default value.
+        let text = "FIELDS x y\nSIZE 4 4\nTYPE F F\nCOUNT 1 1\n\
+            WIDTH 1\nHEIGHT 1\nPOINTS 1\nDATA ascii\n1 2\n";
+        let pcd = Pcd::read(text.as_bytes()).expect("Failed to read test data");
+        let (pc, _) = { pcd.to_point_cloud::<Point3>() }.expect("Failed to convert point cloud");
+        let coords = pc[0].coords();
+        assert_eq!([coords.x, coords.y, coords.z], [1., 2., 0.]);
+    }
+
+    #[test]
+    fn test_zstd() {
+        let pc = PointCloud::from_vec(
+            vec![Point3::default().with_coords(Vector4::new(1., 2., 3., 1.)); 16],
+            4,
+        );
+        let pcd = Pcd::from_point_cloud(&pc, &Default::default(), PcdData::BinaryZstd);
+
+        let mut output = Vec::new();
+        pcd.write(&mut output).expect("Failed to write test data");
+
+        let pcd2 = Pcd::read(&*output).expect("Failed to read test data");
+        let expected = if cfg!(feature = "zstd") {
+            PcdData::BinaryZstd
+        } else {
+            PcdData::BinaryCompressed
+        };
+        assert_eq!(pcd2.header.data, expected);
+
+        let (pc2, _) = { pcd2.to_point_cloud::<Point3>() }.expect("Failed to convert point cloud");
+        assert_eq!(pc, pc2);
+    }
+
+    #[test]
+    fn test_incompressible() {
+        // An xorshift sequence of coordinates, which LZF can't shrink.
+        let mut state = 0x2545_f491_u32;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            f32::from_bits(state & 0x3fff_ffff)
+        };
+        let storage = { 0..300 }
+            .map(|_| Point3::default().with_coords(Vector4::new(next(), next(), next(), 1.)))
+            .collect::<Vec<_>>();
+        let pc = PointCloud::from_vec(storage, 1);
+
+        let pcd = Pcd::from_point_cloud(&pc, &Default::default(), PcdData::BinaryCompressed);
+        let mut output = Vec::new();
+        pcd.write(&mut output).expect("Failed to write test data");
+
+        let pcd2 = Pcd::read(&*output).expect("Failed to read test data");
+        assert_eq!(pcd2.header.data, PcdData::BinaryCompressed);
+        let (pc2, _) = { pcd2.to_point_cloud::<Point3>() }.expect("Failed to convert point cloud");
+        assert_eq!(pc, pc2);
+    }
+
     #[test]
     fn test_io_pcd() {
         let pc = PointCloud::from_vec(
@@ -256,23 +376,51 @@ mod tests {
             2,
         );
 
-        let mut file = tempfile::tempfile().expect("Failed to open test file");
+        for data_type in [PcdData::Ascii, PcdData::Binary, PcdData::BinaryCompressed] {
+            let mut file = tempfile::tempfile().expect("Failed to open test file");
 
-        let pcd = Pcd::from_point_cloud(&pc, &Default::default(), PcdData::BinaryCompressed);
+            let pcd = Pcd::from_point_cloud(&pc, &Default::default(), data_type);
 
-        pcd.write(&mut file).expect("Failed to write test file");
+            pcd.write(&mut file).expect("Failed to write test file");
 
-        file.seek(SeekFrom::Start(0))
-            .expect("Failed to seek to start");
+            file.seek(SeekFrom::Start(0))
+                .expect("Failed to seek to start");
 
-        let pcd2 = Pcd::read(BufReader::new(file)).expect("Failed to read test file");
+            let pcd2 = Pcd::read(BufReader::new(file)).expect("Failed to read test file");
 
-        assert_eq!(pcd, pcd2);
+            assert_eq!(pcd, pcd2);
 
-        let (pc2, _) = pcd2
-            .to_point_cloud()
-            .expect("Failed to convert point cloud");
+            let (pc2, _) = pcd2
+                .to_point_cloud()
+                .expect("Failed to convert point cloud");
 
-        assert_eq!(pc, pc2);
+            assert_eq!(pc, pc2);
+        }
+    }
+
+    #[test]
+    fn test_pcd_reader() {
+        let pc = PointCloud::from_vec(
+            { (0..6).map(|i| i as f32) }
+                .map(|i| Point3::default().with_coords(Vector4::new(i, -i, 1., 1.)))
+                .collect(),
+            3,
+        );
+
+        for data_type in [PcdData::Ascii, PcdData::Binary, PcdData::BinaryCompressed] {
+            let mut output = Vec::new();
+            { Pcd::from_point_cloud(&pc, &Default::default(), data_type) }
+                .write(&mut output)
+                .expect("Failed to write test data");
+
+            let reader = PcdReader::new(&*output).expect("Failed to read test header");
+            assert_eq!(reader.header().width, 3);
+            let points = { reader.points::<Point3>() }
+                .expect("Failed to read test data")
+                .filter(|point| !matches!(point, Ok(point) if point.coords().x < 2.))
+                .collect::<Result<Vec<_>, _>>()
+                .expect("Failed to read test data");
+            assert_eq!(points, { pc.iter() }.skip(2).cloned().collect::<Vec<_>>());
+        }
     }
 }
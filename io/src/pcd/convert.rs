@@ -1,11 +1,11 @@
 use core::slice;
 use std::{error::Error, mem};
 
-use nalgebra::{Quaternion, Vector3};
+use nalgebra::{Quaternion, UnitQuaternion, Vector3};
 use num::FromPrimitive;
 use pcc_common::{
-    point::{Data, DataFields},
-    point_cloud::PointCloud,
+    point::{Data, DataFields, FieldInfo},
+    point_cloud::{Metadata, PointCloud},
 };
 
 use super::{Pcd, PcdData, PcdField, PcdFieldData, PcdFieldType, PcdHeader};
@@ -16,6 +16,37 @@ pub struct Viewpoint {
     pub quat: Quaternion<f32>,
 }
 
+impl Viewpoint {
+    /// Converts the viewpoint to the sensor pose of point clouds. Degenerate
+    /// quaternions are regarded as the identity.
+    pub fn to_metadata(&self) -> Metadata {
+        let orientation = UnitQuaternion::try_new(self.quat, f32::EPSILON);
+        Metadata {
+            sensor_origin: self.origin,
+            sensor_orientation: orientation.unwrap_or_else(UnitQuaternion::identity),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&PcdHeader> for Viewpoint {
+    fn from(header: &PcdHeader) -> Self {
+        Viewpoint {
+            origin: header.viewpoint_origin,
+            quat: header.viewpoint_quat,
+        }
+    }
+}
+
+impl From<&Metadata> for Viewpoint {
+    fn from(metadata: &Metadata) -> Self {
+        Viewpoint {
+            origin: metadata.sensor_origin,
+            quat: metadata.sensor_orientation.into_inner(),
+        }
+    }
+}
+
 impl Pcd {
     pub fn from_point_cloud<P>(
         point_cloud: &PointCloud<P>,
@@ -69,131 +100,142 @@ impl Pcd {
         P: Data + DataFields,
         P::Data: FromPrimitive,
     {
-        let fields = {
-            let mut fields = <P as DataFields>::fields()
-                .map(|field| (field, None))
-                .collect::<Vec<_>>();
-            fields.sort_by_key(|(field, _)| field.name);
-            for pcd_field in self.header.fields {
-                let entry = match &*pcd_field.name {
-                    "rgb" => fields.binary_search_by_key(&"rgba", |(field, _)| field.name),
-                    name => fields.binary_search_by_key(&name, |(field, _)| field.name),
-                };
-                if let Ok((_, pcds)) = entry.map(|index| &mut fields[index]) {
-                    if let Some(old) = pcds.replace(pcd_field) {
-                        return Err(format!(
-                            "Found multiple fields in PCD file matching one field in the point cloud: {:?}", 
-                            old
-                        ).into());
-                    }
-                }
-            }
-            if fields.iter().any(|(_, pcd)| pcd.is_none()) {
-                log::warn!(
-                    "Found a field in the point cloud with no matching field in the PCD file, 
-keeping with default values"
-                )
-            }
-            fields.sort_by_key(|(field, _)| field.offset);
-            fields
-        };
+        let fields = map_fields::<P>(&self.header.fields)?;
 
         let mut storage = vec![P::default(); self.header.width * self.header.height];
         for (src, dst) in { self.data.chunks(self.header.rec_size) }.zip(storage.iter_mut()) {
-            let mut pcd_offset = 0;
-            let dst_slice = dst.as_mut_slice();
-
-            for (field, pcd_field) in
-                { fields.iter() }.map(|(field, pcd_field)| (field, pcd_field.as_ref().unwrap()))
-            {
-                let dst = &mut dst_slice[field.offset..][..field.len];
-                let src = &src[pcd_offset..][..(pcd_field.ty.size() * pcd_field.count)];
-                match pcd_field.ty {
-                    PcdFieldType::U8 => {
-                        for (src, dst) in src.iter().zip(dst.iter_mut()) {
-                            *dst = P::Data::from_u8(*src).unwrap();
-                        }
-                    }
-                    PcdFieldType::I8 => {
-                        for (src, dst) in src.iter().zip(dst.iter_mut()) {
-                            *dst = P::Data::from_i8(*src as i8).unwrap();
-                        }
-                    }
-                    PcdFieldType::U16 => {
-                        for (src, dst) in src.chunks(2).zip(dst.iter_mut()) {
-                            *dst = P::Data::from_u16(u16::from_ne_bytes(src.try_into().unwrap()))
-                                .unwrap();
-                        }
-                    }
-                    PcdFieldType::I16 => {
-                        for (src, dst) in src.chunks(2).zip(dst.iter_mut()) {
-                            *dst = P::Data::from_i16(i16::from_ne_bytes(src.try_into().unwrap()))
-                                .unwrap();
-                        }
-                    }
-                    PcdFieldType::U32 => {
-                        for (src, dst) in src.chunks(4).zip(dst.iter_mut()) {
-                            *dst = P::Data::from_u32(u32::from_ne_bytes(src.try_into().unwrap()))
-                                .unwrap();
-                        }
-                    }
-                    PcdFieldType::I32 => {
-                        for (src, dst) in src.chunks(4).zip(dst.iter_mut()) {
-                            *dst = P::Data::from_i32(i32::from_ne_bytes(src.try_into().unwrap()))
-                                .unwrap();
-                        }
-                    }
-                    PcdFieldType::F32 => {
-                        for (src, dst) in src.chunks(4).zip(dst.iter_mut()) {
-                            *dst = P::Data::from_f32(f32::from_ne_bytes(src.try_into().unwrap()))
-                                .unwrap();
-                        }
-                    }
-                    PcdFieldType::U64 => {
-                        for (src, dst) in src.chunks(8).zip(dst.iter_mut()) {
-                            *dst = P::Data::from_u64(u64::from_ne_bytes(src.try_into().unwrap()))
-                                .unwrap();
-                        }
-                    }
-                    PcdFieldType::I64 => {
-                        for (src, dst) in src.chunks(8).zip(dst.iter_mut()) {
-                            *dst = P::Data::from_i64(i64::from_ne_bytes(src.try_into().unwrap()))
-                                .unwrap();
-                        }
-                    }
-                    PcdFieldType::F64 => {
-                        for (src, dst) in src.chunks(8).zip(dst.iter_mut()) {
-                            *dst = P::Data::from_f64(f64::from_ne_bytes(src.try_into().unwrap()))
-                                .unwrap();
-                        }
-                    }
-                    PcdFieldType::U128 => {
-                        for (src, dst) in src.chunks(16).zip(dst.iter_mut()) {
-                            *dst = P::Data::from_u128(u128::from_ne_bytes(src.try_into().unwrap()))
-                                .unwrap();
-                        }
-                    }
-                    PcdFieldType::I128 => {
-                        for (src, dst) in src.chunks(16).zip(dst.iter_mut()) {
-                            *dst = P::Data::from_i128(i128::from_ne_bytes(src.try_into().unwrap()))
-                                .unwrap();
-                        }
-                    }
-                }
-                pcd_offset += src.len();
-            }
+            convert_record(&fields, src, dst);
         }
 
+        let viewpoint = Viewpoint::from(&self.header);
         let point_cloud =
-            unsafe { PointCloud::from_raw_parts(storage, self.header.width, self.finite) };
-        let viewpoint = Viewpoint {
-            origin: self.header.viewpoint_origin,
-            quat: self.header.viewpoint_quat,
-        };
+            unsafe { PointCloud::from_raw_parts(storage, self.header.width, self.finite) }
+                .with_metadata(viewpoint.to_metadata());
         Ok((point_cloud, viewpoint))
     }
 }
 
+/// The fields of a point type, each with its matching PCD field and the
+/// offset of that in the records, if any.
+pub(super) type FieldMap = Vec<(FieldInfo, Option<(PcdField, usize)>)>;
+
+pub(super) fn map_fields<P: DataFields>(
+    pcd_fields: &[PcdField],
+) -> Result<FieldMap, Box<dyn Error>> {
+    let mut fields = <P as DataFields>::fields()
+        .map(|field| (field, None))
+        .collect::<Vec<_>>();
+    fields.sort_by_key(|(field, _)| field.name);
+    // The PCD fields are kept with their offsets in the records, so that the
+    // ones not in the point cloud are skipped.
+    let mut pcd_offset = 0;
+    for pcd_field in pcd_fields {
+        let size = pcd_field.ty.size() * pcd_field.count;
+        let entry = match &*pcd_field.name {
+            "rgb" => fields.binary_search_by_key(&"rgba", |(field, _)| field.name),
+            name => fields.binary_search_by_key(&name, |(field, _)| field.name),
+        };
+        if let Ok((_, pcds)) = entry.map(|index| &mut fields[index]) {
+            if let Some((old, _)) = pcds.replace((pcd_field.clone(), pcd_offset)) {
+                return Err(format!(
+                    "Found multiple fields in PCD file matching one field in the point cloud: {:?}",
+                    old
+                )
+                .into());
+            }
+        }
+        pcd_offset += size;
+    }
+    if fields.iter().any(|(_, pcd)| pcd.is_none()) {
+        log::warn!(
+            "Found a field in the point cloud with no matching field in the PCD file, 
+keeping with default values"
+        )
+    }
+    fields.sort_by_key(|(field, _)| field.offset);
+    Ok(fields)
+}
+
+/// Converts a PCD record into the mapped fields of `dst`.
+pub(super) fn convert_record<P>(fields: &FieldMap, src: &[u8], dst: &mut P)
+where
+    P: Data,
+    P::Data: FromPrimitive,
+{
+    let dst_slice = dst.as_mut_slice();
+
+    for (field, (pcd_field, pcd_offset)) in
+        { fields.iter() }.filter_map(|(field, pcd)| Some((field, pcd.as_ref()?)))
+    {
+        let dst = &mut dst_slice[field.offset..][..field.len];
+        let src = &src[*pcd_offset..][..(pcd_field.ty.size() * pcd_field.count)];
+        match pcd_field.ty {
+            PcdFieldType::U8 => {
+                for (src, dst) in src.iter().zip(dst.iter_mut()) {
+                    *dst = P::Data::from_u8(*src).unwrap();
+                }
+            }
+            PcdFieldType::I8 => {
+                for (src, dst) in src.iter().zip(dst.iter_mut()) {
+                    *dst = P::Data::from_i8(*src as i8).unwrap();
+                }
+            }
+            PcdFieldType::U16 => {
+                for (src, dst) in src.chunks(2).zip(dst.iter_mut()) {
+                    *dst = P::Data::from_u16(u16::from_ne_bytes(src.try_into().unwrap())).unwrap();
+                }
+            }
+            PcdFieldType::I16 => {
+                for (src, dst) in src.chunks(2).zip(dst.iter_mut()) {
+                    *dst = P::Data::from_i16(i16::from_ne_bytes(src.try_into().unwrap())).unwrap();
+                }
+            }
+            PcdFieldType::U32 => {
+                for (src, dst) in src.chunks(4).zip(dst.iter_mut()) {
+                    *dst = P::Data::from_u32(u32::from_ne_bytes(src.try_into().unwrap())).unwrap();
+                }
+            }
+            PcdFieldType::I32 => {
+                for (src, dst) in src.chunks(4).zip(dst.iter_mut()) {
+                    *dst = P::Data::from_i32(i32::from_ne_bytes(src.try_into().unwrap())).unwrap();
+                }
+            }
+            PcdFieldType::F32 => {
+                for (src, dst) in src.chunks(4).zip(dst.iter_mut()) {
+                    *dst = P::Data::from_f32(f32::from_ne_bytes(src.try_into().unwrap())).unwrap();
+                }
+            }
+            PcdFieldType::U64 => {
+                for (src, dst) in src.chunks(8).zip(dst.iter_mut()) {
+                    *dst = P::Data::from_u64(u64::from_ne_bytes(src.try_into().unwrap())).unwrap();
+                }
+            }
+            PcdFieldType::I64 => {
+                for (src, dst) in src.chunks(8).zip(dst.iter_mut()) {
+                    *dst = P::Data::from_i64(i64::from_ne_bytes(src.try_into().unwrap())).unwrap();
+                }
+            }
+            PcdFieldType::F64 => {
+                for (src, dst) in src.chunks(8).zip(dst.iter_mut()) {
+                    *dst = P::Data::from_f64(f64::from_ne_bytes(src.try_into().unwrap())).unwrap();
+                }
+            }
+            PcdFieldType::U128 => {
+                for (src, dst) in src.chunks(16).zip(dst.iter_mut()) {
+                    *dst =
+                        P::Data::from_u128(u128::from_ne_bytes(src.try_into().unwrap())).unwrap();
+                }
+            }
+            PcdFieldType::I128 => {
+                for (src, dst) in src.chunks(16).zip(dst.iter_mut()) {
+                    *dst =
+                        P::Data::from_i128(i128::from_ne_bytes(src.try_into().unwrap())).unwrap();
+                }
+            }
+        }
+    }
+}
+
 impl<P> TryFrom<Pcd> for (PointCloud<P>, Viewpoint)
 where
     P: Data + DataFields,
@@ -1,8 +1,14 @@
-use std::{error::Error, io::BufRead};
+use std::{error::Error, io::BufRead, marker::PhantomData};
 
 use nalgebra::{Quaternion, Vector3};
+use num::FromPrimitive;
+use pcc_common::point::{Data, DataFields};
+use rayon::prelude::*;
 
-use super::{PcdData, PcdField, PcdFieldType, PcdHeader};
+use super::{
+    convert::{convert_record, map_fields, FieldMap},
+    PcdData, PcdField, PcdFieldType, PcdHeader, Viewpoint,
+};
 
 impl PcdField {
     fn read_text<'a, I: Iterator<Item = &'a str>, E: Extend<u8>>(
@@ -168,6 +174,7 @@ impl PcdHeader {
                         "ascii" => PcdData::Ascii,
                         "binary" => PcdData::Binary,
                         "binary_compressed" => PcdData::BinaryCompressed,
+                        "binary_zstd" => PcdData::BinaryZstd,
                         _ => return Err(format!("Unknown data type: {:?}", data).into()),
                     };
                     break;
@@ -199,66 +206,120 @@ impl PcdData {
         output.clear();
         match self {
             PcdData::Ascii => read_text(reader, fields, output),
-            PcdData::Binary => read_bytes::<_, false>(reader, fields, output),
-            PcdData::BinaryCompressed => read_bytes::<_, true>(reader, fields, output),
+            _ => read_bytes(reader, fields, output, self.decompress()?),
         }
     }
+
+    fn decompress(&self) -> Result<Option<Decompress>, Box<dyn Error>> {
+        match self {
+            PcdData::Ascii | PcdData::Binary => Ok(None),
+            PcdData::BinaryCompressed => Ok(Some(decompress_lzf)),
+            #[cfg(feature = "zstd")]
+            PcdData::BinaryZstd => Ok(Some(decompress_zstd)),
+            #[cfg(not(feature = "zstd"))]
+            PcdData::BinaryZstd => Err("binary_zstd data requires the `zstd` feature".into()),
+        }
+    }
+}
+
+type Decompress = fn(&[u8], usize) -> Result<Vec<u8>, Box<dyn Error>>;
+
+fn decompress_lzf(data: &[u8], size: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    crate::lzf::decompress(data, size).map_err(|_| "Decompression error".into())
 }
 
+#[cfg(feature = "zstd")]
+fn decompress_zstd(data: &[u8], size: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    zstd::bulk::decompress(data, size).map_err(Into::into)
+}
+
+/// Reads and decompresses the compressed data, which is stored field by field.
+fn read_compressed<R: BufRead>(
+    mut reader: R,
+    decompress: Decompress,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut buf = [0; 4];
+    let compressed_size = {
+        reader.read_exact(&mut buf)?;
+        u32::from_ne_bytes(buf) as usize
+    };
+    let uncompressed_size = {
+        reader.read_exact(&mut buf)?;
+        u32::from_ne_bytes(buf) as usize
+    };
+
+    let mut data = vec![0; compressed_size];
+    reader.read_exact(&mut data)?;
+    decompress(&data, uncompressed_size)
+}
+
+/// The number of lines parsed by a single task.
+const CHUNK_LINES: usize = 4096;
+
 fn read_text<R: BufRead>(
-    reader: R,
+    mut reader: R,
     fields: &[PcdField],
     output: &mut Vec<u8>,
 ) -> Result<bool, Box<dyn Error>> {
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+    let lines = { text.lines() }
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>();
+
+    let chunks = { lines.par_chunks(CHUNK_LINES) }
+        .map(|lines| {
+            let mut output = Vec::new();
+            let mut finite = true;
+            for line in lines {
+                let mut data = line.split_whitespace();
+                for field in fields {
+                    finite &= { field.read_text(&mut data, &mut output) }
+                        .map_err(|err| err.to_string())?
+                }
+            }
+            Ok((output, finite))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    output.reserve(chunks.iter().map(|(chunk, _)| chunk.len()).sum());
     let mut finite = true;
-    for string in reader.lines().flatten() {
-        let mut data = string.split_whitespace();
-        for field in fields {
-            finite &= field.read_text(&mut data, output)?
-        }
+    for (chunk, f) in chunks {
+        output.extend_from_slice(&chunk);
+        finite &= f;
     }
     Ok(finite)
 }
 
-fn read_bytes<R: BufRead, const COMPRESS: bool>(
+fn read_bytes<R: BufRead>(
     mut reader: R,
     fields: &[PcdField],
     output: &mut Vec<u8>,
+    decompress: Option<Decompress>,
 ) -> Result<bool, Box<dyn Error>> {
-    if COMPRESS {
-        let mut buf = [0; 4];
-        let compressed_size = {
-            reader.read_exact(&mut buf)?;
-            u32::from_ne_bytes(buf) as usize
-        };
-        let uncompressed_size = {
-            reader.read_exact(&mut buf)?;
-            u32::from_ne_bytes(buf) as usize
-        };
-
-        output.resize(compressed_size, 0);
-        reader.read_exact(output)?;
-
-        let temp = &*crate::lzf::decompress(output, uncompressed_size as usize)
-            .map_err(|_| "Decompression error")?;
-        let size = uncompressed_size;
-        output.clear();
-        output.reserve(size);
+    if let Some(decompress) = decompress {
+        let temp = &*read_compressed(reader, decompress)?;
+        let size = temp.len();
 
         let record_size = fields
             .iter()
             .fold(0, |acc, field| acc + field.ty.size() * field.count);
         let record_num = size / record_size;
 
-        for record_index in 0..record_num {
-            let mut offset = 0;
+        // The compressed data is stored field by field, so the records are
+        // gathered back in parallel.
+        output.clear();
+        output.resize(record_num * record_size, 0);
+        { output.par_chunks_mut(record_size).enumerate() }.for_each(|(record_index, record)| {
+            let (mut offset, mut record_offset) = (0, 0);
             for field in fields {
                 let field_size = field.ty.size() * field.count;
-                output
-                    .extend_from_slice(&temp[(offset + field_size * record_index)..][..field_size]);
+                record[record_offset..][..field_size]
+                    .copy_from_slice(&temp[(offset + field_size * record_index)..][..field_size]);
                 offset += field_size * record_num;
+                record_offset += field_size;
             }
-        }
+        });
     } else {
         reader.read_to_end(output)?;
     }
@@ -270,3 +331,154 @@ fn read_bytes<R: BufRead, const COMPRESS: bool>(
     }
     Ok(finite)
 }
+
+/// A PCD reader that yields the points one record at a time instead of
+/// loading the whole data into memory, so that huge point clouds can be
+/// filtered on the fly.
+#[derive(Debug)]
+pub struct PcdReader<R> {
+    header: PcdHeader,
+    reader: R,
+}
+
+impl<R: BufRead> PcdReader<R> {
+    /// Reads the header, leaving the data in `reader`.
+    pub fn new(mut reader: R) -> Result<Self, Box<dyn Error>> {
+        let header = PcdHeader::read(&mut reader)?;
+        Ok(PcdReader { header, reader })
+    }
+
+    #[inline]
+    pub fn header(&self) -> &PcdHeader {
+        &self.header
+    }
+
+    #[inline]
+    pub fn viewpoint(&self) -> Viewpoint {
+        Viewpoint::from(&self.header)
+    }
+
+    /// Iterates over the points in the order of the records.
+    ///
+    /// The compressed data are stored field by field, so they are still
+    /// decompressed as a whole in advance, but not converted at once.
+    pub fn points<P>(mut self) -> Result<PcdPoints<R, P>, Box<dyn Error>>
+    where
+        P: Data + DataFields,
+        P::Data: FromPrimitive,
+    {
+        let fields = map_fields::<P>(&self.header.fields)?;
+        let len = self.header.width * self.header.height;
+
+        let data = match self.header.data.decompress()? {
+            Some(decompress) => {
+                let data = read_compressed(&mut self.reader, decompress)?;
+                if data.len() < len * self.header.rec_size {
+                    return Err("Not enough data".into());
+                }
+                Some(data)
+            }
+            None => None,
+        };
+
+        Ok(PcdPoints {
+            header: self.header,
+            reader: self.reader,
+            fields,
+            data,
+            index: 0,
+            len,
+            record: Vec::new(),
+            line: String::new(),
+            _marker: PhantomData,
+        })
+    }
+}
+
+/// The iterator over the points of [`PcdReader::points`].
+#[derive(Debug)]
+pub struct PcdPoints<R, P> {
+    header: PcdHeader,
+    reader: R,
+    fields: FieldMap,
+    /// The decompressed data, if compressed.
+    data: Option<Vec<u8>>,
+    index: usize,
+    len: usize,
+    record: Vec<u8>,
+    line: String,
+    _marker: PhantomData<fn() -> P>,
+}
+
+impl<R: BufRead, P> PcdPoints<R, P> {
+    #[inline]
+    pub fn header(&self) -> &PcdHeader {
+        &self.header
+    }
+
+    fn read_record(&mut self) -> Result<(), Box<dyn Error>> {
+        self.record.clear();
+        match &self.data {
+            Some(data) => {
+                let mut offset = 0;
+                for field in &self.header.fields {
+                    let field_size = field.ty.size() * field.count;
+                    self.record.extend_from_slice(
+                        &data[(offset + field_size * self.index)..][..field_size],
+                    );
+                    offset += field_size * self.len;
+                }
+            }
+            None if self.header.data == PcdData::Ascii => {
+                loop {
+                    self.line.clear();
+                    if self.reader.read_line(&mut self.line)? == 0 {
+                        return Err("Unexpected EOF".into());
+                    }
+                    if !self.line.trim().is_empty() {
+                        break;
+                    }
+                }
+                let mut data = self.line.split_whitespace();
+                for field in &self.header.fields {
+                    field.read_text(&mut data, &mut self.record)?;
+                }
+            }
+            None => {
+                self.record.resize(self.header.rec_size, 0);
+                self.reader.read_exact(&mut self.record)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R, P> Iterator for PcdPoints<R, P>
+where
+    R: BufRead,
+    P: Data,
+    P::Data: FromPrimitive,
+{
+    type Item = Result<P, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.len {
+            return None;
+        }
+        if let Err(err) = self.read_record() {
+            // Stops at the first error since the records are out of sync.
+            self.index = self.len;
+            return Some(Err(err));
+        }
+        self.index += 1;
+
+        let mut point = P::default();
+        convert_record(&self.fields, &self.record, &mut point);
+        Some(Ok(point))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.len - self.index))
+    }
+}
@@ -1,6 +1,66 @@
-use std::{error::Error, io::Write};
+use std::{collections::HashMap, error::Error, fmt::Display, io::Write};
 
 use super::{PcdData, PcdFieldType, PcdHeader};
+use crate::lzf::LzfError;
+
+/// The format of floating-point fields in ASCII PCD data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FloatFormat {
+    /// The shortest representation that reads back to the same value.
+    #[default]
+    Shortest,
+    /// A fixed number of digits after the decimal point.
+    Fixed(usize),
+    /// Scientific notation with a fixed number of digits after the decimal
+    /// point.
+    Scientific(usize),
+}
+
+impl FloatFormat {
+    fn write<W: Write, T: Display + std::fmt::LowerExp>(
+        &self,
+        mut writer: W,
+        value: T,
+    ) -> std::io::Result<()> {
+        match *self {
+            FloatFormat::Shortest => write!(writer, "{}", value),
+            FloatFormat::Fixed(precision) => write!(writer, "{:.*}", precision, value),
+            FloatFormat::Scientific(precision) => write!(writer, "{:.*e}", precision, value),
+        }
+    }
+}
+
+/// Options for writing ASCII PCD data.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct AsciiOptions {
+    /// The formats of the floating-point fields by their names. The fields
+    /// not present use `default_format`.
+    pub formats: HashMap<String, FloatFormat>,
+    pub default_format: FloatFormat,
+    /// Writes NaN as `nan` like PCL instead of `NaN`.
+    pub pcl_nan: bool,
+}
+
+impl AsciiOptions {
+    pub fn new(default_format: FloatFormat, pcl_nan: bool) -> Self {
+        AsciiOptions {
+            formats: HashMap::new(),
+            default_format,
+            pcl_nan,
+        }
+    }
+
+    pub fn with_format(mut self, field: &str, format: FloatFormat) -> Self {
+        self.formats.insert(field.to_owned(), format);
+        self
+    }
+
+    fn format(&self, field: &str) -> FloatFormat {
+        { self.formats.get(field) }
+            .copied()
+            .unwrap_or(self.default_format)
+    }
+}
 
 impl PcdHeader {
     pub fn write<W>(&self, mut writer: W) -> Result<(), Box<dyn Error>>
@@ -50,34 +110,57 @@ impl PcdHeader {
         )?;
 
         writeln!(writer, "POINTS {}", self.width * self.height)?;
-        writeln!(writer, "DATA {}", self.data.type_str())?;
+        writeln!(writer, "DATA {}", self.data.supported().type_str())?;
 
         Ok(())
     }
 }
 
 impl PcdData {
-    pub fn write<W>(
+    #[inline]
+    pub fn write<W>(&self, data: &[u8], header: &PcdHeader, writer: W) -> Result<(), Box<dyn Error>>
+    where
+        W: Write,
+    {
+        self.write_with(data, header, &Default::default(), writer)
+    }
+
+    /// Writes the data, formatting ASCII data with `options`.
+    pub fn write_with<W>(
         &self,
         data: &[u8],
         header: &PcdHeader,
+        options: &AsciiOptions,
         mut writer: W,
     ) -> Result<(), Box<dyn Error>>
     where
         W: Write,
     {
         match self {
-            PcdData::Ascii => write_text(data, header, writer),
+            PcdData::Ascii => write_text(data, header, options, writer),
             PcdData::Binary => writer.write_all(data).map_err(Into::into),
-            PcdData::BinaryCompressed => write_bytes_compressed(data, header, writer),
+            PcdData::BinaryCompressed => write_bytes_compressed(data, header, compress_lzf, writer),
+            #[cfg(feature = "zstd")]
+            PcdData::BinaryZstd => write_bytes_compressed(data, header, compress_zstd, writer),
+            #[cfg(not(feature = "zstd"))]
+            PcdData::BinaryZstd => write_bytes_compressed(data, header, compress_lzf, writer),
         }
     }
 }
 
-fn write_text<W>(data: &[u8], header: &PcdHeader, mut writer: W) -> Result<(), Box<dyn Error>>
+fn write_text<W>(
+    data: &[u8],
+    header: &PcdHeader,
+    options: &AsciiOptions,
+    mut writer: W,
+) -> Result<(), Box<dyn Error>>
 where
     W: Write,
 {
+    let formats = { header.fields.iter() }
+        .map(|field| options.format(&field.name))
+        .collect::<Vec<_>>();
+
     for record in data.chunks(header.rec_size) {
         let mut offset = 0;
         for (fi, field_info) in header.fields.iter().enumerate() {
@@ -86,12 +169,20 @@ where
 
             macro_rules! write_field {
                 ($type:ty) => {
+                    write_field!($type, |value| write!(writer, "{}", value))
+                };
+                (float $type:ty) => {
+                    write_field!($type, |value: $type| if value.is_nan() && options.pcl_nan {
+                        write!(writer, "nan")
+                    } else {
+                        formats[fi].write(&mut writer, value)
+                    })
+                };
+                ($type:ty, $write:expr) => {
                     for index in 0..field_info.count {
-                        write!(
-                            writer,
-                            "{}",
-                            <$type>::from_ne_bytes(field[(index * size)..][..size].try_into()?)
-                        )?;
+                        let value =
+                            <$type>::from_ne_bytes(field[(index * size)..][..size].try_into()?);
+                        $write(value)?;
                         if fi < header.fields.len() - 1 || index < field_info.count - 1 {
                             write!(writer, " ")?
                         }
@@ -107,10 +198,10 @@ where
                 I16 => write_field!(i16),
                 U32 => write_field!(u32),
                 I32 => write_field!(i32),
-                F32 => write_field!(f32),
+                F32 => write_field!(float f32),
                 U64 => write_field!(u64),
                 I64 => write_field!(i64),
-                F64 => write_field!(f64),
+                F64 => write_field!(float f64),
                 U128 => write_field!(u128),
                 I128 => write_field!(i128),
             };
@@ -123,9 +214,23 @@ where
     Ok(())
 }
 
+fn compress_lzf(data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    match crate::lzf::compress(data) {
+        Ok(out) => Ok(out),
+        Err(LzfError::NoCompressionPossible) => Ok(crate::lzf::store(data)),
+        Err(_) => Err("Compression error".into()),
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn compress_zstd(data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL).map_err(Into::into)
+}
+
 fn write_bytes_compressed<W>(
     data: &[u8],
     header: &PcdHeader,
+    compress: fn(&[u8]) -> Result<Vec<u8>, Box<dyn Error>>,
     mut writer: W,
 ) -> Result<(), Box<dyn Error>>
 where
@@ -148,7 +253,7 @@ where
         }
     }
 
-    let out = crate::lzf::compress(&temp).map_err(|_| "Compression error")?;
+    let out = compress(&temp)?;
     writer.write_all(&(out.len() as u32).to_ne_bytes())?;
     writer.write_all(&(data.len() as u32).to_ne_bytes())?;
     writer.write_all(&out)?;
@@ -0,0 +1,589 @@
+//! The PLY format, e.g. of the point clouds exported by MeshLab and
+//! CloudCompare, of which only the vertices are read and written.
+//!
+//! The vertex properties are renamed after the fields of the points, i.e.
+//! `nx`, `ny` and `nz` to `normal`, and `red`, `green`, `blue` and `alpha` to
+//! the packed `rgba`. The values pass through `f64`, so the 64-bit and
+//! 128-bit integer fields, which PLY doesn't support, are written as doubles.
+
+use std::{
+    error::Error,
+    io::{BufRead, Write},
+};
+
+use num::FromPrimitive;
+use pcc_common::{
+    point::{Data, DataFields},
+    point_cloud::PointCloud,
+};
+
+use crate::{
+    pcd::{PcdField, PcdFieldData, PcdFieldType},
+    registry::{CloudReader, CloudWriter},
+    DynPointCloud,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PlyData {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+impl PlyData {
+    pub fn type_str(&self) -> &'static str {
+        match self {
+            PlyData::Ascii => "ascii",
+            PlyData::BinaryLittleEndian => "binary_little_endian",
+            PlyData::BinaryBigEndian => "binary_big_endian",
+        }
+    }
+}
+
+fn parse_type(ty: &str) -> Result<PcdFieldType, String> {
+    use PcdFieldType::*;
+    Ok(match ty {
+        "char" | "int8" => I8,
+        "uchar" | "uint8" => U8,
+        "short" | "int16" => I16,
+        "ushort" | "uint16" => U16,
+        "int" | "int32" => I32,
+        "uint" | "uint32" => U32,
+        "float" | "float32" => F32,
+        "double" | "float64" => F64,
+        _ => return Err(format!("Unknown property type: {:?}", ty)),
+    })
+}
+
+/// The type of a field as written to PLY files.
+fn written_type(ty: PcdFieldType) -> PcdFieldType {
+    use PcdFieldType::*;
+    match ty {
+        U64 | I64 | U128 | I128 => F64,
+        ty => ty,
+    }
+}
+
+fn type_str(ty: PcdFieldType) -> &'static str {
+    use PcdFieldType::*;
+    match written_type(ty) {
+        I8 => "char",
+        U8 => "uchar",
+        I16 => "short",
+        U16 => "ushort",
+        I32 => "int",
+        U32 => "uint",
+        F32 => "float",
+        _ => "double",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Property {
+    Scalar(String, PcdFieldType),
+    List(PcdFieldType, PcdFieldType),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+fn read_header<R: BufRead>(mut reader: R) -> Result<(PlyData, Vec<Element>), Box<dyn Error>> {
+    let mut string = String::new();
+    let mut data_type = None;
+    let mut elements = Vec::<Element>::new();
+
+    let mut magic = true;
+    loop {
+        string.clear();
+        if reader.read_line(&mut string)? == 0 {
+            return Err("Unexpected EOF".into());
+        }
+        let line = string.trim();
+        if magic {
+            if line != "ply" {
+                return Err("Not a PLY file".into());
+            }
+            magic = false;
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("format") => {
+                data_type = Some(match words.next() {
+                    Some("ascii") => PlyData::Ascii,
+                    Some("binary_little_endian") => PlyData::BinaryLittleEndian,
+                    Some("binary_big_endian") => PlyData::BinaryBigEndian,
+                    format => return Err(format!("Unknown format: {:?}", format).into()),
+                })
+            }
+            Some("element") => {
+                let name = words.next().ok_or("Missing element name")?;
+                let count = words.next().ok_or("Missing element count")?;
+                elements.push(Element {
+                    name: name.to_owned(),
+                    count: count.parse()?,
+                    properties: Vec::new(),
+                });
+            }
+            Some("property") => {
+                let element = elements.last_mut().ok_or("Property without element")?;
+                let ty = words.next().ok_or("Missing property type")?;
+                let property = if ty == "list" {
+                    let count = parse_type(words.next().ok_or("Missing list count type")?)?;
+                    let item = parse_type(words.next().ok_or("Missing list item type")?)?;
+                    Property::List(count, item)
+                } else {
+                    let name = words.next().ok_or("Missing property name")?;
+                    Property::Scalar(name.to_owned(), parse_type(ty)?)
+                };
+                element.properties.push(property);
+            }
+            Some("end_header") => break,
+            _ => {}
+        }
+    }
+
+    Ok((data_type.ok_or("Missing format")?, elements))
+}
+
+/// The body of a PLY file, or a record in native endianness.
+enum Body<'a> {
+    Ascii(std::str::SplitWhitespace<'a>),
+    Binary(&'a [u8], bool),
+}
+
+impl<'a> Body<'a> {
+    fn native(data: &'a [u8]) -> Self {
+        Body::Binary(data, cfg!(target_endian = "big"))
+    }
+
+    fn next(&mut self, ty: PcdFieldType) -> Result<f64, Box<dyn Error>> {
+        match self {
+            Body::Ascii(words) => Ok(words.next().ok_or("Not enough properties")?.parse()?),
+            Body::Binary(data, big_endian) => {
+                if data.len() < ty.size() {
+                    return Err("Unexpected EOF".into());
+                }
+                let (bytes, rest) = { *data }.split_at(ty.size());
+                *data = rest;
+
+                macro_rules! decode {
+                    ($($ty:ident => $type:ty),*) => {
+                        match ty {
+                            $(PcdFieldType::$ty => {
+                                let bytes = bytes.try_into().unwrap();
+                                let value = if *big_endian {
+                                    <$type>::from_be_bytes(bytes)
+                                } else {
+                                    <$type>::from_le_bytes(bytes)
+                                };
+                                value as f64
+                            })*
+                        }
+                    };
+                }
+                Ok(decode!(
+                    U8 => u8, I8 => i8, U16 => u16, I16 => i16, U32 => u32, I32 => i32,
+                    F32 => f32, U64 => u64, I64 => i64, F64 => f64, U128 => u128, I128 => i128
+                ))
+            }
+        }
+    }
+
+    fn skip(&mut self, property: &Property) -> Result<(), Box<dyn Error>> {
+        match *property {
+            Property::Scalar(_, ty) => self.next(ty).map(drop),
+            Property::List(count, item) => {
+                for _ in 0..(self.next(count)? as usize) {
+                    self.next(item)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn encode(value: f64, ty: PcdFieldType, output: &mut Vec<u8>) {
+    macro_rules! encode {
+        ($($ty:ident => $type:ty),*) => {
+            match ty {
+                $(PcdFieldType::$ty => output.extend_from_slice(&(value as $type).to_ne_bytes()),)*
+            }
+        };
+    }
+    encode!(
+        U8 => u8, I8 => i8, U16 => u16, I16 => i16, U32 => u32, I32 => i32,
+        F32 => f32, U64 => u64, I64 => i64, F64 => f64, U128 => u128, I128 => i128
+    )
+}
+
+/// Where the value of a vertex property goes in the records.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Slot {
+    /// A component of a field.
+    Field(usize, usize),
+    /// A channel of the packed `rgba` field in `[b, g, r, a]`, where the
+    /// floating-point channels in `[0, 1]` are scaled to bytes.
+    Color(usize, bool),
+    Skip,
+}
+
+fn slot(name: &str, ty: PcdFieldType, fields: &mut Vec<PcdField>) -> Slot {
+    let float = matches!(ty, PcdFieldType::F32 | PcdFieldType::F64);
+    let channel = match name {
+        "blue" | "diffuse_blue" => Some(0),
+        "green" | "diffuse_green" => Some(1),
+        "red" | "diffuse_red" => Some(2),
+        "alpha" | "diffuse_alpha" => Some(3),
+        _ => None,
+    };
+    let (name, component, ty) = match (channel, name) {
+        (Some(_), _) => ("rgba".to_owned(), 0, PcdFieldType::F32),
+        (_, "nx" | "normal_x") => ("normal".to_owned(), 0, ty),
+        (_, "ny" | "normal_y") => ("normal".to_owned(), 1, ty),
+        (_, "nz" | "normal_z") => ("normal".to_owned(), 2, ty),
+        // CloudCompare prefixes the scalar fields.
+        _ => {
+            let name = name.strip_prefix("scalar_").unwrap_or(name);
+            (name.to_ascii_lowercase(), 0, ty)
+        }
+    };
+
+    let index = match fields.iter().position(|field| field.name == name) {
+        Some(index) => {
+            let field = &mut fields[index];
+            field.count = field.count.max(component + 1);
+            index
+        }
+        None => {
+            fields.push(PcdField {
+                name,
+                ty,
+                count: component + 1,
+            });
+            fields.len() - 1
+        }
+    };
+    match channel {
+        Some(channel) => Slot::Color(channel, float),
+        None => Slot::Field(index, component),
+    }
+}
+
+/// Reads the vertices of a PLY file as a point cloud of one row.
+pub fn read<R: BufRead>(mut reader: R) -> Result<DynPointCloud, Box<dyn Error>> {
+    let (data_type, elements) = read_header(&mut reader)?;
+
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let text;
+    let mut body = match data_type {
+        PlyData::Ascii => {
+            text = String::from_utf8(bytes)?;
+            Body::Ascii(text.split_whitespace())
+        }
+        PlyData::BinaryLittleEndian => Body::Binary(&bytes, false),
+        PlyData::BinaryBigEndian => Body::Binary(&bytes, true),
+    };
+
+    let position = { elements.iter() }
+        .position(|element| element.name == "vertex")
+        .ok_or("Missing vertex element")?;
+    for element in &elements[..position] {
+        for _ in 0..element.count {
+            element.properties.iter().try_for_each(|p| body.skip(p))?;
+        }
+    }
+    let vertex = &elements[position];
+
+    let mut fields = Vec::new();
+    let slots = { vertex.properties.iter() }
+        .map(|property| match property {
+            Property::Scalar(name, ty) => slot(name, *ty, &mut fields),
+            Property::List(..) => Slot::Skip,
+        })
+        .collect::<Vec<_>>();
+
+    let mut finite = true;
+    let mut data = Vec::new();
+    let mut values = fields
+        .iter()
+        .map(|field| vec![0.; field.count])
+        .collect::<Vec<_>>();
+    for _ in 0..vertex.count {
+        let mut color = [0, 0, 0, u8::MAX];
+        for (property, slot) in vertex.properties.iter().zip(&slots) {
+            match (property, *slot) {
+                (Property::Scalar(_, ty), Slot::Field(index, component)) => {
+                    let value = body.next(*ty)?;
+                    finite &= value.is_finite();
+                    values[index][component] = value;
+                }
+                (Property::Scalar(_, ty), Slot::Color(channel, float)) => {
+                    let value = body.next(*ty)?;
+                    color[channel] = (if float { value * 255. } else { value }) as u8;
+                }
+                (property, _) => body.skip(property)?,
+            }
+        }
+
+        for (field, values) in fields.iter().zip(&values) {
+            if field.name == "rgba" {
+                data.extend_from_slice(&u32::from_le_bytes(color).to_ne_bytes());
+            } else {
+                values
+                    .iter()
+                    .for_each(|&value| encode(value, field.ty, &mut data));
+            }
+        }
+    }
+
+    Ok(DynPointCloud {
+        fields,
+        width: vertex.count,
+        height: 1,
+        viewpoint: Default::default(),
+        finite,
+        data,
+    })
+}
+
+/// Whether the field is the packed color, which is written as 4 channels.
+fn is_color(field: &PcdField) -> bool {
+    matches!(&*field.name, "rgb" | "rgba") && field.count == 1 && field.ty.size() == 4
+}
+
+/// The names of the vertex properties of a field.
+fn property_names(field: &PcdField) -> Vec<String> {
+    if is_color(field) {
+        return { ["red", "green", "blue", "alpha"].iter() }
+            .map(|name| name.to_string())
+            .collect();
+    }
+    match (&*field.name, field.count) {
+        ("normal", 3) => ["nx", "ny", "nz"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+        (name, 1) => vec![name.to_owned()],
+        (name, count) => (0..count)
+            .map(|index| format!("{}_{}", name, index))
+            .collect(),
+    }
+}
+
+fn write_value<W: Write>(
+    writer: &mut W,
+    data_type: PlyData,
+    ty: PcdFieldType,
+    value: f64,
+) -> std::io::Result<()> {
+    macro_rules! write_value {
+        ($($ty:ident => $type:ty),*) => {
+            match written_type(ty) {
+                $(PcdFieldType::$ty => {
+                    let value = value as $type;
+                    match data_type {
+                        PlyData::Ascii => write!(writer, "{} ", value),
+                        PlyData::BinaryLittleEndian => writer.write_all(&value.to_le_bytes()),
+                        PlyData::BinaryBigEndian => writer.write_all(&value.to_be_bytes()),
+                    }
+                })*
+                _ => unreachable!(),
+            }
+        };
+    }
+    write_value!(
+        U8 => u8, I8 => i8, U16 => u16, I16 => i16, U32 => u32, I32 => i32,
+        F32 => f32, F64 => f64
+    )
+}
+
+/// Writes a point cloud as the vertices of a PLY file.
+pub fn write<W: Write>(
+    point_cloud: &DynPointCloud,
+    data_type: PlyData,
+    mut writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let names = point_cloud
+        .fields
+        .iter()
+        .map(property_names)
+        .collect::<Vec<_>>();
+
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format {} 1.0", data_type.type_str())?;
+    writeln!(writer, "element vertex {}", point_cloud.len())?;
+    for (field, names) in point_cloud.fields.iter().zip(&names) {
+        let ty = if is_color(field) {
+            "uchar"
+        } else {
+            type_str(field.ty)
+        };
+        for name in names {
+            writeln!(writer, "property {} {}", ty, name)?;
+        }
+    }
+    writeln!(writer, "end_header")?;
+
+    for record in point_cloud.records().take(point_cloud.len()) {
+        let mut record = Body::native(record);
+        for field in &point_cloud.fields {
+            if is_color(field) {
+                let rgba = record.next(PcdFieldType::U32)? as u32;
+                let [b, g, r, a] = rgba.to_le_bytes();
+                for channel in [r, g, b, a] {
+                    write_value(&mut writer, data_type, PcdFieldType::U8, channel as f64)?;
+                }
+                continue;
+            }
+            for _ in 0..field.count {
+                let value = record.next(field.ty)?;
+                write_value(&mut writer, data_type, field.ty, value)?;
+            }
+        }
+        if data_type == PlyData::Ascii {
+            writeln!(writer)?;
+        }
+    }
+    Ok(())
+}
+
+#[inline]
+pub fn read_ply<P, R>(reader: R) -> Result<PointCloud<P>, Box<dyn Error>>
+where
+    R: BufRead,
+    P: Data + DataFields,
+    P::Data: FromPrimitive,
+{
+    let (point_cloud, _) = read(reader)?.into_point_cloud()?;
+    Ok(point_cloud)
+}
+
+#[inline]
+pub fn write_ply<P, W>(
+    point_cloud: &PointCloud<P>,
+    data_type: PlyData,
+    writer: W,
+) -> Result<(), Box<dyn Error>>
+where
+    W: Write,
+    P: Data + DataFields,
+    P::Data: PcdFieldData,
+{
+    let point_cloud = DynPointCloud::from_point_cloud(point_cloud, &Default::default());
+    write(&point_cloud, data_type, writer)
+}
+
+/// The PLY format, written with `data` as its data type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlyFormat {
+    pub data: PlyData,
+}
+
+impl PlyFormat {
+    pub fn new(data: PlyData) -> Self {
+        PlyFormat { data }
+    }
+}
+
+impl Default for PlyFormat {
+    fn default() -> Self {
+        PlyFormat::new(PlyData::BinaryLittleEndian)
+    }
+}
+
+impl CloudReader for PlyFormat {
+    fn extensions(&self) -> &[&str] {
+        &["ply"]
+    }
+
+    fn check_magic(&self, magic: &[u8]) -> bool {
+        magic.starts_with(b"ply\n") || magic.starts_with(b"ply\r\n")
+    }
+
+    fn read(&self, reader: &mut dyn BufRead) -> Result<DynPointCloud, Box<dyn Error>> {
+        read(reader)
+    }
+}
+
+impl CloudWriter for PlyFormat {
+    fn extensions(&self) -> &[&str] {
+        &["ply"]
+    }
+
+    fn write(
+        &self,
+        point_cloud: &DynPointCloud,
+        writer: &mut dyn Write,
+    ) -> Result<(), Box<dyn Error>> {
+        write(point_cloud, self.data, writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::point::{Normal, Point, Point3Rgba, Point3RgbaN, PointRgba};
+
+    use super::*;
+
+    #[test]
+    fn test_io_ply() {
+        let storage = (0..10)
+            .map(|index| {
+                let t = index as f32 / 10.;
+                Point3RgbaN::default()
+                    .with_coords(Vector4::new(t, -t, t * t, 1.))
+                    .with_normal(Vector4::new(0., t.cos(), t.sin(), 0.))
+                    .with_curvature(t)
+                    .with_rgba(0x80FF_4020 + index)
+            })
+            .collect::<Vec<_>>();
+        let pc = PointCloud::from_vec(storage, 10);
+
+        for data_type in [
+            PlyData::Ascii,
+            PlyData::BinaryLittleEndian,
+            PlyData::BinaryBigEndian,
+        ] {
+            let mut output = Vec::new();
+            write_ply(&pc, data_type, &mut output).expect("Failed to write test data");
+            let pc2 = read_ply::<Point3RgbaN, _>(&*output).expect("Failed to read test data");
+            assert_eq!(pc, pc2);
+        }
+    }
+
+    #[test]
+    fn test_meshlab() {
+        let text = "ply
+format ascii 1.0
+comment VCGLIB generated
+element vertex 3
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+property float quality
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0 0.5
+1 0 0 0 255 0 0.5
+0 1 0 0 0 255 0.5
+3 0 1 2
+";
+        let pc = read_ply::<Point3Rgba, _>(text.as_bytes()).expect("Failed to read test data");
+        assert_eq!(pc.len(), 3);
+        assert_eq!(pc[1].coords(), &Vector4::new(1., 0., 0., 1.));
+        assert_eq!(pc[0].rgba(), 0xFFFF_0000);
+        assert_eq!(pc[2].rgba(), 0xFF00_00FF);
+    }
+}
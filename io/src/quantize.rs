@@ -0,0 +1,523 @@
+//! A container format that quantizes the fields of point clouds with
+//! configurable bits, trading precision for size.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    io::{BufRead, Read, Write},
+};
+
+use nalgebra::{Quaternion, Vector3};
+
+use crate::{
+    pcd::{PcdField, PcdFieldType, Viewpoint},
+    CloudReader, CloudWriter, DynPointCloud,
+};
+
+const MAGIC: &[u8; 4] = b"PCCQ";
+const VERSION: u8 = 1;
+
+const FIELD_TYPES: [PcdFieldType; 12] = {
+    use PcdFieldType::*;
+    [U8, I8, U16, I16, U32, I32, F32, U64, I64, F64, U128, I128]
+};
+
+/// The number of bits of the quantized fields. Zero bits keep the field
+/// lossless.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quantization {
+    /// The bits of the coordinates `x`, `y` and `z`. Floating-point fields
+    /// take 2 to 32 bits.
+    pub position_bits: u32,
+    /// The bits of every component of the normals.
+    pub normal_bits: u32,
+    /// The bits of every channel of the colors `rgb` and `rgba`, at most 8.
+    pub color_bits: u32,
+    /// The bits of other floating-point fields by their names, overriding the
+    /// categories above.
+    pub fields: HashMap<String, u32>,
+}
+
+impl Quantization {
+    pub fn new(position_bits: u32, normal_bits: u32, color_bits: u32) -> Self {
+        Quantization {
+            position_bits,
+            normal_bits,
+            color_bits,
+            fields: HashMap::new(),
+        }
+    }
+
+    pub fn with_field(mut self, field: &str, bits: u32) -> Self {
+        self.fields.insert(field.to_owned(), bits);
+        self
+    }
+
+    fn encoding(&self, field: &PcdField) -> Encoding {
+        let is_float = matches!(field.ty, PcdFieldType::F32 | PcdFieldType::F64);
+        match (&*field.name, self.fields.get(&field.name)) {
+            (_, Some(&bits)) if is_float => Encoding::float(bits),
+            ("rgb" | "rgba", _) if field.ty.size() == 4 => match self.color_bits {
+                bits @ 1..=7 => Encoding::Color { bits },
+                _ => Encoding::Raw,
+            },
+            ("x" | "y" | "z", _) if is_float => Encoding::float(self.position_bits),
+            ("normal" | "normal_x" | "normal_y" | "normal_z", _) if is_float => {
+                Encoding::float(self.normal_bits)
+            }
+            _ => Encoding::Raw,
+        }
+    }
+}
+
+impl Default for Quantization {
+    fn default() -> Self {
+        Quantization::new(16, 10, 8)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Raw,
+    /// Maps the finite values in `[min, max]` evenly to the codes below
+    /// `2^bits - 1`, which is reserved for non-finite values.
+    Float {
+        bits: u32,
+        min: f64,
+        max: f64,
+    },
+    /// Keeps the higher `bits` bits of every 8-bit channel.
+    Color {
+        bits: u32,
+    },
+}
+
+impl Encoding {
+    fn float(bits: u32) -> Self {
+        match bits {
+            0 => Encoding::Raw,
+            bits => Encoding::Float {
+                bits,
+                min: f64::INFINITY,
+                max: f64::NEG_INFINITY,
+            },
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        match *self {
+            Encoding::Raw => true,
+            Encoding::Float { bits, .. } => (2..=32).contains(&bits),
+            Encoding::Color { bits } => (1..=7).contains(&bits),
+        }
+    }
+
+    fn tag(&self) -> u8 {
+        match self {
+            Encoding::Raw => 0,
+            Encoding::Float { .. } => 1,
+            Encoding::Color { .. } => 2,
+        }
+    }
+
+    fn bits(&self) -> u32 {
+        match *self {
+            Encoding::Raw => 0,
+            Encoding::Float { bits, .. } | Encoding::Color { bits } => bits,
+        }
+    }
+}
+
+fn read_float(ty: PcdFieldType, bytes: &[u8]) -> f64 {
+    match ty {
+        PcdFieldType::F32 => f32::from_ne_bytes(bytes.try_into().unwrap()) as f64,
+        _ => f64::from_ne_bytes(bytes.try_into().unwrap()),
+    }
+}
+
+fn write_float(ty: PcdFieldType, value: f64, output: &mut Vec<u8>) {
+    match ty {
+        PcdFieldType::F32 => output.extend_from_slice(&(value as f32).to_ne_bytes()),
+        _ => output.extend_from_slice(&value.to_ne_bytes()),
+    }
+}
+
+struct BitWriter {
+    data: Vec<u8>,
+    buffer: u64,
+    len: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            data: Vec::new(),
+            buffer: 0,
+            len: 0,
+        }
+    }
+
+    /// Writes the lower `bits` bits of `value`, where `bits` is at most 32.
+    fn write(&mut self, value: u64, bits: u32) {
+        self.buffer |= (value & ((1 << bits) - 1)) << self.len;
+        self.len += bits;
+        while self.len >= 8 {
+            self.data.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        if cfg!(target_endian = "big") {
+            bytes
+                .iter()
+                .rev()
+                .for_each(|&byte| self.write(byte as u64, 8));
+        } else {
+            bytes.iter().for_each(|&byte| self.write(byte as u64, 8));
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.data.push(self.buffer as u8);
+        }
+        self.data
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    buffer: u64,
+    len: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            buffer: 0,
+            len: 0,
+        }
+    }
+
+    /// Reads `bits` bits, where `bits` is at most 32.
+    fn read(&mut self, bits: u32) -> Result<u64, Box<dyn Error>> {
+        while self.len < bits {
+            let (&byte, rest) = self.data.split_first().ok_or("Unexpected EOF")?;
+            self.data = rest;
+            self.buffer |= (byte as u64) << self.len;
+            self.len += 8;
+        }
+        let value = self.buffer & ((1 << bits) - 1);
+        self.buffer >>= bits;
+        self.len -= bits;
+        Ok(value)
+    }
+
+    fn read_bytes(&mut self, size: usize, output: &mut Vec<u8>) -> Result<(), Box<dyn Error>> {
+        let start = output.len();
+        for _ in 0..size {
+            output.push(self.read(8)? as u8);
+        }
+        if cfg!(target_endian = "big") {
+            output[start..].reverse();
+        }
+        Ok(())
+    }
+}
+
+/// Writes `point_cloud` in the quantized container format.
+pub fn compress<W: Write>(
+    point_cloud: &DynPointCloud,
+    quantization: &Quantization,
+    mut writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let rec_size = point_cloud.rec_size();
+    let mut encodings = { point_cloud.fields.iter() }
+        .map(|field| quantization.encoding(field))
+        .collect::<Vec<_>>();
+    if let Some(encoding) = encodings.iter().find(|encoding| !encoding.is_valid()) {
+        return Err(format!("Invalid bits for quantization: {}", encoding.bits()).into());
+    }
+
+    let mut offset = 0;
+    for (field, encoding) in point_cloud.fields.iter().zip(&mut encodings) {
+        let size = field.ty.size();
+        if let Encoding::Float { min, max, .. } = encoding {
+            for record in point_cloud.data.chunks(rec_size) {
+                for value in record[offset..][..size * field.count].chunks(size) {
+                    let value = read_float(field.ty, value);
+                    if value.is_finite() {
+                        *min = min.min(value);
+                        *max = max.max(value);
+                    }
+                }
+            }
+        }
+        offset += size * field.count;
+    }
+
+    let mut bits = BitWriter::new();
+    for record in point_cloud.data.chunks(rec_size) {
+        let mut offset = 0;
+        for (field, encoding) in point_cloud.fields.iter().zip(&encodings) {
+            let size = field.ty.size();
+            for value in record[offset..][..size * field.count].chunks(size) {
+                match *encoding {
+                    Encoding::Raw => bits.write_bytes(value),
+                    Encoding::Float {
+                        bits: num,
+                        min,
+                        max,
+                    } => {
+                        let value = read_float(field.ty, value);
+                        let levels = ((1u64 << num) - 2) as f64;
+                        let code = if !value.is_finite() {
+                            (1 << num) - 1
+                        } else if max > min {
+                            ((value - min) / (max - min) * levels).round() as u64
+                        } else {
+                            0
+                        };
+                        bits.write(code, num);
+                    }
+                    Encoding::Color { bits: num } => {
+                        let color = u32::from_ne_bytes(value.try_into().unwrap());
+                        for channel in 0..4 {
+                            let channel = (color >> (channel * 8)) & 0xff;
+                            bits.write((channel >> (8 - num)) as u64, num);
+                        }
+                    }
+                }
+            }
+            offset += size * field.count;
+        }
+    }
+    let data = bits.finish();
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION, point_cloud.finite as u8])?;
+    writer.write_all(&(point_cloud.width as u64).to_le_bytes())?;
+    writer.write_all(&(point_cloud.height as u64).to_le_bytes())?;
+    let viewpoint =
+        { point_cloud.viewpoint.origin.iter() }.chain(point_cloud.viewpoint.quat.coords.iter());
+    for value in viewpoint {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+
+    writer.write_all(&(point_cloud.fields.len() as u32).to_le_bytes())?;
+    for (field, encoding) in point_cloud.fields.iter().zip(&encodings) {
+        let ty = FIELD_TYPES.iter().position(|&ty| ty == field.ty).unwrap();
+        writer.write_all(&(field.name.len() as u32).to_le_bytes())?;
+        writer.write_all(field.name.as_bytes())?;
+        writer.write_all(&[ty as u8, encoding.tag(), encoding.bits() as u8])?;
+        writer.write_all(&(field.count as u32).to_le_bytes())?;
+        if let Encoding::Float { min, max, .. } = *encoding {
+            writer.write_all(&min.to_le_bytes())?;
+            writer.write_all(&max.to_le_bytes())?;
+        }
+    }
+
+    writer.write_all(&(data.len() as u64).to_le_bytes())?;
+    writer.write_all(&data)?;
+    Ok(())
+}
+
+fn read_array<R: Read, const N: usize>(mut reader: R) -> std::io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Reads a point cloud in the quantized container format.
+pub fn decompress<R: Read>(mut reader: R) -> Result<DynPointCloud, Box<dyn Error>> {
+    if &read_array::<_, 4>(&mut reader)? != MAGIC {
+        return Err("Not a quantized point cloud".into());
+    }
+    let [version, finite] = read_array(&mut reader)?;
+    if version != VERSION {
+        return Err(format!("Unknown version: {}", version).into());
+    }
+    let width = u64::from_le_bytes(read_array(&mut reader)?) as usize;
+    let height = u64::from_le_bytes(read_array(&mut reader)?) as usize;
+    let mut viewpoint = [0.; 7];
+    for value in &mut viewpoint {
+        *value = f32::from_le_bytes(read_array(&mut reader)?);
+    }
+
+    let num_fields = u32::from_le_bytes(read_array(&mut reader)?);
+    let mut fields = Vec::new();
+    let mut encodings = Vec::new();
+    for _ in 0..num_fields {
+        let len = u32::from_le_bytes(read_array(&mut reader)?) as usize;
+        let mut name = vec![0; len];
+        reader.read_exact(&mut name)?;
+        let [ty, tag, bits] = read_array(&mut reader)?;
+        let count = u32::from_le_bytes(read_array(&mut reader)?) as usize;
+
+        let ty = *FIELD_TYPES
+            .get(ty as usize)
+            .ok_or_else(|| format!("Unknown field type: {}", ty))?;
+        let bits = bits as u32;
+        let encoding = match tag {
+            0 => Encoding::Raw,
+            1 => Encoding::Float {
+                bits,
+                min: f64::from_le_bytes(read_array(&mut reader)?),
+                max: f64::from_le_bytes(read_array(&mut reader)?),
+            },
+            2 => Encoding::Color { bits },
+            _ => return Err(format!("Unknown encoding: {}", tag).into()),
+        };
+        if !encoding.is_valid() {
+            return Err(format!("Invalid bits for quantization: {}", bits).into());
+        }
+
+        fields.push(PcdField {
+            name: String::from_utf8(name)?,
+            ty,
+            count,
+        });
+        encodings.push(encoding);
+    }
+
+    let len = u64::from_le_bytes(read_array(&mut reader)?) as usize;
+    let mut packed = vec![0; len];
+    reader.read_exact(&mut packed)?;
+
+    let mut bits = BitReader::new(&packed);
+    let mut data = Vec::new();
+    for _ in 0..width * height {
+        for (field, encoding) in fields.iter().zip(&encodings) {
+            for _ in 0..field.count {
+                match *encoding {
+                    Encoding::Raw => bits.read_bytes(field.ty.size(), &mut data)?,
+                    Encoding::Float {
+                        bits: num,
+                        min,
+                        max,
+                    } => {
+                        let code = bits.read(num)?;
+                        let levels = ((1u64 << num) - 2) as f64;
+                        let value = if code == (1 << num) - 1 {
+                            f64::NAN
+                        } else if max > min {
+                            min + code as f64 / levels * (max - min)
+                        } else {
+                            min
+                        };
+                        write_float(field.ty, value, &mut data);
+                    }
+                    Encoding::Color { bits: num } => {
+                        let mut color = 0;
+                        for channel in 0..4 {
+                            let value = (bits.read(num)? as u32) << (8 - num);
+                            color |= value << (channel * 8);
+                        }
+                        data.extend_from_slice(&color.to_ne_bytes());
+                    }
+                }
+            }
+        }
+    }
+
+    let [x, y, z, i, j, k, w] = viewpoint;
+    Ok(DynPointCloud {
+        fields,
+        width,
+        height,
+        viewpoint: Viewpoint {
+            origin: Vector3::new(x, y, z),
+            quat: Quaternion::new(w, i, j, k),
+        },
+        finite: finite != 0,
+        data,
+    })
+}
+
+/// The quantized container format, written with `quantization`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct QuantizedFormat {
+    pub quantization: Quantization,
+}
+
+impl QuantizedFormat {
+    pub fn new(quantization: Quantization) -> Self {
+        QuantizedFormat { quantization }
+    }
+}
+
+impl CloudReader for QuantizedFormat {
+    fn extensions(&self) -> &[&str] {
+        &["pcq"]
+    }
+
+    fn check_magic(&self, magic: &[u8]) -> bool {
+        magic.starts_with(MAGIC)
+    }
+
+    fn read(&self, reader: &mut dyn BufRead) -> Result<DynPointCloud, Box<dyn Error>> {
+        decompress(reader)
+    }
+}
+
+impl CloudWriter for QuantizedFormat {
+    fn extensions(&self) -> &[&str] {
+        &["pcq"]
+    }
+
+    fn write(
+        &self,
+        point_cloud: &DynPointCloud,
+        writer: &mut dyn Write,
+    ) -> Result<(), Box<dyn Error>> {
+        compress(point_cloud, &self.quantization, writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::{
+        point::{Normal, Point, Point3LN, PointLabel},
+        point_cloud::PointCloud,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_quantization() {
+        let storage = (0..100)
+            .map(|index| {
+                let t = index as f32 / 10.;
+                Point3LN::default()
+                    .with_coords(Vector4::new(t.sin() * 10., t.cos() * 10., t, 1.))
+                    .with_normal(Vector4::new(t.cos(), -t.sin(), 0., 0.))
+                    .with_curvature(t)
+                    .with_label(index)
+            })
+            .collect::<Vec<_>>();
+        let pc = PointCloud::from_vec(storage, 10);
+        let dyn_pc = DynPointCloud::from_point_cloud(&pc, &Default::default());
+
+        let quantization = Quantization::new(12, 8, 8).with_field("curvature", 4);
+        let mut output = Vec::new();
+        compress(&dyn_pc, &quantization, &mut output).expect("Failed to compress point cloud");
+        assert!(output.len() < dyn_pc.data.len());
+
+        let dyn_pc2 = decompress(&*output).expect("Failed to decompress point cloud");
+        assert_eq!(dyn_pc2.fields, dyn_pc.fields);
+        assert_eq!((dyn_pc2.width, dyn_pc2.height), (10, 10));
+
+        let (pc2, _) =
+            { dyn_pc2.into_point_cloud::<Point3LN>() }.expect("Failed to convert point cloud");
+        for (p, p2) in pc.iter().zip(pc2.iter()) {
+            assert!((p.coords() - p2.coords()).amax() <= 20. / 4094.);
+            assert!((p.normal() - p2.normal()).amax() <= 2. / 254.);
+            assert!((p.curvature() - p2.curvature()).abs() <= 9.9 / 14.);
+            assert_eq!(p.label(), p2.label());
+        }
+    }
+}
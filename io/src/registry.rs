@@ -0,0 +1,235 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    gltf::GltfFormat,
+    pcd::{Pcd, PcdData},
+    ply::PlyFormat,
+    quantize::QuantizedFormat,
+    DynPointCloud,
+};
+
+pub trait CloudReader {
+    /// The file extensions of the format, in lower case and without the dot.
+    fn extensions(&self) -> &[&str];
+
+    /// Checks whether the leading bytes of a file belong to the format.
+    fn check_magic(&self, magic: &[u8]) -> bool;
+
+    fn read(&self, reader: &mut dyn BufRead) -> Result<DynPointCloud, Box<dyn Error>>;
+}
+
+pub trait CloudWriter {
+    /// The file extensions of the format, in lower case and without the dot.
+    fn extensions(&self) -> &[&str];
+
+    fn write(
+        &self,
+        point_cloud: &DynPointCloud,
+        writer: &mut dyn Write,
+    ) -> Result<(), Box<dyn Error>>;
+}
+
+/// The PCD format, written with `data` as its data type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcdFormat {
+    pub data: PcdData,
+}
+
+impl PcdFormat {
+    pub fn new(data: PcdData) -> Self {
+        PcdFormat { data }
+    }
+}
+
+impl Default for PcdFormat {
+    fn default() -> Self {
+        PcdFormat::new(PcdData::BinaryCompressed)
+    }
+}
+
+impl CloudReader for PcdFormat {
+    fn extensions(&self) -> &[&str] {
+        &["pcd"]
+    }
+
+    fn check_magic(&self, magic: &[u8]) -> bool {
+        [&b"# .PCD"[..], b"VERSION", b"FIELDS"]
+            .iter()
+            .any(|prefix| magic.starts_with(prefix))
+    }
+
+    fn read(&self, reader: &mut dyn BufRead) -> Result<DynPointCloud, Box<dyn Error>> {
+        Pcd::read(reader).map(Into::into)
+    }
+}
+
+impl CloudWriter for PcdFormat {
+    fn extensions(&self) -> &[&str] {
+        &["pcd"]
+    }
+
+    fn write(
+        &self,
+        point_cloud: &DynPointCloud,
+        writer: &mut dyn Write,
+    ) -> Result<(), Box<dyn Error>> {
+        point_cloud.clone().into_pcd(self.data).write(writer)
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    { path.extension() }
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+}
+
+/// A set of point cloud formats, selected by the magic bytes or the
+/// extensions of files. Formats registered later take precedence.
+pub struct Registry {
+    readers: Vec<Box<dyn CloudReader>>,
+    writers: Vec<Box<dyn CloudWriter>>,
+}
+
+impl Registry {
+    /// Creates an empty registry, without even the built-in formats.
+    pub fn new() -> Self {
+        Registry {
+            readers: Vec::new(),
+            writers: Vec::new(),
+        }
+    }
+
+    pub fn register_reader(&mut self, reader: impl CloudReader + 'static) -> &mut Self {
+        self.readers.push(Box::new(reader));
+        self
+    }
+
+    pub fn register_writer(&mut self, writer: impl CloudWriter + 'static) -> &mut Self {
+        self.writers.push(Box::new(writer));
+        self
+    }
+
+    pub fn reader(&self, magic: &[u8], extension: Option<&str>) -> Option<&dyn CloudReader> {
+        let mut readers = self.readers.iter().rev();
+        let by_magic = readers.clone().find(|reader| reader.check_magic(magic));
+        let by_extension = || {
+            let extension = extension?;
+            readers.find(|reader| reader.extensions().contains(&extension))
+        };
+        by_magic.or_else(by_extension).map(|reader| &**reader)
+    }
+
+    pub fn writer(&self, extension: &str) -> Option<&dyn CloudWriter> {
+        { self.writers.iter().rev() }
+            .find(|writer| writer.extensions().contains(&extension))
+            .map(|writer| &**writer)
+    }
+
+    /// Reads a point cloud from `reader`, detecting its format by the magic
+    /// bytes and then the optional `extension`.
+    pub fn read_from(
+        &self,
+        reader: &mut dyn BufRead,
+        extension: Option<&str>,
+    ) -> Result<DynPointCloud, Box<dyn Error>> {
+        let magic = reader.fill_buf()?;
+        let format = { self.reader(magic, extension) }.ok_or("Unknown point cloud format")?;
+        format.read(reader)
+    }
+
+    pub fn read<Q: AsRef<Path>>(&self, path: Q) -> Result<DynPointCloud, Box<dyn Error>> {
+        let path = path.as_ref();
+        let mut reader = BufReader::new(File::open(path)?);
+        self.read_from(&mut reader, extension(path).as_deref())
+    }
+
+    pub fn write<Q: AsRef<Path>>(
+        &self,
+        path: Q,
+        point_cloud: &DynPointCloud,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let extension = extension(path).unwrap_or_default();
+        let format = { self.writer(&extension) }
+            .ok_or_else(|| format!("Unknown point cloud format: {:?}", extension))?;
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        format.write(point_cloud, &mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Default for Registry {
+    /// Creates a registry with all the built-in formats.
+    fn default() -> Self {
+        let mut registry = Registry::new();
+        registry
+            .register_reader(PcdFormat::default())
+            .register_writer(PcdFormat::default())
+            .register_reader(PlyFormat::default())
+            .register_writer(PlyFormat::default())
+            .register_reader(QuantizedFormat::default())
+            .register_writer(QuantizedFormat::default())
+            .register_writer(GltfFormat);
+        registry
+    }
+}
+
+/// Reads a point cloud from the file of any built-in format.
+#[inline]
+pub fn read<Q: AsRef<Path>>(path: Q) -> Result<DynPointCloud, Box<dyn Error>> {
+    Registry::default().read(path)
+}
+
+/// Writes a point cloud to the file of any built-in format, selected by its
+/// extension.
+#[inline]
+pub fn write<Q: AsRef<Path>>(path: Q, point_cloud: &DynPointCloud) -> Result<(), Box<dyn Error>> {
+    Registry::default().write(path, point_cloud)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::{
+        point::{Point, Point3},
+        point_cloud::PointCloud,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let pc = PointCloud::from_vec(
+            vec![Point3::default().with_coords(Vector4::new(1., 2., 3., 1.)); 3],
+            1,
+        );
+        let dir = tempfile::tempdir().expect("Failed to create test directory");
+
+        let path = dir.path().join("test.pcd");
+        write(
+            &path,
+            &DynPointCloud::from_point_cloud(&pc, &Default::default()),
+        )
+        .expect("Failed to write test file");
+
+        // Detected by the magic bytes regardless of the extension.
+        let other = dir.path().join("test.bin");
+        std::fs::rename(&path, &other).unwrap();
+        let dyn_pc = read(&other).expect("Failed to read test file");
+        assert_eq!(dyn_pc.len(), 3);
+        assert!(dyn_pc.field("x").is_some());
+
+        let (pc2, _) =
+            { dyn_pc.into_point_cloud::<Point3>() }.expect("Failed to convert point cloud");
+        assert_eq!(pc, pc2);
+
+        assert!(write(dir.path().join("test.unknown"), &Default::default()).is_err());
+    }
+}
@@ -0,0 +1,379 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use nalgebra::{RealField, Scalar, Vector4};
+use num::{FromPrimitive, ToPrimitive};
+use pcc_common::{
+    point::{DataFields, Point},
+    point_cloud::{AsPointCloud, PointCloud},
+};
+
+use crate::pcd::{Pcd, PcdData, PcdFieldData, Viewpoint};
+
+/// The name of the manifest file in a tile directory.
+pub const INDEX_FILE: &str = "index.tiles";
+
+/// The maximum depth of octree tiling, which stops splitting tiles of
+/// coincident points.
+const MAX_OCTREE_DEPTH: usize = 21;
+
+/// The way to split a point cloud into spatial tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tiling<T: Scalar> {
+    /// Cells of a uniform grid with the size of `tile_unit`, starting from
+    /// the minimum corner of the point cloud.
+    Grid { tile_unit: Vector4<T> },
+    /// Nodes of an octree over the bound of the point cloud, split until
+    /// every tile has at most `max_points` points.
+    Octree { max_points: usize },
+}
+
+/// The entry of a tile file in the manifest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tile<T: Scalar> {
+    /// The file name of the tile, relative to the tile directory.
+    pub name: String,
+    pub len: usize,
+    /// The tight bound of the points in the tile.
+    pub bound: [Vector4<T>; 2],
+}
+
+impl<T: Scalar + PartialOrd> Tile<T> {
+    pub fn intersects(&self, min: &Vector4<T>, max: &Vector4<T>) -> bool {
+        let [tile_min, tile_max] = &self.bound;
+        tile_min.xyz() <= max.xyz() && min.xyz() <= tile_max.xyz()
+    }
+}
+
+/// Writes a point cloud as a directory of PCD tiles and a manifest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileWriter<T: Scalar> {
+    pub tiling: Tiling<T>,
+    pub data: PcdData,
+}
+
+impl<T: Scalar> TileWriter<T> {
+    pub fn new(tiling: Tiling<T>, data: PcdData) -> Self {
+        TileWriter { tiling, data }
+    }
+}
+
+impl<T> TileWriter<T>
+where
+    T: RealField + ToPrimitive + PcdFieldData,
+{
+    /// Splits the finite points of `point_cloud` into tiles and writes them
+    /// into `dir`, which is created if absent.
+    pub fn write<P, Q>(
+        &self,
+        point_cloud: &PointCloud<P>,
+        viewpoint: &Viewpoint,
+        dir: Q,
+    ) -> Result<TileIndex<T>, Box<dyn Error>>
+    where
+        P: Point<Data = T> + DataFields,
+        Q: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        let mut tiles = Vec::new();
+        for (index, indices) in self.split(point_cloud).into_iter().enumerate() {
+            let storage = { indices.iter() }
+                .map(|&index| point_cloud[index].clone())
+                .collect::<Vec<_>>();
+            let tile = PointCloud::from_vec(storage, indices.len());
+            let bound = tile.finite_bound().unwrap();
+
+            let name = format!("tile_{}.pcd", index);
+            let mut writer = BufWriter::new(File::create(dir.join(&name))?);
+            Pcd::from_point_cloud(&tile, viewpoint, self.data).write(&mut writer)?;
+            writer.flush()?;
+
+            tiles.push(Tile {
+                name,
+                len: tile.len(),
+                bound,
+            });
+        }
+
+        let index = TileIndex {
+            dir: dir.to_owned(),
+            tiles,
+        };
+        index.write_manifest()?;
+        Ok(index)
+    }
+
+    fn split<P: Point<Data = T>>(&self, point_cloud: &PointCloud<P>) -> Vec<Vec<usize>> {
+        let [min, max] = match point_cloud.finite_bound() {
+            Some(bound) => bound,
+            None => return Vec::new(),
+        };
+        let finite = { point_cloud.iter().enumerate() }
+            .filter(|(_, point)| point.is_finite())
+            .map(|(index, _)| index);
+
+        match &self.tiling {
+            Tiling::Grid { tile_unit } => {
+                let mut tiles = BTreeMap::<_, Vec<_>>::new();
+                for index in finite {
+                    let key = (point_cloud[index].coords() - &min)
+                        .component_div(tile_unit)
+                        .map(|x| x.floor().to_usize().unwrap());
+                    tiles.entry(*key.xyz().as_ref()).or_default().push(index);
+                }
+                tiles.into_values().collect()
+            }
+            Tiling::Octree { max_points } => {
+                let mut tiles = Vec::new();
+                split_octree(
+                    point_cloud,
+                    finite.collect(),
+                    [min, max],
+                    (*max_points).max(1),
+                    0,
+                    &mut tiles,
+                );
+                tiles
+            }
+        }
+    }
+}
+
+fn split_octree<P>(
+    point_cloud: &PointCloud<P>,
+    indices: Vec<usize>,
+    [min, max]: [Vector4<P::Data>; 2],
+    max_points: usize,
+    depth: usize,
+    tiles: &mut Vec<Vec<usize>>,
+) where
+    P: Point,
+    P::Data: RealField,
+{
+    if indices.len() <= max_points || depth >= MAX_OCTREE_DEPTH {
+        if !indices.is_empty() {
+            tiles.push(indices);
+        }
+        return;
+    }
+
+    let center = (&min + &max) / nalgebra::convert::<_, P::Data>(2.);
+    let mut children: [Vec<usize>; 8] = Default::default();
+    for index in indices {
+        let coords = point_cloud[index].coords();
+        let octant = (coords.x > center.x) as usize
+            | ((coords.y > center.y) as usize) << 1
+            | ((coords.z > center.z) as usize) << 2;
+        children[octant].push(index);
+    }
+
+    for (octant, indices) in children.into_iter().enumerate() {
+        let mut child_min = min.clone();
+        let mut child_max = center.clone();
+        for axis in 0..3 {
+            if octant & (1 << axis) != 0 {
+                child_min[axis] = center[axis].clone();
+                child_max[axis] = max[axis].clone();
+            }
+        }
+        let bound = [child_min, child_max];
+        split_octree(point_cloud, indices, bound, max_points, depth + 1, tiles);
+    }
+}
+
+/// The manifest of a tile directory, which loads the tiles lazily.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileIndex<T: Scalar> {
+    dir: PathBuf,
+    pub tiles: Vec<Tile<T>>,
+}
+
+impl<T: Scalar> TileIndex<T> {
+    #[inline]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.tiles.iter().map(|tile| tile.len).sum()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Scalar + ToPrimitive> TileIndex<T> {
+    fn write_manifest(&self) -> Result<(), Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(self.dir.join(INDEX_FILE))?);
+        writeln!(writer, "# Point cloud tile index")?;
+        writeln!(writer, "TILES {}", self.tiles.len())?;
+        for tile in &self.tiles {
+            write!(writer, "{} {}", tile.name, tile.len)?;
+            for value in tile.bound.iter().flat_map(|bound| bound.iter().take(3)) {
+                write!(writer, " {}", value.to_f64().unwrap())?;
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<T: RealField + FromPrimitive> TileIndex<T> {
+    /// Opens the manifest of the tile directory `dir`, without loading any
+    /// tile.
+    pub fn open<Q: AsRef<Path>>(dir: Q) -> Result<Self, Box<dyn Error>> {
+        let dir = dir.as_ref();
+        let reader = BufReader::new(File::open(dir.join(INDEX_FILE))?);
+
+        let mut num = None;
+        let mut tiles = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            if let Some(data) = line.strip_prefix("TILES ") {
+                num = Some(data.trim().parse::<usize>()?);
+                continue;
+            }
+
+            let mut items = line.split_whitespace();
+            let name = items.next().unwrap().to_owned();
+            let len = { items.next() }
+                .ok_or_else(|| format!("Missing tile length: {:?}", line))?
+                .parse()?;
+            let values = items
+                .map(|item| item.parse::<f64>().map(|value| T::from_f64(value).unwrap()))
+                .try_collect::<Vec<_>>()?;
+            if values.len() != 6 {
+                return Err(format!("Invalid tile bound: {:?}", line).into());
+            }
+            let bound = [
+                Vector4::new(
+                    values[0].clone(),
+                    values[1].clone(),
+                    values[2].clone(),
+                    T::one(),
+                ),
+                Vector4::new(
+                    values[3].clone(),
+                    values[4].clone(),
+                    values[5].clone(),
+                    T::one(),
+                ),
+            ];
+            tiles.push(Tile { name, len, bound });
+        }
+
+        if num != Some(tiles.len()) {
+            return Err("TILES conflicts with the number of tiles".into());
+        }
+        Ok(TileIndex {
+            dir: dir.to_owned(),
+            tiles,
+        })
+    }
+
+    /// Returns the tiles that intersect with the box of `min` and `max`.
+    pub fn query<'a>(
+        &'a self,
+        min: &'a Vector4<T>,
+        max: &'a Vector4<T>,
+    ) -> impl Iterator<Item = &'a Tile<T>> + 'a {
+        { self.tiles.iter() }.filter(move |tile| tile.intersects(min, max))
+    }
+
+    pub fn load<P>(&self, tile: &Tile<T>) -> Result<PointCloud<P>, Box<dyn Error>>
+    where
+        P: Point<Data = T> + DataFields,
+    {
+        let reader = BufReader::new(File::open(self.dir.join(&tile.name))?);
+        let (point_cloud, _) = Pcd::read(reader)?.to_point_cloud()?;
+        Ok(point_cloud)
+    }
+
+    /// Reads the points in the box of `min` and `max`, loading only the tiles
+    /// intersecting with it.
+    pub fn read_region<P>(
+        &self,
+        min: &Vector4<T>,
+        max: &Vector4<T>,
+    ) -> Result<PointCloud<P>, Box<dyn Error>>
+    where
+        P: Point<Data = T> + DataFields,
+    {
+        let mut storage = Vec::new();
+        for tile in self.query(min, max) {
+            let point_cloud = self.load::<P>(tile)?;
+            let indices = point_cloud.box_select(min, max);
+            storage.extend(indices.into_iter().map(|index| point_cloud[index].clone()));
+        }
+        Ok(PointCloud::from_vec(storage, 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::{
+        point::{Point, Point3},
+        point_cloud::PointCloud,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_tiling() {
+        let storage = (0..1000)
+            .map(|index| {
+                let coords = Vector4::new(
+                    (index % 10) as f32,
+                    (index / 10 % 10) as f32,
+                    (index / 100) as f32,
+                    1.,
+                );
+                Point3::default().with_coords(coords)
+            })
+            .collect::<Vec<_>>();
+        let pc = PointCloud::from_vec(storage, 1);
+        let (min, max) = (
+            Vector4::new(1.5, 1.5, 1.5, 1.),
+            Vector4::new(3.5, 3.5, 3.5, 1.),
+        );
+
+        let tilings = [
+            Tiling::Grid {
+                tile_unit: Vector4::new(5., 5., 5., 1.),
+            },
+            Tiling::Octree { max_points: 100 },
+        ];
+        for tiling in tilings {
+            let dir = tempfile::tempdir().expect("Failed to create test directory");
+            let writer = TileWriter::new(tiling, PcdData::Binary);
+            let index = { writer.write(&pc, &Default::default(), dir.path()) }
+                .expect("Failed to write tiles");
+            assert_eq!(index.len(), 1000);
+            assert!(index.tiles.len() >= 8);
+            assert!(index.query(&min, &max).count() < index.tiles.len());
+
+            let index2 = TileIndex::open(dir.path()).expect("Failed to open tile index");
+            assert_eq!(index, index2);
+
+            let region =
+                { index2.read_region::<Point3>(&min, &max) }.expect("Failed to read region");
+            assert_eq!(region.len(), 8);
+        }
+    }
+}
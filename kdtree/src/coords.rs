@@ -0,0 +1,423 @@
+use std::{borrow::Borrow, ptr::NonNull};
+
+use nalgebra::{convert, RealField, Scalar, Vector4};
+use pcc_common::search::SearchType;
+
+use crate::{
+    node::Node, CountResultSet, KnnResultSet, RadiusResultSet, ResultSet, SearchScratch,
+    VisitResultSet,
+};
+
+/// A kd-tree over bare coordinates, e.g. keypoints or coordinates reduced
+/// from descriptors, which don't need wrapping in point clouds.
+///
+/// The results are the indices of the coordinates in the slice the tree is
+/// built on. Non-finite coordinates are left out, since they can't be split.
+///
+/// The points inserted after the construction are appended to the leaves
+/// without rebalancing, so the tree is rebuilt once their number exceeds
+/// the rebuild threshold times the number of points at the last build, 0.5
+/// by default.
+pub struct CoordsKdTree<'a, T: Scalar> {
+    pub(crate) root: Option<NonNull<Node<'a, T>>>,
+    len: usize,
+    balanced: usize,
+    rebuild_threshold: Option<T>,
+}
+
+unsafe impl<'a, T: Scalar + Send + Sync> Send for CoordsKdTree<'a, T> {}
+unsafe impl<'a, T: Scalar + Sync> Sync for CoordsKdTree<'a, T> {}
+
+fn is_finite<T: RealField>(coords: &Vector4<T>) -> bool {
+    coords.iter().all(|x| x.is_finite())
+}
+
+impl<'a, T: RealField> CoordsKdTree<'a, T> {
+    /// Builds the tree over `coords`, such as `&[Vector4<T>]`, or an empty
+    /// tree if `coords` is empty.
+    pub fn new<C: Borrow<Vector4<T>>>(coords: &'a [C]) -> Self {
+        Self::build(coords.len(), move |index| coords[index].borrow())
+    }
+
+    /// Builds the tree over `len` coordinates, where `coords` returns the
+    /// coordinates of a point by its index.
+    pub(crate) fn build(len: usize, coords: impl Fn(usize) -> &'a Vector4<T>) -> Self {
+        let mut indices = { 0..len }
+            .filter(|&index| is_finite(coords(index)))
+            .collect::<Vec<_>>();
+        let len = indices.len();
+        let root = (len > 0).then(|| Node::build(&coords, &mut indices, None));
+        CoordsKdTree {
+            root,
+            len,
+            balanced: len,
+            rebuild_threshold: Some(convert(0.5)),
+        }
+    }
+
+    /// Sets the ratio of the points inserted since the last build to the
+    /// points at it, beyond which the tree is rebuilt, or `None` to never
+    /// rebuild automatically.
+    pub fn with_rebuild_threshold(mut self, rebuild_threshold: Option<T>) -> Self {
+        self.rebuild_threshold = rebuild_threshold;
+        self
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the tree exceeds the rebuild threshold after inserting
+    /// `additional` more points.
+    fn is_unbalanced(&self, additional: usize) -> bool {
+        match &self.rebuild_threshold {
+            Some(threshold) => {
+                let inserted = T::from_usize(self.len + additional - self.balanced).unwrap();
+                inserted > threshold.clone() * T::from_usize(self.balanced).unwrap()
+            }
+            None => false,
+        }
+    }
+
+    fn insert_unbalanced(&mut self, index: usize, pivot: &'a Vector4<T>) {
+        if !is_finite(pivot) {
+            return;
+        }
+        match self.root {
+            Some(mut root) => unsafe { root.as_mut() }.insert(index, pivot),
+            None => {
+                let node = Box::leak(Box::new(Node::new_leaf(index, pivot)));
+                self.root = Some(node.into());
+            }
+        }
+        self.len += 1;
+    }
+
+    pub fn insert(&mut self, index: usize, pivot: &'a Vector4<T>) {
+        self.insert_unbalanced(index, pivot);
+        if self.is_unbalanced(0) {
+            self.rebuild();
+        }
+    }
+
+    /// Inserts the points of `index` and `pivot` in bulk, which rebuilds the
+    /// tree at most once, if the points would exceed the rebuild threshold.
+    pub fn insert_many<I>(&mut self, points: I)
+    where
+        I: IntoIterator<Item = (usize, &'a Vector4<T>)>,
+    {
+        let points = { points.into_iter() }
+            .filter(|(_, pivot)| is_finite(pivot))
+            .collect::<Vec<_>>();
+        if self.is_unbalanced(points.len()) {
+            let mut all = Vec::with_capacity(self.len + points.len());
+            if let Some(root) = self.root {
+                unsafe { root.as_ref() }.leaves(&mut all);
+            }
+            all.extend(points);
+            self.rebuild_from(all);
+        } else {
+            for (index, pivot) in points {
+                self.insert_unbalanced(index, pivot);
+            }
+        }
+    }
+
+    /// Rebuilds the tree from all of its points, balancing it.
+    pub fn rebuild(&mut self) {
+        let mut points = Vec::with_capacity(self.len);
+        if let Some(root) = self.root {
+            unsafe { root.as_ref() }.leaves(&mut points);
+        }
+        self.rebuild_from(points);
+    }
+
+    fn rebuild_from(&mut self, points: Vec<(usize, &'a Vector4<T>)>) {
+        self.destroy();
+        self.len = points.len();
+        self.balanced = points.len();
+        if points.is_empty() {
+            return;
+        }
+
+        // The tree is built over the positions in `points`, which are then
+        // mapped to their indices.
+        let mut positions = (0..points.len()).collect::<Vec<_>>();
+        let coords = |position: usize| points[position].1;
+        let mut root = Node::build(&coords, &mut positions, None);
+        unsafe { root.as_mut() }.map_indices(&|position: usize| points[position].0);
+        self.root = Some(root);
+    }
+
+    pub fn search_typed(
+        &self,
+        pivot: &Vector4<T>,
+        result: &mut impl ResultSet<Key = T, Value = usize>,
+    ) {
+        SearchScratch::with_local(|scratch| self.search_typed_with(pivot, result, scratch))
+    }
+
+    /// Like [`CoordsKdTree::search_typed`], but traverses the tree with the
+    /// buffers in `scratch` instead of the thread-local ones.
+    pub fn search_typed_with(
+        &self,
+        pivot: &Vector4<T>,
+        result: &mut impl ResultSet<Key = T, Value = usize>,
+        scratch: &mut SearchScratch,
+    ) {
+        if let Some(root) = self.root {
+            unsafe { root.as_ref() }.search(pivot, result, scratch, &T::one())
+        }
+    }
+
+    /// Like [`CoordsKdTree::search_typed`], but prunes the branches that
+    /// can't be closer than the farthest result divided by `1 + epsilon`, so
+    /// the results may miss some neighbors, but are within `1 + epsilon`
+    /// times the distances of the exact ones.
+    pub fn search_approx_typed(
+        &self,
+        pivot: &Vector4<T>,
+        epsilon: T,
+        result: &mut impl ResultSet<Key = T, Value = usize>,
+    ) {
+        if let Some(root) = self.root {
+            let factor = T::one() + epsilon;
+            SearchScratch::with_local(|scratch| {
+                unsafe { root.as_ref() }.search(pivot, result, scratch, &factor)
+            })
+        }
+    }
+
+    pub fn search_exact_typed(
+        &self,
+        pivot: &Vector4<T>,
+        result: &mut impl ResultSet<Key = T, Value = usize>,
+    ) {
+        if let Some(root) = self.root {
+            unsafe { root.as_ref() }.search_exact(pivot, result)
+        }
+    }
+
+    pub fn search(&self, pivot: &Vector4<T>, ty: SearchType<T>, result: &mut Vec<(usize, T)>) {
+        result.clear();
+        match ty {
+            SearchType::Knn(num) => {
+                let mut rs = KnnResultSet::new(num);
+                self.search_typed(pivot, &mut rs);
+                rs.drain_sorted_into(result);
+            }
+            SearchType::Radius(radius) => {
+                let mut rs = RadiusResultSet::new(radius);
+                self.search_typed(pivot, &mut rs);
+                result.extend(rs.into_iter().map(|(d, v)| (v, d)));
+            }
+            SearchType::KnnWithin { k, radius } => {
+                let mut rs = KnnResultSet::with_radius(k, radius);
+                self.search_typed(pivot, &mut rs);
+                rs.drain_sorted_into(result);
+            }
+        }
+    }
+
+    pub fn search_exact(
+        &self,
+        pivot: &Vector4<T>,
+        ty: SearchType<T>,
+        result: &mut Vec<(usize, T)>,
+    ) {
+        result.clear();
+        match ty {
+            SearchType::Knn(num) => {
+                let mut rs = KnnResultSet::new(num);
+                self.search_exact_typed(pivot, &mut rs);
+                rs.drain_sorted_into(result);
+            }
+            SearchType::Radius(radius) => {
+                let mut rs = RadiusResultSet::new(radius);
+                self.search_exact_typed(pivot, &mut rs);
+                result.extend(rs.into_iter().map(|(d, v)| (v, d)));
+            }
+            SearchType::KnnWithin { k, radius } => {
+                let mut rs = KnnResultSet::with_radius(k, radius);
+                self.search_exact_typed(pivot, &mut rs);
+                rs.drain_sorted_into(result);
+            }
+        }
+    }
+
+    /// Like [`CoordsKdTree::search`], but approximate like
+    /// [`CoordsKdTree::search_approx_typed`].
+    pub fn search_approx(
+        &self,
+        pivot: &Vector4<T>,
+        ty: SearchType<T>,
+        epsilon: T,
+        result: &mut Vec<(usize, T)>,
+    ) {
+        result.clear();
+        match ty {
+            SearchType::Knn(num) => {
+                let mut rs = KnnResultSet::new(num);
+                self.search_approx_typed(pivot, epsilon, &mut rs);
+                rs.drain_sorted_into(result);
+            }
+            SearchType::Radius(radius) => {
+                let mut rs = RadiusResultSet::new(radius);
+                self.search_approx_typed(pivot, epsilon, &mut rs);
+                result.extend(rs.into_iter().map(|(d, v)| (v, d)));
+            }
+            SearchType::KnnWithin { k, radius } => {
+                let mut rs = KnnResultSet::with_radius(k, radius);
+                self.search_approx_typed(pivot, epsilon, &mut rs);
+                rs.drain_sorted_into(result);
+            }
+        }
+    }
+
+    pub fn search_with(
+        &self,
+        pivot: &Vector4<T>,
+        ty: SearchType<T>,
+        visitor: &mut dyn FnMut(usize, T),
+    ) {
+        match ty {
+            SearchType::Knn(num) => {
+                let mut rs = KnnResultSet::new(num);
+                self.search_typed(pivot, &mut rs);
+                rs.into_iter().for_each(|(d, v)| visitor(v, d));
+            }
+            SearchType::Radius(radius) => {
+                let mut rs = VisitResultSet::new(radius, |d, v| visitor(v, d));
+                self.search_typed(pivot, &mut rs);
+            }
+            SearchType::KnnWithin { k, radius } => {
+                let mut rs = KnnResultSet::with_radius(k, radius);
+                self.search_typed(pivot, &mut rs);
+                rs.into_iter().for_each(|(d, v)| visitor(v, d));
+            }
+        }
+    }
+
+    pub fn count_radius(&self, pivot: &Vector4<T>, radius: T, max: usize) -> usize {
+        let mut rs = CountResultSet::new(radius, max);
+        self.search_typed(pivot, &mut rs);
+        rs.count()
+    }
+}
+
+impl<'a, T: Scalar> CoordsKdTree<'a, T> {
+    fn destroy(&mut self) {
+        if let Some(mut root) = self.root.take() {
+            unsafe {
+                root.as_mut().destroy();
+                let _ = Box::from_raw(root.as_ptr());
+            }
+        }
+    }
+}
+
+impl<'a, T: Scalar> Drop for CoordsKdTree<'a, T> {
+    fn drop(&mut self) {
+        self.destroy()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coords_kdtree() {
+        let coords = { (0..64).map(|i| i as f32) }
+            .map(|i| Vector4::new(i % 4., (i / 4.).floor() % 4., (i / 16.).floor(), 1.))
+            .collect::<Vec<_>>();
+        let tree = CoordsKdTree::new(&coords);
+
+        let mut result = Vec::new();
+        tree.search_exact(
+            &Vector4::new(1.1, 2., 3.2, 1.),
+            SearchType::Knn(1),
+            &mut result,
+        );
+        assert_eq!(result.len(), 1);
+        assert_eq!(coords[result[0].0], Vector4::new(1., 2., 3., 1.));
+        assert_eq!(
+            tree.count_radius(&Vector4::new(0., 0., 0., 1.), 1.1, usize::MAX),
+            4
+        );
+
+        let refs = coords.iter().collect::<Vec<_>>();
+        let tree = CoordsKdTree::new(&refs);
+        tree.search(
+            &Vector4::new(3., 3., 0., 1.),
+            SearchType::Radius(0.5),
+            &mut result,
+        );
+        assert_eq!(result, [(15, 0.)]);
+
+        // Bounded by both the number of neighbors and the radius.
+        let origin = Vector4::new(0., 0., 0., 1.);
+        tree.search(
+            &origin,
+            SearchType::KnnWithin { k: 8, radius: 1.1 },
+            &mut result,
+        );
+        assert_eq!(result.len(), 4);
+        tree.search(
+            &origin,
+            SearchType::KnnWithin { k: 2, radius: 1.1 },
+            &mut result,
+        );
+        assert_eq!(result.len(), 2);
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let coords = { (0..256).map(|i| i as f32) }
+            .map(|i| Vector4::new(i * 0.1, (i * 0.7).sin(), 0., 1.))
+            .collect::<Vec<_>>();
+        let mut tree = CoordsKdTree::new(&coords[..16]);
+
+        // Within the threshold, so appended to the leaves.
+        tree.insert_many((16..20).map(|index| (index, &coords[index])));
+        assert_eq!((tree.len(), tree.balanced), (20, 16));
+        // Beyond the threshold, so rebuilt with all the points.
+        tree.insert_many((20..256).map(|index| (index, &coords[index])));
+        assert_eq!((tree.len(), tree.balanced), (256, 256));
+
+        let pivot = Vector4::new(12.3, 0.2, 0., 1.);
+        let mut result = Vec::new();
+        tree.search(&pivot, SearchType::Knn(3), &mut result);
+        let mut expected = { coords.iter().enumerate() }
+            .map(|(index, coord)| (index, (coord - pivot).xyz().norm()))
+            .collect::<Vec<_>>();
+        expected.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+        expected.truncate(3);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_search_approx() {
+        let coords = { (0..512).map(|i| i as f32) }
+            .map(|i| Vector4::new((i * 0.37).sin(), (i * 0.71).cos(), (i * 0.13).sin(), 1.))
+            .collect::<Vec<_>>();
+        let tree = CoordsKdTree::new(&coords);
+
+        let pivot = Vector4::new(0.1, 0.2, -0.3, 1.);
+        let (mut exact, mut approx) = (Vec::new(), Vec::new());
+        tree.search_exact(&pivot, SearchType::Knn(5), &mut exact);
+        tree.search_approx(&pivot, SearchType::Knn(5), 0., &mut approx);
+        assert_eq!(approx, exact);
+
+        tree.search_approx(&pivot, SearchType::Knn(5), 0.5, &mut approx);
+        assert_eq!(approx.len(), 5);
+        assert!({ approx.iter().zip(&exact) }.all(|((_, a), (_, e))| *a <= e * 1.5));
+    }
+}
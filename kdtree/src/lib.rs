@@ -1,20 +1,19 @@
 #![feature(type_alias_impl_trait)]
 
+mod coords;
 mod node;
+mod planar;
 mod result;
-
-use std::ptr::NonNull;
+mod scratch;
 
 use nalgebra::{RealField, Vector4};
-use node::Node;
 use pcc_common::{point::Point, point_cloud::PointCloud, search::SearchType};
 
-pub use self::result::*;
+pub use self::{coords::CoordsKdTree, planar::PlanarKdTree, result::*, scratch::SearchScratch};
 
 pub struct KdTree<'a, P: Point> {
     point_cloud: &'a PointCloud<P>,
-    root: Option<NonNull<Node<'a, P::Data>>>,
-    indices: Vec<usize>,
+    inner: CoordsKdTree<'a, P::Data>,
 }
 
 unsafe impl<'a, P: Point + Send> Send for KdTree<'a, P> {}
@@ -25,17 +24,40 @@ where
     P::Data: RealField,
 {
     pub fn insert(&mut self, index: usize, pivot: &'a Vector4<P::Data>) {
-        match self.root {
-            Some(mut root) => unsafe { root.as_mut() }.insert(index, pivot),
-            None => {
-                let node = Box::leak(Box::new(Node::new_leaf(index, pivot)));
-                self.root = Some(node.into());
-            }
-        }
-        if self.indices.len() <= index {
-            self.indices.resize(index + 1, 0)
-        }
-        self.indices[index] = index;
+        self.inner.insert(index, pivot);
+    }
+
+    /// Inserts the points of `index` and `pivot` in bulk, like
+    /// [`CoordsKdTree::insert_many`].
+    pub fn insert_many<I>(&mut self, points: I)
+    where
+        I: IntoIterator<Item = (usize, &'a Vector4<P::Data>)>,
+    {
+        self.inner.insert_many(points)
+    }
+
+    /// Sets the rebuild threshold like
+    /// [`CoordsKdTree::with_rebuild_threshold`].
+    pub fn with_rebuild_threshold(mut self, rebuild_threshold: Option<P::Data>) -> Self {
+        self.inner = self.inner.with_rebuild_threshold(rebuild_threshold);
+        self
+    }
+
+    /// Rebuilds the tree from all of its points, balancing it.
+    #[inline]
+    pub fn rebuild(&mut self) {
+        self.inner.rebuild()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
     }
 }
 
@@ -48,9 +70,18 @@ where
         pivot: &Vector4<P::Data>,
         result: &mut impl ResultSet<Key = P::Data, Value = usize>,
     ) {
-        if let Some(root) = self.root {
-            unsafe { root.as_ref() }.search(pivot, result)
-        }
+        self.inner.search_typed(pivot, result)
+    }
+
+    /// Like [`KdTree::search_typed`], but traverses the tree with the buffers
+    /// in `scratch` instead of the thread-local ones.
+    pub fn search_typed_with(
+        &self,
+        pivot: &Vector4<P::Data>,
+        result: &mut impl ResultSet<Key = P::Data, Value = usize>,
+        scratch: &mut SearchScratch,
+    ) {
+        self.inner.search_typed_with(pivot, result, scratch)
     }
 
     pub fn search_exact_typed(
@@ -58,20 +89,17 @@ where
         pivot: &Vector4<P::Data>,
         result: &mut impl ResultSet<Key = P::Data, Value = usize>,
     ) {
-        if let Some(root) = self.root {
-            unsafe { root.as_ref() }.search_exact(pivot, result)
-        }
+        self.inner.search_exact_typed(pivot, result)
     }
-}
 
-impl<'a, P: Point> Drop for KdTree<'a, P> {
-    fn drop(&mut self) {
-        if let Some(mut root) = self.root {
-            unsafe {
-                root.as_mut().destroy();
-                let _ = Box::from_raw(root.as_ptr());
-            }
-        }
+    /// See [`CoordsKdTree::search_approx_typed`].
+    pub fn search_approx_typed(
+        &self,
+        pivot: &Vector4<P::Data>,
+        epsilon: P::Data,
+        result: &mut impl ResultSet<Key = P::Data, Value = usize>,
+    ) {
+        self.inner.search_approx_typed(pivot, epsilon, result)
     }
 }
 
@@ -82,13 +110,9 @@ where
     pub fn new(point_cloud: &'a PointCloud<P>) -> Self {
         assert!(!point_cloud.is_empty());
 
-        let mut indices = (0..point_cloud.len()).collect::<Vec<_>>();
-        let root = Node::build(0, point_cloud, &mut indices, None);
-        KdTree {
-            point_cloud,
-            root: Some(root),
-            indices,
-        }
+        let inner =
+            CoordsKdTree::build(point_cloud.len(), move |index| point_cloud[index].coords());
+        KdTree { point_cloud, inner }
     }
 }
 
@@ -106,19 +130,7 @@ where
         ty: SearchType<P::Data>,
         result: &mut Vec<(usize, P::Data)>,
     ) {
-        result.clear();
-        match ty {
-            SearchType::Knn(num) => {
-                let mut rs = KnnResultSet::new(num);
-                self.search_typed(pivot, &mut rs);
-                result.extend(rs.into_iter().map(|(d, v)| (v, d)));
-            }
-            SearchType::Radius(radius) => {
-                let mut rs = RadiusResultSet::new(radius);
-                self.search_typed(pivot, &mut rs);
-                result.extend(rs.into_iter().map(|(d, v)| (v, d)));
-            }
-        }
+        self.inner.search(pivot, ty, result)
     }
 
     fn search_exact(
@@ -127,18 +139,29 @@ where
         ty: SearchType<P::Data>,
         result: &mut Vec<(usize, P::Data)>,
     ) {
-        result.clear();
-        match ty {
-            SearchType::Knn(num) => {
-                let mut rs = KnnResultSet::new(num);
-                self.search_exact_typed(pivot, &mut rs);
-                result.extend(rs.into_iter().map(|(d, v)| (v, d)));
-            }
-            SearchType::Radius(radius) => {
-                let mut rs = RadiusResultSet::new(radius);
-                self.search_exact_typed(pivot, &mut rs);
-                result.extend(rs.into_iter().map(|(d, v)| (v, d)));
-            }
-        }
+        self.inner.search_exact(pivot, ty, result)
+    }
+
+    fn search_approx(
+        &self,
+        pivot: &Vector4<P::Data>,
+        ty: SearchType<P::Data>,
+        epsilon: P::Data,
+        result: &mut Vec<(usize, P::Data)>,
+    ) {
+        self.inner.search_approx(pivot, ty, epsilon, result)
+    }
+
+    fn search_with(
+        &self,
+        pivot: &Vector4<P::Data>,
+        ty: SearchType<P::Data>,
+        visitor: &mut dyn FnMut(usize, P::Data),
+    ) {
+        self.inner.search_with(pivot, ty, visitor)
+    }
+
+    fn count_radius(&self, pivot: &Vector4<P::Data>, radius: P::Data, max: usize) -> usize {
+        self.inner.count_radius(pivot, radius, max)
     }
 }
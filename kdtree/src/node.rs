@@ -2,9 +2,8 @@ use std::ptr::NonNull;
 
 use bitvec::vec::BitVec;
 use nalgebra::{convert, RealField, Scalar, Vector3, Vector4};
-use pcc_common::point::Point;
 
-use crate::ResultSet;
+use crate::{ResultSet, SearchScratch};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub(crate) enum Node<'a, T: Scalar> {
@@ -44,42 +43,44 @@ impl<'a, T: Scalar> Node<'a, T> {
     }
 }
 
-fn cut_split<T: Scalar + PartialOrd, P: Point<Data = T>>(
-    coords: &[P],
+fn cut_split<'a, T: Scalar + PartialOrd>(
+    coords: &impl Fn(usize) -> &'a Vector4<T>,
     indices: &mut [usize],
     dim: usize,
     value: T,
 ) -> (usize, usize) {
+    // `right` is exclusive, so that it doesn't underflow if no coordinate is
+    // less than `value`, e.g. the mean of equal coordinates rounded down.
     let mut left = 0;
-    let mut right = indices.len() - 1;
+    let mut right = indices.len();
     loop {
-        while left <= right && coords[indices[left]].coords()[dim] < value {
+        while left < right && coords(indices[left])[dim] < value {
             left += 1
         }
-        while left <= right && coords[indices[right]].coords()[dim] >= value {
+        while left < right && coords(indices[right - 1])[dim] >= value {
             right -= 1
         }
-        if left > right {
+        if left >= right {
             break;
         }
-        indices.swap(left, right);
+        indices.swap(left, right - 1);
         left += 1;
         right -= 1;
     }
 
     let limit_left = left;
-    right = indices.len() - 1;
+    right = indices.len();
     loop {
-        while left <= right && coords[indices[left]].coords()[dim] <= value {
+        while left < right && coords(indices[left])[dim] <= value {
             left += 1
         }
-        while left <= right && coords[indices[right]].coords()[dim] > value {
+        while left < right && coords(indices[right - 1])[dim] > value {
             right -= 1
         }
-        if left > right {
+        if left >= right {
             break;
         }
-        indices.swap(left, right);
+        indices.swap(left, right - 1);
         left += 1;
         right -= 1;
     }
@@ -89,23 +90,22 @@ fn cut_split<T: Scalar + PartialOrd, P: Point<Data = T>>(
     (limit_left, limit_right)
 }
 
-fn cut<T: RealField, P: Point<Data = T>>(
-    coords: &[P],
+fn cut<'a, T: RealField>(
+    coords: &impl Fn(usize) -> &'a Vector4<T>,
     indices: &mut [usize],
     last: Option<usize>,
 ) -> (usize, usize, T) {
     let sum = { indices.iter() }
-        .map(|&i| coords[i].coords().xyz())
+        .map(|&i| coords(i).xyz())
         .fold(Vector3::zeros(), |acc, coord| acc + coord);
 
-    let mean = sum / T::from_usize(coords.len()).unwrap();
-    let var = { indices.iter() }.map(|&i| coords[i].coords().xyz()).fold(
-        Vector3::zeros(),
-        |acc, coord| {
+    let mean = sum / T::from_usize(indices.len()).unwrap();
+    let var = { indices.iter() }
+        .map(|&i| coords(i).xyz())
+        .fold(Vector3::zeros(), |acc, coord| {
             let diff = coord - mean.clone();
             acc + diff.component_mul(&diff)
-        },
-    );
+        });
 
     let dim = {
         let dim = var.imax();
@@ -114,8 +114,8 @@ fn cut<T: RealField, P: Point<Data = T>>(
                 .enumerate()
                 .filter(|(i, _)| i != &dim)
                 .fold(None, |acc, (i, v)| match acc {
-                    Some(d) if v > &var[d] => Some(i),
-                    _ => acc,
+                    Some(d) if v <= &var[d] => acc,
+                    _ => Some(i),
                 })
                 .unwrap()
         } else {
@@ -139,24 +139,21 @@ fn cut<T: RealField, P: Point<Data = T>>(
 }
 
 impl<'a, T: RealField> Node<'a, T> {
-    pub fn build<P>(
-        start_index: usize,
-        coords: &'a [P],
-        indices: &mut [usize],
-        last_dim: Option<usize>,
-    ) -> NonNull<Self>
+    /// Builds the tree of the points in `indices`, where `coords` returns the
+    /// coordinates of a point by its index.
+    pub fn build<F>(coords: &F, indices: &mut [usize], last_dim: Option<usize>) -> NonNull<Self>
     where
-        P: Point<Data = T>,
+        F: Fn(usize) -> &'a Vector4<T>,
     {
         let node = if indices.len() == 1 {
-            let coord: &'a Vector4<T> = coords[indices[0]].coords();
-            Node::new_leaf(start_index, coord)
+            let coord = coords(indices[0]);
+            Node::new_leaf(indices[0], coord)
         } else {
             let (split, dim, value) = cut(coords, indices, last_dim);
             let (left, right) = indices.split_at_mut(split);
 
-            let left = Node::build(start_index, coords, left, Some(dim));
-            let right = Node::build(start_index + split, coords, right, Some(dim));
+            let left = Node::build(coords, left, Some(dim));
+            let right = Node::build(coords, right, Some(dim));
 
             Node::Branch {
                 children: [left, right],
@@ -168,6 +165,36 @@ impl<'a, T: RealField> Node<'a, T> {
     }
 }
 
+impl<'a, T: Scalar> Node<'a, T> {
+    /// Collects the indices and the coordinates of the leaves.
+    pub(crate) fn leaves(&self, output: &mut Vec<(usize, &'a Vector4<T>)>) {
+        match *self {
+            Node::Leaf { index, coord } => output.push((index, coord)),
+            Node::Branch {
+                children: [left, right],
+                ..
+            } => unsafe {
+                left.as_ref().leaves(output);
+                right.as_ref().leaves(output);
+            },
+        }
+    }
+
+    /// Replaces the indices of the leaves with the results of `map`.
+    pub(crate) fn map_indices(&mut self, map: &impl Fn(usize) -> usize) {
+        match self {
+            Node::Leaf { index, .. } => *index = map(*index),
+            Node::Branch {
+                children: [left, right],
+                ..
+            } => unsafe {
+                left.as_mut().map_indices(map);
+                right.as_mut().map_indices(map);
+            },
+        }
+    }
+}
+
 impl<'a, T: RealField> Node<'a, T> {
     pub fn insert(&mut self, index: usize, pivot: &'a Vector4<T>) {
         let mut node = self;
@@ -240,14 +267,14 @@ impl<'a, T: RealField> Node<'a, T> {
         &self,
         pivot: &Vector4<T>,
         result: &mut impl ResultSet<Key = T, Value = usize>,
-        other_branches: &mut Vec<NonNull<Node<'a, T>>>,
-        checker: &mut BitVec,
+        scratch: &mut SearchScratch,
+        factor: &T,
     ) {
         let mut node = self;
         loop {
             match *node {
                 Node::Leaf { index, coord } => {
-                    if !check_and_set(index, checker) {
+                    if !check_and_set(index, &mut scratch.checker) {
                         let distance = (coord.xyz() - pivot.xyz()).norm();
                         result.push(distance, index);
                     }
@@ -264,10 +291,10 @@ impl<'a, T: RealField> Node<'a, T> {
                         (right, Some(left))
                     };
 
-                    let min_distance = (pivot[dim].clone() - value.clone()).abs();
+                    let min_distance = (pivot[dim].clone() - value.clone()).abs() * factor.clone();
                     if let Some(other) = other {
-                        if result.max_key() < Some(&min_distance) || !result.is_full() {
-                            other_branches.push(other)
+                        if !result.is_full() || result.max_key() > Some(&min_distance) {
+                            scratch.other_branches.push(other.cast())
                         }
                     }
 
@@ -302,7 +329,7 @@ impl<'a, T: RealField> Node<'a, T> {
 
                 let min_distance = (pivot[dim].clone() - value.clone()).abs();
                 if let Some(other) = other {
-                    if result.max_key() < Some(&min_distance) {
+                    if !result.is_full() || result.max_key() > Some(&min_distance) {
                         unsafe { other.as_ref() }.search_exact(pivot, result)
                     }
                 }
@@ -310,16 +337,25 @@ impl<'a, T: RealField> Node<'a, T> {
         }
     }
 
-    pub fn search(&self, pivot: &Vector4<T>, result: &mut impl ResultSet<Key = T, Value = usize>) {
-        let mut other_branches = Vec::new();
-        let mut checker = BitVec::new();
+    /// Searches the tree, where the branches are pruned once their minimum
+    /// distances times `factor` reach the maximum key of `result`, so that a
+    /// factor above one trades the accuracy for the speed.
+    pub fn search(
+        &self,
+        pivot: &Vector4<T>,
+        result: &mut impl ResultSet<Key = T, Value = usize>,
+        scratch: &mut SearchScratch,
+        factor: &T,
+    ) {
+        scratch.clear();
 
         let mut node = self;
         loop {
-            node.search_one(pivot, result, &mut other_branches, &mut checker);
+            node.search_one(pivot, result, scratch, factor);
 
-            node = match other_branches.pop() {
-                Some(node) => unsafe { node.as_ref() },
+            node = match scratch.other_branches.pop() {
+                // The stack only holds the nodes of this tree pushed above.
+                Some(node) => unsafe { node.cast::<Node<'a, T>>().as_ref() },
                 None => break,
             }
         }
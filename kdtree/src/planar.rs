@@ -0,0 +1,287 @@
+use std::cmp::Ordering;
+
+use nalgebra::{convert, Matrix2x3, RealField, Vector2, Vector3, Vector4};
+use pcc_common::{
+    point::Point,
+    point_cloud::PointCloud,
+    search::{Search, SearchType},
+};
+
+use crate::{CountResultSet, KnnResultSet, RadiusResultSet, ResultSet, VisitResultSet};
+
+/// A 2D kd-tree over the points projected onto a plane, so that the
+/// dimension along the normal of the plane doesn't influence the neighbors,
+/// e.g. for digital elevation models and other 2.5D processing.
+///
+/// The tree is implicit, where the node of the range `start..end` of the
+/// points is at its middle. Non-finite points are left out.
+///
+/// As a [`Search`], the pivots are projected likewise, and the distances are
+/// the ones on the plane.
+#[derive(Debug, Clone)]
+pub struct PlanarKdTree<'a, P: Point> {
+    point_cloud: &'a PointCloud<P>,
+    projection: Matrix2x3<P::Data>,
+    nodes: Vec<(usize, Vector2<P::Data>)>,
+    dims: Vec<usize>,
+}
+
+impl<'a, T: RealField, P: Point<Data = T>> PlanarKdTree<'a, P> {
+    /// Builds the tree on the XY plane.
+    pub fn new(point_cloud: &'a PointCloud<P>) -> Self {
+        Self::with_projection(point_cloud, Matrix2x3::identity())
+    }
+
+    /// Builds the tree on the plane perpendicular to `normal`.
+    pub fn with_normal(point_cloud: &'a PointCloud<P>, normal: &Vector3<P::Data>) -> Self {
+        let normal = normal.normalize();
+        let axis = if normal.x.clone().abs() < convert(0.5) {
+            Vector3::x()
+        } else {
+            Vector3::y()
+        };
+        let u = normal.cross(&axis).normalize();
+        let v = normal.cross(&u);
+        Self::with_projection(
+            point_cloud,
+            Matrix2x3::from_rows(&[u.transpose(), v.transpose()]),
+        )
+    }
+
+    /// Builds the tree on the 2D coordinates `projection * [x, y, z]`.
+    pub fn with_projection(point_cloud: &'a PointCloud<P>, projection: Matrix2x3<P::Data>) -> Self {
+        let mut nodes = { point_cloud.iter().enumerate() }
+            .filter(|(_, point)| point.is_finite())
+            .map(|(index, point)| (index, &projection * point.coords().xyz()))
+            .collect::<Vec<_>>();
+        let mut dims = vec![0; nodes.len()];
+        Self::build(&mut nodes, &mut dims);
+
+        PlanarKdTree {
+            point_cloud,
+            projection,
+            nodes,
+            dims,
+        }
+    }
+
+    fn build(nodes: &mut [(usize, Vector2<P::Data>)], dims: &mut [usize]) {
+        if nodes.len() <= 1 {
+            return;
+        }
+
+        let (min, max) = { nodes.iter() }.fold(
+            (nodes[0].1.clone(), nodes[0].1.clone()),
+            |(min, max), (_, coords)| (min.inf(coords), max.sup(coords)),
+        );
+        let dim = (max - min).imax();
+
+        let mid = nodes.len() / 2;
+        nodes.select_nth_unstable_by(mid, |(_, a), (_, b)| {
+            a[dim].partial_cmp(&b[dim]).unwrap_or(Ordering::Equal)
+        });
+        dims[mid] = dim;
+
+        let (left, right) = nodes.split_at_mut(mid);
+        let (left_dims, right_dims) = dims.split_at_mut(mid);
+        Self::build(left, left_dims);
+        Self::build(&mut right[1..], &mut right_dims[1..]);
+    }
+
+    #[inline]
+    pub fn projection(&self) -> &Matrix2x3<P::Data> {
+        &self.projection
+    }
+
+    /// Projects `coords` onto the plane of the tree.
+    #[inline]
+    pub fn project(&self, coords: &Vector4<P::Data>) -> Vector2<P::Data> {
+        &self.projection * coords.xyz()
+    }
+
+    fn search_range(
+        &self,
+        range: (usize, usize),
+        pivot: &Vector2<P::Data>,
+        result: &mut impl ResultSet<Key = P::Data, Value = usize>,
+    ) {
+        let (start, end) = range;
+        if start >= end {
+            return;
+        }
+        let mid = (start + end) / 2;
+        let (index, coords) = &self.nodes[mid];
+        result.push((coords - pivot).norm(), *index);
+
+        let dim = self.dims[mid];
+        let diff = pivot[dim].clone() - coords[dim].clone();
+        let (near, far) = if diff < T::zero() {
+            ((start, mid), (mid + 1, end))
+        } else {
+            ((mid + 1, end), (start, mid))
+        };
+        self.search_range(near, pivot, result);
+        if !result.is_full() || result.max_key() > Some(&diff.abs()) {
+            self.search_range(far, pivot, result);
+        }
+    }
+
+    /// Searches the tree for `pivot` on the plane.
+    pub fn search_typed(
+        &self,
+        pivot: &Vector2<P::Data>,
+        result: &mut impl ResultSet<Key = P::Data, Value = usize>,
+    ) {
+        self.search_range((0, self.nodes.len()), pivot, result)
+    }
+
+    fn range_query_range(
+        &self,
+        range: (usize, usize),
+        [min, max]: [&Vector2<P::Data>; 2],
+        result: &mut Vec<usize>,
+    ) {
+        let (start, end) = range;
+        if start >= end {
+            return;
+        }
+        let mid = (start + end) / 2;
+        let (index, coords) = &self.nodes[mid];
+        if min <= coords && coords <= max {
+            result.push(*index);
+        }
+
+        let dim = self.dims[mid];
+        if min[dim] <= coords[dim] {
+            self.range_query_range((start, mid), [min, max], result);
+        }
+        if coords[dim] <= max[dim] {
+            self.range_query_range((mid + 1, end), [min, max], result);
+        }
+    }
+
+    /// The indices of the points within the rectangle of `min` and `max` on
+    /// the plane.
+    pub fn range_query(&self, min: &Vector2<P::Data>, max: &Vector2<P::Data>) -> Vec<usize> {
+        let mut result = Vec::new();
+        self.range_query_range((0, self.nodes.len()), [min, max], &mut result);
+        result
+    }
+}
+
+impl<'a, P: Point> Search<'a, P> for PlanarKdTree<'a, P>
+where
+    P::Data: RealField,
+{
+    fn input(&self) -> &'a PointCloud<P> {
+        self.point_cloud
+    }
+
+    fn search(
+        &self,
+        pivot: &Vector4<P::Data>,
+        ty: SearchType<P::Data>,
+        result: &mut Vec<(usize, P::Data)>,
+    ) {
+        result.clear();
+        let pivot = self.project(pivot);
+        match ty {
+            SearchType::Knn(num) => {
+                let mut rs = KnnResultSet::new(num);
+                self.search_typed(&pivot, &mut rs);
+                rs.drain_sorted_into(result);
+            }
+            SearchType::Radius(radius) => {
+                let mut rs = RadiusResultSet::new(radius);
+                self.search_typed(&pivot, &mut rs);
+                result.extend(rs.into_iter().map(|(d, v)| (v, d)));
+            }
+            SearchType::KnnWithin { k, radius } => {
+                let mut rs = KnnResultSet::with_radius(k, radius);
+                self.search_typed(&pivot, &mut rs);
+                rs.drain_sorted_into(result);
+            }
+        }
+    }
+
+    fn search_with(
+        &self,
+        pivot: &Vector4<P::Data>,
+        ty: SearchType<P::Data>,
+        visitor: &mut dyn FnMut(usize, P::Data),
+    ) {
+        let pivot = self.project(pivot);
+        match ty {
+            SearchType::Knn(num) => {
+                let mut rs = KnnResultSet::new(num);
+                self.search_typed(&pivot, &mut rs);
+                rs.into_iter().for_each(|(d, v)| visitor(v, d));
+            }
+            SearchType::Radius(radius) => {
+                let mut rs = VisitResultSet::new(radius, |d, v| visitor(v, d));
+                self.search_typed(&pivot, &mut rs);
+            }
+            SearchType::KnnWithin { k, radius } => {
+                let mut rs = KnnResultSet::with_radius(k, radius);
+                self.search_typed(&pivot, &mut rs);
+                rs.into_iter().for_each(|(d, v)| visitor(v, d));
+            }
+        }
+    }
+
+    fn count_radius(&self, pivot: &Vector4<P::Data>, radius: P::Data, max: usize) -> usize {
+        let mut rs = CountResultSet::new(radius, max);
+        self.search_typed(&self.project(pivot), &mut rs);
+        rs.count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pcc_common::point::Point3;
+
+    use super::*;
+
+    #[test]
+    fn test_planar() {
+        let storage = { (0..100).map(|i| i as f32) }
+            .map(|i| {
+                let coords = Vector4::new(i % 10., (i / 10.).floor(), i * 7. % 13., 1.);
+                Point3::default().with_coords(coords)
+            })
+            .collect::<Vec<_>>();
+        let pc = PointCloud::from_vec(storage, 1);
+        let tree = PlanarKdTree::new(&pc);
+
+        // The heights don't matter.
+        let mut result = Vec::new();
+        tree.search(
+            &Vector4::new(3.1, 4.2, 100., 1.),
+            SearchType::Knn(1),
+            &mut result,
+        );
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].0, 43);
+        assert!((result[0].1 - (0.01f32 + 0.04).sqrt()).abs() < 1e-5);
+
+        tree.search(
+            &Vector4::new(3., 4., -5., 1.),
+            SearchType::Radius(1.1),
+            &mut result,
+        );
+        let mut indices = result.iter().map(|&(index, _)| index).collect::<Vec<_>>();
+        indices.sort();
+        assert_eq!(indices, [33, 42, 43, 44, 53]);
+
+        let mut indices = tree.range_query(&Vector2::new(1.5, 0.5), &Vector2::new(3., 2.));
+        indices.sort();
+        assert_eq!(indices, [12, 13, 22, 23]);
+
+        // On the YZ plane, the points with the same Y and Z collapse.
+        let tree = PlanarKdTree::with_normal(&pc, &Vector3::x());
+        assert_eq!(
+            tree.count_radius(&Vector4::new(0., 4., 5., 1.), 1e-3, usize::MAX),
+            1
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::BinaryHeap;
+use std::{collections::BinaryHeap, marker::PhantomData};
 
 #[derive(Debug, Copy, Clone)]
 struct Node<K, V> {
@@ -43,21 +43,57 @@ pub trait ResultSet {
     fn is_full(&self) -> bool;
 
     fn max_key(&self) -> Option<&Self::Key>;
+
+    /// Reserves the capacity for at least `additional` more values, if the
+    /// values are stored.
+    fn reserve(&mut self, _additional: usize) {}
+
+    /// Pushes the values of `other` into this set, e.g. to combine the
+    /// results of searches over multiple partitions.
+    fn merge(&mut self, other: Self)
+    where
+        Self: Sized;
+
+    /// Moves the stored values to the end of `output` in the ascending order
+    /// of their keys as `(value, key)` pairs, like the results of searches.
+    ///
+    /// The set is left empty but keeps its capacity, so it can be reused
+    /// across queries without allocations.
+    fn drain_sorted_into(&mut self, output: &mut Vec<(Self::Value, Self::Key)>);
 }
 
+/// Keeps the `num` values with the smallest keys, optionally only the ones
+/// within `radius`.
 pub struct KnnResultSet<K, V> {
     data: BinaryHeap<Node<K, V>>,
     num: usize,
+    radius: Option<K>,
 }
 
 impl<K: PartialOrd, V: PartialOrd> KnnResultSet<K, V> {
     pub fn new(num: usize) -> Self {
         KnnResultSet {
-            data: BinaryHeap::with_capacity(128),
+            data: BinaryHeap::with_capacity(num.min(128)),
             num,
+            radius: None,
         }
     }
 
+    /// Keeps at most `num` values within `radius`. The radius bounds the
+    /// search from the start, so the branches beyond it are pruned even
+    /// before `num` values are found.
+    pub fn with_radius(num: usize, radius: K) -> Self {
+        KnnResultSet {
+            data: BinaryHeap::with_capacity(num.min(128)),
+            num,
+            radius: Some(radius),
+        }
+    }
+
+    fn is_filled(&self) -> bool {
+        self.data.len() >= self.num
+    }
+
     pub fn clear(&mut self) {
         self.data.clear();
     }
@@ -95,11 +131,13 @@ impl<K: PartialOrd, V: PartialOrd> ResultSet for KnnResultSet<K, V> {
     type Value = V;
 
     fn push(&mut self, key: K, value: V) {
-        if self.max_key() <= Some(&key) {
+        if self.radius.as_ref().map_or(false, |radius| *radius <= key) {
             return;
         }
-
-        if self.is_full() {
+        if self.is_filled() {
+            if self.data.peek().map(|node| &node.key) <= Some(&key) {
+                return;
+            }
             self.data.pop();
         }
 
@@ -107,11 +145,35 @@ impl<K: PartialOrd, V: PartialOrd> ResultSet for KnnResultSet<K, V> {
     }
 
     fn is_full(&self) -> bool {
-        self.data.len() >= self.num
+        self.radius.is_some() || self.is_filled()
     }
 
     fn max_key(&self) -> Option<&K> {
-        self.data.peek().map(|node| &node.key)
+        match &self.radius {
+            Some(radius) if !self.is_filled() => Some(radius),
+            _ => self.data.peek().map(|node| &node.key),
+        }
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        let additional = additional.min(self.num.saturating_sub(self.data.len()));
+        self.data.reserve(additional);
+    }
+
+    fn merge(&mut self, other: Self) {
+        for node in other.data {
+            self.push(node.key, node.value);
+        }
+    }
+
+    fn drain_sorted_into(&mut self, output: &mut Vec<(V, K)>) {
+        // The heap pops the largest keys first.
+        let start = output.len();
+        output.reserve(self.data.len());
+        while let Some(node) = self.data.pop() {
+            output.push((node.value, node.key));
+        }
+        output[start..].reverse();
     }
 }
 
@@ -177,6 +239,121 @@ impl<K: PartialOrd, V: PartialOrd> ResultSet for RadiusResultSet<K, V> {
     fn max_key(&self) -> Option<&K> {
         Some(&self.radius)
     }
+
+    fn reserve(&mut self, additional: usize) {
+        self.data.reserve(additional);
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.data.reserve(other.data.len());
+        for node in other.data {
+            self.push(node.key, node.value);
+        }
+    }
+
+    fn drain_sorted_into(&mut self, output: &mut Vec<(V, K)>) {
+        self.data.sort_unstable();
+        output.extend(self.data.drain(..).map(|node| (node.value, node.key)));
+    }
+}
+
+/// Counts the values within `radius` without storing them, and stops the
+/// search once `max` values are counted.
+pub struct CountResultSet<K, V> {
+    count: usize,
+    max: usize,
+    radius: K,
+    _marker: PhantomData<V>,
+}
+
+impl<K: PartialOrd, V> CountResultSet<K, V> {
+    pub fn new(radius: K, max: usize) -> Self {
+        CountResultSet {
+            count: 0,
+            max,
+            radius,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.count = 0;
+    }
+
+    /// The number of values counted, which is at most `max`.
+    pub fn count(&self) -> usize {
+        self.count.min(self.max)
+    }
+}
+
+impl<K: PartialOrd, V> ResultSet for CountResultSet<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn push(&mut self, key: K, _: V) {
+        if key < self.radius {
+            self.count += 1;
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        true
+    }
+
+    fn max_key(&self) -> Option<&K> {
+        // No key is smaller than `None`, which prunes all the other branches.
+        (self.count < self.max).then_some(&self.radius)
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.count += other.count;
+    }
+
+    /// Nothing is stored, so `output` is left untouched.
+    fn drain_sorted_into(&mut self, _: &mut Vec<(V, K)>) {}
+}
+
+/// Passes the values within `radius` to `visitor` with their keys instead of
+/// storing them.
+pub struct VisitResultSet<K, V, F> {
+    radius: K,
+    visitor: F,
+    _marker: PhantomData<V>,
+}
+
+impl<K: PartialOrd, V, F: FnMut(K, V)> VisitResultSet<K, V, F> {
+    pub fn new(radius: K, visitor: F) -> Self {
+        VisitResultSet {
+            radius,
+            visitor,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<K: PartialOrd, V, F: FnMut(K, V)> ResultSet for VisitResultSet<K, V, F> {
+    type Key = K;
+    type Value = V;
+
+    fn push(&mut self, key: K, value: V) {
+        if key < self.radius {
+            (self.visitor)(key, value);
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        true
+    }
+
+    fn max_key(&self) -> Option<&K> {
+        Some(&self.radius)
+    }
+
+    /// The values of `other` are already visited by its own visitor.
+    fn merge(&mut self, _: Self) {}
+
+    /// Nothing is stored, so `output` is left untouched.
+    fn drain_sorted_into(&mut self, _: &mut Vec<(V, K)>) {}
 }
 
 #[cfg(test)]
@@ -195,4 +372,69 @@ mod tests {
         };
         assert!(node1.cmp(&node2) == std::cmp::Ordering::Less);
     }
+
+    #[test]
+    fn test_knn_result_set_with_radius() {
+        let mut result = KnnResultSet::<f32, usize>::with_radius(2, 1.);
+        assert!(result.is_full());
+        assert_eq!(result.max_key(), Some(&1.));
+        result.push(1.5, 0);
+        result.push(0.8, 1);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result.max_key(), Some(&1.));
+        result.push(0.2, 2);
+        result.push(0.5, 3);
+        assert_eq!(result.max_key(), Some(&0.5));
+        let mut values = result.into_iter().map(|(_, v)| v).collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, [2, 3]);
+    }
+
+    #[test]
+    fn test_merge_and_drain_sorted() {
+        let mut result = KnnResultSet::<f32, usize>::new(3);
+        result.push(0.4, 0);
+        result.push(0.9, 1);
+        let mut other = KnnResultSet::new(3);
+        other.push(0.1, 2);
+        other.push(0.6, 3);
+        result.merge(other);
+
+        let mut output = vec![(9, 0.)];
+        result.drain_sorted_into(&mut output);
+        assert_eq!(output, [(9, 0.), (2, 0.1), (0, 0.4), (3, 0.6)]);
+        assert!(result.is_empty());
+
+        let mut result = RadiusResultSet::<f32, usize>::new(1.);
+        result.push(0.7, 0);
+        result.push(0.2, 1);
+        result.push(1.2, 2);
+        output.clear();
+        result.drain_sorted_into(&mut output);
+        assert_eq!(output, [(1, 0.2), (0, 0.7)]);
+    }
+
+    #[test]
+    fn test_count_result_set() {
+        let mut result = CountResultSet::<f32, usize>::new(1., 2);
+        result.push(0.5, 0);
+        assert_eq!(result.max_key(), Some(&1.));
+        result.push(1., 1);
+        result.push(0., 2);
+        assert_eq!(result.count(), 2);
+        assert_eq!(result.max_key(), None);
+        result.push(0., 3);
+        assert_eq!(result.count(), 2);
+    }
+
+    #[test]
+    fn test_visit_result_set() {
+        let mut visited = Vec::new();
+        let mut result =
+            VisitResultSet::<f32, usize, _>::new(1., |key, value| visited.push((value, key)));
+        result.push(0.5, 0);
+        result.push(1., 1);
+        result.push(0., 2);
+        assert_eq!(visited, [(0, 0.5), (2, 0.)]);
+    }
 }
@@ -0,0 +1,122 @@
+use std::{cell::RefCell, ptr::NonNull};
+
+use bitvec::vec::BitVec;
+
+/// The buffers used by [`KdTree::search_typed`](crate::KdTree::search_typed)
+/// to traverse the tree, i.e. the stack of the branches left to visit and the
+/// bits of the visited points, which can be reused across queries to avoid
+/// allocating them every time.
+///
+/// The searches without an explicit scratch use a thread-local one.
+#[derive(Debug, Default)]
+pub struct SearchScratch {
+    pub(crate) other_branches: Vec<NonNull<u8>>,
+    pub(crate) checker: BitVec,
+}
+
+// The pointers are only alive during a search.
+unsafe impl Send for SearchScratch {}
+unsafe impl Sync for SearchScratch {}
+
+impl SearchScratch {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.other_branches.clear();
+        self.checker.clear();
+    }
+
+    /// Runs `f` with the thread-local scratch, or a new one if it's already
+    /// in use, e.g. by a search in the visitor of another search.
+    pub(crate) fn with_local<R>(f: impl FnOnce(&mut SearchScratch) -> R) -> R {
+        thread_local! {
+            static SCRATCH: RefCell<SearchScratch> = RefCell::new(SearchScratch::new());
+        }
+        SCRATCH.with(|scratch| match scratch.try_borrow_mut() {
+            Ok(mut scratch) => f(&mut scratch),
+            Err(_) => f(&mut SearchScratch::new()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::search::SearchType;
+
+    use super::*;
+    use crate::{CoordsKdTree, KnnResultSet, ResultSet};
+
+    fn grid() -> Vec<Vector4<f32>> {
+        { (0..64).map(|i| i as f32) }
+            .map(|i| Vector4::new(i % 4., (i / 4.).floor() % 4., (i / 16.).floor(), 1.))
+            .collect()
+    }
+
+    #[test]
+    fn test_nested_search() {
+        let coords = grid();
+        let tree = CoordsKdTree::new(&coords);
+        let knn = |pivot: &Vector4<f32>| {
+            let mut result = Vec::new();
+            tree.search_exact(pivot, SearchType::Knn(7), &mut result);
+            result
+        };
+
+        // The radius search visits the neighbors during the traversal with the
+        // thread-local scratch, so the nested ones must use new scratches.
+        let pivot = Vector4::new(1.5, 1.5, 1.5, 1.);
+        let (mut visited, mut nested) = (Vec::new(), Vec::new());
+        tree.search_with(&pivot, SearchType::Radius(1.), &mut |index, _| {
+            let mut result = Vec::new();
+            tree.search(&coords[index], SearchType::Knn(7), &mut result);
+            visited.push(index);
+            nested.push(result);
+        });
+        assert_eq!(visited.len(), 8);
+        for (index, result) in visited.into_iter().zip(nested) {
+            assert_eq!(result, knn(&coords[index]));
+        }
+
+        // The thread-local scratch is released afterwards.
+        let mut result = Vec::new();
+        tree.search(&pivot, SearchType::Knn(7), &mut result);
+        assert_eq!(result, knn(&pivot));
+    }
+
+    #[test]
+    fn test_reused_scratch() {
+        let coords = grid();
+        let tree = CoordsKdTree::new(&coords);
+
+        let mut scratch = SearchScratch::new();
+        let pivots = [
+            Vector4::new(0.2, 3.1, 1.8, 1.),
+            Vector4::new(2.5, 0.4, 3.3, 1.),
+            Vector4::new(0.2, 3.1, 1.8, 1.),
+            Vector4::new(-1., -1., -1., 1.),
+        ];
+        let mut results = Vec::new();
+        for pivot in &pivots {
+            let search = |scratch: &mut SearchScratch| {
+                let mut rs = KnnResultSet::new(5);
+                tree.search_typed_with(pivot, &mut rs, scratch);
+                let mut result = Vec::new();
+                rs.drain_sorted_into(&mut result);
+                result
+            };
+            let reused = search(&mut scratch);
+            assert_eq!(reused, search(&mut SearchScratch::new()));
+
+            let mut exact = Vec::new();
+            tree.search_exact(pivot, SearchType::Knn(5), &mut exact);
+            assert_eq!(reused, exact);
+
+            results.push(reused);
+        }
+        // The same pivot again after the scratch is used by the others.
+        assert_eq!(results[0], results[2]);
+    }
+}
@@ -10,7 +10,7 @@ use num::ToPrimitive;
 use pcc_common::{point::Point, point_cloud::PointCloud};
 use petgraph::prelude::UnGraph;
 
-use crate::{node::Node, point_cloud::coords_to_key, CreateOptions, OcTreePc};
+use crate::{node::Node, point_cloud::coords_to_key, CreateError, CreateOptions, OcTreePc};
 
 #[derive(Debug, Default)]
 struct Leaf<'a, L> {
@@ -37,15 +37,18 @@ impl<'a, L: Default, T: RealField + ToPrimitive> OcTreePcAdjacency<'a, L, T> {
     pub fn from_point_cloud<P: Point<Data = T>>(
         point_cloud: &'a PointCloud<P>,
         options: CreateOptions<T>,
-    ) -> Self {
+    ) -> Result<Self, CreateError<T>> {
         let mut tree = OcTreePcAdjacency {
             inner: OcTreePc::new(point_cloud, options, |tree, mul, add| {
                 for point in point_cloud.iter() {
-                    let key = coords_to_key(point.coords(), mul.clone(), add);
+                    let key = match coords_to_key(point.coords(), mul, add, tree.max_key()) {
+                        Some(key) => key,
+                        None => continue,
+                    };
                     let leaf: &mut Leaf<_> = tree.get_or_insert_with(&key, Default::default);
                     leaf.num += 1;
                 }
-            }),
+            })?,
         };
 
         // SAFETY: The shadow tree calls `neighbor` function, only affecting `leaf`
@@ -60,7 +63,7 @@ impl<'a, L: Default, T: RealField + ToPrimitive> OcTreePcAdjacency<'a, L, T> {
             mem::forget(neigh_shadow);
         }
 
-        tree
+        Ok(tree)
     }
 }
 
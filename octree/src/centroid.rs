@@ -4,7 +4,7 @@ use nalgebra::{ClosedAdd, ComplexField, RealField, Scalar, Vector4};
 use num::ToPrimitive;
 use pcc_common::{point::Point, point_cloud::PointCloud};
 
-use crate::{node::Node, point_cloud::coords_to_key, CreateOptions, OcTreePc};
+use crate::{node::Node, point_cloud::coords_to_key, CreateError, CreateOptions, OcTreePc};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 struct Leaf<T: Scalar> {
@@ -61,30 +61,37 @@ impl<T: RealField + ToPrimitive> OcTreePcCentroid<T> {
     pub fn from_point_cloud<P: Point<Data = T>>(
         point_cloud: &PointCloud<P>,
         options: CreateOptions<T>,
-    ) -> Self {
-        OcTreePcCentroid {
+    ) -> Result<Self, CreateError<T>> {
+        Ok(OcTreePcCentroid {
             inner: OcTreePc::new(point_cloud, options, |tree, mul, add| {
                 for point in point_cloud.iter() {
-                    let key = coords_to_key(point.coords(), mul.clone(), add);
+                    let key = match coords_to_key(point.coords(), mul, add, tree.max_key()) {
+                        Some(key) => key,
+                        None => continue,
+                    };
                     let leaf = tree.get_or_insert_with(&key, Leaf::default);
                     leaf.sum += point.coords();
                     leaf.count += 1;
                 }
-            }),
-        }
+            })?,
+        })
     }
 }
 
 impl<T: RealField + ToPrimitive + Copy> OcTreePcCentroid<T> {
+    /// # Panics
+    ///
+    /// Panics if `coords` is out of the bound of the tree.
     pub fn add_coords(&mut self, coords: &Vector4<T>) {
-        let key = self.inner.coords_to_key(coords);
+        let key = { self.inner.coords_to_key(coords) }
+            .expect("The coordinates are out of the bound of the tree");
         let leaf = self.inner.get_or_insert_with(&key, Leaf::default);
         leaf.sum += coords;
         leaf.count += 1;
     }
 
     pub fn count_at(&self, coords: &Vector4<T>) -> Option<Vector4<T>> {
-        let key = self.inner.coords_to_key(coords);
+        let key = self.inner.coords_to_key(coords)?;
         self.inner.root().map(|root| {
             let node = root.find(&key, self.inner.depth());
             Self::count_recursive(unsafe { node.as_ref() }).consume()
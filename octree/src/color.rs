@@ -0,0 +1,189 @@
+use std::{iter::Sum, ops::Add};
+
+use nalgebra::{RealField, Scalar, Vector4};
+use num::ToPrimitive;
+use pcc_common::{point::PointRgba, point_cloud::PointCloud};
+
+use crate::{node::Node, point_cloud::coords_to_key, CreateError, CreateOptions, OcTreePc};
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+struct Leaf {
+    /// The sums of the channels in the order of [`PointRgba::rgba_array`].
+    sum: [f32; 4],
+    count: usize,
+}
+
+impl Leaf {
+    fn push(&mut self, rgba: u32) {
+        for (sum, channel) in self.sum.iter_mut().zip(0..4) {
+            *sum += ((rgba >> (channel * 8)) & 0xff) as f32;
+        }
+        self.count += 1;
+    }
+
+    fn consume(self) -> u32 {
+        let num = self.count as f32;
+        { self.sum.iter().enumerate() }.fold(0, |acc, (channel, &sum)| {
+            acc | (((sum / num).round() as u32) << (channel * 8))
+        })
+    }
+}
+
+impl Add for Leaf {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Leaf {
+            sum: [0, 1, 2, 3].map(|i| self.sum[i] + rhs.sum[i]),
+            count: self.count + rhs.count,
+        }
+    }
+}
+
+impl Sum for Leaf {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Default::default(), |acc, elem| acc + elem)
+    }
+}
+
+/// An octree whose leaves average the RGBA colors of their points, so that
+/// the colors of the voxels can be queried without the original point cloud,
+/// e.g. for LOD rendering.
+#[derive(Debug)]
+pub struct OcTreePcColor<T: Scalar> {
+    inner: OcTreePc<Leaf, T>,
+}
+
+impl<T: Scalar + num::Zero> Default for OcTreePcColor<T> {
+    fn default() -> Self {
+        Self {
+            inner: Default::default(),
+        }
+    }
+}
+
+impl<T: RealField + ToPrimitive> OcTreePcColor<T> {
+    pub fn from_point_cloud<P: PointRgba<Data = T>>(
+        point_cloud: &PointCloud<P>,
+        options: CreateOptions<T>,
+    ) -> Result<Self, CreateError<T>> {
+        Ok(OcTreePcColor {
+            inner: OcTreePc::new(point_cloud, options, |tree, mul, add| {
+                for point in point_cloud.iter() {
+                    let key = match coords_to_key(point.coords(), mul, add, tree.max_key()) {
+                        Some(key) => key,
+                        None => continue,
+                    };
+                    let leaf = tree.get_or_insert_with(&key, Leaf::default);
+                    leaf.push(point.rgba());
+                }
+            })?,
+        })
+    }
+}
+
+impl<T: RealField + ToPrimitive + Copy> OcTreePcColor<T> {
+    /// # Panics
+    ///
+    /// Panics if `coords` is out of the bound of the tree.
+    pub fn add_color(&mut self, coords: &Vector4<T>, rgba: u32) {
+        let key = { self.inner.coords_to_key(coords) }
+            .expect("The coordinates are out of the bound of the tree");
+        let leaf = self.inner.get_or_insert_with(&key, Leaf::default);
+        leaf.push(rgba);
+    }
+
+    /// The average color of the points in the deepest existing node
+    /// containing `coords`.
+    pub fn color_at(&self, coords: &Vector4<T>) -> Option<u32> {
+        let key = self.inner.coords_to_key(coords)?;
+        self.inner.root().map(|root| {
+            let node = root.find(&key, self.inner.depth());
+            Self::color_recursive(unsafe { node.as_ref() }).consume()
+        })
+    }
+
+    /// The average color of all the points.
+    pub fn color(&self) -> Option<u32> {
+        { self.inner.root() }.map(|root| Self::color_recursive(root).consume())
+    }
+
+    fn color_recursive(node: &Node<(), Leaf>) -> Leaf {
+        match node {
+            Node::Leaf { content } => *content,
+            Node::Branch { children, .. } => children
+                .iter()
+                .flatten()
+                .map(|child| Self::color_recursive(unsafe { child.as_ref() }))
+                .sum(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pcc_common::point::{Point, Point3Rgba};
+
+    use super::*;
+
+    fn average(colors: &[u32]) -> u32 {
+        (0..4).fold(0, |acc, channel| {
+            let sum = { colors.iter() }
+                .map(|rgba| (rgba >> (channel * 8)) & 0xff)
+                .sum::<u32>();
+            acc | (((sum as f32 / colors.len() as f32).round() as u32) << (channel * 8))
+        })
+    }
+
+    #[test]
+    fn test_color() {
+        // The voxels of the side 1 are aligned to the minimum of the points.
+        let leaves = [
+            (
+                Vector4::new(0.2, 0.2, 0.2, 1.),
+                [0xff00_0010, 0x8000_0020, 0x4010_2031],
+            ),
+            (
+                Vector4::new(2.3, 0.4, 1.4, 1.),
+                [0x0102_0304, 0xfefd_fcfb, 0x0000_0000],
+            ),
+            (
+                Vector4::new(3.3, 3.3, 3.3, 1.),
+                [0x1234_5678, 0x1234_5678, 0x1234_5678],
+            ),
+        ];
+        let storage = { leaves.iter() }
+            .flat_map(|(coords, colors)| {
+                { colors.iter().enumerate() }.map(move |(i, &rgba)| {
+                    let offset = Vector4::new(0.1, 0.2, 0.3, 0.) * i as f32;
+                    Point3Rgba::default()
+                        .with_coords(coords + offset)
+                        .with_rgba(rgba)
+                })
+            })
+            .collect::<Vec<_>>();
+        let point_cloud = PointCloud::from_vec(storage, 1);
+
+        let mut tree =
+            OcTreePcColor::from_point_cloud(&point_cloud, CreateOptions::new(1.)).unwrap();
+        for (coords, colors) in &leaves {
+            assert_eq!(tree.color_at(coords), Some(average(colors)));
+        }
+        let all = point_cloud
+            .iter()
+            .map(|point| point.rgba())
+            .collect::<Vec<_>>();
+        assert_eq!(tree.color(), Some(average(&all)));
+
+        tree.add_color(&leaves[2].0, 0);
+        let mut colors = leaves[2].1.to_vec();
+        colors.push(0);
+        assert_eq!(tree.color_at(&leaves[2].0), Some(average(&colors)));
+
+        // An empty leaf adds nothing.
+        let mut leaf = Leaf::default();
+        leaf.push(0x1020_3040);
+        assert_eq!((leaf + Leaf::default()).consume(), 0x1020_3040);
+        assert_eq!(OcTreePcColor::<f32>::default().color(), None);
+    }
+}
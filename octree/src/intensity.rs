@@ -0,0 +1,251 @@
+use std::{iter::Sum, ops::Add};
+
+use nalgebra::{RealField, Scalar, Vector4};
+use num::ToPrimitive;
+use pcc_common::{point::PointIntensity, point_cloud::PointCloud};
+
+use crate::{node::Node, point_cloud::coords_to_key, CreateError, CreateOptions, OcTreePc};
+
+/// The statistics of the intensities of the points in a node.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct IntensityStats<T: Scalar> {
+    pub count: usize,
+    pub mean: T,
+    /// The population variance.
+    pub variance: T,
+    pub min: T,
+    pub max: T,
+}
+
+/// Accumulates the statistics with Welford's algorithm, merging the leaves
+/// with the parallel variant.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Leaf<T: Scalar> {
+    count: usize,
+    mean: T,
+    m2: T,
+    min: T,
+    max: T,
+}
+
+impl<T: RealField> Leaf<T> {
+    fn push(&mut self, intensity: T) {
+        *self = self.clone()
+            + Leaf {
+                count: 1,
+                mean: intensity.clone(),
+                m2: T::zero(),
+                min: intensity.clone(),
+                max: intensity,
+            };
+    }
+
+    fn consume(self) -> IntensityStats<T> {
+        let variance = if self.count > 0 {
+            self.m2 / T::from_usize(self.count).unwrap()
+        } else {
+            T::zero()
+        };
+        IntensityStats {
+            count: self.count,
+            mean: self.mean,
+            variance,
+            min: self.min,
+            max: self.max,
+        }
+    }
+}
+
+impl<T: RealField> Add for Leaf<T> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        if self.count == 0 {
+            return rhs;
+        }
+        if rhs.count == 0 {
+            return self;
+        }
+
+        let count = self.count + rhs.count;
+        let (na, nb) = (
+            T::from_usize(self.count).unwrap(),
+            T::from_usize(rhs.count).unwrap(),
+        );
+        let n = T::from_usize(count).unwrap();
+        let delta = rhs.mean.clone() - self.mean.clone();
+        Leaf {
+            count,
+            mean: self.mean + delta.clone() * nb.clone() / n.clone(),
+            m2: self.m2 + rhs.m2 + delta.clone() * delta * na * nb / n,
+            min: self.min.min(rhs.min),
+            max: self.max.max(rhs.max),
+        }
+    }
+}
+
+impl<T: RealField> Sum for Leaf<T> {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Default::default(), |acc, elem| acc + elem)
+    }
+}
+
+impl<T: Scalar + num::Zero> Default for Leaf<T> {
+    fn default() -> Self {
+        Leaf {
+            count: 0,
+            mean: T::zero(),
+            m2: T::zero(),
+            min: T::zero(),
+            max: T::zero(),
+        }
+    }
+}
+
+/// An octree whose leaves keep the statistics of the intensities of their
+/// points, so that the intensities of the voxels can be queried without the
+/// original point cloud.
+#[derive(Debug)]
+pub struct OcTreePcIntensityStats<T: Scalar> {
+    inner: OcTreePc<Leaf<T>, T>,
+}
+
+impl<T: Scalar + num::Zero> Default for OcTreePcIntensityStats<T> {
+    fn default() -> Self {
+        Self {
+            inner: Default::default(),
+        }
+    }
+}
+
+impl<T: RealField + ToPrimitive> OcTreePcIntensityStats<T> {
+    pub fn from_point_cloud<P: PointIntensity<Data = T>>(
+        point_cloud: &PointCloud<P>,
+        options: CreateOptions<T>,
+    ) -> Result<Self, CreateError<T>> {
+        Ok(OcTreePcIntensityStats {
+            inner: OcTreePc::new(point_cloud, options, |tree, mul, add| {
+                for point in point_cloud.iter() {
+                    let key = match coords_to_key(point.coords(), mul, add, tree.max_key()) {
+                        Some(key) => key,
+                        None => continue,
+                    };
+                    let leaf = tree.get_or_insert_with(&key, Leaf::default);
+                    leaf.push(point.intensity());
+                }
+            })?,
+        })
+    }
+}
+
+impl<T: RealField + ToPrimitive + Copy> OcTreePcIntensityStats<T> {
+    /// # Panics
+    ///
+    /// Panics if `coords` is out of the bound of the tree.
+    pub fn add_intensity(&mut self, coords: &Vector4<T>, intensity: T) {
+        let key = { self.inner.coords_to_key(coords) }
+            .expect("The coordinates are out of the bound of the tree");
+        let leaf = self.inner.get_or_insert_with(&key, Leaf::default);
+        leaf.push(intensity);
+    }
+
+    /// The statistics of the points in the deepest existing node containing
+    /// `coords`.
+    pub fn stats_at(&self, coords: &Vector4<T>) -> Option<IntensityStats<T>> {
+        let key = self.inner.coords_to_key(coords)?;
+        self.inner.root().map(|root| {
+            let node = root.find(&key, self.inner.depth());
+            Self::stats_recursive(unsafe { node.as_ref() }).consume()
+        })
+    }
+
+    /// The statistics of all the points.
+    pub fn stats(&self) -> Option<IntensityStats<T>> {
+        { self.inner.root() }.map(|root| Self::stats_recursive(root).consume())
+    }
+
+    fn stats_recursive(node: &Node<(), Leaf<T>>) -> Leaf<T> {
+        match node {
+            Node::Leaf { content } => *content,
+            Node::Branch { children, .. } => children
+                .iter()
+                .flatten()
+                .map(|child| Self::stats_recursive(unsafe { child.as_ref() }))
+                .sum(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pcc_common::point::{Point, Point3IN};
+
+    use super::*;
+
+    /// The mean and the population variance computed directly.
+    fn stats(intensities: &[f32]) -> (f32, f32) {
+        let num = intensities.len() as f32;
+        let mean = intensities.iter().sum::<f32>() / num;
+        let variance = { intensities.iter() }
+            .map(|x| (x - mean) * (x - mean))
+            .sum::<f32>()
+            / num;
+        (mean, variance)
+    }
+
+    fn assert_stats(stats: IntensityStats<f32>, intensities: &[f32]) {
+        let (mean, variance) = self::stats(intensities);
+        assert_eq!(stats.count, intensities.len());
+        assert!((stats.mean - mean).abs() < 1e-4);
+        assert!((stats.variance - variance).abs() < 1e-3);
+        let min = intensities.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = intensities
+            .iter()
+            .copied()
+            .fold(f32::NEG_INFINITY, f32::max);
+        assert_eq!((stats.min, stats.max), (min, max));
+    }
+
+    #[test]
+    fn test_intensity_stats() {
+        // The voxels of the side 1 are aligned to the minimum of the points.
+        let leaves = [
+            (Vector4::new(0.2, 0.2, 0.2, 1.), vec![10., 12., 17., 9.]),
+            (Vector4::new(2.3, 0.4, 1.4, 1.), vec![100.]),
+            (Vector4::new(3.3, 3.3, 3.3, 1.), vec![55., 40., 71.]),
+        ];
+        let storage = { leaves.iter() }
+            .flat_map(|(coords, intensities)| {
+                { intensities.iter().enumerate() }.map(move |(i, &intensity)| {
+                    let offset = Vector4::new(0.1, 0.1, 0.2, 0.) * i as f32;
+                    Point3IN::default()
+                        .with_coords(coords + offset)
+                        .with_intensity(intensity)
+                })
+            })
+            .collect::<Vec<_>>();
+        let point_cloud = PointCloud::from_vec(storage, 1);
+
+        let tree =
+            OcTreePcIntensityStats::from_point_cloud(&point_cloud, CreateOptions::new(1.)).unwrap();
+        for (coords, intensities) in &leaves {
+            assert_stats(tree.stats_at(coords).unwrap(), intensities);
+        }
+        let all = point_cloud
+            .iter()
+            .map(|point| point.intensity())
+            .collect::<Vec<_>>();
+        assert_stats(tree.stats().unwrap(), &all);
+
+        // Merging with an empty leaf, in either order, changes nothing.
+        let mut leaf = Leaf::default();
+        for intensity in [3., 5., 10.] {
+            leaf.push(intensity);
+        }
+        assert_eq!(Leaf::default() + leaf, leaf);
+        assert_eq!(leaf + Leaf::default(), leaf);
+        assert_stats(leaf.consume(), &[3., 5., 10.]);
+        let empty = Leaf::<f32>::default().consume();
+        assert_eq!((empty.count, empty.mean, empty.variance), (0, 0., 0.));
+    }
+}
@@ -0,0 +1,242 @@
+use nalgebra::{RealField, Vector4};
+use num::ToPrimitive;
+use pcc_common::{
+    point::Point,
+    point_cloud::{AsPointCloud, PointCloud},
+};
+
+use crate::point_cloud::{coords_to_key, CreateError, CreateOptions, OcTreePc};
+
+/// The ID of the point cloud and the index of the point in it.
+type Item = (usize, usize);
+
+/// An octree over multiple point clouds without merging them, whose leaves
+/// keep the points tagged by the IDs of their point clouds, i.e. their
+/// indices in [`OcTreePcJoint::point_clouds`].
+///
+/// It answers the neighbor queries across the point clouds, e.g. how much a
+/// source point cloud overlaps a target one, or which points changed between
+/// two scans. The queries scan the leaf voxels overlapping the search balls,
+/// so they're the fastest with radii around the resolution.
+#[derive(Debug)]
+pub struct OcTreePcJoint<'a, P: Point> {
+    inner: OcTreePc<Vec<Item>, P::Data>,
+    point_clouds: Vec<&'a PointCloud<P>>,
+}
+
+impl<'a, T: RealField + ToPrimitive, P: Point<Data = T>> OcTreePcJoint<'a, P> {
+    /// Builds the tree over `point_clouds`, bounded by the union of their
+    /// finite bounds if no bound is given in `options`.
+    pub fn new(
+        point_clouds: Vec<&'a PointCloud<P>>,
+        options: CreateOptions<P::Data>,
+    ) -> Result<Self, CreateError<P::Data>> {
+        let bound = options.bound.clone().or_else(|| {
+            { point_clouds.iter().filter_map(|pc| pc.finite_bound()) }
+                .reduce(|[min1, max1], [min2, max2]| [min1.inf(&min2), max1.sup(&max2)])
+        });
+
+        let inner = OcTreePc::<Vec<Item>, _>::with_bound(bound, options, |tree, mul, add| {
+            for (id, point_cloud) in point_clouds.iter().enumerate() {
+                for (index, point) in point_cloud.iter().enumerate() {
+                    let key = match coords_to_key(point.coords(), mul, add, tree.max_key()) {
+                        Some(key) => key,
+                        None => continue,
+                    };
+                    tree.get_or_insert_with(&key, Vec::new).push((id, index));
+                }
+            }
+        })?;
+
+        Ok(OcTreePcJoint {
+            inner,
+            point_clouds,
+        })
+    }
+
+    #[inline]
+    pub fn point_clouds(&self) -> &[&'a PointCloud<P>] {
+        &self.point_clouds
+    }
+
+    /// The points of all the point clouds in the leaf voxel containing
+    /// `pivot`, as pairs of the IDs of the point clouds and the indices.
+    pub fn voxel_search(&self, pivot: &Vector4<P::Data>) -> &[(usize, usize)] {
+        match self.inner.coords_to_key(pivot) {
+            Some(key) => self.inner.get(&key).map_or(&[], |items| &items[..]),
+            None => &[],
+        }
+    }
+
+    /// The key of the leaf voxel nearest to `coords`, which is clamped into
+    /// the bound of the tree.
+    fn clamped_key(&self, coords: &Vector4<P::Data>) -> Option<[usize; 3]> {
+        let (min, max) = &self.inner.bound;
+        let coords = coords.sup(min).inf(max);
+        coords_to_key(
+            &coords,
+            &self.inner.mul,
+            &self.inner.add,
+            self.inner.max_key(),
+        )
+    }
+
+    fn visit_range(
+        &self,
+        [min, max]: [[usize; 3]; 2],
+        cloud: usize,
+        mut visitor: impl FnMut(usize),
+    ) {
+        for x in min[0]..=max[0] {
+            for y in min[1]..=max[1] {
+                for z in min[2]..=max[2] {
+                    if let Some(items) = self.inner.get(&[x, y, z]) {
+                        { items.iter() }
+                            .filter(|&&(id, _)| id == cloud)
+                            .for_each(|&(_, index)| visitor(index));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Visits the points of the point cloud `cloud` within `radius` from
+    /// `pivot` with their distances.
+    pub fn radius_search_with(
+        &self,
+        cloud: usize,
+        pivot: &Vector4<P::Data>,
+        radius: P::Data,
+        mut visitor: impl FnMut(usize, P::Data),
+    ) {
+        let extent = Vector4::repeat(radius.clone());
+        let min = self.clamped_key(&(pivot - &extent));
+        let max = self.clamped_key(&(pivot + &extent));
+        let range = match (min, max) {
+            (Some(min), Some(max)) => [min, max],
+            _ => return,
+        };
+
+        let point_cloud = self.point_clouds[cloud];
+        self.visit_range(range, cloud, |index| {
+            let distance = (point_cloud[index].coords() - pivot).xyz().norm();
+            if distance <= radius {
+                visitor(index, distance);
+            }
+        });
+    }
+
+    pub fn radius_search(
+        &self,
+        cloud: usize,
+        pivot: &Vector4<P::Data>,
+        radius: P::Data,
+        result: &mut Vec<(usize, P::Data)>,
+    ) {
+        result.clear();
+        self.radius_search_with(cloud, pivot, radius, |index, distance| {
+            result.push((index, distance))
+        });
+    }
+
+    /// The nearest point of the point cloud `cloud` to `pivot` and its
+    /// distance.
+    pub fn nearest(&self, cloud: usize, pivot: &Vector4<P::Data>) -> Option<(usize, P::Data)> {
+        let center = self.clamped_key(pivot)?;
+        let max_key = self.inner.max_key();
+        let max_ring = { center.iter() }
+            .map(|&x| x.max(max_key - x))
+            .max()
+            .unwrap();
+        let min_extent = self.inner.mul.min();
+        let point_cloud = self.point_clouds[cloud];
+
+        let mut nearest: Option<(usize, P::Data)> = None;
+        for ring in 0..=max_ring {
+            let min = center.map(|x| x.saturating_sub(ring));
+            let max = center.map(|x| (x + ring).min(max_key));
+            // Only the voxels on the surface of the cube of the ring are new.
+            for x in min[0]..=max[0] {
+                for y in min[1]..=max[1] {
+                    for z in min[2]..=max[2] {
+                        let key = [x, y, z];
+                        let chebyshev = (0..3).map(|i| key[i].abs_diff(center[i])).max();
+                        if chebyshev != Some(ring) {
+                            continue;
+                        }
+                        self.visit_range([key, key], cloud, |index| {
+                            let distance = (point_cloud[index].coords() - pivot).xyz().norm();
+                            if nearest.as_ref().map_or(true, |(_, d)| &distance < d) {
+                                nearest = Some((index, distance));
+                            }
+                        });
+                    }
+                }
+            }
+
+            // The points in the farther rings are at least this far away.
+            let covered = min_extent.clone() * T::from_usize(ring).unwrap();
+            if nearest.as_ref().map_or(false, |(_, d)| *d <= covered) {
+                break;
+            }
+        }
+        nearest
+    }
+
+    /// The indices of the finite points of the point cloud `from` without any
+    /// point of the point cloud `to` within `radius`, e.g. the points added
+    /// or moved in `from` since the scan `to`.
+    pub fn changes(&self, from: usize, to: usize, radius: P::Data) -> Vec<usize> {
+        { self.point_clouds[from].iter().enumerate() }
+            .filter(|(_, point)| point.is_finite())
+            .filter(|(_, point)| {
+                let mut found = false;
+                self.radius_search_with(to, point.coords(), radius.clone(), |_, _| found = true);
+                !found
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// The ratio of the finite points of the point cloud `from` with any point
+    /// of the point cloud `to` within `radius`, or zero if `from` has no
+    /// finite points.
+    pub fn overlap(&self, from: usize, to: usize, radius: P::Data) -> P::Data {
+        let finite = { self.point_clouds[from].iter() }
+            .filter(|point| point.is_finite())
+            .count();
+        if finite == 0 {
+            return T::zero();
+        }
+        let changes = self.changes(from, to, radius).len();
+        T::from_usize(finite - changes).unwrap() / T::from_usize(finite).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pcc_common::point::Point3;
+
+    use super::*;
+
+    #[test]
+    fn test_joint() {
+        let cloud = |offset: f32| {
+            let storage = { (0..10).map(|i| i as f32) }
+                .map(|i| Point3::default().with_coords(Vector4::new(i + offset, 0., 0., 1.)))
+                .collect::<Vec<_>>();
+            PointCloud::from_vec(storage, 1)
+        };
+        let source = cloud(0.);
+        let target = cloud(5.05);
+
+        let tree = OcTreePcJoint::new(vec![&source, &target], CreateOptions::new(0.5)).unwrap();
+
+        let (index, distance) = tree.nearest(1, &Vector4::new(0., 0., 0., 1.)).unwrap();
+        assert_eq!(index, 0);
+        assert!((distance - 5.05).abs() < 1e-5);
+
+        assert_eq!(tree.changes(0, 1, 0.1), [0, 1, 2, 3, 4]);
+        assert!((tree.overlap(1, 0, 0.1) - 0.5).abs() < 1e-5);
+    }
+}
@@ -4,8 +4,11 @@
 mod adjacency;
 mod base;
 mod centroid;
+mod color;
 mod count;
+mod intensity;
 mod iter;
+mod joint;
 mod node;
 mod point_cloud;
 mod search;
@@ -14,8 +17,11 @@ pub use self::{
     adjacency::OcTreePcAdjacency,
     base::OcTree,
     centroid::OcTreePcCentroid,
+    color::OcTreePcColor,
     count::OcTreePcCount,
+    intensity::{IntensityStats, OcTreePcIntensityStats},
     iter::{DepthIter, DepthIterMut},
-    point_cloud::{CreateOptions, OcTreePc},
+    joint::OcTreePcJoint,
+    point_cloud::{CreateError, CreateOptions, OcTreePc},
     search::OcTreePcSearch,
 };
@@ -1,9 +1,10 @@
 use std::{
-    array,
+    error::Error,
+    fmt,
     ops::{Deref, DerefMut},
 };
 
-use nalgebra::{convert, ComplexField, RealField, Scalar, Vector4};
+use nalgebra::{convert, ComplexField, RealField, Scalar, Vector3, Vector4};
 use num::ToPrimitive;
 use pcc_common::{
     point::Point,
@@ -15,71 +16,186 @@ use crate::OcTree;
 #[derive(Debug)]
 pub struct OcTreePc<L, T: Scalar> {
     pub(crate) inner: OcTree<L>,
-    pub(crate) mul: T,
+    pub(crate) mul: Vector3<T>,
     pub(crate) add: Vector4<T>,
-    bound: (Vector4<T>, Vector4<T>),
+    pub(crate) bound: (Vector4<T>, Vector4<T>),
 }
 
 impl<L, T: Scalar + num::Zero> Default for OcTreePc<L, T> {
     fn default() -> Self {
         OcTreePc {
             inner: OcTree::new(1),
-            mul: T::zero(),
+            mul: Vector3::zeros(),
             add: Vector4::zeros(),
             bound: (Vector4::zeros(), Vector4::zeros()),
         }
     }
 }
 
-pub struct CreateOptions<T> {
-    pub resolution: T,
+/// The options to create an [`OcTreePc`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateOptions<T: Scalar> {
+    /// The extents of the leaf voxels along the axes, which are the same for
+    /// cubic voxels.
+    pub resolution: Vector3<T>,
+    /// The bounding box of the tree, or the finite bound of the point cloud
+    /// if `None`. The points outside it are left out.
     pub bound: Option<[Vector4<T>; 2]>,
+    /// The maximum depth of the tree, which the number of leaf voxels along
+    /// every axis must fit in.
+    pub max_depth: usize,
 }
 
+impl<T: Scalar> CreateOptions<T> {
+    pub const DEFAULT_MAX_DEPTH: usize = 21;
+
+    /// Cubic leaf voxels with the side `resolution`.
+    pub fn new(resolution: T) -> Self {
+        CreateOptions {
+            resolution: Vector3::repeat(resolution),
+            bound: None,
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// Leaf voxels with different `extents` along the axes.
+    pub fn with_extents(mut self, extents: Vector3<T>) -> Self {
+        self.resolution = extents;
+        self
+    }
+
+    pub fn with_bound(mut self, min: Vector4<T>, max: Vector4<T>) -> Self {
+        self.bound = Some([min, max]);
+        self
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CreateError<T: Scalar> {
+    InvalidResolution(Vector3<T>),
+    InvalidBound([Vector4<T>; 2]),
+    TooDeep {
+        resolution: Vector3<T>,
+        bound: [Vector4<T>; 2],
+        max_depth: usize,
+    },
+}
+
+impl<T: Scalar> fmt::Display for CreateError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CreateError::InvalidResolution(resolution) => {
+                write!(
+                    f,
+                    "octree with resolution {:?}, expected positive finite extents",
+                    resolution.as_slice()
+                )
+            }
+            CreateError::InvalidBound([min, max]) => {
+                write!(
+                    f,
+                    "octree with bound from {:?} to {:?}, expected a finite non-empty box",
+                    min.xyz().as_slice(),
+                    max.xyz().as_slice()
+                )
+            }
+            CreateError::TooDeep {
+                resolution,
+                bound: [min, max],
+                max_depth,
+            } => {
+                write!(
+                    f,
+                    "octree with resolution {:?} over {:?} to {:?}, deeper than {max_depth} levels",
+                    resolution.as_slice(),
+                    min.xyz().as_slice(),
+                    max.xyz().as_slice()
+                )
+            }
+        }
+    }
+}
+
+impl<T: Scalar> Error for CreateError<T> {}
+
 impl<L, T: RealField + ToPrimitive> OcTreePc<L, T> {
+    /// Creates the tree with `options`, where `build` fills the tree given the
+    /// extents of the leaf voxels and the origin of the keys.
+    ///
+    /// The tree is empty if `point_cloud` has no finite points and no bound is
+    /// given.
     pub fn new<F, P: Point<Data = T>>(
         point_cloud: &PointCloud<P>,
         options: CreateOptions<T>,
         build: F,
-    ) -> Self
+    ) -> Result<Self, CreateError<T>>
+    where
+        F: FnOnce(&mut OcTree<L>, &Vector3<T>, &Vector4<T>),
+    {
+        let bound = options.bound.clone().or_else(|| point_cloud.finite_bound());
+        Self::with_bound(bound, options, build)
+    }
+
+    /// Like [`OcTreePc::new`], but with `bound` instead of the one in
+    /// `options`, where `None` results in an empty tree.
+    pub(crate) fn with_bound<F>(
+        bound: Option<[Vector4<T>; 2]>,
+        options: CreateOptions<T>,
+        build: F,
+    ) -> Result<Self, CreateError<T>>
     where
-        F: FnOnce(&mut OcTree<L>, T, &Vector4<T>),
+        F: FnOnce(&mut OcTree<L>, &Vector3<T>, &Vector4<T>),
     {
-        let [min, max] = match options.bound.or_else(|| point_cloud.finite_bound()) {
+        let mul = options.resolution;
+        if !mul.iter().all(|x| x.is_finite() && *x > T::zero()) {
+            return Err(CreateError::InvalidResolution(mul));
+        }
+
+        let [min, max] = match bound {
             Some(bound) => bound,
-            None => return Default::default(),
+            None => return Ok(Default::default()),
         };
+        let valid = { min.xyz().iter().zip(max.xyz().iter()) }
+            .all(|(min, max)| min.is_finite() && max.is_finite() && min <= max);
+        if !valid {
+            return Err(CreateError::InvalidBound([min, max]));
+        }
 
-        let mul = options.resolution;
-        let len = &max - &min;
-
-        let depth = ComplexField::ceil(ComplexField::log2((len / mul.clone()).xyz().max()))
-            .to_usize()
-            .expect("Failed to get the depth of the OC tree");
-
-        let max_value = if depth >= 1 { (1 << depth) - 1 } else { 0 };
-
-        let add = {
-            let center_value = T::from_usize(max_value / 2).unwrap();
-            let center_key = Vector4::from([
-                center_value.clone(),
-                center_value.clone(),
-                center_value,
-                T::one(),
-            ]);
-            let center = (&max + &min) / convert::<_, T>(2.);
-            center - center_key
+        // The depth is the number of bits of the largest key, which must also
+        // fit in `usize` with a bit to spare for the sides of the voxels.
+        let max_depth = options.max_depth.min(usize::BITS as usize - 1);
+        let max_key = { (&max - &min).xyz().component_div(&mul).iter() }
+            .map(|x| x.clone().floor().to_usize())
+            .try_fold(0, |acc, x| Some(acc.max(x?)));
+        let depth = match max_key {
+            Some(max_key) if max_key >> max_depth == 0 => {
+                (usize::BITS - max_key.leading_zeros()).max(1) as usize
+            }
+            _ => {
+                return Err(CreateError::TooDeep {
+                    resolution: mul,
+                    bound: [min, max],
+                    max_depth: options.max_depth,
+                })
+            }
         };
 
+        let add = min.clone();
+
         let mut inner = OcTree::new(depth);
-        build(&mut inner, mul.clone(), &add);
+        build(&mut inner, &mul, &add);
 
-        OcTreePc {
+        Ok(OcTreePc {
             inner,
             mul,
             add,
             bound: (min, max),
-        }
+        })
     }
 }
 
@@ -99,58 +215,100 @@ impl<L, T: Scalar> DerefMut for OcTreePc<L, T> {
 
 pub(crate) fn key_to_coords<T: ComplexField>(
     key: &[usize; 3],
-    mul: T,
+    mul: &Vector3<T>,
     add: &Vector4<T>,
 ) -> Vector4<T> {
-    let key = Vector4::from([
-        T::from_usize(key[0]).unwrap(),
-        T::from_usize(key[1]).unwrap(),
-        T::from_usize(key[2]).unwrap(),
-        T::zero(),
-    ]);
-    let mut result = key * mul + add;
+    let key = Vector3::from(*key).map(|x| T::from_usize(x).unwrap());
+    let mut result = key.component_mul(mul).insert_row(3, T::zero()) + add;
     result.w = T::one();
     result
 }
 
-pub(crate) fn coords_to_key<T: ComplexField + ToPrimitive>(
+/// The key of the leaf voxel containing `coords`, or `None` if it's out of
+/// the keys within `max_key`.
+pub(crate) fn coords_to_key<T: RealField + ToPrimitive>(
     coords: &Vector4<T>,
-    mul: T,
+    mul: &Vector3<T>,
     add: &Vector4<T>,
-) -> [usize; 3] {
-    let key = (coords - add) * mul;
-    let mut iter = key.into_iter().filter_map(|v| v.to_usize());
-    array::from_fn(|_| iter.next().unwrap())
+    max_key: usize,
+) -> Option<[usize; 3]> {
+    let key = (coords - add).xyz().component_div(mul);
+    let mut ret = [0; 3];
+    for (r, x) in ret.iter_mut().zip(key.iter()) {
+        *r = x.clone().floor().to_usize().filter(|&x| x <= max_key)?;
+    }
+    Some(ret)
 }
 
 impl<L, T: ComplexField> OcTreePc<L, T> {
     pub fn key_to_coords(&self, key: &[usize; 3]) -> Vector4<T> {
         assert!(key.iter().all(|&v| v <= self.inner.max_key()));
-        key_to_coords(key, self.mul.clone(), &self.add)
+        key_to_coords(key, &self.mul, &self.add)
     }
 }
 
 impl<L, T: RealField + ToPrimitive> OcTreePc<L, T> {
-    pub fn coords_to_key(&self, coords: &Vector4<T>) -> [usize; 3] {
-        assert!(&self.bound.0 <= coords && coords <= &self.bound.1);
-        coords_to_key(coords, self.mul.clone(), &self.add)
+    /// The key of the leaf voxel containing `coords`, or `None` if it's out
+    /// of the bound of the tree.
+    pub fn coords_to_key(&self, coords: &Vector4<T>) -> Option<[usize; 3]> {
+        if !(self.bound.0.xyz() <= coords.xyz() && coords.xyz() <= self.bound.1.xyz()) {
+            return None;
+        }
+        coords_to_key(coords, &self.mul, &self.add, self.inner.max_key())
     }
 }
 
 impl<L, T: ComplexField> OcTreePc<L, T> {
-    pub fn side(&self, depth: usize) -> T {
-        self.mul.clone() * T::from_usize((self.inner.max_key() + 1) >> depth).unwrap()
+    /// The extents of the voxels at `depth` along the axes.
+    pub fn side(&self, depth: usize) -> Vector3<T> {
+        &self.mul * T::from_usize((self.inner.max_key() + 1) >> depth).unwrap()
     }
 
     pub fn diagonal(&self, depth: usize) -> T {
-        self.side(depth) * T::from_usize(3).unwrap().sqrt()
+        let side = self.side(depth);
+        side.iter()
+            .fold(T::zero(), |acc, x| acc + x.clone() * x.clone())
+            .sqrt()
     }
 
     pub fn center(&self, key: &[usize; 3], depth: usize) -> Vector4<T> {
-        let radius = self.side(depth) / convert(2.);
-        let coords = self.key_to_coords(key);
-        let mut ret = coords.map(|v| v + radius.clone());
-        ret.w = T::one();
-        ret
+        let half = self.side(depth) / convert::<_, T>(2.);
+        self.key_to_coords(key) + half.insert_row(3, T::zero())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pcc_common::point::Point3;
+
+    use super::*;
+
+    #[test]
+    fn test_create_options() {
+        let storage = { [[0., 0., 0.], [3.9, 0.5, 0.2], [1., 0.9, 0.1]].into_iter() }
+            .map(|[x, y, z]| Point3::default().with_coords(Vector4::new(x, y, z, 1.)))
+            .collect::<Vec<_>>();
+        let pc = PointCloud::from_vec(storage, 1);
+
+        let options = CreateOptions::new(1.).with_extents(Vector3::new(1., 0.25, 0.1));
+        let tree = OcTreePc::<(), f32>::new(&pc, options, |_, _, _| {}).unwrap();
+        assert_eq!(tree.depth(), 2);
+        assert_eq!(
+            tree.coords_to_key(&Vector4::new(1., 0.9, 0.1, 1.)),
+            Some([1, 3, 1])
+        );
+        assert_eq!(tree.coords_to_key(&Vector4::new(5., 0., 0., 1.)), None);
+
+        let options = CreateOptions::new(0.).with_bound(Vector4::zeros(), Vector4::repeat(1.));
+        assert!(matches!(
+            OcTreePc::<(), f32>::new(&pc, options, |_, _, _| {}),
+            Err(CreateError::InvalidResolution(_))
+        ));
+
+        let options = CreateOptions::new(1e-3).with_max_depth(8);
+        assert!(matches!(
+            OcTreePc::<(), f32>::new(&pc, options, |_, _, _| {}),
+            Err(CreateError::TooDeep { .. })
+        ));
     }
 }
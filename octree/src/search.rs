@@ -6,7 +6,7 @@ use pcc_common::{point::Point, point_cloud::PointCloud, search::SearchType};
 
 use crate::{
     node::{key_child, Node},
-    point_cloud::{CreateOptions, OcTreePc},
+    point_cloud::{coords_to_key, CreateError, CreateOptions, OcTreePc},
 };
 
 type Item<'a, T> = (usize, &'a Vector4<T>);
@@ -33,8 +33,10 @@ where
         &'b self,
         pivot: &Vector4<P::Data>,
     ) -> &'b [(usize, &'a Vector4<P::Data>)] {
-        let key = self.inner.coords_to_key(pivot);
-        self.inner.get(&key).map_or(&[], Deref::deref)
+        match self.inner.coords_to_key(pivot) {
+            Some(key) => self.inner.get(&key).map_or(&[], Deref::deref),
+            None => &[],
+        }
     }
 }
 
@@ -154,14 +156,21 @@ where
         result_set: &mut Vec<(usize, P::Data)>,
     ) {
         result_set.clear();
+        self.radius_search_with(pivot, radius, &mut |index, distance| {
+            result_set.push((index, distance))
+        });
+    }
+
+    /// Passes the points within `radius` to `visitor` with their distances
+    /// instead of collecting them.
+    pub fn radius_search_with(
+        &self,
+        pivot: &Vector4<P::Data>,
+        radius: P::Data,
+        visitor: &mut dyn FnMut(usize, P::Data),
+    ) {
         if let Some(node) = self.inner.root() {
-            self.radius_search_recursive(
-                &NodeKey { node, key: [0; 3] },
-                pivot,
-                radius,
-                1,
-                result_set,
-            );
+            self.radius_search_recursive(&NodeKey { node, key: [0; 3] }, pivot, radius, 1, visitor);
         }
     }
 
@@ -171,7 +180,7 @@ where
         pivot: &Vector4<P::Data>,
         radius: P::Data,
         depth: usize,
-        result_set: &mut Vec<(usize, P::Data)>,
+        visitor: &mut dyn FnMut(usize, P::Data),
     ) {
         let half_diagonal = self.half_diagonal(depth);
 
@@ -192,18 +201,14 @@ where
             })
         }) {
             match child.node {
-                Node::Branch { .. } => self.radius_search_recursive(
-                    &child,
-                    pivot,
-                    radius.clone(),
-                    depth + 1,
-                    result_set,
-                ),
+                Node::Branch { .. } => {
+                    self.radius_search_recursive(&child, pivot, radius.clone(), depth + 1, visitor)
+                }
                 Node::Leaf { content } => {
                     for &(index, coords) in content {
                         let distance = (coords - pivot).norm();
                         if distance <= radius {
-                            result_set.push((index, distance))
+                            visitor(index, distance)
                         }
                     }
                 }
@@ -216,17 +221,23 @@ impl<'a, P: Point> OcTreePcSearch<'a, P>
 where
     P::Data: RealField + ToPrimitive,
 {
-    pub fn new(point_cloud: &'a PointCloud<P>, options: CreateOptions<P::Data>) -> Self {
-        OcTreePcSearch {
+    pub fn new(
+        point_cloud: &'a PointCloud<P>,
+        options: CreateOptions<P::Data>,
+    ) -> Result<Self, CreateError<P::Data>> {
+        Ok(OcTreePcSearch {
             point_cloud,
             inner: OcTreePc::new(point_cloud, options, |tree, mul, add| {
                 for (index, point) in point_cloud.iter().enumerate() {
-                    let key = crate::point_cloud::coords_to_key(point.coords(), mul.clone(), add);
+                    let key = match coords_to_key(point.coords(), mul, add, tree.max_key()) {
+                        Some(key) => key,
+                        None => continue,
+                    };
                     let vec = tree.get_or_insert_with(&key, Vec::new);
                     vec.push((index, point.coords()));
                 }
-            }),
-        }
+            })?,
+        })
     }
 }
 
@@ -247,6 +258,37 @@ where
         match ty {
             SearchType::Knn(num) => self.knn_search(pivot, num, result),
             SearchType::Radius(radius) => self.radius_search(pivot, radius, result),
+            SearchType::KnnWithin { k, radius } => {
+                self.knn_search(pivot, k, result);
+                result.retain(|(_, distance)| *distance < radius);
+            }
+        }
+    }
+
+    fn search_with(
+        &self,
+        pivot: &Vector4<P::Data>,
+        ty: SearchType<P::Data>,
+        visitor: &mut dyn FnMut(usize, P::Data),
+    ) {
+        match ty {
+            SearchType::Knn(num) => {
+                let mut result = Vec::new();
+                self.knn_search(pivot, num, &mut result);
+                for (index, distance) in result {
+                    visitor(index, distance)
+                }
+            }
+            SearchType::Radius(radius) => self.radius_search_with(pivot, radius, visitor),
+            SearchType::KnnWithin { k, radius } => {
+                let mut result = Vec::new();
+                self.knn_search(pivot, k, &mut result);
+                for (index, distance) in result {
+                    if distance < radius {
+                        visitor(index, distance)
+                    }
+                }
+            }
         }
     }
 }
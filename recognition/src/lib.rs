@@ -0,0 +1,9 @@
+mod pipeline;
+mod tuning;
+mod vocabulary;
+
+pub use self::{
+    pipeline::{Description, Pipeline, PoseEstimate},
+    tuning::{CloudStats, Suggestion},
+    vocabulary::{bow_similarity, Vocabulary},
+};
@@ -0,0 +1,213 @@
+use nalgebra::{convert, DVector, Isometry3, RealField, Vector4};
+use num::ToPrimitive;
+use pcc_common::{
+    feature::{Feature, Keypoints},
+    filter::Filter,
+    point::{Normal3, Point},
+    point_cloud::PointCloud,
+    search::{SearchParam, SearchParamError},
+};
+use pcc_features::{Fpfh, Normal};
+use pcc_filters::UniformSampling;
+use pcc_registration::{match_descriptors, Correspondence, RansacAlignment};
+use pcc_search::KdTree;
+use rand::{rngs::ThreadRng, RngCore};
+
+/// The key points of a point cloud and their descriptors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Description<T: RealField> {
+    /// The indices of the key points in the original point cloud.
+    pub key_points: Vec<usize>,
+    /// The descriptors of the key points, one for each entry in `key_points`.
+    pub descriptors: Vec<DVector<T>>,
+}
+
+/// The estimated pose of the source point cloud in the target point cloud.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoseEstimate<T: RealField> {
+    /// The transformation mapping the source onto the target.
+    pub transform: Isometry3<T>,
+    /// The inlier correspondences, indexed into the original point clouds.
+    pub correspondences: Vec<Correspondence<T>>,
+}
+
+/// A feature-based recognition pipeline, which detects key points, computes
+/// FPFH descriptors on them, matches the descriptors and estimates the pose
+/// with RANSAC.
+#[derive(Debug, Clone)]
+pub struct Pipeline<T: RealField, K = UniformSampling<T>, R: RngCore = ThreadRng> {
+    pub key_point: K,
+    pub normal: Normal<T>,
+    pub normal_search: SearchParam<T>,
+    pub descriptor: Fpfh,
+    pub descriptor_search: SearchParam<T>,
+    pub reciprocal: bool,
+    pub alignment: RansacAlignment<T, R>,
+}
+
+impl<T: RealField> Pipeline<T> {
+    /// Creates a pipeline with the default parameters derived from
+    /// `resolution`, the mean spacing between neighboring points.
+    pub fn new(resolution: T) -> Result<Self, SearchParamError<T>> {
+        let normal_search = SearchParam::radius(resolution.clone() * convert(3.))?;
+        let descriptor_search =
+            SearchParam::radius(resolution.clone() * convert(5.))?.larger_than(&normal_search)?;
+
+        let grid = resolution.clone() * convert(5.);
+        Ok(Pipeline {
+            key_point: UniformSampling::new(Vector4::new(
+                grid.clone(),
+                grid.clone(),
+                grid,
+                T::one(),
+            )),
+            normal: Normal::new(Vector4::new(T::zero(), T::zero(), T::zero(), T::one())),
+            normal_search,
+            descriptor: Fpfh::new([11; 3]),
+            descriptor_search,
+            reciprocal: true,
+            alignment: RansacAlignment::new(1000, resolution * convert(2.), rand::thread_rng()),
+        })
+    }
+}
+
+impl<T: RealField, K, R: RngCore> Pipeline<T, K, R> {
+    /// Replaces the random number generator of the alignment, e.g. with a
+    /// seeded one for reproducible results.
+    pub fn with_rng<R2: RngCore>(self, rng: R2) -> Pipeline<T, K, R2> {
+        Pipeline {
+            key_point: self.key_point,
+            normal: self.normal,
+            normal_search: self.normal_search,
+            descriptor: self.descriptor,
+            descriptor_search: self.descriptor_search,
+            reciprocal: self.reciprocal,
+            alignment: self.alignment.with_rng(rng),
+        }
+    }
+}
+
+impl<T, K, R> Pipeline<T, K, R>
+where
+    T: RealField + ToPrimitive + Copy,
+    R: RngCore,
+    Normal3: pcc_common::point::Normal<Data = T>,
+{
+    /// Detects the key points of `input` and computes their descriptors.
+    pub fn describe<P>(&mut self, input: &PointCloud<P>) -> Description<T>
+    where
+        P: Point<Data = T>,
+        K: Filter<PointCloud<P>>,
+    {
+        let key_points = if input.is_empty() {
+            Vec::new()
+        } else {
+            self.key_point.filter_indices(input)
+        };
+        if key_points.is_empty() {
+            return Description {
+                key_points,
+                descriptors: Vec::new(),
+            };
+        }
+
+        let storage = { key_points.iter() }
+            .map(|&index| input[index].clone())
+            .collect::<Vec<_>>();
+        let len = storage.len();
+        let keypoints = PointCloud::from_vec(storage, len);
+
+        // The normals are still needed on the whole surface, as the
+        // descriptors of the key points are computed from their neighbors.
+        let tree = KdTree::new(input);
+        let normals: PointCloud<Normal3> = self.normal.compute(input, &tree, self.normal_search);
+        let descriptors: PointCloud<_> = self.descriptor.compute(
+            Keypoints::new(&keypoints, input, &normals),
+            &tree,
+            self.descriptor_search,
+        );
+
+        let descriptors = descriptors.iter().cloned().collect();
+        Description {
+            key_points,
+            descriptors,
+        }
+    }
+
+    /// Estimates the pose of `source` in `target`, or returns `None` if there
+    /// are not enough consistent correspondences.
+    pub fn compute<P>(
+        &mut self,
+        source: &PointCloud<P>,
+        target: &PointCloud<P>,
+    ) -> Option<PoseEstimate<T>>
+    where
+        P: Point<Data = T>,
+        K: Filter<PointCloud<P>>,
+    {
+        let source_desc = self.describe(source);
+        let target_desc = self.describe(target);
+
+        let correspondences = {
+            match_descriptors(
+                &source_desc.descriptors,
+                &target_desc.descriptors,
+                self.reciprocal,
+            )
+        }
+        .into_iter()
+        .map(|corr| {
+            Correspondence::new(
+                source_desc.key_points[corr.source],
+                target_desc.key_points[corr.target],
+                corr.distance,
+            )
+        })
+        .collect::<Vec<_>>();
+
+        let (transform, inliers) = self.alignment.compute(source, target, &correspondences)?;
+        Some(PoseEstimate {
+            transform,
+            correspondences: inliers.into_iter().map(|i| correspondences[i]).collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Isometry3, Vector3, Vector4};
+    use pcc_common::{
+        point::{Point, Point3},
+        point_cloud::PointCloud,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_pipeline() {
+        let resolution = 0.05;
+        let storage = (0..40)
+            .flat_map(|x| (0..40).map(move |y| (x as f32 * resolution, y as f32 * resolution)))
+            .map(|(x, y)| {
+                let z = (x * 3.).sin() * (y * 5.).cos() * 0.3 + x * x * 0.2;
+                Point3::default().with_coords(Vector4::new(x, y, z, 1.))
+            })
+            .collect::<Vec<_>>();
+        let target = PointCloud::from_vec(storage, 1);
+
+        let truth = Isometry3::new(Vector3::new(0.2, -0.1, 0.05), Vector3::new(0., 0., 0.3));
+        let mut source = PointCloud::new();
+        target.transform(&truth.inverse().to_homogeneous(), &mut source);
+
+        let mut pipeline = Pipeline::new(resolution)
+            .unwrap()
+            .with_rng(StdRng::seed_from_u64(0));
+        let estimate = pipeline.compute(&source, &target).unwrap();
+
+        let error = estimate.transform * truth.inverse();
+        assert!(error.translation.vector.norm() < resolution * 2.);
+        assert!(error.rotation.angle() < 0.05);
+        assert!(estimate.correspondences.len() >= 3);
+    }
+}
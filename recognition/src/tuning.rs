@@ -0,0 +1,178 @@
+use std::fmt;
+
+use nalgebra::{convert, RealField, Vector4};
+use num::ToPrimitive;
+use pcc_common::{
+    point::Point,
+    point_cloud::{AsPointCloud, PointCloud},
+    search::{Search, SearchType},
+};
+use pcc_search::KdTree;
+
+/// The statistics of a point cloud from which the parameters of pipelines
+/// are suggested.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CloudStats<T: RealField> {
+    /// The number of finite points.
+    pub num_points: usize,
+    /// The bound of the finite points.
+    pub bound: [Vector4<T>; 2],
+    /// The mean distance from a point to its nearest neighbor.
+    pub resolution: T,
+    /// The standard deviation of the nearest-neighbor distances divided by
+    /// their mean, which is zero for uniformly sampled point clouds.
+    pub density_variation: T,
+}
+
+impl<T: RealField + ToPrimitive + Copy> CloudStats<T> {
+    /// The number of points sampled by [`CloudStats::new`] by default.
+    pub const DEFAULT_SAMPLES: usize = 10000;
+
+    /// Measures `input` with the nearest neighbors of up to `max_samples`
+    /// evenly strided points, or returns `None` if it has less than 2 finite
+    /// points.
+    pub fn new<P: Point<Data = T>>(input: &PointCloud<P>, max_samples: usize) -> Option<Self> {
+        let finite = { input.iter().enumerate() }
+            .filter(|(_, point)| point.is_finite())
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        if finite.len() < 2 {
+            return None;
+        }
+        let bound = input.finite_bound()?;
+
+        let finite = input.create_sub(&finite, 1);
+        let tree = KdTree::new(&finite);
+        let step = (finite.len() / max_samples.max(1)).max(1);
+
+        let mut result = Vec::with_capacity(2);
+        let distances = { finite.iter().step_by(step) }
+            .filter_map(|point| {
+                tree.search_exact(point.coords(), SearchType::Knn(2), &mut result);
+                result.get(1).map(|&(_, distance)| distance)
+            })
+            .collect::<Vec<_>>();
+
+        let num = T::from_usize(distances.len())?;
+        let mean = distances.iter().fold(T::zero(), |acc, &d| acc + d) / num;
+        let var =
+            { distances.iter() }.fold(T::zero(), |acc, &d| acc + (d - mean) * (d - mean)) / num;
+        let density_variation = if mean > T::zero() {
+            var.sqrt() / mean
+        } else {
+            T::zero()
+        };
+
+        Some(CloudStats {
+            num_points: finite.len(),
+            bound,
+            resolution: mean,
+            density_variation,
+        })
+    }
+
+    #[inline]
+    pub fn diagonal(&self) -> T {
+        (self.bound[1] - self.bound[0]).xyz().norm()
+    }
+}
+
+/// Default parameters of common processing steps suggested from the
+/// statistics of a point cloud, as a starting point for tuning.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion<T: RealField> {
+    pub stats: CloudStats<T>,
+    /// The leaf size of voxel grids for downsampling.
+    pub voxel_size: T,
+    /// The search radius of normal estimation.
+    pub normal_radius: T,
+    /// The search radius of FPFH descriptors, larger than `normal_radius`.
+    pub fpfh_radius: T,
+    /// The number of neighbors of statistical outlier removal.
+    pub outlier_k: usize,
+}
+
+impl<T: RealField + ToPrimitive + Copy> Suggestion<T> {
+    /// Derives the parameters from `stats`.
+    ///
+    /// The radii are multiples of the resolution like in [`Pipeline::new`],
+    /// enlarged where the density is uneven so that sparse regions still
+    /// have enough neighbors, and the voxel size is capped by the bound so
+    /// that small point clouds aren't collapsed.
+    ///
+    /// [`Pipeline::new`]: crate::Pipeline::new
+    pub fn new(stats: CloudStats<T>) -> Self {
+        let spacing = stats.resolution * (T::one() + stats.density_variation);
+
+        let voxel_size = (spacing * convert(2.)).min(stats.diagonal() / convert(16.));
+        let normal_radius = spacing * convert(3.);
+        let fpfh_radius = spacing * convert(5.);
+
+        let k = (stats.density_variation * convert(2.) + T::one()) * convert(10.);
+        let outlier_k = { k.round().to_usize().unwrap_or(usize::MAX) }
+            .clamp(8, 50)
+            .min(stats.num_points - 1);
+
+        Suggestion {
+            stats,
+            voxel_size,
+            normal_radius,
+            fpfh_radius,
+            outlier_k,
+        }
+    }
+
+    /// Suggests the parameters for `input`, or returns `None` if it has less
+    /// than 2 finite points.
+    pub fn from_point_cloud<P: Point<Data = T>>(input: &PointCloud<P>) -> Option<Self> {
+        CloudStats::new(input, CloudStats::<T>::DEFAULT_SAMPLES).map(Self::new)
+    }
+}
+
+impl<T: RealField + fmt::Display> fmt::Display for Suggestion<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [min, max] = &self.stats.bound;
+        writeln!(f, "points: {}", self.stats.num_points)?;
+        writeln!(
+            f,
+            "bound: [{}, {}, {}] - [{}, {}, {}]",
+            min.x, min.y, min.z, max.x, max.y, max.z
+        )?;
+        writeln!(f, "resolution: {}", self.stats.resolution)?;
+        writeln!(f, "density variation: {}", self.stats.density_variation)?;
+        writeln!(f, "voxel size: {}", self.voxel_size)?;
+        writeln!(f, "normal radius: {}", self.normal_radius)?;
+        writeln!(f, "fpfh radius: {}", self.fpfh_radius)?;
+        write!(f, "outlier k: {}", self.outlier_k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::{
+        point::{Point, Point3},
+        point_cloud::PointCloud,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_suggestion() {
+        let resolution = 0.05;
+        let storage = { (0..40).flat_map(|x| (0..40).map(move |y| (x, y))) }
+            .map(|(x, y)| Vector4::new(x as f32 * resolution, y as f32 * resolution, 0., 1.))
+            .chain([Vector4::new(f32::NAN, 0., 0., 1.)])
+            .map(|coords| Point3::default().with_coords(coords))
+            .collect::<Vec<_>>();
+        let input = PointCloud::from_vec(storage, 1);
+
+        let suggestion = Suggestion::from_point_cloud(&input).unwrap();
+        assert_eq!(suggestion.stats.num_points, 1600);
+        assert!((suggestion.stats.resolution - resolution).abs() < 1e-4);
+        assert!(suggestion.stats.density_variation < 1e-3);
+        assert!((suggestion.voxel_size - resolution * 2.).abs() < 1e-3);
+        assert!(suggestion.fpfh_radius > suggestion.normal_radius);
+        assert_eq!(suggestion.outlier_k, 10);
+    }
+}
@@ -0,0 +1,258 @@
+use nalgebra::{DVector, RealField};
+use pcc_features::metrics::DescriptorTree;
+use rand::{Rng, RngCore};
+
+/// A vocabulary of descriptors, e.g. FPFH, for the bag-of-features place
+/// recognition and loop closure detection.
+///
+/// The words are the centers of the clusters of the training descriptors,
+/// and every word is weighted by its inverse document frequency, i.e. the
+/// log of the number of the training documents, e.g. the frames, divided by
+/// the ones containing it. The words in no document are weighted zero.
+#[derive(Debug, Clone)]
+pub struct Vocabulary<T: RealField> {
+    /// The words, indexed once for quantizing the descriptors.
+    words: DescriptorTree<'static, T>,
+    pub idf: Vec<T>,
+}
+
+impl<T: RealField> PartialEq for Vocabulary<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.words() == other.words() && self.idf == other.idf
+    }
+}
+
+/// Assigns every descriptor to its nearest word, or `None` if it doesn't
+/// match any word, e.g. of a different length or not finite.
+fn assign<T: RealField>(
+    words: &DescriptorTree<T>,
+    descriptors: &[&DVector<T>],
+) -> Vec<Option<usize>> {
+    let mut result = Vec::new();
+    { descriptors.iter() }
+        .map(|descriptor| {
+            if !descriptor.iter().all(|x| x.is_finite()) {
+                return None;
+            }
+            words.knn(descriptor, 1, &mut result);
+            result.first().map(|&(word, _)| word)
+        })
+        .collect()
+}
+
+impl<T: RealField> Vocabulary<T> {
+    /// Creates a vocabulary of `words` weighted by `idf`, e.g. the ones of a
+    /// trained vocabulary saved before.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `words` and `idf` have different lengths.
+    pub fn new(words: Vec<DVector<T>>, idf: Vec<T>) -> Self {
+        assert_eq!(words.len(), idf.len(), "Every word must have a weight");
+        Vocabulary {
+            words: DescriptorTree::from_vec(words),
+            idf,
+        }
+    }
+
+    #[inline]
+    pub fn words(&self) -> &[DVector<T>] {
+        self.words.descriptors()
+    }
+
+    /// Trains a vocabulary of at most `num_words` words from the descriptors
+    /// of `documents` by k-means, initialized by k-means++ and iterated at
+    /// most `max_iterations` times.
+    ///
+    /// Only the finite descriptors of the same length as the first non-empty
+    /// one are clustered.
+    pub fn train<R: RngCore>(
+        documents: &[&[DVector<T>]],
+        num_words: usize,
+        max_iterations: usize,
+        rng: &mut R,
+    ) -> Self {
+        let dims = { documents.iter().flat_map(|document| document.iter()) }
+            .find(|descriptor| !descriptor.is_empty())
+            .map_or(0, |descriptor| descriptor.len());
+        let descriptors = { documents.iter().flat_map(|document| document.iter()) }
+            .filter(|descriptor| dims > 0 && descriptor.len() == dims)
+            .filter(|descriptor| descriptor.iter().all(|x| x.is_finite()))
+            .collect::<Vec<_>>();
+        if descriptors.is_empty() || num_words == 0 {
+            return Vocabulary::new(Vec::new(), Vec::new());
+        }
+
+        // K-means++: every next word is drawn with the probability
+        // proportional to the squared distance to the nearest chosen word.
+        let mut words = vec![descriptors[rng.gen_range(0..descriptors.len())].clone()];
+        let mut distances = { descriptors.iter() }
+            .map(|descriptor| (*descriptor - &words[0]).norm_squared())
+            .collect::<Vec<_>>();
+        while words.len() < num_words {
+            let total = distances.iter().fold(T::zero(), |acc, d| acc + d.clone());
+            if total <= T::zero() {
+                break;
+            }
+            let mut target = total * T::from_f64(rng.gen::<f64>()).unwrap();
+            let next = { distances.iter() }
+                .position(|d| {
+                    target -= d.clone();
+                    target <= T::zero()
+                })
+                .unwrap_or(descriptors.len() - 1);
+            let word = descriptors[next].clone();
+            for (distance, descriptor) in distances.iter_mut().zip(&descriptors) {
+                let new = (*descriptor - &word).norm_squared();
+                if new < *distance {
+                    *distance = new;
+                }
+            }
+            words.push(word);
+        }
+
+        // Lloyd's iterations, where the empty clusters keep their words.
+        let mut assignments = assign(&DescriptorTree::new(&words), &descriptors);
+        for _ in 0..max_iterations {
+            let mut sums = vec![(DVector::zeros(dims), 0); words.len()];
+            for (descriptor, word) in descriptors.iter().zip(&assignments) {
+                if let Some(word) = word {
+                    sums[*word].0 += *descriptor;
+                    sums[*word].1 += 1;
+                }
+            }
+            for (word, (sum, num)) in words.iter_mut().zip(sums) {
+                if num > 0 {
+                    *word = sum / T::from_usize(num).unwrap();
+                }
+            }
+
+            let new = assign(&DescriptorTree::new(&words), &descriptors);
+            if new == assignments {
+                break;
+            }
+            assignments = new;
+        }
+
+        let idf = vec![T::zero(); words.len()];
+        let mut vocabulary = Vocabulary::new(words, idf);
+        vocabulary.update_idf(documents);
+        vocabulary
+    }
+
+    /// Recomputes the inverse document frequencies of the words from
+    /// `documents`, e.g. the frames of the map to recognize places in.
+    pub fn update_idf(&mut self, documents: &[&[DVector<T>]]) {
+        let mut counts = vec![0; self.words().len()];
+        for document in documents {
+            let mut contained = vec![false; self.words().len()];
+            let descriptors = document.iter().collect::<Vec<_>>();
+            for word in assign(&self.words, &descriptors).into_iter().flatten() {
+                contained[word] = true;
+            }
+            { counts.iter_mut().zip(contained) }
+                .filter(|(_, contained)| *contained)
+                .for_each(|(count, _)| *count += 1);
+        }
+
+        let num = T::from_usize(documents.len()).unwrap();
+        self.idf = { counts.into_iter() }
+            .map(|count| match count {
+                0 => T::zero(),
+                _ => (num.clone() / T::from_usize(count).unwrap()).ln(),
+            })
+            .collect();
+    }
+
+    /// The index of the nearest word to `descriptor`, or `None` if it doesn't
+    /// match any word.
+    pub fn quantize(&self, descriptor: &DVector<T>) -> Option<usize> {
+        assign(&self.words, &[descriptor])[0]
+    }
+
+    /// Quantizes the descriptors of a document into its bag of words, i.e.
+    /// the term frequencies weighted by the inverse document frequencies,
+    /// normalized to the unit length unless all zeros.
+    pub fn bow(&self, descriptors: &[DVector<T>]) -> DVector<T> {
+        let mut bow = DVector::zeros(self.words().len());
+        let assignments = assign(&self.words, &descriptors.iter().collect::<Vec<_>>());
+        let words = assignments.into_iter().flatten().collect::<Vec<_>>();
+        if words.is_empty() {
+            return bow;
+        }
+
+        let tf = T::one() / T::from_usize(words.len()).unwrap();
+        for word in words {
+            bow[word] += tf.clone() * self.idf[word].clone();
+        }
+        let norm = bow.norm();
+        if norm > T::zero() {
+            bow /= norm;
+        }
+        bow
+    }
+}
+
+/// The similarity of two bags of words of the same vocabulary, i.e. their
+/// cosine similarity, in `[0, 1]` for the normalized ones.
+pub fn bow_similarity<T: RealField>(a: &DVector<T>, b: &DVector<T>) -> T {
+    if a.len() != b.len() {
+        return T::zero();
+    }
+    a.dot(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_vocabulary() {
+        let descriptor = |center: f64, i: usize| {
+            DVector::from_fn(4, |d, _| center + ((i * 7 + d) % 5) as f64 * 0.01)
+        };
+        let documents = [
+            (0..10).map(|i| descriptor(0., i)).collect::<Vec<_>>(),
+            (0..10).map(|i| descriptor(1., i)).collect::<Vec<_>>(),
+            (0..10)
+                .map(|i| descriptor(if i % 2 == 0 { 0. } else { 2. }, i))
+                .collect::<Vec<_>>(),
+        ];
+        let documents = documents.iter().map(|d| &d[..]).collect::<Vec<_>>();
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let vocabulary = Vocabulary::train(&documents, 3, 10, &mut rng);
+        assert_eq!(vocabulary.words().len(), 3);
+
+        let word = |center| vocabulary.quantize(&descriptor(center, 0)).unwrap();
+        assert!(word(0.) != word(1.) && word(1.) != word(2.) && word(0.) != word(2.));
+        // In 2 of the 3 documents.
+        assert!((vocabulary.idf[word(0.)] - 1.5f64.ln()).abs() < 1e-9);
+
+        let bows = { documents.iter() }
+            .map(|document| vocabulary.bow(document))
+            .collect::<Vec<_>>();
+        assert!((bow_similarity(&bows[0], &bows[0]) - 1.).abs() < 1e-9);
+        assert!(bow_similarity(&bows[0], &bows[1]).abs() < 1e-9);
+        assert!(bow_similarity(&bows[0], &bows[2]) > 0.);
+    }
+
+    #[test]
+    fn test_non_finite() {
+        let descriptor = |x: f64| DVector::from_element(2, x);
+        let documents = [
+            vec![descriptor(0.), descriptor(f64::NAN)],
+            vec![descriptor(1.), descriptor(f64::INFINITY)],
+        ];
+        let documents = documents.iter().map(|d| &d[..]).collect::<Vec<_>>();
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let vocabulary = Vocabulary::train(&documents, 4, 10, &mut rng);
+        assert_eq!(vocabulary.words().len(), 2);
+        assert!(vocabulary.words().iter().flatten().all(|x| x.is_finite()));
+        assert_eq!(vocabulary.quantize(&descriptor(f64::NAN)), None);
+        assert!(vocabulary.quantize(&descriptor(0.1)).is_some());
+    }
+}
@@ -0,0 +1,55 @@
+use nalgebra::{DVector, RealField};
+
+/// A pair of matched points, indexed into the source and the target
+/// respectively.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Correspondence<T> {
+    pub source: usize,
+    pub target: usize,
+    pub distance: T,
+}
+
+impl<T> Correspondence<T> {
+    pub fn new(source: usize, target: usize, distance: T) -> Self {
+        Correspondence {
+            source,
+            target,
+            distance,
+        }
+    }
+}
+
+fn nearest<T: RealField>(pivot: &DVector<T>, descriptors: &[DVector<T>]) -> Option<(usize, T)> {
+    { descriptors.iter().enumerate() }
+        .filter(|(_, descriptor)| descriptor.len() == pivot.len())
+        .map(|(index, descriptor)| (index, (descriptor - pivot).norm()))
+        .fold(None, |acc, (index, distance)| match acc {
+            Some((_, ref min)) if *min <= distance => acc,
+            _ => Some((index, distance)),
+        })
+}
+
+/// Matches every source descriptor to its nearest target descriptor in the
+/// descriptor space.
+///
+/// If `reciprocal` is set, only the pairs that are the nearest to each other in
+/// both directions are kept. Empty descriptors are never matched.
+pub fn match_descriptors<T: RealField>(
+    source: &[DVector<T>],
+    target: &[DVector<T>],
+    reciprocal: bool,
+) -> Vec<Correspondence<T>> {
+    { source.iter().enumerate() }
+        .filter(|(_, descriptor)| !descriptor.is_empty())
+        .filter_map(|(index, descriptor)| {
+            let (target_index, distance) = nearest(descriptor, target)?;
+            if reciprocal {
+                let (back, _) = nearest(&target[target_index], source)?;
+                if back != index {
+                    return None;
+                }
+            }
+            Some(Correspondence::new(index, target_index, distance))
+        })
+        .collect()
+}
@@ -0,0 +1,105 @@
+use nalgebra::{Isometry3, RealField, Translation3};
+use pcc_common::{point::Point, point_cloud::PointCloud};
+use petgraph::{algo::dijkstra, graph::NodeIndex};
+
+use crate::PoseGraph;
+
+/// The explicit loop closing heuristic (ELCH).
+///
+/// After a loop is detected between `loop_start` and `loop_end`, and the drift
+/// accumulated along the loop is measured as `loop_transform` (which maps the
+/// scan of `loop_end` onto the scan of `loop_start`), the drift is distributed
+/// over all the scans in the graph, weighted by their graph distances to both
+/// ends of the loop.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Elch<T: RealField> {
+    pub loop_start: NodeIndex,
+    pub loop_end: NodeIndex,
+    pub loop_transform: Isometry3<T>,
+}
+
+impl<T: RealField> Elch<T> {
+    pub fn new(loop_start: NodeIndex, loop_end: NodeIndex, loop_transform: Isometry3<T>) -> Self {
+        Elch {
+            loop_start,
+            loop_end,
+            loop_transform,
+        }
+    }
+}
+
+impl<T: RealField + Copy + Default> Elch<T> {
+    /// Returns the weight of the loop transform applied to every scan in the
+    /// graph. Scans unreachable from either end of the loop are skipped.
+    pub fn weights<P: Point<Data = T>>(&self, graph: &PoseGraph<P>) -> Vec<(NodeIndex, T)> {
+        let from_start = dijkstra(graph.graph(), self.loop_start, None, |e| *e.weight());
+        let from_end = dijkstra(graph.graph(), self.loop_end, None, |e| *e.weight());
+
+        { graph.graph().node_indices() }
+            .filter_map(|index| {
+                let ds = *from_start.get(&index)?;
+                let de = *from_end.get(&index)?;
+                let sum = ds + de;
+                Some((index, if sum > T::zero() { ds / sum } else { T::zero() }))
+            })
+            .collect()
+    }
+
+    /// Corrects the poses and the point clouds of all the scans in the graph,
+    /// and returns the number of corrected scans.
+    pub fn compute<P: Point<Data = T>>(&self, graph: &mut PoseGraph<P>) -> usize {
+        let weights = self.weights(graph);
+
+        let pivot = Translation3::from(graph[self.loop_start].pose.translation.vector);
+        let mut temp = PointCloud::new();
+        for &(index, weight) in &weights {
+            let correction = Isometry3::identity().lerp_slerp(&self.loop_transform, weight);
+            let correction = pivot * correction * pivot.inverse();
+
+            let scan = &mut graph[index];
+            scan.pose = correction * scan.pose;
+            scan.point_cloud
+                .transform(&correction.to_homogeneous(), &mut temp);
+            std::mem::swap(&mut scan.point_cloud, &mut temp);
+        }
+
+        weights.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Isometry3, Vector3, Vector4};
+    use pcc_common::{
+        point::{Point, Point3},
+        point_cloud::PointCloud,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_elch() {
+        let mut graph = PoseGraph::<Point3>::new();
+        let scans = (0..5)
+            .map(|index| {
+                let pose = Isometry3::translation(index as f32, 0., 0.);
+                let point = Point3::default().with_coords(Vector4::new(index as f32, 0., 0., 1.));
+                graph.add_scan(PointCloud::from_vec(vec![point], 1), pose)
+            })
+            .collect::<Vec<_>>();
+        graph.connect_chain(&scans);
+
+        let elch = Elch::new(scans[0], scans[4], Isometry3::translation(0., -2., 0.));
+        assert_eq!(elch.compute(&mut graph), 5);
+
+        assert_eq!(graph[scans[0]].pose.translation.vector, Vector3::zeros());
+        assert_eq!(
+            graph[scans[2]].pose.translation.vector,
+            Vector3::new(2., -1., 0.)
+        );
+        assert_eq!(
+            graph[scans[4]].point_cloud[0].coords(),
+            &Vector4::new(4., -2., 0., 1.)
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use nalgebra::{Isometry3, RealField, Vector4};
+use num::ToPrimitive;
+use pcc_common::{
+    filter::ApproxFilter,
+    point::{Centroid, Point},
+    point_cloud::PointCloud,
+    robust::{RobustKernel, Squared},
+    search::{Search, SearchType},
+};
+use pcc_filters::VoxelGrid;
+use pcc_search::KdTree;
+
+use crate::{
+    reweigh_correspondences, rigid_transform_weighted, weigh_correspondences, Correspondence,
+    CorrespondenceWeigher,
+};
+
+/// Aligns a source point cloud to a target point cloud with point-to-point
+/// ICP, which iteratively matches every source point to its nearest target
+/// point and estimates the rigid transformation between the pairs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Icp<T, K = Squared> {
+    pub max_iterations: usize,
+    /// Only correspondences shorter than it are kept, as the radius of
+    /// [`SearchType::KnnWithin`] is exclusive.
+    pub max_distance: T,
+    /// The iterations stop once an iteration translates less than it and
+    /// rotates less than it in radians.
+    pub epsilon: T,
+    /// The robust kernel applied to the distances of the correspondences.
+    pub kernel: K,
+}
+
+impl<T> Icp<T> {
+    pub fn new(max_iterations: usize, max_distance: T, epsilon: T) -> Self {
+        Icp {
+            max_iterations,
+            max_distance,
+            epsilon,
+            kernel: Squared,
+        }
+    }
+}
+
+impl<T, K> Icp<T, K> {
+    pub fn with_kernel<K2>(self, kernel: K2) -> Icp<T, K2> {
+        Icp {
+            max_iterations: self.max_iterations,
+            max_distance: self.max_distance,
+            epsilon: self.epsilon,
+            kernel,
+        }
+    }
+}
+
+impl<T: RealField + Copy, K: RobustKernel<T>> Icp<T, K> {
+    /// Returns the transformation from `source` to `target` starting from
+    /// `initial`, or `None` if too few correspondences are found.
+    pub fn compute<P: Point<Data = T>>(
+        &self,
+        source: &PointCloud<P>,
+        target: &PointCloud<P>,
+        initial: Isometry3<T>,
+    ) -> Option<Isometry3<T>> {
+        let weigher = |_: &P, _: &P, _: &Correspondence<T>| T::one();
+        self.compute_with(source, target, initial, &weigher)
+    }
+
+    /// Like [`Icp::compute`], but the correspondences are weighed by
+    /// `weigher` before the robust kernel.
+    pub fn compute_with<P, W>(
+        &self,
+        source: &PointCloud<P>,
+        target: &PointCloud<P>,
+        initial: Isometry3<T>,
+        weigher: &W,
+    ) -> Option<Isometry3<T>>
+    where
+        P: Point<Data = T>,
+        W: CorrespondenceWeigher<P> + ?Sized,
+    {
+        if target.is_empty() {
+            return None;
+        }
+        let tree = KdTree::new(target);
+
+        let mut transform = initial;
+        let mut result = Vec::new();
+        for _ in 0..self.max_iterations {
+            let moved = { source.iter() }
+                .map(|point| (transform * point.na_point()).to_homogeneous())
+                .collect::<Vec<Vector4<T>>>();
+
+            let correspondences = { source.iter().zip(&moved).enumerate() }
+                .filter(|(_, (point, _))| point.is_finite())
+                .filter_map(|(index, (_, coords))| {
+                    let ty = SearchType::KnnWithin {
+                        k: 1,
+                        radius: self.max_distance,
+                    };
+                    tree.search_exact(coords, ty, &mut result);
+                    let &(target_index, distance) = result.first()?;
+                    Some(Correspondence::new(index, target_index, distance))
+                })
+                .collect::<Vec<_>>();
+
+            let mut weighted = weigh_correspondences(source, target, &correspondences, weigher);
+            reweigh_correspondences(&mut weighted, &self.kernel);
+
+            let step = rigid_transform_weighted(weighted.iter().map(|(corr, weight)| {
+                (&moved[corr.source], target[corr.target].coords(), *weight)
+            }))?;
+            transform = step * transform;
+
+            if step.translation.vector.norm() < self.epsilon && step.rotation.angle() < self.epsilon
+            {
+                break;
+            }
+        }
+
+        Some(transform)
+    }
+}
+
+/// Runs [`Icp`] coarse-to-fine on both point clouds downsampled with voxel
+/// grids, warm-starting every level from the transformation of the previous
+/// one.
+///
+/// Every level is a pair of the leaf size and the maximum correspondence
+/// distance, from the coarsest to the finest. A non-positive leaf size runs
+/// the level on the original point clouds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiResolutionIcp<T, K = Squared> {
+    pub icp: Icp<T, K>,
+    pub levels: Vec<(T, T)>,
+}
+
+impl<T, K> MultiResolutionIcp<T, K> {
+    pub fn new(icp: Icp<T, K>, levels: Vec<(T, T)>) -> Self {
+        MultiResolutionIcp { icp, levels }
+    }
+}
+
+impl<T, K> MultiResolutionIcp<T, K>
+where
+    T: RealField + Copy + ToPrimitive + Default,
+    K: RobustKernel<T> + Clone,
+{
+    pub fn compute<P>(
+        &self,
+        source: &PointCloud<P>,
+        target: &PointCloud<P>,
+        initial: Isometry3<T>,
+    ) -> Option<Isometry3<T>>
+    where
+        P: Point<Data = T> + Centroid<Result = P>,
+        <P as Centroid>::Accumulator: Default,
+    {
+        let weigher = |_: &P, _: &P, _: &Correspondence<T>| T::one();
+        self.compute_with(source, target, initial, &weigher)
+    }
+
+    /// Returns the transformation of the finest level that succeeds, or
+    /// `None` if no level does.
+    pub fn compute_with<P, W>(
+        &self,
+        source: &PointCloud<P>,
+        target: &PointCloud<P>,
+        initial: Isometry3<T>,
+        weigher: &W,
+    ) -> Option<Isometry3<T>>
+    where
+        P: Point<Data = T> + Centroid<Result = P>,
+        <P as Centroid>::Accumulator: Default,
+        W: CorrespondenceWeigher<P> + ?Sized,
+    {
+        let mut transform = initial;
+        let mut success = false;
+
+        for &(leaf, max_distance) in &self.levels {
+            let icp = Icp {
+                max_distance,
+                ..self.icp.clone()
+            };
+
+            let estimate = if leaf > T::zero() {
+                let mut grid = VoxelGrid::new(Vector4::new(leaf, leaf, leaf, T::one()));
+                let (source, target) = (grid.filter(source), grid.filter(target));
+                icp.compute_with(&source, &target, transform, weigher)
+            } else {
+                icp.compute_with(source, target, transform, weigher)
+            };
+
+            if let Some(estimate) = estimate {
+                transform = estimate;
+                success = true;
+            }
+        }
+
+        success.then(|| transform)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Isometry3, Vector3, Vector4};
+    use pcc_common::{
+        point::{Point, Point3},
+        point_cloud::PointCloud,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_multi_resolution() {
+        let step = 0.05;
+        let storage = (0..20)
+            .flat_map(|i| (0..20).map(move |j| (i as f32 * step, j as f32 * step)))
+            .flat_map(|(a, b)| [[a, b, 0.], [a, 0., b], [0., a, b]])
+            .map(|[x, y, z]| Point3::default().with_coords(Vector4::new(x, y, z, 1.)))
+            .collect::<Vec<_>>();
+        let target = PointCloud::from_vec(storage, 1);
+
+        let truth = Isometry3::new(Vector3::new(0.03, -0.02, 0.01), Vector3::new(0., 0., 0.05));
+        let mut source = PointCloud::new();
+        target.transform(&truth.inverse().to_homogeneous(), &mut source);
+
+        let icp = MultiResolutionIcp::new(
+            Icp::new(50, 0., 1e-6),
+            vec![(step * 4., step * 8.), (0., step * 2.)],
+        );
+        let transform = icp
+            .compute(&source, &target, Isometry3::identity())
+            .unwrap();
+
+        let error = transform * truth.inverse();
+        assert!(error.translation.vector.norm() < 1e-3);
+        assert!(error.rotation.angle() < 1e-3);
+    }
+}
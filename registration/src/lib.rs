@@ -0,0 +1,25 @@
+mod correspondence;
+mod elch;
+mod icp;
+mod narf;
+mod pose_graph;
+mod ransac;
+mod scan_match;
+mod transformation;
+mod weighting;
+mod world_model;
+
+pub use self::{
+    correspondence::{match_descriptors, Correspondence},
+    elch::Elch,
+    icp::{Icp, MultiResolutionIcp},
+    narf::NarfMatcher,
+    pose_graph::{PoseGraph, Scan},
+    ransac::RansacAlignment,
+    scan_match::{CorrelativeMatcher, OccupancyGrid},
+    transformation::{rigid_transform, rigid_transform_weighted},
+    weighting::{
+        reweigh_correspondences, weigh_correspondences, CorrespondenceWeigher, IntensityWeigher,
+    },
+    world_model::WorldModel,
+};
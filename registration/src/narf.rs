@@ -0,0 +1,121 @@
+use nalgebra::{RealField, Scalar};
+use pcc_features::{narf_distance, NarfData};
+
+use crate::Correspondence;
+
+/// Matches the NARFs of two frames by their descriptors with
+/// [`narf_distance`].
+///
+/// The indices of the correspondences are into the NARF slices, whose
+/// positions form the keypoints to align, e.g. with
+/// [`RansacAlignment`](crate::RansacAlignment).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NarfMatcher<T: Scalar> {
+    /// Pairs with larger descriptor distances are rejected.
+    pub max_distance: T,
+    /// The ratio test: the best match is kept only if its distance is less
+    /// than `ratio` times the distance of the best match at another
+    /// keypoint.
+    pub ratio: T,
+    /// If set, only the pairs that are the best matches of each other are
+    /// kept.
+    pub reciprocal: bool,
+}
+
+impl<T: Scalar> NarfMatcher<T> {
+    pub fn new(max_distance: T, ratio: T, reciprocal: bool) -> Self {
+        NarfMatcher {
+            max_distance,
+            ratio,
+            reciprocal,
+        }
+    }
+}
+
+impl<T: RealField> NarfMatcher<T> {
+    /// The best match of `pivot` in `candidates` that passes the ratio test,
+    /// and its distance.
+    ///
+    /// The NARFs rotated to several dominant orientations share the same
+    /// keypoint, so the second best match is searched at other positions.
+    fn best(&self, pivot: &NarfData<T>, candidates: &[NarfData<T>]) -> Option<(usize, T)> {
+        let distances = { candidates.iter().enumerate() }
+            .filter_map(|(index, narf)| Some((index, narf_distance(pivot, narf)?)))
+            .collect::<Vec<_>>();
+
+        let (best, distance) =
+            { distances.iter().cloned() }.fold(None, |acc, (index, distance)| match acc {
+                Some((_, ref min)) if *min <= distance => acc,
+                _ => Some((index, distance)),
+            })?;
+        if distance > self.max_distance {
+            return None;
+        }
+
+        let position = &candidates[best].position;
+        let second = { distances.iter() }
+            .filter(|(index, _)| candidates[*index].position != *position)
+            .map(|(_, distance)| distance.clone())
+            .reduce(|min, distance| if distance < min { distance } else { min });
+        match second {
+            Some(second) if distance >= second.clone() * self.ratio.clone() => None,
+            _ => Some((best, distance)),
+        }
+    }
+
+    pub fn compute(
+        &self,
+        source: &[NarfData<T>],
+        target: &[NarfData<T>],
+    ) -> Vec<Correspondence<T>> {
+        { source.iter().enumerate() }
+            .filter_map(|(index, narf)| {
+                let (target_index, distance) = self.best(narf, target)?;
+                if self.reciprocal {
+                    let (back, _) = self.best(&target[target_index], source)?;
+                    if source[back].position != narf.position {
+                        return None;
+                    }
+                }
+                Some(Correspondence::new(index, target_index, distance))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_features::NarfData;
+
+    use super::*;
+
+    #[test]
+    fn test_narf_matcher() {
+        let narf = |x: f32, descriptor: Vec<f32>| NarfData {
+            position: Vector4::new(x, 0., 0., 1.),
+            descriptor,
+            ..Default::default()
+        };
+        let source = [
+            narf(0., vec![0.1, 0.2, 0.3, 0.4]),
+            narf(1., vec![-0.5, 0.5, -0.5, 0.5]),
+            narf(2., vec![0., 0., 0., 0.]),
+        ];
+        let target = [
+            // A rotated version of the first source descriptor.
+            narf(10., vec![0.3, 0.4, 0.1, 0.2]),
+            narf(11., vec![-0.5, 0.5, -0.5, 0.45]),
+            narf(12., vec![0.01, 0., 0., 0.]),
+            narf(13., vec![0., 0.01, 0., 0.]),
+        ];
+
+        let matcher = NarfMatcher::new(0.1, 0.8, false);
+        let correspondences = matcher.compute(&source, &target);
+        let pairs = { correspondences.iter() }
+            .map(|corr| (corr.source, corr.target))
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, [(0, 0), (1, 1)]);
+        assert!(correspondences[0].distance < 1e-6);
+    }
+}
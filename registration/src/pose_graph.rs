@@ -0,0 +1,110 @@
+use std::ops::{Index, IndexMut};
+
+use nalgebra::{Isometry3, RealField};
+use pcc_common::{point::Point, point_cloud::PointCloud};
+use petgraph::graph::{EdgeIndex, NodeIndex, UnGraph};
+
+/// A scan registered in the world frame, i.e. the points of `point_cloud` are
+/// already transformed by `pose`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scan<P: Point>
+where
+    P::Data: RealField,
+{
+    pub point_cloud: PointCloud<P>,
+    pub pose: Isometry3<P::Data>,
+}
+
+/// A graph of scans, whose edges connect the scans registered with each other
+/// and are weighted by the distance between their sensor positions.
+#[derive(Debug, Clone)]
+pub struct PoseGraph<P: Point>
+where
+    P::Data: RealField,
+{
+    graph: UnGraph<Scan<P>, P::Data>,
+}
+
+impl<P: Point> PoseGraph<P>
+where
+    P::Data: RealField,
+{
+    #[inline]
+    pub fn new() -> Self {
+        PoseGraph {
+            graph: UnGraph::default(),
+        }
+    }
+
+    #[inline]
+    pub fn add_scan(&mut self, point_cloud: PointCloud<P>, pose: Isometry3<P::Data>) -> NodeIndex {
+        self.graph.add_node(Scan { point_cloud, pose })
+    }
+
+    pub fn connect(&mut self, a: NodeIndex, b: NodeIndex) -> EdgeIndex {
+        let distance = (&self.graph[a].pose.translation.vector
+            - &self.graph[b].pose.translation.vector)
+            .norm();
+        self.graph.update_edge(a, b, distance)
+    }
+
+    /// Connects the scans one after another in the order of `scans`.
+    pub fn connect_chain(&mut self, scans: &[NodeIndex]) {
+        for pair in scans.windows(2) {
+            self.connect(pair[0], pair[1]);
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.graph.node_count()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.graph.node_count() == 0
+    }
+
+    #[inline]
+    pub fn graph(&self) -> &UnGraph<Scan<P>, P::Data> {
+        &self.graph
+    }
+
+    #[inline]
+    pub fn scans(&self) -> impl Iterator<Item = (NodeIndex, &Scan<P>)> {
+        { self.graph.node_indices() }.map(|index| (index, &self.graph[index]))
+    }
+}
+
+impl<P: Point> Default for PoseGraph<P>
+where
+    P::Data: RealField,
+{
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Point> Index<NodeIndex> for PoseGraph<P>
+where
+    P::Data: RealField,
+{
+    type Output = Scan<P>;
+
+    #[inline]
+    fn index(&self, index: NodeIndex) -> &Self::Output {
+        &self.graph[index]
+    }
+}
+
+impl<P: Point> IndexMut<NodeIndex> for PoseGraph<P>
+where
+    P::Data: RealField,
+{
+    #[inline]
+    fn index_mut(&mut self, index: NodeIndex) -> &mut Self::Output {
+        &mut self.graph[index]
+    }
+}
@@ -0,0 +1,349 @@
+use nalgebra::{Isometry3, Point3, RealField, Vector4};
+use pcc_common::{
+    point::Point,
+    point_cloud::PointCloud,
+    robust::{RobustKernel, Squared},
+};
+use rand::{rngs::ThreadRng, RngCore};
+
+use crate::{rigid_transform, rigid_transform_weighted, Correspondence};
+
+/// The number of the reweighted refinements of the best transformation.
+const REFINEMENTS: usize = 10;
+
+/// Estimates the rigid transformation between 2 point clouds from a set of
+/// (possibly wrong) correspondences with RANSAC.
+///
+/// The best transformation is refined on its inlier correspondences, which are
+/// iteratively reweighted by `kernel` applied to their distances, and the
+/// refined one is kept if its inliers score no lower.
+#[derive(Debug, Clone)]
+pub struct RansacAlignment<T, R: RngCore = ThreadRng, K = Squared> {
+    pub max_iterations: usize,
+    pub inlier_threshold: T,
+    /// If set, the samples whose source and target triangles differ too much
+    /// are rejected before estimating and scoring their transformations.
+    ///
+    /// The ratio of every pair of corresponding edge lengths must be at least
+    /// `similarity`, which is in `(0, 1)` and usually about `0.9`.
+    pub similarity: Option<T>,
+    /// The robust kernel applied to the distances of the inlier
+    /// correspondences when refining the best transformation.
+    pub kernel: K,
+    pub rng: R,
+}
+
+impl<T, R: RngCore> RansacAlignment<T, R> {
+    pub fn new(max_iterations: usize, inlier_threshold: T, rng: R) -> Self {
+        RansacAlignment {
+            max_iterations,
+            inlier_threshold,
+            similarity: None,
+            kernel: Squared,
+            rng,
+        }
+    }
+}
+
+impl<T, R: RngCore, K> RansacAlignment<T, R, K> {
+    pub fn with_rng<R2: RngCore>(self, rng: R2) -> RansacAlignment<T, R2, K> {
+        RansacAlignment {
+            max_iterations: self.max_iterations,
+            inlier_threshold: self.inlier_threshold,
+            similarity: self.similarity,
+            kernel: self.kernel,
+            rng,
+        }
+    }
+
+    pub fn with_kernel<K2>(self, kernel: K2) -> RansacAlignment<T, R, K2> {
+        RansacAlignment {
+            max_iterations: self.max_iterations,
+            inlier_threshold: self.inlier_threshold,
+            similarity: self.similarity,
+            kernel,
+            rng: self.rng,
+        }
+    }
+
+    pub fn with_similarity(mut self, similarity: T) -> Self {
+        self.similarity = Some(similarity);
+        self
+    }
+}
+
+impl<T: RealField + Copy, R: RngCore, K: RobustKernel<T>> RansacAlignment<T, R, K> {
+    fn distance<P: Point<Data = T>>(
+        transform: &Isometry3<T>,
+        source: &PointCloud<P>,
+        target: &PointCloud<P>,
+        corr: &Correspondence<T>,
+    ) -> T {
+        let point = transform * Point3::from(source[corr.source].coords().xyz());
+        (point.coords - target[corr.target].coords().xyz()).norm()
+    }
+
+    fn inliers<P: Point<Data = T>>(
+        &self,
+        transform: &Isometry3<T>,
+        source: &PointCloud<P>,
+        target: &PointCloud<P>,
+        correspondences: &[Correspondence<T>],
+    ) -> Vec<usize> {
+        { correspondences.iter().enumerate() }
+            .filter(|(_, corr)| {
+                Self::distance(transform, source, target, corr) < self.inlier_threshold
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Checks if the edges of the sampled source and target triangles have
+    /// similar lengths, as rigid transformations preserve them.
+    fn is_similar(&self, pairs: &[(&Vector4<T>, &Vector4<T>); 3]) -> bool {
+        let similarity = match self.similarity {
+            Some(similarity) => similarity,
+            None => return true,
+        };
+        [(0, 1), (1, 2), (2, 0)].into_iter().all(|(i, j)| {
+            let source = (pairs[i].0 - pairs[j].0).xyz().norm();
+            let target = (pairs[i].1 - pairs[j].1).xyz().norm();
+            let (min, max) = if source < target {
+                (source, target)
+            } else {
+                (target, source)
+            };
+            min >= max * similarity
+        })
+    }
+
+    fn sample(&mut self, len: usize) -> [usize; 3] {
+        let mut ret = [0; 3];
+        for i in 0..3 {
+            ret[i] = loop {
+                let index = self.rng.next_u64() as usize % len;
+                if !ret[..i].contains(&index) {
+                    break index;
+                }
+            };
+        }
+        ret
+    }
+
+    /// Returns the estimated transformation from `source` to `target` and the
+    /// indices of the inlier correspondences, or `None` if no consensus can be
+    /// reached.
+    pub fn compute<P: Point<Data = T>>(
+        &mut self,
+        source: &PointCloud<P>,
+        target: &PointCloud<P>,
+        correspondences: &[Correspondence<T>],
+    ) -> Option<(Isometry3<T>, Vec<usize>)> {
+        self.compute_inner(source, target, correspondences, None)
+    }
+
+    /// Like [`RansacAlignment::compute`], but scores the transformations by
+    /// the sum of the `weights` of their inlier correspondences, e.g. the
+    /// confidences of the points from the noise models of the sensors, and
+    /// refines the best one with the weights as well.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `correspondences` and `weights` have different lengths.
+    pub fn compute_weighted<P: Point<Data = T>>(
+        &mut self,
+        source: &PointCloud<P>,
+        target: &PointCloud<P>,
+        correspondences: &[Correspondence<T>],
+        weights: &[T],
+    ) -> Option<(Isometry3<T>, Vec<usize>)> {
+        assert_eq!(
+            correspondences.len(),
+            weights.len(),
+            "Every correspondence must have a weight"
+        );
+        self.compute_inner(source, target, correspondences, Some(weights))
+    }
+
+    fn compute_inner<P: Point<Data = T>>(
+        &mut self,
+        source: &PointCloud<P>,
+        target: &PointCloud<P>,
+        correspondences: &[Correspondence<T>],
+        weights: Option<&[T]>,
+    ) -> Option<(Isometry3<T>, Vec<usize>)> {
+        if correspondences.len() < 3 {
+            return None;
+        }
+
+        let pair =
+            |corr: &Correspondence<T>| (source[corr.source].coords(), target[corr.target].coords());
+        let weight = |index: usize| weights.map_or(T::one(), |weights| weights[index]);
+        let score = |inliers: &[usize]| {
+            { inliers.iter() }.fold(T::zero(), |acc, &index| acc + weight(index))
+        };
+
+        let mut best: Option<(Isometry3<T>, Vec<usize>, T)> = None;
+        for _ in 0..self.max_iterations {
+            let sample = self
+                .sample(correspondences.len())
+                .map(|i| pair(&correspondences[i]));
+            if !self.is_similar(&sample) {
+                continue;
+            }
+            let transform = match rigid_transform(sample) {
+                Some(transform) => transform,
+                None => continue,
+            };
+
+            let inliers = self.inliers(&transform, source, target, correspondences);
+            let inlier_score = score(&inliers);
+            if matches!(best, Some((_, _, ref b)) if *b >= inlier_score) {
+                continue;
+            }
+            best = Some((transform, inliers, inlier_score));
+        }
+
+        let (transform, inliers, inlier_score) =
+            best.filter(|(_, inliers, _)| inliers.len() >= 3)?;
+        // Iteratively reweighted least squares on the inliers.
+        let mut refined = None;
+        for _ in 0..REFINEMENTS {
+            let current = refined.as_ref().unwrap_or(&transform);
+            let residuals = { inliers.iter() }
+                .map(|&i| Self::distance(current, source, target, &correspondences[i]))
+                .collect::<Vec<_>>();
+            let robust = self.kernel.weights(&residuals);
+            let pairs = inliers.iter().zip(robust).map(|(&i, robust)| {
+                let (source, target) = pair(&correspondences[i]);
+                (source, target, weight(i) * robust)
+            });
+            match rigid_transform_weighted(pairs) {
+                Some(next) => refined = Some(next),
+                None => break,
+            }
+        }
+        Some(match refined {
+            Some(refined) => {
+                let refined_inliers = self.inliers(&refined, source, target, correspondences);
+                if score(&refined_inliers) >= inlier_score {
+                    (refined, refined_inliers)
+                } else {
+                    (transform, inliers)
+                }
+            }
+            None => (transform, inliers),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Vector3, Vector4};
+    use pcc_common::{point::Point3, robust::Trimmed};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    fn source() -> PointCloud<Point3> {
+        let storage = { 0..12 }
+            .map(|i| {
+                let (x, y, z) = (
+                    (i % 4) as f32,
+                    (i / 4) as f32 * 1.5,
+                    ((i * i) % 5) as f32 * 0.7,
+                );
+                Point3::default().with_coords(Vector4::new(x, y, z, 1.))
+            })
+            .collect::<Vec<_>>();
+        PointCloud::from_vec(storage, 12)
+    }
+
+    #[test]
+    fn test_compute_weighted() {
+        let source = source();
+        // The first copy of the source is the one most correspondences agree
+        // with, and the second one is the one the heavy correspondences agree
+        // with.
+        let (light, heavy) = (Vector3::new(1., 0., 0.), Vector3::new(0., 2., 0.));
+        let storage = { [light, heavy].into_iter() }
+            .flat_map(|t| source.iter().map(move |point| point.coords().xyz() + t))
+            .map(|coords| Point3::default().with_coords(coords.insert_row(3, 1.)))
+            .collect::<Vec<_>>();
+        let target = PointCloud::from_vec(storage, 24);
+
+        let correspondences = { (0..12).map(|i| Correspondence::new(i, i, 0.)) }
+            .chain((0..6).map(|i| Correspondence::new(i, 12 + i, 0.)))
+            .collect::<Vec<_>>();
+        let weights = { (0..18).map(|i| if i < 12 { 0.1 } else { 1. }) }.collect::<Vec<_>>();
+
+        let mut ransac = RansacAlignment::new(200, 0.01, StdRng::seed_from_u64(0));
+        let (transform, inliers) = ransac.compute(&source, &target, &correspondences).unwrap();
+        assert!((transform.translation.vector - light).norm() < 1e-4);
+        assert_eq!(inliers.len(), 12);
+
+        let (transform, inliers) = ransac
+            .compute_weighted(&source, &target, &correspondences, &weights)
+            .unwrap();
+        assert!((transform.translation.vector - heavy).norm() < 1e-4);
+        assert_eq!(inliers, (12..18).collect::<Vec<_>>());
+        assert!(transform.rotation.angle() < 1e-4);
+    }
+
+    #[test]
+    fn test_kernel() {
+        let source = source();
+        // 2 of the targets are biased along +Z within the inlier threshold.
+        let storage = { source.iter().enumerate() }
+            .map(|(i, point)| {
+                let bias = if i % 5 == 3 { 0.04 } else { 0. };
+                point.coords() + Vector4::new(1., 0., bias, 0.)
+            })
+            .map(|coords| Point3::default().with_coords(coords))
+            .collect::<Vec<_>>();
+        let target = PointCloud::from_vec(storage, 12);
+        let correspondences =
+            { (0..12).map(|i| Correspondence::new(i, i, 0.)) }.collect::<Vec<_>>();
+
+        let truth = Vector3::new(1., 0., 0.);
+        let mut ransac = RansacAlignment::new(200, 0.05, StdRng::seed_from_u64(0));
+        let (transform, inliers) = ransac.compute(&source, &target, &correspondences).unwrap();
+        assert_eq!(inliers.len(), 12);
+        assert!((transform.translation.vector - truth).norm() > 5e-3);
+
+        let mut ransac = ransac.with_kernel(Trimmed::new(0.75));
+        let (transform, inliers) = ransac.compute(&source, &target, &correspondences).unwrap();
+        assert_eq!(inliers.len(), 12);
+        assert!((transform.translation.vector - truth).norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_similarity() {
+        let ransac = RansacAlignment::new(200, 10., StdRng::seed_from_u64(0));
+        let (a, b, c) = (Vector4::new(0., 0., 0., 1.), Vector4::x(), Vector4::y());
+        let (b2, c2) = (
+            Vector4::new(1.05, 0., 0., 0.),
+            Vector4::new(0., 1.2, 0., 0.),
+        );
+        assert!(ransac.is_similar(&[(&a, &a), (&b, &b2), (&c, &c2)]));
+        let mut ransac = ransac.with_similarity(0.9);
+        assert!(ransac.is_similar(&[(&a, &a), (&b, &b2), (&c, &c)]));
+        assert!(!ransac.is_similar(&[(&a, &a), (&b, &b2), (&c, &c2)]));
+
+        // No triangle of the target scaled up from the source is similar
+        // enough, though all of them are inliers within the loose threshold.
+        let source = source();
+        let storage = { source.iter() }
+            .map(|point| {
+                Point3::default().with_coords((point.coords().xyz() * 1.5).insert_row(3, 1.))
+            })
+            .collect::<Vec<_>>();
+        let target = PointCloud::from_vec(storage, 12);
+        let correspondences =
+            { (0..12).map(|i| Correspondence::new(i, i, 0.)) }.collect::<Vec<_>>();
+        assert_eq!(ransac.compute(&source, &target, &correspondences), None);
+        ransac.similarity = Some(0.6);
+        let (_, inliers) = ransac.compute(&source, &target, &correspondences).unwrap();
+        assert_eq!(inliers.len(), 12);
+    }
+}
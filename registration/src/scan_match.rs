@@ -0,0 +1,358 @@
+use std::cmp::Ordering;
+
+use nalgebra::{Isometry2, Point2, RealField, Scalar, Vector2};
+use num::ToPrimitive;
+use pcc_common::{
+    point::Point,
+    point_cloud::{AsPointCloud, PointCloud},
+};
+
+/// A 2-D lookup table of the likelihood of hitting every cell, built from the
+/// points of a reference scan projected onto the XY plane.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OccupancyGrid<T: Scalar> {
+    origin: Vector2<T>,
+    resolution: T,
+    width: usize,
+    height: usize,
+    cells: Vec<T>,
+}
+
+impl<T: RealField + ToPrimitive + Copy> OccupancyGrid<T> {
+    /// Every cell takes the Gaussian of `stddev` of its distance to the
+    /// nearest reference point. Returns `None` if the reference scan has no
+    /// finite points.
+    pub fn new<P: Point<Data = T>>(
+        reference: &PointCloud<P>,
+        resolution: T,
+        stddev: T,
+    ) -> Option<Self> {
+        let [min, max] = reference.finite_bound()?;
+        let three = T::from_u8(3).unwrap();
+        let radius = (stddev * three / resolution).ceil().to_isize().unwrap_or(0);
+        let margin = T::from_isize(radius + 1).unwrap() * resolution;
+
+        let origin = min.xy() - Vector2::repeat(margin);
+        let size = (max.xy() - min.xy()).map(|x| {
+            ((x + margin * (T::one() + T::one())) / resolution)
+                .ceil()
+                .to_usize()
+                .unwrap()
+                + 1
+        });
+        let (width, height) = (size.x, size.y);
+
+        let mut grid = OccupancyGrid {
+            origin,
+            resolution,
+            width,
+            height,
+            cells: vec![T::zero(); width * height],
+        };
+        let var = stddev * stddev;
+        for point in reference.iter().filter(|point| point.is_finite()) {
+            let coords = point.coords().xy();
+            let [cx, cy] = grid.cell(&coords);
+            for y in (cy - radius)..=(cy + radius) {
+                for x in (cx - radius)..=(cx + radius) {
+                    if let Some(index) = grid.index(x, y) {
+                        let center = grid.center(x, y);
+                        let d2 = (center - coords).norm_squared();
+                        let likelihood = if var > T::zero() {
+                            (-d2 / (var + var)).exp()
+                        } else {
+                            T::one()
+                        };
+                        if likelihood > grid.cells[index] {
+                            grid.cells[index] = likelihood;
+                        }
+                    }
+                }
+            }
+        }
+        Some(grid)
+    }
+
+    #[inline]
+    pub fn resolution(&self) -> T {
+        self.resolution
+    }
+
+    /// The cell containing `coords`, which may be out of the grid.
+    pub fn cell(&self, coords: &Vector2<T>) -> [isize; 2] {
+        let cell = (coords - self.origin) / self.resolution;
+        [cell.x, cell.y].map(|x| x.floor().to_isize().unwrap_or(isize::MIN / 2))
+    }
+
+    fn center(&self, x: isize, y: isize) -> Vector2<T> {
+        let half = T::from_f64(0.5).unwrap();
+        let cell = Vector2::new(T::from_isize(x).unwrap(), T::from_isize(y).unwrap());
+        self.origin + (cell + Vector2::repeat(half)) * self.resolution
+    }
+
+    fn index(&self, x: isize, y: isize) -> Option<usize> {
+        let inside =
+            (0..self.width as isize).contains(&x) && (0..self.height as isize).contains(&y);
+        inside.then(|| y as usize * self.width + x as usize)
+    }
+
+    /// The likelihood of the cell `(x, y)`, or zero out of the grid.
+    pub fn get(&self, x: isize, y: isize) -> T {
+        self.index(x, y)
+            .map_or(T::zero(), |index| self.cells[index])
+    }
+
+    /// Takes the maximum of every block of `size * size` cells starting at
+    /// every cell, including those out of the grid that cover some of it.
+    fn max_pooled(&self, size: usize) -> PooledGrid<T> {
+        let pad = size as isize - 1;
+        let width = self.width + size - 1;
+        let height = self.height + size - 1;
+
+        let mut rows = vec![T::zero(); width * self.height];
+        for y in 0..self.height as isize {
+            for x in 0..width as isize {
+                let value = { (0..size as isize).map(|dx| self.get(x - pad + dx, y)) }
+                    .fold(T::zero(), |a, b| a.max(b));
+                rows[y as usize * width + x as usize] = value;
+            }
+        }
+
+        let mut cells = vec![T::zero(); width * height];
+        for y in 0..height as isize {
+            for x in 0..width {
+                let value = { (0..size as isize).map(|dy| y - pad + dy) }
+                    .filter(|y| (0..self.height as isize).contains(y))
+                    .map(|y| rows[y as usize * width + x])
+                    .fold(T::zero(), |a, b| a.max(b));
+                cells[y as usize * width + x] = value;
+            }
+        }
+
+        PooledGrid { pad, width, cells }
+    }
+}
+
+struct PooledGrid<T> {
+    pad: isize,
+    width: usize,
+    cells: Vec<T>,
+}
+
+impl<T: RealField + Copy> PooledGrid<T> {
+    fn get(&self, x: isize, y: isize) -> T {
+        let (x, y) = (x + self.pad, y + self.pad);
+        let height = (self.cells.len() / self.width) as isize;
+        if (0..self.width as isize).contains(&x) && (0..height).contains(&y) {
+            self.cells[y as usize * self.width + x as usize]
+        } else {
+            T::zero()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Candidate<T> {
+    score: T,
+    angle: usize,
+    offset: [isize; 2],
+}
+
+/// Matches planar scans against an [`OccupancyGrid`] by exhaustively
+/// correlating all the poses within a search window around the initial pose,
+/// accelerated with branch and bound over `levels` of max-pooled grids.
+///
+/// The scans are projected onto the XY plane, and the score of a pose is the
+/// mean likelihood of the cells hit by the scan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorrelativeMatcher<T> {
+    /// The half size of the translational search window.
+    pub linear_window: T,
+    /// The half size of the angular search window in radians.
+    pub angular_window: T,
+    pub angular_step: T,
+    pub levels: usize,
+    /// Poses that don't score higher than it are rejected.
+    pub min_score: T,
+}
+
+impl<T> CorrelativeMatcher<T> {
+    pub fn new(
+        linear_window: T,
+        angular_window: T,
+        angular_step: T,
+        levels: usize,
+        min_score: T,
+    ) -> Self {
+        CorrelativeMatcher {
+            linear_window,
+            angular_window,
+            angular_step,
+            levels,
+            min_score,
+        }
+    }
+}
+
+impl<T: RealField + ToPrimitive + Copy> CorrelativeMatcher<T> {
+    fn score(cells: &[[isize; 2]], offset: [isize; 2], f: impl Fn(isize, isize) -> T) -> T {
+        let sum = { cells.iter() }
+            .map(|[x, y]| f(x + offset[0], y + offset[1]))
+            .fold(T::zero(), |a, b| a + b);
+        sum / T::from_usize(cells.len()).unwrap()
+    }
+
+    /// Returns the best pose of `scan` in the frame of `grid` and its score,
+    /// or `None` if no pose in the window scores higher than `min_score`.
+    pub fn compute<P: Point<Data = T>>(
+        &self,
+        grid: &OccupancyGrid<T>,
+        scan: &PointCloud<P>,
+        initial: &Isometry2<T>,
+    ) -> Option<(Isometry2<T>, T)> {
+        let points = { scan.iter().filter(|point| point.is_finite()) }
+            .map(|point| Point2::from(point.coords().xy()))
+            .collect::<Vec<_>>();
+        if points.is_empty() {
+            return None;
+        }
+
+        let num_angles = if self.angular_step > T::zero() {
+            (self.angular_window / self.angular_step)
+                .floor()
+                .to_isize()
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        let angles = { (-num_angles..=num_angles).map(|k| T::from_isize(k).unwrap()) }
+            .map(|k| k * self.angular_step)
+            .collect::<Vec<_>>();
+        let cells = { angles.iter() }
+            .map(|&angle| {
+                let pose = initial * Isometry2::new(Vector2::zeros(), angle);
+                { points.iter() }
+                    .map(|point| grid.cell(&(pose * point).coords))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let window = { (self.linear_window / grid.resolution).ceil() }
+            .to_isize()
+            .unwrap_or(0)
+            .max(0);
+        let levels = self.levels.max(1);
+        let pooled = { (0..levels).map(|level| grid.max_pooled(1 << level)) }.collect::<Vec<_>>();
+
+        let top = levels - 1;
+        let step = 1 << top;
+        let mut candidates = Vec::new();
+        for (angle, cells) in cells.iter().enumerate() {
+            for oy in (-window..=window).step_by(step) {
+                for ox in (-window..=window).step_by(step) {
+                    let offset = [ox, oy];
+                    let score = Self::score(cells, offset, |x, y| pooled[top].get(x, y));
+                    candidates.push(Candidate {
+                        score,
+                        angle,
+                        offset,
+                    });
+                }
+            }
+        }
+
+        let mut best = None;
+        self.branch(&cells, &pooled, window, top, candidates, &mut best);
+
+        best.map(
+            |Candidate {
+                 score,
+                 angle,
+                 offset,
+             }| {
+                let translation = Vector2::new(
+                    T::from_isize(offset[0]).unwrap(),
+                    T::from_isize(offset[1]).unwrap(),
+                ) * grid.resolution;
+                let pose = Isometry2::new(translation, T::zero())
+                    * initial
+                    * Isometry2::new(Vector2::zeros(), angles[angle]);
+                (pose, score)
+            },
+        )
+    }
+
+    fn branch(
+        &self,
+        cells: &[Vec<[isize; 2]>],
+        pooled: &[PooledGrid<T>],
+        window: isize,
+        level: usize,
+        mut candidates: Vec<Candidate<T>>,
+        best: &mut Option<Candidate<T>>,
+    ) {
+        candidates.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+
+        for candidate in candidates {
+            let min_score = best.as_ref().map_or(self.min_score, |best| best.score);
+            if candidate.score <= min_score {
+                break;
+            }
+            if level == 0 {
+                *best = Some(candidate);
+                continue;
+            }
+
+            let half = 1 << (level - 1);
+            let children = [[0, 0], [half, 0], [0, half], [half, half]]
+                .into_iter()
+                .map(|[dx, dy]| [candidate.offset[0] + dx, candidate.offset[1] + dy])
+                .filter(|offset| offset.iter().all(|&o| o <= window))
+                .map(|offset| Candidate {
+                    score: Self::score(&cells[candidate.angle], offset, |x, y| {
+                        pooled[level - 1].get(x, y)
+                    }),
+                    angle: candidate.angle,
+                    offset,
+                })
+                .collect();
+            self.branch(cells, pooled, window, level - 1, children, best);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Isometry2, Isometry3, Vector2, Vector3, Vector4};
+    use pcc_common::{
+        point::{Point, Point3},
+        point_cloud::PointCloud,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_correlative() {
+        let storage = { (0..100).map(|i| i as f32 * 0.05) }
+            .flat_map(|t| [[t, 0.], [0., t], [t, 3.], [5., t * 0.6]])
+            .map(|[x, y]| Point3::default().with_coords(Vector4::new(x, y, 0., 1.)))
+            .collect::<Vec<_>>();
+        let reference = PointCloud::from_vec(storage, 1);
+        let grid = OccupancyGrid::new(&reference, 0.05, 0.05).unwrap();
+
+        let truth = Isometry2::new(Vector2::new(0.2, -0.15), 0.04);
+        let truth3 = Isometry3::new(Vector3::new(0.2, -0.15, 0.), Vector3::new(0., 0., 0.04));
+        let mut scan = PointCloud::new();
+        reference.transform(&truth3.inverse().to_homogeneous(), &mut scan);
+
+        let matcher = CorrelativeMatcher::new(0.4, 0.1, 0.01, 4, 0.5);
+        let (pose, score) = matcher
+            .compute(&grid, &scan, &Isometry2::identity())
+            .unwrap();
+
+        let error = pose * truth.inverse();
+        assert!(error.translation.vector.norm() < 0.06);
+        assert!(error.rotation.angle().abs() < 0.015);
+        assert!(score > 0.5);
+    }
+}
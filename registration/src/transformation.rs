@@ -0,0 +1,100 @@
+use nalgebra::{
+    Isometry3, Matrix3, RealField, Rotation3, Translation3, UnitQuaternion, Vector3, Vector4,
+};
+
+/// Estimates the rigid transformation that best maps the source points onto
+/// their paired target points in the least-squares sense, using the SVD-based
+/// method of Kabsch/Umeyama.
+///
+/// Returns `None` if fewer than 3 pairs are given or the decomposition fails.
+pub fn rigid_transform<'a, T, I>(pairs: I) -> Option<Isometry3<T>>
+where
+    T: RealField + Copy,
+    I: IntoIterator<Item = (&'a Vector4<T>, &'a Vector4<T>)>,
+{
+    rigid_transform_weighted(pairs.into_iter().map(|(s, t)| (s, t, T::one())))
+}
+
+/// Like [`rigid_transform`], but every pair contributes to the least-squares
+/// error in proportion to its weight. Pairs with non-positive weights are
+/// ignored.
+pub fn rigid_transform_weighted<'a, T, I>(pairs: I) -> Option<Isometry3<T>>
+where
+    T: RealField + Copy,
+    I: IntoIterator<Item = (&'a Vector4<T>, &'a Vector4<T>, T)>,
+{
+    let pairs = { pairs.into_iter() }
+        .filter(|(_, _, weight)| *weight > T::zero())
+        .map(|(source, target, weight)| (source.xyz(), target.xyz(), weight))
+        .collect::<Vec<_>>();
+    if pairs.len() < 3 {
+        return None;
+    }
+
+    let (source_sum, target_sum, weight_sum) = pairs.iter().fold(
+        (Vector3::zeros(), Vector3::zeros(), T::zero()),
+        |(ss, ts, ws), (source, target, weight)| {
+            (ss + source * *weight, ts + target * *weight, ws + *weight)
+        },
+    );
+    let source_centroid = source_sum / weight_sum;
+    let target_centroid = target_sum / weight_sum;
+
+    let covariance = pairs
+        .iter()
+        .fold(Matrix3::zeros(), |acc, (source, target, weight)| {
+            acc + (source - source_centroid) * (target - target_centroid).transpose() * *weight
+        });
+
+    let svd = covariance.try_svd(true, true, T::default_epsilon(), 0)?;
+    let (u, v_t) = (svd.u?, svd.v_t?);
+    let mut v = v_t.transpose();
+    if (v * u.transpose()).determinant() < T::zero() {
+        v.column_mut(2).neg_mut();
+    }
+    let rotation = Rotation3::from_matrix_unchecked(v * u.transpose());
+
+    let translation = target_centroid - rotation * source_centroid;
+    Some(Isometry3::from_parts(
+        Translation3::from(translation),
+        UnitQuaternion::from_rotation_matrix(&rotation),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rigid_transform_weighted() {
+        let truth = Isometry3::new(Vector3::new(0.5, -1., 2.), Vector3::new(0.1, 0.3, -0.2));
+        let source = [
+            Vector3::new(0., 0., 0.),
+            Vector3::new(1., 0., 0.),
+            Vector3::new(0., 2., 0.),
+            Vector3::new(0., 0., 3.),
+            Vector3::new(1., 1., 1.),
+        ]
+        .map(|x| x.insert_row(3, 1.));
+        let mut target = source.map(|x| {
+            (truth * nalgebra::Point3::from(x.xyz()))
+                .coords
+                .insert_row(3, 1.)
+        });
+        let weights = [0.5, 2., 1., 3., 0.];
+        // The last pair is an outlier without any weight.
+        target[4] += Vector4::new(10., -5., 3., 0.);
+
+        let pairs = source.iter().zip(&target).zip(weights);
+        let transform = rigid_transform_weighted(pairs.map(|((s, t), w)| (s, t, w))).unwrap();
+        assert!((transform.translation.vector - truth.translation.vector).norm() < 1e-4);
+        assert!(transform.rotation.angle_to(&truth.rotation) < 1e-4);
+
+        // Not enough pairs with positive weights.
+        let pairs = source.iter().zip(&target).zip([1., 1., 0., -1., 0.]);
+        assert_eq!(
+            rigid_transform_weighted(pairs.map(|((s, t), w)| (s, t, w))),
+            None
+        );
+    }
+}
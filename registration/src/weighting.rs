@@ -0,0 +1,142 @@
+use nalgebra::RealField;
+use pcc_common::{
+    point::{Point, PointIntensity},
+    point_cloud::PointCloud,
+    robust::RobustKernel,
+};
+
+use crate::Correspondence;
+
+/// Weighs the correspondences for the estimation of transformations, like in
+/// the iterations of ICP. A weight of zero rejects the correspondence.
+pub trait CorrespondenceWeigher<P: Point> {
+    fn weigh(&self, source: &P, target: &P, correspondence: &Correspondence<P::Data>) -> P::Data;
+}
+
+impl<P, F> CorrespondenceWeigher<P> for F
+where
+    P: Point,
+    F: Fn(&P, &P, &Correspondence<P::Data>) -> P::Data,
+{
+    #[inline]
+    fn weigh(&self, source: &P, target: &P, correspondence: &Correspondence<P::Data>) -> P::Data {
+        self(source, target, correspondence)
+    }
+}
+
+/// Weighs the correspondences between [`PointIntensity`] points, like LiDAR
+/// returns, by the Gaussian of `stddev` of their intensity difference, and
+/// rejects those whose difference exceeds `max_difference`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct IntensityWeigher<T> {
+    pub stddev: T,
+    pub max_difference: T,
+}
+
+impl<T> IntensityWeigher<T> {
+    pub fn new(stddev: T, max_difference: T) -> Self {
+        IntensityWeigher {
+            stddev,
+            max_difference,
+        }
+    }
+}
+
+impl<T, P> CorrespondenceWeigher<P> for IntensityWeigher<T>
+where
+    T: RealField,
+    P: PointIntensity<Data = T>,
+{
+    fn weigh(&self, source: &P, target: &P, _: &Correspondence<T>) -> T {
+        let difference = (source.intensity() - target.intensity()).abs();
+        if !(difference <= self.max_difference) {
+            return T::zero();
+        }
+        if self.stddev <= T::zero() {
+            return T::one();
+        }
+
+        let var = self.stddev.clone() * self.stddev.clone();
+        (-difference.clone() * difference / (var.clone() + var)).exp()
+    }
+}
+
+/// Weighs `correspondences` between `source` and `target` with `weigher`,
+/// dropping the rejected ones, for [`rigid_transform_weighted`].
+///
+/// [`rigid_transform_weighted`]: crate::rigid_transform_weighted
+pub fn weigh_correspondences<T, P, W>(
+    source: &PointCloud<P>,
+    target: &PointCloud<P>,
+    correspondences: &[Correspondence<T>],
+    weigher: &W,
+) -> Vec<(Correspondence<T>, T)>
+where
+    T: RealField,
+    P: Point<Data = T>,
+    W: CorrespondenceWeigher<P> + ?Sized,
+{
+    { correspondences.iter() }
+        .map(|corr| {
+            let weight = weigher.weigh(&source[corr.source], &target[corr.target], corr);
+            (corr.clone(), weight)
+        })
+        .filter(|(_, weight)| *weight > T::zero())
+        .collect()
+}
+
+/// Scales the weights of `weighted` correspondences by `kernel` applied to
+/// their distances, the residuals of ICP, and drops the rejected ones.
+pub fn reweigh_correspondences<T, K>(weighted: &mut Vec<(Correspondence<T>, T)>, kernel: &K)
+where
+    T: RealField,
+    K: RobustKernel<T> + ?Sized,
+{
+    let residuals = { weighted.iter() }
+        .map(|(corr, _)| corr.distance.clone())
+        .collect::<Vec<_>>();
+    let weights = kernel.weights(&residuals);
+
+    for ((_, weight), robust) in weighted.iter_mut().zip(weights) {
+        *weight *= robust;
+    }
+    weighted.retain(|(_, weight)| *weight > T::zero());
+}
+
+#[cfg(test)]
+mod tests {
+    use pcc_common::point::Point3IR;
+
+    use super::*;
+
+    #[test]
+    fn test_intensity_weigher() {
+        let point = |intensity| Point3IR::default().with_intensity(intensity);
+        let corr = Correspondence::new(0, 0, 0.);
+        let weigher = IntensityWeigher::new(2., 5.);
+
+        assert_eq!(weigher.weigh(&point(10.), &point(10.), &corr), 1.);
+        // exp(-3^2 / (2 * 2^2))
+        let weight = weigher.weigh(&point(10.), &point(13.), &corr);
+        assert!((weight - (-9f32 / 8.).exp()).abs() < 1e-6);
+        assert_eq!(weight, weigher.weigh(&point(13.), &point(10.), &corr));
+
+        assert!(weigher.weigh(&point(10.), &point(15.), &corr) > 0.);
+        assert_eq!(weigher.weigh(&point(10.), &point(15.5), &corr), 0.);
+        assert_eq!(weigher.weigh(&point(f32::NAN), &point(10.), &corr), 0.);
+
+        let source = PointCloud::from_vec(vec![point(1.), point(2.), point(3.)], 1);
+        let target = PointCloud::from_vec(vec![point(1.), point(9.)], 1);
+        let correspondences = [
+            Correspondence::new(0, 0, 0.),
+            Correspondence::new(1, 1, 0.),
+            Correspondence::new(2, 0, 0.),
+        ];
+        let weighted = weigh_correspondences(&source, &target, &correspondences, &weigher);
+        let sources = weighted
+            .iter()
+            .map(|(corr, _)| corr.source)
+            .collect::<Vec<_>>();
+        assert_eq!(sources, [0, 2]);
+    }
+}
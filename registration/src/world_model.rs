@@ -0,0 +1,247 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use nalgebra::{Isometry3, RealField, Vector4};
+use num::ToPrimitive;
+use pcc_common::{
+    point::Point,
+    point_cloud::{PointCloud, PoseProvider},
+};
+
+/// A global map that accumulates registered frames into hashed voxels.
+///
+/// Every voxel keeps at most `max_points` points, replacing its oldest ones
+/// with newer ones, and points older than `lifetime` are dropped by
+/// [`WorldModel::decay`].
+#[derive(Debug, Clone)]
+pub struct WorldModel<P: Point>
+where
+    P::Data: RealField,
+{
+    pub voxel_size: P::Data,
+    pub max_points: usize,
+    pub lifetime: Option<P::Data>,
+    voxels: HashMap<[i64; 3], Vec<(P, P::Data)>>,
+}
+
+impl<T: RealField + ToPrimitive + Copy, P: Point<Data = T>> WorldModel<P> {
+    pub fn new(voxel_size: P::Data, max_points: usize, lifetime: Option<P::Data>) -> Self {
+        WorldModel {
+            voxel_size,
+            max_points,
+            lifetime,
+            voxels: HashMap::new(),
+        }
+    }
+
+    fn key(&self, coords: &Vector4<P::Data>) -> Option<[i64; 3]> {
+        let mut key = [0; 3];
+        for (k, x) in key.iter_mut().zip(coords.iter()) {
+            *k = (*x / self.voxel_size).floor().to_i64()?;
+        }
+        Some(key)
+    }
+
+    /// Adds the finite points of `frame` in the sensor frame, registered at
+    /// `pose` in the world frame, and returns the number of added points.
+    pub fn insert(
+        &mut self,
+        frame: &PointCloud<P>,
+        pose: &Isometry3<P::Data>,
+        timestamp: P::Data,
+    ) -> usize {
+        if self.max_points == 0 {
+            return 0;
+        }
+
+        let mut num = 0;
+        for point in frame.iter().filter(|point| point.is_finite()) {
+            let point = point.clone().with_na_point(pose * point.na_point());
+            let key = match self.key(point.coords()) {
+                Some(key) => key,
+                None => continue,
+            };
+            let voxel = self.voxels.entry(key).or_default();
+            if voxel.len() < self.max_points {
+                voxel.push((point, timestamp));
+            } else {
+                let oldest = { voxel.iter_mut() }
+                    .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+                match oldest {
+                    Some(oldest) if oldest.1 < timestamp => *oldest = (point, timestamp),
+                    _ => continue,
+                }
+            }
+            num += 1;
+        }
+        num
+    }
+
+    /// Like [`WorldModel::insert`], but registered at the pose at `timestamp`
+    /// from `poses`, or `None` if the pose is unknown.
+    pub fn insert_timed<Pp>(
+        &mut self,
+        frame: &PointCloud<P>,
+        poses: &Pp,
+        timestamp: P::Data,
+    ) -> Option<usize>
+    where
+        Pp: PoseProvider<P::Data> + ?Sized,
+    {
+        let pose = poses.pose(timestamp)?;
+        Some(self.insert(frame, &pose, timestamp))
+    }
+
+    /// Drops the points older than `lifetime` at `now`, and returns the
+    /// number of dropped points.
+    pub fn decay(&mut self, now: P::Data) -> usize {
+        let lifetime = match self.lifetime {
+            Some(lifetime) => lifetime,
+            None => return 0,
+        };
+
+        let mut num = 0;
+        self.voxels.retain(|_, voxel| {
+            let len = voxel.len();
+            voxel.retain(|(_, timestamp)| now - *timestamp <= lifetime);
+            num += len - voxel.len();
+            !voxel.is_empty()
+        });
+        num
+    }
+
+    /// Extracts the points within `radius` of `center`, e.g. as the target of
+    /// the next registration.
+    pub fn submap(&self, center: &Vector4<P::Data>, radius: P::Data) -> PointCloud<P> {
+        let offset = Vector4::new(radius, radius, radius, T::zero());
+        let range = self
+            .key(&(center - offset))
+            .zip(self.key(&(center + offset)));
+        let num_keys = range.and_then(|(min, max)| {
+            { min.iter().zip(max.iter()) }.try_fold(1usize, |acc, (min, max)| {
+                let len = max.checked_sub(*min)?.checked_add(1)?.max(0);
+                acc.checked_mul(len.to_usize()?)
+            })
+        });
+
+        // Looks up the keys covered by the radius, unless there are more of
+        // them than the voxels in the map, e.g. with a large radius.
+        let voxels = match (range, num_keys) {
+            (Some((min, max)), Some(num_keys)) if num_keys <= self.voxels.len() => {
+                { min[0]..=max[0] }
+                    .flat_map(|x| (min[1]..=max[1]).map(move |y| (x, y)))
+                    .flat_map(|(x, y)| (min[2]..=max[2]).map(move |z| [x, y, z]))
+                    .filter_map(|key| self.voxels.get(&key))
+                    .collect::<Vec<_>>()
+            }
+            _ => self.voxels.values().collect(),
+        };
+
+        let storage = { voxels.into_iter().flatten() }
+            .filter(|(point, _)| (point.coords() - center).xyz().norm() <= radius)
+            .map(|(point, _)| point.clone())
+            .collect::<Vec<_>>();
+        PointCloud::from_vec(storage, 1)
+    }
+
+    /// Returns all the points of the map.
+    pub fn to_point_cloud(&self) -> PointCloud<P> {
+        let storage = { self.voxels.values().flatten() }
+            .map(|(point, _)| point.clone())
+            .collect::<Vec<_>>();
+        PointCloud::from_vec(storage, 1)
+    }
+
+    /// The number of points in the map.
+    pub fn len(&self) -> usize {
+        self.voxels.values().map(Vec::len).sum()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.voxels.is_empty()
+    }
+
+    #[inline]
+    pub fn num_voxels(&self) -> usize {
+        self.voxels.len()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.voxels.clear()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use nalgebra::{Isometry3, Vector3, Vector4};
+    use pcc_common::{
+        point::{Point, Point3},
+        point_cloud::PointCloud,
+    };
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_world_model() {
+        let frame = PointCloud::from_vec(
+            vec![
+                Point3::default().with_coords(Vector4::new(0.1, 0.1, 0.1, 1.)),
+                Point3::default().with_coords(Vector4::new(0.2, 0.2, 0.2, 1.)),
+                Point3::default().with_coords(Vector4::new(0.3, 0.3, 0.3, 1.)),
+                Point3::default().with_coords(Vector4::new(f32::NAN, 0., 0., 1.)),
+            ],
+            1,
+        );
+
+        let mut world = WorldModel::new(1., 2, Some(1.5));
+        assert_eq!(world.insert(&frame, &Isometry3::identity(), 0.), 2);
+
+        let pose = Isometry3::new(Vector3::new(5., 0., 0.), Vector3::zeros());
+        assert_eq!(world.insert(&frame, &pose, 1.), 2);
+        assert_eq!((world.len(), world.num_voxels()), (4, 2));
+
+        let submap = world.submap(&Vector4::new(5., 0., 0., 1.), 1.);
+        assert_eq!(submap.len(), 2);
+
+        assert_eq!(world.decay(2.), 2);
+        assert_eq!((world.len(), world.num_voxels()), (2, 1));
+
+        assert_eq!(world.insert(&frame, &pose, 2.), 2);
+        assert_eq!(world.len(), 2);
+    }
+
+    #[test]
+    fn test_submap() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let storage = { iter::repeat_with(|| Vector3::from_fn(|_, _| rng.gen_range(-5f32..5.))) }
+            .map(|v| Point3::default().with_coords(v.insert_row(3, 1.)))
+            .take(1000)
+            .collect::<Vec<_>>();
+        let frame = PointCloud::from_vec(storage, 1);
+
+        let mut world = WorldModel::new(0.5, usize::MAX, None);
+        assert_eq!(world.insert(&frame, &Isometry3::identity(), 0.), 1000);
+
+        let center = Vector4::new(-0.3, 1.2, 0.7, 1.);
+        let within = |radius: f32| {
+            { frame.iter() }
+                .filter(|point| (point.coords() - center).xyz().norm() <= radius)
+                .count()
+        };
+        // Both looking up the keys covered by the radius and walking all the
+        // voxels for larger radii.
+        for radius in [0., 0.4, 1., 2.5, 20., f32::INFINITY] {
+            let submap = world.submap(&center, radius);
+            assert_eq!(submap.len(), within(radius), "radius {radius}");
+            assert!(submap
+                .iter()
+                .all(|point| (point.coords() - center).xyz().norm() <= radius));
+        }
+        assert!(world.submap(&center, -1.).is_empty());
+        assert!(world.submap(&center, f32::NAN).is_empty());
+    }
+}
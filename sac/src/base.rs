@@ -1,8 +1,12 @@
 mod arrsac;
 
 pub use arrsac::Arrsac;
-use nalgebra::{Scalar, Vector4};
-use pcc_common::{point::Point, point_cloud::PointCloud};
+use nalgebra::{RealField, Scalar, Vector4};
+use pcc_common::{
+    point::{Point, PointNormal},
+    point_cloud::PointCloud,
+};
+use rand::RngCore;
 use sample_consensus::{Consensus, Estimator, Model};
 
 pub struct PcSac<'a, P, C> {
@@ -29,6 +33,66 @@ impl<'a, T: Scalar, P: Point<Data = T>, C> PcSac<'a, P, C> {
             self.point_cloud.iter().map(|point| point.coords().clone()),
         )
     }
+
+    /// Like [`PcSac::compute`], but feeds the estimator with the normals of
+    /// the points as well, e.g. for
+    /// [`NormalSphereEstimator`](crate::NormalSphereEstimator).
+    pub fn compute_with_normals<E: Estimator<(Vector4<T>, Vector4<T>)>>(
+        &mut self,
+        estimator: &E,
+    ) -> Option<(E::Model, C::Inliers)>
+    where
+        P: PointNormal,
+        C: Consensus<E, (Vector4<T>, Vector4<T>)>,
+    {
+        self.inner.model_inliers(
+            estimator,
+            { self.point_cloud.iter() }
+                .map(|point| (point.coords().clone(), point.normal().clone())),
+        )
+    }
+}
+
+impl<'a, T, P, R> PcSac<'a, P, Arrsac<R, T>>
+where
+    T: num::Float + RealField,
+    P: Point<Data = T>,
+    R: RngCore,
+{
+    /// Like [`PcSac::compute`], but scores the models in parallel for large
+    /// point clouds. See [`Arrsac::model_inliers_par`] for details.
+    pub fn compute_par<E>(&mut self, estimator: &E) -> Option<(E::Model, Vec<usize>)>
+    where
+        E: Estimator<Vector4<T>>,
+        E::Model: Sync,
+    {
+        let coords = { self.point_cloud.iter() }
+            .map(|point| point.coords().clone())
+            .collect::<Vec<_>>();
+        self.inner.model_inliers_par(estimator, &coords)
+    }
+
+    /// Like [`PcSac::compute`], but scores the models by the sum of the
+    /// `weights` of their inliers. See [`Arrsac::model_inliers_weighted`] for
+    /// details.
+    ///
+    /// For example, the weights can be the inverse variances of the points,
+    /// as the noise grows with the range for most sensors.
+    pub fn compute_weighted<E>(
+        &mut self,
+        estimator: &E,
+        weights: &[T],
+    ) -> Option<(E::Model, Vec<usize>)>
+    where
+        E: Estimator<Vector4<T>>,
+        E::Model: Sync,
+    {
+        let coords = { self.point_cloud.iter() }
+            .map(|point| point.coords().clone())
+            .collect::<Vec<_>>();
+        self.inner
+            .model_inliers_weighted(estimator, &coords, weights)
+    }
 }
 
 pub trait SacModel<Data>: Model<Data> {
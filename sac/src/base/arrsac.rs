@@ -1,10 +1,15 @@
 //！Copied and modified from github@rust-cv/arrsac.
 
-use core::cmp::Reverse;
+use core::{
+    cmp::{Ordering, Reverse},
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering::Relaxed},
+};
 
 use nalgebra::{RealField, Scalar};
 use num::FromPrimitive;
 use rand::RngCore;
+use rayon::prelude::*;
 use sample_consensus::{Consensus, Estimator, Model};
 
 /// The ARRSAC algorithm for sample consensus.
@@ -21,10 +26,14 @@ pub struct Arrsac<R, T: Scalar> {
     block_size: usize,
     likelihood_ratio_threshold: T,
     inlier_threshold: T,
+    parallel_threshold: usize,
     rng: R,
     random_samples: Vec<u32>,
 }
 
+/// The number of data points scored at a time in the parallel path.
+const PARALLEL_CHUNK: usize = 4096;
+
 impl<R, T: Scalar + FromPrimitive> Arrsac<R, T>
 where
     R: RngCore,
@@ -63,6 +72,7 @@ where
             block_size: 64,
             likelihood_ratio_threshold: T::from_f32(1e3).unwrap(),
             inlier_threshold,
+            parallel_threshold: 1 << 16,
             rng,
             random_samples: vec![],
         }
@@ -160,6 +170,18 @@ where
             ..self
         }
     }
+
+    /// Minimum number of data points for [`Arrsac::model_inliers_par`] to
+    /// score the hypotheses in parallel
+    ///
+    /// Default: `65536`
+    #[must_use]
+    pub fn parallel_threshold(self, parallel_threshold: usize) -> Self {
+        Self {
+            parallel_threshold,
+            ..self
+        }
+    }
 }
 
 impl<R, T: num::Float + RealField> Arrsac<R, T>
@@ -177,6 +199,7 @@ where
         &mut self,
         estimator: &E,
         data: impl Iterator<Item = Data> + Clone,
+        scorer: &impl Scorer<E::Model>,
     ) -> (Vec<(E::Model, usize)>, T)
     where
         E: Estimator<Data>,
@@ -264,8 +287,8 @@ where
             self.initialization_hypotheses,
         );
 
-        // Sort the hypotheses by their inliers.
-        hypotheses.sort_unstable_by_key(|&(_, inliers)| Reverse(inliers));
+        // Sort the hypotheses by their scores.
+        scorer.rank(&mut hypotheses, initial_datapoints);
 
         // Filter down the hypotheses to just the best ones.
         hypotheses.truncate(self.max_candidate_hypotheses >> (self.initialization_blocks - 1));
@@ -273,6 +296,69 @@ where
         (hypotheses, delta)
     }
 
+    /// Runs the adaptive block processing with `scorer`, and returns the
+    /// surviving hypotheses and their inlier counts among the evaluated data.
+    fn hypotheses<E, Data>(
+        &mut self,
+        estimator: &E,
+        data: impl Iterator<Item = Data> + Clone,
+        scorer: &impl Scorer<E::Model>,
+    ) -> Vec<(E::Model, usize)>
+    where
+        E: Estimator<Data>,
+    {
+        // Don't do anything if we don't have enough data.
+        if data.clone().count() < E::MIN_SAMPLES {
+            return vec![];
+        }
+        // Generate the initial set of hypotheses. This also gets us an estimate of
+        // delta.
+        let (mut hypotheses, delta) = self.initial_hypotheses(estimator, data.clone(), scorer);
+
+        // If there are no initial hypotheses then initialization failed, so exit early.
+        if hypotheses.is_empty() {
+            return vec![];
+        }
+
+        // Gradually increase how many datapoints we are evaluating until we evaluate
+        // them all. This starts at the first block that was not evaluated in
+        // initial_hypotheses.
+        'outer: for block in self.initialization_blocks.. {
+            let samples_up_to_beginning_of_block = block * self.block_size;
+            let samples_up_to_end_of_block = samples_up_to_beginning_of_block + self.block_size;
+            // Score hypotheses with samples.
+            let range = samples_up_to_beginning_of_block..samples_up_to_end_of_block;
+            if !scorer.score(&mut hypotheses, range) {
+                // We reached the last datapoint, so break out of the outer loop.
+                break 'outer;
+            }
+            // Sort the hypotheses by their scores to find the best.
+            scorer.rank(&mut hypotheses, samples_up_to_end_of_block);
+            // Populate hypotheses with hypotheses that pass SPRT.
+            self.populate_hypotheses_sprt(
+                estimator,
+                &mut hypotheses,
+                delta,
+                data.clone(),
+                samples_up_to_end_of_block,
+                self.estimations_per_block,
+            );
+            // This will retain at least half of the hypotheses each time
+            // and gradually decrease as the number of samples we are evaluating increases.
+            // NOTE:
+            // The paper says to use a peculiar formula that just results in doing
+            // this basic right shift below, but as written it contained some apparent
+            // errors in where it was ran. This seems to be the correct location
+            // to do this.
+            scorer.rank(&mut hypotheses, samples_up_to_end_of_block);
+            hypotheses.truncate(self.max_candidate_hypotheses >> block);
+            if hypotheses.len() <= 1 {
+                break 'outer;
+            }
+        }
+        hypotheses
+    }
+
     /// Populates `self.random_samples` using a len.
     fn populate_samples(&mut self, num: usize, len: usize) {
         // We can generate no hypotheses if the amout of data is too low.
@@ -442,6 +528,257 @@ where
     }
 }
 
+impl<R, T: num::Float + RealField> Arrsac<R, T>
+where
+    R: RngCore,
+{
+    /// Counts the inliers of a model in chunks in parallel, or returns `None`
+    /// as soon as it can't have more than `best` inliers.
+    fn par_count_inliers<Data, M>(&self, data: &[Data], model: &M, best: usize) -> Option<usize>
+    where
+        Data: Sync,
+        M: Model<Data> + Sync,
+    {
+        let threshold = self.inlier_threshold;
+        let outliers = AtomicUsize::new(0);
+        { data.par_chunks(PARALLEL_CHUNK) }
+            .try_for_each(|chunk| {
+                let num = { chunk.iter() }
+                    .filter(|data| T::from_f64(model.residual(data)).unwrap() >= threshold)
+                    .count();
+                let total = outliers.fetch_add(num, Relaxed) + num;
+                (data.len() - total > best).then_some(())
+            })
+            .map(|_| data.len() - outliers.into_inner())
+    }
+
+    /// Gets indices of inliers for a model in parallel.
+    fn par_inliers<Data, M>(&self, data: &[Data], model: &M) -> Vec<usize>
+    where
+        Data: Sync,
+        M: Model<Data> + Sync,
+    {
+        let threshold = self.inlier_threshold;
+        { data.par_iter().enumerate() }
+            .filter(|(_, data)| T::from_f64(model.residual(data)).unwrap() < threshold)
+            .map(|(ix, _)| ix)
+            .collect()
+    }
+
+    /// Like [`Consensus::model_inliers`], but if there are at least
+    /// `parallel_threshold` data points, the hypotheses are scored on the
+    /// blocks in parallel, and the surviving ones are rescored on all the data
+    /// in parallel, skipping the rest of a hypothesis once it can't beat the
+    /// best one.
+    pub fn model_inliers_par<E, Data>(
+        &mut self,
+        estimator: &E,
+        data: &[Data],
+    ) -> Option<(E::Model, Vec<usize>)>
+    where
+        E: Estimator<Data>,
+        E::Model: Sync,
+        Data: Clone + Sync,
+    {
+        if data.len() < self.parallel_threshold {
+            return self.model_inliers(estimator, data.iter().cloned());
+        }
+
+        let scorer = ParScorer {
+            data,
+            threshold: self.inlier_threshold,
+        };
+        let hypotheses = self.hypotheses(estimator, data.iter().cloned(), &scorer);
+        let (model, _) = { hypotheses.into_iter() }.fold(None, |acc, (model, _)| {
+            let best = acc.as_ref().map_or(0, |(_, best)| *best);
+            match self.par_count_inliers(data, &model, best) {
+                Some(inliers) => Some((model, inliers)),
+                None => acc,
+            }
+        })?;
+        let inliers = self.par_inliers(data, &model);
+        Some((model, inliers))
+    }
+
+    /// The sum of the `weights` of the inliers of a model.
+    fn weighted_score<Data, M>(&self, data: &[Data], weights: &[T], model: &M) -> T
+    where
+        Data: Sync,
+        M: Model<Data> + Sync,
+    {
+        let threshold = self.inlier_threshold;
+        let score = |(data, weight): (&Data, &T)| {
+            if T::from_f64(model.residual(data)).unwrap() < threshold {
+                *weight
+            } else {
+                T::zero()
+            }
+        };
+        if data.len() < self.parallel_threshold {
+            { data.iter().zip(weights) }
+                .map(score)
+                .fold(T::zero(), |a, b| a + b)
+        } else {
+            { data.par_iter().zip(weights) }
+                .map(score)
+                .reduce(T::zero, |a, b| a + b)
+        }
+    }
+
+    /// Like [`Arrsac::model_inliers_par`], but ranks the hypotheses in the
+    /// block processing and selects the final one by the sums of the `weights`
+    /// of their inliers, e.g. the confidences of the data points from the
+    /// noise models of the sensors.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` and `weights` have different lengths.
+    pub fn model_inliers_weighted<E, Data>(
+        &mut self,
+        estimator: &E,
+        data: &[Data],
+        weights: &[T],
+    ) -> Option<(E::Model, Vec<usize>)>
+    where
+        E: Estimator<Data>,
+        E::Model: Sync,
+        Data: Clone + Sync,
+    {
+        assert_eq!(data.len(), weights.len(), "Every datum must have a weight");
+
+        let scorer = WeightedScorer {
+            inner: ParScorer {
+                data,
+                threshold: self.inlier_threshold,
+            },
+            weights,
+        };
+        let hypotheses = self.hypotheses(estimator, data.iter().cloned(), &scorer);
+        let (model, _) = { hypotheses.into_iter() }
+            .map(|(model, _)| {
+                let score = self.weighted_score(data, weights, &model);
+                (model, score)
+            })
+            .fold(None, |acc, (model, score)| match acc {
+                Some((_, ref best)) if *best >= score => acc,
+                _ => Some((model, score)),
+            })?;
+        let inliers = if data.len() < self.parallel_threshold {
+            self.inliers(data.iter().cloned(), &model)
+        } else {
+            self.par_inliers(data, &model)
+        };
+        Some((model, inliers))
+    }
+}
+
+/// How the block processing of [`Arrsac`] scores and ranks the hypotheses.
+trait Scorer<M> {
+    /// Adds the numbers of the inliers among the data points in `range` to
+    /// the counts of the hypotheses, and returns whether the data points are
+    /// all there.
+    fn score(&self, hypotheses: &mut [(M, usize)], range: Range<usize>) -> bool;
+
+    /// Sorts the hypotheses from the best to the worst by their scores on the
+    /// first `num_checked` data points.
+    fn rank(&self, hypotheses: &mut Vec<(M, usize)>, _num_checked: usize) {
+        hypotheses.sort_unstable_by_key(|&(_, inliers)| Reverse(inliers));
+    }
+}
+
+/// Scores the hypotheses on the data points from an iterator.
+struct SerialScorer<I, T> {
+    data: I,
+    threshold: T,
+}
+
+impl<I, Data, M, T> Scorer<M> for SerialScorer<I, T>
+where
+    I: Iterator<Item = Data> + Clone,
+    M: Model<Data>,
+    T: num::Float + RealField,
+{
+    fn score(&self, hypotheses: &mut [(M, usize)], range: Range<usize>) -> bool {
+        let len = range.len();
+        let block = { self.data.clone().skip(range.start).take(len) }.collect::<Vec<_>>();
+        for (hypothesis, inlier_count) in hypotheses.iter_mut() {
+            *inlier_count += { block.iter() }
+                .filter(|data| T::from_f64(hypothesis.residual(data)).unwrap() < self.threshold)
+                .count();
+        }
+        block.len() == len
+    }
+}
+
+/// Scores the hypotheses on the data points in a slice in parallel.
+struct ParScorer<'a, Data, T> {
+    data: &'a [Data],
+    threshold: T,
+}
+
+impl<'a, Data, M, T> Scorer<M> for ParScorer<'a, Data, T>
+where
+    Data: Sync,
+    M: Model<Data> + Sync,
+    T: num::Float + RealField,
+{
+    fn score(&self, hypotheses: &mut [(M, usize)], range: Range<usize>) -> bool {
+        let len = range.len();
+        let block = self.data.get(range.start..).unwrap_or_default();
+        let block = &block[..len.min(block.len())];
+        let threshold = self.threshold;
+        let counts = { hypotheses.par_iter() }
+            .map(|(hypothesis, _)| {
+                { block.iter() }
+                    .filter(|data| T::from_f64(hypothesis.residual(data)).unwrap() < threshold)
+                    .count()
+            })
+            .collect::<Vec<_>>();
+        for ((_, inlier_count), count) in hypotheses.iter_mut().zip(counts) {
+            *inlier_count += count;
+        }
+        block.len() == len
+    }
+}
+
+/// Like [`ParScorer`], but ranks the hypotheses by the sums of the weights of
+/// their inliers.
+struct WeightedScorer<'a, Data, T> {
+    inner: ParScorer<'a, Data, T>,
+    weights: &'a [T],
+}
+
+impl<'a, Data, M, T> Scorer<M> for WeightedScorer<'a, Data, T>
+where
+    Data: Sync,
+    M: Model<Data> + Sync,
+    T: num::Float + RealField,
+{
+    fn score(&self, hypotheses: &mut [(M, usize)], range: Range<usize>) -> bool {
+        self.inner.score(hypotheses, range)
+    }
+
+    fn rank(&self, hypotheses: &mut Vec<(M, usize)>, num_checked: usize) {
+        let num_checked = num_checked.min(self.weights.len());
+        let (data, weights) = (
+            &self.inner.data[..num_checked],
+            &self.weights[..num_checked],
+        );
+        let threshold = self.inner.threshold;
+        let scores = { hypotheses.par_iter() }
+            .map(|(hypothesis, _)| {
+                { data.iter().zip(weights) }
+                    .filter(|(data, _)| T::from_f64(hypothesis.residual(data)).unwrap() < threshold)
+                    .fold(T::zero(), |acc, (_, weight)| acc + *weight)
+            })
+            .collect::<Vec<_>>();
+
+        let mut scored = hypotheses.drain(..).zip(scores).collect::<Vec<_>>();
+        scored.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+        hypotheses.extend(scored.into_iter().map(|(hypothesis, _)| hypothesis));
+    }
+}
+
 impl<E, R, Data, T: num::Float + RealField> Consensus<E, Data> for Arrsac<R, T>
 where
     E: Estimator<Data>,
@@ -460,67 +797,11 @@ where
     where
         I: Iterator<Item = Data> + Clone,
     {
-        // Don't do anything if we don't have enough data.
-        if data.clone().count() < E::MIN_SAMPLES {
-            return None;
-        }
-        // Generate the initial set of hypotheses. This also gets us an estimate of
-        // delta.
-        let (mut hypotheses, delta) = self.initial_hypotheses(estimator, data.clone());
-
-        // If there are no initial hypotheses then initialization failed, so exit early.
-        if hypotheses.is_empty() {
-            return None;
-        }
-
-        // Gradually increase how many datapoints we are evaluating until we evaluate
-        // them all. This starts at the first block that was not evaluated in
-        // initial_hypotheses.
-        'outer: for block in self.initialization_blocks.. {
-            let samples_up_to_beginning_of_block = block * self.block_size;
-            let samples_up_to_end_of_block = samples_up_to_beginning_of_block + self.block_size;
-            // Score hypotheses with samples.
-            for sample in samples_up_to_beginning_of_block..samples_up_to_end_of_block {
-                // Score the hypotheses with the new datapoint.
-                let new_datapoint = if let Some(datapoint) = data.clone().nth(sample) {
-                    datapoint
-                } else {
-                    // We reached the last datapoint, so break out of the outer loop.
-                    break 'outer;
-                };
-                for (hypothesis, inlier_count) in hypotheses.iter_mut() {
-                    if T::from_f64(hypothesis.residual(&new_datapoint)).unwrap()
-                        < self.inlier_threshold
-                    {
-                        *inlier_count += 1;
-                    }
-                }
-            }
-            // Sort the hypotheses by their inliers to find the best.
-            hypotheses.sort_unstable_by_key(|&(_, inliers)| Reverse(inliers));
-            // Populate hypotheses with hypotheses that pass SPRT.
-            self.populate_hypotheses_sprt(
-                estimator,
-                &mut hypotheses,
-                delta,
-                data.clone(),
-                samples_up_to_end_of_block,
-                self.estimations_per_block,
-            );
-            // This will retain at least half of the hypotheses each time
-            // and gradually decrease as the number of samples we are evaluating increases.
-            // NOTE:
-            // The paper says to use a peculiar formula that just results in doing
-            // this basic right shift below, but as written it contained some apparent
-            // errors in where it was ran. This seems to be the correct location
-            // to do this.
-            hypotheses.sort_unstable_by_key(|&(_, inliers)| Reverse(inliers));
-            hypotheses.truncate(self.max_candidate_hypotheses >> block);
-            if hypotheses.len() <= 1 {
-                break 'outer;
-            }
-        }
-        hypotheses
+        let scorer = SerialScorer {
+            data: data.clone(),
+            threshold: self.inlier_threshold,
+        };
+        self.hypotheses(estimator, data.clone(), &scorer)
             .into_iter()
             .max_by_key(|&(_, inliers)| inliers)
             .map(|(model, _)| {
@@ -529,3 +810,70 @@ where
             })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::PlaneEstimator;
+
+    /// A grid of `width` by `height` on the plane `z`, which is shuffled by
+    /// the stride `step` coprime to its size.
+    fn grid(width: usize, height: usize, z: f32, step: usize) -> Vec<Vector4<f32>> {
+        let len = width * height;
+        { 0..len }
+            .map(|i| (i * step) % len)
+            .map(|i| Vector4::new((i % width) as f32 * 0.1, (i / width) as f32 * 0.1, z, 1.))
+            .collect()
+    }
+
+    #[test]
+    fn test_model_inliers_par() {
+        let mut data = grid(30, 20, 0., 7);
+        // Outliers scattered over the blocks after the initial ones.
+        for i in (0..data.len()).step_by(5) {
+            data[i].z = (i % 13) as f32 * 0.3 + 0.5;
+        }
+
+        let serial = Arrsac::new(0.01, StdRng::seed_from_u64(0))
+            .model_inliers(&PlaneEstimator, data.iter().cloned())
+            .unwrap();
+        let par = Arrsac::new(0.01, StdRng::seed_from_u64(0))
+            .parallel_threshold(0)
+            .model_inliers_par(&PlaneEstimator, &data)
+            .unwrap();
+        assert_eq!(par.1, serial.1);
+        assert_eq!(par.1.len(), 480);
+    }
+
+    #[test]
+    fn test_model_inliers_weighted() {
+        // The larger plane has the light points, and the smaller one the heavy
+        // points, interleaved into the same blocks.
+        let (light, heavy) = (grid(20, 15, 0., 7), grid(10, 10, 5., 3));
+        let mut data = Vec::new();
+        let mut weights = Vec::new();
+        for (i, coords) in light.into_iter().enumerate() {
+            data.push(coords);
+            weights.push(0.1f32);
+            if i % 3 == 0 {
+                data.push(heavy[i / 3]);
+                weights.push(1.);
+            }
+        }
+
+        let (plane, inliers) = Arrsac::new(0.01, StdRng::seed_from_u64(0))
+            .model_inliers(&PlaneEstimator, data.iter().cloned())
+            .unwrap();
+        assert_eq!(inliers.len(), 300);
+        assert!(plane.distance(&Vector4::new(0., 0., 0., 1.)) < 0.01);
+
+        let (plane, inliers) = Arrsac::new(0.01, StdRng::seed_from_u64(0))
+            .model_inliers_weighted(&PlaneEstimator, &data, &weights)
+            .unwrap();
+        assert_eq!(inliers.len(), 100);
+        assert!(plane.distance(&Vector4::new(0., 0., 5., 1.)) < 0.01);
+    }
+}
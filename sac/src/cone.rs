@@ -10,8 +10,8 @@ use crate::{
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Cone<T: Scalar> {
-    circle: Circle<T>,
-    height: T,
+    pub circle: Circle<T>,
+    pub height: T,
 }
 
 impl<T: RealField> Cone<T> {
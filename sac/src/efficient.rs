@@ -0,0 +1,585 @@
+use std::collections::HashMap;
+
+use nalgebra::{convert, Matrix3, RealField, Vector3};
+use num::ToPrimitive;
+use pcc_common::{point::PointNormal, point_cloud::PointCloud};
+use rand::RngCore;
+
+use crate::{
+    circle::Circle,
+    cone::Cone,
+    cylinder::Cylinder,
+    normal::{closest_points, NormalSphereEstimator},
+    plane::{Plane, PlaneEstimator},
+    selector::{Primitive, PrimitiveKind},
+    sphere::Sphere,
+};
+
+/// The number of the random points used to estimate the scores of the
+/// candidates.
+const SUBSET_SIZE: usize = 1024;
+/// The number of the samples drawn between 2 checks of the candidates.
+const BATCH_SIZE: usize = 32;
+
+/// The unbounded surfaces of the candidates, whose extents are only
+/// determined by their inliers.
+#[derive(Debug, Clone)]
+enum Shape<T: RealField> {
+    Plane {
+        coords: Vector3<T>,
+        normal: Vector3<T>,
+    },
+    Sphere {
+        center: Vector3<T>,
+        radius: T,
+    },
+    Cylinder {
+        coords: Vector3<T>,
+        axis: Vector3<T>,
+        radius: T,
+    },
+    Cone {
+        apex: Vector3<T>,
+        axis: Vector3<T>,
+        angle: T,
+    },
+}
+
+impl<T: RealField + Copy> Shape<T> {
+    /// The distance of `point` to the surface and the unit surface normal
+    /// there.
+    fn distance_normal(&self, point: &Vector3<T>) -> (T, Vector3<T>) {
+        match self {
+            Shape::Plane { coords, normal } => ((point - coords).dot(normal).abs(), *normal),
+            Shape::Sphere { center, radius } => {
+                let delta = point - center;
+                let norm = delta.norm();
+                ((norm - *radius).abs(), delta / norm)
+            }
+            Shape::Cylinder {
+                coords,
+                axis,
+                radius,
+            } => {
+                let delta = point - coords;
+                let radial = delta - axis * delta.dot(axis);
+                let norm = radial.norm();
+                ((norm - *radius).abs(), radial / norm)
+            }
+            Shape::Cone { apex, axis, angle } => {
+                let delta = point - apex;
+                let along = delta.dot(axis);
+                let radial = delta - axis * along;
+                let norm = radial.norm();
+                let (sin, cos) = angle.sin_cos();
+                if along * cos + norm * sin < T::zero() {
+                    // Behind the apex.
+                    (delta.norm(), -axis)
+                } else {
+                    let normal = radial / norm * cos - axis * sin;
+                    ((norm * cos - along * sin).abs(), normal)
+                }
+            }
+        }
+    }
+
+    /// Bounds the surface by the extent of `inliers` along the axis.
+    fn into_primitive(self, inliers: impl Iterator<Item = Vector3<T>>) -> Primitive<T> {
+        let point = |v: Vector3<T>| v.insert_row(3, T::one());
+        let vector = |v: Vector3<T>| v.insert_row(3, T::zero());
+        match self {
+            Shape::Plane { coords, normal } => Primitive::Plane(Plane {
+                coords: point(coords),
+                normal: vector(normal),
+            }),
+            Shape::Sphere { center, radius } => Primitive::Sphere(Sphere {
+                coords: point(center),
+                radius,
+            }),
+            Shape::Cylinder {
+                coords,
+                axis,
+                radius,
+            } => {
+                let (min, max) = { inliers.map(|inlier| (inlier - coords).dot(&axis)) }.fold(
+                    (T::max_value().unwrap(), T::min_value().unwrap()),
+                    |(min, max), t| (min.min(t), max.max(t)),
+                );
+                Primitive::Cylinder(Cylinder {
+                    circle: Circle {
+                        center: point(coords + axis * min),
+                        normal: vector(axis),
+                        radius,
+                    },
+                    height: max - min,
+                })
+            }
+            Shape::Cone { apex, axis, angle } => {
+                let height = { inliers.map(|inlier| (inlier - apex).dot(&axis)) }
+                    .fold(T::zero(), |max, t| max.max(t));
+                Primitive::Cone(Cone {
+                    circle: Circle {
+                        center: point(apex + axis * height),
+                        normal: vector(-axis),
+                        radius: height * angle.tan(),
+                    },
+                    height,
+                })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Candidate<T: RealField> {
+    shape: Shape<T>,
+    score: usize,
+    exact: bool,
+}
+
+/// The points with normals that haven't been extracted yet.
+struct Remaining<T: RealField> {
+    coords: Vec<Vector3<T>>,
+    normals: Vec<Vector3<T>>,
+    /// The indices of the points in the input.
+    indices: Vec<usize>,
+    removed: Vec<bool>,
+    num: usize,
+    /// The minimum corner of the octree.
+    origin: Vector3<T>,
+    /// The side lengths of the cells and the points in them in every level of
+    /// the octree, from the coarsest to the finest.
+    levels: Vec<(T, HashMap<[usize; 3], Vec<usize>>)>,
+}
+
+impl<T: RealField + ToPrimitive + Copy> Remaining<T> {
+    fn key(&self, coords: &Vector3<T>, side: T) -> [usize; 3] {
+        let key = (coords - self.origin).map(|x| (x / side).floor().to_usize().unwrap_or(0));
+        key.into()
+    }
+}
+
+/// Extracts multiple primitives from a point cloud with normals with the
+/// efficient RANSAC of Schnabel et al.
+///
+/// The minimal samples are drawn locally from the cells of an octree at
+/// random levels, the candidate primitives are scored on random subsets of
+/// the points first, and the best one is extracted with its inliers once it
+/// is unlikely that a better one has been missed. The extraction repeats
+/// until no primitive with enough inliers is likely to be found.
+///
+/// Planes, spheres, cylinders and cones are supported. Tori, which the paper
+/// extracts as well, are not, as there is no torus model to fit them to.
+#[derive(Debug, Clone)]
+pub struct EfficientRansac<T: RealField, R> {
+    /// The maximum distance of the inliers to the surfaces.
+    pub epsilon: T,
+    /// The maximum angle in radians between the normals of the inliers and
+    /// the surfaces.
+    pub max_angle: T,
+    /// Primitives with fewer inliers are not extracted.
+    pub min_support: usize,
+    /// The acceptable probability of missing a better primitive.
+    pub probability: T,
+    pub kinds: Vec<PrimitiveKind>,
+    /// The number of the levels of the octree.
+    pub num_levels: usize,
+    pub rng: R,
+}
+
+impl<T: RealField, R: RngCore> EfficientRansac<T, R> {
+    /// Creates an extractor of all the supported kinds of primitives.
+    pub fn new(epsilon: T, max_angle: T, min_support: usize, rng: R) -> Self {
+        EfficientRansac {
+            epsilon,
+            max_angle,
+            min_support,
+            probability: convert(0.01),
+            kinds: vec![
+                PrimitiveKind::Plane,
+                PrimitiveKind::Sphere,
+                PrimitiveKind::Cylinder,
+                PrimitiveKind::Cone,
+            ],
+            num_levels: 8,
+            rng,
+        }
+    }
+}
+
+impl<T: RealField + ToPrimitive + Copy, R: RngCore> EfficientRansac<T, R> {
+    fn random(&mut self, len: usize) -> usize {
+        self.rng.next_u64() as usize % len
+    }
+
+    fn is_compatible(&self, shape: &Shape<T>, coords: &Vector3<T>, normal: &Vector3<T>) -> bool {
+        let (distance, surface) = shape.distance_normal(coords);
+        distance <= self.epsilon && surface.dot(normal).abs() >= self.max_angle.cos()
+    }
+
+    fn make_shapes(&self, samples: &[(Vector3<T>, Vector3<T>); 3]) -> Vec<Shape<T>> {
+        let [(a, na), (b, nb), (c, _)] = samples;
+        let point = |v: &Vector3<T>| v.insert_row(3, T::one());
+        let vector = |v: &Vector3<T>| v.insert_row(3, T::zero());
+
+        let mut shapes = Vec::with_capacity(self.kinds.len());
+        for kind in &self.kinds {
+            let shape = match kind {
+                PrimitiveKind::Plane => {
+                    let plane = PlaneEstimator::make(&point(a), &point(b), &point(c));
+                    let normal = plane.normal.xyz().normalize();
+                    normal
+                        .iter()
+                        .all(|x| x.is_finite())
+                        .then(|| Shape::Plane { coords: *a, normal })
+                }
+                PrimitiveKind::Sphere => {
+                    let sphere = NormalSphereEstimator::try_make(
+                        &(point(a), vector(na)),
+                        &(point(b), vector(nb)),
+                    );
+                    sphere.map(|sphere| Shape::Sphere {
+                        center: sphere.coords.xyz(),
+                        radius: sphere.radius,
+                    })
+                }
+                PrimitiveKind::Cylinder => {
+                    let axis = na.cross(nb).normalize();
+                    closest_points(&point(a), &vector(na), &point(b), &vector(nb)).map(|(t, s)| {
+                        let coords = (a + na * t + b + nb * s) * convert::<_, T>(0.5);
+                        let radius = ((a - coords).cross(&axis).norm()
+                            + (b - coords).cross(&axis).norm())
+                            * convert::<_, T>(0.5);
+                        Shape::Cylinder {
+                            coords,
+                            axis,
+                            radius,
+                        }
+                    })
+                }
+                PrimitiveKind::Cone => Self::make_cone(samples),
+                PrimitiveKind::Line => None,
+            };
+            shapes.extend(shape.filter(|shape| {
+                { samples.iter() }.all(|(coords, normal)| self.is_compatible(shape, coords, normal))
+            }));
+        }
+        shapes
+    }
+
+    /// The apex of a cone is the intersection of the tangent planes at the
+    /// samples, and its axis is perpendicular to the plane through the unit
+    /// directions from the apex to the samples.
+    fn make_cone(samples: &[(Vector3<T>, Vector3<T>); 3]) -> Option<Shape<T>> {
+        let [(a, na), (b, nb), (c, nc)] = samples;
+        let matrix = Matrix3::from_rows(&[na.transpose(), nb.transpose(), nc.transpose()]);
+        let rhs = Vector3::new(na.dot(a), nb.dot(b), nc.dot(c));
+        let apex = matrix.lu().solve(&rhs)?;
+
+        let [da, db, dc] = [a, b, c].map(|x| (x - apex).normalize());
+        let mut axis = (db - da).cross(&(dc - da)).normalize();
+        if axis.dot(&da) < T::zero() {
+            axis = -axis;
+        }
+        let cos = (da.dot(&axis) + db.dot(&axis) + dc.dot(&axis)) / convert(3.);
+        let angle = cos.min(T::one()).acos();
+
+        let valid = axis.iter().all(|x| x.is_finite())
+            && angle > convert(1e-3)
+            && angle < T::frac_pi_2() - convert(1e-3);
+        valid.then(|| Shape::Cone { apex, axis, angle })
+    }
+
+    fn remaining<P: PointNormal<Data = T>>(&self, input: &PointCloud<P>) -> Remaining<T> {
+        let mut remaining = Remaining {
+            coords: Vec::new(),
+            normals: Vec::new(),
+            indices: Vec::new(),
+            removed: Vec::new(),
+            num: 0,
+            origin: Vector3::zeros(),
+            levels: Vec::new(),
+        };
+        for (index, point) in input.iter().enumerate() {
+            let normal = point.normal().xyz();
+            if point.is_finite() && normal.iter().all(|x| x.is_finite()) {
+                remaining.coords.push(point.coords().xyz());
+                remaining.normals.push(normal.normalize());
+                remaining.indices.push(index);
+            }
+        }
+        remaining.num = remaining.coords.len();
+        remaining.removed = vec![false; remaining.num];
+
+        let (min, max) = { remaining.coords.iter() }.fold(
+            (
+                Vector3::repeat(T::max_value().unwrap()),
+                Vector3::repeat(T::min_value().unwrap()),
+            ),
+            |(min, max), coords| (min.inf(coords), max.sup(coords)),
+        );
+        remaining.origin = min;
+        let extent = (max - min).max();
+        for level in 1..=self.num_levels {
+            let side = extent / T::from_usize(1 << level).unwrap();
+            let mut cells = HashMap::<_, Vec<_>>::new();
+            for (index, coords) in remaining.coords.iter().enumerate() {
+                cells
+                    .entry(remaining.key(coords, side))
+                    .or_default()
+                    .push(index);
+            }
+            remaining.levels.push((side, cells));
+        }
+        remaining
+    }
+
+    /// Draws a sample of 3 points from the cell of a random point at a random
+    /// level.
+    fn draw(&mut self, remaining: &Remaining<T>) -> Option<[(Vector3<T>, Vector3<T>); 3]> {
+        let first = loop {
+            let index = self.random(remaining.coords.len());
+            if !remaining.removed[index] {
+                break index;
+            }
+        };
+        let (side, cells) = &remaining.levels[self.random(remaining.levels.len())];
+        let cell = { cells[&remaining.key(&remaining.coords[first], *side)].iter() }
+            .filter(|&&index| index != first && !remaining.removed[index])
+            .collect::<Vec<_>>();
+        if cell.len() < 2 {
+            return None;
+        }
+
+        let second = self.random(cell.len());
+        let third = loop {
+            let third = self.random(cell.len());
+            if third != second {
+                break third;
+            }
+        };
+        let sample = |index: usize| (remaining.coords[index], remaining.normals[index]);
+        Some([sample(first), sample(*cell[second]), sample(*cell[third])])
+    }
+
+    /// The indices of the remaining points compatible with `shape`.
+    fn inliers(&self, shape: &Shape<T>, remaining: &Remaining<T>) -> Vec<usize> {
+        { remaining.coords.iter().zip(&remaining.normals).enumerate() }
+            .filter(|&(index, _)| !remaining.removed[index])
+            .filter(|(_, (coords, normal))| self.is_compatible(shape, coords, normal))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// The probability of drawing a minimal sample of a primitive with
+    /// `support` inliers in one draw.
+    fn draw_probability(&self, support: usize, remaining: &Remaining<T>) -> T {
+        let ratio = T::from_usize(support).unwrap() / T::from_usize(remaining.num).unwrap();
+        ratio / T::from_usize(remaining.levels.len() * 4).unwrap()
+    }
+
+    /// Checks if a primitive with `support` inliers would have been drawn
+    /// among `num_draws` draws with the desired probability.
+    fn is_confident(&self, support: usize, num_draws: usize, remaining: &Remaining<T>) -> bool {
+        let miss = T::one() - self.draw_probability(support, remaining);
+        miss.powi(num_draws.min(i32::MAX as usize) as i32) <= self.probability
+    }
+
+    /// Returns the extracted primitives and the indices of their inliers in
+    /// `input`.
+    pub fn compute<P: PointNormal<Data = T>>(
+        &mut self,
+        input: &PointCloud<P>,
+    ) -> Vec<(Primitive<T>, Vec<usize>)> {
+        let mut remaining = self.remaining(input);
+        let mut primitives = Vec::new();
+        let mut candidates = Vec::<Candidate<T>>::new();
+        let mut num_draws = 0;
+
+        while remaining.num >= self.min_support.max(3) && !self.kinds.is_empty() {
+            let subset = (0..SUBSET_SIZE.min(remaining.num))
+                .map(|_| self.random(remaining.coords.len()))
+                .filter(|&index| !remaining.removed[index])
+                .collect::<Vec<_>>();
+            for _ in 0..BATCH_SIZE {
+                num_draws += 1;
+                let samples = match self.draw(&remaining) {
+                    Some(samples) => samples,
+                    None => continue,
+                };
+
+                for shape in self.make_shapes(&samples) {
+                    let hits = { subset.iter() }
+                        .filter(|&&index| {
+                            let (coords, normal) =
+                                (&remaining.coords[index], &remaining.normals[index]);
+                            self.is_compatible(&shape, coords, normal)
+                        })
+                        .count();
+                    let score = hits * remaining.num / subset.len().max(1);
+                    candidates.push(Candidate {
+                        shape,
+                        score,
+                        exact: false,
+                    });
+                }
+            }
+
+            candidates.sort_by(|a, b| b.score.cmp(&a.score));
+            candidates.retain(|candidate| candidate.score >= self.min_support);
+            let best = match candidates.first_mut() {
+                Some(best) => best,
+                None if self.is_confident(self.min_support, num_draws, &remaining) => break,
+                None => continue,
+            };
+            if !best.exact {
+                best.score = self.inliers(&best.shape, &remaining).len();
+                best.exact = true;
+                continue;
+            }
+            if !self.is_confident(best.score, num_draws, &remaining) {
+                continue;
+            }
+
+            let best = candidates.swap_remove(0);
+            let inliers = self.inliers(&best.shape, &remaining);
+            for &index in &inliers {
+                remaining.removed[index] = true;
+            }
+            remaining.num -= inliers.len();
+
+            let coords = inliers.iter().map(|&index| remaining.coords[index]);
+            let primitive = best.shape.into_primitive(coords);
+            let indices = inliers
+                .iter()
+                .map(|&index| remaining.indices[index])
+                .collect();
+            primitives.push((primitive, indices));
+
+            // The scores of the other candidates are outdated.
+            candidates.clear();
+            num_draws = 0;
+        }
+        primitives
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::point::{Normal, Point, Point3N};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    /// Extracts the primitives from the points with the normals.
+    fn extract(samples: impl Iterator<Item = ([f32; 3], [f32; 3])>) -> Vec<Primitive<f32>> {
+        let storage = { samples.map(|(c, n)| (Vector3::from(c), Vector3::from(n))) }
+            .map(|(coords, normal)| {
+                Point3N::default()
+                    .with_coords(coords.insert_row(3, 1.))
+                    .with_normal(normal.normalize().insert_row(3, 0.))
+            })
+            .collect::<Vec<_>>();
+        let len = storage.len();
+        let input = PointCloud::from_vec(storage, len);
+
+        let mut ransac = EfficientRansac::new(0.01, 0.1, 100, StdRng::seed_from_u64(0));
+        let primitives = ransac.compute(&input);
+        let num = { primitives.iter() }
+            .map(|(_, inliers)| inliers.len())
+            .sum::<usize>();
+        assert!(num * 10 >= len * 9, "{num} of {len} points extracted");
+        { primitives.into_iter() }
+            .map(|(primitive, _)| primitive)
+            .collect()
+    }
+
+    #[test]
+    fn test_sphere() {
+        // A Fibonacci sphere.
+        let golden = std::f32::consts::PI * (3. - 5f32.sqrt());
+        let samples = { 0..500 }.map(|i| {
+            let z = 1. - (i as f32 + 0.5) / 250.;
+            let r = (1. - z * z).sqrt();
+            let (sin, cos) = (golden * i as f32).sin_cos();
+            let normal = [r * cos, r * sin, z];
+            (normal.map(|x| x * 2. + 1.), normal)
+        });
+        let primitives = extract(samples);
+        assert_eq!(primitives.len(), 1);
+        match &primitives[0] {
+            Primitive::Sphere(sphere) => {
+                assert!((sphere.coords - Vector4::new(1., 1., 1., 1.)).norm() < 0.01);
+                assert!((sphere.radius - 2.).abs() < 0.01);
+            }
+            primitive => panic!("Not a sphere: {primitive:?}"),
+        }
+    }
+
+    #[test]
+    fn test_cylinder() {
+        // Around the z axis.
+        let samples = { (0..40).flat_map(|a| (0..20).map(move |h| (a, h))) }.map(|(a, h)| {
+            let (sin, cos) = (a as f32 * std::f32::consts::TAU / 40.).sin_cos();
+            ([cos * 0.5, sin * 0.5, h as f32 * 0.1], [cos, sin, 0.])
+        });
+        let primitives = extract(samples);
+        assert_eq!(primitives.len(), 1);
+        match &primitives[0] {
+            Primitive::Cylinder(cylinder) => {
+                let axis = cylinder.circle.normal.xyz();
+                assert!(axis.normalize().z.abs() > 0.999);
+                assert!((cylinder.circle.radius - 0.5).abs() < 0.01);
+                assert!((cylinder.height - 1.9).abs() < 0.02);
+            }
+            primitive => panic!("Not a cylinder: {primitive:?}"),
+        }
+    }
+
+    #[test]
+    fn test_cone() {
+        // With the apex at the origin, opening along the z axis.
+        let angle = 0.5f32;
+        let (sin, cos) = angle.sin_cos();
+        let samples = { (0..40).flat_map(|a| (1..21).map(move |h| (a, h))) }.map(|(a, h)| {
+            let (s, c) = (a as f32 * std::f32::consts::TAU / 40.).sin_cos();
+            let (z, r) = (h as f32 * 0.1, h as f32 * 0.1 * angle.tan());
+            ([c * r, s * r, z], [c * cos, s * cos, -sin])
+        });
+        let primitives = extract(samples);
+        assert_eq!(primitives.len(), 1);
+        match &primitives[0] {
+            Primitive::Cone(cone) => {
+                let apex = cone.circle.center + cone.circle.normal.normalize() * cone.height;
+                assert!(apex.xyz().norm() < 0.01);
+                assert!((cone.height - 2.).abs() < 0.02);
+                assert!((cone.circle.radius - 2. * angle.tan()).abs() < 0.02);
+            }
+            primitive => panic!("Not a cone: {primitive:?}"),
+        }
+    }
+
+    #[test]
+    fn test_efficient_ransac() {
+        let mut storage = Vec::new();
+        for x in 0..20 {
+            for y in 0..20 {
+                let coords = Vector4::new(x as f32 * 0.1, y as f32 * 0.1, 0., 1.);
+                let point = Point3N::default()
+                    .with_coords(coords)
+                    .with_normal(Vector4::z());
+                storage.push(point);
+            }
+        }
+        let input = PointCloud::from_vec(storage, 20);
+
+        let mut ransac = EfficientRansac::new(0.01, 0.1, 100, StdRng::seed_from_u64(0));
+        let primitives = ransac.compute(&input);
+        assert_eq!(primitives.len(), 1);
+
+        let (primitive, inliers) = &primitives[0];
+        assert_eq!(primitive.kind(), PrimitiveKind::Plane);
+        assert_eq!(inliers.len(), 400);
+    }
+}
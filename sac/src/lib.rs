@@ -2,18 +2,28 @@ mod base;
 mod circle;
 mod cone;
 mod cylinder;
+mod efficient;
 mod line;
+mod normal;
 mod plane;
+mod prerejection;
+mod selector;
 mod sphere;
+mod tracker;
 
 pub use self::{
     base::{Arrsac, PcSac, SacModel},
     circle::{Circle, CircleEstimator},
     cone::{Cone, ConeEstimator},
     cylinder::{Cylinder, CylinderEstimator},
+    efficient::EfficientRansac,
     line::{Line, LineEstimator, ParallelLineEstimator, Stick, StickEstimator},
+    normal::{NormalCylinderEstimator, NormalModel, NormalSphereEstimator, SurfaceNormal},
     plane::{ParallelPlaneEstimator, PerpendicularPlaneEstimator, Plane, PlaneEstimator},
+    prerejection::{is_degenerate, Prerejective},
+    selector::{BestModelSelector, Primitive, PrimitiveKind},
     sphere::{Sphere, SphereEstimator},
+    tracker::PlaneTracker,
 };
 
 #[cfg(test)]
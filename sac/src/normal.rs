@@ -0,0 +1,232 @@
+use nalgebra::{convert, RealField, Scalar, Vector4};
+use num::ToPrimitive;
+use sample_consensus::{Estimator, Model};
+
+use crate::{base::SacModel, circle::Circle, cylinder::Cylinder, line::Line, sphere::Sphere};
+
+/// Models whose surfaces have well-defined normals.
+pub trait SurfaceNormal<T: Scalar> {
+    /// The unit normal of the surface near `coords`.
+    fn surface_normal(&self, coords: &Vector4<T>) -> Vector4<T>;
+}
+
+impl<T: RealField> SurfaceNormal<T> for Sphere<T> {
+    fn surface_normal(&self, coords: &Vector4<T>) -> Vector4<T> {
+        (coords - &self.coords)
+            .xyz()
+            .normalize()
+            .insert_row(3, T::zero())
+    }
+}
+
+impl<T: RealField> SurfaceNormal<T> for Cylinder<T> {
+    /// The normal of the lateral surface, ignoring the caps.
+    fn surface_normal(&self, coords: &Vector4<T>) -> Vector4<T> {
+        self.circle.target_radius(coords).normalize()
+    }
+}
+
+/// A model fitted to points with normals, i.e. `(coords, normal)` pairs.
+///
+/// The residual of a point blends its distance to the surface and the angle
+/// in radians between its normal and the surface normal, regardless of the
+/// orientation, by `normal_weight`, rejecting the points that are close to
+/// the surface but inconsistent with it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NormalModel<M, T: Scalar> {
+    pub model: M,
+    pub normal_weight: T,
+}
+
+impl<M, T> NormalModel<M, T>
+where
+    M: SurfaceNormal<T>,
+    T: RealField,
+{
+    pub fn angle(&self, (coords, normal): &(Vector4<T>, Vector4<T>)) -> T {
+        let surface = self.model.surface_normal(coords);
+        let cos = surface.dot(normal).abs() / normal.norm();
+        cos.min(T::one()).acos()
+    }
+}
+
+impl<M, T> Model<(Vector4<T>, Vector4<T>)> for NormalModel<M, T>
+where
+    M: Model<Vector4<T>> + SurfaceNormal<T>,
+    T: RealField + ToPrimitive,
+{
+    fn residual(&self, data: &(Vector4<T>, Vector4<T>)) -> f64 {
+        let weight = self.normal_weight.to_f64().unwrap();
+        let angle = self.angle(data).to_f64().unwrap();
+        (1. - weight) * self.model.residual(&data.0) + weight * angle
+    }
+}
+
+impl<M, T> SacModel<(Vector4<T>, Vector4<T>)> for NormalModel<M, T>
+where
+    M: SacModel<Vector4<T>> + SurfaceNormal<T>,
+    T: RealField + ToPrimitive,
+{
+    fn project(&self, (coords, _): &(Vector4<T>, Vector4<T>)) -> (Vector4<T>, Vector4<T>) {
+        let projected = self.model.project(coords);
+        let normal = self.model.surface_normal(&projected);
+        (projected, normal)
+    }
+}
+
+/// The parameters of the closest points of the lines `a + t * da` and
+/// `b + s * db`, or `None` if they're parallel.
+pub(crate) fn closest_points<T: RealField>(
+    a: &Vector4<T>,
+    da: &Vector4<T>,
+    b: &Vector4<T>,
+    db: &Vector4<T>,
+) -> Option<(T, T)> {
+    let w = (a - b).xyz();
+    let (da, db) = (da.xyz(), db.xyz());
+    let (aa, ab, bb) = (da.dot(&da), da.dot(&db), db.dot(&db));
+    let (d, e) = (da.dot(&w), db.dot(&w));
+
+    let denom = aa.clone() * bb.clone() - ab.clone() * ab.clone();
+    if denom <= T::default_epsilon() * aa.clone() * bb.clone() {
+        return None;
+    }
+    let t = (ab.clone() * e.clone() - bb * d.clone()) / denom.clone();
+    let s = (aa * e - ab * d) / denom;
+    Some((t, s))
+}
+
+/// Estimates spheres from 2 points with normals instead of 4 points, whose
+/// center is the midpoint of the closest points of the normal lines.
+pub struct NormalSphereEstimator<T> {
+    pub normal_weight: T,
+}
+
+impl<T: RealField> NormalSphereEstimator<T> {
+    pub fn try_make(
+        (a, na): &(Vector4<T>, Vector4<T>),
+        (b, nb): &(Vector4<T>, Vector4<T>),
+    ) -> Option<Sphere<T>> {
+        let (t, s) = closest_points(a, na, b, nb)?;
+        let ca = a + na * t;
+        let cb = b + nb * s;
+        let coords = (ca + cb) * convert::<_, T>(0.5);
+
+        let radius =
+            ((a - &coords).xyz().norm() + (b - &coords).xyz().norm()) * convert::<_, T>(0.5);
+        Some(Sphere { coords, radius })
+    }
+}
+
+impl<T: RealField + ToPrimitive> Estimator<(Vector4<T>, Vector4<T>)> for NormalSphereEstimator<T> {
+    type Model = NormalModel<Sphere<T>, T>;
+
+    type ModelIter = Option<Self::Model>;
+
+    const MIN_SAMPLES: usize = 2;
+
+    fn estimate<I>(&self, mut data: I) -> Self::ModelIter
+    where
+        I: Iterator<Item = (Vector4<T>, Vector4<T>)> + Clone,
+    {
+        match (data.next(), data.next()) {
+            (Some(a), Some(b)) => Self::try_make(&a, &b).map(|model| NormalModel {
+                model,
+                normal_weight: self.normal_weight.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Estimates cylinders from 3 points with normals instead of 4 points.
+///
+/// The axis is perpendicular to the normals of the first 2 points, and passes
+/// through the closest points of their normal lines. The cylinder spans the
+/// projections of all 3 points onto the axis.
+pub struct NormalCylinderEstimator<T> {
+    pub normal_weight: T,
+}
+
+impl<T: RealField> NormalCylinderEstimator<T> {
+    pub fn try_make(
+        (a, na): &(Vector4<T>, Vector4<T>),
+        (b, nb): &(Vector4<T>, Vector4<T>),
+        (c, _): &(Vector4<T>, Vector4<T>),
+    ) -> Option<Cylinder<T>> {
+        let (t, s) = closest_points(a, na, b, nb)?;
+        let direction = na.xyz().cross(&nb.xyz()).normalize();
+        let axis = Line {
+            coords: (a + na * t + b + nb * s) * convert::<_, T>(0.5),
+            direction: direction.insert_row(3, T::zero()),
+        };
+
+        let radius = (axis.distance(a) + axis.distance(b)) * convert::<_, T>(0.5);
+        let heights = [a, b, c].map(|point| (point - &axis.coords).dot(&axis.direction));
+        let min = { heights.iter().cloned() }.fold(heights[0].clone(), T::min);
+        let max = { heights.iter().cloned() }.fold(heights[0].clone(), T::max);
+
+        Some(Cylinder {
+            circle: Circle {
+                center: &axis.coords + &axis.direction * min.clone(),
+                normal: axis.direction,
+                radius,
+            },
+            height: max - min,
+        })
+    }
+}
+
+impl<T: RealField + ToPrimitive> Estimator<(Vector4<T>, Vector4<T>)>
+    for NormalCylinderEstimator<T>
+{
+    type Model = NormalModel<Cylinder<T>, T>;
+
+    type ModelIter = Option<Self::Model>;
+
+    const MIN_SAMPLES: usize = 3;
+
+    fn estimate<I>(&self, mut data: I) -> Self::ModelIter
+    where
+        I: Iterator<Item = (Vector4<T>, Vector4<T>)> + Clone,
+    {
+        match (data.next(), data.next(), data.next()) {
+            (Some(a), Some(b), Some(c)) => Self::try_make(&a, &b, &c).map(|model| NormalModel {
+                model,
+                normal_weight: self.normal_weight.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+
+    use super::*;
+
+    #[test]
+    fn test_normal_estimators() {
+        let sample =
+            |x: f32, y: f32, z: f32| (Vector4::new(x + 1., y, z, 1.), Vector4::new(x, y, z, 0.));
+
+        let a = sample(2., 0., 0.);
+        let b = sample(0., 0., -2.);
+        let sphere = NormalSphereEstimator::try_make(&a, &b).unwrap();
+        assert!((sphere.coords - Vector4::new(1., 0., 0., 1.)).norm() < 1e-5);
+        assert!((sphere.radius - 2.).abs() < 1e-5);
+
+        let c = sample(0., 2., 0.);
+        let cylinder = NormalCylinderEstimator::try_make(&a, &c, &b).unwrap();
+        assert!((cylinder.circle.radius - 2.).abs() < 1e-5);
+        assert!((cylinder.height - 2.).abs() < 1e-5);
+
+        let model = NormalModel {
+            model: sphere,
+            normal_weight: 0.5,
+        };
+        assert!(model.residual(&c) < 1e-5);
+        assert!(model.residual(&(c.0, Vector4::x())) > 0.5);
+    }
+}
@@ -38,7 +38,7 @@ impl<T: RealField> Plane<T> {
 
 impl<T: RealField + ToPrimitive> Model<Vector4<T>> for Plane<T> {
     fn residual(&self, data: &Vector4<T>) -> f64 {
-        self.distance_directed(data).to_f64().unwrap()
+        self.distance(data).to_f64().unwrap()
     }
 }
 
@@ -145,3 +145,19 @@ impl<T: RealField + ToPrimitive> Estimator<Vector4<T>> for ParallelPlaneEstimato
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plane_residual() {
+        let plane = Plane {
+            coords: Vector4::new(0., 0., 0., 1.),
+            normal: Vector4::new(0., 0., 2., 0.),
+        };
+        // The points behind the plane are as far from it as the ones in front.
+        assert_eq!(plane.residual(&Vector4::new(1., 2., 0.5, 1.)), 0.5);
+        assert_eq!(plane.residual(&Vector4::new(1., 2., -0.5, 1.)), 0.5);
+    }
+}
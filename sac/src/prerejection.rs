@@ -0,0 +1,87 @@
+use std::{iter::Flatten, option};
+
+use nalgebra::{RealField, Vector4};
+use sample_consensus::Estimator;
+
+/// Wraps an estimator to reject the random samples that fail `check`, e.g.
+/// [`is_degenerate`], before estimating and scoring their models. It can be
+/// passed to any consensus in place of the inner estimator.
+pub struct Prerejective<E, F> {
+    pub estimator: E,
+    /// Returns `true` if the samples can be used.
+    pub check: F,
+}
+
+impl<E, F> Prerejective<E, F> {
+    pub fn new(estimator: E, check: F) -> Self {
+        Prerejective { estimator, check }
+    }
+}
+
+impl<E, F, Data> Estimator<Data> for Prerejective<E, F>
+where
+    E: Estimator<Data>,
+    F: Fn(&[Data]) -> bool,
+    Data: Clone,
+{
+    type Model = E::Model;
+
+    type ModelIter = Flatten<option::IntoIter<E::ModelIter>>;
+
+    const MIN_SAMPLES: usize = E::MIN_SAMPLES;
+
+    fn estimate<I>(&self, data: I) -> Self::ModelIter
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let samples = data.collect::<Vec<_>>();
+        let models = (self.check)(&samples).then(|| self.estimator.estimate(samples.into_iter()));
+        models.into_iter().flatten()
+    }
+}
+
+/// Checks if `samples` can't determine a model of 3D shapes, i.e. if any 2
+/// of them are within `tolerance`, or all of them are within `tolerance` of
+/// the line through the first 2.
+pub fn is_degenerate<T: RealField>(samples: &[Vector4<T>], tolerance: T) -> bool {
+    for (index, a) in samples.iter().enumerate() {
+        for b in &samples[index + 1..] {
+            if (a - b).xyz().norm() <= tolerance {
+                return true;
+            }
+        }
+    }
+
+    match samples {
+        [a, b, rest @ ..] if !rest.is_empty() => {
+            let direction = (b - a).xyz().normalize();
+            rest.iter().all(|point| {
+                let side = (point - a).xyz();
+                side.cross(&direction).norm() <= tolerance
+            })
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+
+    use super::*;
+
+    #[test]
+    fn test_degenerate() {
+        let a = Vector4::new(0., 0., 0., 1.);
+        let b = Vector4::new(1., 0., 0., 1.);
+        assert!(is_degenerate(
+            &[a, b, Vector4::new(2., 0.001, 0., 1.)],
+            0.01
+        ));
+        assert!(is_degenerate(
+            &[a, b, Vector4::new(1., 0.001, 0., 1.)],
+            0.01
+        ));
+        assert!(!is_degenerate(&[a, b, Vector4::new(0., 1., 0., 1.)], 0.01));
+    }
+}
@@ -0,0 +1,239 @@
+use std::cmp::Ordering;
+
+use nalgebra::{convert, RealField, Scalar, Vector4};
+use num::ToPrimitive;
+use pcc_common::{point::Point, point_cloud::PointCloud};
+use sample_consensus::Consensus;
+
+use crate::{
+    cone::{Cone, ConeEstimator},
+    cylinder::{Cylinder, CylinderEstimator},
+    line::{Line, LineEstimator},
+    plane::{Plane, PlaneEstimator},
+    sphere::{Sphere, SphereEstimator},
+};
+
+/// The kinds of the primitives fitted by [`BestModelSelector`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PrimitiveKind {
+    Line,
+    Plane,
+    Sphere,
+    Cylinder,
+    Cone,
+}
+
+impl PrimitiveKind {
+    /// The dimension of the manifold of the primitive.
+    pub fn dimension(&self) -> usize {
+        match self {
+            PrimitiveKind::Line => 1,
+            PrimitiveKind::Plane => 2,
+            PrimitiveKind::Sphere => 2,
+            PrimitiveKind::Cylinder => 2,
+            PrimitiveKind::Cone => 2,
+        }
+    }
+
+    /// The number of degrees of freedom of the primitive.
+    pub fn num_params(&self) -> usize {
+        match self {
+            PrimitiveKind::Line => 4,
+            PrimitiveKind::Plane => 3,
+            PrimitiveKind::Sphere => 4,
+            // The axis, the radius and the 2 ends.
+            PrimitiveKind::Cylinder => 7,
+            // The apex, the axis, the angle and the base.
+            PrimitiveKind::Cone => 7,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Primitive<T: Scalar> {
+    Line(Line<T>),
+    Plane(Plane<T>),
+    Sphere(Sphere<T>),
+    Cylinder(Cylinder<T>),
+    Cone(Cone<T>),
+}
+
+impl<T: RealField> Primitive<T> {
+    pub fn kind(&self) -> PrimitiveKind {
+        match self {
+            Primitive::Line(_) => PrimitiveKind::Line,
+            Primitive::Plane(_) => PrimitiveKind::Plane,
+            Primitive::Sphere(_) => PrimitiveKind::Sphere,
+            Primitive::Cylinder(_) => PrimitiveKind::Cylinder,
+            Primitive::Cone(_) => PrimitiveKind::Cone,
+        }
+    }
+
+    pub fn distance(&self, point: &Vector4<T>) -> T {
+        match self {
+            Primitive::Line(line) => line.distance(point),
+            Primitive::Plane(plane) => plane.distance(point),
+            Primitive::Sphere(sphere) => sphere.distance(point),
+            Primitive::Cylinder(cylinder) => cylinder.distance(point),
+            Primitive::Cone(cone) => cone.distance(point),
+        }
+    }
+}
+
+/// Fits several kinds of primitives to the same region of a point cloud, and
+/// selects the one that explains it best, e.g. for abstracting scenes into
+/// primitives.
+///
+/// The candidates are compared by their GRIC (Geometric Robust Information
+/// Criterion) scores per point, which penalize the residuals robustly as well
+/// as the dimensions and the numbers of parameters of the primitives, so that
+/// simpler primitives are preferred unless the complex ones fit notably
+/// better. Lower scores are better.
+pub struct BestModelSelector<C, T: Scalar> {
+    pub consensus: C,
+    /// The standard deviation of the noise of the points.
+    pub sigma: T,
+    pub kinds: Vec<PrimitiveKind>,
+}
+
+impl<C, T: Scalar> BestModelSelector<C, T> {
+    /// Creates a selector of all the kinds of primitives.
+    pub fn new(consensus: C, sigma: T) -> Self {
+        BestModelSelector {
+            consensus,
+            sigma,
+            kinds: vec![
+                PrimitiveKind::Line,
+                PrimitiveKind::Plane,
+                PrimitiveKind::Sphere,
+                PrimitiveKind::Cylinder,
+                PrimitiveKind::Cone,
+            ],
+        }
+    }
+}
+
+impl<C, T> BestModelSelector<C, T>
+where
+    C: Consensus<LineEstimator, Vector4<T>>
+        + Consensus<PlaneEstimator, Vector4<T>>
+        + Consensus<SphereEstimator, Vector4<T>>
+        + Consensus<CylinderEstimator, Vector4<T>>
+        + Consensus<ConeEstimator, Vector4<T>>,
+    T: RealField + ToPrimitive,
+{
+    /// The GRIC score of `primitive` per point of `coords`.
+    pub fn score(&self, primitive: &Primitive<T>, coords: &[Vector4<T>]) -> T {
+        let kind = primitive.kind();
+        let num = T::from_usize(coords.len()).unwrap();
+        let dimension = T::from_usize(kind.dimension()).unwrap();
+        let num_params = T::from_usize(kind.num_params()).unwrap();
+        // The dimension of the data.
+        let r = convert::<_, T>(3.);
+
+        let sigma2 = self.sigma.clone() * self.sigma.clone();
+        let max_residual = convert::<_, T>(2.) * (r.clone() - dimension.clone());
+        let residuals = { coords.iter() }.fold(T::zero(), |acc, coords| {
+            let distance = primitive.distance(coords);
+            let residual = distance.clone() * distance / sigma2.clone();
+            acc + residual.min(max_residual.clone())
+        });
+
+        let gric = residuals
+            + r.clone().ln() * dimension * num.clone()
+            + (r * num.clone()).ln() * num_params;
+        gric / num
+    }
+
+    fn fit_kind(&mut self, kind: PrimitiveKind, coords: &[Vector4<T>]) -> Option<Primitive<T>> {
+        let data = coords.iter().cloned();
+        Some(match kind {
+            PrimitiveKind::Line => Primitive::Line(self.consensus.model(&LineEstimator, data)?),
+            PrimitiveKind::Plane => Primitive::Plane(self.consensus.model(&PlaneEstimator, data)?),
+            PrimitiveKind::Sphere => {
+                Primitive::Sphere(self.consensus.model(&SphereEstimator, data)?)
+            }
+            PrimitiveKind::Cylinder => {
+                Primitive::Cylinder(self.consensus.model(&CylinderEstimator, data)?)
+            }
+            PrimitiveKind::Cone => Primitive::Cone(self.consensus.model(&ConeEstimator, data)?),
+        })
+    }
+
+    /// Fits every kind of primitives in `kinds` to `input`, and returns the
+    /// fitted ones with their scores from the best to the worst.
+    pub fn fit<P: Point<Data = T>>(&mut self, input: &PointCloud<P>) -> Vec<(Primitive<T>, T)> {
+        let coords = { input.iter() }
+            .map(|point| point.coords().clone())
+            .filter(|coords| coords.iter().all(|x| x.is_finite()))
+            .collect::<Vec<_>>();
+
+        let mut candidates = Vec::with_capacity(self.kinds.len());
+        for kind in self.kinds.clone() {
+            if let Some(primitive) = self.fit_kind(kind, &coords) {
+                let score = self.score(&primitive, &coords);
+                candidates.push((primitive, score));
+            }
+        }
+        candidates.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        candidates
+    }
+
+    /// The best primitive fitted to `input` and its score, or `None` if no
+    /// primitive can be fitted.
+    pub fn select<P: Point<Data = T>>(
+        &mut self,
+        input: &PointCloud<P>,
+    ) -> Option<(Primitive<T>, T)> {
+        self.fit(input).into_iter().next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pcc_common::point::Point3;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::Arrsac;
+
+    fn point_cloud(coords: impl Iterator<Item = [f32; 3]>) -> PointCloud<Point3> {
+        let storage = { coords.map(|[x, y, z]| Vector4::new(x, y, z, 1.)) }
+            .map(|coords| Point3::default().with_coords(coords))
+            .collect::<Vec<_>>();
+        PointCloud::from_vec(storage, 1)
+    }
+
+    #[test]
+    fn test_best_model_selector() {
+        let mut selector =
+            BestModelSelector::new(Arrsac::new(0.01, StdRng::seed_from_u64(0)), 0.01);
+
+        let plane =
+            point_cloud({ 0..100 }.map(|i| [(i % 10) as f32 * 0.1, (i / 10) as f32 * 0.1, 0.]));
+        let (primitive, _) = selector.select(&plane).unwrap();
+        assert_eq!(primitive.kind(), PrimitiveKind::Plane);
+
+        let sphere = point_cloud({ 0..100 }.map(|i| {
+            let (theta, phi) = ((i % 10) as f32 * 0.6, (i / 10) as f32 * 0.3 + 0.1);
+            [phi.sin() * theta.cos(), phi.sin() * theta.sin(), phi.cos()]
+        }));
+        let (primitive, _) = selector.select(&sphere).unwrap();
+        assert_eq!(primitive.kind(), PrimitiveKind::Sphere);
+
+        // A simpler primitive wins a tie with a complex one.
+        let coords = { 0..20 }
+            .map(|i| Vector4::new(i as f32 * 0.1, 0., 0., 1.))
+            .collect::<Vec<_>>();
+        let origin = Vector4::new(0., 0., 0., 1.);
+        let line = Primitive::Line(Line {
+            coords: origin,
+            direction: Vector4::x(),
+        });
+        let plane = Primitive::Plane(Plane {
+            coords: origin,
+            normal: Vector4::z(),
+        });
+        assert!(selector.score(&line, &coords) < selector.score(&plane, &coords));
+    }
+}
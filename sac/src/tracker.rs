@@ -0,0 +1,151 @@
+use nalgebra::{RealField, Vector4};
+use pcc_common::{point::Point, point_cloud::PointCloud};
+use sample_consensus::{Consensus, Estimator};
+
+use crate::plane::Plane;
+
+/// Tracks a dominant plane, e.g. the ground, across the frames of a
+/// sequence.
+///
+/// The estimation of each frame is warm-started from the plane of the
+/// previous one by only sampling the points within `band` of it. The result
+/// is accepted if its inliers overlap those of the previous plane by at least
+/// `min_overlap` in the intersection over union; otherwise the plane is
+/// re-detected from the whole frame.
+pub struct PlaneTracker<C, T: RealField> {
+    pub consensus: C,
+    /// The maximum distance of the inliers to the plane.
+    pub threshold: T,
+    /// The maximum distance to the previous plane of the points sampled for
+    /// warm-starting.
+    pub band: T,
+    pub min_overlap: T,
+    plane: Option<Plane<T>>,
+}
+
+impl<C, T: RealField> PlaneTracker<C, T> {
+    pub fn new(consensus: C, threshold: T, band: T, min_overlap: T) -> Self {
+        PlaneTracker {
+            consensus,
+            threshold,
+            band,
+            min_overlap,
+            plane: None,
+        }
+    }
+
+    /// The plane of the last tracked frame.
+    pub fn plane(&self) -> Option<&Plane<T>> {
+        self.plane.as_ref()
+    }
+
+    /// Forgets the tracked plane so that the next frame is detected from
+    /// scratch.
+    pub fn reset(&mut self) {
+        self.plane = None
+    }
+
+    fn inliers<P: Point<Data = T>>(
+        plane: &Plane<T>,
+        input: &PointCloud<P>,
+        threshold: &T,
+    ) -> Vec<usize> {
+        { input.iter().enumerate() }
+            .filter(|(_, point)| plane.distance(point.coords()) <= *threshold)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Estimates the plane of `input` with `estimator`, and returns it with
+    /// its inliers, or `None` if no plane is found, in which case the tracked
+    /// plane is also lost.
+    pub fn track<E, P>(
+        &mut self,
+        estimator: &E,
+        input: &PointCloud<P>,
+    ) -> Option<(Plane<T>, Vec<usize>)>
+    where
+        E: Estimator<Vector4<T>, Model = Plane<T>>,
+        C: Consensus<E, Vector4<T>>,
+        P: Point<Data = T>,
+    {
+        let warm = self.plane.take().and_then(|previous| {
+            let near = Self::inliers(&previous, input, &self.band);
+            let (plane, _) = self.consensus.model_inliers(
+                estimator,
+                near.iter().map(|&index| input[index].coords().clone()),
+            )?;
+
+            let inliers = Self::inliers(&plane, input, &self.threshold);
+            let expected = Self::inliers(&previous, input, &self.threshold);
+            let intersection = { inliers.iter() }
+                .filter(|index| expected.binary_search(index).is_ok())
+                .count();
+            let union = inliers.len() + expected.len() - intersection;
+            let overlap = T::from_usize(intersection).unwrap() / T::from_usize(union).unwrap();
+            (overlap >= self.min_overlap).then_some((plane, inliers))
+        });
+
+        let result = warm.or_else(|| {
+            let (plane, _) = self
+                .consensus
+                .model_inliers(estimator, input.iter().map(|point| point.coords().clone()))?;
+            let inliers = Self::inliers(&plane, input, &self.threshold);
+            Some((plane, inliers))
+        });
+        self.plane = result.as_ref().map(|(plane, _)| plane.clone());
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pcc_common::point::Point3;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{base::Arrsac, plane::PlaneEstimator};
+
+    /// Optionally a wall at `x = 4`, and the smaller ground at `z = height`
+    /// below it.
+    fn frame(height: f32, wall: bool) -> PointCloud<Point3> {
+        let ground = { (0..12).flat_map(|x| (0..12).map(move |y| (x, y))) }
+            .map(|(x, y)| Vector4::new(x as f32 * 0.25, y as f32 * 0.25, height, 1.));
+        let wall = { (0..16).flat_map(|y| (0..16).map(move |z| (y, z))) }
+            .filter(|_| wall)
+            .map(|(y, z)| Vector4::new(4., y as f32 * 0.25, 0.5 + z as f32 * 0.25, 1.));
+        let storage = { wall.chain(ground) }
+            .map(|coords| Point3::default().with_coords(coords))
+            .collect::<Vec<_>>();
+        PointCloud::from_vec(storage, 1)
+    }
+
+    #[test]
+    fn test_plane_tracker() {
+        let arrsac = Arrsac::new(0.01, StdRng::seed_from_u64(0));
+        let mut tracker = PlaneTracker::new(arrsac, 0.05, 0.1, 0.5);
+        let axis = |plane: &Plane<f32>| plane.normal.xyz().normalize().map(f32::abs);
+
+        let (plane, inliers) = tracker.track(&PlaneEstimator, &frame(0., false)).unwrap();
+        assert!(axis(&plane).z > 0.99);
+        assert_eq!(inliers.len(), 144);
+
+        // The ground is still tracked, though the wall is larger.
+        let input = frame(0.02, true);
+        let (plane, inliers) = tracker.track(&PlaneEstimator, &input).unwrap();
+        assert!(axis(&plane).z > 0.99);
+        assert!((plane.distance(&Vector4::new(0., 0., 0.02, 1.))) < 0.01);
+        assert_eq!(inliers, (256..400).collect::<Vec<_>>());
+        assert_eq!(tracker.plane(), Some(&plane));
+
+        // Nothing is near the tracked plane, so the wall is detected instead.
+        let (plane, inliers) = tracker.track(&PlaneEstimator, &frame(1., true)).unwrap();
+        assert!(axis(&plane).x > 0.99);
+        assert_eq!(inliers.len(), 256);
+
+        tracker.reset();
+        assert_eq!(tracker.plane(), None);
+        let (plane, _) = tracker.track(&PlaneEstimator, &input).unwrap();
+        assert!(axis(&plane).x > 0.99);
+    }
+}
@@ -0,0 +1,142 @@
+use nalgebra::RealField;
+use num::ToPrimitive;
+use pcc_common::{point::Point, point_cloud::PointCloud, search::Search};
+use pcc_kdtree::KdTree;
+
+use crate::{__searcher, OrganizedNeighbor};
+
+/// Builds the searcher of a point cloud on its first use and shares it among
+/// the following filters and features on the same point cloud, e.g. in one
+/// frame of a pipeline, instead of each of them building its own.
+pub struct SearchCache<'a, P: Point> {
+    input: &'a PointCloud<P>,
+    epsilon: P::Data,
+    storage: (Option<OrganizedNeighbor<'a, P>>, Option<KdTree<'a, P>>),
+}
+
+impl<'a, P: Point> SearchCache<'a, P>
+where
+    P::Data: RealField + ToPrimitive,
+{
+    /// `epsilon` is the tolerance of the projection of organized point clouds,
+    /// as in [`searcher!`](crate::searcher).
+    pub fn new(input: &'a PointCloud<P>, epsilon: P::Data) -> Self {
+        SearchCache {
+            input,
+            epsilon,
+            storage: (None, None),
+        }
+    }
+
+    #[inline]
+    pub fn input(&self) -> &'a PointCloud<P> {
+        self.input
+    }
+
+    /// Whether the searcher has been built.
+    #[inline]
+    pub fn is_built(&self) -> bool {
+        self.storage.0.is_some() || self.storage.1.is_some()
+    }
+
+    /// Returns the searcher chosen like [`searcher!`](crate::searcher),
+    /// building it on the first call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the input is empty.
+    pub fn searcher(&mut self) -> &dyn Search<'a, P> {
+        if !self.is_built() {
+            __searcher(self.input, self.epsilon.clone(), &mut self.storage);
+        }
+        match self.storage {
+            (Some(ref org_neigh), _) => org_neigh as _,
+            (None, Some(ref kdtree)) => kdtree as _,
+            (None, None) => unreachable!(),
+        }
+    }
+
+    /// Returns the k-d tree of the input, building it on the first call even
+    /// if the input is organized. If no searcher is built before, later calls
+    /// to [`Self::searcher`] reuse this tree.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the input is empty.
+    pub fn kdtree(&mut self) -> &KdTree<'a, P> {
+        let input = self.input;
+        self.storage.1.get_or_insert_with(|| KdTree::new(input))
+    }
+
+    /// Drops the searchers built, e.g. when they are no longer needed in a
+    /// long-lived pipeline.
+    pub fn clear(&mut self) {
+        self.storage = (None, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::{point::Point3, search::SearchType};
+
+    use super::*;
+
+    /// The address of a searcher, to tell which one is returned.
+    fn addr<T: ?Sized>(searcher: &T) -> *const u8 {
+        searcher as *const T as *const u8
+    }
+
+    #[test]
+    fn test_search_cache() {
+        let storage = { (0..10).flat_map(|y| (0..10).map(move |x| (x, y))) }
+            .map(|(x, y)| {
+                Vector4::new(
+                    x as f32 * 0.1,
+                    y as f32 * 0.1,
+                    ((x * y) % 3) as f32 * 0.01,
+                    1.,
+                )
+            })
+            .map(|coords| Point3::default().with_coords(coords))
+            .collect::<Vec<_>>();
+        let organized = PointCloud::from_vec(storage.clone(), 10);
+        let unorganized = PointCloud::from_vec(storage, 1);
+
+        let mut cache = SearchCache::new(&organized, f32::EPSILON);
+        assert!(!cache.is_built());
+        let searcher = addr(cache.searcher());
+        assert!(cache.is_built());
+        assert_eq!(addr(cache.searcher()), searcher);
+        // The organized searcher is kept after the tree is built.
+        let kdtree = addr(cache.kdtree());
+        assert_ne!(kdtree, searcher);
+        assert_eq!(addr(cache.searcher()), searcher);
+
+        let pivot = Vector4::new(0.42, 0.37, 0., 1.);
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        cache
+            .searcher()
+            .search(&pivot, SearchType::Radius(0.25), &mut a);
+        cache
+            .kdtree()
+            .search(&pivot, SearchType::Radius(0.25), &mut b);
+        a.sort_by_key(|&(index, _)| index);
+        b.sort_by_key(|&(index, _)| index);
+        assert!(a.len() > 10);
+        assert_eq!(
+            a.iter().map(|&(index, _)| index).collect::<Vec<_>>(),
+            b.iter().map(|&(index, _)| index).collect::<Vec<_>>()
+        );
+
+        cache.clear();
+        assert!(!cache.is_built());
+
+        // The tree built first is shared by the unorganized point cloud.
+        let mut cache = SearchCache::new(&unorganized, f32::EPSILON);
+        let kdtree = addr(cache.kdtree());
+        assert!(cache.is_built());
+        assert_eq!(addr(cache.searcher()), kdtree);
+        assert_eq!(cache.input().len(), 100);
+    }
+}
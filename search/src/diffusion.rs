@@ -0,0 +1,97 @@
+use nalgebra::{convert, RealField};
+
+use crate::KnnGraph;
+
+impl<T: RealField> KnnGraph<T> {
+    /// The mean of the finite `values` of the neighbors of the point `index`,
+    /// or `None` if there are none.
+    fn neighbor_mean(&self, index: usize, values: &[T]) -> Option<T> {
+        let (sum, num) = { self.neighbors(index) }
+            .map(|(neighbor, _)| &values[neighbor])
+            .filter(|value| value.is_finite())
+            .fold((T::zero(), 0), |(sum, num), value| {
+                (sum + value.clone(), num + 1)
+            });
+        (num > 0).then(|| sum / T::from_usize(num).unwrap())
+    }
+
+    /// Smooths the per-point `values` with Laplacian smoothing, which moves
+    /// each value by `lambda` towards the mean of its neighbors in every
+    /// iteration.
+    ///
+    /// Non-finite values are left as is and don't affect their neighbors.
+    pub fn diffuse(&self, values: &mut [T], lambda: T, iterations: usize) {
+        let mut next = values.to_vec();
+        for _ in 0..iterations {
+            for (index, value) in next.iter_mut().enumerate() {
+                if !values[index].is_finite() {
+                    continue;
+                }
+                if let Some(mean) = self.neighbor_mean(index, values) {
+                    let delta = mean - values[index].clone();
+                    *value = values[index].clone() + delta * lambda.clone();
+                }
+            }
+            values.clone_from_slice(&next);
+        }
+    }
+
+    /// Propagates the sparse known `values` to the unknown ones, each of
+    /// which becomes the mean of its known or already estimated neighbors in
+    /// every iteration, approximating the harmonic interpolation of the known
+    /// values.
+    ///
+    /// The unknown values that nothing reaches within `iterations` stay
+    /// `None`.
+    pub fn propagate(&self, values: &[Option<T>], iterations: usize) -> Vec<Option<T>> {
+        let nan = convert::<_, T>(f64::NAN);
+        let to_dense = |values: &[Option<T>]| {
+            { values.iter() }
+                .map(|value| value.clone().unwrap_or_else(|| nan.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let mut current = values.to_vec();
+        for _ in 0..iterations {
+            let dense = to_dense(&current);
+            let mut changed = false;
+            for (index, value) in current.iter_mut().enumerate() {
+                if values[index].is_some() {
+                    continue;
+                }
+                let mean = self.neighbor_mean(index, &dense);
+                changed |= mean != *value;
+                *value = mean;
+            }
+            if !changed {
+                break;
+            }
+        }
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::KnnGraph;
+
+    #[test]
+    fn test_propagate() {
+        // A path graph 0 - 1 - 2 - 3 - 4.
+        let graph = KnnGraph {
+            offsets: vec![0, 1, 3, 5, 7, 8],
+            indices: vec![1, 0, 2, 1, 3, 2, 4, 3],
+            distances: vec![1.; 8],
+        };
+
+        let values = graph.propagate(&[Some(0.), None, None, None, Some(4.)], 1000);
+        for (index, value) in values.into_iter().enumerate() {
+            assert!((value.unwrap() - index as f32).abs() < 1e-3);
+        }
+
+        // The values converge to their mean weighted by the degrees.
+        let mut values = [0., 0., 3., 0., 0.];
+        graph.diffuse(&mut values, 0.5, 100);
+        assert!(values.iter().all(|value| (value - 0.75).abs() < 1e-3));
+    }
+}
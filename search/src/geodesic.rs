@@ -0,0 +1,178 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+};
+
+use nalgebra::RealField;
+
+use crate::KnnGraph;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Seed<T>(T, usize);
+
+impl<T: PartialEq> Eq for Seed<T> {}
+
+impl<T: PartialOrd> PartialOrd for Seed<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: PartialOrd> Ord for Seed<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        { self.0.partial_cmp(&other.0) }
+            .unwrap_or(Ordering::Equal)
+            .then(self.1.cmp(&other.1))
+    }
+}
+
+impl<T: RealField> KnnGraph<T> {
+    /// Adds the reverse of every edge, so that the graph becomes undirected
+    /// as the geodesics on the surface are.
+    pub fn symmetrize(&self) -> Self {
+        let mut rows = vec![Vec::new(); self.len()];
+        for (index, row) in rows.iter_mut().enumerate() {
+            row.extend(self.neighbors(index).map(|(n, d)| (n, d.clone())));
+        }
+        for index in 0..self.len() {
+            for (neighbor, distance) in self.neighbors(index) {
+                rows[neighbor].push((index, distance.clone()));
+            }
+        }
+
+        let mut graph = KnnGraph {
+            offsets: vec![0],
+            indices: Vec::with_capacity(self.num_edges() * 2),
+            distances: Vec::with_capacity(self.num_edges() * 2),
+        };
+        for mut row in rows {
+            row.sort_by(|(a, _), (b, _)| a.cmp(b));
+            row.dedup_by_key(|(index, _)| *index);
+            row.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+            for (index, distance) in row {
+                graph.indices.push(index);
+                graph.distances.push(distance);
+            }
+            graph.offsets.push(graph.indices.len());
+        }
+        graph
+    }
+
+    /// Runs Dijkstra's algorithm from `seeds` on top of the known
+    /// `distances`, updating the ones that get shorter, until `target` is
+    /// settled if any.
+    fn dijkstra(
+        &self,
+        distances: &mut [Option<T>],
+        parents: &mut [Option<usize>],
+        seeds: &[usize],
+        target: Option<usize>,
+    ) {
+        let mut heap = BinaryHeap::new();
+        for &seed in seeds {
+            distances[seed] = Some(T::zero());
+            parents[seed] = None;
+            heap.push(Reverse(Seed(T::zero(), seed)));
+        }
+
+        while let Some(Reverse(Seed(distance, index))) = heap.pop() {
+            if distances[index].as_ref().map_or(false, |d| *d < distance) {
+                continue;
+            }
+            if Some(index) == target {
+                break;
+            }
+            for (neighbor, weight) in self.neighbors(index) {
+                let next = distance.clone() + weight.clone();
+                if distances[neighbor].as_ref().map_or(true, |d| next < *d) {
+                    distances[neighbor] = Some(next.clone());
+                    parents[neighbor] = Some(index);
+                    heap.push(Reverse(Seed(next, neighbor)));
+                }
+            }
+        }
+    }
+
+    /// The lengths of the shortest paths along the edges from the nearest
+    /// point of `seeds` to every point, or `None` for the unreachable ones.
+    ///
+    /// The paths follow the directions of the edges, so the graph should be
+    /// [symmetrized](KnnGraph::symmetrize) first for geodesic distances.
+    pub fn geodesic_distances(&self, seeds: &[usize]) -> Vec<Option<T>> {
+        let mut distances = vec![None; self.len()];
+        let mut parents = vec![None; self.len()];
+        self.dijkstra(&mut distances, &mut parents, seeds, None);
+        distances
+    }
+
+    /// The shortest path from `from` to `to` including both ends, and its
+    /// length, or `None` if `to` is unreachable.
+    pub fn shortest_path(&self, from: usize, to: usize) -> Option<(Vec<usize>, T)> {
+        let mut distances = vec![None; self.len()];
+        let mut parents = vec![None; self.len()];
+        self.dijkstra(&mut distances, &mut parents, &[from], Some(to));
+
+        let length = distances[to].clone()?;
+        let mut path = vec![to];
+        while let Some(parent) = parents[*path.last().unwrap()] {
+            path.push(parent);
+        }
+        path.reverse();
+        Some((path, length))
+    }
+
+    /// Samples up to `num` points starting from `start`, each of which is the
+    /// farthest from the previous ones in geodesic distance, which spreads
+    /// them evenly over the surface.
+    ///
+    /// Only the points reachable from `start` are sampled.
+    pub fn farthest_geodesic_sampling(&self, num: usize, start: usize) -> Vec<usize> {
+        let mut distances = vec![None; self.len()];
+        let mut parents = vec![None; self.len()];
+        let mut samples = Vec::with_capacity(num);
+
+        let mut next = Some(start);
+        while let Some(index) = next.filter(|_| samples.len() < num) {
+            samples.push(index);
+            self.dijkstra(&mut distances, &mut parents, &[index], None);
+
+            next = { distances.iter().enumerate() }
+                .filter_map(|(index, distance)| Some((index, distance.clone()?)))
+                .filter(|(_, distance)| *distance > T::zero())
+                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+                .map(|(index, _)| index);
+        }
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::{
+        point::{Point, Point3},
+        point_cloud::PointCloud,
+    };
+
+    use crate::build_knn_graph;
+
+    #[test]
+    fn test_geodesic() {
+        let storage = [0., 1., 2., 4., 7.]
+            .map(|x| Point3::default().with_coords(Vector4::new(x, 0., 0., 1.)));
+        let input = PointCloud::from_vec(storage.to_vec(), 1);
+        let graph = build_knn_graph(&input, 1).symmetrize();
+
+        let distances = graph.geodesic_distances(&[0]);
+        assert_eq!(
+            distances,
+            [Some(0.), Some(1.), Some(2.), Some(4.), Some(7.)]
+        );
+
+        let (path, length) = graph.shortest_path(4, 1).unwrap();
+        assert_eq!(path, [4, 3, 2, 1]);
+        assert_eq!(length, 6.);
+
+        assert_eq!(graph.farthest_geodesic_sampling(3, 0), [0, 4, 3]);
+    }
+}
@@ -0,0 +1,183 @@
+use std::{cmp::Ordering, ops::Range};
+
+use nalgebra::RealField;
+use pcc_common::{
+    point::Point,
+    point_cloud::PointCloud,
+    search::{Search, SearchType},
+};
+use pcc_kdtree::KdTree;
+use rayon::prelude::*;
+
+/// The k-nearest-neighbor graph of a point cloud in the compressed sparse row
+/// format.
+///
+/// The neighbors of the point `i` are `indices[offsets[i]..offsets[i + 1]]`
+/// sorted by their distances in `distances`. The graph is directed: a point
+/// being a neighbor of another doesn't make the converse true.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct KnnGraph<T> {
+    pub offsets: Vec<usize>,
+    pub indices: Vec<usize>,
+    pub distances: Vec<T>,
+}
+
+impl<T: RealField> KnnGraph<T> {
+    /// Searches the `k` nearest neighbors of every point of `searcher`'s
+    /// input in parallel, excluding the point itself. Non-finite points have
+    /// no neighbors and are no one's neighbors.
+    pub fn new<'a, P, S>(searcher: &S, k: usize) -> Self
+    where
+        P: Point<Data = T> + Sync + 'a,
+        S: Search<'a, P> + Sync + ?Sized,
+    {
+        let input = searcher.input();
+        let rows = { (0..input.len()).into_par_iter() }
+            .map_init(Vec::new, |result, index| {
+                let point = &input[index];
+                if !point.is_finite() {
+                    return Vec::new();
+                }
+                searcher.search_exact(point.coords(), SearchType::Knn(k + 1), result);
+
+                let mut row = { result.drain(..) }
+                    .filter(|(neighbor, distance)| *neighbor != index && distance.is_finite())
+                    .collect::<Vec<_>>();
+                row.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+                row.truncate(k);
+                row
+            })
+            .collect::<Vec<_>>();
+
+        let mut offsets = Vec::with_capacity(rows.len() + 1);
+        offsets.push(0);
+        let len = rows.iter().map(Vec::len).sum();
+        let mut indices = Vec::with_capacity(len);
+        let mut distances = Vec::with_capacity(len);
+        for row in rows {
+            for (index, distance) in row {
+                indices.push(index);
+                distances.push(distance);
+            }
+            offsets.push(indices.len());
+        }
+
+        KnnGraph {
+            offsets,
+            indices,
+            distances,
+        }
+    }
+}
+
+impl<T> KnnGraph<T> {
+    /// The number of vertices, i.e. points.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of directed edges.
+    #[inline]
+    pub fn num_edges(&self) -> usize {
+        self.indices.len()
+    }
+
+    #[inline]
+    fn range(&self, index: usize) -> Range<usize> {
+        self.offsets[index]..self.offsets[index + 1]
+    }
+
+    /// The neighbors of the point `index` and their distances, nearest first.
+    pub fn neighbors(&self, index: usize) -> impl Iterator<Item = (usize, &T)> + '_ {
+        let range = self.range(index);
+        self.indices[range.clone()]
+            .iter()
+            .copied()
+            .zip(&self.distances[range])
+    }
+}
+
+/// Builds the k-nearest-neighbor graph of `input` with a k-d tree on its
+/// finite points. See [`KnnGraph::new`] for details.
+pub fn build_knn_graph<P>(input: &PointCloud<P>, k: usize) -> KnnGraph<P::Data>
+where
+    P: Point + Clone + Sync,
+    P::Data: RealField,
+{
+    let finite = { input.iter().enumerate() }
+        .filter(|(_, point)| point.is_finite())
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    if finite.is_empty() {
+        return KnnGraph {
+            offsets: vec![0; input.len() + 1],
+            indices: Vec::new(),
+            distances: Vec::new(),
+        };
+    }
+
+    let sub = input.create_sub(&finite, 1);
+    let KnnGraph {
+        offsets: sub_offsets,
+        mut indices,
+        distances,
+    } = KnnGraph::new(&KdTree::new(&sub), k);
+    for index in &mut indices {
+        *index = finite[*index];
+    }
+
+    let mut offsets = Vec::with_capacity(input.len() + 1);
+    offsets.push(0);
+    let mut next = finite.iter().zip(&sub_offsets[1..]).peekable();
+    for index in 0..input.len() {
+        let last = *offsets.last().unwrap();
+        match next.next_if(|(&finite, _)| finite == index) {
+            Some((_, &offset)) => offsets.push(offset),
+            None => offsets.push(last),
+        }
+    }
+
+    KnnGraph {
+        offsets,
+        indices,
+        distances,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::{
+        point::{Point, Point3},
+        point_cloud::PointCloud,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_knn_graph() {
+        let storage = [0., 1., 3., 6., f32::NAN]
+            .map(|x| Point3::default().with_coords(Vector4::new(x, 0., 0., 1.)));
+        let input = PointCloud::from_vec(storage.to_vec(), 1);
+
+        let graph = build_knn_graph(&input, 2);
+        assert_eq!(graph.len(), 5);
+        assert_eq!(graph.num_edges(), 8);
+
+        let neighbors = graph.neighbors(1).collect::<Vec<_>>();
+        assert_eq!(neighbors, [(0, &1.), (2, &2.)]);
+        let neighbors = graph
+            .neighbors(3)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        assert_eq!(neighbors, [2, 1]);
+        assert_eq!(graph.neighbors(4).count(), 0);
+    }
+}
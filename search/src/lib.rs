@@ -1,4 +1,9 @@
+mod cache;
+mod diffusion;
+mod geodesic;
+mod graph;
 mod neighbors;
+mod voxel_hash;
 
 use nalgebra::RealField;
 use num::ToPrimitive;
@@ -6,7 +11,12 @@ use pcc_common::{point::Point, point_cloud::PointCloud, search::Search};
 pub use pcc_kdtree::*;
 pub use pcc_octree::*;
 
-pub use self::neighbors::*;
+pub use self::{
+    cache::SearchCache,
+    graph::{build_knn_graph, KnnGraph},
+    neighbors::*,
+    voxel_hash::VoxelHashSearch,
+};
 
 #[inline]
 pub fn __searcher<'a, 'b, T, P>(
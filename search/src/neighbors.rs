@@ -46,6 +46,18 @@ where
     P: Point,
     P::Data: RealField + ToPrimitive,
 {
+    /// The pixels between the roots `r1` and `r2` of the search box, clamped
+    /// to `0..=limit`.
+    fn span(r1: P::Data, r2: P::Data, limit: usize) -> [usize; 2] {
+        let (min, max) = (r1.clone().min(r2.clone()).floor(), r1.max(r2).ceil());
+        if !(min.is_finite() && max.is_finite()) {
+            return [0, limit];
+        }
+        let limit = P::Data::from_usize(limit).unwrap();
+        let clamp = |x: P::Data| x.clamp(zero(), limit.clone()).to_usize().unwrap();
+        [clamp(min), clamp(max)]
+    }
+
     fn search_box(&self, pivot: &Vector4<P::Data>, radius_sqr: P::Data) -> [usize; 4] {
         let pp = &self.kr * pivot.xyz() + self.proj_matrix.column(3);
 
@@ -58,16 +70,7 @@ where
         let [ymin, ymax] = if d >= zero() {
             let y1 = (b.clone() - d.clone().sqrt()) / a.clone();
             let y2 = (b + d.sqrt()) / a.clone();
-
-            let min = y1
-                .clone()
-                .floor()
-                .min(y2.clone().floor())
-                .to_usize()
-                .unwrap();
-            let max = y1.ceil().min(y2.ceil()).to_usize().unwrap();
-
-            [min.clamp(0, ylimit), max.clamp(0, ylimit)]
+            Self::span(y1, y2, ylimit)
         } else {
             [0, ylimit]
         };
@@ -79,16 +82,7 @@ where
         let [xmin, xmax] = if d >= zero() {
             let x1 = (b.clone() - d.clone().sqrt()) / a.clone();
             let x2 = (b + d.sqrt()) / a;
-
-            let min = x1
-                .clone()
-                .floor()
-                .min(x2.clone().floor())
-                .to_usize()
-                .unwrap();
-            let max = x1.ceil().min(x2.ceil()).to_usize().unwrap();
-
-            [min.clamp(0, xlimit), max.clamp(0, xlimit)]
+            Self::span(x1, x2, xlimit)
         } else {
             [0, xlimit]
         };
@@ -103,14 +97,26 @@ where
         result: &mut Vec<(usize, P::Data)>,
     ) {
         result.clear();
+        self.radius_search_with(pivot, radius, |index, distance| {
+            result.push((index, distance))
+        });
+    }
 
+    /// Passes the points within `radius` to `visitor` with their distances
+    /// instead of collecting them.
+    pub fn radius_search_with(
+        &self,
+        pivot: &Vector4<P::Data>,
+        radius: P::Data,
+        mut visitor: impl FnMut(usize, P::Data),
+    ) {
         let [xmin, xmax, ymin, ymax] = self.search_box(pivot, radius.clone() * radius.clone());
         for x in xmin..=xmax {
             for y in ymin..=ymax {
                 let index = self.point_cloud.width() * y + x;
                 let distance = (self.point_cloud[index].coords() - pivot).norm();
                 if distance <= radius {
-                    result.push((index, distance));
+                    visitor(index, distance);
                 }
             }
         }
@@ -172,7 +178,7 @@ where
                 top.chain(bottom).chain(left).chain(right).peekable()
             };
             if points.peek().is_none() {
-                result.extend(rr.into_iter().map(|(d, v)| (v, d)));
+                rr.drain_sorted_into(result);
                 break;
             }
 
@@ -211,6 +217,37 @@ where
         match ty {
             SearchType::Knn(n) => self.knn_search(pivot, n, result),
             SearchType::Radius(radius) => self.radius_search(pivot, radius, result),
+            SearchType::KnnWithin { k, radius } => {
+                self.knn_search(pivot, k, result);
+                result.retain(|(_, distance)| *distance < radius);
+            }
+        }
+    }
+
+    fn search_with(
+        &self,
+        pivot: &Vector4<P::Data>,
+        ty: SearchType<P::Data>,
+        visitor: &mut dyn FnMut(usize, P::Data),
+    ) {
+        match ty {
+            SearchType::Knn(n) => {
+                let mut result = Vec::new();
+                self.knn_search(pivot, n, &mut result);
+                for (index, distance) in result {
+                    visitor(index, distance)
+                }
+            }
+            SearchType::Radius(radius) => self.radius_search_with(pivot, radius, visitor),
+            SearchType::KnnWithin { k, radius } => {
+                let mut result = Vec::new();
+                self.knn_search(pivot, k, &mut result);
+                for (index, distance) in result {
+                    if distance < radius {
+                        visitor(index, distance)
+                    }
+                }
+            }
         }
     }
 }
@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+
+use nalgebra::{RealField, Vector4};
+use num::ToPrimitive;
+use pcc_common::{
+    point::Point,
+    point_cloud::PointCloud,
+    search::{Search, SearchType},
+};
+use pcc_kdtree::{KnnResultSet, ResultSet};
+
+/// A searcher storing the points in a hash map of cubic voxels of
+/// `voxel_size`, which is cheaper to build than a kd-tree, e.g. for every
+/// frame of a lidar with roughly uniform density.
+///
+/// Radius searches scan the voxels overlapping the search balls, so they're
+/// the fastest with radii no larger than `voxel_size`. KNN searches by
+/// [`Search::search`] are approximate, only scanning the 27 voxels around the
+/// pivots, while [`Search::search_exact`] expands the scanned voxels until
+/// the results are exact.
+#[derive(Debug, Clone)]
+pub struct VoxelHashSearch<'a, P: Point> {
+    point_cloud: &'a PointCloud<P>,
+    voxel_size: P::Data,
+    voxels: HashMap<[i64; 3], Vec<usize>>,
+    bound: Option<[[i64; 3]; 2]>,
+}
+
+impl<'a, T, P> VoxelHashSearch<'a, P>
+where
+    T: RealField + ToPrimitive,
+    P: Point<Data = T>,
+{
+    /// # Panics
+    ///
+    /// Panics if `voxel_size` is not positive.
+    pub fn new(point_cloud: &'a PointCloud<P>, voxel_size: P::Data) -> Self {
+        assert!(voxel_size > T::zero(), "The voxel size must be positive");
+
+        let mut searcher = VoxelHashSearch {
+            point_cloud,
+            voxel_size,
+            voxels: HashMap::new(),
+            bound: None,
+        };
+        for (index, point) in point_cloud.iter().enumerate() {
+            let key = match searcher.key(point.coords()) {
+                Some(key) => key,
+                None => continue,
+            };
+            searcher.bound = Some(match searcher.bound {
+                Some([min, max]) => [
+                    [0, 1, 2].map(|i| min[i].min(key[i])),
+                    [0, 1, 2].map(|i| max[i].max(key[i])),
+                ],
+                None => [key, key],
+            });
+            searcher.voxels.entry(key).or_default().push(index);
+        }
+        searcher
+    }
+
+    #[inline]
+    pub fn voxel_size(&self) -> &P::Data {
+        &self.voxel_size
+    }
+
+    /// Returns the key of the voxel containing `coords`, or `None` if they're
+    /// not finite or too far away to be keyed.
+    fn key(&self, coords: &Vector4<P::Data>) -> Option<[i64; 3]> {
+        let [x, y, z] = [0, 1, 2].map(|i| {
+            (coords[i].clone() / self.voxel_size.clone())
+                .floor()
+                .to_i64()
+        });
+        Some([x?, y?, z?])
+    }
+
+    /// Visits the points in the voxels whose Chebyshev distances to `center`
+    /// are exactly `ring`.
+    fn visit_ring(&self, center: [i64; 3], ring: i64, mut visitor: impl FnMut(usize)) {
+        for dx in -ring..=ring {
+            for dy in -ring..=ring {
+                for dz in -ring..=ring {
+                    if dx.abs().max(dy.abs()).max(dz.abs()) < ring {
+                        continue;
+                    }
+                    let key = [center[0] + dx, center[1] + dy, center[2] + dz];
+                    if let Some(indices) = self.voxels.get(&key) {
+                        indices.iter().for_each(|&index| visitor(index));
+                    }
+                }
+            }
+        }
+    }
+
+    /// The ring beyond which there are no voxels.
+    fn max_ring(&self, center: [i64; 3]) -> i64 {
+        match self.bound {
+            Some([min, max]) => { (0..3).map(|i| (center[i] - min[i]).max(max[i] - center[i])) }
+                .max()
+                .unwrap()
+                .max(0),
+            None => -1,
+        }
+    }
+
+    pub fn radius_search_with(
+        &self,
+        pivot: &Vector4<P::Data>,
+        radius: P::Data,
+        mut visitor: impl FnMut(usize, P::Data),
+    ) {
+        let center = match self.key(pivot) {
+            Some(center) => center,
+            None => return,
+        };
+        let rings = { (radius.clone() / self.voxel_size.clone()).ceil() }
+            .to_i64()
+            .unwrap_or(i64::MAX)
+            .min(self.max_ring(center));
+        for ring in 0..=rings {
+            self.visit_ring(center, ring, |index| {
+                let distance = (self.point_cloud[index].coords() - pivot).norm();
+                if distance < radius {
+                    visitor(index, distance);
+                }
+            });
+        }
+    }
+
+    pub fn radius_search(
+        &self,
+        pivot: &Vector4<P::Data>,
+        radius: P::Data,
+        result: &mut Vec<(usize, P::Data)>,
+    ) {
+        result.clear();
+        self.radius_search_with(pivot, radius, |index, distance| {
+            result.push((index, distance))
+        });
+    }
+
+    /// Searches the `n` nearest neighbors, only within the 27 voxels around
+    /// `pivot` if not `exact`.
+    pub fn knn_search(
+        &self,
+        pivot: &Vector4<P::Data>,
+        n: usize,
+        exact: bool,
+        result: &mut Vec<(usize, P::Data)>,
+    ) {
+        self.knn_search_in(pivot, KnnResultSet::new(n), exact, result)
+    }
+
+    /// Like [`VoxelHashSearch::knn_search`], but only within `radius`, which
+    /// also stops expanding the scanned voxels beyond it.
+    pub fn knn_search_within(
+        &self,
+        pivot: &Vector4<P::Data>,
+        n: usize,
+        radius: P::Data,
+        exact: bool,
+        result: &mut Vec<(usize, P::Data)>,
+    ) {
+        self.knn_search_in(pivot, KnnResultSet::with_radius(n, radius), exact, result)
+    }
+
+    fn knn_search_in(
+        &self,
+        pivot: &Vector4<P::Data>,
+        mut rr: KnnResultSet<P::Data, usize>,
+        exact: bool,
+        result: &mut Vec<(usize, P::Data)>,
+    ) {
+        result.clear();
+
+        let center = match self.key(pivot) {
+            Some(center) => center,
+            None => return,
+        };
+        let max_ring = if exact { self.max_ring(center) } else { 1 };
+        for ring in 0..=max_ring {
+            self.visit_ring(center, ring, |index| {
+                let distance = (self.point_cloud[index].coords() - pivot).norm();
+                rr.push(distance, index);
+            });
+
+            // The points in the farther rings are at least this far away.
+            let covered = self.voxel_size.clone() * T::from_i64(ring).unwrap();
+            if rr.is_full() && rr.max_key().map_or(false, |max| *max <= covered) {
+                break;
+            }
+        }
+        rr.drain_sorted_into(result);
+    }
+}
+
+impl<'a, P> Search<'a, P> for VoxelHashSearch<'a, P>
+where
+    P: Point,
+    P::Data: RealField + ToPrimitive,
+{
+    fn input(&self) -> &'a PointCloud<P> {
+        self.point_cloud
+    }
+
+    fn search(
+        &self,
+        pivot: &Vector4<P::Data>,
+        ty: SearchType<P::Data>,
+        result: &mut Vec<(usize, P::Data)>,
+    ) {
+        match ty {
+            SearchType::Knn(n) => self.knn_search(pivot, n, false, result),
+            SearchType::KnnWithin { k, radius } => {
+                self.knn_search_within(pivot, k, radius, false, result)
+            }
+            SearchType::Radius(radius) => self.radius_search(pivot, radius, result),
+        }
+    }
+
+    fn search_exact(
+        &self,
+        pivot: &Vector4<P::Data>,
+        ty: SearchType<P::Data>,
+        result: &mut Vec<(usize, P::Data)>,
+    ) {
+        match ty {
+            SearchType::Knn(n) => self.knn_search(pivot, n, true, result),
+            SearchType::KnnWithin { k, radius } => {
+                self.knn_search_within(pivot, k, radius, true, result)
+            }
+            SearchType::Radius(radius) => self.radius_search(pivot, radius, result),
+        }
+    }
+
+    fn search_with(
+        &self,
+        pivot: &Vector4<P::Data>,
+        ty: SearchType<P::Data>,
+        visitor: &mut dyn FnMut(usize, P::Data),
+    ) {
+        match ty {
+            SearchType::Knn(n) => {
+                let mut result = Vec::new();
+                self.knn_search(pivot, n, false, &mut result);
+                for (index, distance) in result {
+                    visitor(index, distance)
+                }
+            }
+            SearchType::Radius(radius) => self.radius_search_with(pivot, radius, visitor),
+            SearchType::KnnWithin { k, radius } => {
+                let mut result = Vec::new();
+                self.knn_search_within(pivot, k, radius, false, &mut result);
+                for (index, distance) in result {
+                    visitor(index, distance)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pcc_common::point::Point3;
+
+    use super::*;
+
+    #[test]
+    fn test_voxel_hash() {
+        let storage = { (0..1000).map(|i| i as f32) }
+            .map(|i| {
+                let coords = Vector4::new((i * 0.37) % 5., (i * 0.61) % 3., (i * 0.13) % 4., 1.);
+                Point3::default().with_coords(coords)
+            })
+            .collect::<Vec<_>>();
+        let pc = PointCloud::from_vec(storage, 1);
+        let searcher = VoxelHashSearch::new(&pc, 0.5);
+
+        let pivot = Vector4::new(2.2, 1.4, 1.9, 1.);
+        let mut brute = { pc.iter().enumerate() }
+            .map(|(index, point)| (index, (point.coords() - pivot).norm()))
+            .collect::<Vec<_>>();
+        brute.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+
+        let mut result = Vec::new();
+        searcher.search(&pivot, SearchType::Radius(0.8), &mut result);
+        result.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+        let expected = brute.iter().take_while(|(_, d)| *d < 0.8);
+        assert!(result.iter().eq(expected));
+
+        searcher.search_exact(&pivot, SearchType::Knn(50), &mut result);
+        result.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+        assert_eq!(result, brute[..50]);
+
+        let nan = Vector4::new(f32::NAN, 0., 0., 1.);
+        searcher.search(&nan, SearchType::Radius(0.8), &mut result);
+        assert!(result.is_empty());
+        searcher.search_exact(&nan, SearchType::Knn(50), &mut result);
+        assert!(result.is_empty());
+    }
+}
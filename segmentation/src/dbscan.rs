@@ -0,0 +1,118 @@
+use nalgebra::{RealField, Scalar};
+use num::ToPrimitive;
+use pcc_common::{
+    point::Point,
+    point_cloud::PointCloud,
+    search::{Search, SearchType},
+};
+use pcc_search::searcher;
+
+/// Density-based clustering of the points, which grows clusters from the core
+/// points with at least `min_pts` neighbors (including themselves) within
+/// `eps`.
+///
+/// The result labels every point with its cluster, or `None` if it's noise
+/// or non-finite. Border points reachable from several clusters join the
+/// first one that reaches them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Dbscan<T: Scalar> {
+    pub eps: T,
+    pub min_pts: usize,
+}
+
+impl<T: Scalar> Dbscan<T> {
+    pub fn new(eps: T, min_pts: usize) -> Self {
+        Dbscan { eps, min_pts }
+    }
+}
+
+impl<T: RealField + ToPrimitive> Dbscan<T> {
+    pub fn compute<P: Point<Data = T>>(&self, input: &PointCloud<P>) -> Vec<Option<usize>> {
+        if input.is_empty() {
+            return Vec::new();
+        }
+        searcher!(searcher in input, T::default_epsilon());
+        self.compute_with(searcher)
+    }
+
+    /// Like [`Dbscan::compute`], but reuses `searcher` on the input instead of
+    /// building a new one.
+    pub fn compute_with<'a, P, S>(&self, searcher: &S) -> Vec<Option<usize>>
+    where
+        P: Point<Data = T> + 'a,
+        S: Search<'a, P> + ?Sized,
+    {
+        let input = searcher.input();
+        let mut labels = vec![None; input.len()];
+        let mut visited = vec![false; input.len()];
+
+        let mut neighbors = Vec::new();
+        let region_query = |index: usize, neighbors: &mut Vec<usize>| {
+            neighbors.clear();
+            let ty = SearchType::Radius(self.eps.clone());
+            searcher.search_with(input[index].coords(), ty, &mut |neighbor, _| {
+                neighbors.push(neighbor)
+            });
+            neighbors.len() >= self.min_pts
+        };
+
+        let mut num_clusters = 0;
+        let mut queue = Vec::new();
+        for index in 0..input.len() {
+            if visited[index] || !input[index].is_finite() {
+                continue;
+            }
+            visited[index] = true;
+            if !region_query(index, &mut neighbors) {
+                continue;
+            }
+
+            let label = Some(num_clusters);
+            num_clusters += 1;
+            labels[index] = label;
+
+            queue.clear();
+            queue.extend_from_slice(&neighbors);
+            while let Some(neighbor) = queue.pop() {
+                if labels[neighbor].is_none() {
+                    labels[neighbor] = label;
+                }
+                if visited[neighbor] {
+                    continue;
+                }
+                visited[neighbor] = true;
+                if region_query(neighbor, &mut neighbors) {
+                    queue.extend_from_slice(&neighbors);
+                }
+            }
+        }
+
+        labels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::{
+        point::{Point, Point3},
+        point_cloud::PointCloud,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_dbscan() {
+        let storage = { (0..10).map(|i| i as f32 * 0.1) }
+            .flat_map(|t| [[t, 0.], [t + 5., 0.]])
+            .chain([[2.5, 3.], [f32::NAN, 0.]])
+            .map(|[x, y]| Point3::default().with_coords(Vector4::new(x, y, 0., 1.)))
+            .collect::<Vec<_>>();
+        let input = PointCloud::from_vec(storage, 1);
+
+        let labels = Dbscan::new(0.15, 3).compute(&input);
+        assert!(labels[..20].iter().step_by(2).all(|&l| l == Some(0)));
+        assert!(labels[1..20].iter().step_by(2).all(|&l| l == Some(1)));
+        assert_eq!(labels[20..], [None, None]);
+    }
+}
@@ -0,0 +1,206 @@
+use nalgebra::{RealField, Scalar, Vector2};
+use pcc_common::{point::PointRange, range_image::RangeImage};
+
+/// Fast segmentation of the range images of spinning lidars by Bogoslavskyi
+/// and Stachniss, which removes the ground column by column and then grows
+/// clusters over neighboring pixels that the angle criterion connects.
+///
+/// Two neighboring beams of ranges `d1 >= d2` with the angle `ψ` between them
+/// are connected if `β = atan2(d2 sin ψ, d1 - d2 cos ψ)` exceeds `theta`,
+/// i.e. their points are on a surface not too oblique to the beams.
+///
+/// The elevations of the range image are measured from the horizontal plane
+/// of the sensor, so it should be mounted level, with the lower rows of the
+/// image facing the ground.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DepthClustering<T: Scalar> {
+    /// The minimum angle `β` in radians of connected neighbors.
+    pub theta: T,
+    /// Vertically neighboring pixels inclined less than it in radians are
+    /// ground.
+    pub ground_angle: T,
+    /// Clusters with fewer pixels are discarded.
+    pub min_size: usize,
+}
+
+impl<T: Scalar> DepthClustering<T> {
+    pub fn new(theta: T, ground_angle: T, min_size: usize) -> Self {
+        DepthClustering {
+            theta,
+            ground_angle,
+            min_size,
+        }
+    }
+}
+
+fn valid_range<T: RealField>(range: T) -> Option<T> {
+    (range.is_finite() && range > T::zero()).then_some(range)
+}
+
+impl<T: RealField> DepthClustering<T> {
+    /// Marks the ground pixels of `image`, which are connected to the lowest
+    /// observed pixel of their columns through pixels inclined less than
+    /// `ground_angle`.
+    pub fn ground<P: PointRange<Data = T>>(&self, image: &RangeImage<P>) -> Vec<bool> {
+        let (width, height) = (image.width(), image.height());
+        let mut ground = vec![false; image.len()];
+
+        let beam = |x: usize, y: usize| {
+            let range = valid_range(image[(x, y)].range())?;
+            let pixel = Vector2::new(x, y).map(|x| T::from_usize(x).unwrap());
+            let elevation = image.image_to_angle(&pixel).y.clone();
+            Some((range, elevation))
+        };
+        for x in 0..width {
+            let mut rows = (0..height).filter_map(|y| Some((y, beam(x, y)?)));
+            let (mut last, mut last_beam) = match rows.next() {
+                Some(row) => row,
+                None => continue,
+            };
+
+            for (y, (range, elevation)) in rows {
+                if y != last + 1 {
+                    break;
+                }
+                let (last_range, last_elevation) = &last_beam;
+                let dv = range.clone() * elevation.clone().sin()
+                    - last_range.clone() * last_elevation.clone().sin();
+                let dh = range.clone() * elevation.clone().cos()
+                    - last_range.clone() * last_elevation.clone().cos();
+                if dv.abs().atan2(dh.abs()) >= self.ground_angle {
+                    break;
+                }
+
+                ground[last * width + x] = true;
+                ground[y * width + x] = true;
+                (last, last_beam) = (y, (range, elevation));
+            }
+        }
+        ground
+    }
+
+    /// Labels every pixel of `image` with its cluster, or `None` if it's
+    /// ground, unobserved, or in a cluster smaller than `min_size`.
+    pub fn compute<P: PointRange<Data = T>>(&self, image: &RangeImage<P>) -> Vec<Option<usize>> {
+        let ground = self.ground(image);
+        self.compute_with(image, &ground)
+    }
+
+    /// Like [`DepthClustering::compute`], but excludes the pixels marked in
+    /// `ground` instead of detecting the ground.
+    pub fn compute_with<P: PointRange<Data = T>>(
+        &self,
+        image: &RangeImage<P>,
+        ground: &[bool],
+    ) -> Vec<Option<usize>> {
+        let width = image.width();
+        let ar = image.angular_resolution();
+        // Full panoramas wrap around horizontally.
+        let wrap = T::from_usize(width + 1).unwrap() * ar.x.clone() > T::two_pi();
+
+        let range = |index: usize| {
+            let range = valid_range(image[index].range())?;
+            (!ground[index]).then_some(range)
+        };
+        let connected = |a: T, b: T, psi: &T| {
+            let (d1, d2) = if a > b { (a, b) } else { (b, a) };
+            let beta = (d2.clone() * psi.clone().sin()).atan2(d1 - d2 * psi.clone().cos());
+            beta > self.theta
+        };
+
+        let (labels, _) = pcc_common::flood_fill(
+            width,
+            image.len(),
+            wrap,
+            self.min_size,
+            |index| range(index).is_some(),
+            |index, neighbor| {
+                let psi = if index / width == neighbor / width {
+                    &ar.x
+                } else {
+                    &ar.y
+                };
+                connected(range(index).unwrap(), range(neighbor).unwrap(), psi)
+            },
+        );
+        labels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Affine3, Vector4};
+    use pcc_common::{
+        point::{Point, Point3, Point3Range},
+        point_cloud::PointCloud,
+        range_image::CreateOptions,
+    };
+
+    use super::*;
+
+    /// Casts the beams of a sensor 1 above the ground `y = -1` at 2 plates
+    /// facing it at `z = 4`, which are apart in `x`. The beams go through the
+    /// centers of the pixels of 1 degree.
+    fn scene() -> PointCloud<Point3> {
+        let step = 1f32.to_radians();
+        let storage = { (-40..=40).flat_map(|x| (-40..=20).map(move |y| (x, y))) }
+            .filter_map(|(x, y)| {
+                let elevation = y as f32 * step;
+                let azimuth = x as f32 * step / elevation.cos();
+                let dir = Vector4::new(
+                    azimuth.sin() * elevation.cos(),
+                    elevation.sin(),
+                    azimuth.cos() * elevation.cos(),
+                    0.,
+                );
+                let plate = 4. / dir.z;
+                let hit = dir * plate;
+                let range = if (0.5..2.).contains(&hit.x.abs()) && hit.y.abs() < 1. {
+                    plate
+                } else if dir.y < 0. {
+                    -1. / dir.y
+                } else {
+                    return None;
+                };
+                let coords = dir * range + Vector4::w();
+                Some(Point3::default().with_coords(coords))
+            })
+            .collect::<Vec<_>>();
+        PointCloud::from_vec(storage, 1)
+    }
+
+    #[test]
+    fn test_depth_clustering() {
+        let scene = scene();
+        let options = CreateOptions {
+            point_cloud: &scene,
+            angular_resolution: Vector2::repeat(1f32.to_radians()),
+            noise: 0.,
+            min_range: 0.,
+            border_size: 0,
+        };
+        let image = RangeImage::<Point3Range>::new(
+            &[100f32.to_radians(), 100f32.to_radians()],
+            Affine3::identity(),
+            &options,
+        );
+
+        let dc = DepthClustering::new(10f32.to_radians(), 5f32.to_radians(), 20);
+        let pixel = |x: f32, y: f32, z: f32| {
+            let (pixel, _) = image.point_to_image(&Vector4::new(x, y, z, 1.));
+            let [[x, y]] = pixel.map(|x| x.round() as usize).data.0;
+            y * image.width() + x
+        };
+        let ground = dc.ground(&image);
+        assert!(ground[pixel(0., -1., 2.)]);
+        assert!(ground[pixel(0., -1., 10.)]);
+        assert!(!ground[pixel(1., 0., 4.)]);
+
+        let labels = dc.compute(&image);
+        let (left, right) = (labels[pixel(-1., 0., 4.)], labels[pixel(1., 0., 4.)]);
+        assert!(left.is_some() && right.is_some() && left != right);
+        assert_eq!(labels[pixel(0., -1., 2.)], None);
+        let max = labels.iter().flatten().max();
+        assert_eq!(max, Some(&1));
+    }
+}
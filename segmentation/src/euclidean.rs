@@ -0,0 +1,112 @@
+use nalgebra::{RealField, Scalar};
+use num::ToPrimitive;
+use pcc_common::{
+    point::Point,
+    point_cloud::PointCloud,
+    search::{Search, SearchType},
+};
+use pcc_search::searcher;
+
+/// Euclidean clustering of the points, which connects every pair of points
+/// within `tolerance` and keeps the connected components of
+/// `min_size..=max_size` points, e.g. to split the objects left after the
+/// planes are removed.
+///
+/// The result is the clusters of point indices, each sorted, in the
+/// descending order of their sizes. Non-finite points belong to no cluster.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EuclideanClusterExtraction<T: Scalar> {
+    pub tolerance: T,
+    pub min_size: usize,
+    pub max_size: usize,
+}
+
+impl<T: Scalar> EuclideanClusterExtraction<T> {
+    pub fn new(tolerance: T, min_size: usize, max_size: usize) -> Self {
+        EuclideanClusterExtraction {
+            tolerance,
+            min_size,
+            max_size,
+        }
+    }
+}
+
+impl<T: RealField + ToPrimitive> EuclideanClusterExtraction<T> {
+    pub fn compute<P: Point<Data = T>>(&self, input: &PointCloud<P>) -> Vec<Vec<usize>> {
+        if input.is_empty() {
+            return Vec::new();
+        }
+        searcher!(searcher in input, T::default_epsilon());
+        self.compute_with(searcher)
+    }
+
+    /// Like [`EuclideanClusterExtraction::compute`], but reuses `searcher` on
+    /// the input instead of building a new one.
+    pub fn compute_with<'a, P, S>(&self, searcher: &S) -> Vec<Vec<usize>>
+    where
+        P: Point<Data = T> + 'a,
+        S: Search<'a, P> + ?Sized,
+    {
+        let input = searcher.input();
+        let mut visited = vec![false; input.len()];
+
+        let mut clusters = Vec::new();
+        let mut queue = Vec::new();
+        for index in 0..input.len() {
+            if visited[index] || !input[index].is_finite() {
+                continue;
+            }
+            visited[index] = true;
+
+            let mut cluster = Vec::new();
+            queue.push(index);
+            while let Some(index) = queue.pop() {
+                cluster.push(index);
+                let ty = SearchType::Radius(self.tolerance.clone());
+                searcher.search_with(input[index].coords(), ty, &mut |neighbor, _| {
+                    if !visited[neighbor] {
+                        visited[neighbor] = true;
+                        queue.push(neighbor);
+                    }
+                });
+            }
+
+            if (self.min_size..=self.max_size).contains(&cluster.len()) {
+                cluster.sort_unstable();
+                clusters.push(cluster);
+            }
+        }
+
+        clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.len()));
+        clusters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::{
+        point::{Point, Point3},
+        point_cloud::PointCloud,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_euclidean_cluster_extraction() {
+        let storage = { (0..10).map(|i| i as f32 * 0.1) }
+            .flat_map(|t| [[t, 0.], [t * 0.5 + 5., 0.]])
+            .chain([[2.5, 3.], [f32::NAN, 0.]])
+            .map(|[x, y]| Point3::default().with_coords(Vector4::new(x, y, 0., 1.)))
+            .collect::<Vec<_>>();
+        let input = PointCloud::from_vec(storage, 1);
+
+        let clusters = EuclideanClusterExtraction::new(0.15, 2, 10).compute(&input);
+        assert_eq!(clusters.len(), 2);
+        assert!({ clusters.iter() }.all(|cluster| cluster.len() == 10));
+        assert!(clusters[0].iter().all(|&i| i % 2 == clusters[0][0] % 2));
+
+        let clusters = EuclideanClusterExtraction::new(0.15, 2, 9).compute(&input);
+        assert!(clusters.is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+use pcc_common::{point::PointLabel, point_cloud::PointCloud};
+
+/// The confusion matrix of semantic labels predicted for the points, for
+/// evaluating segmentations against the ground truth.
+///
+/// The labels not less than `num_classes` are ignored, e.g. for unlabeled
+/// points.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfusionMatrix {
+    pub num_classes: usize,
+    /// The numbers of the points indexed by `truth * num_classes + predicted`.
+    pub counts: Vec<usize>,
+}
+
+impl ConfusionMatrix {
+    pub fn new(num_classes: usize) -> Self {
+        ConfusionMatrix {
+            num_classes,
+            counts: vec![0; num_classes * num_classes],
+        }
+    }
+
+    /// Compares the labels of `predicted` and `truth` point by point.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the point clouds have different lengths.
+    pub fn compute<P, Q>(
+        predicted: &PointCloud<P>,
+        truth: &PointCloud<Q>,
+        num_classes: usize,
+    ) -> Self
+    where
+        P: PointLabel,
+        Q: PointLabel,
+    {
+        assert_eq!(
+            predicted.len(),
+            truth.len(),
+            "Every point must have a ground truth"
+        );
+        let mut ret = Self::new(num_classes);
+        for (predicted, truth) in predicted.iter().zip(truth.iter()) {
+            ret.add(truth.label(), predicted.label());
+        }
+        ret
+    }
+
+    pub fn add(&mut self, truth: u32, predicted: u32) {
+        let (truth, predicted) = (truth as usize, predicted as usize);
+        if truth < self.num_classes && predicted < self.num_classes {
+            self.counts[truth * self.num_classes + predicted] += 1;
+        }
+    }
+
+    pub fn count(&self, truth: u32, predicted: u32) -> usize {
+        self.counts[truth as usize * self.num_classes + predicted as usize]
+    }
+
+    fn true_positives(&self, class: u32) -> usize {
+        self.count(class, class)
+    }
+
+    /// The number of the points of `class` in the ground truth.
+    pub fn support(&self, class: u32) -> usize {
+        let row = class as usize * self.num_classes;
+        self.counts[row..row + self.num_classes].iter().sum()
+    }
+
+    /// The number of the points predicted as `class`.
+    pub fn num_predicted(&self, class: u32) -> usize {
+        { 0..self.num_classes as u32 }
+            .map(|truth| self.count(truth, class))
+            .sum()
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    /// The intersection over union of `class`, or `None` if it's neither in
+    /// the ground truth nor predicted.
+    pub fn iou(&self, class: u32) -> Option<f64> {
+        let tp = self.true_positives(class);
+        let union = self.support(class) + self.num_predicted(class) - tp;
+        (union > 0).then(|| tp as f64 / union as f64)
+    }
+
+    /// The precision of `class`, or `None` if it's never predicted.
+    pub fn precision(&self, class: u32) -> Option<f64> {
+        let num = self.num_predicted(class);
+        (num > 0).then(|| self.true_positives(class) as f64 / num as f64)
+    }
+
+    /// The recall of `class`, or `None` if it's not in the ground truth.
+    pub fn recall(&self, class: u32) -> Option<f64> {
+        let num = self.support(class);
+        (num > 0).then(|| self.true_positives(class) as f64 / num as f64)
+    }
+
+    /// The ratio of the correctly labeled points, or `None` if there are no
+    /// points.
+    pub fn accuracy(&self) -> Option<f64> {
+        let total = self.total();
+        let correct = { 0..self.num_classes as u32 }
+            .map(|class| self.true_positives(class))
+            .sum::<usize>();
+        (total > 0).then(|| correct as f64 / total as f64)
+    }
+
+    /// The mean of the IoUs of the classes that are in the ground truth or
+    /// predicted, or `None` if there are no such classes.
+    pub fn mean_iou(&self) -> Option<f64> {
+        let (sum, num) = { 0..self.num_classes as u32 }
+            .filter_map(|class| self.iou(class))
+            .fold((0., 0), |(sum, num), iou| (sum + iou, num + 1));
+        (num > 0).then(|| sum / num as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pcc_common::{
+        point::{Point3LN, PointLabel},
+        point_cloud::PointCloud,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_confusion_matrix() {
+        let cloud = |labels: &[u32]| {
+            let storage = { labels.iter() }
+                .map(|&label| Point3LN::default().with_label(label))
+                .collect::<Vec<_>>();
+            PointCloud::from_vec(storage, 1)
+        };
+        let predicted = cloud(&[0, 0, 1, 1, 1, 2]);
+        let truth = cloud(&[0, 1, 1, 1, 0, 9]);
+
+        let matrix = ConfusionMatrix::compute(&predicted, &truth, 3);
+        assert_eq!(matrix.total(), 5);
+        assert_eq!(matrix.count(0, 1), 1);
+        assert_eq!(matrix.accuracy(), Some(0.6));
+        assert_eq!(matrix.precision(1), Some(2. / 3.));
+        assert_eq!(matrix.recall(1), Some(2. / 3.));
+        assert_eq!(matrix.iou(0), Some(1. / 3.));
+        assert_eq!(matrix.iou(2), None);
+        assert_eq!(matrix.mean_iou(), Some((1. / 3. + 0.5) / 2.));
+    }
+}
@@ -0,0 +1,18 @@
+mod dbscan;
+mod depth;
+mod euclidean;
+mod evaluation;
+mod optics;
+mod organized;
+
+pub use self::{
+    dbscan::Dbscan,
+    depth::DepthClustering,
+    euclidean::EuclideanClusterExtraction,
+    evaluation::ConfusionMatrix,
+    optics::{Optics, OpticsOrdering},
+    organized::{
+        Comparator, EuclideanComparator, OrganizedSegmentation, PlaneComparator, PlaneRefinement,
+        RgbComparator,
+    },
+};
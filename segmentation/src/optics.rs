@@ -0,0 +1,221 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+};
+
+use nalgebra::{RealField, Scalar};
+use num::ToPrimitive;
+use pcc_common::{
+    point::Point,
+    point_cloud::PointCloud,
+    search::{Search, SearchType},
+};
+use pcc_search::searcher;
+
+/// Orders the points by density with OPTICS, from which clusterings of
+/// different densities can be extracted without searching again.
+///
+/// Like [`Dbscan`](crate::Dbscan), the core points have at least `min_pts`
+/// neighbors (including themselves), but within `max_eps` which bounds the
+/// densities that can be extracted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Optics<T: Scalar> {
+    pub max_eps: T,
+    pub min_pts: usize,
+}
+
+/// The result of [`Optics`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpticsOrdering<T> {
+    /// The indices of the finite points in the cluster ordering.
+    pub ordering: Vec<usize>,
+    /// The reachability distance of every point, or `None` if it isn't
+    /// reachable from any core point within `max_eps`.
+    pub reachability: Vec<Option<T>>,
+    /// The core distance of every point, or `None` if it isn't a core point.
+    pub core_distance: Vec<Option<T>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Seed<T>(T, usize);
+
+impl<T: PartialEq> Eq for Seed<T> {}
+
+impl<T: PartialOrd> PartialOrd for Seed<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: PartialOrd> Ord for Seed<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        { self.0.partial_cmp(&other.0) }
+            .unwrap_or(Ordering::Equal)
+            .then(self.1.cmp(&other.1))
+    }
+}
+
+impl<T: Scalar> Optics<T> {
+    pub fn new(max_eps: T, min_pts: usize) -> Self {
+        Optics { max_eps, min_pts }
+    }
+}
+
+impl<T: RealField + ToPrimitive> Optics<T> {
+    pub fn compute<P: Point<Data = T>>(&self, input: &PointCloud<P>) -> OpticsOrdering<T> {
+        if input.is_empty() {
+            return OpticsOrdering {
+                ordering: Vec::new(),
+                reachability: Vec::new(),
+                core_distance: Vec::new(),
+            };
+        }
+        searcher!(searcher in input, T::default_epsilon());
+        self.compute_with(searcher)
+    }
+
+    /// Like [`Optics::compute`], but reuses `searcher` on the input instead of
+    /// building a new one.
+    pub fn compute_with<'a, P, S>(&self, searcher: &S) -> OpticsOrdering<T>
+    where
+        P: Point<Data = T> + 'a,
+        S: Search<'a, P> + ?Sized,
+    {
+        let input = searcher.input();
+        let mut ordering = Vec::with_capacity(input.len());
+        let mut reachability = vec![None; input.len()];
+        let mut core_distance = vec![None; input.len()];
+        let mut processed = vec![false; input.len()];
+
+        let mut neighbors = Vec::new();
+        let mut distances = Vec::new();
+        let mut process = |index: usize, neighbors: &mut Vec<(usize, T)>| {
+            neighbors.clear();
+            let ty = SearchType::Radius(self.max_eps.clone());
+            searcher.search_with(input[index].coords(), ty, &mut |neighbor, distance| {
+                neighbors.push((neighbor, distance))
+            });
+            if self.min_pts == 0 || neighbors.len() < self.min_pts {
+                return None;
+            }
+
+            distances.clear();
+            distances.extend(neighbors.iter().map(|(_, distance)| distance.clone()));
+            let (_, core, _) = distances.select_nth_unstable_by(self.min_pts - 1, |a, b| {
+                a.partial_cmp(b).unwrap_or(Ordering::Equal)
+            });
+            Some(core.clone())
+        };
+
+        let mut seeds = BinaryHeap::new();
+        for index in 0..input.len() {
+            if processed[index] || !input[index].is_finite() {
+                continue;
+            }
+
+            seeds.clear();
+            seeds.push(Reverse(Seed(T::zero(), index)));
+            while let Some(Reverse(Seed(reach, current))) = seeds.pop() {
+                if processed[current] {
+                    continue;
+                }
+                if current != index && reachability[current].as_ref() != Some(&reach) {
+                    continue;
+                }
+                processed[current] = true;
+                ordering.push(current);
+
+                let core = match process(current, &mut neighbors) {
+                    Some(core) => core,
+                    None => continue,
+                };
+                core_distance[current] = Some(core.clone());
+
+                for (neighbor, distance) in neighbors.drain(..) {
+                    if processed[neighbor] {
+                        continue;
+                    }
+                    let reach = core.clone().max(distance);
+                    if reachability[neighbor].as_ref().map_or(true, |r| &reach < r) {
+                        reachability[neighbor] = Some(reach.clone());
+                        seeds.push(Reverse(Seed(reach, neighbor)));
+                    }
+                }
+            }
+        }
+
+        OpticsOrdering {
+            ordering,
+            reachability,
+            core_distance,
+        }
+    }
+}
+
+impl<T: RealField> OpticsOrdering<T> {
+    /// Extracts the clustering that [`Dbscan`](crate::Dbscan) would produce
+    /// with `eps`, which should be no larger than `max_eps`, labeling noise
+    /// and non-finite points with `None`.
+    ///
+    /// The core points get the same clusters as DBSCAN, but a border point may
+    /// be labeled as noise if it precedes the core points of its cluster in
+    /// the ordering.
+    pub fn extract_dbscan(&self, eps: T) -> Vec<Option<usize>> {
+        let mut labels = vec![None; self.reachability.len()];
+        let mut current = None;
+        let mut num_clusters = 0;
+
+        for &index in &self.ordering {
+            let reachable = { self.reachability[index].as_ref() }.map_or(false, |r| r <= &eps);
+            if reachable {
+                labels[index] = current;
+                continue;
+            }
+
+            let core = { self.core_distance[index].as_ref() }.map_or(false, |c| c <= &eps);
+            if core {
+                current = Some(num_clusters);
+                num_clusters += 1;
+                labels[index] = current;
+            }
+        }
+
+        labels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::{
+        point::{Point, Point3},
+        point_cloud::PointCloud,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_optics() {
+        // A dense cluster and a sparse one, which a single `eps` of DBSCAN can
+        // only find both of if it merges them with the noise.
+        let storage = { (0..10).map(|i| i as f32) }
+            .flat_map(|t| [[t * 0.1, 0.], [t * 0.5 + 10., 0.]])
+            .chain([[5., 5.], [f32::NAN, 0.]])
+            .map(|[x, y]| Point3::default().with_coords(Vector4::new(x, y, 0., 1.)))
+            .collect::<Vec<_>>();
+        let input = PointCloud::from_vec(storage, 1);
+
+        let result = Optics::new(1., 3).compute(&input);
+        assert_eq!(result.ordering.len(), 21);
+
+        // The end points starting the clusters in the ordering are border points.
+        let dense = result.extract_dbscan(0.15);
+        assert!(dense[2..20].iter().step_by(2).all(|&l| l == Some(0)));
+        assert!(dense[1..20].iter().step_by(2).all(|l| l.is_none()));
+
+        let sparse = result.extract_dbscan(0.6);
+        assert!(sparse[2..20].iter().step_by(2).all(|&l| l == Some(0)));
+        assert!(sparse[3..20].iter().step_by(2).all(|&l| l == Some(1)));
+        assert_eq!(sparse[20..], [None, None]);
+    }
+}
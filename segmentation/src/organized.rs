@@ -0,0 +1,380 @@
+use nalgebra::{RealField, Scalar, Vector4};
+use pcc_common::{
+    point::{Point, PointNormal, PointRgba},
+    point_cloud::PointCloud,
+};
+use pcc_sac::Plane;
+
+/// Decides whether 2 neighboring points of an organized point cloud belong to
+/// the same segment.
+pub trait Comparator<P> {
+    fn compare(&self, a: &P, b: &P) -> bool;
+}
+
+/// Both comparators must agree.
+impl<P, A, B> Comparator<P> for (A, B)
+where
+    A: Comparator<P>,
+    B: Comparator<P>,
+{
+    fn compare(&self, a: &P, b: &P) -> bool {
+        self.0.compare(a, b) && self.1.compare(a, b)
+    }
+}
+
+/// Compares the local planes of the points, i.e. their normals and the
+/// distances from their tangent planes to each other.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PlaneComparator<T: Scalar> {
+    /// The maximum angle in radians between the normals.
+    pub angular_threshold: T,
+    pub distance_threshold: T,
+}
+
+impl<T: Scalar> PlaneComparator<T> {
+    pub fn new(angular_threshold: T, distance_threshold: T) -> Self {
+        PlaneComparator {
+            angular_threshold,
+            distance_threshold,
+        }
+    }
+}
+
+impl<T: RealField, P: PointNormal<Data = T>> Comparator<P> for PlaneComparator<T> {
+    fn compare(&self, a: &P, b: &P) -> bool {
+        let (na, nb) = (a.normal().xyz(), b.normal().xyz());
+        let cos = na.dot(&nb) / (na.norm() * nb.norm());
+        if cos < self.angular_threshold.clone().cos() {
+            return false;
+        }
+        let side = (b.coords() - a.coords()).xyz();
+        let da = side.dot(&na).abs() / na.norm();
+        let db = side.dot(&nb).abs() / nb.norm();
+        da <= self.distance_threshold && db <= self.distance_threshold
+    }
+}
+
+/// Compares the euclidean distances between the points.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EuclideanComparator<T: Scalar> {
+    pub distance_threshold: T,
+}
+
+impl<T: Scalar> EuclideanComparator<T> {
+    pub fn new(distance_threshold: T) -> Self {
+        EuclideanComparator { distance_threshold }
+    }
+}
+
+impl<T: RealField, P: Point<Data = T>> Comparator<P> for EuclideanComparator<T> {
+    fn compare(&self, a: &P, b: &P) -> bool {
+        (b.coords() - a.coords()).xyz().norm() <= self.distance_threshold
+    }
+}
+
+/// Compares the euclidean distances between the RGB colors of the points,
+/// whose channels range in `[0, 255]`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RgbComparator {
+    pub color_threshold: f32,
+}
+
+impl RgbComparator {
+    pub fn new(color_threshold: f32) -> Self {
+        RgbComparator { color_threshold }
+    }
+}
+
+impl<P: PointRgba> Comparator<P> for RgbComparator {
+    fn compare(&self, a: &P, b: &P) -> bool {
+        let (a, b) = (a.rgba_array(), b.rgba_array());
+        let distance2 = { a.iter().zip(&b).take(3) }.fold(0., |acc, (a, b)| {
+            let delta = a - b;
+            acc + delta * delta
+        });
+        distance2 <= self.color_threshold * self.color_threshold
+    }
+}
+
+/// Segments organized point clouds into the 4-connected components of the
+/// neighboring points that `comparator` connects, e.g. the planar regions
+/// with [`PlaneComparator`].
+///
+/// The result labels every point with its segment, or `None` if it's
+/// non-finite or in a segment smaller than `min_size`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OrganizedSegmentation<C> {
+    pub comparator: C,
+    pub min_size: usize,
+}
+
+impl<C> OrganizedSegmentation<C> {
+    pub fn new(comparator: C, min_size: usize) -> Self {
+        OrganizedSegmentation {
+            comparator,
+            min_size,
+        }
+    }
+
+    pub fn compute<T, P>(&self, input: &PointCloud<P>) -> Vec<Option<usize>>
+    where
+        T: RealField,
+        P: Point<Data = T>,
+        C: Comparator<P>,
+    {
+        let is_finite = |index: usize| input[index].coords().iter().all(|x| x.is_finite());
+        let (labels, _) = pcc_common::flood_fill(
+            input.width(),
+            input.len(),
+            false,
+            self.min_size,
+            is_finite,
+            |index, neighbor| self.comparator.compare(&input[index], &input[neighbor]),
+        );
+        labels
+    }
+}
+
+fn find(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+    index
+}
+
+/// Merges the adjacent planar regions of organized point clouds, e.g. the
+/// output of [`OrganizedSegmentation`], whose fitted planes are consistent,
+/// so that the planes over-segmented by noise or occlusion become large
+/// clean ones.
+///
+/// 2 adjacent regions are merged if the angle between their normals is at
+/// most `angular_threshold` in radians, and the centroid of each one is
+/// within `distance_threshold` of the plane of the other.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PlaneRefinement<T: Scalar> {
+    pub angular_threshold: T,
+    pub distance_threshold: T,
+}
+
+impl<T: Scalar> PlaneRefinement<T> {
+    pub fn new(angular_threshold: T, distance_threshold: T) -> Self {
+        PlaneRefinement {
+            angular_threshold,
+            distance_threshold,
+        }
+    }
+}
+
+impl<T: RealField> PlaneRefinement<T> {
+    /// Fits the planes of the regions labeled by `labels`, or `None` for the
+    /// regions with fewer than 3 points.
+    pub fn fit<P: Point<Data = T>>(
+        input: &PointCloud<P>,
+        labels: &[Option<usize>],
+    ) -> Vec<Option<Plane<T>>> {
+        let num = { labels.iter().flatten() }.fold(0, |acc, &label| acc.max(label + 1));
+        let mut regions = vec![Vec::new(); num];
+        for (point, label) in input.iter().zip(labels) {
+            if let Some(label) = *label {
+                regions[label].push(point.coords());
+            }
+        }
+
+        { regions.into_iter() }
+            .map(|coords| {
+                let cov = pcc_common::cov_matrix(coords.iter().copied())?;
+                let se = cov.symmetric_eigen();
+                let normal = se.eigenvectors.column(se.eigenvalues.imin()).into_owned();
+
+                let sum = { coords.iter() }.fold(Vector4::zeros(), |acc, &coords| acc + coords);
+                let centroid = sum / T::from_usize(coords.len()).unwrap();
+                Some(Plane {
+                    coords: centroid,
+                    normal: normal.insert_row(3, T::zero()),
+                })
+            })
+            .collect()
+    }
+
+    fn is_consistent(&self, a: &Plane<T>, b: &Plane<T>) -> bool {
+        let cos = a.normal.dot(&b.normal).abs();
+        cos >= self.angular_threshold.clone().cos()
+            && a.distance(&b.coords) <= self.distance_threshold
+            && b.distance(&a.coords) <= self.distance_threshold
+    }
+
+    /// Merges the regions of `input` labeled by `labels` in place, relabels
+    /// them compactly, and returns the refitted planes of the merged regions
+    /// indexed by their new labels.
+    ///
+    /// The regions whose planes can't be fitted are left unmerged.
+    pub fn refine<P: Point<Data = T>>(
+        &self,
+        input: &PointCloud<P>,
+        labels: &mut [Option<usize>],
+    ) -> Vec<Plane<T>> {
+        assert_eq!(input.len(), labels.len(), "Every point must have a label");
+
+        let planes = Self::fit(input, labels);
+        let mut parents = (0..planes.len()).collect::<Vec<_>>();
+
+        let width = input.width();
+        for index in 0..labels.len() {
+            let a = match labels[index] {
+                Some(a) => a,
+                None => continue,
+            };
+            // Only right and down neighbors, which cover every adjacency.
+            let right = (index % width + 1 < width).then(|| index + 1);
+            let down = Some(index + width).filter(|&down| down < labels.len());
+            for neighbor in [right, down].into_iter().flatten() {
+                let b = match labels[neighbor] {
+                    Some(b) if b != a => b,
+                    _ => continue,
+                };
+                let (ra, rb) = (find(&mut parents, a), find(&mut parents, b));
+                if ra == rb {
+                    continue;
+                }
+                if let (Some(pa), Some(pb)) = (&planes[a], &planes[b]) {
+                    if self.is_consistent(pa, pb) {
+                        parents[rb] = ra;
+                    }
+                }
+            }
+        }
+
+        let mut map = vec![None; planes.len()];
+        let mut num = 0;
+        for label in labels.iter_mut() {
+            if let Some(old) = *label {
+                let root = find(&mut parents, old);
+                let new = *map[root].get_or_insert_with(|| {
+                    num += 1;
+                    num - 1
+                });
+                *label = Some(new);
+            }
+        }
+
+        let planes = Self::fit(input, labels);
+        // Drops the regions whose planes can't be fitted.
+        let mut map = vec![None; planes.len()];
+        let mut ret = Vec::with_capacity(planes.len());
+        for (old, plane) in planes.into_iter().enumerate() {
+            if let Some(plane) = plane {
+                map[old] = Some(ret.len());
+                ret.push(plane);
+            }
+        }
+        for label in labels.iter_mut() {
+            *label = label.and_then(|old| map[old]);
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::{
+        point::{Normal, Point, Point3, Point3RgbaN},
+        point_cloud::PointCloud,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_comparators() {
+        let point = |x: f32, z: f32, normal: Vector4<f32>, rgba: u32| {
+            Point3RgbaN::default()
+                .with_coords(Vector4::new(x, 0., z, 1.))
+                .with_normal(normal)
+                .with_rgba(rgba)
+        };
+        let (up, tilted) = (Vector4::z(), Vector4::new(0.5, 0., 0.75f32.sqrt(), 0.));
+        let a = point(0., 0., up, 0xff_00_00_00);
+
+        let plane = PlaneComparator::new(0.1, 0.01);
+        assert!(plane.compare(&a, &point(0.1, 0., up, 0)));
+        assert!(!plane.compare(&a, &point(0.1, 0., tilted, 0)));
+        assert!(!plane.compare(&a, &point(0.1, 0.05, up, 0)));
+
+        let euclidean = EuclideanComparator::new(0.2);
+        assert!(euclidean.compare(&a, &point(0.1, 0.1, up, 0)));
+        assert!(!euclidean.compare(&a, &point(0.3, 0., up, 0)));
+
+        // The alpha channel is ignored.
+        let rgb = RgbComparator::new(10.);
+        assert!(rgb.compare(&a, &point(0., 0., up, 0x00_00_00_05)));
+        assert!(!rgb.compare(&a, &point(0., 0., up, 0xff_00_20_00)));
+
+        let both = (euclidean, rgb);
+        assert!(both.compare(&a, &point(0.1, 0., up, 0xff_00_00_05)));
+        assert!(!both.compare(&a, &point(0.3, 0., up, 0xff_00_00_05)));
+        assert!(!both.compare(&a, &point(0.1, 0., up, 0xff_00_20_00)));
+    }
+
+    #[test]
+    fn test_organized_segmentation() {
+        // 2 planes at different depths side by side, with a missing point in
+        // one corner and an isolated one in the other.
+        let storage = { (0..4).flat_map(|y| (0..8).map(move |x| (x, y))) }
+            .map(|(x, y)| {
+                let z = match (x, y) {
+                    (0, 0) => f32::NAN,
+                    (7, 3) => 5.,
+                    (x, _) if x < 4 => 0.,
+                    _ => 1.,
+                };
+                let coords = Vector4::new(x as f32 * 0.1, y as f32 * 0.1, z, 1.);
+                Point3::default().with_coords(coords)
+            })
+            .collect::<Vec<_>>();
+        let input = PointCloud::from_vec(storage, 8);
+
+        let segmentation = OrganizedSegmentation::new(EuclideanComparator::new(0.15), 2);
+        let labels = segmentation.compute(&input);
+        let expected = { (0..4).flat_map(|y| (0..8).map(move |x| (x, y))) }
+            .map(|(x, y)| match (x, y) {
+                (0, 0) | (7, 3) => None,
+                (x, _) if x < 4 => Some(0),
+                _ => Some(1),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(labels, expected);
+    }
+
+    #[test]
+    fn test_plane_refinement() {
+        // 2 coplanar regions side by side and one inclined at 45 degrees.
+        let storage = { (0..6).flat_map(|y| (0..12).map(move |x| (x, y))) }
+            .map(|(x, y)| {
+                let (x, y) = (x as f32 * 0.1, y as f32 * 0.1);
+                let z = if x > 0.75 { x - 0.75 } else { 0. };
+                Point3::default().with_coords(Vector4::new(x, y, z, 1.))
+            })
+            .collect::<Vec<_>>();
+        let input = PointCloud::from_vec(storage, 12);
+
+        let mut labels = { input.iter() }
+            .map(|point| {
+                let x = point.coords().x;
+                Some(if x < 0.35 {
+                    0
+                } else if x < 0.75 {
+                    1
+                } else {
+                    2
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let planes = PlaneRefinement::new(0.1, 0.01).refine(&input, &mut labels);
+        assert_eq!(planes.len(), 2);
+        assert!(planes[0].normal.z.abs() > 0.99);
+        assert!(labels[..8].iter().all(|&label| label == Some(0)));
+        assert_eq!(labels[11], Some(1));
+    }
+}
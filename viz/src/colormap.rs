@@ -0,0 +1,188 @@
+use nalgebra::ComplexField;
+use pcc_common::{
+    point::{Point, PointLabel, PointRgba},
+    point_cloud::PointCloud,
+};
+
+/// Maps scalars in `[0, 1]` to RGB colors in `[0, 1]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Colormap {
+    #[default]
+    Viridis,
+    Turbo,
+    Jet,
+    Gray,
+}
+
+/// Samples of viridis at evenly spaced scalars.
+const VIRIDIS: [[f32; 3]; 9] = [
+    [0.267, 0.005, 0.329],
+    [0.278, 0.175, 0.483],
+    [0.230, 0.322, 0.546],
+    [0.172, 0.449, 0.558],
+    [0.128, 0.567, 0.551],
+    [0.153, 0.680, 0.504],
+    [0.360, 0.785, 0.388],
+    [0.678, 0.864, 0.190],
+    [0.993, 0.906, 0.144],
+];
+
+impl Colormap {
+    pub fn color(&self, value: f32) -> [f32; 3] {
+        let value = if value.is_nan() {
+            0.
+        } else {
+            value.clamp(0., 1.)
+        };
+        match self {
+            Colormap::Viridis => {
+                let position = value * (VIRIDIS.len() - 1) as f32;
+                let index = (position as usize).min(VIRIDIS.len() - 2);
+                let t = position - index as f32;
+                let [from, to] = [VIRIDIS[index], VIRIDIS[index + 1]];
+                [0, 1, 2].map(|c| from[c] + (to[c] - from[c]) * t)
+            }
+            Colormap::Turbo => {
+                // The polynomial approximation by Mikhailov.
+                let poly = |c: [f32; 6]| {
+                    let y = c.iter().rev().fold(0., |acc, &c| acc * value + c);
+                    y.clamp(0., 1.)
+                };
+                [
+                    poly([0.1357, 4.6154, -42.6603, 132.1311, -152.9424, 59.2864]),
+                    poly([0.0914, 2.1942, 4.8430, -14.1850, 4.2773, 2.8296]),
+                    poly([0.1067, 12.6419, -60.5820, 110.3628, -89.9031, 27.3482]),
+                ]
+            }
+            Colormap::Jet => {
+                let channel = |offset: f32| (1.5 - (4. * value - offset).abs()).clamp(0., 1.);
+                [channel(3.), channel(2.), channel(1.)]
+            }
+            Colormap::Gray => [value; 3],
+        }
+    }
+
+    /// Maps `values` linearly from their finite range to colors.
+    pub fn colors(&self, values: &[f32]) -> Vec<[f32; 3]> {
+        let (min, max) = { values.iter().filter(|value| value.is_finite()) }
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &value| {
+                (min.min(value), max.max(value))
+            });
+        let range = if max > min { max - min } else { 1. };
+        { values.iter() }
+            .map(|&value| self.color((value - min) / range))
+            .collect()
+    }
+
+    /// Creates a point cloud of the same shape as `input`, whose points have
+    /// the coordinates of the input ones and the colors mapped from `f` as in
+    /// [`Colormap::colors`], e.g. for exporting the heights, intensities or
+    /// curvatures of the points.
+    pub fn colorize<P, Q, F>(&self, input: &PointCloud<P>, f: F) -> PointCloud<Q>
+    where
+        P: Point,
+        P::Data: ComplexField,
+        Q: PointRgba<Data = P::Data>,
+        F: FnMut(&P) -> f32,
+    {
+        let values = input.iter().map(f).collect::<Vec<_>>();
+        self.colorize_values(input, &values)
+    }
+
+    /// Like [`Colormap::colorize`], but maps the given `values` of the points,
+    /// e.g. the deviations of the points from a mesh.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `input` and `values` have different lengths.
+    pub fn colorize_values<P, Q>(&self, input: &PointCloud<P>, values: &[f32]) -> PointCloud<Q>
+    where
+        P: Point,
+        P::Data: ComplexField,
+        Q: PointRgba<Data = P::Data>,
+    {
+        assert_eq!(input.len(), values.len(), "Every point must have a value");
+        let mut colors = self.colors(values).into_iter();
+        input.map(|point| {
+            let color = colors.next().unwrap();
+            Q::default()
+                .with_coords(point.coords().clone())
+                .with_rgba(pack_rgb(color))
+        })
+    }
+}
+
+/// Packs an RGB color in `[0, 1]` as an opaque RGBA value of [`PointRgba`].
+pub fn pack_rgb(color: [f32; 3]) -> u32 {
+    let [r, g, b] = color.map(|c| (c.clamp(0., 1.) * 255.).round() as u32);
+    b | (g << 8) | (r << 16) | (0xff << 24)
+}
+
+/// A distinct color of `label` in `[0, 1]`, whose hue is spread by the golden
+/// ratio so that consecutive labels differ clearly.
+pub fn label_color(label: u32) -> [f32; 3] {
+    let hue = (label as f32 * 0.618_034).fract() * 6.;
+    let channel = |offset: f32| {
+        let h = (hue + offset) % 6.;
+        let c = (h - 3.).abs() - 1.;
+        0.2 + 0.75 * c.clamp(0., 1.)
+    };
+    [channel(0.), channel(4.), channel(2.)]
+}
+
+/// Like [`Colormap::colorize`], but colors the points by their labels with
+/// [`label_color`].
+pub fn colorize_labels<P, Q>(input: &PointCloud<P>) -> PointCloud<Q>
+where
+    P: PointLabel,
+    P::Data: ComplexField,
+    Q: PointRgba<Data = P::Data>,
+{
+    input.map(|point| {
+        Q::default()
+            .with_coords(point.coords().clone())
+            .with_rgba(pack_rgb(label_color(point.label())))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::point::{Point3, Point3Rgba};
+
+    use super::*;
+
+    #[test]
+    fn test_colormap() {
+        assert_eq!(Colormap::Gray.color(0.25), [0.25; 3]);
+        assert_eq!(Colormap::Viridis.color(0.), [0.267, 0.005, 0.329]);
+        let top = Colormap::Viridis.color(2.);
+        assert!({ top.iter().zip([0.993, 0.906, 0.144]) }.all(|(a, b)| (a - b).abs() < 1e-6));
+        let turbo = Colormap::Turbo.color(0.);
+        assert!({ turbo.iter().zip([0.1357, 0.0914, 0.1067]) }.all(|(a, b)| (a - b).abs() < 1e-6));
+        assert_eq!(Colormap::Jet.color(0.), [0., 0., 0.5]);
+        assert_eq!(Colormap::Jet.color(1.), [0.5, 0., 0.]);
+
+        let colors = Colormap::Gray.colors(&[1., 3., f32::NAN, 2.]);
+        assert_eq!(colors[0], [0.; 3]);
+        assert_eq!(colors[1], [1.; 3]);
+        assert_eq!(colors[3], [0.5; 3]);
+
+        assert_eq!(pack_rgb([1., 0.5, 0.]), 0xffff_8000);
+        assert_ne!(label_color(0), label_color(1));
+
+        let input = PointCloud::from_vec(
+            vec![
+                Point3::default().with_coords(Vector4::new(0., 0., 0., 1.)),
+                Point3::default().with_coords(Vector4::new(0., 0., 2., 1.)),
+            ],
+            2,
+        );
+        let output: PointCloud<Point3Rgba> =
+            Colormap::Gray.colorize(&input, |point| point.coords().z);
+        assert_eq!(output.width(), 2);
+        assert_eq!(output[1].coords(), input[1].coords());
+        assert_eq!(output[0].rgba(), 0xff00_0000);
+        assert_eq!(output[1].rgba(), 0xffff_ffff);
+    }
+}
@@ -0,0 +1,15 @@
+mod colormap;
+pub mod log;
+#[cfg(feature = "rerun")]
+mod recording;
+mod scene;
+#[cfg(feature = "kiss3d")]
+mod window;
+
+#[cfg(feature = "rerun")]
+pub use self::recording::RerunLogger;
+pub use self::{
+    colormap::{colorize_labels, label_color, pack_rgb, Colormap},
+    log::{Logger, LoggerExt, MemoryLogger},
+    scene::{Line, Scene, Vertex},
+};
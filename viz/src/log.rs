@@ -0,0 +1,220 @@
+use std::error::Error;
+
+use nalgebra::{Matrix4, RealField};
+use num::ToPrimitive;
+use pcc_common::{
+    point::{Point, PointRange},
+    point_cloud::PointCloud,
+    range_image::RangeImage,
+};
+use pcc_registration::Correspondence;
+
+use crate::{Line, Scene, Vertex};
+
+/// A sink of data logged at the checkpoints of pipelines for debugging.
+///
+/// Every entry is identified by a slash-separated path like
+/// `registration/source`, and entries logged to the same path replace the
+/// previous one in the viewer.
+pub trait Logger {
+    fn log_points(&mut self, path: &str, points: &[Vertex]) -> Result<(), Box<dyn Error>>;
+
+    fn log_lines(&mut self, path: &str, lines: &[Line]) -> Result<(), Box<dyn Error>>;
+
+    /// Logs a row-major depth image, where unobserved pixels are NaN.
+    fn log_depth(
+        &mut self,
+        path: &str,
+        width: usize,
+        height: usize,
+        depth: &[f32],
+    ) -> Result<(), Box<dyn Error>>;
+
+    /// Logs the transformation from the entity of `path` to its parent.
+    fn log_pose(&mut self, path: &str, pose: &Matrix4<f32>) -> Result<(), Box<dyn Error>>;
+}
+
+/// Conversions from the types of the library to the primitives of [`Logger`].
+pub trait LoggerExt: Logger {
+    fn log_scene(&mut self, path: &str, scene: &Scene) -> Result<(), Box<dyn Error>> {
+        self.log_points(&format!("{}/points", path), &scene.points)?;
+        self.log_lines(&format!("{}/lines", path), &scene.lines)
+    }
+
+    fn log_cloud<P>(
+        &mut self,
+        path: &str,
+        point_cloud: &PointCloud<P>,
+        color: [f32; 3],
+    ) -> Result<(), Box<dyn Error>>
+    where
+        P: Point,
+        P::Data: ToPrimitive,
+    {
+        let mut scene = Scene::new();
+        scene.add_cloud(point_cloud, color);
+        self.log_points(path, &scene.points)
+    }
+
+    fn log_correspondences<P, T>(
+        &mut self,
+        path: &str,
+        source: &PointCloud<P>,
+        target: &PointCloud<P>,
+        correspondences: &[Correspondence<T>],
+        color: [f32; 3],
+    ) -> Result<(), Box<dyn Error>>
+    where
+        P: Point,
+        P::Data: ToPrimitive,
+    {
+        let mut scene = Scene::new();
+        scene.add_correspondences(source, target, correspondences, color);
+        self.log_lines(path, &scene.lines)
+    }
+
+    fn log_range_image<P>(
+        &mut self,
+        path: &str,
+        range_image: &RangeImage<P>,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        P: PointRange,
+        P::Data: RealField + ToPrimitive,
+    {
+        let depth = { range_image.iter() }
+            .map(|point| point.range().to_f32().unwrap_or(f32::NAN))
+            .map(|range| if range.is_finite() { range } else { f32::NAN })
+            .collect::<Vec<_>>();
+        let (width, height) = match depth.len() {
+            0 => (0, 0),
+            _ => (range_image.width(), range_image.height()),
+        };
+        self.log_depth(path, width, height, &depth)
+    }
+
+    fn log_transform<T>(&mut self, path: &str, pose: &Matrix4<T>) -> Result<(), Box<dyn Error>>
+    where
+        T: RealField + ToPrimitive,
+    {
+        let pose = pose.map(|x| x.to_f32().unwrap_or(f32::NAN));
+        self.log_pose(path, &pose)
+    }
+}
+
+impl<L: Logger + ?Sized> LoggerExt for L {}
+
+/// An entry of [`MemoryLogger`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogEntry {
+    Points(Vec<Vertex>),
+    Lines(Vec<Line>),
+    Depth {
+        width: usize,
+        height: usize,
+        depth: Vec<f32>,
+    },
+    Pose(Matrix4<f32>),
+}
+
+/// A logger that keeps the entries in memory, in the order of logging.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MemoryLogger {
+    pub entries: Vec<(String, LogEntry)>,
+}
+
+impl MemoryLogger {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the last entry logged to `path`.
+    pub fn get(&self, path: &str) -> Option<&LogEntry> {
+        { self.entries.iter().rev() }
+            .find(|(p, _)| p == path)
+            .map(|(_, entry)| entry)
+    }
+}
+
+impl Logger for MemoryLogger {
+    fn log_points(&mut self, path: &str, points: &[Vertex]) -> Result<(), Box<dyn Error>> {
+        let entry = LogEntry::Points(points.to_vec());
+        self.entries.push((path.to_owned(), entry));
+        Ok(())
+    }
+
+    fn log_lines(&mut self, path: &str, lines: &[Line]) -> Result<(), Box<dyn Error>> {
+        let entry = LogEntry::Lines(lines.to_vec());
+        self.entries.push((path.to_owned(), entry));
+        Ok(())
+    }
+
+    fn log_depth(
+        &mut self,
+        path: &str,
+        width: usize,
+        height: usize,
+        depth: &[f32],
+    ) -> Result<(), Box<dyn Error>> {
+        let entry = LogEntry::Depth {
+            width,
+            height,
+            depth: depth.to_vec(),
+        };
+        self.entries.push((path.to_owned(), entry));
+        Ok(())
+    }
+
+    fn log_pose(&mut self, path: &str, pose: &Matrix4<f32>) -> Result<(), Box<dyn Error>> {
+        self.entries.push((path.to_owned(), LogEntry::Pose(*pose)));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Matrix4, Vector3, Vector4};
+    use pcc_common::{
+        point::{Point, Point3},
+        point_cloud::PointCloud,
+    };
+    use pcc_registration::Correspondence;
+
+    use super::*;
+
+    #[test]
+    fn test_memory_logger() {
+        let pc = PointCloud::from_vec(
+            vec![
+                Point3::default().with_coords(Vector4::new(1., 2., 3., 1.)),
+                Point3::default().with_coords(Vector4::new(4., 5., 6., 1.)),
+            ],
+            1,
+        );
+        let correspondences = [Correspondence {
+            source: 0,
+            target: 1,
+            distance: 1.,
+        }];
+        let pose = Matrix4::new_translation(&Vector3::new(1., 0., 0.));
+
+        let mut logger = MemoryLogger::new();
+        let dyn_logger: &mut dyn Logger = &mut logger;
+        { dyn_logger.log_cloud("icp/source", &pc, [1., 0., 0.]) }.expect("Failed to log");
+        { dyn_logger.log_correspondences("icp/matches", &pc, &pc, &correspondences, [1.; 3]) }
+            .expect("Failed to log");
+        dyn_logger
+            .log_transform("icp/source", &pose)
+            .expect("Failed to log");
+
+        assert_eq!(logger.entries.len(), 3);
+        match logger.get("icp/matches") {
+            Some(LogEntry::Lines(lines)) => {
+                assert_eq!(lines[0].from, [1., 2., 3.]);
+                assert_eq!(lines[0].to, [4., 5., 6.]);
+            }
+            entry => panic!("Unexpected entry: {:?}", entry),
+        }
+        assert_eq!(logger.get("icp/source"), Some(&LogEntry::Pose(pose)));
+    }
+}
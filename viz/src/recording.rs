@@ -0,0 +1,68 @@
+use std::error::Error;
+
+use nalgebra::Matrix4;
+use rerun::{external::ndarray::Array2, RecordingStream};
+
+use crate::{log::Logger, Line, Vertex};
+
+fn color([r, g, b]: [f32; 3]) -> rerun::Color {
+    let channel = |c: f32| (c.clamp(0., 1.) * 255.).round() as u8;
+    rerun::Color::from_rgb(channel(r), channel(g), channel(b))
+}
+
+/// Logs to the rerun viewer through a recording stream.
+pub struct RerunLogger {
+    pub stream: RecordingStream,
+}
+
+impl RerunLogger {
+    pub fn new(stream: RecordingStream) -> Self {
+        RerunLogger { stream }
+    }
+
+    /// Spawns a rerun viewer, and logs to it as the application `name`.
+    pub fn spawn(name: &str) -> Result<Self, Box<dyn Error>> {
+        let stream = rerun::RecordingStreamBuilder::new(name).spawn()?;
+        Ok(RerunLogger::new(stream))
+    }
+}
+
+impl Logger for RerunLogger {
+    fn log_points(&mut self, path: &str, points: &[Vertex]) -> Result<(), Box<dyn Error>> {
+        let archetype = rerun::Points3D::new(points.iter().map(|vertex| vertex.position))
+            .with_colors(points.iter().map(|vertex| color(vertex.color)));
+        self.stream.log(path, &archetype)?;
+        Ok(())
+    }
+
+    fn log_lines(&mut self, path: &str, lines: &[Line]) -> Result<(), Box<dyn Error>> {
+        let archetype = rerun::LineStrips3D::new(lines.iter().map(|line| [line.from, line.to]))
+            .with_colors(lines.iter().map(|line| color(line.color)));
+        self.stream.log(path, &archetype)?;
+        Ok(())
+    }
+
+    fn log_depth(
+        &mut self,
+        path: &str,
+        width: usize,
+        height: usize,
+        depth: &[f32],
+    ) -> Result<(), Box<dyn Error>> {
+        let image = Array2::from_shape_vec((height, width), depth.to_vec())?;
+        self.stream
+            .log(path, &rerun::DepthImage::try_from(image)?)?;
+        Ok(())
+    }
+
+    fn log_pose(&mut self, path: &str, pose: &Matrix4<f32>) -> Result<(), Box<dyn Error>> {
+        let columns = [0, 1, 2].map(|j| [0, 1, 2].map(|i| pose[(i, j)]));
+        let translation = [pose[(0, 3)], pose[(1, 3)], pose[(2, 3)]];
+        let transform = rerun::Transform3D::from_translation_mat3x3(
+            translation,
+            rerun::datatypes::Mat3x3::from(columns),
+        );
+        self.stream.log(path, &transform)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+use nalgebra::{Matrix4, RealField, Vector4};
+use num::ToPrimitive;
+use pcc_common::{
+    point::{Normal, Point, PointRgba},
+    point_cloud::PointCloud,
+};
+use pcc_registration::Correspondence;
+
+use crate::Colormap;
+
+/// A colored vertex of a scene.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+}
+
+/// A colored line segment of a scene.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Line {
+    pub from: [f32; 3],
+    pub to: [f32; 3],
+    pub color: [f32; 3],
+}
+
+/// The primitives to display, independent of the backend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scene {
+    pub points: Vec<Vertex>,
+    pub lines: Vec<Line>,
+    pub point_size: f32,
+    pub background: [f32; 3],
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene {
+            points: Vec::new(),
+            lines: Vec::new(),
+            point_size: 2.,
+            background: [0.; 3],
+        }
+    }
+}
+
+fn position<T: ToPrimitive>(coords: &Vector4<T>) -> [f32; 3] {
+    [0, 1, 2].map(|i| coords[i].to_f32().unwrap_or(f32::NAN))
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn push_points<'a, P, I>(&mut self, points: I)
+    where
+        P: Point + 'a,
+        P::Data: ToPrimitive,
+        I: Iterator<Item = (&'a P, [f32; 3])>,
+    {
+        let points =
+            { points.filter(|(point, _)| point.is_finite()) }.map(|(point, color)| Vertex {
+                position: position(point.coords()),
+                color,
+            });
+        self.points.extend(points);
+    }
+
+    /// Adds the finite points of `point_cloud` in a uniform color.
+    pub fn add_cloud<P>(&mut self, point_cloud: &PointCloud<P>, color: [f32; 3]) -> &mut Self
+    where
+        P: Point,
+        P::Data: ToPrimitive,
+    {
+        self.push_points(point_cloud.iter().map(|point| (point, color)));
+        self
+    }
+
+    /// Adds the finite points of `point_cloud`, colored by mapping the scalar
+    /// from `f` of every point through `colormap`.
+    pub fn add_cloud_with<P, F>(
+        &mut self,
+        point_cloud: &PointCloud<P>,
+        colormap: Colormap,
+        f: F,
+    ) -> &mut Self
+    where
+        P: Point,
+        P::Data: ToPrimitive,
+        F: FnMut(&P) -> f32,
+    {
+        let values = point_cloud.iter().map(f).collect::<Vec<_>>();
+        let colors = colormap.colors(&values);
+        self.push_points(point_cloud.iter().zip(colors));
+        self
+    }
+
+    /// Adds the finite points of `point_cloud` in their own colors.
+    pub fn add_rgb_cloud<P>(&mut self, point_cloud: &PointCloud<P>) -> &mut Self
+    where
+        P: PointRgba,
+        P::Data: ToPrimitive,
+    {
+        let colors = point_cloud.iter().map(|point| {
+            let [b, g, r, _] = point.rgba_array();
+            [r / 255., g / 255., b / 255.]
+        });
+        self.push_points(point_cloud.iter().zip(colors));
+        self
+    }
+
+    /// Adds the normals of the finite points of `point_cloud` as segments of
+    /// `length`.
+    pub fn add_normals<T, P>(
+        &mut self,
+        point_cloud: &PointCloud<P>,
+        length: T,
+        color: [f32; 3],
+    ) -> &mut Self
+    where
+        T: RealField + ToPrimitive,
+        P: Point<Data = T> + Normal,
+    {
+        let lines = { point_cloud.iter() }
+            .filter(|point| point.is_finite() && point.normal().iter().all(|x| x.is_finite()))
+            .map(|point| {
+                let to = point.coords() + point.normal() * length.clone();
+                Line {
+                    from: position(point.coords()),
+                    to: position(&to),
+                    color,
+                }
+            });
+        self.lines.extend(lines);
+        self
+    }
+
+    /// Adds the finite points of `keypoints` as crosses of `size`, which
+    /// stand out from the points of the clouds.
+    pub fn add_keypoints<P>(
+        &mut self,
+        keypoints: &PointCloud<P>,
+        size: f32,
+        color: [f32; 3],
+    ) -> &mut Self
+    where
+        P: Point,
+        P::Data: ToPrimitive,
+    {
+        let half = size / 2.;
+        for point in keypoints.iter().filter(|point| point.is_finite()) {
+            let center = position(point.coords());
+            for axis in 0..3 {
+                let (mut from, mut to) = (center, center);
+                from[axis] -= half;
+                to[axis] += half;
+                self.lines.push(Line { from, to, color });
+            }
+        }
+        self
+    }
+
+    /// Adds the wireframe of a triangle mesh.
+    pub fn add_mesh<P>(
+        &mut self,
+        vertices: &PointCloud<P>,
+        triangles: &[[usize; 3]],
+        color: [f32; 3],
+    ) -> &mut Self
+    where
+        P: Point,
+        P::Data: ToPrimitive,
+    {
+        for &[a, b, c] in triangles {
+            for (from, to) in [(a, b), (b, c), (c, a)] {
+                self.lines.push(Line {
+                    from: position(vertices[from].coords()),
+                    to: position(vertices[to].coords()),
+                    color,
+                });
+            }
+        }
+        self
+    }
+
+    /// Adds the result of a registration: `target` in the first color and
+    /// `source` moved by `transformation` in the second color.
+    pub fn add_registration<P>(
+        &mut self,
+        source: &PointCloud<P>,
+        target: &PointCloud<P>,
+        transformation: &Matrix4<P::Data>,
+        [target_color, source_color]: [[f32; 3]; 2],
+    ) -> &mut Self
+    where
+        P: Point,
+        P::Data: RealField + Copy + ToPrimitive,
+    {
+        let mut transformed = PointCloud::new();
+        source.transform(transformation, &mut transformed);
+        self.add_cloud(target, target_color)
+            .add_cloud(&transformed, source_color)
+    }
+
+    /// Adds the correspondences between `source` and `target` as segments.
+    pub fn add_correspondences<P, T>(
+        &mut self,
+        source: &PointCloud<P>,
+        target: &PointCloud<P>,
+        correspondences: &[Correspondence<T>],
+        color: [f32; 3],
+    ) -> &mut Self
+    where
+        P: Point,
+        P::Data: ToPrimitive,
+    {
+        let lines = correspondences.iter().map(|corr| Line {
+            from: position(source[corr.source].coords()),
+            to: position(target[corr.target].coords()),
+            color,
+        });
+        self.lines.extend(lines);
+        self
+    }
+
+    /// The center and the radius of the bounding sphere of the points.
+    pub fn bound(&self) -> Option<([f32; 3], f32)> {
+        let mut iter = { self.points.iter().map(|vertex| vertex.position) }
+            .chain(self.lines.iter().flat_map(|line| [line.from, line.to]))
+            .filter(|position| position.iter().all(|x| x.is_finite()));
+        let first = iter.next()?;
+        let (min, max) = iter.fold((first, first), |(min, max), position| {
+            (
+                [0, 1, 2].map(|i| min[i].min(position[i])),
+                [0, 1, 2].map(|i| max[i].max(position[i])),
+            )
+        });
+        let center = [0, 1, 2].map(|i| (min[i] + max[i]) / 2.);
+        let radius = { (0..3).map(|i| (max[i] - min[i]).powi(2)) }
+            .sum::<f32>()
+            .sqrt()
+            / 2.;
+        Some((center, radius))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pcc_common::{
+        point::{Normal, Point, Point3N},
+        point_cloud::PointCloud,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_scene() {
+        let pc = PointCloud::from_vec(
+            vec![
+                Point3N::default()
+                    .with_coords(Vector4::new(0., 0., 0., 1.))
+                    .with_normal(Vector4::new(0., 0., 1., 0.)),
+                Point3N::default()
+                    .with_coords(Vector4::new(2., 0., 0., 1.))
+                    .with_normal(Vector4::new(0., 0., 1., 0.)),
+                Point3N::default().with_coords(Vector4::new(f32::NAN, 0., 0., 1.)),
+            ],
+            1,
+        );
+
+        let mut scene = Scene::new();
+        scene
+            .add_cloud_with(&pc, Colormap::Gray, |point| point.coords().x)
+            .add_normals(&pc, 0.5, [1., 0., 0.])
+            .add_keypoints(&pc, 1., [0., 1., 0.]);
+
+        assert_eq!(scene.points.len(), 2);
+        assert_eq!(scene.points[1].color, [1.; 3]);
+        assert_eq!(scene.lines.len(), 2 + 6);
+        assert_eq!(scene.lines[0].to, [0., 0., 0.5]);
+
+        let (center, radius) = scene.bound().unwrap();
+        assert_eq!(center, [1., 0., 0.]);
+        assert!((radius - 11f32.sqrt() / 2.).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,35 @@
+use kiss3d::{
+    camera::ArcBall,
+    light::Light,
+    nalgebra::{Point3, Vector3},
+    window::Window,
+};
+
+use crate::Scene;
+
+impl Scene {
+    /// Displays the scene in a window with an orbit camera until the window
+    /// is closed.
+    pub fn show(&self, title: &str) {
+        let mut window = Window::new(title);
+        let [r, g, b] = self.background;
+        window.set_background_color(r, g, b);
+        window.set_point_size(self.point_size);
+        window.set_light(Light::StickToCamera);
+
+        let ([x, y, z], radius) = self.bound().unwrap_or(([0.; 3], 1.));
+        let at = Point3::new(x, y, z);
+        let eye = at + Vector3::new(0., 0., radius.max(f32::EPSILON) * 2.5);
+        let mut camera = ArcBall::new(eye, at);
+
+        let point = |[x, y, z]: [f32; 3]| Point3::new(x, y, z);
+        while window.render_with_camera(&mut camera) {
+            for vertex in &self.points {
+                window.draw_point(&point(vertex.position), &point(vertex.color));
+            }
+            for line in &self.lines {
+                window.draw_line(&point(line.from), &point(line.to), &point(line.color));
+            }
+        }
+    }
+}